@@ -0,0 +1,18 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use trading_bot_rust::bench::ScanFixture;
+
+/// Benchmarks the butterfly and box spread scanners over a chain of realistic size, 50 expiries
+/// of 200 strikes.
+fn scanners(c: &mut Criterion) {
+    let fixture: ScanFixture = ScanFixture::new(50, 200);
+
+    c.bench_function("butterfly_contenders", |b| {
+        b.iter(|| black_box(fixture.butterfly()))
+    });
+    c.bench_function("boxspread_contenders", |b| {
+        b.iter(|| black_box(fixture.boxspread()))
+    });
+}
+
+criterion_group!(benches, scanners);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+/// Generates the gRPC control plane service when the `grpc` feature is enabled. The messages are
+/// plain prost structs in `src/grpc.rs`, so no `.proto` file or `protoc` is needed.
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        use tonic_build::manual::{Builder, Method, MethodBuilder, Service};
+
+        let method = |name: &str, route_name: &str, input: &str, output: &str| -> MethodBuilder {
+            Method::builder()
+                .name(name)
+                .route_name(route_name)
+                .input_type(format!("crate::grpc::{}", input))
+                .output_type(format!("crate::grpc::{}", output))
+                .codec_path("tonic::codec::ProstCodec")
+        };
+        let service: Service = Service::builder()
+            .name("Control")
+            .package("trading_bot")
+            .method(method("get_status", "GetStatus", "StatusRequest", "StatusReply").build())
+            .method(
+                method(
+                    "pause_trading",
+                    "PauseTrading",
+                    "PauseRequest",
+                    "StatusReply",
+                )
+                .build(),
+            )
+            .method(
+                method(
+                    "stream_events",
+                    "StreamEvents",
+                    "StreamEventsRequest",
+                    "EventReply",
+                )
+                .server_streaming()
+                .build(),
+            )
+            .method(
+                method(
+                    "list_contenders",
+                    "ListContenders",
+                    "ListContendersRequest",
+                    "ContendersReply",
+                )
+                .build(),
+            )
+            .build();
+        Builder::new().build_client(false).compile(&[service]);
+    }
+}
@@ -0,0 +1,220 @@
+use chrono::NaiveDate;
+use std::error::Error;
+
+use crate::{
+    chain::{OptionChain, Quotes},
+    dividends::DividendCalendar,
+    ibkr::models::OrderBody,
+    orders::{build_request_data, LiquiditySizing, OrderLadder, OrderSizing},
+    rates::RateCurve,
+    scanner::{OptionType, Scanner},
+    structs::{AlgoConfig, Contender, ExpiryFilter, LegPricing, StrategyConfig},
+    underlying::UnderlyingSpec,
+};
+
+/// Scans an option chain for calendar, butterfly and box spread contenders, the scanning stage of
+/// the bot for apps that bring their own quotes.
+pub struct ChainScanner {
+    scanner: Scanner,
+}
+
+impl ChainScanner {
+    /// Creates a scanner with the bot's defaults: no DTE, moneyness or delta limits, same-day
+    /// expirations included and box spreads discounted at a zero rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticker` - The underlying, e.g. `"SPX"`, which the minimum leg bid scales with.
+    /// * `arb_val` - The minimum arbitrage value of a contender.
+    /// * `wing_widths` - The butterfly and box spread wing widths to scan, e.g. `[5.0, 10.0]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let scanner = ChainScanner::new("SPX", 0.10, vec![5.0, 10.0]);
+    /// ```
+    pub fn new(ticker: &str, arb_val: f64, wing_widths: Vec<f64>) -> Self {
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths,
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        ChainScanner {
+            scanner: Scanner {
+                arb_val,
+                butterfly_config: config.clone(),
+                boxspread_config: config.clone(),
+                calendar_config: config,
+                expiry_filter: ExpiryFilter {
+                    exclude_same_day: false,
+                    min_minutes_to_close: 0,
+                },
+                include_below_threshold: false,
+                near_miss_epsilon: None,
+                early_exercise: None,
+                dividends: DividendCalendar::new(),
+                rates: RateCurve::default(),
+                price_scale: UnderlyingSpec::for_ticker(ticker).price_scale,
+                leg_pricing: LegPricing::default(),
+                skipped_expiries: Vec::new(),
+                box_financing: None,
+                max_iv_deviation: None,
+            },
+        }
+    }
+
+    /// Scans the chain and ranks the contenders, best first.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategies` - The strategies to scan.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `quotes` - The quotes of the chain's contracts.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if known.
+    /// * `scan_time` - The scan timestamp in milliseconds, which the order ids are derived from.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the contenders sorted by rank or an error.
+    #[allow(clippy::too_many_arguments)]
+    pub fn scan(
+        &self,
+        strategies: OptionType,
+        chain: &OptionChain,
+        quotes: &Quotes,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+        scan_time: i64,
+    ) -> Result<Vec<Contender>, Box<dyn Error>> {
+        let mut contenders: Vec<Contender> = self.scanner.scan(
+            &strategies,
+            quotes,
+            chain,
+            current_date,
+            minutes_to_close,
+            spot,
+        )?;
+        for contender in &mut contenders {
+            contender.scan_time = scan_time;
+        }
+        contenders.sort_by(|a, b| b.rank_value.total_cmp(&a.rank_value));
+        Ok(contenders)
+    }
+}
+
+/// Builds the IBKR combo orders of contenders, the order-building stage of the bot.
+pub struct OrderBuilder {
+    account_id: Option<String>,
+    discount_value: Option<f64>,
+    underlying: UnderlyingSpec,
+    sizing: OrderSizing,
+    algo: AlgoConfig,
+}
+
+impl OrderBuilder {
+    /// Creates a builder for single-price limit orders routed through SMART, without an algo.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The account the orders are for.
+    /// * `ticker` - The underlying, e.g. `"SPX"`, which sets the tick size and listing exchange.
+    /// * `discount_value` - The amount added to each order's mid price.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let builder = OrderBuilder::new("DU1234567", "SPX", 0.0);
+    /// ```
+    pub fn new(account_id: &str, ticker: &str, discount_value: f64) -> Self {
+        OrderBuilder {
+            account_id: Some(account_id.to_string()),
+            discount_value: Some(discount_value),
+            underlying: UnderlyingSpec::for_ticker(ticker),
+            sizing: OrderSizing {
+                liquidity: LiquiditySizing::Off,
+                ladder: OrderLadder::default(),
+            },
+            algo: AlgoConfig::default(),
+        }
+    }
+
+    /// Builds the orders of each contender. Contenders that would trade against the orders of a
+    /// better-ranked contender are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `contenders` - The contenders, best first.
+    /// * `chain` - The option chain the contenders were scanned from, for the leg conids.
+    /// * `num_fills` - The number of combos of each order.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<(Contender, Vec<OrderBody>)>` with each remaining contender and its orders.
+    pub fn build(
+        &self,
+        contenders: &[Contender],
+        chain: &OptionChain,
+        num_fills: i32,
+    ) -> Vec<(Contender, Vec<OrderBody>)> {
+        let orders: Vec<OrderBody> = build_request_data(
+            contenders,
+            num_fills,
+            &self.account_id,
+            Some(chain),
+            self.discount_value,
+            &self.underlying,
+            &self.sizing,
+            &self.algo,
+        )
+        .orders;
+
+        contenders
+            .iter()
+            .filter_map(|contender| {
+                // Every order id of a contender shares the prefix before the order index.
+                let order_id: String = contender.order_id(0);
+                let prefix: &str = order_id.strip_suffix('0').unwrap_or(&order_id);
+                let contender_orders: Vec<OrderBody> = orders
+                    .iter()
+                    .filter(|order| order.c_oid.starts_with(prefix))
+                    .cloned()
+                    .collect();
+                (!contender_orders.is_empty()).then(|| (contender.clone(), contender_orders))
+            })
+            .collect()
+    }
+}
+
+/// A destination for the orders of contenders, such as a brokerage account or a simulator.
+pub trait Broker {
+    /// Places the orders built for a contender.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender` - The contender the orders were built from.
+    /// * `orders` - Its orders.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is an error if the orders could not be placed.
+    fn place_orders(
+        &mut self,
+        contender: &Contender,
+        orders: &[OrderBody],
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Cancels every working order.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is an error if the orders could not be canceled.
+    fn cancel_orders(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Returns the number of orders still working.
+    fn working_order_count(&self) -> usize;
+}
@@ -0,0 +1,1581 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fs::File,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::backtest::{load_snapshots, run_backtest, BacktestParams, BacktestTrade, Snapshot};
+use crate::control::{
+    is_paused, serve_control_plane, set_contenders, spawn_scan_webhook, take_scan_filter,
+    update_status, ScanFilter,
+};
+use crate::dividends::{DividendCalendar, EarlyExerciseScreen};
+use crate::error_report::{
+    init_error_reporting, install_panic_hook, set_contender_summary, set_iteration, ErrorReporting,
+};
+use crate::events::{clear_sinks, emit, init_events, Event};
+use crate::fast_market::FastMarket;
+use crate::fill_model::FillModel;
+use crate::gateway_log::spawn_gateway_log_tail;
+use crate::helpers::{
+    apply_profile, calc_final_num_orders, format_strike, get_algo_config, get_arb_value,
+    get_assignment_check_seconds, get_auto_roll_days, get_backtest_seed, get_box_financing,
+    get_clock_skew_config, get_close_offset, get_combo_quotes, get_dedup_seconds,
+    get_discount_value, get_dividend_calendar, get_dotenv_variable, get_early_exercise_screen,
+    get_events_config, get_exchange_routing, get_expiration_calendar, get_expiry_filter,
+    get_expiry_selectors, get_export_contenders, get_fast_market_config, get_fill_cooldown_seconds,
+    get_fill_model, get_fill_poll_seconds, get_fill_type, get_gateway_log_path, get_grpc_addr,
+    get_gth_config, get_http_config, get_leg_pricing, get_liquidity_sizing, get_log_level,
+    get_margin_efficient_ranking, get_max_contender_correlation, get_max_daily_loss,
+    get_max_iv_deviation, get_max_loss_per_trade, get_max_margin_usage, get_metrics_addr,
+    get_min_quote_quality, get_mode, get_monte_carlo_config, get_near_miss_epsilon,
+    get_optimize_folds, get_option, get_order_ladder, get_order_ttl_seconds, get_param_grid,
+    get_portfolio_cache_config, get_preferred_account, get_rate_config, get_record_quotes,
+    get_redaction_config, get_requote_config, get_scan_schedule, get_scan_webhook_addr,
+    get_scan_webhook_token, get_scan_when_locked, get_seconds_to_sleep, get_secrets_config,
+    get_shadow_config, get_shared_state_config, get_skip_preflight, get_state_path,
+    get_strategy_config, get_strike_dif_value, get_threshold_schedule, get_ticker,
+    get_watchdog_seconds, get_watchlist_path, get_xsp_fallback_net_liq, take_flag_arg,
+    take_profile_arg,
+};
+use crate::ibkr::{
+    preflight::{can_resume_ordering, must_stop_ordering},
+    IbkrConfig, IBKR,
+};
+use crate::implied_rates::{implied_rate_curve, write_curve, ExpiryRate};
+use crate::latency::StageTimings;
+use crate::lock::InstanceLock;
+use crate::logging::{
+    log_debug, log_detail, log_error, log_message, log_summary, print_contender_table,
+    set_log_level, set_pretty, start_correlation,
+};
+use crate::machine::{spawn_command_reader, wait_for_event, Command, LoopEvent, Phase, Timings};
+use crate::metrics::spawn_metrics_server;
+use crate::monte_carlo::{simulate, MonteCarloReport};
+use crate::optimize::{OptimizeParams, ParamGrid, ParamSet, WalkForwardWindow};
+use crate::orders::OrderSizing;
+use crate::positions::{parse_combo_args, ComboRequest, OpenCombo};
+use crate::rates::{RateClient, RateCurve};
+use crate::redact::init_redaction;
+use crate::reload::ConfigWatcher;
+use crate::report::{build_report, from_backtest, load_journal, write_report, Report, ReportTrade};
+use crate::scanner::{OptionType, Scanner};
+use crate::schedule::{ScanSchedule, SessionCalendar, ThresholdSchedule, TradingSession};
+use crate::secrets::load_secrets;
+use crate::setup::{load_bot_config, run_setup, BOT_CONFIG_PATH};
+use crate::shared_state::{
+    check_daily_loss, check_kill_switch, RiskCheck, SharedState, MAX_PNL_FAILURES,
+};
+use crate::structs::{
+    Contender, GthConfig, RateConfig, SecretsConfig, ShadowConfig, SharedStateConfig,
+    StrategyConfig,
+};
+use crate::testutil::{synthetic_snapshots, ChainParams};
+use crate::underlying::UnderlyingSpec;
+use crate::watchdog::{enter_phase, stop_watching, take_abort};
+use crate::watchlist::{import_watchlist, parse_quote_args, QuoteRequest};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::America::New_York;
+use rand::rngs::ThreadRng;
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Replays recorded quotes through the scanners and fill model and prints the results per strategy.
+///
+/// The scanners, discount, sizing and fill model are configured from the `.env` file exactly as for
+/// live trading.
+///
+/// # Arguments
+///
+/// * `path` - The path of the recorded quotes file.
+/// * `report_path` - Where to write the performance report, if anywhere.
+///
+/// # Example
+///
+/// ```
+/// backtest("quotes.jsonl", Some("backtest.html"));
+/// ```
+fn backtest(path: &str, report_path: Option<&str>) {
+    let snapshots: Vec<Snapshot> = if !Path::new(path).exists() {
+        println!(
+            "{} not found, backtesting a synthetic session instead.",
+            path
+        );
+        synthetic_snapshots(
+            &ChainParams {
+                num_expiries: 5,
+                num_strikes: 60,
+                seed: get_backtest_seed(),
+                ..ChainParams::default()
+            },
+            Utc::now().date_naive(),
+            30,
+        )
+    } else {
+        match load_snapshots(path) {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                println!("Failed to load {}: {}", path, e);
+                exit(1);
+            }
+        }
+    };
+
+    let option: String = get_option();
+    let fill: String = get_fill_type();
+    let strike_dif_value: f64 = get_strike_dif_value();
+    let fill_model: FillModel = get_fill_model();
+    let (num_orders, num_fills) = calc_final_num_orders(&fill, 100000.0);
+    let seed: u64 = get_backtest_seed();
+
+    let scanner: Scanner = Scanner {
+        arb_val: get_arb_value(),
+        butterfly_config: get_strategy_config("BUTTERFLY", strike_dif_value),
+        boxspread_config: get_strategy_config("BOXSPREAD", strike_dif_value),
+        calendar_config: get_strategy_config("CALENDAR", strike_dif_value),
+        expiry_filter: get_expiry_filter(),
+        include_below_threshold: false,
+        near_miss_epsilon: None,
+        early_exercise: None,
+        dividends: get_dividend_calendar(&get_ticker()),
+        rates: RateCurve::flat(get_rate_config().fallback_rate),
+        price_scale: UnderlyingSpec::for_ticker(&get_ticker()).price_scale,
+        leg_pricing: get_leg_pricing(),
+        skipped_expiries: Vec::new(),
+        box_financing: get_box_financing(),
+        max_iv_deviation: get_max_iv_deviation(),
+    };
+    let params: BacktestParams = BacktestParams {
+        scanner: &scanner,
+        option_type: OptionType::from_str(&option).unwrap_or(OptionType::All),
+        num_orders: num_orders.max(0) as usize,
+        num_fills,
+        discount_value: get_discount_value(),
+        fill_model: &fill_model,
+        seed,
+    };
+
+    let trades: Vec<BacktestTrade> = match run_backtest(&snapshots, &params) {
+        Ok(trades) => trades,
+        Err(e) => {
+            println!("Backtest failed: {}", e);
+            exit(1);
+        }
+    };
+
+    let mut by_strategy: BTreeMap<&str, (usize, usize, f64, f64, f64)> = BTreeMap::new();
+    for trade in &trades {
+        let entry: &mut (usize, usize, f64, f64, f64) =
+            by_strategy.entry(&trade.strategy).or_default();
+        entry.0 += 1;
+        entry.4 += trade.fill_probability;
+        if trade.filled {
+            entry.1 += 1;
+            entry.2 += trade.quoted_edge;
+            entry.3 += trade.realized_edge;
+        }
+    }
+
+    println!(
+        "Replayed {} snapshots, {} contenders.",
+        snapshots.len(),
+        trades.len()
+    );
+    for (strategy, (contenders, fills, quoted_edge, realized_edge, fill_probability)) in by_strategy
+    {
+        println!(
+            "{}: {}/{} filled (expected {:.0}%), quoted edge {:.2}, realized edge {:.2}.",
+            strategy,
+            fills,
+            contenders,
+            fill_probability / contenders as f64 * 100.0,
+            quoted_edge,
+            realized_edge
+        );
+    }
+
+    let mut rng: StdRng = StdRng::seed_from_u64(seed);
+    let report: MonteCarloReport = simulate(&trades, &get_monte_carlo_config(), &mut rng);
+    if report.simulations > 0 {
+        println!(
+            "Monte Carlo over {} simulations: P&L mean {:.2}, 5th {:.2}, median {:.2}, 95th {:.2}, loss probability {:.0}%.",
+            report.simulations,
+            report.mean_pnl,
+            report.pnl_p5,
+            report.pnl_p50,
+            report.pnl_p95,
+            report.loss_probability * 100.0
+        );
+        println!(
+            "Max drawdown: median {:.2}, 95th {:.2}, worst {:.2}.",
+            report.drawdown_p50, report.drawdown_p95, report.worst_drawdown
+        );
+    }
+
+    if let Some(report_path) = report_path {
+        let report: Report = build_report(&from_backtest(&trades));
+        match write_report(&report, report_path) {
+            Ok(_) => println!("Wrote report to {}.", report_path),
+            Err(e) => println!("Failed to write {}: {}", report_path, e),
+        }
+    }
+}
+
+/// Builds a performance report from the fills of the live trade journal.
+///
+/// # Arguments
+///
+/// * `path` - The path of the journal file.
+/// * `report_path` - Where to write the report, as HTML if it ends in `.html` and CSV otherwise.
+///
+/// # Example
+///
+/// ```
+/// report("journal.jsonl", "report.html");
+/// ```
+fn report(path: &str, report_path: &str) {
+    let trades: Vec<ReportTrade> = match load_journal(path) {
+        Ok(trades) => trades,
+        Err(e) => {
+            println!("Failed to load {}: {}", path, e);
+            exit(1);
+        }
+    };
+
+    let report: Report = build_report(&trades);
+    println!(
+        "{} fills, win rate {:.1}%, P&L {:.2}, edge captured {:.1}%, Sharpe {:.2}, max drawdown {:.2}.",
+        report.overall.trades,
+        report.overall.win_rate * 100.0,
+        report.overall.total_pnl,
+        report.overall.edge_captured * 100.0,
+        report.overall.sharpe,
+        report.overall.max_drawdown
+    );
+    match write_report(&report, report_path) {
+        Ok(_) => println!("Wrote report to {}.", report_path),
+        Err(e) => println!("Failed to write {}: {}", report_path, e),
+    }
+}
+
+/// Sweeps the parameter grid over recorded quotes with walk-forward windows and prints the best
+/// parameter set of each window along with its out-of-sample edge.
+///
+/// # Arguments
+///
+/// * `path` - The path of the recorded quotes file.
+///
+/// # Example
+///
+/// ```
+/// optimize("quotes.jsonl");
+/// ```
+fn optimize(path: &str) {
+    let snapshots: Vec<Snapshot> = match load_snapshots(path) {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            println!("Failed to load {}: {}", path, e);
+            exit(1);
+        }
+    };
+
+    let option: String = get_option();
+    let fill: String = get_fill_type();
+    let strike_dif_value: f64 = get_strike_dif_value();
+    let fill_model: FillModel = get_fill_model();
+    let (num_orders, num_fills) = calc_final_num_orders(&fill, 100000.0);
+    let butterfly_config: StrategyConfig = get_strategy_config("BUTTERFLY", strike_dif_value);
+
+    let grid: ParamGrid = get_param_grid(&ParamSet {
+        discount_value: get_discount_value(),
+        arb_val: get_arb_value(),
+        wing_widths: butterfly_config.wing_widths,
+        dte_window: butterfly_config.dte_window,
+    });
+    let params: OptimizeParams = OptimizeParams {
+        option_type: OptionType::from_str(&option).unwrap_or(OptionType::All),
+        expiry_filter: get_expiry_filter(),
+        num_orders: num_orders.max(0) as usize,
+        num_fills,
+        fill_model: &fill_model,
+        seed: get_backtest_seed(),
+    };
+
+    println!(
+        "Sweeping {} parameter sets over {} snapshots.",
+        grid.param_sets().len(),
+        snapshots.len()
+    );
+    let windows: Vec<WalkForwardWindow> =
+        match crate::optimize::optimize(&snapshots, &grid, &params, get_optimize_folds()) {
+            Ok(windows) => windows,
+            Err(e) => {
+                println!("Optimization failed: {}", e);
+                exit(1);
+            }
+        };
+
+    for window in &windows {
+        println!(
+            "Train {:?}, test {:?}: discount {:.2}, arb {:.2}, wings {:?}, DTE {:?}; train edge {:.2}, test edge {:.2}.",
+            window.train,
+            window.test,
+            window.best.discount_value,
+            window.best.arb_val,
+            window.best.wing_widths,
+            window.best.dte_window,
+            window.train_edge,
+            window.test_edge
+        );
+    }
+    println!(
+        "Out-of-sample edge: {:.2}.",
+        windows.iter().map(|window| window.test_edge).sum::<f64>()
+    );
+}
+
+/// Reports the financing rates implied by the box spreads of every expiration in the last recorded
+/// scan, alongside the benchmark risk-free curve.
+///
+/// # Arguments
+///
+/// * `path` - The path of the recorded quotes file.
+/// * `out_path` - Where to write the curve, as JSON if it ends in `.json` and CSV otherwise.
+///
+/// # Example
+///
+/// ```
+/// rates("quotes.jsonl", "rates.csv");
+/// ```
+fn rates(path: &str, out_path: &str) {
+    let snapshot: Snapshot = match load_snapshots(path) {
+        Ok(mut snapshots) if !snapshots.is_empty() => snapshots.pop().unwrap(),
+        Ok(_) => {
+            println!("No recorded scans in {}.", path);
+            exit(1);
+        }
+        Err(e) => {
+            println!("Failed to load {}: {}", path, e);
+            exit(1);
+        }
+    };
+
+    let rate_config: RateConfig = get_rate_config();
+    let benchmark: RateCurve = RateClient::new(&rate_config)
+        .and_then(|mut rate_client| rate_client.refresh(Utc::now().date_naive()))
+        .unwrap_or_else(|| RateCurve::flat(rate_config.fallback_rate));
+    let widths: Vec<f64> = get_strategy_config("BOXSPREAD", get_strike_dif_value()).wing_widths;
+    let (chain, quotes) = snapshot.to_chain();
+    let curve: Vec<ExpiryRate> =
+        implied_rate_curve(&chain, &quotes, snapshot.date, &widths, &benchmark);
+
+    let percent = |rate: Option<f64>| -> String {
+        rate.map_or("-".to_string(), |rate| format!("{:.2}%", rate * 100.0))
+    };
+    println!(
+        "Implied box rates on {} from {} widths, benchmark {}:",
+        snapshot.date.format("%y%m%d"),
+        widths.len(),
+        benchmark.summary()
+    );
+    for rate in &curve {
+        println!(
+            "{} ({} DTE, {} boxes): mid {}, borrow {}, lend {}, benchmark {}.",
+            rate.exp_date,
+            rate.dte,
+            rate.boxes,
+            percent(Some(rate.mid_rate)),
+            percent(rate.borrow_rate),
+            percent(rate.lend_rate),
+            percent(Some(rate.benchmark_rate))
+        );
+    }
+    match write_curve(&curve, out_path) {
+        Ok(_) => println!("Wrote the rate curve to {}.", out_path),
+        Err(e) => println!("Failed to write {}: {}", out_path, e),
+    }
+}
+
+/// Prices a structure given on the command line and, if asked, submits it through the order path
+/// when it clears the thresholds. A submitted order is saved to the state file, so the next run
+/// resumes tracking it.
+///
+/// # Arguments
+///
+/// * `ibkr` - The connected IBKR client.
+/// * `quote_request` - The structure, and whether and how many to submit.
+/// * `live` - Whether the bot trades live, without which nothing is submitted.
+fn quote(ibkr: &mut IBKR, quote_request: &QuoteRequest, live: bool) {
+    let contender: Contender = match ibkr.quote_structure(&quote_request.entry) {
+        Ok(contender) => contender,
+        Err(e) => {
+            log_message(format!("Failed to quote the structure: {}.", e));
+            return;
+        }
+    };
+    log_message(format!(
+        "Quote {} {}: {} = {:.2}, {} the thresholds ({}).",
+        contender.type_spread,
+        contender.exp_date,
+        contender.reason.formula,
+        contender.reason.checks[0].value,
+        if contender.above_threshold {
+            "clears"
+        } else {
+            "misses"
+        },
+        contender.reason.summary()
+    ));
+    if !quote_request.submit {
+        return;
+    }
+    if !contender.above_threshold {
+        log_message("Not submitting a structure that misses the thresholds.".to_string());
+        return;
+    }
+    if !live {
+        log_message("Not submitting, the bot isn't trading live.".to_string());
+        return;
+    }
+    let mut stage_timings: StageTimings = StageTimings::new();
+    if let Err(e) =
+        ibkr.order_contender_contracts(&[contender], quote_request.num_fills, &mut stage_timings)
+    {
+        log_message(format!("Failed to submit the structure: {}.", e));
+        return;
+    }
+    let state_path: String = get_state_path();
+    if let Err(e) = ibkr.save_state(&state_path) {
+        log_message(format!(
+            "Failed to save the state to {}: {}.",
+            state_path, e
+        ));
+    }
+}
+
+/// Picks the held combo a `close` or `roll` request names, listing the held combos if it names
+/// none.
+///
+/// # Arguments
+///
+/// * `ibkr` - The connected IBKR client.
+/// * `combo_request` - The combo, or none to list them.
+/// * `strategy` - The strategy the combo must be, e.g. `"Calendar"` to roll, or `None` for any.
+///
+/// # Returns
+///
+/// An `Option` with the combo and the number of combos to close or roll, or `None` after logging
+/// why there is nothing to do.
+fn select_held_combo(
+    ibkr: &IBKR,
+    combo_request: &ComboRequest,
+    strategy: Option<&str>,
+) -> Option<(OpenCombo, i32)> {
+    let held_combos: Vec<(OpenCombo, f64)> = match ibkr.held_combos("journal.jsonl") {
+        Ok(held_combos) => held_combos
+            .into_iter()
+            .filter(|(combo, _)| strategy.is_none_or(|strategy| combo.strategy == strategy))
+            .collect(),
+        Err(e) => {
+            log_message(format!("Failed to look up the open combos: {}.", e));
+            return None;
+        }
+    };
+    let Some(order_ref) = &combo_request.order_ref else {
+        if held_combos.is_empty() {
+            log_message("No combos from the journal are held.".to_string());
+        }
+        for (combo, held) in &held_combos {
+            log_message(format!("Held {}: {}.", held, combo.describe()));
+        }
+        return None;
+    };
+
+    let matches: Vec<&(OpenCombo, f64)> = held_combos
+        .iter()
+        .filter(|(combo, _)| combo.order_ref.starts_with(order_ref.as_str()))
+        .collect();
+    let (combo, held) = match matches.as_slice() {
+        [held_combo] => held_combo,
+        [] => {
+            log_message(format!("No held combo was opened by order {}.", order_ref));
+            return None;
+        }
+        _ => {
+            log_message(format!(
+                "Order {} matches {} held combos, give more of it.",
+                order_ref,
+                matches.len()
+            ));
+            return None;
+        }
+    };
+    match combo_request.num_fills {
+        Some(num_fills) if num_fills as f64 > *held => {
+            log_message(format!(
+                "Only {} of {} are held, not {}.",
+                held, combo.order_ref, num_fills
+            ));
+            None
+        }
+        Some(num_fills) => Some((combo.clone(), num_fills)),
+        None => Some((combo.clone(), *held as i32)),
+    }
+}
+
+/// Lists the combos from the trade journal the account still holds, or sells one of them at its
+/// mid less `CLOSE_OFFSET`.
+///
+/// # Arguments
+///
+/// * `ibkr` - The connected IBKR client.
+/// * `combo_request` - The combo to close, or none to list them.
+/// * `live` - Whether the bot trades live, without which nothing is submitted.
+fn close(ibkr: &mut IBKR, combo_request: &ComboRequest, live: bool) {
+    let Some((combo, quantity)) = select_held_combo(ibkr, combo_request, None) else {
+        return;
+    };
+    if !live {
+        log_message("Not closing, the bot isn't trading live.".to_string());
+        return;
+    }
+    match ibkr.close_combo(&combo, quantity, get_close_offset()) {
+        Ok(order) => log_message(format!(
+            "Closing {} of {} with order {} at {:.2}.",
+            quantity,
+            combo.describe(),
+            order.c_oid,
+            order.price
+        )),
+        Err(e) => log_message(format!("Failed to close {}: {}.", combo.order_ref, e)),
+    }
+}
+
+/// Lists the calendars from the trade journal the account still holds, or rolls the short leg of
+/// one of them to a later expiration at the roll's mid less `CLOSE_OFFSET`.
+///
+/// # Arguments
+///
+/// * `ibkr` - The connected IBKR client.
+/// * `combo_request` - The calendar to roll and the expiration to roll to, or none to list them.
+/// * `live` - Whether the bot trades live, without which nothing is submitted.
+fn roll(ibkr: &mut IBKR, combo_request: &ComboRequest, live: bool) {
+    let Some((combo, quantity)) = select_held_combo(ibkr, combo_request, Some("Calendar")) else {
+        return;
+    };
+    if !live {
+        log_message("Not rolling, the bot isn't trading live.".to_string());
+        return;
+    }
+    match ibkr.roll_calendar(&combo, combo_request.expiry, quantity, get_close_offset()) {
+        Ok(order) => log_message(format!(
+            "Rolling {} of {} with order {} at {:.2}.",
+            quantity,
+            combo.describe(),
+            order.c_oid,
+            order.price
+        )),
+        Err(e) => log_message(format!("Failed to roll {}: {}.", combo.order_ref, e)),
+    }
+}
+
+/// Drops the lowest ranked contenders that would push the combos working across all instances past
+/// the global exposure cap.
+///
+/// # Arguments
+///
+/// * `shared_state` - The risk state shared between instances.
+/// * `contender_contracts` - The contenders about to be ordered, best first.
+/// * `num_fills` - The number of combos ordered per contender.
+/// * `max_global_exposure` - The cap on combos working across all instances.
+fn limit_global_exposure(
+    shared_state: &mut SharedState,
+    contender_contracts: &mut Vec<Contender>,
+    num_fills: i32,
+    max_global_exposure: f64,
+) {
+    let global_exposure: f64 = match shared_state.global_exposure() {
+        Ok(global_exposure) => global_exposure,
+        Err(e) => {
+            log_message(format!(
+                "Failed to get the global exposure, skipping orders: {}.",
+                e
+            ));
+            contender_contracts.clear();
+            return;
+        }
+    };
+
+    let available: f64 = (max_global_exposure - global_exposure).max(0.0);
+    let allowed: usize = (available / num_fills.max(1) as f64).floor() as usize;
+    if contender_contracts.len() > allowed {
+        log_message(format!(
+            "Global exposure of {:.0} combos leaves room for {} of {} contenders.",
+            global_exposure,
+            allowed,
+            contender_contracts.len()
+        ));
+        contender_contracts.truncate(allowed);
+    }
+}
+
+/// Cancels the working orders and saves the state after a panic unwound out of the trading loop,
+/// so no live limit orders are left behind.
+///
+/// # Arguments
+///
+/// * `ibkr` - The IBKR client tracking the working orders.
+/// * `mode` - Whether the bot sends orders.
+/// * `state_path` - The path the state is saved to.
+pub(crate) fn clean_up_after_panic(ibkr: &mut IBKR, mode: bool, state_path: &str) {
+    if mode {
+        log_message("Panicked, cancelling pending orders before exiting.".to_string());
+        ibkr.cancel_pending_orders();
+        if let Err(e) = ibkr.save_state(state_path) {
+            log_message(format!("Failed to save the state: {}.", e));
+        }
+    }
+}
+
+/// Runs the bot application with the command line arguments of the process: the `init`,
+/// `backtest`, `report`, `optimize`, `rates`, `quote`, `close` and `roll` subcommands, or the
+/// trading loop.
+///
+/// This function initializes the bot, connects to the IBKR system, and enters a loop where it checks
+/// if the market is open, calculates the number of orders and fills, retrieves contender contracts,
+/// and submits orders based on the current market conditions.
+///
+/// The loop runs as a state machine (idle, scanning, orders working, cancelling, cooldown) driven by
+/// timers, fills and commands typed on standard input (`scan`, `cancel`, `stop`), so a fill is
+/// reacted to immediately rather than after the orders expire. It runs until the market is closed,
+/// there are no trades to make, or it is stopped.
+///
+/// # Workflow:
+///
+/// 1. Initializes the log file.
+/// 2. Retrieves necessary data (ticker, options, fill type, etc.) from environment variables or user input.
+/// 3. Initializes the IBKR connection.
+/// 4. Enters a loop that performs market checks, calculates orders, submits trades, and logs results.
+/// 5. Handles errors and logs them appropriately.
+///
+/// # Example
+///
+/// ```
+/// trading_bot_rust::run();
+/// ```
+pub fn run() {
+    let mut args: Vec<String> = env::args().collect();
+    match load_bot_config(BOT_CONFIG_PATH) {
+        Ok(0) => {}
+        Ok(num_loaded) => println!("Loaded {} settings from {}.", num_loaded, BOT_CONFIG_PATH),
+        Err(e) if args.get(1).map(String::as_str) == Some("init") => {
+            println!("Ignoring the invalid {}: {}.", BOT_CONFIG_PATH, e)
+        }
+        Err(e) => {
+            println!("Failed to load {}: {}.", BOT_CONFIG_PATH, e);
+            exit(1);
+        }
+    }
+    let profile: Option<String> = take_profile_arg(&mut args);
+    set_pretty(take_flag_arg(&mut args, "--pretty"));
+    set_log_level(get_log_level());
+    if let Some(profile) = &profile {
+        match apply_profile(profile) {
+            Ok(num_overrides) => println!(
+                "Using the {} profile, overriding {} settings.",
+                profile, num_overrides
+            ),
+            Err(e) => {
+                println!("Failed to apply the {} profile: {}.", profile, e);
+                exit(1);
+            }
+        }
+    }
+    match args.get(1).map(String::as_str) {
+        Some("backtest") => {
+            backtest(
+                args.get(2).map_or("quotes.jsonl", String::as_str),
+                args.get(3).map(String::as_str),
+            );
+            return;
+        }
+        Some("report") => {
+            report(
+                args.get(2).map_or("journal.jsonl", String::as_str),
+                args.get(3).map_or("report.html", String::as_str),
+            );
+            return;
+        }
+        Some("init") => {
+            if let Err(e) = run_setup(BOT_CONFIG_PATH) {
+                println!("Setup failed: {}.", e);
+                exit(1);
+            }
+            return;
+        }
+        Some("optimize") => {
+            optimize(args.get(2).map_or("quotes.jsonl", String::as_str));
+            return;
+        }
+        Some("rates") => {
+            rates(
+                args.get(2).map_or("quotes.jsonl", String::as_str),
+                args.get(3).map_or("rates.csv", String::as_str),
+            );
+            return;
+        }
+        _ => {}
+    }
+    // A quote connects like the trading loop, then prices its structure instead of scanning.
+    let quote_request: Option<QuoteRequest> = match args.get(1).map(String::as_str) {
+        Some("quote") => match parse_quote_args(&args[2..]) {
+            Ok(quote_request) => Some(quote_request),
+            Err(e) => {
+                println!("Invalid quote: {}.", e);
+                exit(1);
+            }
+        },
+        _ => None,
+    };
+    let combo_request: Option<(String, ComboRequest)> = match args.get(1).map(String::as_str) {
+        Some(command @ ("close" | "roll")) => match parse_combo_args(&args[2..]) {
+            Ok(combo_request) if command == "close" && combo_request.expiry.is_some() => {
+                println!("Invalid close: --to only applies to roll.");
+                exit(1);
+            }
+            Ok(combo_request) => Some((command.to_string(), combo_request)),
+            Err(e) => {
+                println!("Invalid {}: {}.", command, e);
+                exit(1);
+            }
+        },
+        _ => None,
+    };
+
+    let _ = File::create("log.txt");
+    let secrets_config: SecretsConfig = get_secrets_config();
+    match load_secrets(&secrets_config) {
+        Ok(loaded) if !loaded.is_empty() => log_message(format!(
+            "Loaded {} from the secret backend.",
+            loaded.join(", ")
+        )),
+        Ok(_) => {}
+        Err(e) => log_error(format!("Failed to load secrets: {}", e)),
+    }
+    // Secrets are masked wherever they appear once token redaction is on.
+    let secrets: Vec<String> = secrets_config
+        .keys
+        .iter()
+        .map(String::as_str)
+        .chain(["SCAN_WEBHOOK_TOKEN", "VAULT_TOKEN"])
+        .filter_map(|key| get_dotenv_variable(key).ok())
+        .collect();
+    init_redaction(get_redaction_config(), &secrets);
+    let _error_reporting: ErrorReporting =
+        init_error_reporting(get_dotenv_variable("SENTRY_DSN").ok());
+    install_panic_hook(Path::new("."));
+    if let Err(e) = init_events(&get_events_config()) {
+        log_error(format!("Failed to open the event stream: {}", e));
+    }
+    let mut ibkr: IBKR = IBKR::new();
+
+    let ticker: String = get_ticker();
+    let option: String = get_option();
+    let fill: String = get_fill_type();
+    let shadow: Option<ShadowConfig> = get_shadow_config();
+    // Shadow mode sends orders, but only ever to the paper account.
+    let mut mode: bool = shadow.is_some() || get_mode();
+    if quote_request
+        .as_ref()
+        .is_some_and(|quote_request| !quote_request.submit)
+        || combo_request
+            .as_ref()
+            .is_some_and(|(_, combo_request)| combo_request.order_ref.is_none())
+    {
+        mode = false;
+    }
+    let mut seconds_to_sleep: u64 = get_seconds_to_sleep();
+    let strike_dif_value: f64 = get_strike_dif_value();
+    let max_daily_loss: Option<f64> = get_max_daily_loss();
+    let xsp_fallback_net_liq: Option<f64> = get_xsp_fallback_net_liq();
+    let order_ttl_seconds: Option<u64> = get_order_ttl_seconds();
+    let early_exercise: Option<EarlyExerciseScreen> = get_early_exercise_screen(&ticker);
+    let dividends: DividendCalendar = get_dividend_calendar(&ticker);
+    let mut scan_schedule: ScanSchedule = get_scan_schedule(seconds_to_sleep);
+    let mut arb_value: f64 = get_arb_value();
+    let mut discount_value: f64 = get_discount_value();
+    let mut near_miss_epsilon: Option<f64> = get_near_miss_epsilon();
+    let mut threshold_schedule: ThresholdSchedule = get_threshold_schedule();
+    let mut active_thresholds: (f64, f64) = (arb_value, discount_value);
+    let mut gth_config: GthConfig = get_gth_config(seconds_to_sleep);
+    let mut fast_market: Option<FastMarket> =
+        get_fast_market_config(seconds_to_sleep).map(FastMarket::new);
+    let session_calendar: SessionCalendar = SessionCalendar {
+        overnight: gth_config.enabled,
+    };
+    let shared_state_config: SharedStateConfig = get_shared_state_config();
+    let mut shared_state: SharedState = match SharedState::connect(&shared_state_config, &ticker) {
+        Ok(shared_state) => shared_state,
+        Err(e) => {
+            log_error(format!("Failed to connect the shared state: {}", e));
+            exit(1);
+        }
+    };
+
+    if let Some(metrics_addr) = get_metrics_addr() {
+        match spawn_metrics_server(&metrics_addr) {
+            Ok(()) => log_message(format!(
+                "Serving gateway metrics on http://{}/metrics.",
+                metrics_addr
+            )),
+            Err(e) => log_error(format!(
+                "Failed to serve metrics on {}: {}",
+                metrics_addr, e
+            )),
+        }
+    }
+    if let Some(gateway_log_path) = get_gateway_log_path() {
+        log_message(format!("Tailing the gateway log {}.", gateway_log_path));
+        spawn_gateway_log_tail(PathBuf::from(gateway_log_path), Duration::from_secs(1));
+    }
+
+    match ibkr.init(IbkrConfig {
+        ticker,
+        discount_value,
+        arb_val: arb_value,
+        butterfly_config: get_strategy_config("BUTTERFLY", strike_dif_value),
+        boxspread_config: get_strategy_config("BOXSPREAD", strike_dif_value),
+        calendar_config: get_strategy_config("CALENDAR", strike_dif_value),
+        expiry_filter: get_expiry_filter(),
+        dedup_seconds: get_dedup_seconds(),
+        fill_cooldown_seconds: get_fill_cooldown_seconds(),
+        portfolio_cache_config: get_portfolio_cache_config(),
+        max_margin_usage: get_max_margin_usage(),
+        margin_efficient: get_margin_efficient_ranking(),
+        max_loss: get_max_loss_per_trade(),
+        fill_model: get_fill_model(),
+        record_quotes: get_record_quotes(),
+        combo_quotes: get_combo_quotes(),
+        export_contenders: get_export_contenders(),
+        near_miss_epsilon,
+        leg_pricing: get_leg_pricing(),
+        min_quote_quality: get_min_quote_quality(),
+        box_financing: get_box_financing(),
+        max_iv_deviation: get_max_iv_deviation(),
+        max_correlation: get_max_contender_correlation(),
+        requote: get_requote_config(),
+        early_exercise,
+        rate_config: get_rate_config(),
+        dividends,
+        sizing: OrderSizing {
+            liquidity: get_liquidity_sizing(),
+            ladder: get_order_ladder(),
+        },
+        algo: get_algo_config(),
+        routing: get_exchange_routing(),
+        expiration_calendar: get_expiration_calendar(),
+        order_ttl_seconds: order_ttl_seconds.unwrap_or(seconds_to_sleep),
+        http_config: get_http_config(),
+        account_id: get_preferred_account(),
+        shadow,
+        preflight: !get_skip_preflight(),
+        clock_skew: get_clock_skew_config(),
+        domain: match get_dotenv_variable("DOMAIN") {
+            Ok(val) => val,
+            Err(_) => "localhost".to_string(),
+        },
+        port: match get_dotenv_variable("PORT") {
+            Ok(val) => val,
+            Err(_) => "5000".to_string(),
+        },
+        expiry_selectors: get_expiry_selectors(),
+    }) {
+        Ok(_) => log_message("Bot is live.".to_string()),
+        Err(e) => log_error(format!("{}", e)),
+    }
+
+    // The lock and the order monitor are only set up for an ordering bot, so a bot that starts on
+    // delayed data scans until it is restarted.
+    if must_stop_ordering(mode, ibkr.has_delayed_data()) {
+        log_message(
+            "WARNING: market data is delayed, scanning without trading until restarted."
+                .to_string(),
+        );
+        mode = false;
+    }
+    // Whether ordering stopped when market data turned delayed mid-run.
+    let mut downgraded: bool = false;
+
+    // Keep the lock for the lifetime of the bot so a second copy can't trade the same account.
+    let _instance_lock: Option<InstanceLock> = if mode {
+        match InstanceLock::acquire(ibkr.account_id().unwrap_or_default()) {
+            Ok(instance_lock) => Some(instance_lock),
+            Err(e) if get_scan_when_locked() => {
+                log_message(format!("{}, scanning without trading.", e));
+                mode = false;
+                None
+            }
+            Err(e) => {
+                log_error(format!("{}, refusing to start.", e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Some(quote_request) = &quote_request {
+        quote(&mut ibkr, quote_request, mode);
+        return;
+    }
+    if let Some((command, combo_request)) = &combo_request {
+        match command.as_str() {
+            "roll" => roll(&mut ibkr, combo_request, mode),
+            _ => close(&mut ibkr, combo_request, mode),
+        }
+        return;
+    }
+
+    update_status(|status| {
+        status.live = mode;
+        status.account_id = ibkr.account_id().map(str::to_string);
+    });
+    if let Some(grpc_addr) = get_grpc_addr() {
+        serve_control_plane(&grpc_addr);
+    }
+
+    // Resume the orders a previous run left working, once the lock makes the account ours.
+    let state_path: String = get_state_path();
+    if mode {
+        if let Err(e) = ibkr.restore_state(&state_path) {
+            log_message(format!("Failed to restore the saved state: {}.", e));
+        }
+        // Combos filled by earlier runs count towards the global exposure.
+        if Path::new("journal.jsonl").exists() {
+            match ibkr.held_combos("journal.jsonl") {
+                Ok(held_combos) => {
+                    shared_state.set_filled(held_combos.iter().map(|(_, held)| held).sum())
+                }
+                Err(e) => log_message(format!("Failed to get the held combos: {}.", e)),
+            }
+        }
+    }
+
+    if mode {
+        if let Err(e) = ibkr.spawn_order_monitor(Duration::from_secs(1)) {
+            log_error(format!("Failed to start the order monitor: {}", e));
+        }
+        if let Some(assignment_check_seconds) = get_assignment_check_seconds() {
+            ibkr.set_assignment_checks(Duration::from_secs(assignment_check_seconds));
+        }
+        if let Some(auto_roll_days) = get_auto_roll_days() {
+            ibkr.set_auto_roll(auto_roll_days, get_close_offset());
+        }
+    } else {
+        ibkr.start_paper_trading(get_backtest_seed());
+    }
+    if let Some(watchdog_seconds) = get_watchdog_seconds() {
+        ibkr.spawn_watchdog(Duration::from_secs(watchdog_seconds));
+    }
+    let mut timings: Timings = Timings {
+        working: Duration::from_secs(seconds_to_sleep),
+        cooldown: Duration::from_secs(5),
+    };
+    let mut rng: ThreadRng = rand::thread_rng();
+    let fill_poll_interval: Duration = Duration::from_secs(get_fill_poll_seconds());
+    let (command_sender, commands): (Sender<Command>, Receiver<Command>) = mpsc::channel();
+    if let Some(webhook_addr) = get_scan_webhook_addr() {
+        match spawn_scan_webhook(
+            &webhook_addr,
+            get_scan_webhook_token(),
+            command_sender.clone(),
+        ) {
+            Ok(()) => log_message(format!("Listening for scan requests on {}.", webhook_addr)),
+            Err(e) => log_error(format!(
+                "Failed to listen for scan requests on {}: {}",
+                webhook_addr, e
+            )),
+        }
+    }
+    let watchlist_path: String = get_watchlist_path();
+    if Path::new(&watchlist_path).exists() {
+        match import_watchlist(&watchlist_path) {
+            Ok(count) => log_message(format!(
+                "Watching {} structures from {}.",
+                count, watchlist_path
+            )),
+            Err(e) => log_message(format!(
+                "WARNING: failed to import the watchlist from {}: {}.",
+                watchlist_path, e
+            )),
+        }
+    }
+    spawn_command_reader(command_sender);
+    let mut config_watcher: Option<ConfigWatcher> = match ConfigWatcher::new(profile) {
+        Ok(config_watcher) => {
+            log_message(format!(
+                "Watching {} for changes.",
+                config_watcher.path().display()
+            ));
+            Some(config_watcher)
+        }
+        Err(e) => {
+            log_message(format!("Not watching the config file: {}.", e));
+            None
+        }
+    };
+
+    let mut iteration: u64 = 0;
+    // The error that stopped the loop, reported once the working orders are canceled.
+    let mut failure: Option<String> = None;
+    let mut pnl_failures: u32 = 0;
+    // Why the risk checks of this iteration allow scanning but not ordering.
+    let mut skip_orders: Option<&str> = None;
+    let loop_result: thread::Result<()> = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut phase: Phase = Phase::Idle;
+        let mut num_orders: i32 = 0;
+        let mut num_fills: i32 = 0;
+        let mut port_val: f64;
+        let mut stage_timings: StageTimings = StageTimings::new();
+        while phase != Phase::Stopped {
+            let allowance: Duration = match phase {
+                Phase::OrdersWorking { deadline } | Phase::Cooldown { deadline } => {
+                    deadline.saturating_duration_since(Instant::now())
+                }
+                _ => Duration::ZERO,
+            };
+            enter_phase(iteration, phase.name(), allowance);
+            let working_orders: usize = ibkr.working_order_count();
+            update_status(|status| {
+                status.phase = phase.name().to_string();
+                status.iteration = iteration;
+                status.working_orders = working_orders;
+            });
+            let event: LoopEvent = match phase {
+                Phase::Idle => 'idle: {
+                    // Apply config file changes between iterations, never mid-scan.
+                    match config_watcher.as_mut().map(ConfigWatcher::poll) {
+                        Some(Ok(Some(change))) => {
+                            for key in &change.rejected {
+                                log_message(format!(
+                                    "Ignoring the change to {}: it only takes effect after a restart.",
+                                    key
+                                ));
+                            }
+                            if !change.applied.is_empty() {
+                                log_message(format!(
+                                    "Reloaded {} from the config file.",
+                                    change
+                                        .applied
+                                        .iter()
+                                        .map(|(key, value)| format!("{}={}", key, value))
+                                        .collect::<Vec<String>>()
+                                        .join(", ")
+                                ));
+                                arb_value = get_arb_value();
+                                discount_value = get_discount_value();
+                                near_miss_epsilon = get_near_miss_epsilon();
+                                threshold_schedule = get_threshold_schedule();
+                                seconds_to_sleep = get_seconds_to_sleep();
+                                scan_schedule = get_scan_schedule(seconds_to_sleep);
+                                gth_config = get_gth_config(seconds_to_sleep);
+                                set_log_level(get_log_level());
+                                if change.applies("EVENTS_") {
+                                    clear_sinks();
+                                    if let Err(e) = init_events(&get_events_config()) {
+                                        log_message(format!(
+                                            "Failed to reopen the event stream: {}.",
+                                            e
+                                        ));
+                                    }
+                                }
+                            }
+                        }
+                        Some(Err(e)) => {
+                            log_message(format!("Failed to reload the config file: {}.", e))
+                        }
+                        _ => {}
+                    }
+
+                    let session: TradingSession = if mode {
+                        let now: DateTime<Utc> = Utc::now();
+                        let Some(session) = session_calendar.session_at(now) else {
+                            let Some(wait) = session_calendar.next_session_in(now) else {
+                                log_message("Market is closed.".to_string());
+                                break 'idle LoopEvent::Halt;
+                            };
+                            log_message(format!(
+                                "Market is closed, waiting {} seconds for the next session.",
+                                wait.as_secs()
+                            ));
+                            timings.working = wait;
+                            break 'idle LoopEvent::Skip;
+                        };
+                        session
+                    } else {
+                        TradingSession::Regular
+                    };
+
+                    let interval: u64 = match session {
+                        // Only box spreads trade overnight, on their own cadence.
+                        TradingSession::Overnight => {
+                            if !matches!(
+                                OptionType::from_str(&option),
+                                Some(OptionType::BoxSpread | OptionType::All)
+                            ) {
+                                let wait: Duration = session_calendar
+                                    .next_session_in(Utc::now())
+                                    .unwrap_or(Duration::from_secs(gth_config.seconds_to_sleep));
+                                log_message(format!(
+                                    "Only box spreads trade overnight, waiting {} seconds.",
+                                    wait.as_secs()
+                                ));
+                                timings.working = wait;
+                                break 'idle LoopEvent::Skip;
+                            }
+                            gth_config.seconds_to_sleep
+                        }
+                        TradingSession::Regular => {
+                            let ny_time: NaiveTime = Utc::now().with_timezone(&New_York).time();
+                            let Some(interval) = scan_schedule.interval_at(ny_time) else {
+                                let Some(wait) = scan_schedule.next_scan_in(ny_time) else {
+                                    log_message("No more scans scheduled today.".to_string());
+                                    break 'idle LoopEvent::Halt;
+                                };
+                                log_message(format!(
+                                    "Outside the scan schedule, waiting {} seconds.",
+                                    wait.as_secs()
+                                ));
+                                timings.working = wait;
+                                break 'idle LoopEvent::Skip;
+                            };
+                            interval
+                        }
+                    };
+                    ibkr.set_session(session);
+                    let mut thresholds: (f64, f64) = threshold_schedule.thresholds_at(
+                        Utc::now().with_timezone(&New_York).time(),
+                        arb_value,
+                        discount_value,
+                    );
+                    // A fast market scans more often and demands more edge until it calms down.
+                    let interval: u64 = match &fast_market {
+                        Some(fast_market) => {
+                            thresholds.0 = fast_market.arb_threshold(thresholds.0);
+                            fast_market.interval(interval)
+                        }
+                        None => interval,
+                    };
+                    if thresholds != active_thresholds {
+                        log_message(format!(
+                            "Arb threshold is now {:.2} with a discount of {:.2}.",
+                            thresholds.0, thresholds.1
+                        ));
+                        active_thresholds = thresholds;
+                    }
+                    ibkr.set_thresholds(thresholds.0, near_miss_epsilon, thresholds.1);
+                    let cadence: Duration = scan_schedule.jitter(interval, &mut rng);
+                    let session_order_ttl_seconds: Option<u64> = match session {
+                        TradingSession::Overnight => {
+                            gth_config.order_ttl_seconds.or(order_ttl_seconds)
+                        }
+                        TradingSession::Regular => order_ttl_seconds,
+                    };
+                    let order_ttl: Duration =
+                        session_order_ttl_seconds.map_or(cadence, Duration::from_secs);
+                    ibkr.set_order_ttl(order_ttl);
+                    // Orders with a shorter TTL are canceled by the monitor, ending the phase early.
+                    timings.working = cadence.max(order_ttl);
+
+                    iteration += 1;
+                    set_iteration(iteration);
+                    start_correlation(iteration);
+                    ibkr.refresh_rates();
+                    stage_timings = StageTimings::new();
+
+                    if !mode {
+                        port_val = 100000.0;
+                    } else {
+                        let account_start: Instant = Instant::now();
+                        skip_orders = None;
+                        let killed: Result<bool, Box<dyn Error>> = shared_state.is_killed();
+                        if let Err(e) = &killed {
+                            log_message(format!("Failed to check the kill switch: {}.", e));
+                        }
+                        match check_kill_switch(&killed) {
+                            RiskCheck::Killed => {
+                                log_message("Kill switch is set, stopping.".to_string());
+                                break 'idle LoopEvent::Halt;
+                            }
+                            RiskCheck::SkipOrders => {
+                                skip_orders = Some("the kill switch can't be read")
+                            }
+                            _ => {}
+                        }
+
+                        match ibkr.get_portfolio_value() {
+                            Ok(port_value) => {
+                                port_val = port_value;
+                            }
+                            Err(e) => {
+                                failure = Some(format!("{}", e));
+                                break 'idle LoopEvent::Failed;
+                            }
+                        }
+
+                        if let Some(xsp_fallback_net_liq) = xsp_fallback_net_liq {
+                            if port_val < xsp_fallback_net_liq && ibkr.underlying().has_mini() {
+                                log_message(format!(
+                                    "Net liquidation of {:.2} is below {:.2}, switching to XSP.",
+                                    port_val, xsp_fallback_net_liq
+                                ));
+                                if let Err(e) = ibkr.switch_underlying("XSP") {
+                                    log_message(format!("Failed to switch to XSP: {}.", e));
+                                }
+                            }
+                        }
+
+                        let daily_pnl: Option<f64> = match ibkr.get_pnl() {
+                            Ok(pnl) => {
+                                log_message(format!(
+                                    "Daily P&L: {:.2}, unrealized P&L: {:.2}.",
+                                    pnl.daily, pnl.unrealized
+                                ));
+                                pnl_failures = 0;
+                                Some(pnl.daily)
+                            }
+                            Err(e) => {
+                                emit(&Event::Error {
+                                    message: format!("Failed to get P&L: {}", e),
+                                });
+                                log_message(format!("Failed to get P&L: {}.", e));
+                                pnl_failures += 1;
+                                None
+                            }
+                        };
+                        match check_daily_loss(daily_pnl, max_daily_loss, pnl_failures) {
+                            RiskCheck::SkipOrders => skip_orders = Some("the daily P&L is unknown"),
+                            RiskCheck::LimitReached => {
+                                let daily_loss: f64 = -daily_pnl.unwrap_or_default();
+                                log_message(format!(
+                                    "Daily loss of {:.2} reached the limit of {:.2}, stopping.",
+                                    daily_loss,
+                                    max_daily_loss.unwrap_or_default()
+                                ));
+                                if let Err(e) = shared_state
+                                    .trip_kill_switch(&format!("daily loss of {:.2}", daily_loss))
+                                {
+                                    log_message(format!("Failed to set the kill switch: {}.", e));
+                                }
+                                break 'idle LoopEvent::Halt;
+                            }
+                            RiskCheck::PnlUnavailable => {
+                                log_message(format!(
+                                    "Failed to get P&L {} times in a row with a daily loss limit set, stopping.",
+                                    MAX_PNL_FAILURES
+                                ));
+                                break 'idle LoopEvent::Halt;
+                            }
+                            _ => {}
+                        }
+
+                        match ibkr.update_executions() {
+                            Ok(filled) => shared_state.record_fill(filled),
+                            Err(e) => {
+                                emit(&Event::Error {
+                                    message: format!("Failed to get executions: {}", e),
+                                });
+                                log_message(format!("Failed to get executions: {}.", e));
+                            }
+                        }
+                        match ibkr.check_assignments("journal.jsonl") {
+                            Ok(settlements) => {
+                                for settlement in &settlements {
+                                    shared_state.record_settlement(settlement.size);
+                                }
+                            }
+                            Err(e) => {
+                                emit(&Event::Error {
+                                    message: format!("Failed to check for assignments: {}", e),
+                                });
+                                log_message(format!("Failed to check for assignments: {}.", e));
+                            }
+                        }
+                        if let Err(e) = ibkr.roll_expiring_calendars("journal.jsonl") {
+                            emit(&Event::Error {
+                                message: format!("Failed to roll expiring calendars: {}", e),
+                            });
+                            log_message(format!("Failed to roll expiring calendars: {}.", e));
+                        }
+                        if let Err(e) = shared_state.publish_exposure() {
+                            log_message(format!("Failed to update exposure: {}.", e));
+                        }
+                        stage_timings.finish("account_check", account_start);
+                    }
+
+                    // Order sizing is in SPX terms, so a smaller underlying fits more orders.
+                    (num_orders, num_fills) =
+                        calc_final_num_orders(&fill, port_val / ibkr.underlying().price_scale);
+                    if num_orders > 0 {
+                        LoopEvent::Ready
+                    } else {
+                        log_message("Not enough equity in account to make a trade.".to_string());
+                        LoopEvent::Halt
+                    }
+                }
+                Phase::Scanning => {
+                    let start_time: Instant = Instant::now();
+                    update_status(|status| status.last_scan_time = Utc::now().timestamp_millis());
+                    let mut end_time: Option<Duration> = None;
+                    let mut event: LoopEvent = LoopEvent::NothingPlaced;
+                    let mut num_submitted: usize = 0;
+                    let scan_filter: Option<ScanFilter> = take_scan_filter();
+                    match ibkr.get_contender_contracts(
+                        &option,
+                        num_orders,
+                        num_fills,
+                        scan_filter.as_ref(),
+                        &mut stage_timings,
+                    ) {
+                        Ok(mut contender_contracts) => {
+                            if must_stop_ordering(mode, ibkr.has_delayed_data()) {
+                                log_message(
+                                    "WARNING: market data turned delayed, canceling the working orders and scanning without trading."
+                                        .to_string(),
+                                );
+                                ibkr.cancel_pending_orders();
+                                mode = false;
+                                downgraded = true;
+                                update_status(|status| status.live = false);
+                            } else if can_resume_ordering(downgraded, ibkr.has_delayed_data()) {
+                                log_message(
+                                    "Market data is real-time again, resuming trading.".to_string(),
+                                );
+                                mode = true;
+                                downgraded = false;
+                                update_status(|status| status.live = true);
+                            }
+                            set_contender_summary(
+                                contender_contracts
+                                    .iter()
+                                    .map(|contender| {
+                                        format!(
+                                            "{} {} @ {:.2}",
+                                            contender.type_spread,
+                                            contender.exp_date,
+                                            contender.arb_val
+                                        )
+                                    })
+                                    .collect::<Vec<String>>()
+                                    .join("; "),
+                            );
+                            if let Some(max_global_exposure) =
+                                shared_state_config.max_global_exposure
+                            {
+                                limit_global_exposure(
+                                    &mut shared_state,
+                                    &mut contender_contracts,
+                                    num_fills,
+                                    max_global_exposure,
+                                );
+                            }
+                            set_contenders(&contender_contracts);
+                            if let Some(reason) =
+                                skip_orders.filter(|_| mode && !contender_contracts.is_empty())
+                            {
+                                log_message(format!("Not placing orders: {}.", reason));
+                            } else if is_paused() && !contender_contracts.is_empty() {
+                                log_message("Trading is paused, not placing orders.".to_string());
+                            } else if !contender_contracts.is_empty()
+                                && !scan_schedule
+                                    .trading_allowed_at(Utc::now().with_timezone(&New_York).time())
+                            {
+                                log_message(
+                                    "Inside a no-trade window, not placing orders.".to_string(),
+                                );
+                            } else if !contender_contracts.is_empty() {
+                                if mode {
+                                    match ibkr.order_contender_contracts(
+                                        &contender_contracts,
+                                        num_fills,
+                                        &mut stage_timings,
+                                    ) {
+                                        Ok(_) => {
+                                            log_message("Ordering Contracts...".to_string());
+                                            num_submitted = contender_contracts.len();
+                                            event = LoopEvent::OrdersPlaced;
+                                        }
+                                        Err(e) => {
+                                            // Earlier batches may be working, so cancel them before exiting.
+                                            failure = Some(format!("{}", e));
+                                            event = LoopEvent::Failed;
+                                        }
+                                    }
+                                    shared_state.set_working(
+                                        (contender_contracts.len() as i32 * num_fills) as f64,
+                                    );
+                                    if let Err(e) = shared_state.publish_exposure() {
+                                        log_message(format!("Failed to update exposure: {}.", e));
+                                    }
+                                } else {
+                                    ibkr.submit_paper_orders(&contender_contracts, num_fills);
+                                    num_submitted = contender_contracts.len();
+                                }
+                                end_time = Some(start_time.elapsed());
+                                print_contender_table(&contender_contracts, num_fills);
+                                for contender in contender_contracts {
+                                    log_detail(format!(
+                                    "Submitting Order for {} * {} {} @ {:.2} (fill probability {:.0}%):",
+                                    num_fills,
+                                    contender.type_spread,
+                                    contender.exp_date,
+                                    contender.arb_val,
+                                    contender.fill_probability * 100.0
+                                ));
+
+                                    for i in 0..contender.contracts.len() {
+                                        log_debug(format!(
+                                            "\tLeg {}: {} {} * {}{} {} @ {:.2}",
+                                            i + 1,
+                                            contender.action(i),
+                                            contender.multiplier(num_fills, i),
+                                            format_strike(contender.contracts[i].strike),
+                                            contender.contracts[i].type_contract,
+                                            contender.contracts[i].date,
+                                            contender.contracts[i].mkt_price
+                                        ));
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            failure = Some(format!("{}", e));
+                            event = LoopEvent::Failed;
+                        }
+                    }
+                    if let Some(fast_market) = fast_market.as_mut() {
+                        let was_active: bool = fast_market.is_active();
+                        if let Some(sample) = ibkr.take_market_sample() {
+                            if let Some(trigger) = fast_market.observe(sample) {
+                                log_message(format!(
+                                    "Fast market: {}, scanning more often.",
+                                    trigger
+                                ));
+                            } else if was_active && !fast_market.is_active() {
+                                log_message("Fast market is over.".to_string());
+                            }
+                        }
+                    }
+
+                    // Record the current time after running the program.
+                    if let Some(duration) = end_time {
+                        log_message(format!(
+                            "Total time taken: {:?} ({}).",
+                            duration,
+                            stage_timings.summary()
+                        ));
+                    }
+                    emit(&Event::stage_timings(iteration, &stage_timings));
+                    if let Some(mut scan_summary) = ibkr.take_scan_summary() {
+                        scan_summary.orders = num_submitted;
+                        log_summary(scan_summary.line(iteration, &stage_timings));
+                    }
+                    if mode {
+                        if let Err(e) = ibkr.save_state(&state_path) {
+                            log_message(format!("Failed to save the state: {}.", e));
+                        }
+                    }
+                    event
+                }
+                Phase::OrdersWorking { deadline } => {
+                    log_message(format!(
+                        "Orders working for {} seconds.",
+                        deadline.saturating_duration_since(Instant::now()).as_secs()
+                    ));
+                    wait_for_event(deadline, fill_poll_interval, &commands, || {
+                        match ibkr.update_executions() {
+                            Ok(filled) if filled > 0.0 => {
+                                shared_state.record_fill(filled);
+                                return Some(LoopEvent::Filled);
+                            }
+                            Ok(_) => {}
+                            Err(e) => log_message(format!("Failed to get executions: {}.", e)),
+                        }
+                        ibkr.requote_working_orders();
+                        // The order monitor cancels each order at its own TTL.
+                        (ibkr.working_order_count() == 0).then_some(LoopEvent::Expired)
+                    })
+                }
+                Phase::Cancelling | Phase::Failing => {
+                    if mode {
+                        ibkr.cancel_pending_orders();
+                        shared_state.set_working(0.0);
+                        if let Err(e) = shared_state.publish_exposure() {
+                            log_message(format!("Failed to update exposure: {}.", e));
+                        }
+                        if let Err(e) = ibkr.save_state(&state_path) {
+                            log_message(format!("Failed to save the state: {}.", e));
+                        }
+                    }
+                    LoopEvent::Cancelled
+                }
+                Phase::Cooldown { deadline } => {
+                    // Wait to avoid throttling resources.
+                    log_message(format!(
+                        "Sleeping for {} seconds.",
+                        deadline.saturating_duration_since(Instant::now()).as_secs()
+                    ));
+                    wait_for_event(deadline, fill_poll_interval, &commands, || None)
+                }
+                Phase::Stopped => break,
+            };
+
+            match event {
+                LoopEvent::Filled => {
+                    log_message("An order filled, cancelling the rest.".to_string())
+                }
+                LoopEvent::Command(command) => log_message(format!("Received {:?}.", command)),
+                _ => {}
+            }
+            if event != LoopEvent::Failed && take_abort() {
+                // The watchdog canceled the working orders; start over from the pre-scan checks.
+                log_message(format!(
+                    "Aborting iteration {} after the watchdog fired, restarting the loop.",
+                    iteration
+                ));
+                if mode {
+                    ibkr.cancel_pending_orders();
+                }
+                phase = Phase::Idle;
+                continue;
+            }
+            phase = phase.next(event, Instant::now(), &timings);
+        }
+        stop_watching();
+
+        // Don't leave limit orders working once the loop stops.
+        if mode {
+            ibkr.cancel_pending_orders();
+        }
+    }));
+
+    if loop_result.is_err() {
+        clean_up_after_panic(&mut ibkr, mode, &state_path);
+        exit(101);
+    }
+    if let Some(failure) = failure {
+        log_error(failure);
+    }
+    if mode {
+        if let Err(e) = ibkr.save_state(&state_path) {
+            log_message(format!("Failed to save the state: {}.", e));
+        }
+    }
+    log_message("Exiting...".to_string());
+}
@@ -0,0 +1,318 @@
+use chrono::NaiveDate;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use crate::{
+    chain::{yymmdd, ChainKey, ExpiryDate, ExpiryId, OptionChain, Quotes, Right, StrikeKey},
+    fill_model::FillModel,
+    orders::order_model_prices,
+    scanner::{OptionType, Scanner},
+    structs::{Contender, Opt},
+};
+
+/// SPX option contract multiplier.
+const MULTIPLIER: f64 = 100.0;
+
+/// Quote of a single contract within a recorded snapshot.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct SnapshotQuote {
+    pub(crate) date: ExpiryDate,
+    pub(crate) right: String,
+    pub(crate) strike: f64,
+    pub(crate) bid: f64,
+    /// The ask, missing from recordings made before it was captured.
+    #[serde(default)]
+    pub(crate) ask: Option<f64>,
+    pub(crate) mkt: f64,
+    pub(crate) asz: f64,
+    #[serde(default)]
+    pub(crate) bsz: Option<f64>,
+    #[serde(default)]
+    pub(crate) delta: Option<f64>,
+    #[serde(default)]
+    pub(crate) mark: Option<f64>,
+    #[serde(default)]
+    pub(crate) iv: Option<f64>,
+}
+
+/// Quotes for the whole chain at one scan, as recorded to `quotes.jsonl`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Snapshot {
+    pub(crate) time: i64,
+    #[serde(with = "yymmdd")]
+    pub(crate) date: NaiveDate,
+    pub(crate) minutes_to_close: i64,
+    /// The price of the underlying, missing from older recordings or when it couldn't be fetched.
+    #[serde(default)]
+    pub(crate) underlying: Option<f64>,
+    pub(crate) quotes: Vec<SnapshotQuote>,
+}
+
+impl Snapshot {
+    /// Captures a snapshot of the quotes for the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The option chain the quotes belong to.
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `time` - The scan time in Unix milliseconds.
+    /// * `date` - The scan date.
+    /// * `minutes_to_close` - The minutes remaining before the close at scan time.
+    /// * `underlying` - The price of the underlying at scan time, if known.
+    ///
+    /// # Returns
+    ///
+    /// A `Snapshot` with the quotes sorted by expiry, right and strike.
+    pub(crate) fn capture(
+        chain: &OptionChain,
+        contracts_map: &Quotes,
+        time: i64,
+        date: NaiveDate,
+        minutes_to_close: i64,
+        underlying: Option<f64>,
+    ) -> Self {
+        let mut keys: Vec<&ChainKey> = contracts_map.keys().collect();
+        keys.sort();
+
+        let quotes: Vec<SnapshotQuote> = keys
+            .into_iter()
+            .map(|key| {
+                let (expiry, right, strike) = *key;
+                let opt: &Opt = &contracts_map[key];
+                SnapshotQuote {
+                    date: chain.dates()[expiry as usize],
+                    right: right.as_str().to_string(),
+                    strike: strike.to_f64(),
+                    bid: opt.bid,
+                    ask: Some(opt.ask),
+                    mkt: opt.mkt,
+                    asz: opt.asz,
+                    bsz: opt.bsz,
+                    delta: opt.delta,
+                    mark: opt.mark,
+                    iv: opt.iv,
+                }
+            })
+            .collect();
+
+        Snapshot {
+            time,
+            date,
+            minutes_to_close,
+            underlying,
+            quotes,
+        }
+    }
+
+    /// Rebuilds the option chain and quotes from the snapshot, with synthetic conids.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(OptionChain, Quotes)` with expirations in date order.
+    pub(crate) fn to_chain(&self) -> (OptionChain, Quotes) {
+        let mut chain: OptionChain = OptionChain::new();
+        let mut contracts_map: Quotes = Quotes::default();
+
+        let mut dates: Vec<ExpiryDate> = self.quotes.iter().map(|quote| quote.date).collect();
+        dates.sort();
+        dates.dedup();
+        for date in dates {
+            chain.add_expiry(date);
+        }
+
+        for quote in &self.quotes {
+            let Some(right) = Right::from_str(&quote.right) else {
+                continue;
+            };
+            let expiry: ExpiryId = chain.expiry_id(quote.date).unwrap();
+            chain.insert(
+                expiry,
+                right,
+                quote.strike,
+                format!("{}{}{}", quote.date, quote.right, quote.strike),
+            );
+            contracts_map.insert(
+                (expiry, right, StrikeKey::from_f64(quote.strike)),
+                Opt {
+                    asz: quote.asz,
+                    mkt: quote.mkt,
+                    bid: quote.bid,
+                    // Older recordings only have the mid, which sits halfway to the ask.
+                    ask: quote.ask.unwrap_or(2.0 * quote.mkt - quote.bid),
+                    bsz: quote.bsz,
+                    delta: quote.delta,
+                    mark: quote.mark,
+                    iv: quote.iv,
+                    model: None,
+                },
+            );
+        }
+        chain.sort_strikes();
+
+        (chain, contracts_map)
+    }
+}
+
+/// Loads recorded snapshots from a JSONL file, skipping lines that fail to parse.
+///
+/// # Arguments
+///
+/// * `path` - The path of the recorded quotes file.
+///
+/// # Returns
+///
+/// A `Result` containing the snapshots in file order or an error.
+pub(crate) fn load_snapshots(path: &str) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+    let reader: BufReader<File> = BufReader::new(File::open(path)?);
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+    for line in reader.lines() {
+        if let Ok(snapshot) = serde_json::from_str::<Snapshot>(&line?) {
+            snapshots.push(snapshot);
+        }
+    }
+    Ok(snapshots)
+}
+
+/// A contender the backtest would have submitted.
+#[derive(Clone, Debug)]
+pub(crate) struct BacktestTrade {
+    pub(crate) time: i64,
+    pub(crate) strategy: String,
+    pub(crate) fill_probability: f64,
+    pub(crate) filled: bool,
+    pub(crate) quoted_edge: f64,
+    pub(crate) realized_edge: f64,
+}
+
+/// Parameters of a backtest run.
+pub(crate) struct BacktestParams<'a> {
+    pub(crate) scanner: &'a Scanner,
+    pub(crate) option_type: OptionType,
+    pub(crate) num_orders: usize,
+    pub(crate) num_fills: i32,
+    pub(crate) discount_value: f64,
+    pub(crate) fill_model: &'a FillModel,
+    pub(crate) seed: u64,
+}
+
+/// Reprices a contender's orders on another snapshot.
+///
+/// # Arguments
+///
+/// * `contender` - The contender to reprice.
+/// * `chain` - The option chain of the other snapshot.
+/// * `contracts_map` - The quotes of the other snapshot.
+///
+/// # Returns
+///
+/// An `Option<Vec<f64>>` with the model price of each order, or `None` if a leg is not quoted.
+pub(crate) fn reprice_orders(
+    contender: &Contender,
+    chain: &OptionChain,
+    contracts_map: &Quotes,
+) -> Option<Vec<f64>> {
+    let mut repriced: Contender = contender.clone();
+    for leg in repriced.contracts.iter_mut() {
+        let key: ChainKey = (
+            chain.expiry_id(leg.date)?,
+            Right::from_str(&leg.type_contract)?,
+            StrikeKey::from_f64(leg.strike),
+        );
+        leg.mkt_price = contracts_map.get(&key)?.mkt;
+    }
+    if repriced.type_spread == "Calendar" {
+        repriced.arb_val =
+            ((repriced.contracts[0].mkt_price - repriced.contracts[1].mkt_price) * 100.0).round()
+                / 100.0;
+    }
+    Some(order_model_prices(&repriced))
+}
+
+/// Replays recorded snapshots through the scanners and fill model.
+///
+/// Each snapshot is scanned like a live iteration and the best contenders are filled against the
+/// next snapshot. A contender counts as filled only if all of its orders fill; legging risk is not
+/// modeled. Edges are in dollars for the configured number of fills.
+///
+/// # Arguments
+///
+/// * `snapshots` - The recorded snapshots in time order.
+/// * `params` - The scanner, sizing and fill model to use.
+///
+/// # Returns
+///
+/// A `Result` containing the simulated trades or an error.
+pub(crate) fn run_backtest(
+    snapshots: &[Snapshot],
+    params: &BacktestParams,
+) -> Result<Vec<BacktestTrade>, Box<dyn Error>> {
+    let mut rng: StdRng = StdRng::seed_from_u64(params.seed);
+    let chains: Vec<(OptionChain, Quotes)> = snapshots.iter().map(Snapshot::to_chain).collect();
+    let mut trades: Vec<BacktestTrade> = Vec::new();
+
+    for (index, snapshot) in snapshots
+        .iter()
+        .enumerate()
+        .take(snapshots.len().saturating_sub(1))
+    {
+        let (chain, contracts_map) = &chains[index];
+        let (next_chain, next_contracts_map) = &chains[index + 1];
+
+        let mut contenders: Vec<Contender> = params.scanner.scan(
+            &params.option_type,
+            contracts_map,
+            chain,
+            snapshot.date,
+            snapshot.minutes_to_close,
+            snapshot.underlying,
+        )?;
+        contenders.sort_by(|a, b| b.rank_value.partial_cmp(&a.rank_value).unwrap());
+        contenders.truncate(params.num_orders);
+
+        for contender in &contenders {
+            let model_prices: Vec<f64> = order_model_prices(contender);
+            let limit_prices: Vec<f64> = model_prices
+                .iter()
+                .map(|price| ((price + params.discount_value) * 100.0).round() / 100.0)
+                .collect();
+            let next_prices: Option<Vec<f64>> =
+                reprice_orders(contender, next_chain, next_contracts_map);
+
+            let quoted_edge: f64 = contender.quoted_edge();
+            let fill_probability: f64 = params.fill_model.fill_probability(quoted_edge);
+            let filled: bool = params.fill_model.simulate_fill(
+                &limit_prices,
+                next_prices.as_deref(),
+                fill_probability,
+                &mut rng,
+            );
+
+            let size: f64 = params.num_fills as f64 * MULTIPLIER;
+            let slippage: f64 = limit_prices
+                .iter()
+                .zip(&model_prices)
+                .map(|(limit_price, model_price)| limit_price - model_price)
+                .sum::<f64>();
+
+            trades.push(BacktestTrade {
+                time: snapshot.time,
+                strategy: contender.type_spread.clone(),
+                fill_probability,
+                filled,
+                quoted_edge: quoted_edge * size,
+                realized_edge: if filled {
+                    (quoted_edge - slippage) * size
+                } else {
+                    0.0
+                },
+            });
+        }
+    }
+
+    Ok(trades)
+}
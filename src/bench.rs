@@ -0,0 +1,85 @@
+use chrono::NaiveDate;
+
+use crate::{
+    chain::{OptionChain, Quotes},
+    dividends::DividendCalendar,
+    rates::RateCurve,
+    scanner::Scanner,
+    structs::{ExpiryFilter, LegPricing, StrategyConfig},
+    testutil::{synthetic_chain, ChainParams},
+};
+
+/// A scanner and synthetic chain for the benchmarks in `benches/`, which can only reach public
+/// items of the crate.
+pub struct ScanFixture {
+    scanner: Scanner,
+    chain: OptionChain,
+    quotes: Quotes,
+    current_date: NaiveDate,
+}
+
+impl ScanFixture {
+    /// Creates a fixture with a synthetic chain and the default 5 and 10 point wings.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_expiries` - The number of expirations in the chain.
+    /// * `num_strikes` - The number of strikes per expiration and right.
+    pub fn new(num_expiries: usize, num_strikes: usize) -> Self {
+        let start_date: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries,
+                num_strikes,
+                ..ChainParams::default()
+            },
+            start_date,
+        );
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![5.0, 10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        ScanFixture {
+            scanner: Scanner {
+                arb_val: 0.0,
+                butterfly_config: config.clone(),
+                boxspread_config: config.clone(),
+                calendar_config: config,
+                expiry_filter: ExpiryFilter {
+                    exclude_same_day: false,
+                    min_minutes_to_close: 0,
+                },
+                include_below_threshold: false,
+                near_miss_epsilon: None,
+                early_exercise: None,
+                dividends: DividendCalendar::new(),
+                rates: RateCurve::default(),
+                price_scale: 1.0,
+                leg_pricing: LegPricing::default(),
+                skipped_expiries: Vec::new(),
+                box_financing: None,
+                max_iv_deviation: None,
+            },
+            chain,
+            quotes,
+            current_date: start_date,
+        }
+    }
+
+    /// Runs the butterfly scanner, returning the number of contenders.
+    pub fn butterfly(&self) -> usize {
+        self.scanner
+            .get_butterfly_contenders(&self.quotes, &self.chain, self.current_date, 390, None)
+            .map_or(0, |contenders| contenders.len())
+    }
+
+    /// Runs the box spread scanner, returning the number of contenders.
+    pub fn boxspread(&self) -> usize {
+        self.scanner
+            .get_boxspread_contenders(&self.quotes, &self.chain, self.current_date, 390, None)
+            .map_or(0, |contenders| contenders.len())
+    }
+}
@@ -0,0 +1,501 @@
+use chrono::NaiveDate;
+use rustc_hash::FxHashMap;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+use crate::structs::Opt;
+
+/// Index of an expiration date within the chain's `dates` vector.
+pub type ExpiryId = u16;
+
+/// Composite key identifying a single option contract within the chain.
+pub type ChainKey = (ExpiryId, Right, StrikeKey);
+
+/// Quotes for every contract in the chain, keyed by composite key.
+pub type Quotes = FxHashMap<ChainKey, Opt>;
+
+/// Enum representing the right of an option contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Right {
+    Call,
+    Put,
+}
+
+impl Right {
+    /// Both rights, calls first.
+    pub(crate) const ALL: [Right; 2] = [Right::Call, Right::Put];
+
+    /// Converts the IBKR right string (`"C"` or `"P"`) to a `Right`.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - A string representing the right.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Self>` representing the converted right.
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "C" => Some(Right::Call),
+            "P" => Some(Right::Put),
+            _ => None,
+        }
+    }
+
+    /// Returns the IBKR string representation of the right.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Right::Call => "C",
+            Right::Put => "P",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Right::Call => 0,
+            Right::Put => 1,
+        }
+    }
+}
+
+/// Strike price stored as an integer number of thousandths, so it can be hashed cheaply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StrikeKey(i64);
+
+impl StrikeKey {
+    /// Converts a strike price to a `StrikeKey`.
+    ///
+    /// # Arguments
+    ///
+    /// * `strike` - The strike price as `f64`.
+    ///
+    /// # Returns
+    ///
+    /// The `StrikeKey` for the strike price.
+    pub fn from_f64(strike: f64) -> Self {
+        StrikeKey((strike * 1000.0).round() as i64)
+    }
+
+    /// Converts the `StrikeKey` back to a strike price.
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+}
+
+/// An option expiration date, written in `YYMMDD` format in logs, leg keys and files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExpiryDate(NaiveDate);
+
+impl ExpiryDate {
+    /// Creates an `ExpiryDate` from a calendar date.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The expiration date.
+    pub fn new(date: NaiveDate) -> Self {
+        ExpiryDate(date)
+    }
+
+    /// Returns the calendar date of the expiration.
+    pub fn date(self) -> NaiveDate {
+        self.0
+    }
+
+    /// Parses the maturity date IBKR reports for a contract.
+    ///
+    /// # Arguments
+    ///
+    /// * `maturity_date` - The maturity date in `YYYYMMDD` format, e.g. `"20240701"`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `ExpiryDate`, or an error quoting the malformed date.
+    pub(crate) fn from_maturity(maturity_date: &str) -> Result<Self, String> {
+        NaiveDate::parse_from_str(maturity_date.trim(), "%Y%m%d")
+            .map(ExpiryDate)
+            .map_err(|e| format!("invalid maturity date {:?}: {}", maturity_date, e))
+    }
+
+    /// Returns the days from a date to the expiration, negative once it has passed.
+    ///
+    /// # Arguments
+    ///
+    /// * `current_date` - The date counted from, usually today.
+    pub fn days_from(self, current_date: NaiveDate) -> i64 {
+        (self.0 - current_date).num_days()
+    }
+}
+
+impl fmt::Display for ExpiryDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.format("%y%m%d"))
+    }
+}
+
+impl FromStr for ExpiryDate {
+    type Err = String;
+
+    /// Parses an expiration date in `YYMMDD` format, e.g. `"240701"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 6 || !s.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("invalid expiration date {:?}, expected YYMMDD", s));
+        }
+        NaiveDate::parse_from_str(s, "%y%m%d")
+            .map(ExpiryDate)
+            .map_err(|e| format!("invalid expiration date {:?}: {}", s, e))
+    }
+}
+
+impl Serialize for ExpiryDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExpiryDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Serializes a date in `YYMMDD` format like an `ExpiryDate`, for the scan dates recorded next to
+/// expiration dates, with `#[serde(with = "yymmdd")]`.
+pub(crate) mod yymmdd {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        date: &NaiveDate,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        ExpiryDate(*date).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDate, D::Error> {
+        ExpiryDate::deserialize(deserializer).map(ExpiryDate::date)
+    }
+}
+
+/// Sorted strikes for a single expiry and right, supporting O(log n) neighbor queries.
+#[derive(Default)]
+pub(crate) struct StrikeLadder {
+    strikes: Vec<f64>,
+    /// The indexes of the strikes followed by a gap, in order.
+    gap_starts: Vec<usize>,
+}
+
+impl StrikeLadder {
+    /// Returns the sorted strikes in the ladder.
+    pub(crate) fn strikes(&self) -> &[f64] {
+        &self.strikes
+    }
+
+    /// Returns the number of strikes in the ladder.
+    pub(crate) fn len(&self) -> usize {
+        self.strikes.len()
+    }
+
+    /// Returns the index of the given strike in the ladder, if present.
+    ///
+    /// # Arguments
+    ///
+    /// * `strike` - The strike price to search for.
+    pub(crate) fn index_of(&self, strike: f64) -> Option<usize> {
+        let key: StrikeKey = StrikeKey::from_f64(strike);
+        self.strikes
+            .binary_search_by(|s| StrikeKey::from_f64(*s).cmp(&key))
+            .ok()
+    }
+
+    /// Returns whether the given strike is in the ladder.
+    pub(crate) fn contains(&self, strike: f64) -> bool {
+        self.index_of(strike).is_some()
+    }
+
+    /// Returns the strike exactly `width` points away from `strike`, if it is in the ladder.
+    ///
+    /// # Arguments
+    ///
+    /// * `strike` - The reference strike price.
+    /// * `width` - The signed distance from the reference strike.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<f64>` containing the strike at the offset.
+    pub(crate) fn offset(&self, strike: f64, width: f64) -> Option<f64> {
+        self.index_of(strike + width).map(|i| self.strikes[i])
+    }
+
+    /// Returns the smallest spacing between adjacent strikes (e.g. 5 or 25 points).
+    pub(crate) fn interval(&self) -> Option<f64> {
+        self.strikes
+            .windows(2)
+            .map(|pair| ((pair[1] - pair[0]) * 1000.0).round() / 1000.0)
+            .filter(|spacing| *spacing > 0.0)
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+    }
+
+    /// Returns the missing stretches of the strike grid, as the strikes on either side.
+    ///
+    /// Each run of equal spacings is compared to the base interval around it, the spacings on
+    /// either side of the run. A run wider than both is a gap, e.g. the 10 points between 5000 and
+    /// 5010 in a 5-point grid missing 5005, or both 10-point spacings when 5005 and 5015 are
+    /// missing. A single spacing at either end of the ladder wider than its neighbor is a gap too.
+    /// A grid that widens from 5 to 25 points further out has no gap.
+    pub(crate) fn gaps(&self) -> Vec<(f64, f64)> {
+        self.gap_starts
+            .iter()
+            .map(|&i| (self.strikes[i], self.strikes[i + 1]))
+            .collect()
+    }
+
+    /// Returns whether the grid is missing a strike between two strikes of the ladder, so a
+    /// structure spanning them can't be assumed to sit on evenly spaced strikes.
+    ///
+    /// # Arguments
+    ///
+    /// * `low` - The lower strike.
+    /// * `high` - The higher strike.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is `true` if a gap lies between the strikes or either isn't in the ladder.
+    pub(crate) fn has_gap_between(&self, low: f64, high: f64) -> bool {
+        let (Some(low), Some(high)) = (self.index_of(low), self.index_of(high)) else {
+            return true;
+        };
+        let first: usize = self.gap_starts.partition_point(|&i| i < low);
+        self.gap_starts.get(first).is_some_and(|&i| i < high)
+    }
+
+    fn push(&mut self, strike: f64) {
+        self.strikes.push(strike);
+    }
+
+    fn sort(&mut self) {
+        self.strikes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        self.strikes
+            .dedup_by(|a, b| StrikeKey::from_f64(*a) == StrikeKey::from_f64(*b));
+
+        let spacings: Vec<StrikeKey> = self
+            .strikes
+            .windows(2)
+            .map(|pair| StrikeKey::from_f64(pair[1] - pair[0]))
+            .collect();
+        self.gap_starts.clear();
+        let mut start: usize = 0;
+        while start < spacings.len() {
+            let spacing: StrikeKey = spacings[start];
+            let end: usize = start
+                + spacings[start..]
+                    .iter()
+                    .take_while(|&&other| other == spacing)
+                    .count();
+            let wider_before: Option<bool> = start.checked_sub(1).map(|i| spacings[i] < spacing);
+            let wider_after: Option<bool> = spacings.get(end).map(|&next| next < spacing);
+            let is_gap: bool = match (wider_before, wider_after) {
+                (Some(before), Some(after)) => before && after,
+                // At an end of the ladder a run of wider spacings is the grid widening out.
+                (Some(wider), None) | (None, Some(wider)) => wider && end - start == 1,
+                (None, None) => false,
+            };
+            if is_gap {
+                self.gap_starts.extend(start..end);
+            }
+            start = end;
+        }
+    }
+}
+
+/// Flat storage for the option chain: expirations, sorted strikes per expiry and right, and
+/// conids keyed by `(ExpiryId, Right, StrikeKey)`.
+#[derive(Default)]
+pub struct OptionChain {
+    dates: Vec<ExpiryDate>,
+    ladders: Vec<[StrikeLadder; 2]>,
+    conids: FxHashMap<ChainKey, String>,
+    keys: FxHashMap<String, ChainKey>,
+    /// The trading class of each contract, where the gateway listed it.
+    trading_classes: FxHashMap<ChainKey, String>,
+}
+
+impl OptionChain {
+    /// Creates a new, empty `OptionChain`.
+    pub(crate) fn new() -> Self {
+        OptionChain::default()
+    }
+
+    /// Returns the expiration dates in the chain.
+    pub fn dates(&self) -> &Vec<ExpiryDate> {
+        &self.dates
+    }
+
+    /// Returns the `ExpiryId` for the given date, if present in the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The expiration date.
+    pub fn expiry_id(&self, date: ExpiryDate) -> Option<ExpiryId> {
+        self.dates
+            .iter()
+            .position(|d| *d == date)
+            .map(|i| i as ExpiryId)
+    }
+
+    /// Returns whether the chain already contains the given expiration date.
+    pub(crate) fn contains_date(&self, date: ExpiryDate) -> bool {
+        self.expiry_id(date).is_some()
+    }
+
+    /// Adds an expiration date to the chain and returns its `ExpiryId`.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The expiration date.
+    pub fn add_expiry(&mut self, date: ExpiryDate) -> ExpiryId {
+        if let Some(expiry) = self.expiry_id(date) {
+            return expiry;
+        }
+        self.dates.push(date);
+        self.ladders
+            .push([StrikeLadder::default(), StrikeLadder::default()]);
+        (self.dates.len() - 1) as ExpiryId
+    }
+
+    /// Inserts a contract into the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `expiry` - The `ExpiryId` of the contract.
+    /// * `right` - The right of the contract.
+    /// * `strike` - The strike price of the contract.
+    /// * `conid` - The IBKR conid of the contract.
+    pub fn insert(&mut self, expiry: ExpiryId, right: Right, strike: f64, conid: String) {
+        let key: ChainKey = (expiry, right, StrikeKey::from_f64(strike));
+        self.ladders[expiry as usize][right.index()].push(strike);
+        self.keys.insert(conid.clone(), key);
+        self.conids.insert(key, conid);
+        self.trading_classes.remove(&key);
+    }
+
+    /// Sets the trading class of a contract already in the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `expiry` - The `ExpiryId` of the contract.
+    /// * `right` - The right of the contract.
+    /// * `strike` - The strike price of the contract.
+    /// * `trading_class` - The trading class, e.g. `"SPXW"`.
+    pub(crate) fn set_trading_class(
+        &mut self,
+        expiry: ExpiryId,
+        right: Right,
+        strike: f64,
+        trading_class: String,
+    ) {
+        self.trading_classes
+            .insert((expiry, right, StrikeKey::from_f64(strike)), trading_class);
+    }
+
+    /// Returns the trading class of a contract, if the gateway listed it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the contract.
+    pub(crate) fn trading_class(&self, key: &ChainKey) -> Option<&str> {
+        self.trading_classes.get(key).map(String::as_str)
+    }
+
+    /// Returns the trading class of a contract described by date, right and strike.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The expiration date.
+    /// * `right` - The right as `"C"` or `"P"`.
+    /// * `strike` - The strike price.
+    pub(crate) fn trading_class_for(
+        &self,
+        date: ExpiryDate,
+        right: &str,
+        strike: f64,
+    ) -> Option<&str> {
+        self.trading_class(&(
+            self.expiry_id(date)?,
+            Right::from_str(right)?,
+            StrikeKey::from_f64(strike),
+        ))
+    }
+
+    /// Sorts and deduplicates the strike ladders for every expiry and right.
+    pub fn sort_strikes(&mut self) {
+        for ladders in self.ladders.iter_mut() {
+            for ladder in ladders.iter_mut() {
+                ladder.sort();
+            }
+        }
+    }
+
+    /// Returns the strike ladder for the given expiry and right.
+    pub(crate) fn ladder(&self, expiry: ExpiryId, right: Right) -> &StrikeLadder {
+        &self.ladders[expiry as usize][right.index()]
+    }
+
+    /// Returns the sorted strikes for the given expiry and right.
+    pub fn strikes(&self, expiry: ExpiryId, right: Right) -> &[f64] {
+        self.ladder(expiry, right).strikes()
+    }
+
+    /// Returns the conid for the given key, if present in the chain.
+    pub(crate) fn conid(&self, key: &ChainKey) -> Option<&String> {
+        self.conids.get(key)
+    }
+
+    /// Returns the conid for a contract described by date, right and strike.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The expiration date.
+    /// * `right` - The right as `"C"` or `"P"`.
+    /// * `strike` - The strike price.
+    pub fn conid_for(&self, date: ExpiryDate, right: &str, strike: f64) -> Option<&String> {
+        let key: ChainKey = (
+            self.expiry_id(date)?,
+            Right::from_str(right)?,
+            StrikeKey::from_f64(strike),
+        );
+        self.conids.get(&key)
+    }
+
+    /// Returns the key for the given conid, if present in the chain.
+    pub(crate) fn key(&self, conid: &str) -> Option<&ChainKey> {
+        self.keys.get(conid)
+    }
+
+    /// Builds the comma-separated conid batches used for market data snapshot requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_size` - The maximum number of conids per batch.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<String>` of comma-terminated conid lists.
+    pub(crate) fn conid_batches(&self, batch_size: usize) -> Vec<String> {
+        let mut conids: Vec<(&ChainKey, &String)> = self.conids.iter().collect();
+        conids.sort_by_key(|(key, _)| **key);
+
+        conids
+            .chunks(batch_size)
+            .map(|chunk| {
+                chunk.iter().fold(String::new(), |mut acc, (_, conid)| {
+                    acc.push_str(conid);
+                    acc.push(',');
+                    acc
+                })
+            })
+            .collect()
+    }
+}
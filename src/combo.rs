@@ -0,0 +1,183 @@
+use crate::structs::Contract;
+
+/// The conid of USD, the currency combo conidexes are priced in.
+pub(crate) const USD_CONID: &str = "28812380";
+
+/// The shape of a combo order, which decides how its legs are validated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ComboSpread {
+    /// Two strikes of the same right and expiration, e.g. each half of a butterfly or box spread.
+    Vertical,
+    /// One strike and right in two expirations, selling the nearer one.
+    Calendar,
+}
+
+impl ComboSpread {
+    /// Returns the name of the spread, e.g. `"vertical"`.
+    fn name(&self) -> &'static str {
+        match self {
+            ComboSpread::Vertical => "vertical",
+            ComboSpread::Calendar => "calendar",
+        }
+    }
+}
+
+/// A leg of a combo order.
+#[derive(Clone)]
+struct ComboLeg<'a> {
+    conid: &'a str,
+    contract: &'a Contract,
+    /// The signed quantity per combo, positive when the leg is bought.
+    ratio: i32,
+}
+
+/// Builds the conidex of a combo order, e.g. `28812380;;;123/-1,456/1`, checking its legs match
+/// the spread.
+///
+/// # Example
+///
+/// ```
+/// let conidex = ComboBuilder::new(ComboSpread::Vertical)
+///     .leg("123", &short_call, -1)
+///     .leg("456", &long_call, 1)
+///     .build()?;
+/// ```
+#[derive(Clone)]
+pub(crate) struct ComboBuilder<'a> {
+    spread: ComboSpread,
+    legs: Vec<ComboLeg<'a>>,
+}
+
+impl<'a> ComboBuilder<'a> {
+    /// Starts a combo of a spread with no legs.
+    ///
+    /// # Arguments
+    ///
+    /// * `spread` - The shape of the combo.
+    pub(crate) fn new(spread: ComboSpread) -> Self {
+        ComboBuilder {
+            spread,
+            legs: Vec::new(),
+        }
+    }
+
+    /// Adds a leg. Legs keep the order they are added in.
+    ///
+    /// # Arguments
+    ///
+    /// * `conid` - The conid of the leg.
+    /// * `contract` - The leg's expiration, right and strike.
+    /// * `ratio` - The signed quantity per combo, positive to buy the leg and negative to sell it.
+    pub(crate) fn leg(mut self, conid: &'a str, contract: &'a Contract, ratio: i32) -> Self {
+        self.legs.push(ComboLeg {
+            conid,
+            contract,
+            ratio,
+        });
+        self
+    }
+
+    /// Checks the legs against the spread: two distinct legs of the same right, one bought and
+    /// one sold in equal ratios, differing only in strike for a vertical, and only in expiration
+    /// with the nearer one sold for a calendar.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is an error describing the first problem found.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let [first, second] = self.legs.as_slice() else {
+            return Err(format!(
+                "a {} combo has 2 legs, got {}",
+                self.spread.name(),
+                self.legs.len()
+            ));
+        };
+        for leg in &self.legs {
+            if leg.conid.is_empty() {
+                return Err(format!("leg {} has no conid", leg.contract.describe()));
+            }
+            if !["C", "P"].contains(&leg.contract.type_contract.as_str()) {
+                return Err(format!(
+                    "leg {} has an unknown right {:?}",
+                    leg.contract.describe(),
+                    leg.contract.type_contract
+                ));
+            }
+        }
+        if first.conid == second.conid {
+            return Err(format!("both legs are conid {}", first.conid));
+        }
+        if first.ratio.abs() != 1 || first.ratio + second.ratio != 0 {
+            return Err(format!(
+                "a {} combo buys one leg and sells the other 1:1, got ratios {} and {}",
+                self.spread.name(),
+                first.ratio,
+                second.ratio
+            ));
+        }
+        if first.contract.type_contract != second.contract.type_contract {
+            return Err(format!(
+                "a {} combo has legs of one right, got {} and {}",
+                self.spread.name(),
+                first.contract.type_contract,
+                second.contract.type_contract
+            ));
+        }
+
+        match self.spread {
+            ComboSpread::Vertical => {
+                if first.contract.date != second.contract.date {
+                    return Err(format!(
+                        "a vertical combo has legs of one expiration, got {} and {}",
+                        first.contract.date, second.contract.date
+                    ));
+                }
+                if first.contract.strike == second.contract.strike {
+                    return Err(format!(
+                        "a vertical combo has legs of two strikes, got {} twice",
+                        first.contract.strike
+                    ));
+                }
+            }
+            ComboSpread::Calendar => {
+                if first.contract.strike != second.contract.strike {
+                    return Err(format!(
+                        "a calendar combo has legs of one strike, got {} and {}",
+                        first.contract.strike, second.contract.strike
+                    ));
+                }
+                let (short, long) = if first.ratio < 0 {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                if short.contract.date >= long.contract.date {
+                    return Err(format!(
+                        "a calendar combo sells the nearer expiration, got short {} and long {}",
+                        short.contract.date, long.contract.date
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the legs and formats the conidex.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the conidex, e.g. `28812380;;;123/-1,456/1`, or an error if the legs
+    /// don't match the spread.
+    pub(crate) fn build(&self) -> Result<String, String> {
+        self.validate()?;
+        Ok(format!(
+            "{};;;{}",
+            USD_CONID,
+            self.legs
+                .iter()
+                .map(|leg| format!("{}/{}", leg.conid, leg.ratio))
+                .collect::<Vec<String>>()
+                .join(",")
+        ))
+    }
+}
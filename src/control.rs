@@ -0,0 +1,330 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{chain::ExpiryDate, logging::log_message, machine::Command, structs::Contender};
+#[cfg(feature = "grpc")]
+use crate::{grpc::spawn_server, logging::log_error};
+
+/// What the run loop is doing, as reported to the control plane.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BotStatus {
+    /// The phase of the run loop, e.g. `"scanning"`.
+    pub(crate) phase: String,
+    pub(crate) iteration: u64,
+    /// Whether orders are sent to the broker rather than simulated.
+    pub(crate) live: bool,
+    pub(crate) account_id: Option<String>,
+    pub(crate) working_orders: usize,
+    /// The timestamp of the last scan in milliseconds, `0` before the first.
+    pub(crate) last_scan_time: i64,
+}
+
+/// Limits a manual scan to one strategy and/or expiry.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ScanFilter {
+    /// The strategy, e.g. `"butterfly"`, matched case-insensitively.
+    pub(crate) strategy: Option<String>,
+    /// The expiry, matched against every leg.
+    pub(crate) expiry: Option<ExpiryDate>,
+}
+
+impl ScanFilter {
+    /// Parses the arguments of the `scan` command, where a number is an expiry and a word a
+    /// strategy.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The arguments, e.g. `"butterfly 250117"`.
+    ///
+    /// # Returns
+    ///
+    /// The `ScanFilter`, which matches every contender if `args` is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let scan_filter = ScanFilter::parse("butterfly 250117");
+    /// assert_eq!(scan_filter.expiry, Some("250117".parse().unwrap()));
+    /// ```
+    pub(crate) fn parse(args: &str) -> Self {
+        let mut scan_filter: ScanFilter = ScanFilter::default();
+        for arg in args.split_whitespace() {
+            if arg.chars().all(|c| c.is_ascii_digit()) {
+                match arg.parse::<ExpiryDate>() {
+                    Ok(expiry) => scan_filter.expiry = Some(expiry),
+                    Err(e) => log_message(format!("Ignoring the {}.", e)),
+                }
+            } else {
+                scan_filter.strategy = Some(arg.to_string());
+            }
+        }
+        scan_filter
+    }
+
+    /// Returns whether the filter limits the scan at all.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.strategy.is_none() && self.expiry.is_none()
+    }
+
+    /// Returns whether a contender is of the strategy and has a leg expiring on the expiry.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender` - The contender.
+    pub(crate) fn matches(&self, contender: &Contender) -> bool {
+        self.strategy
+            .as_ref()
+            .is_none_or(|strategy| strategy.eq_ignore_ascii_case(&contender.type_spread))
+            && self.expiry.is_none_or(|expiry| {
+                contender.exp_date == expiry
+                    || contender.contracts.iter().any(|leg| leg.date == expiry)
+            })
+    }
+
+    /// Describes the filter, e.g. `"butterfly expiring 250117"`.
+    pub(crate) fn describe(&self) -> String {
+        match (&self.strategy, &self.expiry) {
+            (Some(strategy), Some(expiry)) => format!("{} expiring {}", strategy, expiry),
+            (Some(strategy), None) => strategy.clone(),
+            (None, Some(expiry)) => format!("contenders expiring {}", expiry),
+            (None, None) => "all contenders".to_string(),
+        }
+    }
+}
+
+/// The status of the run loop.
+static STATUS: Mutex<Option<BotStatus>> = Mutex::new(None);
+
+/// The contenders of the last scan, best first.
+static CONTENDERS: Mutex<Vec<Contender>> = Mutex::new(Vec::new());
+
+/// Whether trading is paused: scans go on, but no orders are placed.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// The filter of the manual scan requested last, until the scan takes it.
+static SCAN_FILTER: Mutex<Option<ScanFilter>> = Mutex::new(None);
+
+/// Updates the status of the run loop.
+///
+/// # Arguments
+///
+/// * `update` - Changes the status in place.
+///
+/// # Example
+///
+/// ```
+/// update_status(|status| status.iteration += 1);
+/// ```
+pub(crate) fn update_status(update: impl FnOnce(&mut BotStatus)) {
+    update(
+        STATUS
+            .lock()
+            .unwrap()
+            .get_or_insert_with(BotStatus::default),
+    );
+}
+
+/// Returns the status of the run loop.
+pub(crate) fn status() -> BotStatus {
+    STATUS.lock().unwrap().clone().unwrap_or_default()
+}
+
+/// Stores the contenders of the last scan.
+///
+/// # Arguments
+///
+/// * `contenders` - The contenders, best first.
+pub(crate) fn set_contenders(contenders: &[Contender]) {
+    *CONTENDERS.lock().unwrap() = contenders.to_vec();
+}
+
+/// Returns the contenders of the last scan, best first.
+pub(crate) fn contenders() -> Vec<Contender> {
+    CONTENDERS.lock().unwrap().clone()
+}
+
+/// Pauses or resumes trading. While paused the bot keeps scanning but places no orders.
+///
+/// # Arguments
+///
+/// * `paused` - Whether trading is paused.
+pub(crate) fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+/// Returns whether trading is paused.
+pub(crate) fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Stores the filter of a manual scan, replacing any the loop hasn't taken yet.
+///
+/// # Arguments
+///
+/// * `scan_filter` - The strategy and expiry to scan.
+pub(crate) fn request_scan(scan_filter: ScanFilter) {
+    *SCAN_FILTER.lock().unwrap() = Some(scan_filter);
+}
+
+/// Takes the filter of the requested manual scan, if any.
+pub(crate) fn take_scan_filter() -> Option<ScanFilter> {
+    SCAN_FILTER.lock().unwrap().take()
+}
+
+/// Parses the request line of a scan webhook call.
+///
+/// # Arguments
+///
+/// * `request_line` - The first line of the HTTP request, e.g.
+///   `"POST /scan?strategy=butterfly&expiry=250117 HTTP/1.1"`.
+///
+/// # Returns
+///
+/// A `Result` containing the `ScanFilter` of the query, or the HTTP status to reply with if the
+/// request isn't a `POST /scan` or its expiry isn't a `YYMMDD` date.
+pub(crate) fn parse_webhook_request(request_line: &str) -> Result<ScanFilter, &'static str> {
+    let mut parts = request_line.split_whitespace();
+    let method: &str = parts.next().unwrap_or_default();
+    let target: &str = parts.next().unwrap_or_default();
+    let (path, query): (&str, &str) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/scan" {
+        return Err("404 Not Found");
+    }
+    if method != "POST" {
+        return Err("405 Method Not Allowed");
+    }
+
+    let mut scan_filter: ScanFilter = ScanFilter::default();
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        match key {
+            "strategy" if !value.is_empty() => scan_filter.strategy = Some(value.to_string()),
+            "expiry" if !value.is_empty() => {
+                scan_filter.expiry = Some(value.parse().map_err(|_| "400 Bad Request")?)
+            }
+            _ => {}
+        }
+    }
+    Ok(scan_filter)
+}
+
+/// Answers one webhook call, requesting a scan if it's a `POST /scan` with the right token.
+///
+/// # Arguments
+///
+/// * `stream` - The connection.
+/// * `token` - The bearer token callers must send, if any.
+/// * `commands` - Where the scan command is sent.
+fn handle_webhook(mut stream: TcpStream, token: Option<&str>, commands: &Sender<Command>) {
+    let mut reader: BufReader<&TcpStream> = BufReader::new(&stream);
+    let mut request_line: String = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut authorized: bool = token.is_none();
+    let mut header: String = String::new();
+    while reader.read_line(&mut header).is_ok_and(|read| read > 0) && header.trim() != "" {
+        if let (Some(token), Some((name, value))) = (token, header.split_once(':')) {
+            if name.trim().eq_ignore_ascii_case("authorization")
+                && value.trim() == format!("Bearer {}", token)
+            {
+                authorized = true;
+            }
+        }
+        header.clear();
+    }
+
+    let status: &str = match parse_webhook_request(&request_line) {
+        Ok(_) if !authorized => "401 Unauthorized",
+        Ok(scan_filter) => {
+            log_message(format!(
+                "Scan of {} requested by webhook.",
+                scan_filter.describe()
+            ));
+            request_scan(scan_filter);
+            match commands.send(Command::ScanNow) {
+                Ok(()) => "202 Accepted",
+                Err(_) => "503 Service Unavailable",
+            }
+        }
+        Err(status) => status,
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    );
+}
+
+/// Listens for scan webhook calls on a background thread. A `POST /scan` triggers an immediate
+/// scan, limited by the optional `strategy` and `expiry` query parameters.
+///
+/// # Arguments
+///
+/// * `addr` - The `host:port` to listen on.
+/// * `token` - The bearer token callers must send in the `Authorization` header, if any.
+/// * `commands` - Where the scan command is sent.
+///
+/// # Returns
+///
+/// A `Result` indicating the listener started, or an error if the address can't be bound.
+pub(crate) fn spawn_scan_webhook(
+    addr: &str,
+    token: Option<String>,
+    commands: Sender<Command>,
+) -> io::Result<()> {
+    let listener: TcpListener = TcpListener::bind(addr)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            handle_webhook(stream, token.as_deref(), &commands);
+        }
+    });
+    Ok(())
+}
+
+/// Describes the status in one line, for the `status` command.
+///
+/// # Returns
+///
+/// A `String` such as `"Iteration 12, scanning, live, trading paused, 2 working orders, 3 contenders."`.
+pub(crate) fn describe_status() -> String {
+    let status: BotStatus = status();
+    format!(
+        "Iteration {}, {}, {}, trading {}, {} working orders, {} contenders.",
+        status.iteration,
+        if status.phase.is_empty() {
+            "starting"
+        } else {
+            &status.phase
+        },
+        if status.live { "live" } else { "simulated" },
+        if is_paused() { "paused" } else { "active" },
+        status.working_orders,
+        contenders().len()
+    )
+}
+
+/// Starts the gRPC control plane on `addr`. Requires the `grpc` feature.
+///
+/// # Arguments
+///
+/// * `addr` - The `host:port` to listen on.
+pub(crate) fn serve_control_plane(addr: &str) {
+    #[cfg(feature = "grpc")]
+    match spawn_server(addr) {
+        Ok(()) => log_message(format!("Serving the gRPC control plane on {}.", addr)),
+        Err(e) => log_error(format!("Failed to serve gRPC on {}: {}", addr, e)),
+    }
+    #[cfg(not(feature = "grpc"))]
+    log_message(format!(
+        "Not serving the gRPC control plane on {}: built without the grpc feature.",
+        addr
+    ));
+}
@@ -0,0 +1,181 @@
+use rustc_hash::FxHashMap;
+use std::time::{Duration, Instant};
+
+use crate::structs::Contender;
+
+/// Short-term memory of recently submitted contenders, so the same spread at the same price is
+/// not resubmitted every iteration while its quote is stale.
+pub(crate) struct RecentContenders {
+    ttl: Duration,
+    submitted: FxHashMap<String, Instant>,
+}
+
+impl RecentContenders {
+    /// Creates a new, empty `RecentContenders`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a submitted contender is remembered. A zero duration disables the memory.
+    pub(crate) fn new(ttl: Duration) -> Self {
+        RecentContenders {
+            ttl,
+            submitted: FxHashMap::default(),
+        }
+    }
+
+    /// Returns whether an identical contender was submitted within the TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender` - The contender to check.
+    /// * `now` - The current instant.
+    pub(crate) fn contains(&self, contender: &Contender, now: Instant) -> bool {
+        self.submitted
+            .get(&contender.fingerprint())
+            .is_some_and(|submitted_at| now.duration_since(*submitted_at) < self.ttl)
+    }
+
+    /// Records the given contenders as submitted and forgets entries older than the TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `contenders` - The contenders that were submitted.
+    /// * `now` - The current instant.
+    pub(crate) fn record(&mut self, contenders: &[Contender], now: Instant) {
+        let ttl: Duration = self.ttl;
+        self.submitted
+            .retain(|_, submitted_at| now.duration_since(*submitted_at) < ttl);
+
+        if ttl.is_zero() {
+            return;
+        }
+        for contender in contenders {
+            self.submitted.insert(contender.fingerprint(), now);
+        }
+    }
+
+    /// Exports the remembered contenders with their submission time as wall-clock time.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant.
+    /// * `now_ms` - The current time in milliseconds since the epoch.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<(String, i64)>` of fingerprints and submission times in milliseconds since the epoch.
+    pub(crate) fn export(&self, now: Instant, now_ms: i64) -> Vec<(String, i64)> {
+        self.submitted
+            .iter()
+            .filter(|(_, submitted_at)| now.duration_since(**submitted_at) < self.ttl)
+            .map(|(fingerprint, submitted_at)| {
+                (
+                    fingerprint.clone(),
+                    now_ms - now.duration_since(*submitted_at).as_millis() as i64,
+                )
+            })
+            .collect()
+    }
+
+    /// Restores contenders exported by a previous run, skipping those older than the TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - Fingerprints and submission times in milliseconds since the epoch.
+    /// * `now` - The current instant.
+    /// * `now_ms` - The current time in milliseconds since the epoch.
+    pub(crate) fn restore(&mut self, entries: &[(String, i64)], now: Instant, now_ms: i64) {
+        for (fingerprint, submitted_ms) in entries {
+            let age: Duration = Duration::from_millis((now_ms - submitted_ms).max(0) as u64);
+            if age >= self.ttl {
+                continue;
+            }
+            if let Some(submitted_at) = now.checked_sub(age) {
+                self.submitted.insert(fingerprint.clone(), submitted_at);
+            }
+        }
+    }
+}
+
+/// Memory of the legs of recently filled combos, so contenders sharing a leg with a fill are
+/// skipped instead of stacking correlated positions off the same mispriced quote.
+pub(crate) struct FillCooldown {
+    cooldown: Duration,
+    /// The legs of each submitted order by cOID, and when it was submitted.
+    orders: FxHashMap<String, (Vec<String>, Instant)>,
+    /// When each leg last filled.
+    filled: FxHashMap<String, Instant>,
+}
+
+impl FillCooldown {
+    /// How long the legs of a submitted order are remembered for matching its fills.
+    const ORDER_MEMORY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Creates a new, empty `FillCooldown`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cooldown` - How long legs are skipped after a fill. A zero duration disables the cooldown.
+    pub(crate) fn new(cooldown: Duration) -> Self {
+        FillCooldown {
+            cooldown,
+            orders: FxHashMap::default(),
+            filled: FxHashMap::default(),
+        }
+    }
+
+    /// Remembers the legs of a submitted order and forgets orders submitted over a day ago.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_ref` - The customer order id (cOID) of the order.
+    /// * `contender` - The contender the order was built from.
+    /// * `now` - The current instant.
+    pub(crate) fn track(&mut self, order_ref: &str, contender: &Contender, now: Instant) {
+        self.orders
+            .retain(|_, (_, submitted_at)| now.duration_since(*submitted_at) < Self::ORDER_MEMORY);
+        if self.cooldown.is_zero() {
+            return;
+        }
+        self.orders
+            .insert(order_ref.to_string(), (contender.leg_keys(), now));
+    }
+
+    /// Starts the cooldown of the legs of a filled order and forgets expired cooldowns.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_ref` - The customer order id (cOID) of the filled order.
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` indicating whether the order was tracked.
+    pub(crate) fn record_fill(&mut self, order_ref: &str, now: Instant) -> bool {
+        let cooldown: Duration = self.cooldown;
+        self.filled
+            .retain(|_, filled_at| now.duration_since(*filled_at) < cooldown);
+
+        let Some((legs, _)) = self.orders.get(order_ref) else {
+            return false;
+        };
+        for leg in legs {
+            self.filled.insert(leg.clone(), now);
+        }
+        true
+    }
+
+    /// Returns whether a contender shares a leg with a combo filled within the cooldown.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender` - The contender to check.
+    /// * `now` - The current instant.
+    pub(crate) fn contains(&self, contender: &Contender, now: Instant) -> bool {
+        contender.leg_keys().iter().any(|leg| {
+            self.filled
+                .get(leg)
+                .is_some_and(|filled_at| now.duration_since(*filled_at) < self.cooldown)
+        })
+    }
+}
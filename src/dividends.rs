@@ -0,0 +1,202 @@
+use chrono::NaiveDate;
+use std::{collections::BTreeMap, error::Error, fs};
+
+/// Upcoming ex-dividend dates of an underlying, with the cash amount paid per share.
+///
+/// The calendar is filled from a file and from the IBKR market data, and is used by the scanners
+/// to avoid spreads priced across an ex-date and by the early-exercise screen.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct DividendCalendar {
+    ex_dates: BTreeMap<NaiveDate, f64>,
+}
+
+impl DividendCalendar {
+    /// Creates a new, empty `DividendCalendar`.
+    pub(crate) fn new() -> Self {
+        DividendCalendar::default()
+    }
+
+    /// Loads the dividends of an underlying from a file with one `TICKER,YYYY-MM-DD,amount` line
+    /// per dividend. Lines without a ticker, `YYYY-MM-DD,amount`, apply to every underlying.
+    ///
+    /// Blank lines, lines starting with `#` and lines that fail to parse, such as a header, are
+    /// skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the dividend file.
+    /// * `ticker` - The underlying to load the dividends of.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the calendar or an error if the file can't be read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let dividends = DividendCalendar::load("dividends.csv", "AAPL")?;
+    /// ```
+    pub(crate) fn load(path: &str, ticker: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(DividendCalendar::parse(&fs::read_to_string(path)?, ticker))
+    }
+
+    /// Parses the dividends of an underlying from the contents of a dividend file.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - The lines of the file.
+    /// * `ticker` - The underlying to keep the dividends of.
+    ///
+    /// # Returns
+    ///
+    /// A `DividendCalendar` with the parsed dividends.
+    pub(crate) fn parse(contents: &str, ticker: &str) -> Self {
+        let mut calendar: DividendCalendar = DividendCalendar::new();
+        for line in contents.lines() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+            let (date, amount) = match fields.as_slice() {
+                [date, amount] => (date, amount),
+                [row_ticker, date, amount] if row_ticker.eq_ignore_ascii_case(ticker) => {
+                    (date, amount)
+                }
+                _ => continue,
+            };
+            if let (Some(ex_date), Ok(amount)) = (parse_ex_date(date), amount.parse::<f64>()) {
+                calendar.add(ex_date, amount);
+            }
+        }
+        calendar
+    }
+
+    /// Adds a dividend, replacing one already on the same ex-date.
+    ///
+    /// # Arguments
+    ///
+    /// * `ex_date` - The ex-dividend date.
+    /// * `amount` - The cash dividend per share.
+    pub(crate) fn add(&mut self, ex_date: NaiveDate, amount: f64) {
+        self.ex_dates.insert(ex_date, amount);
+    }
+
+    /// Sums the dividends going ex after `from` and on or before `to`, i.e. those a short call
+    /// expiring on `to` can be assigned ahead of.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The current date.
+    /// * `to` - The expiration date.
+    ///
+    /// # Returns
+    ///
+    /// An `f64` with the total dividend per share, `0.0` if none go ex in the window.
+    pub(crate) fn dividends_between(&self, from: NaiveDate, to: NaiveDate) -> f64 {
+        if to <= from {
+            return 0.0;
+        }
+        self.ex_dates
+            .range(from.succ_opt().unwrap_or(from)..=to)
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    /// Returns the number of dividends in the calendar.
+    pub(crate) fn len(&self) -> usize {
+        self.ex_dates.len()
+    }
+
+    /// Returns whether the calendar has no dividends.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ex_dates.is_empty()
+    }
+}
+
+/// Parses an ex-dividend date as `YYYY-MM-DD` or IBKR's `YYYYMMDD`.
+///
+/// # Arguments
+///
+/// * `value` - The date to parse.
+///
+/// # Returns
+///
+/// An `Option<NaiveDate>` with the date, or `None` if it is in neither format.
+pub(crate) fn parse_ex_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(value.trim(), "%Y%m%d"))
+        .ok()
+}
+
+/// Screens short legs of American-style options that are likely to be exercised early, which
+/// would break the spread before expiry.
+#[derive(Clone, Debug)]
+pub(crate) struct EarlyExerciseScreen {
+    /// How far in the money a short put may be, in percent of the spot, before it is screened out.
+    pub(crate) max_put_itm_percent: f64,
+}
+
+impl EarlyExerciseScreen {
+    /// Checks a short leg for early-exercise risk.
+    ///
+    /// A short call is at risk when a dividend goes ex before it expires and exceeds the call's
+    /// remaining time value. A short put is at risk when it is deep in the money. Without a spot
+    /// price the time value and moneyness are unknown, so short calls over a dividend and all
+    /// short puts are treated as at risk.
+    ///
+    /// # Arguments
+    ///
+    /// * `right` - `"C"` or `"P"`.
+    /// * `strike` - The strike of the short leg.
+    /// * `mkt_price` - The mid price of the short leg.
+    /// * `dividend` - The dividends going ex before the short leg expires, per share.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<String>` describing the risk, or `None` if the leg passes the screen.
+    pub(crate) fn short_leg_risk(
+        &self,
+        right: &str,
+        strike: f64,
+        mkt_price: f64,
+        dividend: f64,
+        spot: Option<f64>,
+    ) -> Option<String> {
+        match right {
+            "C" => {
+                if dividend <= 0.0 {
+                    return None;
+                }
+                match spot {
+                    Some(spot) => {
+                        let time_value: f64 = mkt_price - (spot - strike).max(0.0);
+                        (time_value < dividend).then(|| {
+                            format!(
+                                "short call {} has {:.2} time value over a {:.2} dividend",
+                                strike, time_value, dividend
+                            )
+                        })
+                    }
+                    None => Some(format!(
+                        "short call {} spans a {:.2} dividend and the spot is unknown",
+                        strike, dividend
+                    )),
+                }
+            }
+            _ => match spot {
+                Some(spot) => {
+                    let itm_percent: f64 = (strike - spot) / spot * 100.0;
+                    (itm_percent > self.max_put_itm_percent).then(|| {
+                        format!("short put {} is {:.1}% in the money", strike, itm_percent)
+                    })
+                }
+                None => Some(format!(
+                    "short put {} moneyness is unknown without the spot",
+                    strike
+                )),
+            },
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    backtrace::Backtrace,
+    fs,
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[cfg(not(feature = "sentry"))]
+use crate::logging::log_message;
+use crate::redact::redact;
+
+/// What the bot was doing when an error or panic happened.
+#[derive(Serialize, Clone, Debug, Default)]
+pub(crate) struct ErrorContext {
+    pub(crate) iteration: u64,
+    pub(crate) last_endpoint: Option<String>,
+    pub(crate) contenders: Option<String>,
+}
+
+/// The context of the current iteration, attached to error reports.
+pub(crate) static ERROR_CONTEXT: Mutex<ErrorContext> = Mutex::new(ErrorContext {
+    iteration: 0,
+    last_endpoint: None,
+    contenders: None,
+});
+
+/// Keeps the error reporting client alive; reports are flushed when it is dropped.
+pub(crate) struct ErrorReporting {
+    #[cfg(feature = "sentry")]
+    _guard: Option<sentry::ClientInitGuard>,
+}
+
+/// Starts reporting panics and errors to Sentry when a DSN is configured.
+///
+/// # Arguments
+///
+/// * `dsn` - The Sentry DSN, if any.
+///
+/// # Returns
+///
+/// An `ErrorReporting` guard to hold for the lifetime of the bot.
+pub(crate) fn init_error_reporting(dsn: Option<String>) -> ErrorReporting {
+    #[cfg(feature = "sentry")]
+    {
+        ErrorReporting {
+            _guard: dsn.map(|dsn| {
+                sentry::init((
+                    dsn,
+                    sentry::ClientOptions {
+                        release: sentry::release_name!(),
+                        ..Default::default()
+                    },
+                ))
+            }),
+        }
+    }
+    #[cfg(not(feature = "sentry"))]
+    {
+        if dsn.is_some() {
+            log_message(
+                "SENTRY_DSN is set but the bot was built without the sentry feature.".to_string(),
+            );
+        }
+        ErrorReporting {}
+    }
+}
+
+/// Updates the error context and mirrors it to the Sentry scope.
+///
+/// # Arguments
+///
+/// * `update` - The change to apply to the context.
+fn update_context(update: impl FnOnce(&mut ErrorContext)) {
+    let mut context = ERROR_CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+    update(&mut context);
+
+    #[cfg(feature = "sentry")]
+    sentry::configure_scope(|scope| {
+        scope.set_extra("iteration", context.iteration.into());
+        scope.set_extra("last_endpoint", context.last_endpoint.clone().into());
+        scope.set_extra("contenders", context.contenders.clone().into());
+    });
+}
+
+/// Records the start of a new trading loop iteration.
+///
+/// # Arguments
+///
+/// * `iteration` - The iteration number, starting at 1.
+pub(crate) fn set_iteration(iteration: u64) {
+    update_context(|context| {
+        context.iteration = iteration;
+        context.contenders = None;
+    });
+}
+
+/// Records the IBKR endpoint about to be called.
+///
+/// # Arguments
+///
+/// * `url` - The endpoint URL.
+pub(crate) fn record_endpoint(url: &str) {
+    update_context(|context| context.last_endpoint = Some(url.to_string()));
+}
+
+/// Records a summary of the contenders selected in this iteration.
+///
+/// # Arguments
+///
+/// * `summary` - The contender summary, e.g. `Butterfly 240701 @ 0.35`.
+pub(crate) fn set_contender_summary(summary: String) {
+    update_context(|context| context.contenders = Some(summary));
+}
+
+/// Reports an error to Sentry, if enabled, along with the current context.
+///
+/// The message is redacted as the logs are, and the report is flushed right away since
+/// `log_error` exits the process afterwards.
+///
+/// # Arguments
+///
+/// * `message` - The error message.
+///
+/// # Returns
+///
+/// A `String` with the message as reported.
+pub(crate) fn report_error(message: &str) -> String {
+    let message: String = redact(message);
+    #[cfg(feature = "sentry")]
+    {
+        sentry::capture_message(&message, sentry::Level::Error);
+        if let Some(client) = sentry::Hub::current().client() {
+            client.flush(Some(std::time::Duration::from_secs(2)));
+        }
+    }
+    message
+}
+
+/// Builds a crash report with the panic message and location, the backtrace and the error
+/// context.
+///
+/// # Arguments
+///
+/// * `message` - The panic message.
+/// * `location` - Where the panic happened, if known.
+///
+/// # Returns
+///
+/// The crash report as JSON.
+pub(crate) fn crash_report(message: &str, location: Option<String>) -> serde_json::Value {
+    let context: ErrorContext = ERROR_CONTEXT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+
+    json!({
+        "time": Utc::now().format("%Y%m%dT%H%M%S").to_string(),
+        "message": message,
+        "location": location,
+        "thread": std::thread::current().name().map(str::to_string),
+        "context": context,
+        "backtrace": Backtrace::force_capture().to_string(),
+    })
+}
+
+/// Installs a panic hook that writes a crash report, `crash_<time>.json`, with the panic message,
+/// location, backtrace and error context, then runs the previously installed hook.
+///
+/// # Arguments
+///
+/// * `directory` - The directory the crash reports are written to.
+pub(crate) fn install_panic_hook(directory: &Path) {
+    let directory: PathBuf = directory.to_path_buf();
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info: &PanicHookInfo| {
+        let message: String = if let Some(message) = info.payload().downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = info.payload().downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_string()
+        };
+
+        let report: serde_json::Value = crash_report(
+            &message,
+            info.location().map(|location| location.to_string()),
+        );
+        let path: PathBuf = directory.join(format!(
+            "crash_{}.json",
+            report["time"].as_str().unwrap_or_default()
+        ));
+        if fs::write(&path, report.to_string()).is_ok() {
+            eprintln!("Wrote crash report to {}.", path.display());
+        }
+
+        previous_hook(info);
+    }));
+}
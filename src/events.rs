@@ -0,0 +1,263 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::TcpStream,
+    sync::{Mutex, MutexGuard},
+};
+
+#[cfg(not(all(feature = "kafka", feature = "nats", feature = "mqtt")))]
+use crate::logging::log_message;
+#[cfg(feature = "kafka")]
+use crate::publish::KafkaSink;
+#[cfg(feature = "mqtt")]
+use crate::publish::MqttSink;
+#[cfg(feature = "nats")]
+use crate::publish::NatsSink;
+use crate::{
+    chain::{ExpiryDate, OptionChain},
+    latency::StageTimings,
+    redact::redact_json,
+    structs::{Contender, EventsConfig, Reason},
+};
+
+/// A significant bot event, written as one JSON line with an `event` tag.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event {
+    ScanStart {
+        scan_time: i64,
+    },
+    ScanEnd {
+        scan_time: i64,
+        contenders: usize,
+        duration_ms: u128,
+    },
+    Contender {
+        scan_time: i64,
+        strategy: String,
+        exp_date: ExpiryDate,
+        arb_val: f64,
+        rank_value: f64,
+        fill_probability: f64,
+        legs: Vec<String>,
+        /// The legs as OSI symbols, e.g. `SPXW  241220C05900000`.
+        osi_legs: Vec<String>,
+        reason: Reason,
+    },
+    OrderSubmitted {
+        order_ref: String,
+        strategy: String,
+        exp_date: ExpiryDate,
+        price: f64,
+        quantity: i32,
+    },
+    OrderCanceled {
+        order_id: String,
+    },
+    Fill {
+        execution_id: String,
+        order_ref: String,
+        size: f64,
+        fill_price: f64,
+        realized_edge: f64,
+    },
+    Settlement {
+        order_ref: String,
+        /// `"assignment"`, `"exercise"` or `"expiration"`.
+        outcome: String,
+        size: f64,
+        /// The legs that left the account as OSI symbols with their signed contracts.
+        legs: Vec<String>,
+    },
+    Error {
+        message: String,
+    },
+    StageTimings {
+        iteration: u64,
+        stages_ms: BTreeMap<String, u128>,
+        total_ms: u128,
+    },
+}
+
+impl Event {
+    /// Builds a contender event.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender` - The contender selected by the scan.
+    /// * `ticker` - The ticker of the underlying, for the OSI symbols of the legs.
+    /// * `chain` - The chain the contender was scanned from, for the trading classes of the legs.
+    pub(crate) fn contender(
+        contender: &Contender,
+        ticker: &str,
+        chain: Option<&OptionChain>,
+    ) -> Self {
+        Event::Contender {
+            scan_time: contender.scan_time,
+            strategy: contender.type_spread.clone(),
+            exp_date: contender.exp_date,
+            arb_val: contender.arb_val,
+            rank_value: contender.rank_value,
+            fill_probability: contender.fill_probability,
+            legs: contender
+                .contracts
+                .iter()
+                .map(|contract| contract.describe())
+                .collect(),
+            osi_legs: contender
+                .contracts
+                .iter()
+                .map(|contract| contract.osi_symbol(ticker, chain))
+                .collect(),
+            reason: contender.reason.clone(),
+        }
+    }
+
+    /// Builds a stage timings event.
+    ///
+    /// # Arguments
+    ///
+    /// * `iteration` - The loop iteration the stages ran in.
+    /// * `stage_timings` - How long each stage took.
+    pub(crate) fn stage_timings(iteration: u64, stage_timings: &StageTimings) -> Self {
+        Event::StageTimings {
+            iteration,
+            stages_ms: stage_timings
+                .stages()
+                .iter()
+                .map(|(stage, duration)| (stage.to_string(), duration.as_millis()))
+                .collect(),
+            total_ms: stage_timings.total().as_millis(),
+        }
+    }
+}
+
+/// An event with the time it was emitted.
+#[derive(Serialize)]
+struct TimedEvent<'a> {
+    time: String,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// A destination for the JSON lines of the event stream.
+pub(crate) trait EventSink: Send {
+    /// Writes a single JSON line.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The serialized event, without a trailing newline.
+    fn send(&mut self, line: &str) -> io::Result<()>;
+}
+
+impl EventSink for File {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self, "{}", line)
+    }
+}
+
+impl EventSink for TcpStream {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self, "{}", line)?;
+        self.flush()
+    }
+}
+
+/// The sinks every event is written to.
+static EVENT_SINKS: Mutex<Vec<Box<dyn EventSink>>> = Mutex::new(Vec::new());
+
+/// Adds a sink to the event stream.
+///
+/// # Arguments
+///
+/// * `sink` - The sink to add.
+pub(crate) fn add_sink(sink: Box<dyn EventSink>) {
+    EVENT_SINKS.lock().unwrap().push(sink);
+}
+
+/// Removes every sink from the event stream, closing its file, socket or publisher.
+pub(crate) fn clear_sinks() {
+    EVENT_SINKS.lock().unwrap().clear();
+}
+
+/// Opens the configured event file, socket and broker publishers and adds them to the event stream.
+///
+/// # Arguments
+///
+/// * `config` - The event stream configuration.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an error if a sink could not be opened.
+pub(crate) fn init_events(config: &EventsConfig) -> io::Result<()> {
+    if let Some(path) = &config.path {
+        add_sink(Box::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        ));
+    }
+    if let Some(addr) = &config.addr {
+        add_sink(Box::new(TcpStream::connect(addr)?));
+    }
+
+    if let Some((brokers, topic)) = &config.kafka {
+        #[cfg(feature = "kafka")]
+        add_sink(Box::new(KafkaSink::connect(brokers, topic)?));
+        #[cfg(not(feature = "kafka"))]
+        log_message(format!(
+            "Not publishing events to Kafka {} ({}): built without the kafka feature.",
+            brokers, topic
+        ));
+    }
+    if let Some((url, subject)) = &config.nats {
+        #[cfg(feature = "nats")]
+        add_sink(Box::new(NatsSink::connect(url, subject)?));
+        #[cfg(not(feature = "nats"))]
+        log_message(format!(
+            "Not publishing events to NATS {} ({}): built without the nats feature.",
+            url, subject
+        ));
+    }
+    if let Some(mqtt) = &config.mqtt {
+        #[cfg(feature = "mqtt")]
+        add_sink(Box::new(MqttSink::connect(mqtt)?));
+        #[cfg(not(feature = "mqtt"))]
+        log_message(format!(
+            "Not publishing events to MQTT {} ({}, {}): built without the mqtt feature.",
+            // Leave out the login.
+            mqtt.broker.rsplit('@').next().unwrap_or_default(),
+            mqtt.contender_topic,
+            mqtt.fill_topic
+        ));
+    }
+    Ok(())
+}
+
+/// Writes an event to every sink, redacted as the logs are, dropping sinks that fail.
+///
+/// # Arguments
+///
+/// * `event` - The event to emit.
+///
+/// # Example
+///
+/// ```
+/// emit(&Event::OrderCanceled { order_id: "123".to_string() });
+/// ```
+pub(crate) fn emit(event: &Event) {
+    let mut sinks: MutexGuard<'_, Vec<Box<dyn EventSink>>> = EVENT_SINKS.lock().unwrap();
+    if sinks.is_empty() {
+        return;
+    }
+
+    let Ok(value) = serde_json::to_value(TimedEvent {
+        time: Utc::now().format("%Y-%m-%d %H:%M:%S%.9f UTC").to_string(),
+        event,
+    }) else {
+        return;
+    };
+    let line: String = redact_json(&value).to_string();
+    sinks.retain_mut(|sink| sink.send(&line).is_ok());
+}
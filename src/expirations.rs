@@ -0,0 +1,476 @@
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use std::{collections::BTreeSet, str::FromStr};
+
+use crate::chain::ExpiryDate;
+
+/// How far ahead listed expirations are checked against the calendar. Weeklies are only listed a
+/// few weeks out, so further ahead the rules would expect expirations that don't exist yet.
+const CROSS_CHECK_DAYS: i64 = 28;
+
+/// How far ahead `next N` selectors look for their expirations.
+const SELECTOR_HORIZON_DAYS: i64 = 366;
+
+/// The expirations an index option series lists, from the exchange rules and the holiday
+/// calendar.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExpirationCalendar {
+    /// The weekdays weeklies expire on, e.g. Monday, Wednesday and Friday for SPX.
+    pub(crate) weekdays: Vec<Weekday>,
+    /// Whether the standard monthly expires on the third Friday.
+    pub(crate) monthly: bool,
+    /// Whether an expiration is listed on the last trading day of each month.
+    pub(crate) end_of_month: bool,
+    /// Unscheduled market closures on top of the regular holidays, e.g. national days of mourning.
+    pub(crate) closures: BTreeSet<NaiveDate>,
+}
+
+impl Default for ExpirationCalendar {
+    fn default() -> Self {
+        ExpirationCalendar {
+            weekdays: vec![Weekday::Mon, Weekday::Wed, Weekday::Fri],
+            monthly: true,
+            end_of_month: true,
+            closures: BTreeSet::new(),
+        }
+    }
+}
+
+impl ExpirationCalendar {
+    /// Returns whether the options market is open on a date.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The date.
+    pub(crate) fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+            && !self.closures.contains(&date)
+            && !market_holidays(date.year()).contains(&date)
+    }
+
+    /// Returns the date itself if the market is open then, or else the trading day before it, as
+    /// expirations falling on a holiday move to the day before.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The scheduled expiration date.
+    pub(crate) fn on_or_before(&self, date: NaiveDate) -> NaiveDate {
+        let mut date: NaiveDate = date;
+        while !self.is_trading_day(date) {
+            date -= Duration::days(1);
+        }
+        date
+    }
+
+    /// Generates the expirations the rules list between two dates.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The first date, included.
+    /// * `to` - The last date, included.
+    ///
+    /// # Returns
+    ///
+    /// A `BTreeSet<ExpiryDate>` of the expirations, each on a trading day.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let calendar = ExpirationCalendar::default();
+    /// for expiry in calendar.expirations(Local::now().date_naive(), Local::now().date_naive() + Duration::days(14)) {
+    ///     println!("{}", expiry);
+    /// }
+    /// ```
+    pub(crate) fn expirations(&self, from: NaiveDate, to: NaiveDate) -> BTreeSet<ExpiryDate> {
+        let mut scheduled: Vec<NaiveDate> = Vec::new();
+        // A holiday can move an expiration a few days back, into the range.
+        let mut date: NaiveDate = from;
+        while date <= to + Duration::days(7) {
+            if self.weekdays.contains(&date.weekday()) {
+                scheduled.push(date);
+            }
+            let next_day: NaiveDate = date + Duration::days(1);
+            if self.monthly && date.weekday() == Weekday::Fri && (15..=21).contains(&date.day()) {
+                scheduled.push(date);
+            }
+            if self.end_of_month && next_day.month() != date.month() {
+                scheduled.push(date);
+            }
+            date = next_day;
+        }
+
+        scheduled
+            .into_iter()
+            .map(|date| self.on_or_before(date))
+            .filter(|date| *date >= from && *date <= to)
+            .map(ExpiryDate::new)
+            .collect()
+    }
+
+    /// Returns the kind of an expiration. The monthly takes precedence over the end-of-month
+    /// expiration, and both over the weekly, when they fall on the same day.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The expiration date.
+    pub(crate) fn kind(&self, date: NaiveDate) -> ExpiryKind {
+        let third_friday: NaiveDate = nth_weekday(date.year(), date.month(), Weekday::Fri, 3);
+        let month_end: NaiveDate = NaiveDate::from_ymd_opt(date.year(), date.month(), 1)
+            .unwrap()
+            .checked_add_months(Months::new(1))
+            .unwrap()
+            - Duration::days(1);
+        if self.monthly && self.on_or_before(third_friday) == date {
+            ExpiryKind::Monthly
+        } else if self.end_of_month && self.on_or_before(month_end) == date {
+            ExpiryKind::EndOfMonth
+        } else {
+            ExpiryKind::Weekly
+        }
+    }
+
+    /// Evaluates expiry selectors against the calendar.
+    ///
+    /// # Arguments
+    ///
+    /// * `selectors` - The selectors, whose expirations are combined.
+    /// * `current_date` - The current date, which days to expiry count from.
+    /// * `exclude_same_day` - Whether today's expiration is left out.
+    ///
+    /// # Returns
+    ///
+    /// A `BTreeSet<ExpiryDate>` of the selected expirations.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let selectors = parse_expiry_selectors("next 3 weeklies; monthlies within 60 DTE").unwrap();
+    /// let calendar = ExpirationCalendar::default();
+    /// for expiry in calendar.select(&selectors, Local::now().date_naive(), false) {
+    ///     println!("{}", expiry);
+    /// }
+    /// ```
+    pub(crate) fn select(
+        &self,
+        selectors: &[ExpirySelector],
+        current_date: NaiveDate,
+        exclude_same_day: bool,
+    ) -> BTreeSet<ExpiryDate> {
+        let first: NaiveDate = current_date + Duration::days(i64::from(exclude_same_day));
+        let of_kind = |kind: Option<ExpiryKind>, expiry: &ExpiryDate| {
+            kind.is_none_or(|kind| self.kind(expiry.date()) == kind)
+        };
+
+        let mut selected: BTreeSet<ExpiryDate> = BTreeSet::new();
+        for selector in selectors {
+            match selector {
+                ExpirySelector::Next {
+                    count,
+                    kind,
+                    min_dte,
+                } => {
+                    let from: NaiveDate = first.max(current_date + Duration::days(*min_dte));
+                    selected.extend(
+                        self.expirations(from, from + Duration::days(SELECTOR_HORIZON_DAYS))
+                            .into_iter()
+                            .filter(|expiry| of_kind(*kind, expiry))
+                            .take(*count),
+                    );
+                }
+                ExpirySelector::Within { kind, max_dte } => selected.extend(
+                    self.expirations(first, current_date + Duration::days(*max_dte))
+                        .into_iter()
+                        .filter(|expiry| of_kind(*kind, expiry)),
+                ),
+                ExpirySelector::Dates(dates) => {
+                    selected.extend(dates.iter().filter(|expiry| expiry.date() >= first))
+                }
+            }
+        }
+        selected
+    }
+
+    /// Compares the expirations the gateway lists with those the rules generate, over the weeks
+    /// ahead where every weekly should already be listed.
+    ///
+    /// # Arguments
+    ///
+    /// * `listed` - The expirations the gateway returned, on or after `current_date`.
+    /// * `current_date` - The current date.
+    ///
+    /// # Returns
+    ///
+    /// An `ExpiryCheck` with the expirations only one of the two sources has.
+    pub(crate) fn cross_validate(
+        &self,
+        listed: &BTreeSet<ExpiryDate>,
+        current_date: NaiveDate,
+    ) -> ExpiryCheck {
+        let Some(last_listed) = listed.last() else {
+            return ExpiryCheck::default();
+        };
+        let to: NaiveDate = last_listed
+            .date()
+            .min(current_date + Duration::days(CROSS_CHECK_DAYS));
+        let expected: BTreeSet<ExpiryDate> = self.expirations(current_date, to);
+        let listed: BTreeSet<ExpiryDate> = listed.range(..=ExpiryDate::new(to)).copied().collect();
+        ExpiryCheck {
+            missing: expected.difference(&listed).copied().collect(),
+            unexpected: listed.difference(&expected).copied().collect(),
+            through: Some(ExpiryDate::new(to)),
+        }
+    }
+}
+
+/// The kind of an expiration in the exchange calendar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExpiryKind {
+    Weekly,
+    /// The standard third-Friday expiration.
+    Monthly,
+    /// The last trading day of the month.
+    EndOfMonth,
+}
+
+/// Which expirations the bot scans, evaluated against the exchange calendar.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ExpirySelector {
+    /// The next `count` expirations of a kind, or of any kind if `None`, at least `min_dte` days
+    /// out, e.g. `next 3 weeklies` or `next 5 from 7 DTE`.
+    Next {
+        count: usize,
+        kind: Option<ExpiryKind>,
+        min_dte: i64,
+    },
+    /// Every expiration of a kind, or of any kind if `None`, up to `max_dte` days out, e.g.
+    /// `monthlies within 60 DTE`.
+    Within {
+        kind: Option<ExpiryKind>,
+        max_dte: i64,
+    },
+    /// Explicit expirations, e.g. `240719,240816`.
+    Dates(Vec<ExpiryDate>),
+}
+
+/// Parses the kind of an expiration selector, `None` meaning any kind.
+fn parse_kind(word: &str) -> Result<Option<ExpiryKind>, String> {
+    match word {
+        "expiration" | "expirations" => Ok(None),
+        "weekly" | "weeklies" => Ok(Some(ExpiryKind::Weekly)),
+        "monthly" | "monthlies" => Ok(Some(ExpiryKind::Monthly)),
+        "end-of-month" => Ok(Some(ExpiryKind::EndOfMonth)),
+        _ => Err(format!(
+            "unknown expiration kind {}, expected expirations, weeklies, monthlies or end-of-month",
+            word
+        )),
+    }
+}
+
+/// Parses a number of days to expiry.
+fn parse_dte(word: &str) -> Result<i64, String> {
+    word.parse::<i64>()
+        .ok()
+        .filter(|dte| *dte >= 0)
+        .ok_or(format!("invalid days to expiry {}", word))
+}
+
+impl FromStr for ExpirySelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text: String = s.trim().to_lowercase();
+        if !text.is_empty()
+            && text
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == ',' || c.is_whitespace())
+        {
+            return text
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::parse::<ExpiryDate>)
+                .collect::<Result<Vec<ExpiryDate>, String>>()
+                .map(ExpirySelector::Dates);
+        }
+
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            ["next", count, rest @ ..] => {
+                let count: usize = count
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or(format!("invalid number of expirations {}", count))?;
+                let (kind, rest): (Option<ExpiryKind>, &[&str]) = match rest {
+                    [kind, rest @ ..] if *kind != "from" => (parse_kind(kind)?, rest),
+                    _ => (None, rest),
+                };
+                let min_dte: i64 = match rest {
+                    [] => 0,
+                    ["from", dte, "dte"] => parse_dte(dte)?,
+                    _ => return Err(format!("invalid expiry selector {:?}", s.trim())),
+                };
+                Ok(ExpirySelector::Next {
+                    count,
+                    kind,
+                    min_dte,
+                })
+            }
+            [kind @ .., "within", dte, "dte"] if kind.len() <= 1 => Ok(ExpirySelector::Within {
+                kind: match kind.first() {
+                    Some(kind) => parse_kind(kind)?,
+                    None => None,
+                },
+                max_dte: parse_dte(dte)?,
+            }),
+            _ => Err(format!("invalid expiry selector {:?}", s.trim())),
+        }
+    }
+}
+
+/// Parses semicolon-separated expiry selectors, e.g. `"next 3 weeklies; monthlies within 60 DTE"`.
+///
+/// # Arguments
+///
+/// * `text` - The selectors.
+///
+/// # Returns
+///
+/// A `Result` containing the selectors, or an error describing the first invalid one.
+pub(crate) fn parse_expiry_selectors(text: &str) -> Result<Vec<ExpirySelector>, String> {
+    let selectors: Vec<ExpirySelector> = text
+        .split(';')
+        .filter(|item| !item.trim().is_empty())
+        .map(str::parse::<ExpirySelector>)
+        .collect::<Result<Vec<ExpirySelector>, String>>()?;
+    if selectors.is_empty() {
+        return Err("no expiry selectors".to_string());
+    }
+    Ok(selectors)
+}
+
+/// The differences between the listed expirations and the exchange calendar.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ExpiryCheck {
+    /// The expirations the rules generate but the gateway doesn't list.
+    pub(crate) missing: Vec<ExpiryDate>,
+    /// The expirations the gateway lists but the rules don't generate.
+    pub(crate) unexpected: Vec<ExpiryDate>,
+    /// The last date checked, or `None` if nothing was listed.
+    pub(crate) through: Option<ExpiryDate>,
+}
+
+impl ExpiryCheck {
+    /// Returns whether the two sources agree.
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+
+    /// Describes the differences.
+    ///
+    /// # Returns
+    ///
+    /// A `String` such as `"Listed expirations differ from the exchange calendar through 240726:
+    /// missing 240712, unexpected 240711"`.
+    pub(crate) fn describe(&self) -> String {
+        let join = |dates: &[ExpiryDate]| -> String {
+            dates
+                .iter()
+                .map(|date| date.to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        let through: String = self
+            .through
+            .map_or("-".to_string(), |through| through.to_string());
+        if self.is_consistent() {
+            return format!(
+                "Listed expirations match the exchange calendar through {}",
+                through
+            );
+        }
+
+        let mut differences: Vec<String> = Vec::new();
+        if !self.missing.is_empty() {
+            differences.push(format!("missing {}", join(&self.missing)));
+        }
+        if !self.unexpected.is_empty() {
+            differences.push(format!("unexpected {}", join(&self.unexpected)));
+        }
+        format!(
+            "Listed expirations differ from the exchange calendar through {}: {}",
+            through,
+            differences.join(", ")
+        )
+    }
+}
+
+/// Returns the NYSE and Cboe holidays of a year, on the weekday they are observed.
+///
+/// A holiday on a Saturday is observed the Friday before and one on a Sunday the Monday after,
+/// except New Year's Day on a Saturday, which isn't observed at all.
+///
+/// # Arguments
+///
+/// * `year` - The year.
+///
+/// # Returns
+///
+/// A `Vec<NaiveDate>` of the holidays in date order.
+pub(crate) fn market_holidays(year: i32) -> Vec<NaiveDate> {
+    let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    let observed = |date: NaiveDate| match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    };
+
+    let mut holidays: Vec<NaiveDate> = Vec::new();
+    if date(1, 1).weekday() != Weekday::Sat {
+        holidays.push(observed(date(1, 1)));
+    }
+    holidays.push(nth_weekday(year, 1, Weekday::Mon, 3));
+    holidays.push(nth_weekday(year, 2, Weekday::Mon, 3));
+    holidays.push(easter(year) - Duration::days(2));
+    holidays.push(last_weekday(year, 5, Weekday::Mon));
+    if year >= 2022 {
+        holidays.push(observed(date(6, 19)));
+    }
+    holidays.push(observed(date(7, 4)));
+    holidays.push(nth_weekday(year, 9, Weekday::Mon, 1));
+    holidays.push(nth_weekday(year, 11, Weekday::Thu, 4));
+    holidays.push(observed(date(12, 25)));
+    holidays
+}
+
+/// Returns the `n`th weekday of a month, e.g. the third Monday of January.
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u32) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n as u8).unwrap()
+}
+
+/// Returns the last weekday of a month, e.g. the last Monday of May.
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    let mut date: NaiveDate = nth_weekday(year, month, weekday, 4);
+    while (date + Duration::days(7)).month() == month {
+        date += Duration::days(7);
+    }
+    date
+}
+
+/// Returns Easter Sunday of a year, by the anonymous Gregorian algorithm.
+fn easter(year: i32) -> NaiveDate {
+    let a: i32 = year % 19;
+    let b: i32 = year / 100;
+    let c: i32 = year % 100;
+    let d: i32 = b / 4;
+    let e: i32 = b % 4;
+    let f: i32 = (b + 8) / 25;
+    let g: i32 = (b - f + 1) / 3;
+    let h: i32 = (19 * a + b - d - g + 15) % 30;
+    let i: i32 = c / 4;
+    let k: i32 = c % 4;
+    let l: i32 = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m: i32 = (a + 11 * h + 22 * l) / 451;
+    let month: i32 = (h + l - 7 * m + 114) / 31;
+    let day: i32 = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
@@ -0,0 +1,138 @@
+use crate::{chain::Quotes, structs::FastMarketConfig};
+
+/// What the fast market detector compares between scans.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct MarketSample {
+    /// The price of the underlying, if it could be fetched.
+    pub(crate) spot: Option<f64>,
+    /// The average bid-ask spread of the two-sided quotes, relative to their mid.
+    pub(crate) avg_spread: Option<f64>,
+}
+
+impl MarketSample {
+    /// Samples a scan's quotes and underlying price.
+    ///
+    /// # Arguments
+    ///
+    /// * `quotes` - The quotes of the scan.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    pub(crate) fn capture(quotes: &Quotes, spot: Option<f64>) -> Self {
+        let spreads: Vec<f64> = quotes
+            .values()
+            .filter(|opt| opt.bid > 0.0 && opt.ask >= opt.bid)
+            .map(|opt| (opt.ask - opt.bid) / ((opt.ask + opt.bid) / 2.0))
+            .collect();
+        MarketSample {
+            spot,
+            avg_spread: (!spreads.is_empty())
+                .then(|| spreads.iter().sum::<f64>() / spreads.len() as f64),
+        }
+    }
+}
+
+/// Detects fast markets from the samples of consecutive scans, shortening the sleep interval and
+/// raising the arb threshold while one lasts.
+pub(crate) struct FastMarket {
+    config: FastMarketConfig,
+    previous: Option<MarketSample>,
+    remaining_scans: u32,
+}
+
+impl FastMarket {
+    /// Creates a new `FastMarket` with no previous scan.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The triggers and the adjustments of a fast market.
+    pub(crate) fn new(config: FastMarketConfig) -> Self {
+        FastMarket {
+            config,
+            previous: None,
+            remaining_scans: 0,
+        }
+    }
+
+    /// Returns whether a fast market is in effect.
+    pub(crate) fn is_active(&self) -> bool {
+        self.remaining_scans > 0
+    }
+
+    /// Compares a scan's sample with the previous one, starting or extending a fast market if it
+    /// moved past a trigger and counting down one scan otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - The sample of the latest scan.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<String>` describing the trigger, or `None` if nothing moved past one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// if let Some(trigger) = fast_market.observe(MarketSample::capture(&quotes, spot)) {
+    ///     log_message(format!("Fast market: {}.", trigger));
+    /// }
+    /// ```
+    pub(crate) fn observe(&mut self, sample: MarketSample) -> Option<String> {
+        let previous: Option<MarketSample> = self.previous.replace(sample);
+        let trigger: Option<String> = previous.and_then(|previous| {
+            let moved: Option<String> = self.config.max_move_percent.and_then(|max_move| {
+                let (spot, previous_spot) = (sample.spot?, previous.spot?);
+                let move_percent: f64 = (spot / previous_spot - 1.0) * 100.0;
+                (move_percent.abs() > max_move).then(|| {
+                    format!(
+                        "the underlying moved {:+.2}% from {:.2} to {:.2}",
+                        move_percent, previous_spot, spot
+                    )
+                })
+            });
+            let widened = || -> Option<String> {
+                let max_multiple: f64 = self.config.max_spread_multiple?;
+                let (spread, previous_spread) = (sample.avg_spread?, previous.avg_spread?);
+                (previous_spread > 0.0 && spread > previous_spread * max_multiple).then(|| {
+                    format!(
+                        "the average spread widened from {:.1}% to {:.1}%",
+                        previous_spread * 100.0,
+                        spread * 100.0
+                    )
+                })
+            };
+            moved.or_else(widened)
+        });
+
+        if trigger.is_some() {
+            self.remaining_scans = self.config.scans;
+        } else {
+            self.remaining_scans = self.remaining_scans.saturating_sub(1);
+        }
+        trigger
+    }
+
+    /// Returns the scan interval to use, shortened during a fast market.
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds_to_sleep` - The scan interval of the schedule.
+    pub(crate) fn interval(&self, seconds_to_sleep: u64) -> u64 {
+        if self.is_active() {
+            seconds_to_sleep.min(self.config.seconds_to_sleep)
+        } else {
+            seconds_to_sleep
+        }
+    }
+
+    /// Returns the arb threshold to use, raised during a fast market.
+    ///
+    /// # Arguments
+    ///
+    /// * `arb_val` - The arb threshold of the schedule.
+    pub(crate) fn arb_threshold(&self, arb_val: f64) -> f64 {
+        if self.is_active() {
+            arb_val + self.config.extra_edge
+        } else {
+            arb_val
+        }
+    }
+}
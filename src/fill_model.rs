@@ -0,0 +1,96 @@
+use rand::Rng;
+
+/// How the fill model decides whether a limit order fills.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillMode {
+    /// Fill at the limit only if the combo mid crosses it by the cross threshold on the next quote.
+    Cross,
+    /// Fill at the limit with the estimated fill probability.
+    Probabilistic,
+}
+
+impl FillMode {
+    /// Converts a string (`"cross"` or `"probabilistic"`) to a `FillMode`.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - A string representing the fill mode.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Self>` representing the converted fill mode.
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "cross" => Some(FillMode::Cross),
+            "probabilistic" => Some(FillMode::Probabilistic),
+            _ => None,
+        }
+    }
+}
+
+/// Fill model shared by the backtester and the live fill probability estimate.
+///
+/// The fill probability falls off logistically with the quoted edge: the further a spread looks
+/// from fair, the more likely the quote is stale and the less likely the order fills.
+#[derive(Clone, Debug)]
+pub struct FillModel {
+    pub mode: FillMode,
+    pub cross_threshold: f64,
+    pub probability_midpoint: f64,
+    pub probability_scale: f64,
+}
+
+impl FillModel {
+    /// Estimates the probability that a contender fills.
+    ///
+    /// # Arguments
+    ///
+    /// * `edge` - The quoted edge of the contender per combo.
+    ///
+    /// # Returns
+    ///
+    /// An `f64` between 0 and 1, equal to 0.5 when the edge is at the midpoint.
+    pub(crate) fn fill_probability(&self, edge: f64) -> f64 {
+        1.0 / (1.0 + ((edge - self.probability_midpoint) / self.probability_scale).exp())
+    }
+
+    /// Checks whether a buy limit order fills against the next combo mid.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit_price` - The limit price of the order.
+    /// * `next_price` - The combo mid price on the next quote.
+    pub(crate) fn fills_at_limit(&self, limit_price: f64, next_price: f64) -> bool {
+        next_price <= limit_price - self.cross_threshold
+    }
+
+    /// Simulates whether all orders of a contender fill.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit_prices` - The limit price of each order.
+    /// * `next_prices` - The combo mid price of each order on the next quote, if every leg was quoted.
+    /// * `fill_probability` - The estimated fill probability of the contender.
+    /// * `rng` - The random number generator for probabilistic fills.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is `true` if the contender filled.
+    pub(crate) fn simulate_fill<R: Rng>(
+        &self,
+        limit_prices: &[f64],
+        next_prices: Option<&[f64]>,
+        fill_probability: f64,
+        rng: &mut R,
+    ) -> bool {
+        match self.mode {
+            FillMode::Cross => next_prices.is_some_and(|next_prices| {
+                limit_prices
+                    .iter()
+                    .zip(next_prices)
+                    .all(|(limit_price, next_price)| self.fills_at_limit(*limit_price, *next_price))
+            }),
+            FillMode::Probabilistic => rng.gen::<f64>() < fill_probability,
+        }
+    }
+}
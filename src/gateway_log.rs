@@ -0,0 +1,117 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use crate::logging::log_message;
+
+/// Follows a log file as it grows, like `tail -f`, starting from its end.
+pub(crate) struct LogTail {
+    path: PathBuf,
+    position: u64,
+    /// The start of a line whose end hasn't been written yet.
+    partial: String,
+}
+
+impl LogTail {
+    /// Creates a new `LogTail` that skips what the file already holds.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The log file, which may not exist yet.
+    pub(crate) fn new(path: PathBuf) -> Self {
+        let position: u64 = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        LogTail {
+            path,
+            position,
+            partial: String::new(),
+        }
+    }
+
+    /// Reads the lines written since the last poll. A file that shrank was rotated or truncated,
+    /// so it is read again from the start.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the complete new lines, or an error if the file couldn't be read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tail = LogTail::new(PathBuf::from("gateway.log"));
+    /// for line in tail.poll()? {
+    ///     println!("{}", line);
+    /// }
+    /// ```
+    pub(crate) fn poll(&mut self) -> io::Result<Vec<String>> {
+        let mut file: File = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let len: u64 = file.metadata()?.len();
+        if len < self.position {
+            self.position = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.position))?;
+        let mut bytes: Vec<u8> = Vec::new();
+        self.position += file.read_to_end(&mut bytes)? as u64;
+
+        self.partial += &String::from_utf8_lossy(&bytes);
+        let Some(end) = self.partial.rfind('\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: String = self.partial.drain(..=end).collect();
+        Ok(complete
+            .lines()
+            .map(|line| line.trim_end_matches('\r').to_string())
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+}
+
+/// Returns whether a gateway log line reports an error or warning.
+///
+/// # Arguments
+///
+/// * `line` - The log line.
+pub(crate) fn is_gateway_error(line: &str) -> bool {
+    ["ERROR", "WARN", "Exception", "SEVERE"]
+        .iter()
+        .any(|marker| line.contains(marker))
+}
+
+/// Tails the gateway's log on a background thread, logging its errors and warnings so they carry
+/// the correlation ID of the scan iteration that was running when they happened.
+///
+/// # Arguments
+///
+/// * `path` - The gateway's log file.
+/// * `interval` - How often the file is checked for new lines.
+pub(crate) fn spawn_gateway_log_tail(path: PathBuf, interval: Duration) {
+    thread::spawn(move || {
+        let mut tail: LogTail = LogTail::new(path);
+        loop {
+            match tail.poll() {
+                Ok(lines) => {
+                    for line in lines.iter().filter(|line| is_gateway_error(line)) {
+                        log_message(format!("Gateway: {}", line));
+                    }
+                }
+                Err(e) => {
+                    log_message(format!(
+                        "Failed to read the gateway log {}: {}, no longer tailing it.",
+                        tail.path.display(),
+                        e
+                    ));
+                    break;
+                }
+            }
+            thread::sleep(interval);
+        }
+    });
+}
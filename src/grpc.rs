@@ -0,0 +1,206 @@
+#![cfg(feature = "grpc")]
+
+use std::{error::Error, io, net::SocketAddr, pin::Pin, thread};
+
+use tokio::{
+    runtime::{Builder, Runtime},
+    sync::mpsc::{self, UnboundedSender},
+};
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream, StreamExt};
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{
+    control::{contenders, is_paused, set_paused, status, BotStatus},
+    events::{add_sink, EventSink},
+    logging::log_message,
+    structs::Contender,
+};
+
+include!(concat!(env!("OUT_DIR"), "/trading_bot.Control.rs"));
+
+use control_server::{Control, ControlServer};
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct StatusRequest {}
+
+/// The status of the run loop.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct StatusReply {
+    #[prost(string, tag = "1")]
+    pub(crate) phase: String,
+    #[prost(uint64, tag = "2")]
+    pub(crate) iteration: u64,
+    #[prost(bool, tag = "3")]
+    pub(crate) paused: bool,
+    #[prost(bool, tag = "4")]
+    pub(crate) live: bool,
+    #[prost(string, tag = "5")]
+    pub(crate) account_id: String,
+    #[prost(uint64, tag = "6")]
+    pub(crate) working_orders: u64,
+    #[prost(int64, tag = "7")]
+    pub(crate) last_scan_time: i64,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct PauseRequest {
+    /// Whether to pause or resume trading.
+    #[prost(bool, tag = "1")]
+    pub(crate) paused: bool,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct StreamEventsRequest {}
+
+/// An event of the event stream.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct EventReply {
+    /// The event as the JSON line written to the other event sinks.
+    #[prost(string, tag = "1")]
+    pub(crate) json: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct ListContendersRequest {}
+
+/// A contender of the last scan.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct ContenderReply {
+    #[prost(string, tag = "1")]
+    pub(crate) strategy: String,
+    #[prost(string, tag = "2")]
+    pub(crate) exp_date: String,
+    #[prost(double, tag = "3")]
+    pub(crate) arb_val: f64,
+    #[prost(double, tag = "4")]
+    pub(crate) rank_value: f64,
+    #[prost(double, tag = "5")]
+    pub(crate) fill_probability: f64,
+    #[prost(string, repeated, tag = "6")]
+    pub(crate) legs: Vec<String>,
+}
+
+/// The contenders of the last scan, best first.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub(crate) struct ContendersReply {
+    #[prost(message, repeated, tag = "1")]
+    pub(crate) contenders: Vec<ContenderReply>,
+}
+
+impl From<BotStatus> for StatusReply {
+    fn from(status: BotStatus) -> Self {
+        StatusReply {
+            phase: status.phase,
+            iteration: status.iteration,
+            paused: is_paused(),
+            live: status.live,
+            account_id: status.account_id.unwrap_or_default(),
+            working_orders: status.working_orders as u64,
+            last_scan_time: status.last_scan_time,
+        }
+    }
+}
+
+impl From<&Contender> for ContenderReply {
+    fn from(contender: &Contender) -> Self {
+        ContenderReply {
+            strategy: contender.type_spread.clone(),
+            exp_date: contender.exp_date.to_string(),
+            arb_val: contender.arb_val,
+            rank_value: contender.rank_value,
+            fill_probability: contender.fill_probability,
+            legs: contender
+                .contracts
+                .iter()
+                .map(|contract| contract.describe())
+                .collect(),
+        }
+    }
+}
+
+/// Forwards the event stream to a `StreamEvents` call. It is dropped from the event stream once
+/// the client disconnects.
+struct ChannelSink(UnboundedSender<String>);
+
+impl EventSink for ChannelSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.0
+            .send(line.to_string())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "gRPC client disconnected"))
+    }
+}
+
+/// The gRPC control plane, backed by the shared status in `control`.
+struct ControlService;
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    type StreamEventsStream = Pin<Box<dyn Stream<Item = Result<EventReply, Status>> + Send>>;
+
+    async fn get_status(
+        &self,
+        _request: Request<StatusRequest>,
+    ) -> Result<Response<StatusReply>, Status> {
+        Ok(Response::new(status().into()))
+    }
+
+    async fn pause_trading(
+        &self,
+        request: Request<PauseRequest>,
+    ) -> Result<Response<StatusReply>, Status> {
+        let paused: bool = request.into_inner().paused;
+        set_paused(paused);
+        log_message(format!(
+            "Trading {} over gRPC.",
+            if paused { "paused" } else { "resumed" }
+        ));
+        Ok(Response::new(status().into()))
+    }
+
+    async fn stream_events(
+        &self,
+        _request: Request<StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        add_sink(Box::new(ChannelSink(sender)));
+        let stream = UnboundedReceiverStream::new(receiver)
+            .map(|json| EventReply { json })
+            .map(Ok);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn list_contenders(
+        &self,
+        _request: Request<ListContendersRequest>,
+    ) -> Result<Response<ContendersReply>, Status> {
+        Ok(Response::new(ContendersReply {
+            contenders: contenders().iter().map(ContenderReply::from).collect(),
+        }))
+    }
+}
+
+/// Serves the gRPC control plane on a background thread.
+///
+/// # Arguments
+///
+/// * `addr` - The `host:port` to listen on.
+///
+/// # Returns
+///
+/// A `Result` indicating the server started, or an error if the address or runtime is invalid.
+pub(crate) fn spawn_server(addr: &str) -> Result<(), Box<dyn Error>> {
+    let addr: SocketAddr = addr
+        .parse()
+        .map_err(|e| format!("Invalid GRPC_ADDR {:?}: {}", addr, e))?;
+    let runtime: Runtime = Builder::new_multi_thread().enable_all().build()?;
+    thread::spawn(move || {
+        if let Err(e) = runtime.block_on(
+            Server::builder()
+                .add_service(ControlServer::new(ControlService))
+                .serve(addr),
+        ) {
+            log_message(format!("The gRPC control plane stopped: {}", e));
+        }
+    });
+    Ok(())
+}
@@ -1,7 +1,25 @@
-use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc, Weekday};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
 use chrono_tz::America::New_York;
 use dotenv::dotenv;
-use std::{env, error::Error, io::stdin};
+use std::{collections::BTreeMap, env, error::Error, io::stdin};
+
+use crate::{
+    chain::ExpiryDate,
+    dividends::{DividendCalendar, EarlyExerciseScreen},
+    expirations::{parse_expiry_selectors, ExpirationCalendar, ExpirySelector},
+    fill_model::{FillMode, FillModel},
+    monte_carlo::MonteCarloConfig,
+    optimize::{ParamGrid, ParamSet},
+    orders::{LiquiditySizing, OrderLadder},
+    schedule::{ScanSchedule, ScheduleWindow, ThresholdSchedule, ThresholdWindow},
+    structs::{
+        AlgoConfig, BoxFinancing, ClockSkewConfig, EventsConfig, ExpiryFilter, FastMarketConfig,
+        GthConfig, HttpConfig, LegPricing, LogLevel, MqttConfig, PortfolioCacheConfig, PriceSource,
+        ProxySetting, RateConfig, RedactionConfig, RequoteConfig, SecretBackend, SecretsConfig,
+        ShadowConfig, SharedStateConfig, StrategyConfig,
+    },
+    underlying::ExchangeRouting,
+};
 
 /// Prompts the user with the provided message and returns the input as a trimmed `String`.
 ///
@@ -19,7 +37,7 @@ use std::{env, error::Error, io::stdin};
 /// let user_input = get_user_input("Enter a value:");
 /// println!("You entered: {}", user_input);
 /// ```
-fn get_user_input(prompt: &str) -> String {
+pub(crate) fn get_user_input(prompt: &str) -> String {
     let mut input: String = String::new();
     println!("{}", prompt);
     stdin().read_line(&mut input).expect("Failed to read line");
@@ -43,13 +61,142 @@ fn get_user_input(prompt: &str) -> String {
 /// let ticker = get_dotenv_variable("TICKER").unwrap();
 /// ```
 pub(crate) fn get_dotenv_variable(key: &str) -> Result<String, Box<dyn Error>> {
-    dotenv()?; // Load the .env file.
+    dotenv().ok(); // Load the .env file, if present.
     match env::var(key) {
         Ok(value) => Ok(value),
         Err(e) => Err(Box::new(e)),
     }
 }
 
+/// Separates a profile name from the setting it overrides, e.g. `PAPER__PORT`.
+const PROFILE_SEPARATOR: &str = "__";
+
+/// Takes the `--profile <name>` or `--profile=<name>` argument out of the command line.
+///
+/// # Arguments
+///
+/// * `args` - The command line arguments, left without the profile argument.
+///
+/// # Returns
+///
+/// An `Option<String>` with the profile name, or `None` if no profile was selected.
+///
+/// # Example
+///
+/// ```
+/// let mut args: Vec<String> = env::args().collect();
+/// let profile = take_profile_arg(&mut args);
+/// ```
+pub(crate) fn take_profile_arg(args: &mut Vec<String>) -> Option<String> {
+    let index: usize = args
+        .iter()
+        .position(|arg| arg == "--profile" || arg.starts_with("--profile="))?;
+    let arg: String = args.remove(index);
+    match arg.strip_prefix("--profile=") {
+        Some(profile) => Some(profile.to_string()),
+        None if index < args.len() => Some(args.remove(index)),
+        None => None,
+    }
+}
+
+/// Takes a flag, e.g. `--pretty`, out of the command line.
+///
+/// # Arguments
+///
+/// * `args` - The command line arguments, left without the flag.
+/// * `flag` - The flag.
+///
+/// # Returns
+///
+/// A `bool` indicating whether the flag was given.
+///
+/// # Example
+///
+/// ```
+/// let mut args: Vec<String> = env::args().collect();
+/// let pretty = take_flag_arg(&mut args, "--pretty");
+/// ```
+pub(crate) fn take_flag_arg(args: &mut Vec<String>, flag: &str) -> bool {
+    let before: usize = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() < before
+}
+
+/// Resolves the settings of a profile from a config file holding several profiles.
+///
+/// Settings named `<PROFILE>__<KEY>` override `<KEY>` for that profile only, so one `.env` file
+/// can hold e.g. `PAPER__PORT` and `LIVE__PORT` next to the shared base settings.
+///
+/// # Arguments
+///
+/// * `values` - The settings by name, base and profile overrides alike.
+/// * `profile` - The selected profile, case-insensitive, or `None` for the base settings.
+///
+/// # Returns
+///
+/// A `BTreeMap<String, String>` with the base settings overridden by the profile.
+///
+/// # Example
+///
+/// ```
+/// let settings = resolve_profile(&settings, Some("paper"));
+/// ```
+pub(crate) fn resolve_profile(
+    values: &BTreeMap<String, String>,
+    profile: Option<&str>,
+) -> BTreeMap<String, String> {
+    let prefix: Option<String> =
+        profile.map(|profile| format!("{}{}", profile.to_uppercase(), PROFILE_SEPARATOR));
+    let mut resolved: BTreeMap<String, String> = values
+        .iter()
+        .filter(|(key, _)| !key.contains(PROFILE_SEPARATOR))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    if let Some(prefix) = prefix {
+        for (key, value) in values {
+            if let Some(key) = key.strip_prefix(&prefix) {
+                resolved.insert(key.to_string(), value.clone());
+            }
+        }
+    }
+    resolved
+}
+
+/// Applies a profile's overrides on top of the base settings of the `.env` file, so every getter
+/// reads the profile's values.
+///
+/// # Arguments
+///
+/// * `profile` - The profile name.
+///
+/// # Returns
+///
+/// A `Result` containing the number of overridden settings, or an error if the profile has no
+/// settings.
+///
+/// # Example
+///
+/// ```
+/// apply_profile("paper").unwrap();
+/// ```
+pub(crate) fn apply_profile(profile: &str) -> Result<usize, Box<dyn Error>> {
+    dotenv().ok();
+    let values: BTreeMap<String, String> = env::vars().collect();
+    let base: BTreeMap<String, String> = resolve_profile(&values, None);
+    let overrides: Vec<(String, String)> = resolve_profile(&values, Some(profile))
+        .into_iter()
+        .filter(|(key, value)| base.get(key) != Some(value))
+        .collect();
+    let prefix: String = format!("{}{}", profile.to_uppercase(), PROFILE_SEPARATOR);
+    if !values.keys().any(|key| key.starts_with(&prefix)) {
+        return Err(format!("no settings start with {}", prefix).into());
+    }
+    for (key, value) in &overrides {
+        env::set_var(key, value);
+    }
+    Ok(overrides.len())
+}
+
 /// Gets the selected option strategy based on user input or the `.env` file.
 ///
 /// # Returns
@@ -82,124 +229,2330 @@ DEFAULT for Calendar + Butterfly + Boxspread
     }
 }
 
-/// Gets the selected fill type based on user input or the `.env` file.
+/// Gets the selected fill type based on user input or the `.env` file.
+///
+/// # Returns
+///
+/// A `String` representing the fill type:
+/// * `"1"` for single order, single fill.
+/// * `"2"` for single order, multiple fills.
+/// * `"3"` for multiple orders, single fill.
+/// * `"DEFAULT"` for multiple orders, multiple fills.
+///
+/// # Example
+///
+/// ```
+/// let fill_type = get_fill_type();
+/// println!("Selected fill type: {}", fill_type);
+/// ```
+pub(crate) fn get_fill_type() -> String {
+    match get_dotenv_variable("FILL_TYPE") {
+        Ok(val) => val,
+        Err(_) => {
+            let prompt: &str = "\
+1 for single order, single fill
+2 for single order, multiple fills
+3 for multiple orders, single fill
+DEFAULT for multiple orders, multiple fills
+";
+            get_user_input(&format! {
+            "{}\nEnter which fill type the bot should use:", prompt})
+        }
+    }
+}
+
+/// Gets the ticker based on user input or the `.env` file.
+///
+/// # Returns
+///
+/// A `String` representing the selected ticker.
+///
+/// # Example
+///
+/// ```
+/// let ticker = get_ticker();
+/// println!("Selected ticker: {}", ticker);
+/// ```
+pub(crate) fn get_ticker() -> String {
+    match get_dotenv_variable("TICKER") {
+        Ok(val) => val,
+        Err(_) => get_user_input("Enter which ticker the bot should use:"),
+    }
+}
+
+/// Gets the arbitrage value based on the `.env` file or sets a default value.
+///
+/// # Returns
+///
+/// A `f64` representing the arbitrage value, with a minimum value of 0.10.
+///
+/// # Example
+///
+/// ```
+/// let arb_value = get_arb_value();
+/// println!("Arb Value: {}", arb_value);
+/// ```
+pub(crate) fn get_arb_value() -> f64 {
+    match get_dotenv_variable("ARB_VALUE") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(val) => {
+                if val >= 0.10 {
+                    val
+                } else {
+                    println!("Not a valid Arb Value, setting to 0.10");
+                    0.10
+                }
+            }
+            Err(_) => {
+                println!("Not a valid Arb Value, setting to 0.10");
+                0.10
+            }
+        },
+        Err(_) => {
+            println!("Arb Value not found, setting to 0.10");
+            0.10
+        }
+    }
+}
+
+/// Gets the strike difference value based on the `.env` file or sets a default.
+///
+/// # Returns
+///
+/// A `f64` representing the strike difference value, with a default of 5.0 if invalid.
+///
+/// # Example
+///
+/// ```
+/// let strike_dif = get_strike_dif_value();
+/// println!("Strike Difference Value: {}", strike_dif);
+/// ```
+pub(crate) fn get_strike_dif_value() -> f64 {
+    match get_dotenv_variable("STRIKE_DIF_VALUE") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(val) => {
+                if val >= 0.0 {
+                    val
+                } else {
+                    println!("Not a valid Strike Dif Value, setting to 5.0");
+                    5.0
+                }
+            }
+            Err(_) => {
+                println!("Not a valid Strike Dif Value, setting to 5.0");
+                5.0
+            }
+        },
+        Err(_) => {
+            println!("Strike Dif Value not found, setting to 5.0");
+            5.0
+        }
+    }
+}
+
+/// Parses a comma-separated list of wing widths, e.g. `"5,10,25"`.
+///
+/// # Arguments
+///
+/// * `val` - The comma-separated list of widths.
+///
+/// # Returns
+///
+/// * `Some(Vec<f64>)` containing the widths if every entry is a positive number.
+/// * `None` if the list is empty or any entry is invalid.
+///
+/// # Example
+///
+/// ```
+/// let widths = parse_wing_widths("5,10,25").unwrap();
+/// println!("Wing widths: {:?}", widths);
+/// ```
+pub(crate) fn parse_wing_widths(val: &str) -> Option<Vec<f64>> {
+    let mut widths: Vec<f64> = Vec::new();
+    for width in val.split(',') {
+        match width.trim().parse::<f64>() {
+            Ok(width) if width > 0.0 => widths.push(width),
+            _ => return None,
+        }
+    }
+    if widths.is_empty() {
+        return None;
+    }
+    widths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    widths.dedup();
+    Some(widths)
+}
+
+/// Parses an order ladder of comma-separated `lots@concession` rungs, e.g. `"1@0,2@0.05"`.
+///
+/// # Arguments
+///
+/// * `val` - The comma-separated rungs, least aggressive first.
+///
+/// # Returns
+///
+/// * `Some(OrderLadder)` if every rung has positive lots and a non-negative concession.
+/// * `None` if the list is empty or any rung is invalid.
+///
+/// # Example
+///
+/// ```
+/// let ladder = parse_order_ladder("1@0,2@0.05").unwrap();
+/// println!("Ladder: {:?}", ladder);
+/// ```
+pub(crate) fn parse_order_ladder(val: &str) -> Option<OrderLadder> {
+    let mut rungs: Vec<(i32, f64)> = Vec::new();
+    for rung in val.split(',') {
+        let (lots, price_offset) = rung.trim().split_once('@')?;
+        match (
+            lots.trim().parse::<i32>(),
+            price_offset.trim().parse::<f64>(),
+        ) {
+            (Ok(lots), Ok(price_offset)) if lots > 0 && price_offset >= 0.0 => {
+                rungs.push((lots, price_offset))
+            }
+            _ => return None,
+        }
+    }
+    Some(OrderLadder::new(rungs))
+}
+
+/// Parses a comma-separated list of numbers, e.g. `"-0.05,0,0.05"`.
+///
+/// # Arguments
+///
+/// * `val` - The comma-separated list of numbers.
+///
+/// # Returns
+///
+/// * `Some(Vec<f64>)` containing the numbers in order if every entry is valid.
+/// * `None` if any entry is invalid.
+///
+/// # Example
+///
+/// ```
+/// let values = parse_f64_list("-0.05,0,0.05").unwrap();
+/// println!("Values: {:?}", values);
+/// ```
+pub(crate) fn parse_f64_list(val: &str) -> Option<Vec<f64>> {
+    val.split(',')
+        .map(|value| value.trim().parse::<f64>().ok())
+        .collect()
+}
+
+/// Parses a days-to-expiry window from its minimum and maximum values.
+///
+/// # Arguments
+///
+/// * `min_val` - The minimum days to expiry.
+/// * `max_val` - The maximum days to expiry.
+///
+/// # Returns
+///
+/// * `Some((i64, i64))` if both values are non-negative integers and the minimum does not exceed the maximum.
+/// * `None` otherwise.
+///
+/// # Example
+///
+/// ```
+/// let window = parse_dte_window("30", "90").unwrap();
+/// println!("DTE window: {:?}", window);
+/// ```
+pub(crate) fn parse_dte_window(min_val: &str, max_val: &str) -> Option<(i64, i64)> {
+    let min_dte: i64 = min_val.trim().parse::<i64>().ok()?;
+    let max_dte: i64 = max_val.trim().parse::<i64>().ok()?;
+    if min_dte < 0 || min_dte > max_dte {
+        return None;
+    }
+    Some((min_dte, max_dte))
+}
+
+/// Gets the configuration for a single strategy based on the `.env` file.
+///
+/// Reads `<STRATEGY>_WING_WIDTHS` as a comma-separated list of acceptable wing widths, falling
+/// back to the strike difference value when it is missing or invalid. `<STRATEGY>_MIN_DTE` and
+/// `<STRATEGY>_MAX_DTE` restrict the strategy to expirations in that days-to-expiry window; when
+/// they are not set, the strategy scans the `EXPIRY_SELECTOR` expirations.
+/// `<STRATEGY>_MAX_SPOT_DISTANCE_PERCENT` keeps spreads whose strikes are centered within that
+/// percentage of the spot, and `<STRATEGY>_STRADDLE_SPOT` keeps spreads whose strikes straddle it.
+/// `BUTTERFLY_MAX_NET_DELTA` rejects butterflies whose absolute net delta exceeds it.
+///
+/// # Arguments
+///
+/// * `strategy` - The strategy prefix, e.g. `"BUTTERFLY"` or `"BOXSPREAD"`.
+/// * `strike_dif_value` - The default wing width.
+///
+/// # Returns
+///
+/// A `StrategyConfig` for the strategy.
+///
+/// # Example
+///
+/// ```
+/// let butterfly_config = get_strategy_config("BUTTERFLY", 5.0);
+/// println!("Butterfly wing widths: {:?}", butterfly_config.wing_widths);
+/// ```
+pub(crate) fn get_strategy_config(strategy: &str, strike_dif_value: f64) -> StrategyConfig {
+    let key: String = format!("{}_WING_WIDTHS", strategy);
+    let wing_widths: Vec<f64> = match get_dotenv_variable(&key) {
+        Ok(val) => match parse_wing_widths(&val) {
+            Some(widths) => widths,
+            None => {
+                println!(
+                    "Not a valid {} list, setting to {}",
+                    key,
+                    format_strike(strike_dif_value)
+                );
+                vec![strike_dif_value]
+            }
+        },
+        Err(_) => vec![strike_dif_value],
+    };
+
+    let dte_window: Option<(i64, i64)> = match (
+        get_dotenv_variable(&format!("{}_MIN_DTE", strategy)),
+        get_dotenv_variable(&format!("{}_MAX_DTE", strategy)),
+    ) {
+        (Ok(min_val), Ok(max_val)) => match parse_dte_window(&min_val, &max_val) {
+            Some(window) => Some(window),
+            None => {
+                println!(
+                    "Not a valid {} DTE window, using the EXPIRY_SELECTOR expirations",
+                    strategy
+                );
+                None
+            }
+        },
+        (Err(_), Err(_)) => None,
+        _ => {
+            println!(
+                "{} DTE window needs both MIN_DTE and MAX_DTE, using the EXPIRY_SELECTOR expirations",
+                strategy
+            );
+            None
+        }
+    };
+
+    let max_spot_distance_percent: Option<f64> =
+        get_dotenv_variable(&format!("{}_MAX_SPOT_DISTANCE_PERCENT", strategy))
+            .ok()
+            .and_then(|val| val.parse::<f64>().ok())
+            .filter(|percent| *percent >= 0.0);
+    let straddle_spot: bool = match get_dotenv_variable(&format!("{}_STRADDLE_SPOT", strategy)) {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    };
+
+    let max_net_delta: Option<f64> = get_dotenv_variable(&format!("{}_MAX_NET_DELTA", strategy))
+        .ok()
+        .and_then(|val| val.parse::<f64>().ok())
+        .filter(|delta| *delta >= 0.0);
+
+    StrategyConfig {
+        wing_widths,
+        dte_window,
+        max_spot_distance_percent,
+        straddle_spot,
+        max_net_delta,
+    }
+}
+
+/// Parses a last price from an IBKR snapshot, which prefixes it with `C` for the prior close or
+/// `H` when trading is halted.
+///
+/// # Arguments
+///
+/// * `value` - The value of snapshot field 31, e.g. `"5,512.25"` or `"C5498.50"`.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the price, or `None` if it isn't a positive number.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(parse_last_price("C5498.50"), Some(5498.5));
+/// ```
+pub(crate) fn parse_last_price(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .trim_start_matches(['C', 'H'])
+        .replace(",", "")
+        .parse::<f64>()
+        .ok()
+        .filter(|price| *price > 0.0)
+}
+
+/// Gets the same-day (0DTE) expiration filter based on the `.env` file.
+///
+/// `EXCLUDE_0DTE` drops today's expiration from the chain entirely. Otherwise
+/// `MIN_MINUTES_TO_CLOSE_0DTE` stops scanning today's expiration once fewer minutes than that
+/// remain before the close.
+///
+/// # Returns
+///
+/// An `ExpiryFilter`, defaulting to keeping same-day expirations with no minimum.
+///
+/// # Example
+///
+/// ```
+/// let expiry_filter = get_expiry_filter();
+/// println!("Excluding 0DTE: {}", expiry_filter.exclude_same_day);
+/// ```
+pub(crate) fn get_expiry_filter() -> ExpiryFilter {
+    let exclude_same_day: bool = match get_dotenv_variable("EXCLUDE_0DTE") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    };
+
+    let min_minutes_to_close: i64 = match get_dotenv_variable("MIN_MINUTES_TO_CLOSE_0DTE") {
+        Ok(val) => match val.parse::<i64>() {
+            Ok(parsed_val) if parsed_val >= 0 => parsed_val,
+            _ => {
+                println!("Not a valid number of minutes, setting to 0");
+                0
+            }
+        },
+        Err(_) => 0,
+    };
+
+    ExpiryFilter {
+        exclude_same_day,
+        min_minutes_to_close,
+    }
+}
+
+/// Gets how long submitted contenders are remembered to avoid resubmitting them, based on the
+/// `.env` file.
+///
+/// # Returns
+///
+/// A `u64` representing the number of seconds (default is 300, 0 disables deduplication).
+///
+/// # Example
+///
+/// ```
+/// let dedup_seconds = get_dedup_seconds();
+/// println!("Remembering submissions for {} seconds.", dedup_seconds);
+/// ```
+pub(crate) fn get_dedup_seconds() -> u64 {
+    match get_dotenv_variable("DEDUP_SECONDS") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(parsed_val) => parsed_val,
+            Err(_) => {
+                println!("Not a valid number of seconds, setting to 300");
+                300
+            }
+        },
+        Err(_) => 300,
+    }
+}
+
+/// Gets how long contenders sharing a leg with a filled combo are skipped, based on the `.env`
+/// file.
+///
+/// # Returns
+///
+/// A `u64` representing the number of seconds (default is 0, which disables the cooldown).
+///
+/// # Example
+///
+/// ```
+/// let fill_cooldown_seconds = get_fill_cooldown_seconds();
+/// println!("Skipping filled legs for {} seconds.", fill_cooldown_seconds);
+/// ```
+pub(crate) fn get_fill_cooldown_seconds() -> u64 {
+    match get_dotenv_variable("FILL_COOLDOWN_SECONDS") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(parsed_val) => parsed_val,
+            Err(_) => {
+                println!("Not a valid number of seconds, setting to 0");
+                0
+            }
+        },
+        Err(_) => 0,
+    }
+}
+
+/// Gets how much is logged based on the `.env` file.
+///
+/// # Returns
+///
+/// The `LogLevel` of `LOG_LEVEL`, one of `error`, `warn`, `info`, `debug` or `trace` (default is
+/// `info`).
+///
+/// # Example
+///
+/// ```
+/// let log_level = get_log_level();
+/// println!("Logging at {:?}", log_level);
+/// ```
+pub(crate) fn get_log_level() -> LogLevel {
+    match get_dotenv_variable("LOG_LEVEL") {
+        Ok(val) => match LogLevel::from_str(&val) {
+            Some(parsed_val) => parsed_val,
+            None => {
+                println!("Not a valid log level, setting to info");
+                LogLevel::Info
+            }
+        },
+        Err(_) => LogLevel::Info,
+    }
+}
+
+/// Gets what is redacted from logs, the audit file and the journal based on the `.env` file.
+///
+/// `LOG_REDACTION` is a comma-separated list of `accounts`, `order_ids` and `tokens`, or `all`.
+/// `AUDIT_REDACT_ACCOUNT` set to yes also redacts account IDs, as it did before for the audit file
+/// alone.
+///
+/// # Returns
+///
+/// A `RedactionConfig`, redacting nothing by default.
+///
+/// # Example
+///
+/// ```
+/// let redaction_config = get_redaction_config();
+/// println!("Redacting account IDs: {}", redaction_config.accounts);
+/// ```
+pub(crate) fn get_redaction_config() -> RedactionConfig {
+    let mut config: RedactionConfig = RedactionConfig::default();
+    if let Ok(val) = get_dotenv_variable("LOG_REDACTION") {
+        for item in val
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            match item.to_lowercase().as_str() {
+                "accounts" => config.accounts = true,
+                "order_ids" => config.order_ids = true,
+                "tokens" => config.tokens = true,
+                "all" => {
+                    config = RedactionConfig {
+                        accounts: true,
+                        order_ids: true,
+                        tokens: true,
+                    }
+                }
+                _ => println!("Not a valid redaction {}, ignoring it", item),
+            }
+        }
+    }
+    if let Ok(val) = get_dotenv_variable("AUDIT_REDACT_ACCOUNT") {
+        config.accounts |= val.to_lowercase() == "yes" || val.to_lowercase() == "y";
+    }
+    config
+}
+
+/// Gets the exchange rules and holidays the listed expirations are checked against, based on the
+/// `.env` file.
+///
+/// `EXPIRATION_WEEKDAYS` is a comma-separated list of the weekdays weeklies expire on, e.g.
+/// `mon,tue,wed,thu,fri` for SPX dailies, or `none`. `MARKET_CLOSURES` is a comma-separated list
+/// of unscheduled closures in `YYYY-MM-DD` format, on top of the regular holidays.
+///
+/// # Returns
+///
+/// An `ExpirationCalendar`, defaulting to Monday, Wednesday and Friday weeklies, third-Friday
+/// monthlies and end-of-month expirations.
+///
+/// # Example
+///
+/// ```
+/// let expiration_calendar = get_expiration_calendar();
+/// println!("Weeklies expire on {:?}", expiration_calendar.weekdays);
+/// ```
+pub(crate) fn get_expiration_calendar() -> ExpirationCalendar {
+    let mut calendar: ExpirationCalendar = ExpirationCalendar::default();
+    if let Ok(val) = get_dotenv_variable("EXPIRATION_WEEKDAYS") {
+        let mut weekdays: Vec<Weekday> = Vec::new();
+        for item in val
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty() && !item.eq_ignore_ascii_case("none"))
+        {
+            match item.parse::<Weekday>() {
+                Ok(weekday) if !matches!(weekday, Weekday::Sat | Weekday::Sun) => {
+                    weekdays.push(weekday)
+                }
+                _ => println!("Not a valid expiration weekday {}, ignoring it", item),
+            }
+        }
+        calendar.weekdays = weekdays;
+    }
+    if let Ok(val) = get_dotenv_variable("MARKET_CLOSURES") {
+        for item in val
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            match NaiveDate::parse_from_str(item, "%Y-%m-%d") {
+                Ok(date) => {
+                    calendar.closures.insert(date);
+                }
+                Err(_) => println!("Not a valid market closure date {}, ignoring it", item),
+            }
+        }
+    }
+    calendar
+}
+
+/// Gets the HTTP client configuration of the gateway connections based on the `.env` file.
+///
+/// `PROXY_URL` sends every request through a proxy, or `none` connects directly; when unset the
+/// usual `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` variables apply. `CA_BUNDLE` is a PEM file of
+/// extra root certificates. `ACCEPT_INVALID_CERTS` accepts the gateway's self-signed certificate
+/// without verification (default is yes, or no when `CA_BUNDLE` is set).
+///
+/// # Returns
+///
+/// An `HttpConfig` with the proxy, CA bundle and certificate verification settings.
+///
+/// # Example
+///
+/// ```
+/// let http_config = get_http_config();
+/// println!("Verifying certificates: {}", !http_config.accept_invalid_certs);
+/// ```
+pub(crate) fn get_http_config() -> HttpConfig {
+    let proxy: ProxySetting = match get_dotenv_variable("PROXY_URL") {
+        Ok(val) if val.to_lowercase() == "none" => ProxySetting::Disabled,
+        Ok(val) => ProxySetting::Url(val),
+        Err(_) => ProxySetting::Environment,
+    };
+    let ca_bundle: Option<String> = get_dotenv_variable("CA_BUNDLE").ok();
+    let accept_invalid_certs: bool = match get_dotenv_variable("ACCEPT_INVALID_CERTS") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => {
+            let accept_invalid_certs: bool = ca_bundle.is_none();
+            println!(
+                "ACCEPT_INVALID_CERTS is not set, using {}",
+                if accept_invalid_certs { "yes" } else { "no" }
+            );
+            accept_invalid_certs
+        }
+    };
+
+    HttpConfig {
+        proxy,
+        ca_bundle,
+        accept_invalid_certs,
+    }
+}
+
+/// Gets the risk-free rate configuration based on the `.env` file.
+///
+/// With `FRED_API_KEY` set, the Treasury yield curve is fetched from FRED once a day.
+/// `RISK_FREE_RATE` is the annualized rate in percent used until then, or instead when there is no
+/// key or the fetch fails. `FRED_URL` points the fetch at a mirror or proxy of the FRED API.
+///
+/// # Returns
+///
+/// A `RateConfig`, defaulting to no API key and a zero rate.
+///
+/// # Example
+///
+/// ```
+/// let rate_config = get_rate_config();
+/// println!("Fallback rate: {}", rate_config.fallback_rate);
+/// ```
+pub(crate) fn get_rate_config() -> RateConfig {
+    let fallback_rate: f64 = match get_dotenv_variable("RISK_FREE_RATE") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) => parsed_val / 100.0,
+            Err(_) => {
+                println!("Not a valid rate, setting to 0");
+                0.0
+            }
+        },
+        Err(_) => 0.0,
+    };
+
+    RateConfig {
+        fred_api_key: get_dotenv_variable("FRED_API_KEY").ok(),
+        fred_url: get_dotenv_variable("FRED_URL")
+            .unwrap_or("https://api.stlouisfed.org/fred".to_string()),
+        fallback_rate,
+    }
+}
+
+/// Gets the portfolio value cache configuration based on the `.env` file.
+///
+/// `PORTFOLIO_TTL_SECONDS` sets how long the cached value is used before refreshing it, and
+/// `PORTFOLIO_CHANGE_PERCENT` sets the change that is logged as a possible fill or withdrawal.
+///
+/// # Returns
+///
+/// A `PortfolioCacheConfig`, defaulting to a 300 second TTL and a 5% change threshold.
+///
+/// # Example
+///
+/// ```
+/// let portfolio_cache_config = get_portfolio_cache_config();
+/// println!("Caching portfolio value for {} seconds.", portfolio_cache_config.ttl_seconds);
+/// ```
+pub(crate) fn get_portfolio_cache_config() -> PortfolioCacheConfig {
+    let ttl_seconds: u64 = match get_dotenv_variable("PORTFOLIO_TTL_SECONDS") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(parsed_val) => parsed_val,
+            Err(_) => {
+                println!("Not a valid number of seconds, setting to 300");
+                300
+            }
+        },
+        Err(_) => 300,
+    };
+
+    let change_percent: f64 = match get_dotenv_variable("PORTFOLIO_CHANGE_PERCENT") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val >= 0.0 => parsed_val,
+            _ => {
+                println!("Not a valid percent, setting to 5.0");
+                5.0
+            }
+        },
+        Err(_) => 5.0,
+    };
+
+    PortfolioCacheConfig {
+        ttl_seconds,
+        change_percent,
+    }
+}
+
+/// Gets how far the local clock may drift from the gateway's, based on the `.env` file.
+///
+/// `CLOCK_SKEW_WARN_SECONDS` sets the skew that is logged as a warning at startup, and
+/// `CLOCK_SKEW_MAX_SECONDS` the skew the bot refuses to run with.
+///
+/// # Returns
+///
+/// A `ClockSkewConfig`, defaulting to warning at 2 seconds and refusing at 30 seconds.
+///
+/// # Example
+///
+/// ```
+/// let clock_skew_config = get_clock_skew_config();
+/// println!("Refusing to run with a clock skew over {} seconds.", clock_skew_config.max_seconds);
+/// ```
+pub(crate) fn get_clock_skew_config() -> ClockSkewConfig {
+    let warn_seconds: u64 = match get_dotenv_variable("CLOCK_SKEW_WARN_SECONDS") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(parsed_val) => parsed_val,
+            Err(_) => {
+                println!("Not a valid number of seconds, setting to 2");
+                2
+            }
+        },
+        Err(_) => 2,
+    };
+
+    let max_seconds: u64 = match get_dotenv_variable("CLOCK_SKEW_MAX_SECONDS") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(parsed_val) if parsed_val >= warn_seconds => parsed_val,
+            _ => {
+                println!("Not a valid number of seconds, setting to 30");
+                30.max(warn_seconds)
+            }
+        },
+        Err(_) => 30.max(warn_seconds),
+    };
+
+    ClockSkewConfig {
+        warn_seconds,
+        max_seconds,
+    }
+}
+
+/// Gets how order quantity is matched to the liquidity displayed on a contender's legs, based on
+/// the `.env` file.
+///
+/// # Returns
+///
+/// A `LiquiditySizing` from `LIQUIDITY_SIZING` (`off`, `cap` or `split`), defaulting to `cap`.
+///
+/// # Example
+///
+/// ```
+/// let liquidity_sizing = get_liquidity_sizing();
+/// println!("Liquidity sizing: {:?}", liquidity_sizing);
+/// ```
+pub(crate) fn get_liquidity_sizing() -> LiquiditySizing {
+    match get_dotenv_variable("LIQUIDITY_SIZING") {
+        Ok(val) => match LiquiditySizing::from_str(&val) {
+            Some(liquidity_sizing) => liquidity_sizing,
+            None => {
+                println!("Not a valid liquidity sizing, setting to cap");
+                LiquiditySizing::Cap
+            }
+        },
+        Err(_) => LiquiditySizing::Cap,
+    }
+}
+
+/// Gets the IBKR algo orders are routed with, based on the `.env` file.
+///
+/// `USE_ADAPTIVE` routes orders through the adaptive algo with the `ADAPTIVE_PRIORITY` priority
+/// (`Urgent`, `Normal` or `Patient`, default `Normal`). `ALGO_PARAMS` adds further algo parameters
+/// as semicolon-separated `key=value` pairs.
+///
+/// # Returns
+///
+/// An `AlgoConfig`, defaulting to orders without an algo.
+///
+/// # Example
+///
+/// ```
+/// let algo = get_algo_config();
+/// println!("Algo: {}", algo.label());
+/// ```
+pub(crate) fn get_algo_config() -> AlgoConfig {
+    let use_adaptive: bool = match get_dotenv_variable("USE_ADAPTIVE") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    };
+
+    let adaptive_priority: String = match get_dotenv_variable("ADAPTIVE_PRIORITY") {
+        Ok(val) => match val.to_lowercase().as_str() {
+            "urgent" => "Urgent".to_string(),
+            "normal" => "Normal".to_string(),
+            "patient" => "Patient".to_string(),
+            _ => {
+                println!("Not a valid adaptive priority, setting to Normal");
+                "Normal".to_string()
+            }
+        },
+        Err(_) => "Normal".to_string(),
+    };
+
+    let params: BTreeMap<String, String> = match get_dotenv_variable("ALGO_PARAMS") {
+        Ok(val) => val
+            .split(';')
+            .filter_map(|param| param.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .filter(|(key, _)| !key.is_empty())
+            .collect(),
+        Err(_) => BTreeMap::new(),
+    };
+
+    AlgoConfig {
+        use_adaptive,
+        adaptive_priority,
+        params,
+    }
+}
+
+/// Gets the listing exchange orders are routed to per underlying, based on the `.env` file.
+///
+/// `LISTING_EXCHANGE` sets the default exchange (default `SMART`) and `EXCHANGE_ROUTES` overrides
+/// it per ticker as comma-separated `TICKER:EXCHANGE` pairs, e.g. `SPX:CBOE`.
+///
+/// # Returns
+///
+/// An `ExchangeRouting`, defaulting to SMART routing for every underlying.
+///
+/// # Example
+///
+/// ```
+/// let routing = get_exchange_routing();
+/// println!("SPX routes to {}", routing.exchange_for("SPX"));
+/// ```
+pub(crate) fn get_exchange_routing() -> ExchangeRouting {
+    let default: String = match get_dotenv_variable("LISTING_EXCHANGE") {
+        Ok(val) if !val.trim().is_empty() => val.trim().to_string(),
+        _ => "SMART".to_string(),
+    };
+
+    let routes: Vec<(String, String)> = match get_dotenv_variable("EXCHANGE_ROUTES") {
+        Ok(val) => val
+            .split(',')
+            .filter(|route| !route.trim().is_empty())
+            .filter_map(|route| match route.split_once(':') {
+                Some((ticker, exchange))
+                    if !ticker.trim().is_empty() && !exchange.trim().is_empty() =>
+                {
+                    Some((ticker.trim().to_string(), exchange.trim().to_string()))
+                }
+                _ => {
+                    println!("Not a valid exchange route: {}, skipping", route.trim());
+                    None
+                }
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    ExchangeRouting::new(default, routes)
+}
+
+/// Gets the price ladder orders are sliced across, based on the `.env` file.
+///
+/// # Returns
+///
+/// An `OrderLadder` from `ORDER_LADDER`, e.g. `1@0,2@0.05`, or an empty ladder that submits each
+/// order at a single price.
+///
+/// # Example
+///
+/// ```
+/// let ladder = get_order_ladder();
+/// println!("Laddering orders: {}", !ladder.is_empty());
+/// ```
+pub(crate) fn get_order_ladder() -> OrderLadder {
+    match get_dotenv_variable("ORDER_LADDER") {
+        Ok(val) => match parse_order_ladder(&val) {
+            Some(ladder) => ladder,
+            None => {
+                println!("Not a valid order ladder, submitting at a single price");
+                OrderLadder::default()
+            }
+        },
+        Err(_) => OrderLadder::default(),
+    }
+}
+
+/// Gets the net liquidation below which an SPX bot switches to XSP, based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the value of `XSP_FALLBACK_NET_LIQ`, or `None` to keep trading SPX.
+///
+/// # Example
+///
+/// ```
+/// if let Some(xsp_fallback_net_liq) = get_xsp_fallback_net_liq() {
+///     println!("Switching to XSP below {:.2}.", xsp_fallback_net_liq);
+/// }
+/// ```
+pub(crate) fn get_xsp_fallback_net_liq() -> Option<f64> {
+    match get_dotenv_variable("XSP_FALLBACK_NET_LIQ") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val > 0.0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid net liquidation, disabling the XSP fallback");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Gets the maximum daily loss before the bot stops trading, based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the loss limit as a positive amount, or `None` to disable the kill switch.
+///
+/// # Example
+///
+/// ```
+/// if let Some(max_daily_loss) = get_max_daily_loss() {
+///     println!("Stopping after a daily loss of {:.2}.", max_daily_loss);
+/// }
+/// ```
+pub(crate) fn get_max_daily_loss() -> Option<f64> {
+    match get_dotenv_variable("MAX_DAILY_LOSS") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val > 0.0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid daily loss limit, disabling the kill switch");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Gets the largest worst-case loss a single trade may carry, in dollars, based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the value of `MAX_LOSS_PER_TRADE`, or `None` to not cap the loss.
+///
+/// # Example
+///
+/// ```
+/// if let Some(max_loss) = get_max_loss_per_trade() {
+///     println!("Rejecting trades that can lose more than {:.2}.", max_loss);
+/// }
+/// ```
+pub(crate) fn get_max_loss_per_trade() -> Option<f64> {
+    match get_dotenv_variable("MAX_LOSS_PER_TRADE") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val >= 0.0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid amount, disabling the max loss cap");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Gets the maintenance margin ceiling, in percent of net liquidation, based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the ceiling between 0 and 100, or `None` to disable the margin gate.
+///
+/// # Example
+///
+/// ```
+/// if let Some(max_margin_usage) = get_max_margin_usage() {
+///     println!("Keeping margin usage under {:.1}%.", max_margin_usage);
+/// }
+/// ```
+pub(crate) fn get_max_margin_usage() -> Option<f64> {
+    match get_dotenv_variable("MAX_MARGIN_USAGE_PERCENT") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val > 0.0 && parsed_val <= 100.0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid percent in the range 0-100, disabling the margin gate");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Gets the fill model used by the backtester and the live fill probability estimate, based on the
+/// `.env` file.
+///
+/// `FILL_MODEL` selects `cross` (fill only if the combo mid crosses the limit by
+/// `FILL_CROSS_THRESHOLD`) or `probabilistic`. `FILL_PROBABILITY_MIDPOINT` and
+/// `FILL_PROBABILITY_SCALE` shape the fill probability as a function of the quoted edge.
+///
+/// # Returns
+///
+/// A `FillModel`, defaulting to `cross` with a threshold of 0, a midpoint of 0.5 and a scale of 0.25.
+///
+/// # Example
+///
+/// ```
+/// let fill_model = get_fill_model();
+/// println!("Fill probability at 0.25 edge: {:.2}", fill_model.fill_probability(0.25));
+/// ```
+pub(crate) fn get_fill_model() -> FillModel {
+    let mode: FillMode = match get_dotenv_variable("FILL_MODEL") {
+        Ok(val) => match FillMode::from_str(&val) {
+            Some(mode) => mode,
+            None => {
+                println!("Not a valid fill model, setting to cross");
+                FillMode::Cross
+            }
+        },
+        Err(_) => FillMode::Cross,
+    };
+
+    let cross_threshold: f64 = match get_dotenv_variable("FILL_CROSS_THRESHOLD") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val >= 0.0 => parsed_val,
+            _ => {
+                println!("Not a valid cross threshold, setting to 0.0");
+                0.0
+            }
+        },
+        Err(_) => 0.0,
+    };
+
+    let probability_midpoint: f64 = match get_dotenv_variable("FILL_PROBABILITY_MIDPOINT") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) => parsed_val,
+            Err(_) => {
+                println!("Not a valid fill probability midpoint, setting to 0.5");
+                0.5
+            }
+        },
+        Err(_) => 0.5,
+    };
+
+    let probability_scale: f64 = match get_dotenv_variable("FILL_PROBABILITY_SCALE") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val > 0.0 => parsed_val,
+            _ => {
+                println!("Not a valid fill probability scale, setting to 0.25");
+                0.25
+            }
+        },
+        Err(_) => 0.25,
+    };
+
+    FillModel {
+        mode,
+        cross_threshold,
+        probability_midpoint,
+        probability_scale,
+    }
+}
+
+/// Determines whether each scan's quotes are recorded to `quotes.jsonl` for backtesting, based on
+/// the `.env` file.
+///
+/// # Returns
+///
+/// A `bool` that is `true` when `RECORD_QUOTES` is set to yes (default is `false`).
+///
+/// # Example
+///
+/// ```
+/// let record_quotes = get_record_quotes();
+/// println!("Recording quotes: {}", record_quotes);
+/// ```
+pub(crate) fn get_record_quotes() -> bool {
+    match get_dotenv_variable("RECORD_QUOTES") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    }
+}
+
+/// Determines whether contenders are ranked by edge per dollar of whatif margin rather than by
+/// rank value when not all of them fit within the margin ceiling, based on the `.env` file.
+///
+/// # Returns
+///
+/// A `bool` that is `true` when `MARGIN_EFFICIENT_RANKING` is set to yes (default is `false`).
+///
+/// # Example
+///
+/// ```
+/// let margin_efficient = get_margin_efficient_ranking();
+/// println!("Ranking by margin efficiency: {}", margin_efficient);
+/// ```
+pub(crate) fn get_margin_efficient_ranking() -> bool {
+    match get_dotenv_variable("MARGIN_EFFICIENT_RANKING") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    }
+}
+
+/// Determines whether the startup check of the account's trading permissions and market data
+/// subscriptions is skipped, based on the `.env` file.
+///
+/// # Returns
+///
+/// A `bool` that is `true` when `SKIP_PREFLIGHT` is set to yes (default is `false`).
+///
+/// # Example
+///
+/// ```
+/// let skip_preflight = get_skip_preflight();
+/// println!("Skipping the preflight check: {}", skip_preflight);
+/// ```
+pub(crate) fn get_skip_preflight() -> bool {
+    match get_dotenv_variable("SKIP_PREFLIGHT") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    }
+}
+
+/// Determines whether orders are priced from the NBBO of the combo itself rather than from its
+/// leg quotes alone, based on the `.env` file.
+///
+/// # Returns
+///
+/// A `bool` that is `true` when `COMBO_QUOTES` is set to yes (default is `false`).
+///
+/// # Example
+///
+/// ```
+/// let combo_quotes = get_combo_quotes();
+/// println!("Pricing from combo quotes: {}", combo_quotes);
+/// ```
+pub(crate) fn get_combo_quotes() -> bool {
+    match get_dotenv_variable("COMBO_QUOTES") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    }
+}
+
+/// Determines whether every scan's full contender list, including contenders below the arb
+/// threshold, is exported to a dated CSV file, based on the `.env` file.
+///
+/// # Returns
+///
+/// A `bool` that is `true` when `EXPORT_CONTENDERS` is set to yes (default is `false`).
+///
+/// # Example
+///
+/// ```
+/// let export_contenders = get_export_contenders();
+/// println!("Exporting contenders: {}", export_contenders);
+/// ```
+pub(crate) fn get_export_contenders() -> bool {
+    match get_dotenv_variable("EXPORT_CONTENDERS") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    }
+}
+
+/// Gets where the JSONL event stream is written, based on the `.env` file.
+///
+/// `EVENTS_PATH` names a file the events are appended to and `EVENTS_ADDR` a `host:port` TCP
+/// endpoint they are streamed to. `EVENTS_KAFKA_BROKERS` with `EVENTS_KAFKA_TOPIC` and
+/// `EVENTS_NATS_URL` with `EVENTS_NATS_SUBJECT` mirror the events to Kafka and NATS when the bot is
+/// built with the matching feature. `MQTT_BROKER` publishes contender and fill events to
+/// `MQTT_CONTENDER_TOPIC` and `MQTT_FILL_TOPIC` when built with the `mqtt` feature. Any combination can be set.
+///
+/// # Returns
+///
+/// An `EventsConfig` with the configured destinations.
+///
+/// # Example
+///
+/// ```
+/// let events_config = get_events_config();
+/// println!("Writing events to {:?}", events_config.path);
+/// ```
+pub(crate) fn get_events_config() -> EventsConfig {
+    EventsConfig {
+        path: get_dotenv_variable("EVENTS_PATH").ok(),
+        addr: get_dotenv_variable("EVENTS_ADDR").ok(),
+        kafka: get_dotenv_variable("EVENTS_KAFKA_BROKERS")
+            .ok()
+            .map(|brokers| {
+                (
+                    brokers,
+                    get_dotenv_variable("EVENTS_KAFKA_TOPIC")
+                        .unwrap_or_else(|_| "trading_bot_events".to_string()),
+                )
+            }),
+        nats: get_dotenv_variable("EVENTS_NATS_URL").ok().map(|url| {
+            (
+                url,
+                get_dotenv_variable("EVENTS_NATS_SUBJECT")
+                    .unwrap_or_else(|_| "trading_bot.events".to_string()),
+            )
+        }),
+        mqtt: get_dotenv_variable("MQTT_BROKER")
+            .ok()
+            .map(|broker| MqttConfig {
+                broker,
+                contender_topic: get_dotenv_variable("MQTT_CONTENDER_TOPIC")
+                    .unwrap_or_else(|_| "trading_bot/contenders".to_string()),
+                fill_topic: get_dotenv_variable("MQTT_FILL_TOPIC")
+                    .unwrap_or_else(|_| "trading_bot/fills".to_string()),
+            }),
+    }
+}
+
+/// Gets the secret backend configuration based on the `.env` file.
+///
+/// `SECRETS_BACKEND` selects `env` (default), `keyring` (requires the `keyring` feature, entries
+/// under `SECRETS_KEYRING_SERVICE`), `vault` (`VAULT_ADDR`, `VAULT_TOKEN` and `VAULT_SECRET_PATH`)
+/// or `aws` (`AWS_SECRET_ID`). `SECRET_KEYS` lists the settings read from the backend.
+///
+/// # Returns
+///
+/// A `SecretsConfig`, falling back to the `env` backend when the selected one is incomplete.
+///
+/// # Example
+///
+/// ```
+/// let secrets_config = get_secrets_config();
+/// println!("Secret backend: {:?}", secrets_config.backend);
+/// ```
+pub(crate) fn get_secrets_config() -> SecretsConfig {
+    let keys: Vec<String> = match get_dotenv_variable("SECRET_KEYS") {
+        Ok(val) => val
+            .split(',')
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect(),
+        Err(_) => [
+            "SHADOW_ACCOUNT_ID",
+            "FRED_API_KEY",
+            "SENTRY_DSN",
+            "REDIS_URL",
+        ]
+        .iter()
+        .map(|key| key.to_string())
+        .collect(),
+    };
+
+    let backend: SecretBackend = match get_dotenv_variable("SECRETS_BACKEND")
+        .map(|val| val.to_lowercase())
+        .as_deref()
+    {
+        Ok("keyring") => SecretBackend::Keyring {
+            service: get_dotenv_variable("SECRETS_KEYRING_SERVICE")
+                .unwrap_or_else(|_| "trading_bot_rust".to_string()),
+        },
+        Ok("vault") => match (
+            get_dotenv_variable("VAULT_ADDR"),
+            get_dotenv_variable("VAULT_TOKEN"),
+            get_dotenv_variable("VAULT_SECRET_PATH"),
+        ) {
+            (Ok(addr), Ok(token), Ok(path)) => SecretBackend::Vault { addr, token, path },
+            _ => {
+                println!("VAULT_ADDR, VAULT_TOKEN and VAULT_SECRET_PATH are required, using .env");
+                SecretBackend::Env
+            }
+        },
+        Ok("aws") => match get_dotenv_variable("AWS_SECRET_ID") {
+            Ok(secret_id) => SecretBackend::Aws { secret_id },
+            Err(_) => {
+                println!("AWS_SECRET_ID is required, using .env");
+                SecretBackend::Env
+            }
+        },
+        Ok("env") | Err(_) => SecretBackend::Env,
+        Ok(_) => {
+            println!("Not a valid secret backend, using .env");
+            SecretBackend::Env
+        }
+    };
+
+    SecretsConfig { backend, keys }
+}
+
+/// Gets the configuration of the risk state shared between instances, based on the `.env` file.
+///
+/// `REDIS_URL` points every instance at the same Redis store (requires the `redis` feature),
+/// `REDIS_PREFIX` namespaces its keys and `MAX_GLOBAL_EXPOSURE` caps the combos working across all
+/// instances.
+///
+/// # Returns
+///
+/// A `SharedStateConfig`, defaulting to no Redis, the `trading_bot` prefix and no exposure cap.
+///
+/// # Example
+///
+/// ```
+/// let shared_state_config = get_shared_state_config();
+/// println!("Global exposure cap: {:?}", shared_state_config.max_global_exposure);
+/// ```
+pub(crate) fn get_shared_state_config() -> SharedStateConfig {
+    let max_global_exposure: Option<f64> = match get_dotenv_variable("MAX_GLOBAL_EXPOSURE") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val > 0.0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid exposure, disabling the global exposure cap");
+                None
+            }
+        },
+        Err(_) => None,
+    };
+
+    SharedStateConfig {
+        redis_url: get_dotenv_variable("REDIS_URL").ok(),
+        prefix: get_dotenv_variable("REDIS_PREFIX").unwrap_or_else(|_| "trading_bot".to_string()),
+        max_global_exposure,
+    }
+}
+
+/// Determines whether a second instance on an already locked account keeps scanning without
+/// trading instead of refusing to start, based on the `.env` file.
+///
+/// # Returns
+///
+/// A `bool` that is `true` when `LOCKED_ACCOUNT_MODE` is set to `scan` (default is `refuse`).
+///
+/// # Example
+///
+/// ```
+/// let scan_when_locked = get_scan_when_locked();
+/// println!("Scan when locked: {}", scan_when_locked);
+/// ```
+pub(crate) fn get_scan_when_locked() -> bool {
+    match get_dotenv_variable("LOCKED_ACCOUNT_MODE") {
+        Ok(val) => val.to_lowercase() == "scan",
+        Err(_) => false,
+    }
+}
+
+/// Parses a scan schedule of `;`-separated `HH:MM-HH:MM=<seconds|off>` windows, in New York
+/// time, with an optional `*=<seconds|off>` entry for the times outside every window.
+///
+/// # Arguments
+///
+/// * `spec` - The schedule, e.g. `"09:30-10:30=10;15:00-16:00=10;*=30"`.
+///
+/// # Returns
+///
+/// * `Some((windows, default))` with the windows in order and the interval outside them, if
+///   given.
+/// * `None` if any entry is invalid.
+///
+/// # Example
+///
+/// ```
+/// let (windows, default) = parse_scan_schedule("09:30-10:30=10;*=off").unwrap();
+/// println!("{} windows, default {:?}", windows.len(), default);
+/// ```
+pub(crate) fn parse_scan_schedule(
+    spec: &str,
+) -> Option<(Vec<ScheduleWindow>, Option<Option<u64>>)> {
+    let mut windows: Vec<ScheduleWindow> = Vec::new();
+    let mut default: Option<Option<u64>> = None;
+    for entry in spec
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (times, interval) = entry.split_once('=')?;
+        let interval: Option<u64> = match interval.trim() {
+            "off" => None,
+            seconds => Some(seconds.parse::<u64>().ok().filter(|seconds| *seconds > 0)?),
+        };
+        if times.trim() == "*" {
+            default = Some(interval);
+            continue;
+        }
+        let (start, end) = times.split_once('-')?;
+        let start: NaiveTime = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+        let end: NaiveTime = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+        if start >= end {
+            return None;
+        }
+        windows.push(ScheduleWindow {
+            start,
+            end,
+            interval,
+        });
+    }
+    Some((windows, default))
+}
+
+/// Parses `;`-separated `HH:MM-HH:MM` no-trade windows, in New York time.
+///
+/// # Arguments
+///
+/// * `spec` - The windows, e.g. `"09:30-09:45;15:45-16:15"`.
+///
+/// # Returns
+///
+/// * `Some(windows)` with the `(start, end)` of each window.
+/// * `None` if any window is invalid.
+///
+/// # Example
+///
+/// ```
+/// let no_trade_windows = parse_no_trade_windows("09:30-09:45").unwrap();
+/// println!("{} no-trade windows", no_trade_windows.len());
+/// ```
+pub(crate) fn parse_no_trade_windows(spec: &str) -> Option<Vec<(NaiveTime, NaiveTime)>> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|window| !window.is_empty())
+        .map(|window| {
+            let (start, end) = window.split_once('-')?;
+            let start: NaiveTime = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+            let end: NaiveTime = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+            (start < end).then_some((start, end))
+        })
+        .collect()
+}
+
+/// Parses a threshold schedule of `;`-separated `HH:MM-HH:MM=<arb value>[/<discount value>]`
+/// windows, in New York time. Arb values must be at least 0.10 and discounts within 0.15 of zero,
+/// as for `ARB_VALUE` and `DISCOUNT_VALUE`.
+///
+/// # Arguments
+///
+/// * `spec` - The schedule, e.g. `"09:30-10:00=0.25/0.05;11:30-14:00=0.10"`.
+///
+/// # Returns
+///
+/// * `Some(windows)` with the windows in order.
+/// * `None` if any window is invalid.
+///
+/// # Example
+///
+/// ```
+/// let windows = parse_threshold_schedule("09:30-10:00=0.25/0.05").unwrap();
+/// println!("{} threshold windows", windows.len());
+/// ```
+pub(crate) fn parse_threshold_schedule(spec: &str) -> Option<Vec<ThresholdWindow>> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (times, thresholds) = entry.split_once('=')?;
+            let (start, end) = times.split_once('-')?;
+            let start: NaiveTime = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+            let end: NaiveTime = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+            let (arb_val, discount_value) = match thresholds.split_once('/') {
+                Some((arb_val, discount_value)) => (arb_val, Some(discount_value)),
+                None => (thresholds, None),
+            };
+            let arb_val: f64 = arb_val
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|arb_val| *arb_val >= 0.10)?;
+            let discount_value: Option<f64> = match discount_value {
+                Some(discount_value) => Some(
+                    discount_value
+                        .trim()
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|discount_value| (-0.15..=0.15).contains(discount_value))?,
+                ),
+                None => None,
+            };
+            (start < end).then_some(ThresholdWindow {
+                start,
+                end,
+                arb_val,
+                discount_value,
+            })
+        })
+        .collect()
+}
+
+/// Gets the time-of-day thresholds based on the `.env` file.
+///
+/// `THRESHOLD_SCHEDULE` overrides `ARB_VALUE`, and optionally `DISCOUNT_VALUE`, in the windows
+/// described in `parse_threshold_schedule`.
+///
+/// # Returns
+///
+/// A `ThresholdSchedule` with the configured windows, empty when it is missing or invalid.
+///
+/// # Example
+///
+/// ```
+/// let threshold_schedule = get_threshold_schedule();
+/// println!("Threshold schedule: {:?}", threshold_schedule);
+/// ```
+pub(crate) fn get_threshold_schedule() -> ThresholdSchedule {
+    match get_dotenv_variable("THRESHOLD_SCHEDULE") {
+        Ok(val) => match parse_threshold_schedule(&val) {
+            Some(windows) => ThresholdSchedule { windows },
+            None => {
+                println!(
+                    "Invalid THRESHOLD_SCHEDULE {:?}, using ARB_VALUE and DISCOUNT_VALUE all day",
+                    val
+                );
+                ThresholdSchedule::default()
+            }
+        },
+        Err(_) => ThresholdSchedule::default(),
+    }
+}
+
+/// Gets the scan schedule and sleep jitter based on the `.env` file.
+///
+/// `SCAN_SCHEDULE` sets per-window scan intervals as described in `parse_scan_schedule`; when it
+/// is missing or invalid, the bot scans every `SECONDS_TO_SLEEP` all day. `SLEEP_JITTER_PERCENT`
+/// randomly stretches or shrinks each interval by up to that percentage. `NO_TRADE_WINDOWS` lists
+/// windows, as described in `parse_no_trade_windows`, in which the bot scans but doesn't submit
+/// orders.
+///
+/// # Arguments
+///
+/// * `seconds_to_sleep` - The interval outside the windows, unless the schedule overrides it.
+///
+/// # Returns
+///
+/// A `ScanSchedule` with the configured windows and jitter.
+///
+/// # Example
+///
+/// ```
+/// let scan_schedule = get_scan_schedule(get_seconds_to_sleep());
+/// println!("Scan schedule: {:?}", scan_schedule);
+/// ```
+pub(crate) fn get_scan_schedule(seconds_to_sleep: u64) -> ScanSchedule {
+    let (windows, default) = get_dotenv_variable("SCAN_SCHEDULE")
+        .ok()
+        .and_then(|spec| parse_scan_schedule(&spec))
+        .unwrap_or_default();
+    ScanSchedule {
+        windows,
+        default_interval: default.unwrap_or(Some(seconds_to_sleep)),
+        jitter_percent: match get_dotenv_variable("SLEEP_JITTER_PERCENT") {
+            Ok(val) => val.parse::<f64>().unwrap_or(0.0),
+            Err(_) => 0.0,
+        },
+        no_trade_windows: match get_dotenv_variable("NO_TRADE_WINDOWS") {
+            Ok(val) => match parse_no_trade_windows(&val) {
+                Some(no_trade_windows) => no_trade_windows,
+                None => {
+                    println!("Invalid NO_TRADE_WINDOWS {:?}, trading all session", val);
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        },
+    }
+}
+
+/// Gets how long each order works before it is canceled, based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<u64>` with the value of `ORDER_TTL_SECONDS`, or `None` to work orders until the
+/// next scan.
+///
+/// # Example
+///
+/// ```
+/// let order_ttl_seconds = get_order_ttl_seconds();
+/// println!("Order TTL: {:?} seconds", order_ttl_seconds);
+/// ```
+pub(crate) fn get_order_ttl_seconds() -> Option<u64> {
+    get_dotenv_variable("ORDER_TTL_SECONDS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+}
+
+/// Gets how long a phase of the run loop may take before the watchdog aborts it, based on the
+/// `.env` file.
+///
+/// # Returns
+///
+/// An `Option<u64>` with the value of `WATCHDOG_SECONDS`, or `None` to run without a watchdog.
+///
+/// # Example
+///
+/// ```
+/// let watchdog_seconds = get_watchdog_seconds();
+/// println!("Watchdog budget: {:?} seconds", watchdog_seconds);
+/// ```
+pub(crate) fn get_watchdog_seconds() -> Option<u64> {
+    match get_dotenv_variable("WATCHDOG_SECONDS") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(parsed_val) if parsed_val > 0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid number of seconds, running without a watchdog");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Gets the thresholds for requoting working orders as their legs move, based on the `.env` file.
+///
+/// `REQUOTE_IMPROVE_THRESHOLD` moves an order's limit down with the combo model price once it fell
+/// by at least that much, and `REQUOTE_CANCEL_THRESHOLD` cancels the order before its TTL once the
+/// model price rose by at least that much.
+///
+/// # Returns
+///
+/// An `Option<RequoteConfig>`, or `None` if neither threshold is set and orders work until their
+/// TTL at their submitted price.
+///
+/// # Example
+///
+/// ```
+/// let requote = get_requote_config();
+/// println!("Requote: {:?}", requote);
+/// ```
+pub(crate) fn get_requote_config() -> Option<RequoteConfig> {
+    let threshold = |key: &str| -> Option<f64> {
+        get_dotenv_variable(key)
+            .ok()
+            .and_then(|val| val.parse::<f64>().ok())
+            .filter(|threshold| *threshold > 0.0)
+    };
+    let reprice_threshold: Option<f64> = threshold("REQUOTE_IMPROVE_THRESHOLD");
+    let cancel_threshold: Option<f64> = threshold("REQUOTE_CANCEL_THRESHOLD");
+    if reprice_threshold.is_none() && cancel_threshold.is_none() {
+        return None;
+    }
+
+    Some(RequoteConfig {
+        reprice_threshold,
+        cancel_threshold,
+    })
+}
+
+/// Gets the shadow mode configuration based on the `.env` file.
+///
+/// `SHADOW_MODE` scans with the market data of the gateway at `DOMAIN` and `PORT` while every
+/// order, cancel and account query goes to a second gateway logged into a paper account, at
+/// `SHADOW_DOMAIN` (default is `DOMAIN`) and `SHADOW_PORT` (default is 5001). `SHADOW_ACCOUNT_ID`
+/// picks the paper account when the gateway has several.
+///
+/// # Returns
+///
+/// An `Option<ShadowConfig>`, or `None` unless `SHADOW_MODE` is set to yes.
+///
+/// # Example
+///
+/// ```
+/// if let Some(shadow) = get_shadow_config() {
+///     println!("Mirroring orders to {}:{}", shadow.domain, shadow.port);
+/// }
+/// ```
+pub(crate) fn get_shadow_config() -> Option<ShadowConfig> {
+    match get_dotenv_variable("SHADOW_MODE") {
+        Ok(val) if val.to_lowercase() == "yes" || val.to_lowercase() == "y" => {}
+        _ => return None,
+    }
+
+    Some(ShadowConfig {
+        domain: match get_dotenv_variable("SHADOW_DOMAIN") {
+            Ok(val) => val,
+            Err(_) => match get_dotenv_variable("DOMAIN") {
+                Ok(val) => val,
+                Err(_) => "localhost".to_string(),
+            },
+        },
+        port: match get_dotenv_variable("SHADOW_PORT") {
+            Ok(val) => val,
+            Err(_) => {
+                println!("SHADOW_PORT is not set, using 5001");
+                "5001".to_string()
+            }
+        },
+        account_id: get_dotenv_variable("SHADOW_ACCOUNT_ID").ok(),
+    })
+}
+
+/// Gets the account to trade based on the `.env` file, for gateways logged into several accounts.
+///
+/// # Returns
+///
+/// An `Option<String>` with `ACCOUNT_ID`, or `None` to trade the first account.
+///
+/// # Example
+///
+/// ```
+/// if let Some(account_id) = get_preferred_account() {
+///     println!("Trading account {}", account_id);
+/// }
+/// ```
+pub(crate) fn get_preferred_account() -> Option<String> {
+    get_dotenv_variable("ACCOUNT_ID").ok()
+}
+
+/// Gets the SPX overnight session configuration based on the `.env` file.
+///
+/// `TRADE_GTH` enables trading box spreads during global trading hours with orders allowed
+/// outside regular hours. `GTH_SECONDS_TO_SLEEP` and `GTH_ORDER_TTL_SECONDS` set the scan interval
+/// and order TTL overnight, when quotes are thinner.
+///
+/// # Arguments
+///
+/// * `seconds_to_sleep` - The overnight scan interval unless `GTH_SECONDS_TO_SLEEP` is set.
+///
+/// # Returns
+///
+/// A `GthConfig`, defaulting to regular hours only.
+///
+/// # Example
+///
+/// ```
+/// let gth_config = get_gth_config(get_seconds_to_sleep());
+/// println!("Trade GTH: {}", gth_config.enabled);
+/// ```
+pub(crate) fn get_gth_config(seconds_to_sleep: u64) -> GthConfig {
+    let enabled: bool = match get_dotenv_variable("TRADE_GTH") {
+        Ok(val) => val.to_lowercase() == "yes" || val.to_lowercase() == "y",
+        Err(_) => false,
+    };
+
+    let gth_seconds_to_sleep: u64 = match get_dotenv_variable("GTH_SECONDS_TO_SLEEP") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => seconds,
+            _ => {
+                println!("Not a valid GTH scan interval, using SECONDS_TO_SLEEP");
+                seconds_to_sleep
+            }
+        },
+        Err(_) => seconds_to_sleep,
+    };
+
+    GthConfig {
+        enabled,
+        seconds_to_sleep: gth_seconds_to_sleep,
+        order_ttl_seconds: get_dotenv_variable("GTH_ORDER_TTL_SECONDS")
+            .ok()
+            .and_then(|val| val.parse::<u64>().ok()),
+    }
+}
+
+/// Gets the fast market configuration based on the `.env` file.
+///
+/// `FAST_MARKET_MOVE_PERCENT` and `FAST_MARKET_SPREAD_MULTIPLE` set the triggers: how far the
+/// underlying may move and how many times wider the average spread may get between scans.
+/// During a fast market the bot scans every `FAST_MARKET_SECONDS_TO_SLEEP` seconds and adds
+/// `FAST_MARKET_EXTRA_EDGE` to the arb threshold, until `FAST_MARKET_SCANS` scans pass without a
+/// trigger.
+///
+/// # Arguments
+///
+/// * `seconds_to_sleep` - The regular scan interval, halved during a fast market unless
+///   `FAST_MARKET_SECONDS_TO_SLEEP` is set.
+///
+/// # Returns
+///
+/// An `Option<FastMarketConfig>`, or `None` if neither trigger is set.
+///
+/// # Example
+///
+/// ```
+/// let fast_market_config = get_fast_market_config(get_seconds_to_sleep());
+/// println!("Fast market: {:?}", fast_market_config);
+/// ```
+pub(crate) fn get_fast_market_config(seconds_to_sleep: u64) -> Option<FastMarketConfig> {
+    let positive = |key: &str| -> Option<f64> {
+        get_dotenv_variable(key)
+            .ok()
+            .and_then(|val| val.parse::<f64>().ok())
+            .filter(|value| *value > 0.0)
+    };
+    let max_move_percent: Option<f64> = positive("FAST_MARKET_MOVE_PERCENT");
+    let max_spread_multiple: Option<f64> = positive("FAST_MARKET_SPREAD_MULTIPLE");
+    if max_move_percent.is_none() && max_spread_multiple.is_none() {
+        return None;
+    }
+
+    let fast_seconds_to_sleep: u64 = match get_dotenv_variable("FAST_MARKET_SECONDS_TO_SLEEP") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => seconds,
+            _ => {
+                println!("Not a valid fast market scan interval, halving SECONDS_TO_SLEEP");
+                (seconds_to_sleep / 2).max(1)
+            }
+        },
+        Err(_) => (seconds_to_sleep / 2).max(1),
+    };
+    let scans: u32 = match get_dotenv_variable("FAST_MARKET_SCANS") {
+        Ok(val) => match val.parse::<u32>() {
+            Ok(scans) if scans > 0 => scans,
+            _ => {
+                println!("Not a valid number of fast market scans, using 5");
+                5
+            }
+        },
+        Err(_) => 5,
+    };
+
+    Some(FastMarketConfig {
+        max_move_percent,
+        max_spread_multiple,
+        seconds_to_sleep: fast_seconds_to_sleep,
+        extra_edge: positive("FAST_MARKET_EXTRA_EDGE").unwrap_or(0.0),
+        scans,
+    })
+}
+
+/// Gets how far below the arb threshold a contender may fall and still be logged as a near miss,
+/// based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the value of `NEAR_MISS_EPSILON`, or `None` to not log near misses.
+///
+/// # Example
+///
+/// ```
+/// let near_miss_epsilon = get_near_miss_epsilon();
+/// println!("Near miss epsilon: {:?}", near_miss_epsilon);
+/// ```
+pub(crate) fn get_near_miss_epsilon() -> Option<f64> {
+    get_dotenv_variable("NEAR_MISS_EPSILON")
+        .ok()
+        .and_then(|val| val.parse::<f64>().ok())
+        .filter(|epsilon| *epsilon > 0.0)
+}
+
+/// Gets how far a leg's implied volatility may be from its neighboring strikes' before the
+/// contender is dropped as a likely bad print, based on the `.env` file.
+///
+/// Implied volatilities come from the market data, or are solved from the mid where it has none.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the value of `MAX_IV_DEVIATION` in volatility points, e.g. `0.05` for 5
+/// points, or `None` to not check.
+///
+/// # Example
+///
+/// ```
+/// let max_iv_deviation = get_max_iv_deviation();
+/// println!("Max IV deviation: {:?}", max_iv_deviation);
+/// ```
+pub(crate) fn get_max_iv_deviation() -> Option<f64> {
+    match get_dotenv_variable("MAX_IV_DEVIATION") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val > 0.0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid max IV deviation, not checking implied volatilities");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Gets the box financing settings, based on the `.env` file.
+///
+/// Box financing sells box spreads below fair value to borrow at the rate they imply.
+/// `BOX_FINANCING_MAX_SPREAD` turns it on and sets the highest annualized rate above the risk-free
+/// curve to borrow at, e.g. `0.003` for 30 basis points. `BOX_FINANCING_MAX_NOTIONAL` caps the
+/// credit borrowed per scan in dollars.
+///
+/// # Returns
+///
+/// An `Option<BoxFinancing>`, or `None` to only sell boxes above fair value.
+///
+/// # Example
+///
+/// ```
+/// if let Some(box_financing) = get_box_financing() {
+///     println!("Borrowing at up to {:.4} over the curve", box_financing.max_rate_spread);
+/// }
+/// ```
+pub(crate) fn get_box_financing() -> Option<BoxFinancing> {
+    let max_rate_spread: f64 = match get_dotenv_variable("BOX_FINANCING_MAX_SPREAD") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val >= 0.0 => parsed_val,
+            _ => {
+                println!("Not a valid box financing spread, turning box financing off");
+                return None;
+            }
+        },
+        Err(_) => return None,
+    };
+
+    let max_notional: Option<f64> = match get_dotenv_variable("BOX_FINANCING_MAX_NOTIONAL") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val > 0.0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid box financing notional, leaving it uncapped");
+                None
+            }
+        },
+        Err(_) => None,
+    };
+
+    Some(BoxFinancing {
+        max_rate_spread,
+        max_notional,
+    })
+}
+
+/// Gets the quote quality score below which an expiration is left out of a scan, based on the
+/// `.env` file.
+///
+/// Each expiration scores between `0` and `1`: the fraction of its contracts quoted on both sides,
+/// times the fraction of those quotes that changed since the previous scan, divided by one plus
+/// their average spread relative to the mid.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the value of `MIN_QUOTE_QUALITY`, or `None` to scan every expiration.
+///
+/// # Example
+///
+/// ```
+/// let min_quote_quality = get_min_quote_quality();
+/// println!("Minimum quote quality: {:?}", min_quote_quality);
+/// ```
+pub(crate) fn get_min_quote_quality() -> Option<f64> {
+    match get_dotenv_variable("MIN_QUOTE_QUALITY") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if parsed_val > 0.0 && parsed_val <= 1.0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid minimum quote quality, scanning every expiration");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Gets which price of each leg's quote the arb value is computed from, based on the `.env` file.
+///
+/// `BUY_LEG_PRICE` and `SELL_LEG_PRICE` each select `bid`, `ask`, `mid`, `mark` or `model` for the
+/// bought and sold legs. Marks and model prices fall back to the mid where a quote has none.
+///
+/// # Returns
+///
+/// A `LegPricing`, defaulting to the mid on both sides.
+///
+/// # Example
+///
+/// ```
+/// let leg_pricing = get_leg_pricing();
+/// println!("Buying at the {:?}, selling at the {:?}", leg_pricing.buy, leg_pricing.sell);
+/// ```
+pub(crate) fn get_leg_pricing() -> LegPricing {
+    let mut leg_pricing: LegPricing = LegPricing::default();
+    for (key, source) in [
+        ("BUY_LEG_PRICE", &mut leg_pricing.buy),
+        ("SELL_LEG_PRICE", &mut leg_pricing.sell),
+    ] {
+        if let Ok(val) = get_dotenv_variable(key) {
+            match PriceSource::from_str(&val) {
+                Some(parsed_val) => *source = parsed_val,
+                None => println!("Not a valid {}, setting to mid", key),
+            }
+        }
+    }
+    leg_pricing
+}
+
+/// Gets the highest correlation allowed between two contenders selected in the same scan, based on
+/// the `.env` file.
+///
+/// Correlation is scored from `0` for unrelated spreads to `1` for the same legs on the same
+/// expiration, weighing shared legs, a shared expiration and strikes traded in the same direction.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the value of `MAX_CONTENDER_CORRELATION`, or `None` to not prune the
+/// selection.
+///
+/// # Example
+///
+/// ```
+/// let max_correlation = get_max_contender_correlation();
+/// println!("Max contender correlation: {:?}", max_correlation);
+/// ```
+pub(crate) fn get_max_contender_correlation() -> Option<f64> {
+    get_dotenv_variable("MAX_CONTENDER_CORRELATION")
+        .ok()
+        .and_then(|val| val.parse::<f64>().ok())
+        .filter(|max_correlation| (0.0..1.0).contains(max_correlation))
+}
+
+/// Gets the upcoming dividends of the underlying from the file at `DIVIDENDS_PATH`, based on the
+/// `.env` file.
+///
+/// # Arguments
+///
+/// * `ticker` - The ticker the bot trades.
+///
+/// # Returns
+///
+/// A `DividendCalendar` with the ticker's dividends, empty if the file is not set or can't be read.
+///
+/// # Example
+///
+/// ```
+/// let dividends = get_dividend_calendar("AAPL");
+/// println!("Dividends: {}", dividends.len());
+/// ```
+pub(crate) fn get_dividend_calendar(ticker: &str) -> DividendCalendar {
+    match get_dotenv_variable("DIVIDENDS_PATH") {
+        Ok(path) => match DividendCalendar::load(&path, ticker) {
+            Ok(dividends) => {
+                println!(
+                    "Loaded {} {} dividends from {}",
+                    dividends.len(),
+                    ticker,
+                    path
+                );
+                dividends
+            }
+            Err(e) => {
+                println!("Failed to load {}: {}, scanning without dividends", path, e);
+                DividendCalendar::new()
+            }
+        },
+        Err(_) => {
+            println!("DIVIDENDS_PATH not found, using the dividends from the market data");
+            DividendCalendar::new()
+        }
+    }
+}
+
+/// Gets the early-exercise screen for American-style options, based on the `.env` file.
+///
+/// `EXERCISE_STYLE` is `american` or `european`; when it is not set, the cash-settled indexes
+/// SPX, SPXW, XSP, NDX and RUT are European and every other ticker is American. The screen
+/// rejects short calls over the scanner's dividends and short puts more than
+/// `MAX_SHORT_PUT_ITM_PERCENT` in the money (default 2).
+///
+/// # Arguments
+///
+/// * `ticker` - The ticker the bot trades.
+///
+/// # Returns
+///
+/// An `Option<EarlyExerciseScreen>` for American-style options, or `None` for European-style ones.
+///
+/// # Example
+///
+/// ```
+/// let early_exercise = get_early_exercise_screen("AAPL");
+/// println!("Screening early exercise: {}", early_exercise.is_some());
+/// ```
+pub(crate) fn get_early_exercise_screen(ticker: &str) -> Option<EarlyExerciseScreen> {
+    let american: bool = match get_dotenv_variable("EXERCISE_STYLE") {
+        Ok(val) => val.to_lowercase() == "american",
+        Err(_) => !["SPX", "SPXW", "XSP", "NDX", "RUT"].contains(&ticker.to_uppercase().as_str()),
+    };
+    if !american {
+        return None;
+    }
+
+    Some(EarlyExerciseScreen {
+        max_put_itm_percent: match get_dotenv_variable("MAX_SHORT_PUT_ITM_PERCENT") {
+            Ok(val) => val.parse::<f64>().unwrap_or(2.0),
+            Err(_) => 2.0,
+        },
+    })
+}
+
+/// Gets how often executions are polled while orders are working, so a fill is reacted to
+/// without waiting for the orders to expire, based on the `.env` file.
+///
+/// # Returns
+///
+/// A `u64` with the value of `FILL_POLL_SECONDS` (default is 2).
+///
+/// # Example
+///
+/// ```
+/// let fill_poll_seconds = get_fill_poll_seconds();
+/// println!("Fill poll seconds: {}", fill_poll_seconds);
+/// ```
+pub(crate) fn get_fill_poll_seconds() -> u64 {
+    match get_dotenv_variable("FILL_POLL_SECONDS") {
+        Ok(val) => val.parse::<u64>().unwrap_or(2).max(1),
+        Err(_) => 2,
+    }
+}
+
+/// Gets how often the positions of held combos are checked for assignments, exercises and
+/// expirations, based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<u64>` with the value of `ASSIGNMENT_CHECK_SECONDS` (default is 300), or `None` if
+/// it is 0 to leave the checks off.
+///
+/// # Example
+///
+/// ```
+/// if let Some(assignment_check_seconds) = get_assignment_check_seconds() {
+///     println!("Checking for assignments every {} seconds", assignment_check_seconds);
+/// }
+/// ```
+pub(crate) fn get_assignment_check_seconds() -> Option<u64> {
+    match get_dotenv_variable("ASSIGNMENT_CHECK_SECONDS") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(0) => None,
+            Ok(parsed_val) => Some(parsed_val),
+            Err(_) => {
+                println!("Not a valid number of seconds, checking for assignments every 300");
+                Some(300)
+            }
+        },
+        Err(_) => Some(300),
+    }
+}
+
+/// Gets the path the in-flight state is saved to each iteration and resumed from on startup,
+/// based on the `.env` file.
+///
+/// # Returns
+///
+/// A `String` with the value of `STATE_PATH` (default is `state.json`).
+///
+/// # Example
+///
+/// ```
+/// let state_path = get_state_path();
+/// println!("State path: {}", state_path);
+/// ```
+pub(crate) fn get_state_path() -> String {
+    match get_dotenv_variable("STATE_PATH") {
+        Ok(val) => val,
+        Err(_) => "state.json".to_string(),
+    }
+}
+
+/// Gets the path of the gateway's own log, which is tailed for errors and warnings, based on the
+/// `.env` file.
 ///
 /// # Returns
 ///
-/// A `String` representing the fill type:
-/// * `"1"` for single order, single fill.
-/// * `"2"` for single order, multiple fills.
-/// * `"3"` for multiple orders, single fill.
-/// * `"DEFAULT"` for multiple orders, multiple fills.
+/// An `Option<String>` with the value of `GATEWAY_LOG_PATH`, or `None` to not tail it.
 ///
 /// # Example
 ///
 /// ```
-/// let fill_type = get_fill_type();
-/// println!("Selected fill type: {}", fill_type);
+/// let gateway_log_path = get_gateway_log_path();
+/// println!("Gateway log: {:?}", gateway_log_path);
 /// ```
-pub(crate) fn get_fill_type() -> String {
-    match get_dotenv_variable("FILL_TYPE") {
-        Ok(val) => val,
-        Err(_) => {
-            let prompt: &str = "\
-1 for single order, single fill
-2 for single order, multiple fills
-3 for multiple orders, single fill
-DEFAULT for multiple orders, multiple fills
-";
-            get_user_input(&format! {
-            "{}\nEnter which fill type the bot should use:", prompt})
-        }
-    }
+pub(crate) fn get_gateway_log_path() -> Option<String> {
+    get_dotenv_variable("GATEWAY_LOG_PATH")
+        .ok()
+        .filter(|path| !path.trim().is_empty())
 }
 
-/// Gets the ticker based on user input or the `.env` file.
+/// Gets the seed for probabilistic fills in the backtester and paper runs, based on the `.env`
+/// file.
 ///
 /// # Returns
 ///
-/// A `String` representing the selected ticker.
+/// A `u64` read from `BACKTEST_SEED` (default is 42).
 ///
 /// # Example
 ///
 /// ```
-/// let ticker = get_ticker();
-/// println!("Selected ticker: {}", ticker);
+/// let seed = get_backtest_seed();
+/// println!("Backtest seed: {}", seed);
 /// ```
-pub(crate) fn get_ticker() -> String {
-    match get_dotenv_variable("TICKER") {
-        Ok(val) => val,
-        Err(_) => get_user_input("Enter which ticker the bot should use:"),
+pub(crate) fn get_backtest_seed() -> u64 {
+    match get_dotenv_variable("BACKTEST_SEED") {
+        Ok(val) => match val.parse::<u64>() {
+            Ok(parsed_val) => parsed_val,
+            Err(_) => {
+                println!("Not a valid seed, setting to 42");
+                42
+            }
+        },
+        Err(_) => 42,
     }
 }
 
-/// Gets the arbitrage value based on the `.env` file or sets a default value.
+/// Gets the Monte Carlo settings for the backtest report, based on the `.env` file.
+///
+/// `MONTE_CARLO_SIMULATIONS` sets the number of reshuffled fill sequences and
+/// `MONTE_CARLO_MISS_PERCENT` the chance that each backtest fill is dropped.
 ///
 /// # Returns
 ///
-/// A `f64` representing the arbitrage value, with a minimum value of 0.10.
+/// A `MonteCarloConfig`, defaulting to 1000 simulations with a 10% miss rate.
 ///
 /// # Example
 ///
 /// ```
-/// let arb_value = get_arb_value();
-/// println!("Arb Value: {}", arb_value);
+/// let monte_carlo_config = get_monte_carlo_config();
+/// println!("Running {} simulations.", monte_carlo_config.simulations);
 /// ```
-pub(crate) fn get_arb_value() -> f64 {
-    match get_dotenv_variable("ARB_VALUE") {
-        Ok(val) => match val.parse::<f64>() {
-            Ok(val) => {
-                if val >= 0.10 {
-                    val
-                } else {
-                    println!("Not a valid Arb Value, setting to 0.10");
-                    0.10
-                }
-            }
+pub(crate) fn get_monte_carlo_config() -> MonteCarloConfig {
+    let simulations: usize = match get_dotenv_variable("MONTE_CARLO_SIMULATIONS") {
+        Ok(val) => match val.parse::<usize>() {
+            Ok(parsed_val) => parsed_val,
             Err(_) => {
-                println!("Not a valid Arb Value, setting to 0.10");
-                0.10
+                println!("Not a valid number of simulations, setting to 1000");
+                1000
             }
         },
-        Err(_) => {
-            println!("Arb Value not found, setting to 0.10");
-            0.10
-        }
+        Err(_) => 1000,
+    };
+
+    let miss_percent: f64 = match get_dotenv_variable("MONTE_CARLO_MISS_PERCENT") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(parsed_val) if (0.0..=100.0).contains(&parsed_val) => parsed_val,
+            _ => {
+                println!("Not a valid percent in the range 0-100, setting to 10");
+                10.0
+            }
+        },
+        Err(_) => 10.0,
+    };
+
+    MonteCarloConfig {
+        simulations,
+        miss_probability: miss_percent / 100.0,
     }
 }
 
-/// Gets the strike difference value based on the `.env` file or sets a default.
+/// Gets the parameter grid swept by the optimizer, based on the `.env` file.
+///
+/// `OPTIMIZE_DISCOUNT_VALUES` and `OPTIMIZE_ARB_VALUES` are comma-separated lists,
+/// `OPTIMIZE_WING_WIDTHS` is a `;`-separated list of wing width sets (e.g. `5;5,10`) and
+/// `OPTIMIZE_DTE_WINDOWS` is a `;`-separated list of `min-max` windows (e.g. `0-7;0-30`). A missing
+/// or invalid list falls back to the single value of the current configuration.
+///
+/// # Arguments
+///
+/// * `base` - The current configuration.
 ///
 /// # Returns
 ///
-/// A `f64` representing the strike difference value, with a default of 5.0 if invalid.
+/// A `ParamGrid` with the values to sweep.
 ///
 /// # Example
 ///
 /// ```
-/// let strike_dif = get_strike_dif_value();
-/// println!("Strike Difference Value: {}", strike_dif);
+/// let grid = get_param_grid(&base);
+/// println!("Sweeping {} parameter sets.", grid.param_sets().len());
 /// ```
-pub(crate) fn get_strike_dif_value() -> f64 {
-    match get_dotenv_variable("STRIKE_DIF_VALUE") {
-        Ok(val) => match val.parse::<f64>() {
-            Ok(val) => {
-                if val >= 0.0 {
-                    val
-                } else {
-                    println!("Not a valid Strike Dif Value, setting to 5.0");
-                    5.0
-                }
+pub(crate) fn get_param_grid(base: &ParamSet) -> ParamGrid {
+    let discount_values: Vec<f64> = match get_dotenv_variable("OPTIMIZE_DISCOUNT_VALUES") {
+        Ok(val) => match parse_f64_list(&val) {
+            Some(values) => values,
+            None => {
+                println!("Not a valid list of discount values, using the current one");
+                vec![base.discount_value]
             }
-            Err(_) => {
-                println!("Not a valid Strike Dif Value, setting to 5.0");
-                5.0
+        },
+        Err(_) => vec![base.discount_value],
+    };
+
+    let arb_values: Vec<f64> = match get_dotenv_variable("OPTIMIZE_ARB_VALUES") {
+        Ok(val) => match parse_f64_list(&val) {
+            Some(values) => values,
+            None => {
+                println!("Not a valid list of arb values, using the current one");
+                vec![base.arb_val]
             }
         },
-        Err(_) => {
-            println!("Strike Dif Value not found, setting to 5.0");
-            5.0
-        }
+        Err(_) => vec![base.arb_val],
+    };
+
+    let wing_widths: Vec<Vec<f64>> = match get_dotenv_variable("OPTIMIZE_WING_WIDTHS") {
+        Ok(val) => match val.split(';').map(parse_wing_widths).collect() {
+            Some(values) => values,
+            None => {
+                println!("Not a valid list of wing widths, using the current ones");
+                vec![base.wing_widths.clone()]
+            }
+        },
+        Err(_) => vec![base.wing_widths.clone()],
+    };
+
+    let dte_windows: Vec<Option<(i64, i64)>> = match get_dotenv_variable("OPTIMIZE_DTE_WINDOWS") {
+        Ok(val) => match val
+            .split(';')
+            .map(|window| {
+                let (min_val, max_val) = window.split_once('-')?;
+                parse_dte_window(min_val, max_val).map(Some)
+            })
+            .collect()
+        {
+            Some(values) => values,
+            None => {
+                println!("Not a valid list of DTE windows, using the current one");
+                vec![base.dte_window]
+            }
+        },
+        Err(_) => vec![base.dte_window],
+    };
+
+    ParamGrid {
+        discount_values,
+        arb_values,
+        wing_widths,
+        dte_windows,
+    }
+}
+
+/// Gets the number of walk-forward windows used by the optimizer, based on the `.env` file.
+///
+/// # Returns
+///
+/// A `usize` read from `OPTIMIZE_FOLDS` (default is 4).
+///
+/// # Example
+///
+/// ```
+/// let folds = get_optimize_folds();
+/// println!("Walk-forward windows: {}", folds);
+/// ```
+pub(crate) fn get_optimize_folds() -> usize {
+    match get_dotenv_variable("OPTIMIZE_FOLDS") {
+        Ok(val) => match val.parse::<usize>() {
+            Ok(parsed_val) if parsed_val > 0 => parsed_val,
+            _ => {
+                println!("Not a valid number of folds, setting to 4");
+                4
+            }
+        },
+        Err(_) => 4,
     }
 }
 
@@ -296,7 +2649,7 @@ pub(crate) fn get_discount_value() -> f64 {
     match get_dotenv_variable("DISCOUNT_VALUE") {
         Ok(val) => match val.parse::<f64>() {
             Ok(val) => {
-                if val >= -0.15 && val <= 0.15 {
+                if (-0.15..=0.15).contains(&val) {
                     val
                 } else {
                     println!("Not a valid Discount Value, setting to 0.0");
@@ -312,7 +2665,7 @@ pub(crate) fn get_discount_value() -> f64 {
             let input: String = get_user_input("Enter a Discount Value between -0.15 and 0.15:");
             match input.parse::<f64>() {
                 Ok(val) => {
-                    if val >= -0.15 && val <= 0.15 {
+                    if (-0.15..=0.15).contains(&val) {
                         val
                     } else {
                         println!("Not a valid Discount Value, setting to 0.0");
@@ -328,98 +2681,48 @@ pub(crate) fn get_discount_value() -> f64 {
     }
 }
 
-/// Gets the number of days for the bot to receive data, based on the `.env` file or user input.
-///
-/// # Returns
-///
-/// An `i64` representing the number of days (between 1 and 9), with a default of 5.
+/// Gets the expirations the bot scans, based on the `.env` file or user input.
 ///
-/// # Example
-///
-/// ```
-/// let num_days = get_num_days();
-/// println!("Receiving data for {} days.", num_days);
-/// ```
-pub(crate) fn get_num_days() -> i64 {
-    match get_dotenv_variable("NUM_DAYS") {
-        Ok(val) => match val.parse::<i64>() {
-            Ok(parsed_val) => {
-                if parsed_val > 0 && parsed_val < 10 {
-                    parsed_val
-                } else {
-                    println!("Not a valid number in the range 1-9, setting to 5");
-                    5
-                }
-            }
-            Err(_) => {
-                println!("Not a valid integer, setting to 5");
-                5
-            }
-        },
-        Err(_) => {
-            let input: String = get_user_input("Enter number of days worth of data to receive:");
-            match input.parse::<i64>() {
-                Ok(parsed_input) => {
-                    if parsed_input > 0 && parsed_input < 10 {
-                        parsed_input
-                    } else {
-                        println!("Not a valid number in the range 1-9, setting to 5");
-                        5
-                    }
-                }
-                Err(_) => {
-                    println!("Not a valid integer, setting to 5");
-                    5
-                }
-            }
-        }
-    }
-}
-
-/// Gets the number of days offset for the bot, based on the `.env` file or user input.
+/// `EXPIRY_SELECTOR` is a semicolon-separated list of selectors evaluated against the exchange
+/// calendar: `next N [weeklies|monthlies|end-of-month] [from M DTE]`,
+/// `[weeklies|monthlies|end-of-month] within M DTE`, or a comma-separated list of `YYMMDD` dates.
+/// The replaced `NUM_DAYS` and `NUM_DAYS_OFFSET` are read as `next NUM_DAYS from NUM_DAYS_OFFSET
+/// DTE` when it isn't set.
 ///
 /// # Returns
 ///
-/// An `i64` representing the number of days offset (between 0 and 21), with a default of 0.
+/// A `Vec<ExpirySelector>`, with a default of the next 5 expirations.
 ///
 /// # Example
 ///
 /// ```
-/// let offset_days = get_num_days_offset();
-/// println!("Offsetting {} days.", offset_days);
+/// let expiry_selectors = get_expiry_selectors();
+/// println!("Scanning {:?}", expiry_selectors);
 /// ```
-pub(crate) fn get_num_days_offset() -> i64 {
-    match get_dotenv_variable("NUM_DAYS_OFFSET") {
-        Ok(val) => match val.parse::<i64>() {
-            Ok(parsed_val) => {
-                if parsed_val >= 0 && parsed_val <= 21 {
-                    parsed_val
-                } else {
-                    println!("Not a valid number in the range 0-21, setting to 0");
-                    0
-                }
-            }
-            Err(_) => {
-                println!("Not a valid integer, setting to 0");
-                0
-            }
-        },
-        Err(_) => {
-            let input: String = get_user_input("Enter number of days offset:");
-            match input.parse::<i64>() {
-                Ok(parsed_input) => {
-                    if parsed_input >= 0 && parsed_input <= 21 {
-                        parsed_input
-                    } else {
-                        println!("Not a valid number in the range 0-21, setting to 0");
-                        0
-                    }
-                }
-                Err(_) => {
-                    println!("Not a valid integer, setting to 0");
-                    0
-                }
-            }
+pub(crate) fn get_expiry_selectors() -> Vec<ExpirySelector> {
+    let default_selectors: Vec<ExpirySelector> = vec![ExpirySelector::Next {
+        count: 5,
+        kind: None,
+        min_dte: 0,
+    }];
+    let val: String = match (
+        get_dotenv_variable("EXPIRY_SELECTOR"),
+        get_dotenv_variable("NUM_DAYS"),
+    ) {
+        (Ok(val), _) => val,
+        (Err(_), Ok(num_days)) => {
+            let num_days_offset: String =
+                get_dotenv_variable("NUM_DAYS_OFFSET").unwrap_or("0".to_string());
+            println!("NUM_DAYS and NUM_DAYS_OFFSET are replaced by EXPIRY_SELECTOR, reading them as next {} from {} DTE", num_days, num_days_offset);
+            format!("next {} from {} DTE", num_days, num_days_offset)
+        }
+        (Err(_), Err(_)) => get_user_input("Enter the expirations to scan, e.g. next 5:"),
+    };
+    match parse_expiry_selectors(&val) {
+        Ok(selectors) => selectors,
+        Err(e) => {
+            println!("Not a valid expiry selector ({}), setting to next 5", e);
+            default_selectors
         }
     }
 }
@@ -450,14 +2753,47 @@ pub(crate) fn is_us_stock_market_open(current_time: chrono::DateTime<Utc>) -> bo
         return false;
     }
     let Some(market_open) = New_York
-    .with_ymd_and_hms(ny_time.year(), ny_time.month(), ny_time.day(), 9, 30, 0)
-    .single() else { return false };
+        .with_ymd_and_hms(ny_time.year(), ny_time.month(), ny_time.day(), 9, 30, 0)
+        .single()
+    else {
+        return false;
+    };
     let Some(market_close) = New_York
-    .with_ymd_and_hms(ny_time.year(), ny_time.month(), ny_time.day(), 15, 30, 0)
-    .single() else { return false };
+        .with_ymd_and_hms(ny_time.year(), ny_time.month(), ny_time.day(), 15, 30, 0)
+        .single()
+    else {
+        return false;
+    };
     ny_time >= market_open && ny_time <= market_close
 }
 
+/// Calculates the number of minutes until today's 4:00 PM ET options close.
+///
+/// # Arguments
+///
+/// * `current_time` - The current time in UTC.
+///
+/// # Returns
+///
+/// An `i64` with the minutes remaining, negative once the close has passed.
+///
+/// # Example
+///
+/// ```
+/// let minutes = minutes_to_market_close(Utc::now());
+/// println!("{} minutes to close.", minutes);
+/// ```
+pub(crate) fn minutes_to_market_close(current_time: chrono::DateTime<Utc>) -> i64 {
+    let ny_time: DateTime<chrono_tz::Tz> = current_time.with_timezone(&New_York);
+    let Some(market_close) = New_York
+        .with_ymd_and_hms(ny_time.year(), ny_time.month(), ny_time.day(), 16, 0, 0)
+        .single()
+    else {
+        return 0;
+    };
+    (market_close - ny_time).num_minutes()
+}
+
 /// Calculates the final number of orders and fills based on fill type and portfolio value.
 ///
 /// # Arguments
@@ -517,8 +2853,8 @@ fn get_optimal_num_orders(portfolio_value: f64) -> (i32, i32) {
 ///
 /// # Arguments
 ///
-/// * `current_date` - The current date in `YYMMDD` format.
-/// * `date` - The target date in `YYMMDD` format.
+/// * `current_date` - The current date.
+/// * `date` - The target expiration date.
 ///
 /// # Returns
 ///
@@ -527,13 +2863,12 @@ fn get_optimal_num_orders(portfolio_value: f64) -> (i32, i32) {
 /// # Example
 ///
 /// ```
-/// let days_diff = calc_time_difference("240101", "240201");
+/// let current_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+/// let days_diff = calc_time_difference(current_date, "240201".parse().unwrap());
 /// println!("Days difference: {}", days_diff);
 /// ```
-pub(crate) fn calc_time_difference(current_date: &str, date: &str) -> i64 {
-    let current_time: NaiveDate = NaiveDate::parse_from_str(current_date, "%y%m%d").unwrap();
-    let future_time: NaiveDate = NaiveDate::parse_from_str(date, "%y%m%d").unwrap();
-    ((current_time - future_time).num_hours() as f64 / 24.0 * -1.0) as i64
+pub(crate) fn calc_time_difference(current_date: NaiveDate, date: ExpiryDate) -> i64 {
+    date.days_from(current_date)
 }
 
 /// Calculates the rank value for a contract based on average ask, arbitrage value, and dates.
@@ -542,8 +2877,8 @@ pub(crate) fn calc_time_difference(current_date: &str, date: &str) -> i64 {
 ///
 /// * `avg_ask` - The average ask price as `f64`.
 /// * `arb_val` - The arbitrage value as `f64`.
-/// * `current_date` - The current date in `YYMMDD` format.
-/// * `date` - The target date in `YYMMDD` format.
+/// * `current_date` - The current date.
+/// * `date` - The target expiration date.
 ///
 /// # Returns
 ///
@@ -552,10 +2887,16 @@ pub(crate) fn calc_time_difference(current_date: &str, date: &str) -> i64 {
 /// # Example
 ///
 /// ```
-/// let rank = calc_rank_value(2.5, 0.10, "240101", "240201");
+/// let current_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+/// let rank = calc_rank_value(2.5, 0.10, current_date, "240201".parse().unwrap());
 /// println!("Rank value: {}", rank);
 /// ```
-pub(crate) fn calc_rank_value(avg_ask: f64, arb_val: f64, current_date: &str, date: &str) -> f64 {
+pub(crate) fn calc_rank_value(
+    avg_ask: f64,
+    arb_val: f64,
+    current_date: NaiveDate,
+    date: ExpiryDate,
+) -> f64 {
     let difference: i64 = calc_time_difference(current_date, date) + 1;
     (avg_ask * arb_val) / (difference as f64)
 }
@@ -608,3 +2949,142 @@ pub(crate) fn format_strike(price: f64) -> String {
     }
     formatted
 }
+
+/// Gets the address the gRPC control plane listens on based on the `.env` file. Serving it
+/// requires building with the `grpc` feature.
+///
+/// # Returns
+///
+/// An `Option<String>` with the `host:port` in `GRPC_ADDR`, or `None` to not serve it.
+///
+/// # Example
+///
+/// ```
+/// if let Some(grpc_addr) = get_grpc_addr() {
+///     println!("Serving gRPC on {}", grpc_addr);
+/// }
+/// ```
+pub(crate) fn get_grpc_addr() -> Option<String> {
+    get_dotenv_variable("GRPC_ADDR").ok()
+}
+
+/// Gets the address the gateway metrics are served on based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<String>` with the `host:port` in `METRICS_ADDR`, or `None` to not serve them.
+///
+/// # Example
+///
+/// ```
+/// if let Some(metrics_addr) = get_metrics_addr() {
+///     println!("Serving metrics on {}", metrics_addr);
+/// }
+/// ```
+pub(crate) fn get_metrics_addr() -> Option<String> {
+    get_dotenv_variable("METRICS_ADDR").ok()
+}
+
+/// Gets the path of the watchlist file based on the `.env` file.
+///
+/// # Returns
+///
+/// A `String` with `WATCHLIST_PATH`, defaulting to `watchlist.json`. The watchlist is imported
+/// from it at startup if it exists, and the `export` and `import` commands use it by default.
+///
+/// # Example
+///
+/// ```
+/// let watchlist_path = get_watchlist_path();
+/// println!("Watchlist: {}", watchlist_path);
+/// ```
+pub(crate) fn get_watchlist_path() -> String {
+    get_dotenv_variable("WATCHLIST_PATH").unwrap_or("watchlist.json".to_string())
+}
+
+/// Gets how far below the combo mid the `close` and `roll` subcommands sell a combo, based on the
+/// `.env` file.
+///
+/// # Returns
+///
+/// An `f64` with the value of `CLOSE_OFFSET`, defaulting to `0.0` to sell at the mid. Negative
+/// values sell above the mid.
+///
+/// # Example
+///
+/// ```
+/// let close_offset = get_close_offset();
+/// println!("Close offset: {}", close_offset);
+/// ```
+pub(crate) fn get_close_offset() -> f64 {
+    match get_dotenv_variable("CLOSE_OFFSET") {
+        Ok(val) => match val.parse::<f64>() {
+            Ok(val) if val.is_finite() => val,
+            _ => {
+                println!("Not a valid Close Offset, setting to 0.0");
+                0.0
+            }
+        },
+        Err(_) => 0.0,
+    }
+}
+
+/// Gets how many days before their short leg expires held calendars are rolled automatically,
+/// based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<i64>` with the value of `AUTO_ROLL_DAYS`, or `None` if it is unset or invalid to
+/// only roll with the `roll` command.
+///
+/// # Example
+///
+/// ```
+/// if let Some(auto_roll_days) = get_auto_roll_days() {
+///     println!("Rolling calendars {} days before their short leg expires", auto_roll_days);
+/// }
+/// ```
+pub(crate) fn get_auto_roll_days() -> Option<i64> {
+    match get_dotenv_variable("AUTO_ROLL_DAYS") {
+        Ok(val) => match val.parse::<i64>() {
+            Ok(parsed_val) if parsed_val >= 0 => Some(parsed_val),
+            _ => {
+                println!("Not a valid number of days, not rolling calendars automatically");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Gets the address the scan webhook listens on based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<String>` with the `host:port` in `SCAN_WEBHOOK_ADDR`, or `None` to not listen.
+///
+/// # Example
+///
+/// ```
+/// if let Some(webhook_addr) = get_scan_webhook_addr() {
+///     println!("Listening for scan requests on {}", webhook_addr);
+/// }
+/// ```
+pub(crate) fn get_scan_webhook_addr() -> Option<String> {
+    get_dotenv_variable("SCAN_WEBHOOK_ADDR").ok()
+}
+
+/// Gets the bearer token scan webhook callers must send based on the `.env` file.
+///
+/// # Returns
+///
+/// An `Option<String>` with `SCAN_WEBHOOK_TOKEN`, or `None` to accept any caller.
+///
+/// # Example
+///
+/// ```
+/// let webhook_token = get_scan_webhook_token();
+/// ```
+pub(crate) fn get_scan_webhook_token() -> Option<String> {
+    get_dotenv_variable("SCAN_WEBHOOK_TOKEN").ok()
+}
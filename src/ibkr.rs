@@ -1,72 +1,214 @@
-use chrono::{Datelike, Local};
-use ordered_float::OrderedFloat;
-use reqwest::{
-    blocking::{Client, ClientBuilder, Response},
-    header::CONTENT_TYPE,
-};
-use serde_json::Value;
+use chrono::{Local, NaiveDate, Utc};
+use rustc_hash::FxHashMap;
 use std::{
-    collections::HashMap,
+    collections::BTreeSet,
     error::Error,
-    io::{self, ErrorKind},
+    mem,
     process::exit,
-    sync::{Arc, Mutex},
     thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    helpers::{calc_rank_value, calc_time_difference, calendar_spread_risk_free_profit},
-    logging::{log_error, log_message},
-    orders::build_request_data,
+    backtest::Snapshot,
+    chain::{ExpiryId, OptionChain, Quotes, Right, StrikeLadder},
+    control::ScanFilter,
+    dedup::{FillCooldown, RecentContenders},
+    dividends::{DividendCalendar, EarlyExerciseScreen},
+    events::{emit, Event},
+    expirations::{ExpirationCalendar, ExpirySelector},
+    fast_market::MarketSample,
+    fill_model::FillModel,
+    helpers::{format_strike, minutes_to_market_close},
+    journal::Journal,
+    latency::StageTimings,
+    logging::{log_contenders, log_error, log_message, log_quotes},
+    monitor::WorkingOrders,
+    orders::{worst_case_loss, LiquiditySizing, OrderLadder, OrderSizing},
+    paper::PaperBroker,
+    portfolio::PortfolioCache,
+    positions::PositionWatch,
+    quality::score_expiries,
+    rates::{RateClient, RateCurve},
+    redact::register_account,
+    scan_summary::ScanSummary,
+    scanner::{OptionType, Scanner},
+    schedule::TradingSession,
+    selection::{diversify, set_correlation},
+    state::{load_state, save_state, BotState},
     structs::{
-        AccountResponse, Confirmation, Contender, Contract, MarketDataResponse, Opt,
-        PortfolioResponse, RequestDataStruct, SecDefInfoResponse, SecDefResponse,
+        AlgoConfig, BoxFinancing, ClockSkewConfig, Contender, ExpiryFilter, HttpConfig, LegPricing,
+        Pnl, PortfolioCacheConfig, RateConfig, RequoteConfig, ShadowConfig, StrategyConfig,
     },
+    underlying::{ExchangeRouting, UnderlyingSpec},
+    watchlist::watchlist,
 };
 
-/// Enum representing option types for different strategies.
-enum OptionType {
-    Calendar,
-    Butterfly,
-    BoxSpread,
-    All,
-}
+pub(crate) mod client;
+pub(crate) mod combos;
+pub(crate) mod compat;
+pub(crate) mod endpoints;
+pub(crate) mod market_data;
+pub(crate) mod models;
+pub(crate) mod preflight;
+pub(crate) mod rejection;
+pub(crate) mod trading;
+
+use client::{connect_gateway, GatewayClient};
+use endpoints::fetch_portfolio_value;
+use models::{AccountResponse, ContractRulesResponse, MarketDataResponse, PnlResponse};
+use preflight::{
+    check_clock_skew, check_market_data, check_trading_permissions, MarketDataAvailability,
+};
 
-impl OptionType {
-    /// Converts a string to an `OptionType`.
-    ///
-    /// # Arguments
-    ///
-    /// * `s` - A string representing the option type.
-    ///
-    /// # Returns
-    ///
-    /// An `Option<Self>` representing the converted option type.
-    fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "1" => Some(OptionType::Calendar),
-            "2" => Some(OptionType::Butterfly),
-            "3" => Some(OptionType::BoxSpread),
-            _ => Some(OptionType::All),
-        }
-    }
+/// How many times the preflight check requests a snapshot before giving up on the market data
+/// availability, a second apart.
+const PREFLIGHT_ATTEMPTS: u32 = 5;
+
+/// The configuration `IBKR::init` connects and sets the bot up with, read from the `.env` file.
+pub(crate) struct IbkrConfig {
+    /// The stock ticker to trade.
+    pub(crate) ticker: String,
+    /// The discount value applied to orders.
+    pub(crate) discount_value: f64,
+    /// Arbitrage value threshold.
+    pub(crate) arb_val: f64,
+    /// Configuration for the butterfly scanner, including wing widths.
+    pub(crate) butterfly_config: StrategyConfig,
+    /// Configuration for the box spread scanner, including wing widths.
+    pub(crate) boxspread_config: StrategyConfig,
+    /// Configuration for the calendar scanner.
+    pub(crate) calendar_config: StrategyConfig,
+    /// Filter for same-day (0DTE) expirations.
+    pub(crate) expiry_filter: ExpiryFilter,
+    /// How long submitted contenders are skipped for.
+    pub(crate) dedup_seconds: u64,
+    /// How long contenders sharing a leg with a fill are skipped for.
+    pub(crate) fill_cooldown_seconds: u64,
+    /// TTL and change threshold for the cached portfolio value.
+    pub(crate) portfolio_cache_config: PortfolioCacheConfig,
+    /// Maintenance margin ceiling in percent of net liquidation, if any.
+    pub(crate) max_margin_usage: Option<f64>,
+    /// Whether contenders are ranked by edge per dollar of margin when not all of them fit within
+    /// the margin ceiling.
+    pub(crate) margin_efficient: bool,
+    /// The largest worst-case loss of a single trade in dollars, if any.
+    pub(crate) max_loss: Option<f64>,
+    /// The fill model used to estimate fill probabilities.
+    pub(crate) fill_model: FillModel,
+    /// Whether each scan's quotes are recorded for backtesting.
+    pub(crate) record_quotes: bool,
+    /// Whether orders are priced from the combo's own NBBO.
+    pub(crate) combo_quotes: bool,
+    /// Whether each scan's full contender list is exported to CSV.
+    pub(crate) export_contenders: bool,
+    /// How far below the arb threshold contenders are logged as near misses.
+    pub(crate) near_miss_epsilon: Option<f64>,
+    /// Which price of the bought and sold legs' quotes the arb value uses.
+    pub(crate) leg_pricing: LegPricing,
+    /// The quote quality score below which an expiration is skipped, if any.
+    pub(crate) min_quote_quality: Option<f64>,
+    /// The rates and cap of boxes sold to borrow, if box financing is on.
+    pub(crate) box_financing: Option<BoxFinancing>,
+    /// How far a leg's implied volatility may stray from its neighbors', if checked.
+    pub(crate) max_iv_deviation: Option<f64>,
+    /// The highest correlation allowed between two selected contenders, if any.
+    pub(crate) max_correlation: Option<f64>,
+    /// Thresholds for repricing or canceling working orders as their legs move, if any.
+    pub(crate) requote: Option<RequoteConfig>,
+    /// The early-exercise screen for American-style options, if any.
+    pub(crate) early_exercise: Option<EarlyExerciseScreen>,
+    /// The FRED API key and fallback risk-free rate.
+    pub(crate) rate_config: RateConfig,
+    /// The dividends of the underlying from the dividend file, merged with the next dividend from
+    /// the market data.
+    pub(crate) dividends: DividendCalendar,
+    /// How order quantity is matched to the displayed size of the legs and laddered.
+    pub(crate) sizing: OrderSizing,
+    /// Whether orders use the adaptive router, and its priority and parameters.
+    pub(crate) algo: AlgoConfig,
+    /// The listing exchange orders are routed to, by underlying.
+    pub(crate) routing: ExchangeRouting,
+    /// The exchange rules and holidays the listed expirations are checked against.
+    pub(crate) expiration_calendar: ExpirationCalendar,
+    /// How long each order works before it is canceled.
+    pub(crate) order_ttl_seconds: u64,
+    /// The proxy, CA bundle and certificate verification of the gateway connections.
+    pub(crate) http_config: HttpConfig,
+    /// The account to trade when the gateway has several, or `None` for the first.
+    pub(crate) account_id: Option<String>,
+    /// The gateway and paper account orders are mirrored to in shadow mode, if any.
+    pub(crate) shadow: Option<ShadowConfig>,
+    /// Whether to check the trading permissions and market data subscriptions.
+    pub(crate) preflight: bool,
+    /// How far the local clock may drift from the gateway's.
+    pub(crate) clock_skew: ClockSkewConfig,
+    /// The domain for IBKR API.
+    pub(crate) domain: String,
+    /// The port for IBKR API.
+    pub(crate) port: String,
+    /// Which expirations to scan, evaluated against the exchange calendar.
+    pub(crate) expiry_selectors: Vec<ExpirySelector>,
 }
 
 /// Struct representing the IBKR client, including configuration and internal state.
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) struct IBKR {
     ticker: Option<String>,
     discount_value: Option<f64>,
-    arb_val: Option<f64>,
-    strike_dif_value: Option<f64>,
-    base_url: Option<String>,
-    live_orders: Option<Vec<String>>,
-    client: Option<Client>,
+    scanner: Option<Scanner>,
+    fill_model: Option<FillModel>,
+    record_quotes: Option<bool>,
+    combo_quotes: bool,
+    min_quote_quality: Option<f64>,
+    /// The quotes of the previous scan, kept to measure staleness when scoring quote quality.
+    previous_quotes: Option<Quotes>,
+    /// Whether the last scan's snapshots, or the preflight check before the first scan, came with
+    /// delayed rather than real-time market data.
+    delayed_data: bool,
+    /// The underlying price and spreads of the last scan, until the run loop takes them.
+    market_sample: Option<MarketSample>,
+    /// The statistics of the last scan, until the run loop takes them.
+    scan_summary: Option<ScanSummary>,
+    recent_contenders: Option<RecentContenders>,
+    /// The strategies the account isn't permitted to trade, learned from order rejections.
+    skipped_strategies: BTreeSet<String>,
+    fill_cooldown: Option<FillCooldown>,
+    portfolio_cache: Option<PortfolioCache>,
+    max_margin_usage: Option<f64>,
+    margin_efficient: bool,
+    max_loss: Option<f64>,
+    max_correlation: Option<f64>,
+    requote: Option<RequoteConfig>,
+    journal: Option<Journal>,
+    paper: Option<PaperBroker>,
+    live_orders: Option<WorkingOrders>,
+    order_ttl: Option<Duration>,
+    /// The positions of the legs of held combos, to spot assignments, exercises and expirations.
+    position_watch: PositionWatch,
+    /// How often the positions are checked, and when they are checked next.
+    assignment_checks: Option<(Duration, Instant)>,
+    /// How many days before their short leg expires held calendars are rolled, and how far below
+    /// the mid the rolls are sold.
+    auto_roll: Option<(i64, f64)>,
+    /// The calendars a roll was submitted for, and when, so each is rolled at most once a day.
+    rolls_submitted: FxHashMap<String, NaiveDate>,
+    client: Option<GatewayClient>,
+    data_client: Option<GatewayClient>,
     account_id: Option<String>,
     ticker_id: Option<String>,
     conids_strings: Option<Vec<String>>,
-    dates_slice: Option<Vec<String>>,
-    strike_slice: Option<HashMap<String, HashMap<String, Vec<f64>>>>,
-    conids_map: Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    chain: Option<OptionChain>,
+    rate_client: Option<RateClient>,
+    underlying: UnderlyingSpec,
+    sizing: OrderSizing,
+    algo: AlgoConfig,
+    routing: ExchangeRouting,
+    /// The exchange rules and holidays the listed expirations are checked against.
+    expiration_calendar: ExpirationCalendar,
+    session: TradingSession,
+    /// Which expirations the chain holds, besides those in the strategy DTE windows.
+    expiry_selectors: Vec<ExpirySelector>,
 }
 
 impl IBKR {
@@ -79,17 +221,49 @@ impl IBKR {
         IBKR {
             ticker: None,
             discount_value: None,
-            arb_val: None,
-            strike_dif_value: None,
-            base_url: None,
-            live_orders: None,
+            scanner: None,
+            fill_model: None,
+            record_quotes: None,
+            combo_quotes: false,
+            min_quote_quality: None,
+            previous_quotes: None,
+            delayed_data: false,
+            market_sample: None,
+            scan_summary: None,
+            recent_contenders: None,
+            skipped_strategies: BTreeSet::new(),
+            fill_cooldown: None,
+            portfolio_cache: None,
+            max_margin_usage: None,
+            margin_efficient: false,
+            max_loss: None,
+            max_correlation: None,
+            requote: None,
+            journal: None,
+            paper: None,
+            live_orders: Some(WorkingOrders::new()),
+            order_ttl: None,
+            position_watch: PositionWatch::default(),
+            assignment_checks: None,
+            auto_roll: None,
+            rolls_submitted: FxHashMap::default(),
             client: None,
+            data_client: None,
             account_id: None,
             ticker_id: None,
             conids_strings: None,
-            dates_slice: None,
-            strike_slice: None,
-            conids_map: None,
+            chain: None,
+            rate_client: None,
+            underlying: UnderlyingSpec::for_ticker("SPX"),
+            sizing: OrderSizing {
+                liquidity: LiquiditySizing::Cap,
+                ladder: OrderLadder::default(),
+            },
+            algo: AlgoConfig::default(),
+            routing: ExchangeRouting::default(),
+            expiration_calendar: ExpirationCalendar::default(),
+            session: TradingSession::Regular,
+            expiry_selectors: Vec::new(),
         }
     }
 
@@ -97,65 +271,140 @@ impl IBKR {
     ///
     /// # Arguments
     ///
-    /// * `ticker` - The stock ticker to trade.
-    /// * `discount_value` - The discount value applied to orders.
-    /// * `arb_val` - Arbitrage value threshold.
-    /// * `strike_dif_value` - Difference in strike price for specific strategies.
-    /// * `domain` - The domain for IBKR API.
-    /// * `port` - The port for IBKR API.
-    /// * `num_days` - Number of days to consider for option expiry.
-    /// * `num_days_offset` - Number of days to offset from current day.
+    /// * `config` - The ticker, thresholds, strategies and gateway connection to trade with.
     ///
     /// # Returns
     ///
     /// A `Result` indicating whether the initialization succeeded or failed.
-    pub(crate) fn init(
-        &mut self,
-        ticker: String,
-        discount_value: f64,
-        arb_val: f64,
-        strike_dif_value: f64,
-        domain: String,
-        port: String,
-        num_days: i64,
-        num_days_offset: i64,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut current_month: String = String::new();
-        let mut next_month: String = String::new();
-
+    pub(crate) fn init(&mut self, config: IbkrConfig) -> Result<(), Box<dyn Error>> {
+        let IbkrConfig {
+            ticker,
+            discount_value,
+            arb_val,
+            mut butterfly_config,
+            mut boxspread_config,
+            mut calendar_config,
+            expiry_filter,
+            dedup_seconds,
+            fill_cooldown_seconds,
+            portfolio_cache_config,
+            max_margin_usage,
+            margin_efficient,
+            max_loss,
+            fill_model,
+            record_quotes,
+            combo_quotes,
+            export_contenders,
+            near_miss_epsilon,
+            leg_pricing,
+            min_quote_quality,
+            box_financing,
+            max_iv_deviation,
+            max_correlation,
+            requote,
+            early_exercise,
+            rate_config,
+            dividends,
+            sizing,
+            algo,
+            routing,
+            expiration_calendar,
+            order_ttl_seconds,
+            http_config,
+            account_id,
+            shadow,
+            preflight,
+            clock_skew,
+            domain,
+            port,
+            expiry_selectors,
+        } = config;
+        let mut months: Vec<String> = Vec::new();
+
+        self.underlying = UnderlyingSpec::for_ticker(&ticker).routed(&routing);
         self.ticker = Some(ticker);
+        self.sizing = sizing;
+        self.algo = algo;
+        self.routing = routing;
+        self.expiration_calendar = expiration_calendar;
+        self.expiry_selectors = expiry_selectors;
         self.discount_value = Some(discount_value);
-        self.arb_val = Some(arb_val);
-        self.strike_dif_value = Some(strike_dif_value);
-        self.base_url = Some(format!("https://{}:{}", domain, port));
-        self.live_orders = Some(Vec::new());
-        self.client = Some(
-            ClientBuilder::new()
-                .danger_accept_invalid_certs(true)
-                .build()
-                .unwrap(),
-        );
-        match self.get_account_id() {
-            Ok(account_id) => {
-                self.account_id = Some(account_id);
-            }
+        let dte_windows: Vec<(i64, i64)> = [&butterfly_config, &boxspread_config, &calendar_config]
+            .iter()
+            .filter_map(|config| config.dte_window)
+            .collect();
+        self.recent_contenders = Some(RecentContenders::new(Duration::from_secs(dedup_seconds)));
+        self.fill_cooldown = Some(FillCooldown::new(Duration::from_secs(
+            fill_cooldown_seconds,
+        )));
+        self.portfolio_cache = Some(PortfolioCache::new(
+            Duration::from_secs(portfolio_cache_config.ttl_seconds),
+            portfolio_cache_config.change_percent,
+        ));
+        self.max_margin_usage = max_margin_usage;
+        self.margin_efficient = margin_efficient;
+        self.max_loss = max_loss;
+        self.max_correlation = max_correlation;
+        self.requote = requote;
+        self.journal = Some(Journal::new());
+        self.fill_model = Some(fill_model);
+        self.record_quotes = Some(record_quotes);
+        self.combo_quotes = combo_quotes;
+        self.min_quote_quality = min_quote_quality;
+        // In shadow mode market data comes from the live gateway and everything account-scoped,
+        // orders included, goes to the paper gateway.
+        self.data_client = Some(connect_gateway(&domain, &port, &http_config)?);
+        self.client = match &shadow {
+            Some(shadow) => Some(connect_gateway(&shadow.domain, &shadow.port, &http_config)?),
+            None => self.data_client.clone(),
+        };
+        self.check_clock(&clock_skew)?;
+        self.order_ttl = Some(Duration::from_secs(order_ttl_seconds));
+        match self.get_account_id(account_id.as_deref()) {
+            Ok(account_id) => self.set_account_id(&account_id),
             Err(e) => log_error(format!("Failed to get account ID: {}", e)),
         }
+        if let Some(shadow) = &shadow {
+            if let Some(account_id) = &shadow.account_id {
+                self.set_account_id(account_id);
+            }
+            let account_id: &str = self.account_id.as_deref().unwrap_or_default();
+            if !ShadowConfig::is_paper_account(account_id) {
+                log_error(format!(
+                    "Shadow mode only trades paper accounts, refusing to send orders to {}.",
+                    account_id
+                ));
+            }
+            log_message(format!(
+                "Shadow mode: scanning live data and mirroring orders to paper account {}.",
+                account_id
+            ));
+        }
+        if let Some(account_id) = &self.account_id {
+            register_account(account_id);
+        }
         match self.get_ticker_conid() {
-            Ok((ticker_id, month1, month2)) => {
+            Ok((ticker_id, option_months)) => {
                 self.ticker_id = Some(ticker_id);
-                current_month = month1;
-                next_month = month2;
+                months = option_months;
             }
             Err(e) => log_error(format!("Failed to get ticker ID: {}", e)),
         }
 
-        match self.get_conids_map(num_days, num_days_offset, current_month, next_month) {
-            Ok((conids_strings, dates_slice, strike_slice, conids_map)) => {
-                self.conids_strings = Some(conids_strings);
-                self.dates_slice = Some(dates_slice);
-                self.strike_slice = Some(strike_slice);
-                self.conids_map = Some(conids_map);
+        match self.get_conids_map(&months, &dte_windows, expiry_filter.exclude_same_day) {
+            Ok((chain, default_window)) => {
+                for (name, config) in [
+                    ("Butterfly", &mut butterfly_config),
+                    ("Boxspread", &mut boxspread_config),
+                    ("Calendar", &mut calendar_config),
+                ] {
+                    let (min_dte, max_dte) = *config.dte_window.get_or_insert(default_window);
+                    log_message(format!(
+                        "{} scanner targets {}-{} days to expiry.",
+                        name, min_dte, max_dte
+                    ));
+                }
+                self.set_chain(chain);
             }
             Err(e) => {
                 log_error(format!("Failed to init conid map: {}", e));
@@ -163,1192 +412,859 @@ impl IBKR {
             }
         }
 
+        self.scanner = Some(Scanner {
+            arb_val,
+            butterfly_config,
+            boxspread_config,
+            calendar_config,
+            expiry_filter,
+            include_below_threshold: export_contenders,
+            near_miss_epsilon,
+            early_exercise,
+            dividends,
+            rates: RateCurve::flat(rate_config.fallback_rate),
+            price_scale: self.underlying.price_scale,
+            leg_pricing,
+            skipped_expiries: Vec::new(),
+            box_financing,
+            max_iv_deviation,
+        });
+        self.rate_client = RateClient::new(&rate_config);
+        self.refresh_rates();
+
         self.init_ticker_data()?;
+        if preflight {
+            self.preflight()?;
+        }
+        self.add_upcoming_dividend();
 
         Ok(())
     }
 
-    /// Retrieves contender contracts based on the specified option type.
+    /// Switches the scan and order pipeline to another underlying, discovering its conid and option
+    /// chain and applying its tick rules. The scanner keeps its DTE windows.
     ///
     /// # Arguments
     ///
-    /// * `option` - The type of option strategy.
-    /// * `num_orders` - The number of contender contracts to retrieve.
+    /// * `ticker` - The ticker to switch to, e.g. `"XSP"`.
     ///
     /// # Returns
     ///
-    /// A `Result` containing a vector of contender contracts or an error.
-    pub(crate) fn get_contender_contracts(
-        &self,
-        option: &str,
-        num_orders: i32,
-    ) -> Result<Vec<Contender>, Box<dyn Error>> {
-        let contracts_map: HashMap<String, Opt> = self.get_ticker_data()?;
-        let mut contender_contracts_total: Vec<Contender> = Vec::new();
-
-        let dates_slice: &Vec<String> =
-            self.dates_slice.as_ref().ok_or("dates slice is not set")?;
-        let strike_slice: &HashMap<String, HashMap<String, Vec<f64>>> = self
-            .strike_slice
-            .as_ref()
-            .ok_or("strike slice is not set")?;
-        let conids_map: &HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>> =
-            self.conids_map.as_ref().ok_or("conids map is not set")?;
-
-        match OptionType::from_str(option).ok_or("Invalid option type")? {
-            OptionType::Calendar => {
-                contender_contracts_total.extend(self.get_calendar_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-            }
-            OptionType::Butterfly => {
-                contender_contracts_total.extend(self.get_butterfly_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
+    /// A `Result` indicating whether the switch succeeded. On failure the previous underlying
+    /// stays in use.
+    pub(crate) fn switch_underlying(&mut self, ticker: &str) -> Result<(), Box<dyn Error>> {
+        let previous_ticker: Option<String> = self.ticker.replace(ticker.to_string());
+        let (ticker_id, months) = match self.get_ticker_conid() {
+            Ok(found) => found,
+            Err(e) => {
+                self.ticker = previous_ticker;
+                return Err(e);
             }
-            OptionType::BoxSpread => {
-                contender_contracts_total.extend(self.get_boxspread_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
+        };
+        let previous_ticker_id: Option<String> = self.ticker_id.replace(ticker_id);
+
+        let Some(scanner) = self.scanner.as_ref() else {
+            self.ticker = previous_ticker;
+            self.ticker_id = previous_ticker_id;
+            return Err("scanner is not set".into());
+        };
+        let dte_windows: Vec<(i64, i64)> = [
+            &scanner.butterfly_config,
+            &scanner.boxspread_config,
+            &scanner.calendar_config,
+        ]
+        .iter()
+        .filter_map(|config| config.dte_window)
+        .collect();
+        let chain: OptionChain = match self.get_conids_map(
+            &months,
+            &dte_windows,
+            scanner.expiry_filter.exclude_same_day,
+        ) {
+            Ok((chain, _)) => chain,
+            Err(e) => {
+                self.ticker = previous_ticker;
+                self.ticker_id = previous_ticker_id;
+                return Err(e);
             }
-            OptionType::All => {
-                contender_contracts_total.extend(self.get_calendar_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-                contender_contracts_total.extend(self.get_butterfly_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
-                contender_contracts_total.extend(self.get_boxspread_contenders(
-                    &contracts_map,
-                    dates_slice,
-                    strike_slice,
-                    conids_map,
-                )?);
+        };
+
+        let previous_chain: Option<OptionChain> = self.chain.take();
+        let previous_conids: Option<Vec<String>> = self.conids_strings.take();
+        self.set_chain(chain);
+        let previous_underlying: UnderlyingSpec = mem::replace(
+            &mut self.underlying,
+            UnderlyingSpec::for_ticker(ticker).routed(&self.routing),
+        );
+        let price_scale: f64 = self.underlying.price_scale;
+        let previous_dividends: Option<DividendCalendar> = self.scanner.as_mut().map(|scanner| {
+            scanner.price_scale = price_scale;
+            mem::replace(&mut scanner.dividends, DividendCalendar::new())
+        });
+        if let Err(e) = self.init_ticker_data() {
+            self.ticker = previous_ticker;
+            self.ticker_id = previous_ticker_id;
+            self.chain = previous_chain;
+            self.conids_strings = previous_conids;
+            self.underlying = previous_underlying;
+            if let (Some(scanner), Some(dividends)) = (self.scanner.as_mut(), previous_dividends) {
+                scanner.price_scale = self.underlying.price_scale;
+                scanner.dividends = dividends;
             }
+            return Err(e);
         }
+        self.add_upcoming_dividend();
+        log_message(format!("Switched to {}.", self.underlying.ticker));
 
-        contender_contracts_total.sort_by(|a, b| b.rank_value.partial_cmp(&a.rank_value).unwrap());
-
-        let num_orders_usize: usize = num_orders as usize;
-        if contender_contracts_total.len() > num_orders_usize {
-            contender_contracts_total.truncate(num_orders_usize);
-        }
+        Ok(())
+    }
 
-        Ok(contender_contracts_total)
+    /// Returns the conventions of the underlying the bot trades.
+    pub(crate) fn underlying(&self) -> &UnderlyingSpec {
+        &self.underlying
     }
 
-    /// Retrieves ticker data from the IBKR API and parses it into a map of options.
+    /// Logs the strikes of each expiration and installs the chain and its conid batches.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A `Result` containing a map of options or an error.
-    fn get_ticker_data(&self) -> Result<HashMap<String, Opt>, Box<dyn Error>> {
-        let mut contracts_map: HashMap<String, Opt> = HashMap::new();
-        let chain_url: String = format!(
-            "{}/v1/api/iserver/marketdata/snapshot",
-            self.base_url.as_ref().unwrap()
-        );
-        let conids_arr: &Vec<String> = self.conids_strings.as_ref().unwrap();
-
-        let client: Arc<Client> = Arc::new(
-            self.client
-                .as_ref()
-                .ok_or("Client is not initialized")?
-                .clone(),
-        );
-        let chain_url: Arc<String> = Arc::new(chain_url);
-        let response_arr: Arc<Mutex<Vec<Response>>> = Arc::new(Mutex::new(Vec::new()));
-
-        let mut handles: Vec<thread::JoinHandle<()>> = Vec::new();
-
-        for conid in conids_arr {
-            let client: Arc<Client> = Arc::clone(&client);
-            let chain_url: Arc<String> = Arc::clone(&chain_url);
-            let response_arr: Arc<Mutex<Vec<Response>>> = Arc::clone(&response_arr);
-            let conid: String = conid.clone();
-
-            let handle: thread::JoinHandle<()> = thread::spawn(move || {
-                let params: [(&str, &str); 2] = [("conids", &conid), ("fields", "84,85,86")];
-
-                match client
-                    .get(chain_url.as_ref())
-                    .header("Connection", "keep-alive")
-                    .header("User-Agent", "trading_bot_rust/1.0")
-                    .query(&params)
-                    .send()
-                {
-                    Ok(response) => {
-                        if response.status().is_success() {
-                            let mut response_arr: std::sync::MutexGuard<'_, Vec<Response>> =
-                                response_arr.lock().unwrap();
-                            response_arr.push(response);
-                        } else {
-                            log_error(format!(
-                                "{}\nBody: {:?}",
-                                response.status(),
-                                response.text().unwrap_or_else(|_| "".to_string())
-                            ));
-                        }
-                    }
-                    Err(e) => log_error(format!("Failed to get ticker data: {}", e)),
-                }
-            });
-
-            handles.push(handle);
-        }
-
-        for handle in handles {
-            handle.join().unwrap();
-        }
-
-        let mut response_vec: std::sync::MutexGuard<'_, Vec<Response>> =
-            response_arr.lock().unwrap();
-
-        for response in response_vec.drain(..) {
-            let generic_responses: Vec<MarketDataResponse> = response.json()?;
-
-            for response in &generic_responses {
-                if let Some(field_84_value) = &response.field_84 {
-                    if let Some(field_85_value) = &response.field_85 {
-                        if let Some(field_86_value) = &response.field_86 {
-                            if field_84_value != "" && field_85_value != "" && field_86_value != ""
-                            {
-                                let conid: &String = &response.conid_ex;
-                                let bid_val: f64 = field_84_value
-                                    .replace(",", "")
-                                    .parse::<f64>()
-                                    .map_err(|_| "Failed to parse bid")?;
-                                let ask_val: f64 = field_86_value
-                                    .replace(",", "")
-                                    .parse::<f64>()
-                                    .map_err(|_| "Failed to parse ask")?;
-                                let asz_val: f64 = field_85_value
-                                    .replace(",", "")
-                                    .parse::<f64>()
-                                    .map_err(|_| "Failed to parse asz")?;
-
-                                let mkt_val: f64 =
-                                    ((bid_val + ask_val) / 2.0 * 100.0).round() / 100.0;
-
-                                contracts_map.insert(
-                                    conid.to_string(),
-                                    Opt {
-                                        asz: asz_val,
-                                        mkt: mkt_val,
-                                        bid: bid_val,
-                                    },
-                                );
-                            } else {
-                                let conid: &String = &response.conid_ex;
-                                contracts_map.insert(
-                                    conid.to_string(),
-                                    Opt {
-                                        asz: 0.0,
-                                        mkt: 0.0,
-                                        bid: 0.0,
-                                    },
-                                );
-                            }
-                        } else {
-                            let conid: &String = &response.conid_ex;
-                            contracts_map.insert(
-                                conid.to_string(),
-                                Opt {
-                                    asz: 0.0,
-                                    mkt: 0.0,
-                                    bid: 0.0,
-                                },
-                            );
-                        }
-                    } else {
-                        let conid: &String = &response.conid_ex;
-                        contracts_map.insert(
-                            conid.to_string(),
-                            Opt {
-                                asz: 0.0,
-                                mkt: 0.0,
-                                bid: 0.0,
-                            },
-                        );
-                    }
-                } else {
-                    let conid: &String = &response.conid_ex;
-                    contracts_map.insert(
-                        conid.to_string(),
-                        Opt {
-                            asz: 0.0,
-                            mkt: 0.0,
-                            bid: 0.0,
+    /// * `chain` - The option chain of the underlying.
+    pub(crate) fn set_chain(&mut self, chain: OptionChain) {
+        for (date_index, date) in chain.dates().iter().enumerate() {
+            let ladder: &StrikeLadder = chain.ladder(date_index as ExpiryId, Right::Call);
+            log_message(format!(
+                "Loaded {} strikes for {} ({} point interval).",
+                ladder.len(),
+                date,
+                ladder
+                    .interval()
+                    .map_or("unknown".to_string(), format_strike)
+            ));
+            for right in Right::ALL {
+                let gaps: Vec<(f64, f64)> = chain.ladder(date_index as ExpiryId, right).gaps();
+                if !gaps.is_empty() {
+                    log_message(format!(
+                        "Missing {} strikes for {} between {}; spreads across them are skipped.",
+                        match right {
+                            Right::Call => "call",
+                            Right::Put => "put",
                         },
-                    );
+                        date,
+                        gaps.iter()
+                            .map(|(low, high)| format!(
+                                "{} and {}",
+                                format_strike(*low),
+                                format_strike(*high)
+                            ))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ));
                 }
             }
         }
-
-        return Ok(contracts_map);
+        self.conids_strings = Some(chain.conid_batches(300));
+        self.chain = Some(chain);
     }
 
-    /// Initializes ticker data by sending a request to the IBKR API.
+    /// Adds the next dividend of the underlying from the market data to the scanner's calendar. A
+    /// failed fetch is logged and the calendar kept as it is.
     ///
     /// # Returns
     ///
-    /// A `Result` indicating whether the initialization succeeded or failed.
-    fn init_ticker_data(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let chain_url: String = format!(
-            "{}/v1/api/iserver/marketdata/snapshot",
-            self.base_url.as_ref().unwrap()
-        );
-
-        let conids_arr: &Vec<String> = self.conids_strings.as_ref().unwrap();
-
-        for conid in conids_arr {
-            let params: [(&str, &str); 2] = [("conids", conid), ("fields", "84,85,86")];
-
-            let response: Response = self
-                .client
-                .as_ref()
-                .ok_or("Client is not initialized")?
-                .get(chain_url.clone())
-                .header("Connection", "keep-alive")
-                .header("User-Agent", "trading_bot_rust/1.0")
-                .query(&params)
-                .send()?;
-
-            if !response.status().is_success() {
-                log_error(format!(
-                    "{}\nBody: {:?}",
-                    response.status(),
-                    response.text()?
+    /// An `Option<(NaiveDate, f64)>` with the ex-date and amount of the dividend added, or `None`
+    /// if there is none or the fetch failed.
+    pub(crate) fn add_upcoming_dividend(&mut self) -> Option<(NaiveDate, f64)> {
+        match self.get_upcoming_dividend() {
+            Ok(Some((ex_date, amount))) => {
+                log_message(format!(
+                    "Next dividend of {:.2} goes ex on {}.",
+                    amount, ex_date
                 ));
-                exit(1);
+                if let Some(scanner) = self.scanner.as_mut() {
+                    scanner.dividends.add(ex_date, amount);
+                }
+                Some((ex_date, amount))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                log_message(format!("Failed to get upcoming dividend: {}.", e));
+                None
             }
         }
+    }
 
-        Ok(())
+    /// Refreshes the scanner's risk-free rate curve from FRED, at most once a day. Without a FRED
+    /// API key the fallback rate stays in use.
+    pub(crate) fn refresh_rates(&mut self) {
+        let (Some(rate_client), Some(scanner)) = (self.rate_client.as_mut(), self.scanner.as_mut())
+        else {
+            return;
+        };
+        if let Some(rates) = rate_client.refresh(Local::now().date_naive()) {
+            log_message(format!("Risk-free rate curve: {}.", rates.summary()));
+            scanner.rates = rates;
+        }
     }
 
-    /// Retrieves contender contracts for calendar spreads.
+    /// Scores the quotes of every expiration and has the scanner skip those scoring below the
+    /// minimum quote quality this scan.
     ///
     /// # Arguments
     ///
-    /// * `contracts_map` - A map of options.
-    /// * `dates_slice` - A slice of relevant dates for the options.
-    /// * `strike_slice` - A map of strike prices.
-    /// * `conids_map` - A map of conids for the contracts.
-    ///
-    /// # Returns
-    ///
-    /// A `Result` containing a vector of calendar contenders or an error.
-    pub(crate) fn get_calendar_contenders(
-        &self,
-        contracts_map: &HashMap<String, Opt>,
-        dates_slice: &Vec<String>,
-        strike_slice: &HashMap<String, HashMap<String, Vec<f64>>>,
-        conids_map: &HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>,
-    ) -> Result<Vec<Contender>, Box<dyn Error>> {
-        let arb_threshold: f64 = 0.0 + self.arb_val.as_ref().unwrap();
-        let mut contender_contracts: Vec<Contender> = Vec::new();
-        let now: chrono::DateTime<Local> = Local::now();
-        let current_date: String =
-            format!("{:02}{:02}{:02}", now.year() % 100, now.month(), now.day());
-
-        let date_for_mean: &String = &dates_slice[0];
-        let mut mean_val: f64 = 0.0;
-        if let Some(strike_data) = strike_slice.get(date_for_mean) {
-            let sum: f64 = strike_data["C"].iter().sum();
-            let count: f64 = strike_data["C"].len() as f64;
-            mean_val = sum / count;
-        }
-
-        for date_index in 0..(dates_slice.len() - 1) {
-            let date: &String = &dates_slice[date_index];
-
-            if let Some(strike_data) = strike_slice.get(date) {
-                for (contract_type, strikes) in strike_data.iter() {
-                    for current_strike in strikes {
-                        let current_contract_conid: &String = conids_map
-                            .get(date)
-                            .and_then(|ct| ct.get(contract_type))
-                            .and_then(|ct| ct.get(current_strike.into()))
-                            .ok_or("Error accessing current conid")?;
-                        let current_opt: &Opt = contracts_map
-                            .get(current_contract_conid)
-                            .ok_or("Error accessing current contract")?;
-
-                        let next_date: &String = &dates_slice[date_index + 1];
-                        let next_contract_conid: Option<&String> = conids_map
-                            .get(next_date)
-                            .and_then(|ct| ct.get(contract_type))
-                            .and_then(|ct| ct.get(current_strike.into()));
-
-                        if let Some(next_contract_conid) = next_contract_conid {
-                            let next_opt: &Opt = contracts_map
-                                .get(next_contract_conid)
-                                .ok_or("Error accessing next contract")?;
-
-                            let arb_val: f64 = current_opt.mkt - next_opt.mkt;
-
-                            if arb_val >= arb_threshold
-                                && current_opt.bid > 1.0
-                                && next_opt.bid > 1.0
-                                && current_opt.asz > 0.0
-                                && next_opt.asz > 0.0
-                                && calc_time_difference(date, next_date) == 1
-                                && calendar_spread_risk_free_profit(current_strike, arb_val) > 0.25
-                                && (current_strike - mean_val).abs() <= 500.0
-                            {
-                                let avg_ask: f64 = ((current_opt.asz + next_opt.asz) / 2.0).round();
-                                let rank_value: f64 =
-                                    calc_rank_value(avg_ask, arb_val, &current_date, date);
-
-                                contender_contracts.push(Contender {
-                                    arb_val: (arb_val * 100.0).round() / 100.0,
-                                    avg_ask,
-                                    type_spread: "Calendar".to_string(),
-                                    exp_date: date.clone(),
-                                    rank_value,
-                                    contracts: vec![
-                                        Contract {
-                                            strike: *current_strike,
-                                            mkt_price: current_opt.mkt,
-                                            date: date.clone(),
-                                            type_contract: contract_type.clone(),
-                                        },
-                                        Contract {
-                                            strike: *current_strike,
-                                            mkt_price: next_opt.mkt,
-                                            date: next_date.clone(),
-                                            type_contract: contract_type.clone(),
-                                        },
-                                    ],
-                                });
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(contender_contracts)
+    /// * `contracts_map` - The quotes of this scan.
+    fn skip_low_quality_expiries(&mut self, contracts_map: &Quotes) {
+        let (Some(min_quote_quality), Some(chain), Some(scanner)) = (
+            self.min_quote_quality,
+            self.chain.as_ref(),
+            self.scanner.as_mut(),
+        ) else {
+            return;
+        };
+        scanner.skipped_expiries =
+            score_expiries(chain, contracts_map, self.previous_quotes.as_ref())
+                .into_iter()
+                .filter(|quality| quality.score() < min_quote_quality)
+                .map(|quality| {
+                    log_message(format!(
+                        "Skipping {} this scan: quote quality {:.2} ({}).",
+                        chain.dates()[quality.expiry as usize],
+                        quality.score(),
+                        quality.describe()
+                    ));
+                    quality.expiry
+                })
+                .collect();
+        self.previous_quotes = Some(contracts_map.clone());
     }
 
-    /// Retrieves contender contracts for butterfly spreads.
+    /// Retrieves contender contracts based on the specified option type.
     ///
     /// # Arguments
     ///
-    /// * `contracts_map` - A map of options.
-    /// * `dates_slice` - A slice of relevant dates for the options.
-    /// * `strike_slice` - A map of strike prices.
-    /// * `conids_map` - A map of conids for the contracts.
+    /// * `option` - The type of option strategy.
+    /// * `num_orders` - The number of contender contracts to retrieve.
+    /// * `num_fills` - The number of fills for each contract, for the max-loss cap.
+    /// * `scan_filter` - The strategy and expiry a manual scan is limited to, if any.
+    /// * `stage_timings` - Receives the time spent fetching the chain and calculating contenders.
     ///
     /// # Returns
     ///
-    /// A `Result` containing a vector of butterfly contenders or an error.
-    pub(crate) fn get_butterfly_contenders(
-        &self,
-        contracts_map: &HashMap<String, Opt>,
-        dates_slice: &Vec<String>,
-        strike_slice: &HashMap<String, HashMap<String, Vec<f64>>>,
-        conids_map: &HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>,
+    /// A `Result` containing a vector of contender contracts or an error.
+    pub(crate) fn get_contender_contracts(
+        &mut self,
+        option: &str,
+        num_orders: i32,
+        num_fills: i32,
+        scan_filter: Option<&ScanFilter>,
+        stage_timings: &mut StageTimings,
     ) -> Result<Vec<Contender>, Box<dyn Error>> {
-        let arb_threshold: f64 = 0.0 + self.arb_val.as_ref().unwrap();
-        let mut contender_contracts: Vec<Contender> = Vec::new();
-        let now: chrono::DateTime<Local> = Local::now();
-        let current_date: String =
-            format!("{:02}{:02}{:02}", now.year() % 100, now.month(), now.day());
-
-        for date in dates_slice {
-            if let Some(strike_data) = strike_slice.get(date) {
-                for &contract_type in &["C", "P"] {
-                    if let Some(contract_strikes) = strike_data.get(contract_type) {
-                        if contract_strikes.len() > 2 {
-                            for i in 1..(contract_strikes.len() - 1) {
-                                let current_strike: &f64 = &contract_strikes[i];
-                                let current_contract_conid: &String = conids_map
-                                    .get(date)
-                                    .and_then(|ct| ct.get(contract_type))
-                                    .and_then(|ct| ct.get(current_strike.into()))
-                                    .ok_or("Error accessing current conid")?;
-                                let current_contract: &Opt = contracts_map
-                                    .get(current_contract_conid)
-                                    .ok_or("Error accessing current contract")?;
-
-                                let left_strike: &f64 = &contract_strikes[i - 1];
-                                let left_contract_conid: &String = conids_map
-                                    .get(date)
-                                    .and_then(|ct| ct.get(contract_type))
-                                    .and_then(|ct| ct.get(left_strike.into()))
-                                    .ok_or("Error accessing left conid")?;
-                                let left_contract: &Opt = contracts_map
-                                    .get(left_contract_conid)
-                                    .ok_or("Error accessing left contract")?;
+        let fetch_start: Instant = Instant::now();
+        let contracts_map: Quotes = self.get_ticker_data()?;
+        stage_timings.finish("chain_fetch", fetch_start);
+        self.settle_paper_orders(&contracts_map);
+        let spot_start: Instant = Instant::now();
+        let spot: Option<f64> = self.fetch_spot();
+        stage_timings.finish("spot_fetch", spot_start);
+        self.market_sample = Some(MarketSample::capture(&contracts_map, spot));
+        self.skip_low_quality_expiries(&contracts_map);
+        let mut contender_contracts_total: Vec<Contender> = Vec::new();
 
-                                let right_strike: &f64 = &contract_strikes[i + 1];
-                                let right_contract_conid: &String = conids_map
-                                    .get(date)
-                                    .and_then(|ct| ct.get(contract_type))
-                                    .and_then(|ct| ct.get(right_strike.into()))
-                                    .ok_or("Error accessing right conid")?;
-                                let right_contract: &Opt = contracts_map
-                                    .get(right_contract_conid)
-                                    .ok_or("Error accessing right contract")?;
+        let chain: &OptionChain = self.chain.as_ref().ok_or("option chain is not set")?;
+        let scanner: &Scanner = self.scanner.as_ref().ok_or("scanner is not set")?;
+        let option_type: OptionType = OptionType::from_str(option).ok_or("Invalid option type")?;
+        let current_date: NaiveDate = Local::now().date_naive();
+        let minutes_to_close: i64 = minutes_to_market_close(Utc::now());
+        let scan_time: i64 = Utc::now().timestamp_millis();
+        let scan_start: Instant = Instant::now();
+        emit(&Event::ScanStart { scan_time });
+
+        if self.record_quotes == Some(true) {
+            let snapshot: Snapshot = Snapshot::capture(
+                chain,
+                &contracts_map,
+                scan_time,
+                current_date,
+                minutes_to_close,
+                spot,
+            );
+            log_quotes(&serde_json::to_string(&snapshot)?);
+        }
 
-                                let arb_val: f64 = (2.0 * current_contract.mkt)
-                                    - (left_contract.mkt + right_contract.mkt);
+        let calc_start: Instant = Instant::now();
+        contender_contracts_total.extend(scanner.scan(
+            &option_type,
+            &contracts_map,
+            chain,
+            current_date,
+            minutes_to_close,
+            spot,
+        )?);
+        // Watched structures the scan found on its own are only kept once.
+        for contender in scanner.price_watchlist(
+            &watchlist(),
+            &contracts_map,
+            chain,
+            current_date,
+            minutes_to_close,
+            spot,
+        ) {
+            if contender_contracts_total.iter().any(|scanned| {
+                scanned.type_spread == contender.type_spread
+                    && scanned.leg_keys() == contender.leg_keys()
+            }) {
+                continue;
+            }
+            if contender.above_threshold {
+                log_message(format!(
+                    "Watchlist {} {} shows edge: {} = {:.2}.",
+                    contender.type_spread,
+                    contender.exp_date,
+                    contender.reason.formula,
+                    contender.reason.checks[0].value
+                ));
+            }
+            contender_contracts_total.push(contender);
+        }
+        if !self.skipped_strategies.is_empty() {
+            contender_contracts_total
+                .retain(|contender| !self.skipped_strategies.contains(&contender.type_spread));
+        }
+        if let Some(scan_filter) = scan_filter.filter(|scan_filter| !scan_filter.is_empty()) {
+            log_message(format!("Scanning {} only.", scan_filter.describe()));
+            contender_contracts_total.retain(|contender| scan_filter.matches(contender));
+        }
 
-                                if arb_val >= arb_threshold
-                                    && left_contract.bid > 1.0
-                                    && right_contract.bid > 1.0
-                                    && current_contract.bid > 1.0
-                                    && left_contract.asz > 0.0
-                                    && right_contract.asz > 0.0
-                                    && current_contract.asz > 0.0
-                                    && ((current_strike - left_strike) * 10.0).round() / 10.0
-                                        == self.strike_dif_value.unwrap()
-                                    && ((right_strike - current_strike) * 10.0).round() / 10.0
-                                        == self.strike_dif_value.unwrap()
-                                {
-                                    let avg_ask: f64 = ((left_contract.asz
-                                        + right_contract.asz
-                                        + (2.0 * current_contract.asz))
-                                        / 4.0)
-                                        .round();
-                                    let rank_value: f64 =
-                                        calc_rank_value(avg_ask, arb_val, &current_date, date);
+        for contender in contender_contracts_total.iter_mut() {
+            contender.scan_time = scan_time;
+            if let Some(fill_model) = &self.fill_model {
+                contender.fill_probability = fill_model.fill_probability(contender.quoted_edge());
+            }
+        }
 
-                                    contender_contracts.push(Contender {
-                                        arb_val: (arb_val * 100.0).round() / 100.0,
-                                        avg_ask,
-                                        type_spread: "Butterfly".to_string(),
-                                        exp_date: date.clone(),
-                                        rank_value,
-                                        contracts: vec![
-                                            Contract {
-                                                strike: *left_strike,
-                                                mkt_price: left_contract.mkt,
-                                                date: date.clone(),
-                                                type_contract: contract_type.to_string(),
-                                            },
-                                            Contract {
-                                                strike: *current_strike,
-                                                mkt_price: current_contract.mkt,
-                                                date: date.clone(),
-                                                type_contract: contract_type.to_string(),
-                                            },
-                                            Contract {
-                                                strike: *right_strike,
-                                                mkt_price: right_contract.mkt,
-                                                date: date.clone(),
-                                                type_contract: contract_type.to_string(),
-                                            },
-                                        ],
-                                    });
-                                }
-                            }
-                        }
-                    }
+        contender_contracts_total.sort_by(|a, b| b.rank_value.partial_cmp(&a.rank_value).unwrap());
+        self.scan_summary = Some(ScanSummary::capture(
+            &contracts_map,
+            &contender_contracts_total,
+        ));
+
+        if scanner.include_below_threshold {
+            log_contenders(
+                &contender_contracts_total,
+                &self.underlying.ticker,
+                self.chain.as_ref(),
+            );
+        }
+        if let Some(near_miss_epsilon) = scanner.near_miss_epsilon {
+            for contender in &contender_contracts_total {
+                let Some(shortfall) = contender.reason.shortfall() else {
+                    continue;
+                };
+                if shortfall <= near_miss_epsilon {
+                    log_message(format!(
+                        "Near miss: {} {} {} missed the arb threshold by {:.2} ({}).",
+                        contender.type_spread,
+                        contender.exp_date,
+                        contender.reason.formula,
+                        shortfall,
+                        contender.reason.summary()
+                    ));
                 }
             }
         }
+        contender_contracts_total.retain(|contender| contender.above_threshold);
+
+        if let Some(recent_contenders) = &self.recent_contenders {
+            let now: Instant = Instant::now();
+            let num_contenders: usize = contender_contracts_total.len();
+            contender_contracts_total
+                .retain(|contender| !recent_contenders.contains(contender, now));
+            let num_skipped: usize = num_contenders - contender_contracts_total.len();
+            if num_skipped > 0 {
+                log_message(format!(
+                    "Skipping {} recently submitted contenders.",
+                    num_skipped
+                ));
+            }
+        }
 
-        Ok(contender_contracts)
-    }
-
-    /// Retrieves contender contracts for box spread strategies.
-    ///
-    /// # Arguments
-    ///
-    /// * `contracts_map` - A map of options.
-    /// * `dates_slice` - A slice of relevant dates for the options.
-    /// * `strike_slice` - A map of strike prices.
-    /// * `conids_map` - A map of conids for the contracts.
-    ///
-    /// # Returns
-    ///
-    /// A `Result` containing a vector of box spread contenders or an error.
-    pub(crate) fn get_boxspread_contenders(
-        &self,
-        contracts_map: &HashMap<String, Opt>,
-        dates_slice: &Vec<String>,
-        strike_slice: &HashMap<String, HashMap<String, Vec<f64>>>,
-        conids_map: &HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>,
-    ) -> Result<Vec<Contender>, Box<dyn Error>> {
-        let arb_threshold: f64 = -5.0 - self.arb_val.as_ref().unwrap();
-        let mut contender_contracts: Vec<Contender> = Vec::new();
-        let now: chrono::DateTime<Local> = Local::now();
-        let current_date: String =
-            format!("{:02}{:02}{:02}", now.year() % 100, now.month(), now.day());
-
-        for date in dates_slice {
-            if let Some(strike_data) = strike_slice.get(date) {
-                if let (Some(cs), Some(ps)) = (strike_data.get("C"), strike_data.get("P")) {
-                    if cs.len() > 1 && ps.len() > 1 {
-                        for i in 0..(cs.len() - 1) {
-                            let current_strike_c: &f64 = &cs[i];
-                            let current_c_conid: &String = conids_map
-                                .get(date)
-                                .and_then(|c| c.get("C"))
-                                .and_then(|c| c.get(current_strike_c.into()))
-                                .ok_or("Error accessing current call conid")?;
-                            let current_c: &Opt = contracts_map
-                                .get(current_c_conid)
-                                .ok_or("Error accessing current call contract")?;
-
-                            let current_strike_p: &f64 = &ps[i];
-                            let current_p_conid: &String = conids_map
-                                .get(date)
-                                .and_then(|p| p.get("P"))
-                                .and_then(|p| p.get(current_strike_p.into()))
-                                .ok_or("Error accessing current put conid")?;
-                            let current_p: &Opt = contracts_map
-                                .get(current_p_conid)
-                                .ok_or("Error accessing current put contract")?;
-
-                            let right_strike_c: &f64 = &cs[i + 1];
-                            let right_c_conid: &String = conids_map
-                                .get(date)
-                                .and_then(|c| c.get("C"))
-                                .and_then(|c| c.get(right_strike_c.into()))
-                                .ok_or("Error accessing right call conid")?;
-                            let right_c: &Opt = contracts_map
-                                .get(right_c_conid)
-                                .ok_or("Error accessing right call contract")?;
-
-                            let right_strike_p: &f64 = &ps[i + 1];
-                            let right_p_conid: &String = conids_map
-                                .get(date)
-                                .and_then(|p| p.get("P"))
-                                .and_then(|p| p.get(right_strike_p.into()))
-                                .ok_or("Error accessing right put conid")?;
-                            let right_p: &Opt = contracts_map
-                                .get(right_p_conid)
-                                .ok_or("Error accessing right put contract")?;
-
-                            let arb_val: f64 =
-                                (current_p.mkt + right_c.mkt) - (current_c.mkt + right_p.mkt);
-
-                            if arb_val <= arb_threshold
-                                && current_c.bid > 1.0
-                                && current_p.bid > 1.0
-                                && right_c.bid > 1.0
-                                && right_p.bid > 1.0
-                                && current_c.asz > 0.0
-                                && current_p.asz > 0.0
-                                && right_c.asz > 0.0
-                                && right_p.asz > 0.0
-                                && ((right_strike_c - current_strike_c) * 10.0).round() / 10.0
-                                    == self.strike_dif_value.unwrap()
-                                && ((right_strike_p - current_strike_p) * 10.0).round() / 10.0
-                                    == self.strike_dif_value.unwrap()
-                            {
-                                let avg_ask: f64 =
-                                    ((current_c.asz + right_c.asz + current_p.asz + right_p.asz)
-                                        / 4.0)
-                                        .round();
-                                let rank_value: f64 = calc_rank_value(
-                                    avg_ask,
-                                    (-1.0 * arb_val) - 5.0,
-                                    &current_date,
-                                    date,
-                                );
-
-                                contender_contracts.push(Contender {
-                                    arb_val: (-1.0 * arb_val * 100.0).round() / 100.0,
-                                    avg_ask,
-                                    type_spread: "Boxspread".to_string(),
-                                    exp_date: date.clone(),
-                                    rank_value,
-                                    contracts: vec![
-                                        Contract {
-                                            strike: *current_strike_p,
-                                            mkt_price: current_p.mkt,
-                                            date: date.clone(),
-                                            type_contract: "P".to_string(),
-                                        },
-                                        Contract {
-                                            strike: *current_strike_c,
-                                            mkt_price: current_c.mkt,
-                                            date: date.clone(),
-                                            type_contract: "C".to_string(),
-                                        },
-                                        Contract {
-                                            strike: *right_strike_c,
-                                            mkt_price: right_c.mkt,
-                                            date: date.clone(),
-                                            type_contract: "C".to_string(),
-                                        },
-                                        Contract {
-                                            strike: *right_strike_p,
-                                            mkt_price: right_p.mkt,
-                                            date: date.clone(),
-                                            type_contract: "P".to_string(),
-                                        },
-                                    ],
-                                });
-                            }
-                        }
+        if !contender_contracts_total.is_empty() {
+            match self.get_working_leg_conids() {
+                Ok(working_conids) => {
+                    let num_contenders: usize = contender_contracts_total.len();
+                    contender_contracts_total.retain(|contender| {
+                        !contender.contracts.iter().any(|leg| {
+                            chain
+                                .conid_for(leg.date, &leg.type_contract, leg.strike)
+                                .is_some_and(|conid| working_conids.contains(conid))
+                        })
+                    });
+                    let num_skipped: usize = num_contenders - contender_contracts_total.len();
+                    if num_skipped > 0 {
+                        log_message(format!(
+                            "Skipping {} contenders sharing a leg with a working order.",
+                            num_skipped
+                        ));
                     }
                 }
+                Err(e) => log_message(format!("Failed to get working orders: {}.", e)),
             }
         }
 
-        Ok(contender_contracts)
-    }
+        if let Some(fill_cooldown) = &self.fill_cooldown {
+            let now: Instant = Instant::now();
+            let num_contenders: usize = contender_contracts_total.len();
+            contender_contracts_total.retain(|contender| !fill_cooldown.contains(contender, now));
+            let num_skipped: usize = num_contenders - contender_contracts_total.len();
+            if num_skipped > 0 {
+                log_message(format!(
+                    "Skipping {} contenders sharing a leg with a recent fill.",
+                    num_skipped
+                ));
+            }
+        }
 
-    /// Retrieves the account ID from the IBKR API.
-    ///
-    /// # Returns
-    ///
-    /// A `Result` containing the account ID or an error.
-    fn get_account_id(&self) -> Result<String, Box<dyn Error>> {
-        let search_url: String = format!(
-            "{}/v1/api/portfolio/accounts",
-            self.base_url.as_ref().unwrap()
-        );
+        if let Some(max_loss) = self.max_loss {
+            let discount_value: f64 = self.discount_value.unwrap_or(0.0);
+            contender_contracts_total.retain(|contender| {
+                // Financing boxes lose their interest by design and are capped by notional instead.
+                if contender.is_financing() {
+                    return true;
+                }
+                let loss: f64 = worst_case_loss(contender, discount_value) * num_fills as f64;
+                if loss > max_loss {
+                    log_message(format!(
+                        "Rejecting {} {}: worst-case loss of {:.2} exceeds the cap of {:.2}.",
+                        contender.type_spread, contender.exp_date, loss, max_loss
+                    ));
+                }
+                loss <= max_loss
+            });
+        }
 
-        let response: Response = self
-            .client
+        if let Some(max_notional) = scanner
+            .box_financing
             .as_ref()
-            .ok_or("Client is not initialized")?
-            .get(search_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
+            .and_then(|box_financing| box_financing.max_notional)
+        {
+            let mut borrowed: f64 = 0.0;
+            contender_contracts_total.retain(|contender| {
+                if !contender.is_financing() {
+                    return true;
+                }
+                let notional: f64 = contender.arb_val * 100.0 * num_fills as f64;
+                if borrowed + notional > max_notional {
+                    log_message(format!(
+                        "Skipping financing box {} {}: borrowing {:.2} more would exceed the cap of {:.2}.",
+                        contender.exp_date,
+                        contender.reason.formula,
+                        notional,
+                        max_notional
+                    ));
+                    return false;
+                }
+                borrowed += notional;
+                true
+            });
+        }
+
+        if let Some(max_correlation) = self.max_correlation {
+            let num_contenders: usize = contender_contracts_total.len();
+            contender_contracts_total = diversify(contender_contracts_total, max_correlation);
+            let num_skipped: usize = num_contenders - contender_contracts_total.len();
+            if num_skipped > 0 {
+                log_message(format!(
+                    "Pruned {} contenders correlated above {:.2} with a better-ranked contender.",
+                    num_skipped, max_correlation
+                ));
+            }
+        }
 
-        if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
-                response.status(),
-                response.text()?
+        let num_orders_usize: usize = num_orders as usize;
+        if contender_contracts_total.len() > num_orders_usize {
+            contender_contracts_total.truncate(num_orders_usize);
+        }
+        if contender_contracts_total.len() > 1 {
+            log_message(format!(
+                "Selected {} contenders with an average correlation of {:.2}.",
+                contender_contracts_total.len(),
+                set_correlation(&contender_contracts_total)
             ));
-            exit(1);
         }
+        stage_timings.finish("contender_calc", calc_start);
 
-        let account_result: Vec<AccountResponse> = response.json()?;
-        if let Some(first_account) = account_result.get(0) {
-            return Ok(first_account.id.clone());
-        } else {
-            log_error(format!("No account found in the response"));
-            exit(1);
+        for contender in &contender_contracts_total {
+            emit(&Event::contender(
+                contender,
+                &self.underlying.ticker,
+                self.chain.as_ref(),
+            ));
         }
+        emit(&Event::ScanEnd {
+            scan_time,
+            contenders: contender_contracts_total.len(),
+            duration_ms: scan_start.elapsed().as_millis(),
+        });
+
+        Ok(contender_contracts_total)
     }
 
-    /// Retrieves the ticker conid and relevant months for the options.
+    /// Checks that the account may trade the underlying's options and gets real-time market data
+    /// for them and the underlying, so a missing permission or subscription fails at startup
+    /// instead of as empty snapshots once the market opens.
     ///
     /// # Returns
     ///
-    /// A `Result` containing the ticker conid, current month, and next month or an error.
-    fn get_ticker_conid(&self) -> Result<(String, String, String), Box<dyn Error>> {
-        let search_url: String = format!(
-            "{}/v1/api/iserver/secdef/search?symbol={}",
-            self.base_url.as_ref().unwrap(),
-            self.ticker.as_ref().unwrap()
-        );
-
-        let response: Response = self
-            .client
+    /// A `Result` indicating success, or an error saying what is missing and how to fix it.
+    fn preflight(&mut self) -> Result<(), Box<dyn Error>> {
+        let ticker: &str = self.ticker.as_deref().unwrap_or_default();
+        let ticker_id: &str = self.ticker_id.as_deref().ok_or("Ticker ID is not set")?;
+        let account_id: &str = self.account_id.as_deref().ok_or("Account ID is not set")?;
+        let option_conid: &str = self
+            .conids_strings
             .as_ref()
-            .ok_or("Client is not initialized")?
-            .get(&search_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
-
-        if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
-                response.status(),
-                response.text()?
-            ));
-            exit(1);
-        }
-
-        let search_results: Vec<SecDefResponse> = response.json()?;
-        let mut month1: String = String::new();
-        let mut month2: String = String::new();
-
-        let result: &SecDefResponse = &search_results[0];
-        if let Some(conid) = &result.conid {
-            if !conid.is_empty() {
-                if let Some(sections) = &result.sections {
-                    for section in sections {
-                        if section.sec_type == "OPT" {
-                            if let Some(months) = &section.months {
-                                let months_vec: Vec<&str> = months.split(';').collect();
-                                if months_vec.len() >= 2 {
-                                    month1 = months_vec[0].to_string();
-                                    month2 = months_vec[1].to_string();
-                                }
-                            }
-                            break;
-                        }
+            .and_then(|conids| conids.first())
+            .and_then(|conids| conids.split(',').next())
+            .ok_or("The option chain has no contracts")?;
+
+        let rules: ContractRulesResponse = endpoints::contract_rules(self.client()?, option_conid)?;
+        check_trading_permissions(&rules, account_id, ticker)?;
+
+        let options: String = format!("{} options", ticker);
+        for (conid, name) in [(ticker_id, ticker), (option_conid, options.as_str())] {
+            // The availability is only reported once the subscription is live.
+            let mut availability: Option<MarketDataResponse> = None;
+            for _ in 0..PREFLIGHT_ATTEMPTS {
+                availability = endpoints::snapshot(self.data_client()?, conid, "31,6509")?
+                    .into_iter()
+                    .find(|response| response.field_6509.is_some());
+                if availability.is_some() {
+                    break;
+                }
+                thread::sleep(Duration::from_secs(1));
+            }
+            match availability {
+                Some(response) => {
+                    if check_market_data(&response, name)? == MarketDataAvailability::Delayed {
+                        log_message(format!(
+                            "WARNING: market data for {} is delayed. Subscribe to real-time data in Client Portal under Settings > Market Data Subscriptions (OPRA for options, CBOE Indexes for SPX and XSP).",
+                            name
+                        ));
+                        self.delayed_data = true;
                     }
                 }
-
-                return Ok((conid.to_string(), month1, month2));
+                None => log_message(format!(
+                    "Couldn't check the market data subscription for {}, continuing.",
+                    name
+                )),
             }
         }
 
-        log_error(format!("No ticker conid found in the response"));
-        exit(1);
+        if !self.delayed_data {
+            log_message(format!(
+                "Account {} may trade {} options with real-time market data.",
+                account_id, ticker
+            ));
+        }
+        Ok(())
     }
 
-    /// Retrieves the conid map for the options contracts.
+    /// Compares the local clock with the market data gateway's, warning about a drifting clock
+    /// and refusing to run on one that is far off.
     ///
     /// # Arguments
     ///
-    /// * `num_days` - The number of days for which to retrieve conids.
-    /// * `num_days_offset` - The offset for the number of days.
-    /// * `current_month` - The current month for the options.
-    /// * `next_month` - The next month for the options.
+    /// * `config` - The skew to warn at and the skew to refuse to run with.
     ///
     /// # Returns
     ///
-    /// A `Result` containing conids, dates, strike prices, and conid maps or an error.
-    fn get_conids_map(
-        &self,
-        mut num_days: i64,
-        num_days_offset: i64,
-        current_month: String,
-        next_month: String,
-    ) -> Result<
-        (
-            Vec<String>,
-            Vec<String>,
-            HashMap<String, HashMap<String, Vec<f64>>>,
-            HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>,
-        ),
-        Box<dyn Error>,
-    > {
-        let mut conids_strings: Vec<String> = Vec::new();
-        let mut dates_slice: Vec<String> = Vec::new();
-        let mut strike_slice: HashMap<String, HashMap<String, Vec<f64>>> = HashMap::new();
-        let mut conids_map: HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>> =
-            HashMap::new();
-
-        let search_url: String = format!(
-            "{}/v1/api/iserver/secdef/info?conid={}&sectype=OPT&month={}&exchange=SMART&strike=0",
-            self.base_url.as_ref().unwrap(),
-            self.ticker_id.as_ref().unwrap(),
-            current_month
-        );
-
-        let response: Response = self
-            .client
-            .as_ref()
-            .ok_or("Client is not initialized")?
-            .get(&search_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
-
-        if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
-                response.status(),
-                response.text()?
-            ));
-            exit(1);
+    /// A `Result` indicating success, or an error if the skew exceeds the maximum.
+    fn check_clock(&self, config: &ClockSkewConfig) -> Result<(), Box<dyn Error>> {
+        match endpoints::clock(self.data_client()?) {
+            Ok((local, gateway)) => {
+                let skew_seconds: i64 = (local - gateway).num_seconds();
+                if let Some(warning) = check_clock_skew(skew_seconds, config)? {
+                    log_message(warning);
+                }
+            }
+            Err(e) => log_message(format!(
+                "Couldn't check the local clock against the gateway's: {}, continuing.",
+                e
+            )),
         }
+        Ok(())
+    }
 
-        let search_results: Vec<SecDefInfoResponse> = response.json()?;
-        let current_date: String = Local::now().format("%y%m%d").to_string();
-        let mut conids_str: String = String::new();
-        let mut counter: i32 = 0;
-
-        for sec_def_info in search_results.iter() {
-            let type_opt: &String = &sec_def_info.right;
-            let exp_date: String = sec_def_info
-                .maturity_date
-                .get(2..)
-                .unwrap_or(&sec_def_info.maturity_date)
-                .to_string();
-            let strike: OrderedFloat<f64> = OrderedFloat(sec_def_info.strike);
-            let conid: f64 = sec_def_info.conid;
-
-            if calc_time_difference(&current_date, &exp_date) > (-1 + num_days_offset) {
-                if !strike_slice.contains_key(&exp_date) {
-                    num_days -= 1;
-                    if num_days < 0 {
-                        break;
-                    }
-
-                    dates_slice.push(exp_date.to_string());
+    /// Returns the client of the gateway that account requests and orders go to.
+    fn client(&self) -> Result<&GatewayClient, Box<dyn Error>> {
+        Ok(self.client.as_ref().ok_or("Client is not initialized")?)
+    }
 
-                    strike_slice.insert(exp_date.to_string(), HashMap::new());
-                    strike_slice
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("C".to_string(), Vec::new());
-                    strike_slice
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("P".to_string(), Vec::new());
+    /// Returns the client of the gateway that market data comes from.
+    fn data_client(&self) -> Result<&GatewayClient, Box<dyn Error>> {
+        Ok(self
+            .data_client
+            .as_ref()
+            .ok_or("Client is not initialized")?)
+    }
 
-                    conids_map.insert(exp_date.to_string(), HashMap::new());
-                    conids_map
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("C".to_string(), HashMap::new());
-                    conids_map
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("P".to_string(), HashMap::new());
-                }
+    /// Takes the underlying price and spreads of the last scan, if one fetched quotes since they
+    /// were last taken.
+    pub(crate) fn take_market_sample(&mut self) -> Option<MarketSample> {
+        self.market_sample.take()
+    }
 
-                strike_slice
-                    .get_mut(&exp_date)
-                    .unwrap()
-                    .get_mut(type_opt)
-                    .unwrap()
-                    .push(*strike);
+    /// Takes the statistics of the last scan, if one ran since they were last taken.
+    pub(crate) fn take_scan_summary(&mut self) -> Option<ScanSummary> {
+        self.scan_summary.take()
+    }
 
-                conids_map
-                    .get_mut(&exp_date)
-                    .unwrap()
-                    .get_mut(type_opt)
-                    .unwrap()
-                    .insert(strike, conid.to_string());
+    /// Returns the account ID the bot trades, once initialized.
+    pub(crate) fn account_id(&self) -> Option<&str> {
+        self.account_id.as_deref()
+    }
 
-                conids_str.push_str(&conid.to_string());
-                conids_str.push_str(",");
-                counter += 1;
+    /// Saves the working orders, resubmission cooldowns and journal so a restart can resume them.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the state file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or an error.
+    pub(crate) fn save_state(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let now_ms: i64 = Utc::now().timestamp_millis();
+        save_state(
+            path,
+            &BotState {
+                saved_at: now_ms,
+                live_orders: self
+                    .live_orders
+                    .as_ref()
+                    .map(WorkingOrders::ids)
+                    .unwrap_or_default(),
+                submitted: self
+                    .recent_contenders
+                    .as_ref()
+                    .map(|recent_contenders| recent_contenders.export(Instant::now(), now_ms))
+                    .unwrap_or_default(),
+                journal: self.journal.clone().unwrap_or_default(),
+                position_watch: self.position_watch.clone(),
+            },
+        )
+    }
 
-                if counter == 300 {
-                    conids_strings.push(conids_str);
-                    conids_str = String::new();
-                    counter = 0;
-                }
+    /// Restores the state saved by a previous run, so its working orders are managed and canceled
+    /// like orders placed by this run.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the state file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or an error if the state could not be read.
+    pub(crate) fn restore_state(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let Some(state) = load_state(path)? else {
+            return Ok(());
+        };
+
+        log_message(format!(
+            "Resuming {} working orders saved {} seconds ago.",
+            state.live_orders.len(),
+            (Utc::now().timestamp_millis() - state.saved_at) / 1000
+        ));
+        if let Some(live_orders) = &self.live_orders {
+            let expires_at: Instant = Instant::now() + self.order_ttl.unwrap_or_default();
+            for order_id in &state.live_orders {
+                live_orders.add(order_id, expires_at);
             }
         }
-
-        if num_days > 0 {
-            let search_url_2: String = format!(
-                "{}/v1/api/iserver/secdef/info?conid={}&sectype=OPT&month={}&exchange=SMART&strike=0",
-                self.base_url.as_ref().unwrap(),
-                self.ticker_id.as_ref().unwrap(),
-                next_month
+        if let Some(recent_contenders) = &mut self.recent_contenders {
+            recent_contenders.restore(
+                &state.submitted,
+                Instant::now(),
+                Utc::now().timestamp_millis(),
             );
+        }
+        self.journal = Some(state.journal);
+        self.position_watch = state.position_watch;
+        Ok(())
+    }
 
-            let response_2: Response = self
-                .client
-                .as_ref()
-                .ok_or("Client is not initialized")?
-                .get(&search_url_2)
-                .header("Connection", "keep-alive")
-                .header("User-Agent", "trading_bot_rust/1.0")
-                .send()?;
-
-            if !response_2.status().is_success() {
-                log_error(format!(
-                    "{}\nBody: {:?}",
-                    response_2.status(),
-                    response_2.text()?
-                ));
+    /// Retrieves the account ID from the IBKR API.
+    ///
+    /// # Arguments
+    ///
+    /// * `preferred` - The account to trade when the gateway has several, otherwise the first.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the account ID or an error if the preferred account isn't available.
+    fn get_account_id(&self, preferred: Option<&str>) -> Result<String, Box<dyn Error>> {
+        let account_result: Vec<AccountResponse> = match endpoints::accounts(self.client()?) {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                log_error(format!("{}", e));
                 exit(1);
             }
-
-            let search_results_2: Vec<SecDefInfoResponse> = response_2.json()?;
-
-            for sec_def_info in search_results_2.iter() {
-                let type_opt: &String = &sec_def_info.right;
-                let exp_date: String = sec_def_info
-                    .maturity_date
-                    .get(2..)
-                    .unwrap_or(&sec_def_info.maturity_date)
-                    .to_string();
-                let strike: OrderedFloat<f64> = OrderedFloat(sec_def_info.strike);
-                let conid: f64 = sec_def_info.conid;
-
-                if !strike_slice.contains_key(&exp_date) {
-                    num_days -= 1;
-                    if num_days < 0 {
-                        break;
-                    }
-
-                    dates_slice.push(exp_date.to_string());
-
-                    strike_slice.insert(exp_date.to_string(), HashMap::new());
-                    strike_slice
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("C".to_string(), Vec::new());
-                    strike_slice
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("P".to_string(), Vec::new());
-
-                    conids_map.insert(exp_date.to_string(), HashMap::new());
-                    conids_map
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("C".to_string(), HashMap::new());
-                    conids_map
-                        .get_mut(&exp_date)
-                        .unwrap()
-                        .insert("P".to_string(), HashMap::new());
-                }
-
-                strike_slice
-                    .get_mut(&exp_date)
-                    .unwrap()
-                    .get_mut(type_opt)
-                    .unwrap()
-                    .push(*strike);
-
-                conids_map
-                    .get_mut(&exp_date)
-                    .unwrap()
-                    .get_mut(type_opt)
-                    .unwrap()
-                    .insert(strike, conid.to_string());
-
-                conids_str.push_str(&conid.to_string());
-                conids_str.push_str(",");
-                counter += 1;
-
-                if counter == 300 {
-                    conids_strings.push(conids_str);
-                    conids_str = String::new();
-                    counter = 0;
+        };
+        if let Some(preferred) = preferred {
+            return match account_result
+                .iter()
+                .find(|account| account.id == preferred)
+            {
+                Some(account) => Ok(account.id.clone()),
+                None => {
+                    Err(format!("Account {} is not available on the gateway", preferred).into())
                 }
-            }
+            };
         }
-
-        if !conids_str.is_empty() {
-            conids_strings.push(conids_str);
-        }
-
-        for (_, strikes) in strike_slice.iter_mut() {
-            strikes
-                .get_mut("C")
-                .unwrap()
-                .sort_by(|a, b| a.partial_cmp(b).unwrap());
-            strikes
-                .get_mut("P")
-                .unwrap()
-                .sort_by(|a, b| a.partial_cmp(b).unwrap());
+        if let Some(first_account) = account_result.first() {
+            Ok(first_account.id.clone())
+        } else {
+            log_error("No account found in the response".to_string());
+            exit(1);
         }
-
-        Ok((conids_strings, dates_slice, strike_slice, conids_map))
     }
 
-    /// Retrieves the portfolio value from the IBKR API.
+    /// Retrieves the portfolio value, using the cache while it is fresh.
+    ///
+    /// A stale cached value is returned immediately while a background thread refreshes it. The
+    /// API is only called synchronously when nothing is cached yet.
     ///
     /// # Returns
     ///
     /// A `Result` containing the portfolio value or an error.
     pub(crate) fn get_portfolio_value(&self) -> Result<f64, Box<dyn Error>> {
-        let search_url: String = format!(
-            "{}/v1/api/portfolio/{}/summary",
-            self.base_url.as_ref().unwrap(),
-            self.account_id.as_ref().unwrap()
-        );
-
-        let response: Response = self
-            .client
-            .as_ref()
-            .ok_or("Client is not initialized")?
-            .get(&search_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
-
-        if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
-                response.status(),
-                response.text()?
-            ));
-            exit(1);
+        let account_id: String = self.account_id.clone().ok_or("Account ID is not set")?;
+        let client: GatewayClient = self.client()?.clone();
+
+        let Some(portfolio_cache) = &self.portfolio_cache else {
+            return fetch_portfolio_value(&client, &account_id);
+        };
+
+        match portfolio_cache.get(Instant::now()) {
+            Some((value, true)) => Ok(value),
+            Some((value, false)) => {
+                if portfolio_cache.begin_refresh() {
+                    let portfolio_cache: PortfolioCache = portfolio_cache.clone();
+                    thread::spawn(move || match fetch_portfolio_value(&client, &account_id) {
+                        Ok(new_value) => record_portfolio_value(&portfolio_cache, new_value),
+                        Err(e) => {
+                            portfolio_cache.cancel_refresh();
+                            log_message(format!("Failed to refresh portfolio value: {}.", e));
+                        }
+                    });
+                }
+                Ok(value)
+            }
+            None => {
+                let value: f64 = fetch_portfolio_value(&client, &account_id)?;
+                record_portfolio_value(portfolio_cache, value);
+                Ok(value)
+            }
         }
-
-        let search_results: PortfolioResponse = response.json()?;
-        Ok(search_results.equity_with_loan_value.amount)
     }
 
-    /// Cancels all pending limit orders for the account.
-    ///
-    /// This method iterates over all live orders stored in the `live_orders` field
-    /// and calls the `cancel_order` method for each order. Once all orders are canceled,
-    /// it clears the `live_orders` list and logs a success message.
+    /// Retrieves the daily and unrealized P&L for the account from the IBKR API.
     ///
     /// # Returns
     ///
-    /// No return value, but logs messages indicating the result of each cancellation.
-    pub(crate) fn cancel_pending_orders(&mut self) {
-        log_message(format!("Cancelling all pending limit orders."));
-
-        if let Some(live_orders) = &self.live_orders {
-            let order_ids: Vec<String> = live_orders.iter().cloned().collect();
-
-            for order_id in order_ids {
-                match self.cancel_order(&order_id) {
-                    Ok(message) => log_message(format!("{}.", message)),
-                    Err(e) => log_message(format!("{}.", e)),
-                }
-            }
-        }
+    /// A `Result` containing the `Pnl` summed over all account partitions or an error.
+    pub(crate) fn get_pnl(&self) -> Result<Pnl, Box<dyn Error>> {
+        let pnl_response: PnlResponse = endpoints::pnl(self.client()?)?;
+        Ok(pnl_response
+            .upnl
+            .values()
+            .fold(Pnl::default(), |pnl, partition| Pnl {
+                daily: pnl.daily + partition.dpl,
+                unrealized: pnl.unrealized + partition.upl,
+            }))
+    }
 
-        if let Some(live_orders) = &mut self.live_orders {
-            live_orders.clear();
+    /// Sets the thresholds and discount used from the next scan on, after the config file changed
+    /// or a threshold window began.
+    ///
+    /// # Arguments
+    ///
+    /// * `arb_val` - Arbitrage value threshold.
+    /// * `near_miss_epsilon` - How far below the arb threshold contenders are logged as near misses.
+    /// * `discount_value` - The discount value applied to orders.
+    pub(crate) fn set_thresholds(
+        &mut self,
+        arb_val: f64,
+        near_miss_epsilon: Option<f64>,
+        discount_value: f64,
+    ) {
+        if let Some(scanner) = &mut self.scanner {
+            scanner.arb_val = arb_val;
+            scanner.near_miss_epsilon = near_miss_epsilon;
         }
-
-        log_message(format!("All pending limit orders cancelled."));
+        self.discount_value = Some(discount_value);
     }
 
-    /// Cancels a specific order by order ID.
+    /// Sets the account orders are placed in.
     ///
     /// # Arguments
     ///
-    /// * `order_id` - The ID of the order to be canceled.
+    /// * `account_id` - The account ID, e.g. `"DU1234567"`.
+    pub(crate) fn set_account_id(&mut self, account_id: &str) {
+        self.account_id = Some(account_id.to_string());
+    }
+
+    /// Sets how long orders submitted from now on work before the monitor cancels them.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A `Result` containing a message about the cancellation or an error.
-    fn cancel_order(&self, order_id: &str) -> Result<String, Box<dyn Error>> {
-        let cancel_order_url: String = format!(
-            "{}/v1/api/iserver/account/{}/order/{}",
-            self.base_url.as_ref().unwrap(),
-            self.account_id.as_ref().unwrap(),
-            order_id
-        );
-
-        let response: Response = self
-            .client
-            .as_ref()
-            .ok_or("Client is not initialized")?
-            .delete(&cancel_order_url)
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .send()?;
-
-        if response.status().is_success() {
-            Ok(format!("Order ID {} cancelled successfully", order_id))
-        } else {
-            Err(Box::new(io::Error::new(
-                ErrorKind::Other,
-                format!(
-                    "Failed to cancel order ID {}. HTTP status: {}",
-                    order_id,
-                    response.status()
-                ),
-            )))
-        }
+    /// * `order_ttl` - The TTL of each order.
+    pub(crate) fn set_order_ttl(&mut self, order_ttl: Duration) {
+        self.order_ttl = Some(order_ttl);
     }
 
-    /// Places orders for the contender contracts.
+    /// Starts checking the positions of held combos for assignments, exercises and expirations.
     ///
     /// # Arguments
     ///
-    /// * `contender_contracts` - A vector of contender contracts to be ordered.
-    /// * `num_fills` - The number of fills for each contract.
+    /// * `interval` - How often the positions are checked.
+    pub(crate) fn set_assignment_checks(&mut self, interval: Duration) {
+        self.assignment_checks = Some((interval, Instant::now()));
+    }
+
+    /// Starts rolling held calendars automatically before their short leg expires.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A `Result` indicating whether the orders were successfully placed or not.
-    pub(crate) fn order_contender_contracts(
-        &mut self,
-        contender_contracts: &Vec<Contender>,
-        num_fills: i32,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let order_url: String = format!(
-            "{}/v1/api/iserver/account/{}/orders",
-            self.base_url.as_ref().unwrap(),
-            self.account_id.as_ref().unwrap()
-        );
-
-        let request_data: RequestDataStruct = build_request_data(
-            contender_contracts,
-            num_fills,
-            &self.account_id,
-            &self.conids_map,
-            self.discount_value,
-        );
-
-        let json_data: Vec<u8> = serde_json::to_vec(&request_data)?;
-
-        let response: Response = self
-            .client
-            .as_ref()
-            .ok_or("Client is not initialized")?
-            .post(&order_url)
-            .header(CONTENT_TYPE, "application/json")
-            .header("Connection", "keep-alive")
-            .header("User-Agent", "trading_bot_rust/1.0")
-            .body(json_data)
-            .send()?;
+    /// * `days` - How many days before the short leg's expiration a calendar is rolled.
+    /// * `offset` - How far below the mid to sell the rolls, e.g. `0.05`.
+    pub(crate) fn set_auto_roll(&mut self, days: i64, offset: f64) {
+        self.auto_roll = Some((days, offset));
+    }
 
-        if !response.status().is_success() {
-            log_error(format!(
-                "{}\nBody: {:?}",
-                response.status(),
-                response.text()?
-            ));
-            exit(1);
+    /// Sets the session orders submitted from now on are placed in.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The open session; overnight orders are allowed to work outside regular hours.
+    pub(crate) fn set_session(&mut self, session: TradingSession) {
+        if session != self.session {
+            log_message(format!("Trading the {} session.", session.label()));
         }
+        self.session = session;
+    }
+}
 
-        let mut generic_responses: Vec<Value> = response.json()?;
-
-        loop {
-            if let Some(confirm_id) = generic_responses[0]["id"].as_str() {
-                let confirm_url = format!(
-                    "{}/v1/api/iserver/reply/{}",
-                    self.base_url.as_ref().unwrap(),
-                    confirm_id
-                );
-                let confirm_data: Confirmation = Confirmation { confirmed: true };
-
-                let json_data_confirm: Vec<u8> = serde_json::to_vec(&confirm_data)?;
-                let confirm_response: Response = self
-                    .client
-                    .as_ref()
-                    .ok_or("Client is not initialized")?
-                    .post(&confirm_url)
-                    .header(CONTENT_TYPE, "application/json")
-                    .header("Connection", "keep-alive")
-                    .header("User-Agent", "trading_bot_rust/1.0")
-                    .body(json_data_confirm)
-                    .send()?;
-
-                if confirm_response.status().is_success() {
-                    generic_responses = confirm_response.json()?;
-                } else {
-                    log_error(format!(
-                        "{}\nBody: {:?}",
-                        confirm_response.status(),
-                        confirm_response.text()?
-                    ));
-                    exit(1);
-                }
-            } else if generic_responses[0].get("order_id").is_some() {
-                if let Some(live_orders) = &mut self.live_orders {
-                    for order in &generic_responses {
-                        if let Some(order_id) = order["order_id"].as_str() {
-                            live_orders.push(order_id.to_string());
-                        }
-                    }
-                }
-                break;
-            } else {
-                break;
-            }
-        }
-        Ok(())
+/// Stores a freshly retrieved portfolio value and logs large changes.
+///
+/// # Arguments
+///
+/// * `portfolio_cache` - The portfolio value cache.
+/// * `value` - The new portfolio value.
+fn record_portfolio_value(portfolio_cache: &PortfolioCache, value: f64) {
+    if let Some((previous, change)) = portfolio_cache.update(value, Instant::now()) {
+        log_message(format!(
+            "Portfolio value changed {:+.2}% from {:.2} to {:.2}, possible fill or withdrawal.",
+            change, previous, value
+        ));
     }
 }
@@ -0,0 +1,414 @@
+use chrono::{DateTime, Utc};
+use reqwest::{
+    blocking::{Client, ClientBuilder, RequestBuilder, Response},
+    header::{CONTENT_TYPE, DATE},
+    Certificate, Method, Proxy, StatusCode,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{error::Error, fs};
+
+use super::{compat::ResponseSchema, endpoints, models::AuthStatus};
+use crate::{
+    error_report::record_endpoint,
+    logging::{correlation_id, log_audit, log_message, log_trace},
+    metrics::{count_gateway_error, count_gateway_request, GatewayError},
+    structs::{HttpConfig, ProxySetting},
+    watchdog::note_request,
+};
+
+/// Splits a PEM bundle into its certificates.
+///
+/// # Arguments
+///
+/// * `pem` - The contents of the bundle.
+///
+/// # Returns
+///
+/// A `Vec<String>` with each `BEGIN CERTIFICATE` to `END CERTIFICATE` block.
+pub(crate) fn split_pem_bundle(pem: &str) -> Vec<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+    pem.split_inclusive(END)
+        .filter_map(|block| {
+            let start: usize = block.find("-----BEGIN CERTIFICATE-----")?;
+            block.ends_with(END).then(|| block[start..].to_string())
+        })
+        .collect()
+}
+
+/// Builds the HTTP client of the gateway connections.
+///
+/// # Arguments
+///
+/// * `http_config` - The proxy, CA bundle and certificate verification settings.
+///
+/// # Returns
+///
+/// A `Result` containing the client, or an error if the proxy URL or the CA bundle is invalid.
+pub(crate) fn build_http_client(http_config: &HttpConfig) -> Result<Client, Box<dyn Error>> {
+    let mut builder: ClientBuilder =
+        ClientBuilder::new().danger_accept_invalid_certs(http_config.accept_invalid_certs);
+    builder = match &http_config.proxy {
+        ProxySetting::Environment => builder,
+        ProxySetting::Disabled => builder.no_proxy(),
+        ProxySetting::Url(url) => builder
+            .proxy(Proxy::all(url).map_err(|e| format!("Invalid PROXY_URL {:?}: {}", url, e))?),
+    };
+    if let Some(path) = &http_config.ca_bundle {
+        let pem: String =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let certificates: Vec<String> = split_pem_bundle(&pem);
+        if certificates.is_empty() {
+            return Err(format!("{} holds no PEM certificates", path).into());
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(
+                Certificate::from_pem(certificate.as_bytes())
+                    .map_err(|e| format!("Invalid certificate in {}: {}", path, e))?,
+            );
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// An HTTP client for one Client Portal gateway, handling the transport: the self-signed
+/// certificate, common headers, error statuses and the audit file.
+#[derive(Clone)]
+pub(crate) struct GatewayClient {
+    http: Client,
+    base_url: String,
+    schema: ResponseSchema,
+}
+
+impl GatewayClient {
+    /// Creates a client for the gateway at `domain:port`.
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The domain of the gateway.
+    /// * `port` - The port of the gateway.
+    /// * `http_config` - The proxy, CA bundle and certificate verification settings.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the client or an error if the HTTP client could not be built.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let client = GatewayClient::new("localhost", "5000", &get_http_config())?;
+    /// ```
+    pub(crate) fn new(
+        domain: &str,
+        port: &str,
+        http_config: &HttpConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(GatewayClient {
+            http: build_http_client(http_config)?,
+            base_url: format!("https://{}:{}", domain, port),
+            schema: ResponseSchema::default(),
+        })
+    }
+
+    /// Sets the response schema of the gateway's build, which responses are adapted from.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - The response schema.
+    pub(crate) fn set_schema(&mut self, schema: ResponseSchema) {
+        self.schema = schema;
+    }
+
+    /// Returns the full URL of an API path, e.g. `/iserver/account/trades`.
+    fn url(&self, path: &str) -> String {
+        format!("{}/v1/api{}", self.base_url, path)
+    }
+
+    /// Adds the headers every gateway request carries, including the correlation ID of the scan
+    /// iteration for gateways and proxies that log it.
+    fn with_headers(request: RequestBuilder) -> RequestBuilder {
+        let request: RequestBuilder = request
+            .header("Connection", "keep-alive")
+            .header("User-Agent", "trading_bot_rust/1.0");
+        match correlation_id() {
+            Some(correlation_id) => request.header("X-Correlation-ID", correlation_id),
+            None => request,
+        }
+    }
+
+    /// Logs a request about to be sent at the trace level, and notes it for the watchdog.
+    fn note_request(method: &str, url: &str) {
+        let request: String = format!("{} {}", method, url);
+        log_trace(request.clone());
+        note_request(request);
+    }
+
+    /// Sends a request, counting it and any failure in the gateway metrics.
+    fn send(request: RequestBuilder) -> Result<Response, Box<dyn Error>> {
+        count_gateway_request();
+        request.send().map_err(|e| {
+            count_gateway_error(GatewayError::of_transport(&e));
+            e.into()
+        })
+    }
+
+    /// Reads the body of a response, turning an error status into an error carrying the body.
+    fn read_body(response: Response) -> Result<String, Box<dyn Error>> {
+        let status: StatusCode = response.status();
+        let body: String = response.text()?;
+        if !status.is_success() {
+            count_gateway_error(GatewayError::of_status(status.as_u16()));
+            return Err(format!("{}\nBody: {:?}", status, body).into());
+        }
+        Ok(body)
+    }
+
+    /// Parses a JSON response, adapted to the current schema, naming the endpoint and quoting
+    /// the start of the body when it doesn't match the expected schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The API path the response came from.
+    /// * `body` - The response body.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the parsed response or an error describing the mismatch.
+    fn parse<T: DeserializeOwned>(&self, path: &str, body: &str) -> Result<T, Box<dyn Error>> {
+        let mismatch = |e: serde_json::Error| -> Box<dyn Error> {
+            count_gateway_error(GatewayError::Decode);
+            let excerpt: String = body.chars().take(200).collect();
+            format!(
+                "Unexpected response from {}: {}. Body: {:?}",
+                self.url(path),
+                e,
+                excerpt
+            )
+            .into()
+        };
+        let mut value: Value = serde_json::from_str(body).map_err(mismatch)?;
+        self.schema.adapt(path, &mut value);
+        serde_json::from_value(value).map_err(mismatch)
+    }
+
+    /// Sends a GET request and parses the JSON response.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The API path.
+    /// * `query` - The query parameters.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the parsed response or an error if the request failed.
+    pub(crate) fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Box<dyn Error>> {
+        let url: String = self.url(path);
+        record_endpoint(&url);
+        Self::note_request("GET", &url);
+        let response: Response = Self::send(Self::with_headers(self.http.get(&url)).query(query))?;
+        self.parse(path, &Self::read_body(response)?)
+    }
+
+    /// Sends a GET request and reads the gateway's clock from the `Date` header of the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The API path.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the local time halfway through the request and the gateway's time,
+    /// or an error if the request failed or the response has no valid `Date` header.
+    pub(crate) fn clock(
+        &self,
+        path: &str,
+    ) -> Result<(DateTime<Utc>, DateTime<Utc>), Box<dyn Error>> {
+        let url: String = self.url(path);
+        record_endpoint(&url);
+        Self::note_request("GET", &url);
+        let sent: DateTime<Utc> = Utc::now();
+        let response: Response = Self::send(Self::with_headers(self.http.get(&url)))?;
+        let received: DateTime<Utc> = Utc::now();
+        let date: String = response
+            .headers()
+            .get(DATE)
+            .ok_or("The gateway didn't send a Date header")?
+            .to_str()?
+            .to_string();
+        Self::read_body(response)?;
+        let gateway: DateTime<Utc> = DateTime::parse_from_rfc2822(&date)?.with_timezone(&Utc);
+        Ok((sent + (received - sent) / 2, gateway))
+    }
+
+    /// Sends a POST request with a JSON body and parses the JSON response, without auditing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The API path.
+    /// * `body` - The request body.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the parsed response or an error if the request failed.
+    pub(crate) fn post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, Box<dyn Error>> {
+        let url: String = self.url(path);
+        record_endpoint(&url);
+        Self::note_request("POST", &url);
+        let response: Response = Self::send(Self::with_headers(self.http.post(&url)).json(body))?;
+        self.parse(path, &Self::read_body(response)?)
+    }
+
+    /// Sends a POST request with a JSON body, writing the exact request and response to the audit
+    /// file, and parses the JSON response.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The API path.
+    /// * `body` - The request body.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the parsed response or an error if the request failed.
+    pub(crate) fn post_audited<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, Box<dyn Error>> {
+        let body: Vec<u8> = serde_json::to_vec(body)?;
+        let response: String = self.send_audited(Method::POST, path, Some(body))?;
+        self.parse(path, &response)
+    }
+
+    /// Sends a DELETE request, writing the exact request and response to the audit file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The API path.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the response body or an error if the request failed.
+    pub(crate) fn delete_audited(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        self.send_audited(Method::DELETE, path, None)
+    }
+
+    /// Sends a request, writing the exact request and response to the audit file.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The HTTP method.
+    /// * `path` - The API path.
+    /// * `body` - The JSON request body, if any.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the response body or an error if the request failed.
+    fn send_audited(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<String, Box<dyn Error>> {
+        let url: String = self.url(path);
+        let request_body: String = body
+            .as_deref()
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default()
+            .into_owned();
+        self.audit("request", method.as_str(), &url, None, &request_body);
+
+        record_endpoint(&url);
+        Self::note_request(method.as_str(), &url);
+        let mut request: RequestBuilder =
+            Self::with_headers(self.http.request(method.clone(), &url));
+        if let Some(body) = body {
+            request = request.header(CONTENT_TYPE, "application/json").body(body);
+        }
+        let response: Response = Self::send(request)?;
+
+        let status: StatusCode = response.status();
+        let response_body: String = response.text()?;
+        self.audit(
+            "response",
+            method.as_str(),
+            &url,
+            Some(status),
+            &response_body,
+        );
+        if !status.is_success() {
+            count_gateway_error(GatewayError::of_status(status.as_u16()));
+            return Err(format!("{}\nBody: {:?}", status, response_body).into());
+        }
+        Ok(response_body)
+    }
+
+    /// Appends a request or response to the audit file, redacted as the logs are.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - Either `"request"` or `"response"`.
+    /// * `method` - The HTTP method of the call.
+    /// * `url` - The URL of the call.
+    /// * `status` - The HTTP status code, for responses.
+    /// * `body` - The exact body sent or received.
+    fn audit(
+        &self,
+        direction: &str,
+        method: &str,
+        url: &str,
+        status: Option<StatusCode>,
+        body: &str,
+    ) {
+        log_audit(
+            direction,
+            method,
+            url,
+            status.map(|status| status.as_u16()),
+            body,
+        );
+    }
+}
+
+/// Creates a client for a gateway, adapted to the response schema of the gateway's build.
+///
+/// # Arguments
+///
+/// * `domain` - The domain of the gateway.
+/// * `port` - The port of the gateway.
+/// * `http_config` - The proxy, CA bundle and certificate verification settings.
+///
+/// # Returns
+///
+/// A `Result` containing the client, or an error if the gateway could not be reached or its
+/// version isn't supported.
+pub(crate) fn connect_gateway(
+    domain: &str,
+    port: &str,
+    http_config: &HttpConfig,
+) -> Result<GatewayClient, Box<dyn Error>> {
+    let mut client: GatewayClient = GatewayClient::new(domain, port, http_config)?;
+    let status: AuthStatus = endpoints::auth_status(&client)?;
+    match status.server_info.and_then(|info| info.server_version) {
+        Some(version) => {
+            let schema: ResponseSchema = ResponseSchema::for_version(&version)?;
+            log_message(format!(
+                "Gateway {}:{} runs {}, using the {:?} response schema.",
+                domain, port, version, schema
+            ));
+            client.set_schema(schema);
+        }
+        None => log_message(format!(
+            "Gateway {}:{} doesn't report its version, assuming the current response schema.",
+            domain, port
+        )),
+    }
+    Ok(client)
+}
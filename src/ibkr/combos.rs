@@ -0,0 +1,451 @@
+use chrono::{Local, NaiveDate, Utc};
+use rustc_hash::FxHashMap;
+use std::{error::Error, time::Instant};
+
+use super::{
+    endpoints,
+    models::{OrderBody, OrderReply, PositionResponse, RequestDataStruct},
+    IBKR,
+};
+use crate::{
+    chain::{ExpiryDate, OptionChain},
+    combo::{ComboBuilder, ComboSpread},
+    events::{emit, Event},
+    journal::JournalLeg,
+    logging::log_message,
+    positions::{load_open_combos, ComboSettlement, LegEvent, LegExecution, OpenCombo},
+    structs::Contract,
+    underlying::{osi_symbol, parse_osi_symbol},
+};
+
+/// The most positions the gateway returns per page.
+const POSITIONS_PAGE_SIZE: usize = 100;
+
+impl IBKR {
+    /// Retrieves the positions of the account from the IBKR API.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the signed position by conid, or an error.
+    fn get_positions(&self) -> Result<FxHashMap<String, f64>, Box<dyn Error>> {
+        let account_id: &str = self.account_id.as_deref().ok_or("Account ID is not set")?;
+        let mut positions: FxHashMap<String, f64> = FxHashMap::default();
+        for page in 0.. {
+            let page_positions: Vec<PositionResponse> =
+                endpoints::positions(self.client()?, account_id, page)?;
+            let last_page: bool = page_positions.len() < POSITIONS_PAGE_SIZE;
+            for position in page_positions {
+                *positions.entry(position.conid.to_string()).or_default() += position.position;
+            }
+            if last_page {
+                break;
+            }
+        }
+        Ok(positions)
+    }
+
+    /// Lists the combos of the trade journal the account still holds.
+    ///
+    /// # Arguments
+    ///
+    /// * `journal_path` - The path of the trade journal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing each combo with the number of combos held, or an error if the
+    /// journal or the positions can't be read.
+    pub(crate) fn held_combos(
+        &self,
+        journal_path: &str,
+    ) -> Result<Vec<(OpenCombo, f64)>, Box<dyn Error>> {
+        let positions: FxHashMap<String, f64> = self.get_positions()?;
+        Ok(load_open_combos(journal_path)?
+            .into_iter()
+            .filter_map(|combo| {
+                let held: f64 = combo.held(&positions);
+                (held > 0.0).then_some((combo, held))
+            })
+            .collect())
+    }
+
+    /// Checks the positions of the legs of the journaled combos, if a check is due, and alerts on
+    /// and journals the combos whose legs left the account without an execution: assigned or
+    /// exercised early, or expired. The journal entries settle the combos out of the inventory
+    /// the `close` and `roll` subcommands work from.
+    ///
+    /// # Arguments
+    ///
+    /// * `journal_path` - The path of the trade journal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the settlements found, or an error if the positions or executions
+    /// can't be read.
+    pub(crate) fn check_assignments(
+        &mut self,
+        journal_path: &str,
+    ) -> Result<Vec<ComboSettlement>, Box<dyn Error>> {
+        let Some((interval, next_check)) = self.assignment_checks else {
+            return Ok(Vec::new());
+        };
+        if Instant::now() < next_check {
+            return Ok(Vec::new());
+        }
+        self.assignment_checks = Some((interval, Instant::now() + interval));
+
+        // Without a journal nothing was filled yet.
+        let combos: Vec<OpenCombo> = load_open_combos(journal_path).unwrap_or_default();
+        if combos.is_empty() {
+            return Ok(Vec::new());
+        }
+        let positions: FxHashMap<String, f64> = self.get_positions()?;
+        let executions: Vec<LegExecution> = endpoints::trades(self.client()?)?
+            .into_iter()
+            .filter(|trade| trade.sec_type.as_deref() == Some("OPT"))
+            .filter_map(|trade| {
+                Some(LegExecution {
+                    conid: trade.conid_ex?,
+                    quantity: match trade.side.as_deref()? {
+                        "B" => trade.size,
+                        _ => -trade.size,
+                    },
+                    time: trade.trade_time_r?,
+                    execution_id: trade.execution_id,
+                })
+            })
+            .collect();
+        let settlements: Vec<ComboSettlement> = self.position_watch.reconcile(
+            &combos,
+            &positions,
+            &executions,
+            Local::now().date_naive(),
+            Utc::now().timestamp_millis(),
+        );
+
+        for settlement in &settlements {
+            log_message(format!("WARNING: {}.", settlement.describe()));
+            emit(&Event::Settlement {
+                order_ref: settlement.combo.order_ref.clone(),
+                outcome: settlement.outcome().name().to_string(),
+                size: settlement.size,
+                legs: settlement.events.iter().map(LegEvent::describe).collect(),
+            });
+            if let Some(journal) = &self.journal {
+                journal.record_settlement(settlement);
+            }
+        }
+        Ok(settlements)
+    }
+
+    /// Sells a held combo at its mid less an offset, through the same order endpoint as the
+    /// scanner's orders. The order works until it fills or the day ends.
+    ///
+    /// # Arguments
+    ///
+    /// * `combo` - The combo to close.
+    /// * `quantity` - The number of combos to sell.
+    /// * `offset` - How far below the mid to sell, e.g. `0.05`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the submitted order, or an error if a leg isn't quoted or the order
+    /// is rejected.
+    pub(crate) fn close_combo(
+        &mut self,
+        combo: &OpenCombo,
+        quantity: i32,
+        offset: f64,
+    ) -> Result<OrderBody, Box<dyn Error>> {
+        if let [leg] = combo.legs.as_slice() {
+            if leg.ratio < 0 {
+                return Err(format!(
+                    "{} is a short {}, buy it back instead",
+                    combo.order_ref, leg.symbol
+                )
+                .into());
+            }
+        }
+        let mid: f64 = self.get_combo_mid(&combo.legs)?;
+        let order: OrderBody = self.sell_combo(
+            combo.conidex(),
+            quantity,
+            mid - offset,
+            format!("close-{}", combo.order_ref),
+        )?;
+        if let Some(journal) = &self.journal {
+            journal.record_close(&order.c_oid, combo, quantity, order.price, mid);
+        }
+        Ok(order)
+    }
+
+    /// Picks the calendars due for an automatic roll: those whose short leg expires within the
+    /// configured days and that weren't already rolled today.
+    ///
+    /// # Arguments
+    ///
+    /// * `combos` - The combos of the trade journal.
+    /// * `today` - The current date.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<OpenCombo>` with the calendars to roll, empty if automatic rolls are off.
+    pub(crate) fn due_rolls(&self, combos: Vec<OpenCombo>, today: NaiveDate) -> Vec<OpenCombo> {
+        let Some((days, _)) = self.auto_roll else {
+            return Vec::new();
+        };
+        combos
+            .into_iter()
+            .filter(|combo| {
+                combo.roll_due(today, days)
+                    && self.rolls_submitted.get(&combo.order_ref) != Some(&today)
+            })
+            .collect()
+    }
+
+    /// Records that a calendar was rolled, so it isn't rolled again the same day.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_ref` - The order reference of the calendar.
+    /// * `today` - The current date.
+    pub(crate) fn record_roll(&mut self, order_ref: &str, today: NaiveDate) {
+        self.rolls_submitted.insert(order_ref.to_string(), today);
+    }
+
+    /// Rolls the held calendars of the trade journal whose short leg expires soon to the next
+    /// expiration in the chain, if automatic rolls are on. Each calendar is rolled at most once a
+    /// day, so a roll that fails or doesn't fill is retried the next day.
+    ///
+    /// # Arguments
+    ///
+    /// * `journal_path` - The path of the trade journal.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the submitted roll orders, or an error if the positions can't be
+    /// read.
+    pub(crate) fn roll_expiring_calendars(
+        &mut self,
+        journal_path: &str,
+    ) -> Result<Vec<OrderBody>, Box<dyn Error>> {
+        let Some((_, offset)) = self.auto_roll else {
+            return Ok(Vec::new());
+        };
+        let today: NaiveDate = Local::now().date_naive();
+
+        // Without a journal nothing was filled yet.
+        let due: Vec<OpenCombo> =
+            self.due_rolls(load_open_combos(journal_path).unwrap_or_default(), today);
+        if due.is_empty() {
+            return Ok(Vec::new());
+        }
+        let positions: FxHashMap<String, f64> = self.get_positions()?;
+
+        let mut orders: Vec<OrderBody> = Vec::new();
+        for combo in due {
+            let quantity: i32 = combo.held(&positions) as i32;
+            if quantity < 1 {
+                continue;
+            }
+            self.record_roll(&combo.order_ref, today);
+            match self.roll_calendar(&combo, None, quantity, offset) {
+                Ok(order) => {
+                    log_message(format!(
+                        "Rolling {} of {} before its short leg expires, with order {} at {:.2}.",
+                        quantity,
+                        combo.describe(),
+                        order.c_oid,
+                        order.price
+                    ));
+                    orders.push(order);
+                }
+                Err(e) => log_message(format!(
+                    "Failed to roll {}, retrying tomorrow: {}.",
+                    combo.order_ref, e
+                )),
+            }
+        }
+        Ok(orders)
+    }
+
+    /// Rolls the short leg of a held calendar to a later expiration in one combo order: selling
+    /// the calendar of the short leg and the new expiration buys back the expiring leg and sells
+    /// the new one. The rolled calendar is journaled, so it can be closed or rolled again once the
+    /// roll fills.
+    ///
+    /// # Arguments
+    ///
+    /// * `combo` - The calendar to roll.
+    /// * `expiry` - The expiration to roll the short leg to, or `None` for the next one in the
+    ///   chain.
+    /// * `quantity` - The number of calendars to roll.
+    /// * `offset` - How far below the mid to sell the roll, e.g. `0.05`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the submitted order, or an error if the combo isn't a calendar, the
+    /// expiration isn't between its legs' expirations, a leg isn't quoted or the order is
+    /// rejected.
+    pub(crate) fn roll_calendar(
+        &mut self,
+        combo: &OpenCombo,
+        expiry: Option<ExpiryDate>,
+        quantity: i32,
+        offset: f64,
+    ) -> Result<OrderBody, Box<dyn Error>> {
+        if combo.strategy != "Calendar" || combo.legs.len() != 2 {
+            return Err(format!(
+                "{} is a {}, not a calendar",
+                combo.order_ref, combo.strategy
+            )
+            .into());
+        }
+
+        let (Some(short), Some(long)) = (
+            combo.legs.iter().find(|leg| leg.ratio < 0),
+            combo.legs.iter().find(|leg| leg.ratio > 0),
+        ) else {
+            return Err(format!("{} has no short and long leg", combo.order_ref).into());
+        };
+        let (Some(short_leg), Some(long_leg)) = (
+            parse_osi_symbol(&short.symbol),
+            parse_osi_symbol(&long.symbol),
+        ) else {
+            return Err(format!("{} has legs that aren't OSI symbols", combo.order_ref).into());
+        };
+
+        let chain: &OptionChain = self.chain.as_ref().ok_or("option chain is not set")?;
+        let expiry: ExpiryDate = match expiry {
+            Some(expiry) => expiry,
+            None => chain
+                .dates()
+                .iter()
+                .copied()
+                .find(|date| *date > short_leg.date)
+                .ok_or(format!(
+                    "no expiration after {} in the chain",
+                    short_leg.date
+                ))?,
+        };
+        if expiry <= short_leg.date || expiry >= long_leg.date {
+            return Err(format!(
+                "the short leg rolls to an expiration between {} and {}, got {}",
+                short_leg.date, long_leg.date, expiry
+            )
+            .into());
+        }
+        let rolled_leg: Contract = Contract {
+            date: expiry,
+            ..short_leg.clone()
+        };
+        let rolled_conid: String = chain
+            .conid_for(expiry, &rolled_leg.type_contract, rolled_leg.strike)
+            .ok_or(format!(
+                "{} is not in the selected chain, check EXPIRY_SELECTOR",
+                rolled_leg.describe()
+            ))?
+            .clone();
+        let con_idex: String = ComboBuilder::new(ComboSpread::Calendar)
+            .leg(&short.conid, &short_leg, -1)
+            .leg(&rolled_conid, &rolled_leg, 1)
+            .build()?;
+
+        let roll_legs: Vec<JournalLeg> = vec![
+            JournalLeg {
+                symbol: short.symbol.clone(),
+                ratio: -1,
+                conid: short.conid.clone(),
+            },
+            JournalLeg {
+                symbol: osi_symbol(
+                    &self.underlying.ticker,
+                    chain.trading_class_for(expiry, &rolled_leg.type_contract, rolled_leg.strike),
+                    expiry,
+                    &rolled_leg.type_contract,
+                    rolled_leg.strike,
+                ),
+                ratio: 1,
+                conid: rolled_conid.clone(),
+            },
+        ];
+        let mid: f64 = self.get_combo_mid(&roll_legs)?;
+        let order: OrderBody = self.sell_combo(
+            con_idex,
+            quantity,
+            mid - offset,
+            format!("roll-{}", combo.order_ref),
+        )?;
+        let rolled: OpenCombo = OpenCombo {
+            order_ref: order.c_oid.clone(),
+            exp_date: expiry,
+            legs: vec![
+                JournalLeg {
+                    ratio: -1,
+                    ..roll_legs[1].clone()
+                },
+                long.clone(),
+            ],
+            size: quantity as f64,
+            // The credit of the roll lowers what the calendar cost.
+            fill_price: combo.fill_price - order.price,
+            ..combo.clone()
+        };
+        if let Some(journal) = &self.journal {
+            journal.record_roll(combo, &rolled, order.price, mid);
+        }
+        Ok(order)
+    }
+
+    /// Places a DAY limit order selling a combo, routed like the scanner's orders.
+    ///
+    /// # Arguments
+    ///
+    /// * `con_idex` - The combo conidex, e.g. `28812380;;;123/1,456/-1`.
+    /// * `quantity` - The number of combos to sell.
+    /// * `price` - The limit price, rounded to a valid tick.
+    /// * `c_oid` - The customer order id.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the placed order, or an error if it is rejected.
+    fn sell_combo(
+        &self,
+        con_idex: String,
+        quantity: i32,
+        price: f64,
+        c_oid: String,
+    ) -> Result<OrderBody, Box<dyn Error>> {
+        let account_id: &str = self.account_id.as_deref().ok_or("Account ID is not set")?;
+        let (strategy, strategy_parameters) = self.algo.strategy().unzip();
+        let order: OrderBody = OrderBody {
+            acct_id: account_id.to_string(),
+            con_idex,
+            order_type: "LMT".to_string(),
+            listing_exchange: self.underlying.listing_exchange.clone(),
+            outside_rth: self.session.outside_rth(),
+            price: self.underlying.round_to_tick(price),
+            side: "SELL".to_string(),
+            ticker: self.underlying.ticker.clone(),
+            tif: "DAY".to_string(),
+            referrer: "NO_REFERRER_PROVIDED".to_string(),
+            quantity,
+            use_adaptive: self.algo.use_adaptive,
+            strategy,
+            strategy_parameters: strategy_parameters.unwrap_or_default(),
+            c_oid,
+            price_offset: 0.0,
+        };
+        let replies: Vec<OrderReply> = endpoints::place_orders(
+            self.client()?,
+            account_id,
+            &RequestDataStruct {
+                orders: vec![order.clone()],
+            },
+        )
+        .and_then(|replies| self.confirm_replies(replies))?;
+        if replies.first().is_none_or(|reply| reply.order_id.is_none()) {
+            return Err("the gateway didn't return an order ID".into());
+        }
+        Ok(order)
+    }
+}
@@ -0,0 +1,123 @@
+use serde_json::Value;
+
+/// The response schemas of the Client Portal gateway releases the bot supports.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum ResponseSchema {
+    /// Builds 10.20 to 10.29, which use camelCase keys in account, trade and order reply
+    /// responses and key snapshots by a numeric `conid`.
+    Legacy,
+    /// Builds 10.30 and later 10.x releases.
+    #[default]
+    Current,
+}
+
+impl ResponseSchema {
+    /// Picks the schema of a gateway build.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_version` - The `serverVersion` the gateway reports, e.g.
+    ///   `"Build 10.30.1t, Jul 22, 2024 1:24:32 PM"`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the schema, or an error naming the version if it isn't supported.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let schema = ResponseSchema::for_version("Build 10.25.0p, Dec 5, 2023 6:43:23 PM")?;
+    /// ```
+    pub(crate) fn for_version(server_version: &str) -> Result<Self, String> {
+        let build: &str = server_version
+            .trim()
+            .trim_start_matches("Build")
+            .split(',')
+            .next()
+            .unwrap_or_default()
+            .trim();
+        let mut parts = build.split('.');
+        let major: Option<u32> = parts.next().and_then(|part| part.parse::<u32>().ok());
+        let minor: Option<u32> = parts.next().and_then(|part| {
+            let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u32>().ok()
+        });
+        match (major, minor) {
+            (Some(10), Some(20..=29)) => Ok(ResponseSchema::Legacy),
+            (Some(10), Some(30..)) => Ok(ResponseSchema::Current),
+            _ => Err(format!(
+                "Gateway version {:?} is not supported, use a 10.x build from 10.20 on",
+                server_version
+            )),
+        }
+    }
+
+    /// Rewrites a response of an older gateway into the current schema, so the models only know
+    /// one set of field names.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The API path the response came from.
+    /// * `value` - The parsed response, rewritten in place.
+    pub(crate) fn adapt(&self, path: &str, value: &mut Value) {
+        if *self == ResponseSchema::Current {
+            return;
+        }
+
+        let renames: &[(&str, &str)] = if path == "/portfolio/accounts" {
+            &[("accountId", "id")]
+        } else if path == "/iserver/marketdata/snapshot" {
+            &[("conid", "conidEx")]
+        } else if path == "/iserver/account/trades" {
+            &[
+                ("executionId", "execution_id"),
+                ("orderRef", "order_ref"),
+                ("secType", "sec_type"),
+            ]
+        } else if path.starts_with("/iserver/reply/")
+            || (path.starts_with("/iserver/account/") && path.contains("/order"))
+        {
+            &[
+                ("orderId", "order_id"),
+                ("localOrderId", "local_order_id"),
+                ("orderStatus", "order_status"),
+            ]
+        } else {
+            &[]
+        };
+
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    rename_keys(item, renames);
+                }
+            }
+            _ => rename_keys(value, renames),
+        }
+    }
+}
+
+/// Renames the keys of an object that lacks the new key, turning numbers into strings since the
+/// current schema sends these IDs as strings.
+///
+/// # Arguments
+///
+/// * `value` - The object.
+/// * `renames` - The `(old, new)` key pairs.
+fn rename_keys(value: &mut Value, renames: &[(&str, &str)]) {
+    let Value::Object(object) = value else {
+        return;
+    };
+    for (old, new) in renames {
+        if object.contains_key(*new) {
+            continue;
+        }
+        if let Some(field) = object.remove(*old) {
+            let field: Value = match field {
+                Value::Number(number) => Value::String(number.to_string()),
+                field => field,
+            };
+            object.insert(new.to_string(), field);
+        }
+    }
+}
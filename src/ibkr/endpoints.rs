@@ -0,0 +1,379 @@
+use chrono::{DateTime, Utc};
+use std::{error::Error, time::Instant};
+
+use super::{
+    client::GatewayClient,
+    models::{
+        AccountResponse, AuthStatus, Confirmation, ContractRulesResponse, LiveOrdersResponse,
+        MarginResponse, MarketDataResponse, OrderBody, OrderReplies, OrderReply, PnlResponse,
+        PortfolioResponse, PositionResponse, RequestDataStruct, SecDefInfoResponse, SecDefResponse,
+        TradeResponse, WhatIfResponse,
+    },
+};
+use crate::metrics::observe_snapshot_latency;
+
+/// Retrieves the login state of the gateway's brokerage session.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+///
+/// # Returns
+///
+/// A `Result` containing the `AuthStatus` or an error.
+pub(crate) fn auth_status(client: &GatewayClient) -> Result<AuthStatus, Box<dyn Error>> {
+    client.get("/iserver/auth/status", &[])
+}
+
+/// Reads the gateway's clock.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+///
+/// # Returns
+///
+/// A `Result` containing the local time halfway through the request and the gateway's time, or
+/// an error.
+pub(crate) fn clock(
+    client: &GatewayClient,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), Box<dyn Error>> {
+    client.clock("/iserver/auth/status")
+}
+
+/// Retrieves the accounts the gateway session can trade.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+///
+/// # Returns
+///
+/// A `Result` containing the accounts or an error.
+pub(crate) fn accounts(client: &GatewayClient) -> Result<Vec<AccountResponse>, Box<dyn Error>> {
+    client.get("/portfolio/accounts", &[])
+}
+
+/// Searches the contracts of a symbol.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `symbol` - The symbol, e.g. `"SPX"`.
+///
+/// # Returns
+///
+/// A `Result` containing the matching contracts, best match first, or an error.
+pub(crate) fn search_secdef(
+    client: &GatewayClient,
+    symbol: &str,
+) -> Result<Vec<SecDefResponse>, Box<dyn Error>> {
+    client.get("/iserver/secdef/search", &[("symbol", symbol)])
+}
+
+/// Retrieves the SMART-routed option contracts of an underlying expiring in a month.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `conid` - The conid of the underlying.
+/// * `month` - The month, e.g. `"JAN25"`.
+///
+/// # Returns
+///
+/// A `Result` containing the option contracts or an error.
+pub(crate) fn secdef_info(
+    client: &GatewayClient,
+    conid: &str,
+    month: &str,
+) -> Result<Vec<SecDefInfoResponse>, Box<dyn Error>> {
+    client.get(
+        "/iserver/secdef/info",
+        &[
+            ("conid", conid),
+            ("sectype", "OPT"),
+            ("month", month),
+            ("exchange", "SMART"),
+            ("strike", "0"),
+        ],
+    )
+}
+
+/// Retrieves the trading rules of a contract, including the accounts permitted to trade it.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `conid` - The conid of the contract.
+///
+/// # Returns
+///
+/// A `Result` containing the `ContractRulesResponse` or an error.
+pub(crate) fn contract_rules(
+    client: &GatewayClient,
+    conid: &str,
+) -> Result<ContractRulesResponse, Box<dyn Error>> {
+    client.get(
+        &format!("/iserver/contract/{}/info-and-rules", conid),
+        &[("isBuy", "true")],
+    )
+}
+
+/// Retrieves a market data snapshot. The first request for a conid only subscribes to it.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `conids` - The comma-separated conids or combo conidexes.
+/// * `fields` - The comma-separated field codes, e.g. `"84,86"`.
+///
+/// # Returns
+///
+/// A `Result` containing a snapshot per conid or an error.
+pub(crate) fn snapshot(
+    client: &GatewayClient,
+    conids: &str,
+    fields: &str,
+) -> Result<Vec<MarketDataResponse>, Box<dyn Error>> {
+    let start: Instant = Instant::now();
+    let snapshots: Vec<MarketDataResponse> = client.get(
+        "/iserver/marketdata/snapshot",
+        &[("conids", conids), ("fields", fields)],
+    )?;
+    observe_snapshot_latency(start.elapsed());
+    Ok(snapshots)
+}
+
+/// Retrieves the equity with loan value from the account summary.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `account_id` - The account.
+///
+/// # Returns
+///
+/// A `Result` containing the `PortfolioResponse` or an error.
+pub(crate) fn portfolio_summary(
+    client: &GatewayClient,
+    account_id: &str,
+) -> Result<PortfolioResponse, Box<dyn Error>> {
+    client.get(&format!("/portfolio/{}/summary", account_id), &[])
+}
+
+/// Retrieves a page of the positions of an account.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `account_id` - The account.
+/// * `page` - The page, from 0. Pages hold up to 100 positions.
+///
+/// # Returns
+///
+/// A `Result` containing the positions of the page, empty past the last one, or an error.
+pub(crate) fn positions(
+    client: &GatewayClient,
+    account_id: &str,
+    page: u32,
+) -> Result<Vec<PositionResponse>, Box<dyn Error>> {
+    client.get(
+        &format!("/portfolio/{}/positions/{}", account_id, page),
+        &[],
+    )
+}
+
+/// Retrieves the maintenance margin, net liquidation and excess liquidity from the account
+/// summary.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `account_id` - The account.
+///
+/// # Returns
+///
+/// A `Result` containing the `MarginResponse` or an error.
+pub(crate) fn margin_summary(
+    client: &GatewayClient,
+    account_id: &str,
+) -> Result<MarginResponse, Box<dyn Error>> {
+    client.get(&format!("/portfolio/{}/summary", account_id), &[])
+}
+
+/// Retrieves the daily and unrealized P&L of each account partition.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+///
+/// # Returns
+///
+/// A `Result` containing the `PnlResponse` or an error.
+pub(crate) fn pnl(client: &GatewayClient) -> Result<PnlResponse, Box<dyn Error>> {
+    client.get("/iserver/account/pnl/partitioned", &[])
+}
+
+/// Retrieves the live orders of the session, bypassing the gateway's cache.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+///
+/// # Returns
+///
+/// A `Result` containing the `LiveOrdersResponse` or an error.
+pub(crate) fn live_orders(client: &GatewayClient) -> Result<LiveOrdersResponse, Box<dyn Error>> {
+    client.get("/iserver/account/orders", &[("force", "true")])
+}
+
+/// Retrieves the recent executions of the session.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+///
+/// # Returns
+///
+/// A `Result` containing the executions or an error.
+pub(crate) fn trades(client: &GatewayClient) -> Result<Vec<TradeResponse>, Box<dyn Error>> {
+    client.get("/iserver/account/trades", &[])
+}
+
+/// Previews the margin impact of an order without placing it.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `account_id` - The account.
+/// * `order` - The order to preview.
+///
+/// # Returns
+///
+/// A `Result` containing the `WhatIfResponse` or an error.
+pub(crate) fn whatif(
+    client: &GatewayClient,
+    account_id: &str,
+    order: &OrderBody,
+) -> Result<WhatIfResponse, Box<dyn Error>> {
+    client.post(
+        &format!("/iserver/account/{}/orders/whatif", account_id),
+        &RequestDataStruct {
+            orders: vec![order.clone()],
+        },
+    )
+}
+
+/// Places orders.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `account_id` - The account.
+/// * `request_data` - The orders.
+///
+/// # Returns
+///
+/// A `Result` containing the replies, which may be warnings to confirm with `reply`, or an error.
+pub(crate) fn place_orders(
+    client: &GatewayClient,
+    account_id: &str,
+    request_data: &RequestDataStruct,
+) -> Result<Vec<OrderReply>, Box<dyn Error>> {
+    client
+        .post_audited::<_, OrderReplies>(
+            &format!("/iserver/account/{}/orders", account_id),
+            request_data,
+        )?
+        .into_replies()
+}
+
+/// Confirms a warning IBKR replied to an order request with.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `reply_id` - The `id` of the reply.
+///
+/// # Returns
+///
+/// A `Result` containing the next replies or an error.
+pub(crate) fn reply(
+    client: &GatewayClient,
+    reply_id: &str,
+) -> Result<Vec<OrderReply>, Box<dyn Error>> {
+    client
+        .post_audited::<_, OrderReplies>(
+            &format!("/iserver/reply/{}", reply_id),
+            &Confirmation { confirmed: true },
+        )?
+        .into_replies()
+}
+
+/// Modifies a working order.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `account_id` - The account.
+/// * `order_id` - The IBKR order ID.
+/// * `order` - The order with its new terms.
+///
+/// # Returns
+///
+/// A `Result` containing the replies, which may be warnings to confirm with `reply`, or an error.
+pub(crate) fn modify_order(
+    client: &GatewayClient,
+    account_id: &str,
+    order_id: &str,
+    order: &OrderBody,
+) -> Result<Vec<OrderReply>, Box<dyn Error>> {
+    client
+        .post_audited::<_, OrderReplies>(
+            &format!("/iserver/account/{}/order/{}", account_id, order_id),
+            order,
+        )?
+        .into_replies()
+}
+
+/// Cancels a working order.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `account_id` - The account.
+/// * `order_id` - The IBKR order ID.
+///
+/// # Returns
+///
+/// A `Result` that is an error if the order could not be canceled.
+pub(crate) fn cancel_order(
+    client: &GatewayClient,
+    account_id: &str,
+    order_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    client.delete_audited(&format!(
+        "/iserver/account/{}/order/{}",
+        account_id, order_id
+    ))?;
+    Ok(())
+}
+
+/// Retrieves the portfolio value from the IBKR API.
+///
+/// # Arguments
+///
+/// * `client` - The gateway client.
+/// * `account_id` - The account.
+///
+/// # Returns
+///
+/// A `Result` containing the portfolio value or an error.
+pub(crate) fn fetch_portfolio_value(
+    client: &GatewayClient,
+    account_id: &str,
+) -> Result<f64, Box<dyn Error>> {
+    Ok(portfolio_summary(client, account_id)?
+        .equity_with_loan_value
+        .amount)
+}
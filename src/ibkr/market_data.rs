@@ -0,0 +1,489 @@
+use chrono::{Local, NaiveDate, Utc};
+use rustc_hash::FxHashMap;
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use super::{
+    client::GatewayClient,
+    endpoints,
+    models::{MarketDataResponse, SecDefInfoResponse, SecDefResponse},
+    preflight::has_delayed_quotes,
+    IBKR,
+};
+use crate::{
+    chain::{ExpiryDate, ExpiryId, OptionChain, Quotes, Right},
+    dividends::parse_ex_date,
+    expirations::ExpiryCheck,
+    helpers::{calc_time_difference, minutes_to_market_close, parse_last_price},
+    journal::JournalLeg,
+    logging::{log_debug, log_message},
+    scanner::Scanner,
+    structs::{Contender, Opt},
+    watchlist::WatchlistEntry,
+};
+
+impl IBKR {
+    /// Prices a structure given on the command line on fresh quotes of the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The structure.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the priced contender, above the thresholds or not, or an error if a
+    /// leg is outside the selected chain or the structure can't be priced.
+    pub(crate) fn quote_structure(
+        &mut self,
+        entry: &WatchlistEntry,
+    ) -> Result<Contender, Box<dyn Error>> {
+        let contender: Contender = entry.to_contender()?;
+        let chain: &OptionChain = self.chain.as_ref().ok_or("option chain is not set")?;
+        if let Some(leg) = contender.contracts.iter().find(|leg| {
+            chain
+                .conid_for(leg.date, &leg.type_contract, leg.strike)
+                .is_none()
+        }) {
+            return Err(format!(
+                "{} is not in the selected chain, check EXPIRY_SELECTOR",
+                leg.describe()
+            )
+            .into());
+        }
+
+        let contracts_map: Quotes = self.get_ticker_data()?;
+        let spot: Option<f64> = self.get_underlying_price().ok();
+        let chain: &OptionChain = self.chain.as_ref().ok_or("option chain is not set")?;
+        let scanner: &Scanner = self.scanner.as_ref().ok_or("scanner is not set")?;
+        let mut contender: Contender = scanner.price_structure(
+            entry,
+            &contracts_map,
+            chain,
+            Local::now().date_naive(),
+            minutes_to_market_close(Utc::now()),
+            spot,
+        )?;
+        contender.scan_time = Utc::now().timestamp_millis();
+        if let Some(fill_model) = &self.fill_model {
+            contender.fill_probability = fill_model.fill_probability(contender.quoted_edge());
+        }
+        Ok(contender)
+    }
+
+    /// Retrieves the last price of the underlying, logging a failure so the scan goes on without
+    /// it; the moneyness filters then reject every spread they apply to.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<f64>` with the price of the underlying, or `None` if it couldn't be fetched.
+    pub(crate) fn fetch_spot(&self) -> Option<f64> {
+        match self.get_underlying_price() {
+            Ok(spot) => Some(spot),
+            Err(e) => {
+                log_message(format!("Failed to get underlying price: {}.", e));
+                None
+            }
+        }
+    }
+
+    /// Retrieves the last price of the underlying from the IBKR API.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the price of the underlying or an error.
+    fn get_underlying_price(&self) -> Result<f64, Box<dyn Error>> {
+        self.get_underlying_snapshot()?
+            .iter()
+            .find_map(|response| response.field_31.as_deref().and_then(parse_last_price))
+            .ok_or_else(|| "No last price in the snapshot".into())
+    }
+
+    /// Retrieves the next dividend of the underlying from the IBKR API.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the ex-date and amount of the next dividend, `None` if the underlying
+    /// pays none, or an error.
+    pub(crate) fn get_upcoming_dividend(&self) -> Result<Option<(NaiveDate, f64)>, Box<dyn Error>> {
+        Ok(self.get_underlying_snapshot()?.iter().find_map(|response| {
+            let ex_date: NaiveDate = parse_ex_date(response.field_7288.as_deref()?)?;
+            let amount: f64 = response.field_7286.as_deref()?.trim().parse::<f64>().ok()?;
+            (amount > 0.0).then_some((ex_date, amount))
+        }))
+    }
+
+    /// Retrieves the last price and dividend fields of the underlying from the IBKR API.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the snapshot responses or an error.
+    fn get_underlying_snapshot(&self) -> Result<Vec<MarketDataResponse>, Box<dyn Error>> {
+        let ticker_id: &str = self.ticker_id.as_ref().ok_or("Ticker ID is not set")?;
+        endpoints::snapshot(self.data_client()?, ticker_id, "31,7286,7288")
+    }
+
+    /// Retrieves the NBBO of a combo from the IBKR API.
+    ///
+    /// # Arguments
+    ///
+    /// * `conidex` - The combo conidex, e.g. `28812380;;;123/1,456/-1`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the bid and ask, `None` until the combo is quoted, or an error.
+    pub(crate) fn get_combo_quote(
+        &self,
+        conidex: &str,
+    ) -> Result<Option<(f64, f64)>, Box<dyn Error>> {
+        let responses: Vec<MarketDataResponse> =
+            endpoints::snapshot(self.data_client()?, conidex, "84,86")?;
+        Ok(responses.first().and_then(MarketDataResponse::combo_quote))
+    }
+
+    /// Retrieves ticker data from the IBKR API and parses it into a map of options.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a map of options or an error.
+    pub(crate) fn get_ticker_data(&mut self) -> Result<Quotes, Box<dyn Error>> {
+        let mut contracts_map: Quotes = Quotes::default();
+        let chain: &OptionChain = self.chain.as_ref().ok_or("option chain is not set")?;
+        let conids_arr: &Vec<String> = self.conids_strings.as_ref().unwrap();
+
+        let client: Arc<GatewayClient> = Arc::new(self.data_client()?.clone());
+        let response_arr: Arc<Mutex<Vec<Vec<MarketDataResponse>>>> =
+            Arc::new(Mutex::new(Vec::new()));
+
+        let mut handles: Vec<thread::JoinHandle<Result<(), String>>> = Vec::new();
+
+        for conid in conids_arr {
+            let client: Arc<GatewayClient> = Arc::clone(&client);
+            let response_arr: Arc<Mutex<Vec<Vec<MarketDataResponse>>>> = Arc::clone(&response_arr);
+            let conid: String = conid.clone();
+
+            let handle: thread::JoinHandle<Result<(), String>> = thread::spawn(move || {
+                match endpoints::snapshot(&client, &conid, "84,85,86,88,6509,7308,7633,7635") {
+                    Ok(responses) => response_arr.lock().unwrap().push(responses),
+                    Err(e) => return Err(format!("Failed to get ticker data: {}", e)),
+                }
+                Ok(())
+            });
+
+            handles.push(handle);
+        }
+
+        // Wait for every request before failing, so no thread outlives the scan.
+        let results: Vec<Result<(), String>> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        results.into_iter().collect::<Result<(), String>>()?;
+
+        let mut response_vec: std::sync::MutexGuard<'_, Vec<Vec<MarketDataResponse>>> =
+            response_arr.lock().unwrap();
+
+        let mut delayed: bool = false;
+        for generic_responses in response_vec.drain(..) {
+            delayed |= has_delayed_quotes(
+                generic_responses
+                    .iter()
+                    .filter(|response| chain.key(&response.conid_ex).is_some()),
+            );
+            for response in &generic_responses {
+                let Some(key) = chain.key(&response.conid_ex) else {
+                    continue;
+                };
+                // Sizes, Greeks and marks are optional extras, so only a malformed quote is an error.
+                let bsz: Option<f64> = response.bid_size().ok().flatten();
+                let delta: Option<f64> = response.delta().ok().flatten();
+                let mark: Option<f64> = response.mark().ok().flatten();
+                let iv: Option<f64> = response.implied_volatility().ok().flatten();
+                let quote: Opt = match (response.bid()?, response.ask_size()?, response.ask()?) {
+                    (Some(bid), Some(asz), Some(ask)) => Opt {
+                        asz,
+                        mkt: ((bid + ask) / 2.0 * 100.0).round() / 100.0,
+                        bid,
+                        ask,
+                        bsz,
+                        delta,
+                        mark,
+                        iv,
+                        model: None,
+                    },
+                    _ => Opt {
+                        asz: 0.0,
+                        mkt: 0.0,
+                        bid: 0.0,
+                        ask: 0.0,
+                        bsz: None,
+                        delta: None,
+                        mark: None,
+                        iv: None,
+                        model: None,
+                    },
+                };
+                contracts_map.insert(*key, quote);
+            }
+        }
+        self.record_quote_delay(delayed);
+
+        Ok(contracts_map)
+    }
+
+    /// Records whether a scan's quotes were delayed, logging when they turn delayed or real-time.
+    ///
+    /// # Arguments
+    ///
+    /// * `delayed` - Whether any quote of the scan was delayed.
+    pub(crate) fn record_quote_delay(&mut self, delayed: bool) {
+        if delayed && !self.delayed_data {
+            log_message(
+                "WARNING: the option quotes are delayed, real-time market data isn't entitled."
+                    .to_string(),
+            );
+        } else if !delayed && self.delayed_data {
+            log_message("The option quotes are real-time again.".to_string());
+        }
+        self.delayed_data = delayed;
+    }
+
+    /// Returns whether the last scan came with delayed market data, on which the bot must not
+    /// trade.
+    pub(crate) fn has_delayed_data(&self) -> bool {
+        self.delayed_data
+    }
+
+    /// Initializes ticker data by sending a request to the IBKR API.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the initialization succeeded or failed.
+    pub(crate) fn init_ticker_data(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let conids_arr: &Vec<String> = self.conids_strings.as_ref().unwrap();
+
+        for conid in conids_arr {
+            endpoints::snapshot(
+                self.data_client()?,
+                conid,
+                "84,85,86,88,6509,7308,7633,7635",
+            )?;
+        }
+
+        // The first snapshot request for a conid only subscribes to it, so no price is expected.
+        let _ = self.get_underlying_price();
+
+        Ok(())
+    }
+
+    /// Retrieves the ticker conid and relevant months for the options.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the ticker conid and the listed option months, nearest first, or an error.
+    pub(crate) fn get_ticker_conid(&self) -> Result<(String, Vec<String>), Box<dyn Error>> {
+        let search_results: Vec<SecDefResponse> =
+            endpoints::search_secdef(self.data_client()?, self.ticker.as_ref().unwrap())?;
+        let mut option_months: Vec<String> = Vec::new();
+
+        let result: &SecDefResponse = search_results
+            .first()
+            .ok_or("No ticker conid found in the response")?;
+        if let Some(conid) = &result.conid {
+            if !conid.is_empty() {
+                if let Some(sections) = &result.sections {
+                    for section in sections {
+                        if section.sec_type == "OPT" {
+                            if let Some(months) = &section.months {
+                                option_months = months
+                                    .split(';')
+                                    .filter(|month| !month.is_empty())
+                                    .map(|month| month.to_string())
+                                    .collect();
+                            }
+                            break;
+                        }
+                    }
+                }
+
+                return Ok((conid.to_string(), option_months));
+            }
+        }
+
+        Err("No ticker conid found in the response".into())
+    }
+
+    /// Retrieves the option chain (expirations, strikes and conids) for the options contracts.
+    ///
+    /// The chain holds the expirations the expiry selectors pick from the exchange calendar, plus
+    /// every expiration inside any of the strategy DTE windows. Months are fetched until both are
+    /// covered.
+    ///
+    /// # Arguments
+    ///
+    /// * `months` - The listed option months, nearest first.
+    /// * `dte_windows` - The `(min_dte, max_dte)` windows configured by the strategies.
+    /// * `exclude_same_day` - Whether today's expiration is left out of the chain.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `OptionChain` and the `(min_dte, max_dte)` window of the selected
+    /// expirations, or an error.
+    pub(crate) fn get_conids_map(
+        &self,
+        months: &[String],
+        dte_windows: &[(i64, i64)],
+        exclude_same_day: bool,
+    ) -> Result<(OptionChain, (i64, i64)), Box<dyn Error>> {
+        let mut chain: OptionChain = OptionChain::new();
+        let current_date: NaiveDate = Local::now().date_naive();
+        let selected: BTreeSet<ExpiryDate> =
+            self.expiration_calendar
+                .select(&self.expiry_selectors, current_date, exclude_same_day);
+        let selected_window: (i64, i64) = match (selected.first(), selected.last()) {
+            (Some(first), Some(last)) => (
+                calc_time_difference(current_date, *first),
+                calc_time_difference(current_date, *last),
+            ),
+            _ => (0, 0),
+        };
+        let max_window_dte: i64 = dte_windows
+            .iter()
+            .map(|(_, max)| *max)
+            .max()
+            .unwrap_or(0)
+            .max(selected_window.1);
+        let mut last_dte: i64 = -1;
+        let mut listed: BTreeSet<ExpiryDate> = BTreeSet::new();
+
+        for month in months {
+            if last_dte >= max_window_dte {
+                break;
+            }
+
+            let search_results: Vec<SecDefInfoResponse> = endpoints::secdef_info(
+                self.data_client()?,
+                self.ticker_id.as_ref().unwrap(),
+                month,
+            )?;
+
+            for sec_def_info in search_results.iter() {
+                let exp_date: ExpiryDate =
+                    match ExpiryDate::from_maturity(&sec_def_info.maturity_date) {
+                        Ok(exp_date) => exp_date,
+                        Err(e) => {
+                            log_message(format!(
+                                "Skipping contract {}: {}.",
+                                sec_def_info.conid, e
+                            ));
+                            continue;
+                        }
+                    };
+                let dte: i64 = calc_time_difference(current_date, exp_date);
+                if dte >= 0 {
+                    listed.insert(exp_date);
+                }
+                if dte < 0 || (dte == 0 && exclude_same_day) {
+                    continue;
+                }
+                last_dte = last_dte.max(dte);
+
+                if !chain.contains_date(exp_date)
+                    && !selected.contains(&exp_date)
+                    && !dte_windows
+                        .iter()
+                        .any(|(min_dte, max_dte)| dte >= *min_dte && dte <= *max_dte)
+                {
+                    continue;
+                }
+
+                insert_sec_def_info(&mut chain, sec_def_info, exp_date);
+            }
+        }
+
+        chain.sort_strikes();
+
+        let check: ExpiryCheck = self
+            .expiration_calendar
+            .cross_validate(&listed, current_date);
+        if check.is_consistent() {
+            log_debug(format!("{}.", check.describe()));
+        } else {
+            log_message(format!("WARNING: {}.", check.describe()));
+        }
+
+        Ok((chain, selected_window))
+    }
+
+    /// Retrieves the mid price of option legs from the IBKR API.
+    ///
+    /// # Arguments
+    ///
+    /// * `conids` - The leg conids.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the mid price by conid of the legs quoted on both sides, or an error.
+    pub(crate) fn get_leg_mids(
+        &self,
+        conids: &[String],
+    ) -> Result<FxHashMap<String, f64>, Box<dyn Error>> {
+        let responses: Vec<MarketDataResponse> =
+            endpoints::snapshot(self.data_client()?, &conids.join(","), "84,86")?;
+        Ok(responses
+            .iter()
+            .filter_map(|response| {
+                let (bid, ask) = response.combo_quote()?;
+                Some((response.conid_ex.clone(), (bid + ask) / 2.0))
+            })
+            .collect())
+    }
+
+    /// Prices a combo at the mids of its legs.
+    ///
+    /// # Arguments
+    ///
+    /// * `legs` - The legs with their conids and ratios.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the mid per combo, or an error if a leg isn't quoted on both sides.
+    pub(crate) fn get_combo_mid(&self, legs: &[JournalLeg]) -> Result<f64, Box<dyn Error>> {
+        let conids: Vec<String> = legs.iter().map(|leg| leg.conid.clone()).collect();
+        let mids: FxHashMap<String, f64> = self.get_leg_mids(&conids)?;
+        legs.iter()
+            .map(|leg| Some(mids.get(&leg.conid)? * leg.ratio as f64))
+            .sum::<Option<f64>>()
+            .ok_or_else(|| "a leg isn't quoted on both sides".into())
+    }
+}
+
+/// Inserts a secdef info entry into the option chain, adding its expiration if needed.
+///
+/// # Arguments
+///
+/// * `chain` - The option chain to insert into.
+/// * `sec_def_info` - The secdef info entry for the contract.
+/// * `exp_date` - The expiration date of the contract.
+fn insert_sec_def_info(
+    chain: &mut OptionChain,
+    sec_def_info: &SecDefInfoResponse,
+    exp_date: ExpiryDate,
+) {
+    let Some(right) = Right::from_str(&sec_def_info.right) else {
+        return;
+    };
+    let expiry: ExpiryId = chain.add_expiry(exp_date);
+    chain.insert(
+        expiry,
+        right,
+        sec_def_info.strike,
+        sec_def_info.conid.to_string(),
+    );
+    if let Some(trading_class) = &sec_def_info.trading_class {
+        chain.set_trading_class(expiry, right, sec_def_info.strike, trading_class.clone());
+    }
+}
@@ -0,0 +1,437 @@
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+};
+
+/// Deserializes a field the gateway sends either as a string or as a number, e.g. snapshot
+/// prices, into its string form.
+///
+/// # Arguments
+///
+/// * `deserializer` - The deserializer of the field.
+///
+/// # Returns
+///
+/// A `Result` containing the value as a string, `None` for `null`, or an error naming the
+/// unexpected type.
+fn string_or_number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(Value::Number(value)) => Ok(Some(value.to_string())),
+        Some(value) => Err(de::Error::custom(format!(
+            "expected a string or a number, found {}",
+            value
+        ))),
+    }
+}
+
+/// Parses a numeric snapshot field, which may carry thousands separators.
+///
+/// # Arguments
+///
+/// * `name` - The name of the field, for the error.
+/// * `value` - The field, `None` or empty until it is quoted.
+///
+/// # Returns
+///
+/// A `Result` containing the number, `None` if the field isn't quoted, or an error if it isn't a
+/// number.
+fn parse_number(name: &str, value: &Option<String>) -> Result<Option<f64>, Box<dyn Error>> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => match value.replace(",", "").parse::<f64>() {
+            Ok(number) => Ok(Some(number)),
+            Err(_) => Err(format!("The {} {:?} is not a number", name, value).into()),
+        },
+    }
+}
+
+/// The login state of the gateway's brokerage session.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct AuthStatus {
+    #[serde(default)]
+    pub(crate) authenticated: bool,
+    #[serde(default)]
+    pub(crate) connected: bool,
+    #[serde(rename = "serverInfo", default)]
+    pub(crate) server_info: Option<ServerInfo>,
+}
+
+/// The gateway build answering the requests.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ServerInfo {
+    #[serde(rename = "serverName", default)]
+    pub(crate) server_name: Option<String>,
+    /// The build, e.g. `"Build 10.30.1t, Jul 22, 2024 1:24:32 PM"`.
+    #[serde(rename = "serverVersion", default)]
+    pub(crate) server_version: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Confirmation {
+    pub(crate) confirmed: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct AccountResponse {
+    pub(crate) id: String,
+}
+
+/// The contract details and trading rules of a contract, of which only the accounts permitted to
+/// trade it are used.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct ContractRulesResponse {
+    #[serde(default)]
+    pub(crate) rules: Option<ContractRules>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct ContractRules {
+    /// The accounts of the session permitted to trade the contract.
+    #[serde(rename = "canTradeAcctIds", default)]
+    pub(crate) can_trade_acct_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SecDefResponse {
+    #[serde(rename = "companyName", default)]
+    pub(crate) company_name: String,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub(crate) conid: Option<String>,
+    pub(crate) sections: Option<Vec<Section>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Section {
+    #[serde(rename = "secType")]
+    pub(crate) sec_type: String,
+    pub(crate) months: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SecDefInfoResponse {
+    pub(crate) conid: f64,
+    #[serde(rename = "maturityDate")]
+    pub(crate) maturity_date: String,
+    pub(crate) right: String,
+    pub(crate) strike: f64,
+    /// The trading class, e.g. `SPXW` for PM-settled SPX weeklies, which is the OSI root.
+    #[serde(rename = "tradingClass", default)]
+    pub(crate) trading_class: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct MarketDataResponse {
+    #[serde(rename = "conidEx")]
+    pub(crate) conid_ex: String,
+    /// The bid.
+    #[serde(rename = "84", default, deserialize_with = "string_or_number")]
+    pub(crate) field_84: Option<String>,
+    /// The ask size.
+    #[serde(rename = "85", default, deserialize_with = "string_or_number")]
+    pub(crate) field_85: Option<String>,
+    /// The ask.
+    #[serde(rename = "86", default, deserialize_with = "string_or_number")]
+    pub(crate) field_86: Option<String>,
+    /// The bid size.
+    #[serde(rename = "88", default, deserialize_with = "string_or_number")]
+    pub(crate) field_88: Option<String>,
+    /// The last price, prefixed with `C` when it is the previous close.
+    #[serde(rename = "31", default, deserialize_with = "string_or_number")]
+    pub(crate) field_31: Option<String>,
+    /// The option delta.
+    #[serde(rename = "7308", default, deserialize_with = "string_or_number")]
+    pub(crate) field_7308: Option<String>,
+    /// The implied volatility in percent, e.g. `"15.2%"`.
+    #[serde(rename = "7633", default, deserialize_with = "string_or_number")]
+    pub(crate) field_7633: Option<String>,
+    /// The mark price.
+    #[serde(rename = "7635", default, deserialize_with = "string_or_number")]
+    pub(crate) field_7635: Option<String>,
+    /// The dividend amount of the underlying.
+    #[serde(rename = "7286", default, deserialize_with = "string_or_number")]
+    pub(crate) field_7286: Option<String>,
+    /// The next ex-dividend date of the underlying.
+    #[serde(rename = "7288", default, deserialize_with = "string_or_number")]
+    pub(crate) field_7288: Option<String>,
+    /// The market data availability, e.g. `"RpB"`, whose first letter is `R` for real-time, `D`
+    /// for delayed, `Z` or `Y` for frozen and `N` for not subscribed.
+    #[serde(rename = "6509", default, deserialize_with = "string_or_number")]
+    pub(crate) field_6509: Option<String>,
+}
+
+impl MarketDataResponse {
+    /// Returns the bid, `None` until it is quoted, or an error if it isn't a number.
+    pub(crate) fn bid(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        parse_number(&format!("bid of {}", self.conid_ex), &self.field_84)
+    }
+
+    /// Returns the ask, `None` until it is quoted, or an error if it isn't a number.
+    pub(crate) fn ask(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        parse_number(&format!("ask of {}", self.conid_ex), &self.field_86)
+    }
+
+    /// Returns the ask size, `None` until it is quoted, or an error if it isn't a number.
+    pub(crate) fn ask_size(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        parse_number(&format!("ask size of {}", self.conid_ex), &self.field_85)
+    }
+
+    /// Returns the bid size, `None` until it is quoted, or an error if it isn't a number.
+    pub(crate) fn bid_size(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        parse_number(&format!("bid size of {}", self.conid_ex), &self.field_88)
+    }
+
+    /// Returns the option delta, `None` until it is quoted, or an error if it isn't a number.
+    pub(crate) fn delta(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        parse_number(&format!("delta of {}", self.conid_ex), &self.field_7308)
+    }
+
+    /// Returns the implied volatility as a fraction, `None` until it is quoted, or an error if it
+    /// isn't a number.
+    pub(crate) fn implied_volatility(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        let percent: Option<String> = self
+            .field_7633
+            .as_ref()
+            .map(|value| value.trim_end_matches('%').to_string());
+        Ok(parse_number(
+            &format!("implied volatility of {}", self.conid_ex),
+            &percent,
+        )?
+        .map(|percent| percent / 100.0))
+    }
+
+    /// Returns the mark price, `None` until it is quoted, or an error if it isn't a number.
+    pub(crate) fn mark(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        parse_number(&format!("mark of {}", self.conid_ex), &self.field_7635)
+    }
+
+    /// Parses the bid and ask of a combo snapshot. Combo prices may be negative for credits.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<(f64, f64)>` with the bid and ask, or `None` until both are quoted.
+    pub(crate) fn combo_quote(&self) -> Option<(f64, f64)> {
+        let (bid, ask) = (self.bid().ok()??, self.ask().ok()??);
+        (bid <= ask).then_some((bid, ask))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PortfolioResponse {
+    #[serde(rename = "equitywithloanvalue")]
+    pub(crate) equity_with_loan_value: PortfolioAmount,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PortfolioAmount {
+    pub(crate) amount: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MarginResponse {
+    #[serde(rename = "maintmarginreq")]
+    pub(crate) maint_margin_req: PortfolioAmount,
+    #[serde(rename = "netliquidation")]
+    pub(crate) net_liquidation: PortfolioAmount,
+    #[serde(rename = "excessliquidity")]
+    pub(crate) excess_liquidity: PortfolioAmount,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct WhatIfResponse {
+    pub(crate) maintenance: Option<WhatIfAmount>,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct WhatIfAmount {
+    pub(crate) change: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PnlResponse {
+    pub(crate) upnl: HashMap<String, PnlPartition>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PnlPartition {
+    pub(crate) dpl: f64,
+    pub(crate) upl: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LiveOrdersResponse {
+    #[serde(default)]
+    pub(crate) orders: Vec<LiveOrder>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct LiveOrder {
+    #[serde(default)]
+    pub(crate) conidex: Option<String>,
+    #[serde(default)]
+    pub(crate) status: Option<String>,
+}
+
+impl LiveOrdersResponse {
+    /// Collects the leg conids of the orders still working at the exchange.
+    ///
+    /// Combo conidexes look like `28812380;;;123/1,456/-1`; a single-leg order's conidex is its
+    /// conid.
+    pub(crate) fn working_leg_conids(&self) -> Vec<String> {
+        self.orders
+            .iter()
+            .filter(|order| {
+                matches!(
+                    order.status.as_deref(),
+                    Some("PendingSubmit" | "PreSubmitted" | "Submitted")
+                )
+            })
+            .filter_map(|order| order.conidex.as_deref())
+            .flat_map(|conidex| {
+                let legs: &str = conidex.split_once(";;;").map_or(conidex, |(_, legs)| legs);
+                legs.split(',')
+                    .filter_map(|leg| leg.split('/').next())
+                    .map(str::trim)
+                    .filter(|conid| !conid.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<String>>()
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct TradeResponse {
+    pub(crate) execution_id: String,
+    pub(crate) order_ref: Option<String>,
+    pub(crate) sec_type: Option<String>,
+    /// The conid of the executed contract, e.g. a combo leg.
+    #[serde(rename = "conidEx", default)]
+    pub(crate) conid_ex: Option<String>,
+    /// `"B"` when bought and `"S"` when sold.
+    #[serde(default)]
+    pub(crate) side: Option<String>,
+    /// When the execution happened, in milliseconds since the epoch.
+    #[serde(default)]
+    pub(crate) trade_time_r: Option<i64>,
+    pub(crate) size: f64,
+    pub(crate) price: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PositionResponse {
+    pub(crate) conid: i64,
+    /// The signed number of contracts held, negative when short.
+    pub(crate) position: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OrderBody {
+    #[serde(rename = "acctId")]
+    pub acct_id: String,
+    #[serde(rename = "conidex")]
+    pub con_idex: String,
+    #[serde(rename = "orderType")]
+    pub order_type: String,
+    #[serde(rename = "listingExchange")]
+    pub listing_exchange: String,
+    #[serde(rename = "outsideRTH")]
+    pub outside_rth: bool,
+    pub price: f64,
+    pub side: String,
+    pub ticker: String,
+    pub tif: String,
+    pub referrer: String,
+    pub quantity: i32,
+    #[serde(rename = "useAdaptive")]
+    pub use_adaptive: bool,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub strategy: Option<String>,
+    #[serde(
+        rename = "strategyParameters",
+        skip_serializing_if = "BTreeMap::is_empty",
+        default
+    )]
+    pub strategy_parameters: BTreeMap<String, String>,
+    #[serde(rename = "cOID")]
+    pub c_oid: String,
+    /// The ladder concession added to the limit price, kept out of the request.
+    #[serde(skip)]
+    pub price_offset: f64,
+}
+
+impl OrderBody {
+    /// Returns the conid of each leg with the signed quantity per combo the order trades, positive
+    /// when the leg is bought.
+    pub(crate) fn leg_ratios(&self) -> Vec<(String, i32)> {
+        let sign: i32 = if self.side == "SELL" { -1 } else { 1 };
+        let legs: &str = self
+            .con_idex
+            .split_once(";;;")
+            .map_or(self.con_idex.as_str(), |(_, legs)| legs);
+        legs.split(',')
+            .filter_map(|leg| {
+                let (conid, ratio) = leg.split_once('/')?;
+                Some((conid.to_string(), ratio.parse::<i32>().ok()? * sign))
+            })
+            .collect()
+    }
+
+    /// Returns the combo price at mid the limit was set from: the limit less the discount and the
+    /// ladder concession.
+    ///
+    /// # Arguments
+    ///
+    /// * `discount_value` - The discount the order was priced with.
+    pub(crate) fn model_price(&self, discount_value: f64) -> f64 {
+        self.price - discount_value - self.price_offset
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RequestDataStruct {
+    pub(crate) orders: Vec<OrderBody>,
+}
+
+/// The body of a reply to an order request: the replies, or an error when the request is
+/// rejected outright.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum OrderReplies {
+    Replies(Vec<OrderReply>),
+    Error { error: String },
+}
+
+impl OrderReplies {
+    /// Returns the replies, or the rejection as an error.
+    pub(crate) fn into_replies(self) -> Result<Vec<OrderReply>, Box<dyn Error>> {
+        match self {
+            OrderReplies::Replies(replies) => Ok(replies),
+            OrderReplies::Error { error } => Err(format!("Order rejected: {}", error).into()),
+        }
+    }
+}
+
+/// A reply to an order request: either the accepted order or a warning to confirm by its `id`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct OrderReply {
+    /// The reply ID of a warning that has to be confirmed before the order is placed.
+    #[serde(default)]
+    pub(crate) id: Option<String>,
+    /// The warning to confirm.
+    #[serde(default)]
+    pub(crate) message: Option<Vec<String>>,
+    #[serde(default)]
+    pub(crate) order_id: Option<String>,
+    /// The cOID of the accepted order, when IBKR echoes it.
+    #[serde(default)]
+    pub(crate) local_order_id: Option<String>,
+    #[serde(default)]
+    pub(crate) order_status: Option<String>,
+}
@@ -0,0 +1,176 @@
+use super::models::{ContractRulesResponse, MarketDataResponse};
+use crate::structs::ClockSkewConfig;
+
+/// The kind of market data a snapshot is served with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MarketDataAvailability {
+    RealTime,
+    Delayed,
+    /// The last quote of a closed market, real-time or delayed.
+    Frozen,
+    NotSubscribed,
+}
+
+impl MarketDataAvailability {
+    /// Parses the market data availability field of a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The snapshot, requested with field `6509`.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<MarketDataAvailability>`, or `None` until the field is reported.
+    pub(crate) fn of(response: &MarketDataResponse) -> Option<Self> {
+        match response.field_6509.as_deref()?.trim().chars().next()? {
+            'R' => Some(MarketDataAvailability::RealTime),
+            'D' => Some(MarketDataAvailability::Delayed),
+            'Z' | 'Y' => Some(MarketDataAvailability::Frozen),
+            'N' => Some(MarketDataAvailability::NotSubscribed),
+            _ => None,
+        }
+    }
+}
+
+/// Checks that an account may trade the options of the underlying.
+///
+/// # Arguments
+///
+/// * `rules` - The trading rules of one of the underlying's options.
+/// * `account_id` - The account the bot trades.
+/// * `ticker` - The ticker of the underlying.
+///
+/// # Returns
+///
+/// A `Result` indicating success, or an error saying how to get the permission.
+pub(crate) fn check_trading_permissions(
+    rules: &ContractRulesResponse,
+    account_id: &str,
+    ticker: &str,
+) -> Result<(), String> {
+    let permitted: bool = rules
+        .rules
+        .as_ref()
+        .is_some_and(|rules| rules.can_trade_acct_ids.iter().any(|id| id == account_id));
+    if permitted {
+        Ok(())
+    } else {
+        Err(format!(
+            "Account {} isn't permitted to trade {} options. Request index options trading permissions in Client Portal under Settings > Trading Permissions, then restart the bot",
+            account_id, ticker
+        ))
+    }
+}
+
+/// Checks that a contract has market data.
+///
+/// # Arguments
+///
+/// * `response` - The snapshot of the contract, requested with field `6509`.
+/// * `name` - What the contract is, for the error, e.g. `"SPX options"`.
+///
+/// # Returns
+///
+/// A `Result` containing the availability, or an error saying which subscription is missing.
+/// Delayed data passes, since the bot can still scan on it, and so does frozen data, since it
+/// only means the market is closed.
+pub(crate) fn check_market_data(
+    response: &MarketDataResponse,
+    name: &str,
+) -> Result<MarketDataAvailability, String> {
+    match MarketDataAvailability::of(response) {
+        Some(MarketDataAvailability::NotSubscribed) => Err(format!(
+            "No market data subscription for {}. Subscribe in Client Portal under Settings > Market Data Subscriptions (OPRA for options, CBOE Indexes for SPX and XSP), then restart the bot",
+            name
+        )),
+        Some(availability) => Ok(availability),
+        None => Err(format!("The gateway didn't report the market data availability of {}", name)),
+    }
+}
+
+/// Checks whether any quote of a scan came with delayed market data.
+///
+/// # Arguments
+///
+/// * `quotes` - The snapshots of the scan.
+///
+/// # Returns
+///
+/// `true` if any snapshot reports delayed data. Snapshots without the availability field don't
+/// count.
+pub(crate) fn has_delayed_quotes<'a>(
+    quotes: impl IntoIterator<Item = &'a MarketDataResponse>,
+) -> bool {
+    quotes
+        .into_iter()
+        .any(|quote| MarketDataAvailability::of(quote) == Some(MarketDataAvailability::Delayed))
+}
+
+/// Decides whether the bot has to stop ordering: trading on delayed quotes is how accounts get
+/// hurt, so once market data is delayed the bot only scans.
+///
+/// # Arguments
+///
+/// * `ordering` - Whether the bot sends orders.
+/// * `delayed_data` - Whether any market data of the run was delayed.
+///
+/// # Returns
+///
+/// `true` if the bot has to cancel its working orders and scan without ordering.
+pub(crate) fn must_stop_ordering(ordering: bool, delayed_data: bool) -> bool {
+    ordering && delayed_data
+}
+
+/// Decides whether a bot that stopped ordering on delayed quotes can order again.
+///
+/// # Arguments
+///
+/// * `downgraded` - Whether the bot stopped ordering when market data turned delayed mid-run.
+/// * `delayed_data` - Whether the market data of the last scan was delayed.
+///
+/// # Returns
+///
+/// `true` if the last scan's quotes were real-time, so the bot can send orders again.
+pub(crate) fn can_resume_ordering(downgraded: bool, delayed_data: bool) -> bool {
+    downgraded && !delayed_data
+}
+
+/// Checks the local clock against the gateway's, since the market hours and days to expiration
+/// are computed from the local clock and silently shift when it drifts.
+///
+/// # Arguments
+///
+/// * `skew_seconds` - How far the local clock is ahead of the gateway's, negative when behind.
+/// * `config` - The skew to warn at and the skew to refuse to run with.
+///
+/// # Returns
+///
+/// A `Result` containing a warning if the skew reaches the warning threshold, or an error if it
+/// exceeds the maximum.
+pub(crate) fn check_clock_skew(
+    skew_seconds: i64,
+    config: &ClockSkewConfig,
+) -> Result<Option<String>, String> {
+    let skew: String = format!(
+        "{}s {} the gateway's",
+        skew_seconds.abs(),
+        if skew_seconds > 0 {
+            "ahead of"
+        } else {
+            "behind"
+        }
+    );
+    if skew_seconds.unsigned_abs() > config.max_seconds {
+        Err(format!(
+            "The local clock is {}, more than the {}s CLOCK_SKEW_MAX_SECONDS. Sync it with NTP, e.g. `timedatectl set-ntp true`, then restart the bot",
+            skew, config.max_seconds
+        ))
+    } else if config.warn_seconds > 0 && skew_seconds.unsigned_abs() >= config.warn_seconds {
+        Ok(Some(format!(
+            "WARNING: the local clock is {}. Market hours and days to expiration are computed from it, sync it with NTP.",
+            skew
+        )))
+    } else {
+        Ok(None)
+    }
+}
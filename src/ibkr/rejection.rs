@@ -0,0 +1,212 @@
+use std::{collections::BTreeSet, error::Error};
+
+use super::{trading::OrderBatch, IBKR};
+use crate::{
+    events::{emit, Event},
+    logging::log_message,
+    structs::Contender,
+};
+
+/// Why the gateway rejected an order request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Rejection {
+    /// The account lacks the margin or buying power for the orders.
+    Margin,
+    /// A limit price is too far from the market, e.g. past IBKR's percentage constraint.
+    PriceCap,
+    /// The account isn't permitted to trade the product or order type.
+    Permissions,
+    /// The orders aren't allowed outside regular trading hours.
+    OutsideRth,
+    /// Any other reason.
+    Other,
+}
+
+/// How the bot reacts to a rejection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Remediation {
+    /// Resubmit once with the limits at the model prices, without the discount.
+    Reprice,
+    /// Resubmit with half the quantity.
+    Downsize,
+    /// Stop trading the strategy of the rejected orders.
+    SkipStrategy,
+    /// Stop the bot.
+    Halt,
+}
+
+impl Rejection {
+    /// Classifies a rejection by its message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The error the gateway replied with.
+    ///
+    /// # Returns
+    ///
+    /// The `Rejection`, `Rejection::Other` if the message matches no known reason.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let rejection = Rejection::classify("Order rejected: INSUFFICIENT EQUITY WITH LOAN VALUE");
+    /// assert_eq!(rejection, Rejection::Margin);
+    /// ```
+    pub(crate) fn classify(message: &str) -> Self {
+        let message: String = message.to_lowercase();
+        let mentions = |phrases: &[&str]| phrases.iter().any(|phrase| message.contains(phrase));
+        // Permissions come first, since their messages can mention e.g. "insufficient" or
+        // "margin" too.
+        if mentions(&["permission", "not allowed to trade", "not eligible"]) {
+            Rejection::Permissions
+        } else if mentions(&[
+            "margin",
+            "equity with loan",
+            "buying power",
+            "insufficient funds",
+        ]) {
+            Rejection::Margin
+        } else if mentions(&[
+            "percentage constraint",
+            "price cap",
+            "price constraint",
+            "too far",
+            "price exceeds",
+        ]) {
+            Rejection::PriceCap
+        } else if mentions(&[
+            "outside regular trading hours",
+            "outside of regular trading hours",
+            "outside rth",
+            "market is closed",
+        ]) {
+            Rejection::OutsideRth
+        } else {
+            Rejection::Other
+        }
+    }
+
+    /// Returns the remediation of the rejection. Orders outside regular hours won't be accepted
+    /// until the session changes, and unknown rejections may mean anything, so both halt.
+    pub(crate) fn remediation(&self) -> Remediation {
+        match self {
+            Rejection::Margin => Remediation::Downsize,
+            Rejection::PriceCap => Remediation::Reprice,
+            Rejection::Permissions => Remediation::SkipStrategy,
+            Rejection::OutsideRth | Rejection::Other => Remediation::Halt,
+        }
+    }
+
+    /// Returns the name of the rejection, e.g. `"margin"`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Rejection::Margin => "margin",
+            Rejection::PriceCap => "price cap",
+            Rejection::Permissions => "trading permissions",
+            Rejection::OutsideRth => "outside regular trading hours",
+            Rejection::Other => "unknown reason",
+        }
+    }
+}
+
+impl IBKR {
+    /// Classifies a rejected batch and applies the remediation of its reason.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The rejected batch.
+    /// * `contenders` - The contenders whose orders were submitted.
+    /// * `error` - The rejection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the batches to resubmit, empty if the batch is dropped, or the
+    /// rejection as an error if it halts the bot.
+    pub(crate) fn remediate(
+        &mut self,
+        batch: OrderBatch,
+        contenders: &[Contender],
+        error: Box<dyn Error>,
+    ) -> Result<Vec<OrderBatch>, Box<dyn Error>> {
+        let rejection: Rejection = Rejection::classify(&error.to_string());
+        emit(&Event::Error {
+            message: format!("{} ({})", error, rejection.name()),
+        });
+        let strategies: BTreeSet<String> = contenders
+            .iter()
+            .map(|contender| contender.type_spread.clone())
+            .collect();
+        match rejection.remediation() {
+            Remediation::Reprice if !batch.repriced => {
+                log_message(format!(
+                    "{} ({}), resubmitting at the model prices.",
+                    error,
+                    rejection.name()
+                ));
+                Ok(vec![OrderBatch {
+                    repriced: true,
+                    attempt: batch.attempt + 1,
+                    ..batch
+                }])
+            }
+            Remediation::Downsize if batch.num_fills > 1 => {
+                log_message(format!(
+                    "{} ({}), resubmitting {} fills instead of {}.",
+                    error,
+                    rejection.name(),
+                    batch.num_fills / 2,
+                    batch.num_fills
+                ));
+                Ok(vec![OrderBatch {
+                    num_fills: batch.num_fills / 2,
+                    attempt: batch.attempt + 1,
+                    ..batch
+                }])
+            }
+            // The rejection doesn't say which order it is for, so a mixed batch is retried one
+            // strategy at a time to find the one that isn't permitted.
+            Remediation::SkipStrategy if strategies.len() > 1 => {
+                log_message(format!(
+                    "{} ({}), resubmitting each strategy on its own.",
+                    error,
+                    rejection.name()
+                ));
+                Ok(strategies
+                    .iter()
+                    .map(|strategy| OrderBatch {
+                        contenders: batch
+                            .contenders
+                            .iter()
+                            .filter(|contender| &contender.type_spread == strategy)
+                            .cloned()
+                            .collect(),
+                        attempt: batch.attempt + 1,
+                        ..batch
+                    })
+                    .collect())
+            }
+            Remediation::SkipStrategy => {
+                for strategy in strategies {
+                    log_message(format!(
+                        "{} ({}), no longer trading {}.",
+                        error,
+                        rejection.name(),
+                        strategy
+                    ));
+                    self.skipped_strategies.insert(strategy);
+                }
+                Ok(Vec::new())
+            }
+            Remediation::Halt => Err(format!("{} ({})", error, rejection.name()).into()),
+            Remediation::Reprice | Remediation::Downsize => {
+                log_message(format!(
+                    "{} ({}), dropping {} contenders.",
+                    error,
+                    rejection.name(),
+                    contenders.len()
+                ));
+                Ok(Vec::new())
+            }
+        }
+    }
+}
@@ -0,0 +1,908 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{
+    error::Error,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use super::{
+    client::GatewayClient,
+    endpoints,
+    models::{
+        MarginResponse, OrderBody, OrderReply, RequestDataStruct, TradeResponse, WhatIfAmount,
+        WhatIfResponse,
+    },
+    IBKR,
+};
+use crate::{
+    api::Broker,
+    chain::{ChainKey, Quotes},
+    events::{emit, Event},
+    journal::{EdgeStats, Journal, JournalLeg},
+    latency::StageTimings,
+    logging::log_message,
+    monitor::{requote, spawn_order_monitor, RequoteAction, WorkingOrders},
+    orders::{build_request_data, combo_limit_price, match_replies, AcceptedOrder},
+    paper::{PaperBroker, PaperFill},
+    selection::fit_within_margin,
+    structs::Contender,
+    underlying::osi_symbol,
+    watchdog::spawn_watchdog,
+};
+
+/// The most rounds of order warnings confirmed before the orders are given up on.
+pub(crate) const MAX_CONFIRMATIONS: usize = 10;
+
+impl IBKR {
+    /// Retrieves the leg conids of the account's orders still working, including orders placed
+    /// by earlier runs or other instances.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the set of leg conids or an error.
+    pub(crate) fn get_working_leg_conids(&self) -> Result<FxHashSet<String>, Box<dyn Error>> {
+        Ok(endpoints::live_orders(self.client()?)?
+            .working_leg_conids()
+            .into_iter()
+            .collect())
+    }
+
+    /// Cancels all pending limit orders for the account.
+    ///
+    /// This method takes all live orders out of the `live_orders` field, so the order monitor
+    /// doesn't cancel them again, and calls the `cancel_order` method for each order before
+    /// logging a success message.
+    ///
+    /// # Returns
+    ///
+    /// No return value, but logs messages indicating the result of each cancellation.
+    pub(crate) fn cancel_pending_orders(&mut self) {
+        log_message("Cancelling all pending limit orders.".to_string());
+
+        if let Some(live_orders) = &self.live_orders {
+            let order_ids: Vec<String> = live_orders.take_all();
+
+            for order_id in order_ids {
+                match self.cancel_order(&order_id) {
+                    Ok(message) => {
+                        emit(&Event::OrderCanceled {
+                            order_id: order_id.clone(),
+                        });
+                        log_message(format!("{}.", message))
+                    }
+                    Err(e) => log_message(format!("{}.", e)),
+                }
+            }
+        }
+
+        log_message("All pending limit orders cancelled.".to_string());
+    }
+
+    /// Returns the number of orders still working.
+    pub(crate) fn working_order_count(&self) -> usize {
+        self.live_orders.as_ref().map_or(0, WorkingOrders::len)
+    }
+
+    /// Starts the thread that cancels each order once its TTL elapses, so a long scan doesn't
+    /// delay canceling stale orders.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - How often the working orders are checked.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `JoinHandle` of the monitor thread, or an error if the client is
+    /// not initialized.
+    pub(crate) fn spawn_order_monitor(
+        &self,
+        interval: Duration,
+    ) -> Result<JoinHandle<()>, Box<dyn Error>> {
+        let client: GatewayClient = self.client()?.clone();
+        let account_id: String = self.account_id.clone().ok_or("Account ID is not set")?;
+
+        Ok(spawn_order_monitor(
+            self.live_orders.clone().ok_or("Live orders are not set")?,
+            interval,
+            move |order_id| send_cancel(&client, &account_id, order_id),
+        ))
+    }
+
+    /// Starts the watchdog thread, which cancels the working orders when a phase of the run loop
+    /// is stuck.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - How long a phase may take, besides its deliberate waits.
+    ///
+    /// # Returns
+    ///
+    /// The `JoinHandle` of the watchdog thread.
+    pub(crate) fn spawn_watchdog(&self, budget: Duration) -> JoinHandle<()> {
+        let client: Option<GatewayClient> = self.client.clone();
+        let account_id: Option<String> = self.account_id.clone();
+
+        spawn_watchdog(
+            budget,
+            self.live_orders.clone().unwrap_or_default(),
+            move |order_id| {
+                send_cancel(
+                    client.as_ref().ok_or("Client is not initialized")?,
+                    account_id.as_deref().ok_or("Account ID is not set")?,
+                    order_id,
+                )
+            },
+        )
+    }
+
+    /// Cancels a specific order by order ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The ID of the order to be canceled.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a message about the cancellation or an error.
+    fn cancel_order(&self, order_id: &str) -> Result<String, Box<dyn Error>> {
+        let account_id: &str = self.account_id.as_deref().ok_or("Account ID is not set")?;
+        send_cancel(self.client()?, account_id, order_id)
+    }
+
+    /// Confirms the warnings IBKR replies to order requests with until the orders are accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `replies` - The replies to the order request.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the replies once no confirmation is pending, or an error if a
+    /// confirmation is rejected.
+    pub(crate) fn confirm_replies(
+        &self,
+        replies: Vec<OrderReply>,
+    ) -> Result<Vec<OrderReply>, Box<dyn Error>> {
+        confirm_all(replies, |reply_id| {
+            endpoints::reply(self.client()?, reply_id)
+        })
+    }
+
+    /// Moves the limit price of a working order.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The IBKR order ID.
+    /// * `order` - The order with its new limit price.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the modification succeeded or failed.
+    fn modify_order(&self, order_id: &str, order: &OrderBody) -> Result<(), Box<dyn Error>> {
+        let account_id: &str = self.account_id.as_deref().ok_or("Account ID is not set")?;
+        let replies: Vec<OrderReply> =
+            endpoints::modify_order(self.client()?, account_id, order_id, order)?;
+        self.confirm_replies(replies)?;
+        Ok(())
+    }
+
+    /// Checks the legs of working orders and, past the requote thresholds, moves their limit with
+    /// the combo model price or cancels them before their TTL.
+    pub(crate) fn requote_working_orders(&self) {
+        let (Some(config), Some(live_orders)) = (&self.requote, &self.live_orders) else {
+            return;
+        };
+        let priced: Vec<(String, OrderBody, f64)> = live_orders.priced();
+        if priced.is_empty() {
+            return;
+        }
+
+        let mut conids: Vec<String> = priced
+            .iter()
+            .flat_map(|(_, order, _)| order.leg_ratios())
+            .map(|(conid, _)| conid)
+            .collect();
+        conids.sort();
+        conids.dedup();
+        let mids: FxHashMap<String, f64> = match self.get_leg_mids(&conids) {
+            Ok(mids) => mids,
+            Err(e) => {
+                log_message(format!("Failed to get leg quotes: {}.", e));
+                return;
+            }
+        };
+
+        for (order_id, order, submitted_model_price) in priced {
+            let Some(model_price) = order
+                .leg_ratios()
+                .iter()
+                .map(|(conid, ratio)| Some(mids.get(conid)? * *ratio as f64))
+                .sum::<Option<f64>>()
+            else {
+                continue;
+            };
+
+            match requote(
+                order.price,
+                submitted_model_price,
+                model_price,
+                config,
+                &self.underlying,
+            ) {
+                RequoteAction::Hold => {}
+                RequoteAction::Reprice(price) => {
+                    let mut repriced: OrderBody = order.clone();
+                    repriced.price = price;
+                    match self.modify_order(&order_id, &repriced) {
+                        Ok(()) => {
+                            live_orders.reprice(&order_id, price, model_price);
+                            log_message(format!(
+                                "Repriced order {} from {:.2} to {:.2} as its model price moved from {:.2} to {:.2}.",
+                                order_id, order.price, price, submitted_model_price, model_price
+                            ));
+                        }
+                        Err(e) => {
+                            log_message(format!("Failed to reprice order {}: {}.", order_id, e))
+                        }
+                    }
+                }
+                RequoteAction::Cancel => {
+                    if !live_orders.remove(&order_id) {
+                        continue;
+                    }
+                    match self.cancel_order(&order_id) {
+                        Ok(message) => {
+                            emit(&Event::OrderCanceled {
+                                order_id: order_id.clone(),
+                            });
+                            log_message(format!(
+                                "{} as its model price moved from {:.2} to {:.2}.",
+                                message, submitted_model_price, model_price
+                            ))
+                        }
+                        Err(e) => log_message(format!("{}.", e)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reprices orders from the NBBO of their combos, keeping the leg-based price of combos that
+    /// aren't quoted yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `orders` - The orders to reprice.
+    /// * `discount_value` - The discount the orders are priced with.
+    fn apply_combo_quotes(&self, orders: &mut [OrderBody], discount_value: f64) {
+        for order in orders {
+            match self.get_combo_quote(&order.con_idex) {
+                Ok(Some((bid, ask))) => {
+                    let price: f64 =
+                        combo_limit_price(order, bid, ask, discount_value, &self.underlying);
+                    if price != order.price {
+                        log_message(format!(
+                            "Repricing {} from {:.2} to {:.2} on the combo quote {:.2}/{:.2}.",
+                            order.c_oid, order.price, price, bid, ask
+                        ));
+                        order.price = price;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log_message(format!("Failed to get the combo quote: {}.", e)),
+            }
+        }
+    }
+
+    /// Drops contenders whose whatif maintenance margin would push margin usage above the ceiling.
+    ///
+    /// Contenders are checked in rank order and the margin of each accepted contender counts
+    /// towards the next, since existing positions and earlier orders share the same headroom.
+    /// With margin-efficient ranking, contenders that don't all fit are checked by edge per dollar
+    /// of margin instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender_contracts` - The contenders to check, best first.
+    /// * `num_fills` - The number of fills for each contract.
+    /// * `max_margin_usage` - The maintenance margin ceiling in percent of net liquidation.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the contenders that fit within the ceiling or an error.
+    fn filter_by_margin(
+        &self,
+        contender_contracts: &[Contender],
+        num_fills: i32,
+        max_margin_usage: f64,
+    ) -> Result<Vec<Contender>, Box<dyn Error>> {
+        let margin: MarginResponse = self.get_margin_summary()?;
+        let net_liquidation: f64 = margin.net_liquidation.amount;
+        let maintenance: f64 = margin.maint_margin_req.amount;
+        log_message(format!(
+            "Maintenance margin: {:.2}, excess liquidity: {:.2}, net liquidation: {:.2}.",
+            maintenance, margin.excess_liquidity.amount, net_liquidation
+        ));
+        if net_liquidation <= 0.0 {
+            return Ok(Vec::new());
+        }
+
+        let mut changes: Vec<(Contender, f64)> = Vec::new();
+        for contender in contender_contracts {
+            let request_data: RequestDataStruct = build_request_data(
+                std::slice::from_ref(contender),
+                num_fills,
+                &self.account_id,
+                self.chain.as_ref(),
+                self.discount_value,
+                &self.underlying,
+                &self.sizing,
+                &self.algo,
+            );
+
+            let mut change: f64 = 0.0;
+            for order in &request_data.orders {
+                change += self.get_whatif_maintenance_change(order)?;
+            }
+            changes.push((contender.clone(), change));
+        }
+
+        Ok(fit_within_margin(
+            maintenance,
+            net_liquidation,
+            changes,
+            max_margin_usage,
+            self.margin_efficient,
+        ))
+    }
+
+    /// Retrieves the maintenance margin, net liquidation and excess liquidity from the IBKR API.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `MarginResponse` or an error.
+    fn get_margin_summary(&self) -> Result<MarginResponse, Box<dyn Error>> {
+        let account_id: &str = self.account_id.as_deref().ok_or("Account ID is not set")?;
+        endpoints::margin_summary(self.client()?, account_id)
+    }
+
+    /// Retrieves the change in maintenance margin an order would cause, using the whatif endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order to preview.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the maintenance margin change or an error.
+    fn get_whatif_maintenance_change(&self, order: &OrderBody) -> Result<f64, Box<dyn Error>> {
+        let account_id: &str = self.account_id.as_deref().ok_or("Account ID is not set")?;
+        let whatif: WhatIfResponse = endpoints::whatif(self.client()?, account_id, order)?;
+        if let Some(error) = whatif.error {
+            return Err(format!("Whatif rejected: {}", error).into());
+        }
+        let maintenance: WhatIfAmount = whatif
+            .maintenance
+            .ok_or("Whatif response has no maintenance margin")?;
+        Ok(maintenance
+            .change
+            .replace(",", "")
+            .parse::<f64>()
+            .map_err(|_| "Failed to parse maintenance margin change")?)
+    }
+
+    /// Records submitted orders in the journal, splitting each contender's quoted edge evenly
+    /// across its orders, and tracks their legs for the fill cooldown.
+    ///
+    /// # Arguments
+    ///
+    /// * `submitted` - The orders as they were sent, with the contender each was built from.
+    /// * `num_fills` - The number of fills for each contract.
+    /// * `discount_value` - The discount the orders were priced with.
+    fn journal_orders(
+        &mut self,
+        submitted: &[(&OrderBody, &Contender)],
+        num_fills: i32,
+        discount_value: f64,
+    ) {
+        let mut logged: FxHashSet<String> = FxHashSet::default();
+        for (order, contender) in submitted {
+            if logged.insert(contender.order_id(0)) {
+                let slices: Vec<(i32, f64)> =
+                    self.sizing.slices(num_fills, contender.displayed_size());
+                if slices != [(num_fills, 0.0)] {
+                    log_message(format!(
+                        "Slicing {} {} as {:?} (quantity, price concession) of {} intended fills.",
+                        contender.type_spread, contender.exp_date, slices, num_fills
+                    ));
+                }
+                log_message(format!(
+                    "Submitting {} {}: {} = {:.2} ({}), legs {}.",
+                    contender.type_spread,
+                    contender.exp_date,
+                    contender.reason.formula,
+                    contender.arb_val,
+                    contender.reason.summary(),
+                    contender
+                        .contracts
+                        .iter()
+                        .map(|contract| {
+                            contract.osi_symbol(&self.underlying.ticker, self.chain.as_ref())
+                        })
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ));
+            }
+
+            emit(&Event::OrderSubmitted {
+                order_ref: order.c_oid.clone(),
+                strategy: contender.type_spread.clone(),
+                exp_date: contender.exp_date,
+                price: order.price,
+                quantity: order.quantity,
+            });
+
+            if let Some(fill_cooldown) = &mut self.fill_cooldown {
+                fill_cooldown.track(&order.c_oid, contender, Instant::now());
+            }
+
+            if let Some(journal) = &mut self.journal {
+                let legs: Vec<JournalLeg> = match self.chain.as_ref() {
+                    Some(chain) => order
+                        .leg_ratios()
+                        .into_iter()
+                        .filter_map(|(conid, ratio)| {
+                            let key: &ChainKey = chain.key(&conid)?;
+                            let (expiry, right, strike) = key;
+                            Some(JournalLeg {
+                                symbol: osi_symbol(
+                                    &self.underlying.ticker,
+                                    chain.trading_class(key),
+                                    chain.dates()[*expiry as usize],
+                                    right.as_str(),
+                                    strike.to_f64(),
+                                ),
+                                ratio,
+                                conid,
+                            })
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                journal.record_submitted(
+                    order,
+                    contender,
+                    discount_value,
+                    &self.algo.label(),
+                    &self.underlying.listing_exchange,
+                    legs,
+                );
+            }
+        }
+    }
+
+    /// Pulls recent executions from the IBKR API and records combo fills of journaled orders.
+    ///
+    /// Logs the realized edge per strategy whenever new fills were recorded.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of combos newly filled, or an error.
+    pub(crate) fn update_executions(&mut self) -> Result<f64, Box<dyn Error>> {
+        let trades: Vec<TradeResponse> = endpoints::trades(self.client()?)?;
+        let journal: &mut Journal = self.journal.as_mut().ok_or("Journal is not set")?;
+        let mut filled: f64 = 0.0;
+
+        for trade in &trades {
+            // Only the combo row carries the combo fill price; the leg rows are skipped.
+            if trade.sec_type.as_deref() != Some("BAG") {
+                continue;
+            }
+            let Some(order_ref) = &trade.order_ref else {
+                continue;
+            };
+            let Ok(fill_price) = trade.price.replace(",", "").parse::<f64>() else {
+                continue;
+            };
+            if let Some(realized_edge) =
+                journal.record_execution(&trade.execution_id, order_ref, trade.size, fill_price)
+            {
+                if let Some(fill_cooldown) = &mut self.fill_cooldown {
+                    fill_cooldown.record_fill(order_ref, Instant::now());
+                }
+                emit(&Event::Fill {
+                    execution_id: trade.execution_id.clone(),
+                    order_ref: order_ref.clone(),
+                    size: trade.size,
+                    fill_price,
+                    realized_edge,
+                });
+                filled += trade.size;
+            }
+        }
+
+        if filled > 0.0 {
+            log_edge_stats(journal);
+        }
+
+        Ok(filled)
+    }
+
+    /// Switches to paper trading: orders are simulated locally instead of sent to IBKR, and the
+    /// journal marks them as simulated.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed for probabilistic fills.
+    pub(crate) fn start_paper_trading(&mut self, seed: u64) {
+        let Some(fill_model) = self.fill_model.clone() else {
+            return;
+        };
+        self.paper = Some(PaperBroker::new(fill_model, seed));
+        self.journal = Some(Journal::simulated());
+        log_message("Simulating fills of paper orders.".to_string());
+    }
+
+    /// Places paper orders for the contender contracts, to be filled or canceled on the next scan.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender_contracts` - A vector of contender contracts to be ordered.
+    /// * `num_fills` - The number of fills for each contract.
+    pub(crate) fn submit_paper_orders(
+        &mut self,
+        contender_contracts: &[Contender],
+        num_fills: i32,
+    ) {
+        let Some(paper) = &mut self.paper else {
+            return;
+        };
+        let mut contender_orders: Vec<(RequestDataStruct, &Contender)> = Vec::new();
+        for contender in contender_contracts {
+            let request_data: RequestDataStruct = build_request_data(
+                std::slice::from_ref(contender),
+                num_fills,
+                &self.account_id,
+                self.chain.as_ref(),
+                self.discount_value,
+                &self.underlying,
+                &self.sizing,
+                &self.algo,
+            );
+            if let Err(e) = paper.place_orders(contender, &request_data.orders) {
+                log_message(format!("Failed to place paper orders: {}.", e));
+            }
+            contender_orders.push((request_data, contender));
+        }
+        log_message(format!(
+            "Paper trading {} orders until the next scan.",
+            paper.working_order_count()
+        ));
+
+        if let Some(recent_contenders) = &mut self.recent_contenders {
+            recent_contenders.record(contender_contracts, Instant::now());
+        }
+        let submitted: Vec<(&OrderBody, &Contender)> = contender_orders
+            .iter()
+            .flat_map(|(request_data, contender)| {
+                request_data
+                    .orders
+                    .iter()
+                    .map(move |order| (order, *contender))
+            })
+            .collect();
+        self.journal_orders(&submitted, num_fills, self.discount_value.unwrap_or(0.0));
+    }
+
+    /// Fills or cancels the paper orders of the previous scan against the new quotes and records
+    /// the simulated fills in the journal.
+    ///
+    /// # Arguments
+    ///
+    /// * `contracts_map` - The quotes of the new scan.
+    pub(crate) fn settle_paper_orders(&mut self, contracts_map: &Quotes) {
+        let (Some(paper), Some(chain), Some(journal)) =
+            (&mut self.paper, &self.chain, &mut self.journal)
+        else {
+            return;
+        };
+        let fills: Vec<PaperFill> = paper.settle(chain, contracts_map);
+        let mut new_fills: bool = false;
+        for fill in &fills {
+            if let Some(realized_edge) = journal.record_execution(
+                &fill.execution_id,
+                &fill.order_ref,
+                fill.size,
+                fill.fill_price,
+            ) {
+                if let Some(fill_cooldown) = &mut self.fill_cooldown {
+                    fill_cooldown.record_fill(&fill.order_ref, Instant::now());
+                }
+                emit(&Event::Fill {
+                    execution_id: fill.execution_id.clone(),
+                    order_ref: fill.order_ref.clone(),
+                    size: fill.size,
+                    fill_price: fill.fill_price,
+                    realized_edge,
+                });
+                new_fills = true;
+            }
+        }
+
+        if new_fills {
+            log_edge_stats(journal);
+        }
+    }
+
+    /// Places orders for the contender contracts.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender_contracts` - A vector of contender contracts to be ordered.
+    /// * `num_fills` - The number of fills for each contract.
+    /// * `stage_timings` - Receives the time spent checking margin, building and submitting orders.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the orders were successfully placed or not.
+    pub(crate) fn order_contender_contracts(
+        &mut self,
+        contender_contracts: &[Contender],
+        num_fills: i32,
+        stage_timings: &mut StageTimings,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // The orders are built for the account, so there's nothing to submit without one.
+        let account_id: String = self.account_id.clone().ok_or("Account ID is not set")?;
+        let margin_start: Instant = Instant::now();
+        let contender_contracts: Vec<Contender> = match self.max_margin_usage {
+            Some(max_margin_usage) => {
+                self.filter_by_margin(contender_contracts, num_fills, max_margin_usage)?
+            }
+            None => contender_contracts.to_vec(),
+        };
+        stage_timings.finish("margin_check", margin_start);
+        if contender_contracts.is_empty() {
+            log_message("No contenders fit within the margin ceiling.".to_string());
+            return Ok(());
+        }
+
+        // Rejected batches are remediated by resubmitting them repriced, downsized or split by
+        // strategy, so each batch is tried until it is accepted or dropped.
+        let mut batches: Vec<OrderBatch> = vec![OrderBatch {
+            contenders: contender_contracts,
+            num_fills,
+            repriced: false,
+            attempt: 0,
+        }];
+        while let Some(batch) = batches.pop() {
+            let build_start: Instant = Instant::now();
+            let (contenders, request_data): (Vec<Contender>, RequestDataStruct) =
+                self.build_orders(&batch);
+            stage_timings.finish("order_build", build_start);
+            if contenders.is_empty() {
+                continue;
+            }
+
+            let submit_start: Instant = Instant::now();
+            let placed: Result<Vec<OrderReply>, Box<dyn Error>> =
+                endpoints::place_orders(self.client()?, &account_id, &request_data)
+                    .and_then(|replies| self.confirm_replies(replies));
+            stage_timings.finish("submit", submit_start);
+            match placed {
+                Ok(replies) => self.record_orders(&contenders, &batch, &request_data, &replies),
+                Err(e) => batches.extend(self.remediate(batch, &contenders, e)?),
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the orders of a batch of contenders.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The contenders, their number of fills and whether they are repriced.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(Vec<Contender>, RequestDataStruct)` with the contenders whose orders survived the
+    /// self-match check, and their orders.
+    pub(crate) fn build_orders(&self, batch: &OrderBatch) -> (Vec<Contender>, RequestDataStruct) {
+        let mut request_data: RequestDataStruct = build_request_data(
+            &batch.contenders,
+            batch.num_fills,
+            &self.account_id,
+            self.chain.as_ref(),
+            Some(self.batch_discount(batch)),
+            &self.underlying,
+            &self.sizing,
+            &self.algo,
+        );
+        for order in &mut request_data.orders {
+            order.outside_rth = self.session.outside_rth();
+        }
+        if self.combo_quotes {
+            self.apply_combo_quotes(&mut request_data.orders, self.batch_discount(batch));
+        }
+        // Only contenders whose orders survived the self-match check are journaled.
+        let contenders: Vec<Contender> = batch
+            .contenders
+            .iter()
+            .filter(|contender| {
+                let order_ref: String = contender.order_id(0);
+                request_data
+                    .orders
+                    .iter()
+                    .any(|order| order.c_oid == order_ref)
+            })
+            .cloned()
+            .collect();
+        // IBKR rejects a cOID it has seen as a duplicate, and part of a rejected batch may have
+        // been accepted, so resubmitted orders get new cOIDs.
+        if batch.attempt > 0 {
+            for order in &mut request_data.orders {
+                order.c_oid = format!("{}-r{}", order.c_oid, batch.attempt);
+            }
+        }
+        (contenders, request_data)
+    }
+
+    /// Returns the discount a batch is priced with: none once it is repriced at the model prices.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The batch to price.
+    fn batch_discount(&self, batch: &OrderBatch) -> f64 {
+        if batch.repriced {
+            0.0
+        } else {
+            self.discount_value.unwrap_or(0.0)
+        }
+    }
+
+    /// Records accepted orders: remembers their contenders, journals them and tracks the orders
+    /// until their TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `contenders` - The contenders whose orders were submitted.
+    /// * `batch` - The batch the orders were built from.
+    /// * `request_data` - The submitted orders.
+    /// * `replies` - The replies to the order request.
+    fn record_orders(
+        &mut self,
+        contenders: &[Contender],
+        batch: &OrderBatch,
+        request_data: &RequestDataStruct,
+        replies: &[OrderReply],
+    ) {
+        let (accepted, missing): (Vec<AcceptedOrder>, usize) =
+            match_replies(contenders, &request_data.orders, replies);
+        if missing > 0 {
+            let message: String = format!(
+                "{} of {} order replies have no order id",
+                missing,
+                replies.len()
+            );
+            emit(&Event::Error {
+                message: message.clone(),
+            });
+            log_message(format!("{}.", message));
+        }
+        if accepted.is_empty() {
+            return;
+        }
+
+        let submitted: Vec<(&OrderBody, &Contender)> = accepted
+            .iter()
+            .filter_map(|accepted| accepted.submitted)
+            .collect();
+        if let Some(recent_contenders) = &mut self.recent_contenders {
+            let accepted_contenders: Vec<Contender> = contenders
+                .iter()
+                .filter(|contender| {
+                    submitted
+                        .iter()
+                        .any(|(order, _)| contender.owns_order(&order.c_oid))
+                })
+                .cloned()
+                .collect();
+            recent_contenders.record(&accepted_contenders, Instant::now());
+        }
+        let discount_value: f64 = self.batch_discount(batch);
+        self.journal_orders(&submitted, batch.num_fills, discount_value);
+        if let Some(live_orders) = &self.live_orders {
+            let expires_at: Instant = Instant::now() + self.order_ttl.unwrap_or_default();
+            for accepted in &accepted {
+                match accepted.submitted.filter(|_| self.requote.is_some()) {
+                    Some((order, _)) => live_orders.add_priced(
+                        accepted.order_id,
+                        expires_at,
+                        order.clone(),
+                        order.model_price(discount_value),
+                    ),
+                    None => live_orders.add(accepted.order_id, expires_at),
+                }
+            }
+        }
+    }
+}
+
+/// Contenders submitted together, and how they are priced and sized.
+pub(crate) struct OrderBatch {
+    pub(crate) contenders: Vec<Contender>,
+    pub(crate) num_fills: i32,
+    /// Whether the limits are at the model prices after a price cap rejection.
+    pub(crate) repriced: bool,
+    /// How many times the contenders were resubmitted after a rejection.
+    pub(crate) attempt: u32,
+}
+
+/// Confirms order warnings until none is pending, giving up after `MAX_CONFIRMATIONS` rounds so a
+/// gateway that keeps asking can't stall the bot.
+///
+/// # Arguments
+///
+/// * `replies` - The replies to the order request.
+/// * `confirm` - Confirms a warning by its reply ID, returning the next replies.
+///
+/// # Returns
+///
+/// A `Result` containing the replies once no confirmation is pending, or an error if a
+/// confirmation fails or warnings are still pending after `MAX_CONFIRMATIONS` rounds.
+pub(crate) fn confirm_all<F>(
+    mut replies: Vec<OrderReply>,
+    mut confirm: F,
+) -> Result<Vec<OrderReply>, Box<dyn Error>>
+where
+    F: FnMut(&str) -> Result<Vec<OrderReply>, Box<dyn Error>>,
+{
+    let mut rounds: usize = 0;
+    while let Some(reply_id) = replies.first().and_then(|reply| reply.id.clone()) {
+        if rounds == MAX_CONFIRMATIONS {
+            return Err(format!(
+                "Order warnings still pending after {} confirmations",
+                MAX_CONFIRMATIONS
+            )
+            .into());
+        }
+        replies = confirm(&reply_id)?;
+        rounds += 1;
+    }
+    Ok(replies)
+}
+
+/// Logs the realized edge per strategy recorded in the journal.
+///
+/// # Arguments
+///
+/// * `journal` - The trade journal.
+fn log_edge_stats(journal: &Journal) {
+    for (strategy, stats) in journal.stats() {
+        let stats: &EdgeStats = stats;
+        log_message(format!(
+            "{}: {} fills, {} combos, quoted edge {:.2}, realized edge {:.2}.",
+            strategy, stats.executions, stats.contracts, stats.quoted_edge, stats.realized_edge
+        ));
+    }
+}
+
+/// Cancels an order by order ID. Shared by the trading loop and the order monitor thread.
+///
+/// # Arguments
+///
+/// * `client` - The client of the gateway the order was placed with.
+/// * `account_id` - The account the order belongs to.
+/// * `order_id` - The ID of the order to be canceled.
+///
+/// # Returns
+///
+/// A `Result` containing a message about the cancellation or an error.
+fn send_cancel(
+    client: &GatewayClient,
+    account_id: &str,
+    order_id: &str,
+) -> Result<String, Box<dyn Error>> {
+    endpoints::cancel_order(client, account_id, order_id)
+        .map_err(|e| format!("Failed to cancel order ID {}. {}", order_id, e))?;
+    Ok(format!("Order ID {} cancelled successfully", order_id))
+}
@@ -0,0 +1,189 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::error::Error;
+
+use crate::{
+    chain::{ExpiryDate, ExpiryId, OptionChain, Quotes, Right, StrikeKey, StrikeLadder},
+    helpers::calc_time_difference,
+    rates::RateCurve,
+    structs::Opt,
+};
+
+/// The financing rates implied by the box spreads of one expiration, annualized and continuously
+/// compounded.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct ExpiryRate {
+    pub(crate) exp_date: ExpiryDate,
+    pub(crate) dte: i64,
+    /// The number of box spreads quoted on both sides.
+    pub(crate) boxes: usize,
+    /// The median rate of the boxes at their mid prices.
+    pub(crate) mid_rate: f64,
+    /// The lowest rate to borrow at by selling a box at the bids and asks of its legs, if any box
+    /// can be sold for a credit.
+    pub(crate) borrow_rate: Option<f64>,
+    /// The highest rate to lend at by buying a box at the bids and asks of its legs, if any box
+    /// can be bought for a debit.
+    pub(crate) lend_rate: Option<f64>,
+    /// The risk-free rate of the benchmark curve at the expiration.
+    pub(crate) benchmark_rate: f64,
+}
+
+/// Returns the annualized rate at which a price grows to the width of the box by expiry.
+///
+/// # Arguments
+///
+/// * `width` - The width of the box, which it pays at expiry.
+/// * `price` - The price of the box today.
+/// * `years` - The time to expiry in years.
+fn implied_rate(width: f64, price: f64, years: f64) -> Option<f64> {
+    (price > 0.0 && years > 0.0).then(|| (width / price).ln() / years)
+}
+
+/// Builds the implied financing rate curve from the box spreads of every expiration.
+///
+/// # Arguments
+///
+/// * `chain` - The option chain with expirations, strikes and conids.
+/// * `quotes` - A map of option quotes keyed by chain key.
+/// * `current_date` - The current date.
+/// * `widths` - The widths of the box spreads to price.
+/// * `benchmark` - The risk-free rate curve the rates are compared against.
+///
+/// # Returns
+///
+/// A `Vec<ExpiryRate>` in expiration order, leaving out expirations without a two-sided box.
+///
+/// # Example
+///
+/// ```
+/// let curve = implied_rate_curve(&chain, &quotes, "240701", &[5.0, 25.0], &RateCurve::flat(0.05));
+/// for rate in &curve {
+///     println!("{}: {:.2}%", rate.exp_date, rate.mid_rate * 100.0);
+/// }
+/// ```
+pub(crate) fn implied_rate_curve(
+    chain: &OptionChain,
+    quotes: &Quotes,
+    current_date: NaiveDate,
+    widths: &[f64],
+    benchmark: &RateCurve,
+) -> Vec<ExpiryRate> {
+    let mut curve: Vec<ExpiryRate> = Vec::new();
+
+    for (index, date) in chain.dates().iter().enumerate() {
+        let expiry: ExpiryId = index as ExpiryId;
+        let dte: i64 = calc_time_difference(current_date, *date);
+        let years: f64 = dte as f64 / 365.0;
+        let calls: &StrikeLadder = chain.ladder(expiry, Right::Call);
+        let puts: &StrikeLadder = chain.ladder(expiry, Right::Put);
+        let quote = |right: Right, strike: f64| -> Option<&Opt> {
+            quotes
+                .get(&(expiry, right, StrikeKey::from_f64(strike)))
+                .filter(|opt| opt.bid > 0.0 && opt.ask >= opt.bid)
+        };
+
+        let mut mid_rates: Vec<f64> = Vec::new();
+        let mut borrow_rate: f64 = f64::INFINITY;
+        let mut lend_rate: f64 = f64::NEG_INFINITY;
+        for &width in widths {
+            for low in calls.strikes() {
+                let Some(high) = calls.offset(*low, width) else {
+                    continue;
+                };
+                if calls.has_gap_between(*low, high) || puts.has_gap_between(*low, high) {
+                    continue;
+                }
+                let (Some(low_c), Some(high_c), Some(low_p), Some(high_p)) = (
+                    quote(Right::Call, *low),
+                    quote(Right::Call, high),
+                    quote(Right::Put, *low),
+                    quote(Right::Put, high),
+                ) else {
+                    continue;
+                };
+
+                // A long box buys the lower call and upper put and sells the upper call and
+                // lower put; a short box the reverse.
+                let mid: f64 = low_c.mkt - high_c.mkt + high_p.mkt - low_p.mkt;
+                let long_price: f64 = low_c.ask - high_c.bid + high_p.ask - low_p.bid;
+                let short_credit: f64 = low_c.bid - high_c.ask + high_p.bid - low_p.ask;
+                let Some(mid_rate) = implied_rate(width, mid, years) else {
+                    continue;
+                };
+                mid_rates.push(mid_rate);
+                if let Some(rate) = implied_rate(width, short_credit, years) {
+                    borrow_rate = borrow_rate.min(rate);
+                }
+                if let Some(rate) = implied_rate(width, long_price, years) {
+                    lend_rate = lend_rate.max(rate);
+                }
+            }
+        }
+        if mid_rates.is_empty() {
+            continue;
+        }
+
+        mid_rates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        curve.push(ExpiryRate {
+            exp_date: *date,
+            dte,
+            boxes: mid_rates.len(),
+            mid_rate: mid_rates[mid_rates.len() / 2],
+            borrow_rate: borrow_rate.is_finite().then_some(borrow_rate),
+            lend_rate: lend_rate.is_finite().then_some(lend_rate),
+            benchmark_rate: benchmark.rate(dte),
+        });
+    }
+
+    curve
+}
+
+/// Renders the curve as CSV, with rates in percent.
+///
+/// # Arguments
+///
+/// * `curve` - The implied rate curve.
+///
+/// # Returns
+///
+/// A `String` with the CSV contents, leaving out borrow and lend rates no box could be crossed at.
+pub(crate) fn to_csv(curve: &[ExpiryRate]) -> String {
+    let percent = |rate: Option<f64>| -> String {
+        rate.map_or(String::new(), |rate| format!("{:.3}", rate * 100.0))
+    };
+    let mut csv: String = "exp_date,dte,boxes,mid_rate_percent,borrow_rate_percent,lend_rate_percent,benchmark_rate_percent\n".to_string();
+    for rate in curve {
+        csv += &format!(
+            "{},{},{},{},{},{},{}\n",
+            rate.exp_date,
+            rate.dte,
+            rate.boxes,
+            percent(Some(rate.mid_rate)),
+            percent(rate.borrow_rate),
+            percent(rate.lend_rate),
+            percent(Some(rate.benchmark_rate))
+        );
+    }
+    csv
+}
+
+/// Writes the curve to a file, as JSON if the path ends in `.json` and as CSV otherwise.
+///
+/// # Arguments
+///
+/// * `curve` - The implied rate curve.
+/// * `path` - The output path.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an error.
+pub(crate) fn write_curve(curve: &[ExpiryRate], path: &str) -> Result<(), Box<dyn Error>> {
+    let contents: String = if path.ends_with(".json") {
+        serde_json::to_string_pretty(curve)?
+    } else {
+        to_csv(curve)
+    };
+    std::fs::write(path, contents)?;
+    Ok(())
+}
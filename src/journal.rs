@@ -0,0 +1,340 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::{
+    chain::ExpiryDate,
+    ibkr::models::OrderBody,
+    logging::log_journal,
+    orders::order_model_prices,
+    positions::{ComboSettlement, OpenCombo},
+    structs::{Contender, LegQuote, Reason},
+};
+
+/// SPX option contract multiplier.
+const MULTIPLIER: f64 = 100.0;
+
+/// A leg of a journaled order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct JournalLeg {
+    /// The OSI symbol of the leg, e.g. `SPXW  241220C05900000`, as broker statements list it.
+    pub(crate) symbol: String,
+    /// The signed quantity per combo, positive when the leg is bought.
+    pub(crate) ratio: i32,
+    /// The conid of the leg, to close the combo with. Empty in journals written before it was
+    /// recorded.
+    #[serde(default)]
+    pub(crate) conid: String,
+}
+
+/// A submitted order, kept so its executions can be matched back to the contender.
+#[derive(Clone, Serialize, Deserialize)]
+struct JournalOrder {
+    strategy: String,
+    exp_date: ExpiryDate,
+    model_price: f64,
+    quoted_edge: f64,
+    /// The leg quotes the order was priced from.
+    #[serde(default)]
+    quotes: Vec<LegQuote>,
+    /// When the quotes were fetched, in Unix milliseconds.
+    #[serde(default)]
+    quoted_at: i64,
+    /// The IBKR algo the order was routed with, e.g. `"adaptive:Normal"` or `"none"`.
+    #[serde(default)]
+    algo: String,
+    /// The listing exchange the order was routed to, e.g. `"SMART"` or `"CBOE"`.
+    #[serde(default)]
+    exchange: String,
+    #[serde(default)]
+    legs: Vec<JournalLeg>,
+}
+
+/// Realized edge aggregated over the executions of a single strategy.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct EdgeStats {
+    pub(crate) executions: u32,
+    pub(crate) contracts: f64,
+    pub(crate) quoted_edge: f64,
+    pub(crate) realized_edge: f64,
+}
+
+/// Trade journal linking submitted orders to their executions by customer order id (cOID).
+///
+/// Realized edge for an execution is the quoted arb of its order minus the slippage of the fill
+/// against the model (mid) price, in dollars.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Journal {
+    /// Whether the orders and fills are simulated by a paper run rather than sent to IBKR.
+    #[serde(default)]
+    simulated: bool,
+    orders: FxHashMap<String, JournalOrder>,
+    executions: FxHashSet<String>,
+    stats: BTreeMap<String, EdgeStats>,
+}
+
+impl Journal {
+    /// Creates a new, empty `Journal`.
+    pub(crate) fn new() -> Self {
+        Journal::default()
+    }
+
+    /// Creates a new, empty `Journal` whose orders and fills are marked as simulated.
+    pub(crate) fn simulated() -> Self {
+        Journal {
+            simulated: true,
+            ..Journal::default()
+        }
+    }
+
+    /// Records an order as it was sent, with the model price its limit was set from and an even
+    /// share of its contender's quoted edge.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The submitted order, after any combo quote repricing.
+    /// * `contender` - The contender the order was built from.
+    /// * `discount_value` - The discount the order was priced with.
+    /// * `algo` - The IBKR algo the order was routed with.
+    /// * `exchange` - The listing exchange the order was routed to.
+    /// * `legs` - The legs of the order, to close the combo with later.
+    pub(crate) fn record_submitted(
+        &mut self,
+        order: &OrderBody,
+        contender: &Contender,
+        discount_value: f64,
+        algo: &str,
+        exchange: &str,
+        legs: Vec<JournalLeg>,
+    ) {
+        // Child orders split for liquidity or laddered each carry the edge share of the order
+        // they were sliced from, and their model price excludes the ladder concession.
+        let quoted_edge: f64 = contender.quoted_edge() / order_model_prices(contender).len() as f64;
+        self.record_order(
+            &order.c_oid,
+            &contender.type_spread,
+            contender.exp_date,
+            order.model_price(discount_value),
+            quoted_edge,
+            &contender.reason,
+            contender.scan_time,
+            algo,
+            exchange,
+            legs,
+        );
+    }
+
+    /// Records a submitted order.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_ref` - The customer order id (cOID) of the order.
+    /// * `strategy` - The strategy of the contender, e.g. `"Butterfly"`.
+    /// * `exp_date` - The expiration date of the contender.
+    /// * `model_price` - The combo price at mid, before the discount.
+    /// * `quoted_edge` - The share of the contender's quoted arb attributed to this order.
+    /// * `reason` - The leg quotes and threshold checks that qualified the contender.
+    /// * `quoted_at` - When the leg quotes were fetched, in Unix milliseconds.
+    /// * `algo` - The IBKR algo the order was routed with, so fill quality can be compared.
+    /// * `exchange` - The listing exchange the order was routed to, compared likewise.
+    /// * `legs` - The legs of the order, to cross-reference its fills with broker statements.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record_order(
+        &mut self,
+        order_ref: &str,
+        strategy: &str,
+        exp_date: ExpiryDate,
+        model_price: f64,
+        quoted_edge: f64,
+        reason: &Reason,
+        quoted_at: i64,
+        algo: &str,
+        exchange: &str,
+        legs: Vec<JournalLeg>,
+    ) {
+        log_journal(&json!({
+            "event": "order",
+            "simulated": self.simulated,
+            "order_ref": order_ref,
+            "strategy": strategy,
+            "exp_date": exp_date,
+            "model_price": model_price,
+            "quoted_edge": quoted_edge,
+            "quoted_at": quoted_at,
+            "algo": algo,
+            "exchange": exchange,
+            "legs": legs,
+            "reason": reason,
+        }));
+        self.orders.insert(
+            order_ref.to_string(),
+            JournalOrder {
+                strategy: strategy.to_string(),
+                exp_date,
+                model_price,
+                quoted_edge,
+                quotes: reason.quotes.clone(),
+                quoted_at,
+                algo: algo.to_string(),
+                exchange: exchange.to_string(),
+                legs,
+            },
+        );
+    }
+
+    /// Records an order closing a filled combo. Its fills aren't matched, since the account's
+    /// positions show what is still held.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_ref` - The customer order id (cOID) of the closing order.
+    /// * `combo` - The combo being closed.
+    /// * `quantity` - The number of combos sold.
+    /// * `price` - The limit price.
+    /// * `mid` - The combo mid the limit was set from.
+    pub(crate) fn record_close(
+        &self,
+        order_ref: &str,
+        combo: &OpenCombo,
+        quantity: i32,
+        price: f64,
+        mid: f64,
+    ) {
+        log_journal(&json!({
+            "event": "close",
+            "simulated": self.simulated,
+            "order_ref": order_ref,
+            "closes": combo.order_ref,
+            "strategy": combo.strategy,
+            "exp_date": combo.exp_date,
+            "size": quantity,
+            "price": price,
+            "mid": mid,
+            "fill_price": combo.fill_price,
+            "legs": combo.legs,
+        }));
+    }
+
+    /// Records an order rolling the short leg of a calendar. The rolled calendar is journaled
+    /// like a fill, so it is found as held once the roll fills.
+    ///
+    /// # Arguments
+    ///
+    /// * `combo` - The calendar being rolled.
+    /// * `rolled` - The calendar after the roll, keyed by the roll order's cOID.
+    /// * `price` - The limit price of the roll.
+    /// * `mid` - The mid of the roll the limit was set from.
+    pub(crate) fn record_roll(&self, combo: &OpenCombo, rolled: &OpenCombo, price: f64, mid: f64) {
+        log_journal(&json!({
+            "event": "roll",
+            "simulated": self.simulated,
+            "order_ref": rolled.order_ref,
+            "rolls": combo.order_ref,
+            "strategy": rolled.strategy,
+            "exp_date": rolled.exp_date,
+            "size": rolled.size,
+            "price": price,
+            "mid": mid,
+            "fill_price": rolled.fill_price,
+            "legs": rolled.legs,
+        }));
+    }
+
+    /// Records combos whose legs were assigned, exercised or expired. The combos settle out of
+    /// the inventory, and the legs still held of them are keyed by the settlement's order ref.
+    ///
+    /// # Arguments
+    ///
+    /// * `settlement` - The combos and the legs that left the account.
+    pub(crate) fn record_settlement(&self, settlement: &ComboSettlement) {
+        let remainder: Option<OpenCombo> = settlement.remainder();
+        log_journal(&json!({
+            "event": "settlement",
+            "simulated": self.simulated,
+            "outcome": settlement.outcome().name(),
+            "order_ref": remainder.as_ref().map(|remainder| remainder.order_ref.clone()),
+            "settles": settlement.combo.order_ref,
+            "strategy": settlement.combo.strategy,
+            "exp_date": settlement.combo.exp_date,
+            "size": settlement.size,
+            "fill_price": settlement.combo.fill_price,
+            "settled_legs": settlement
+                .events
+                .iter()
+                .map(|event| json!({
+                    "symbol": event.leg.symbol,
+                    "conid": event.leg.conid,
+                    "outcome": event.outcome.name(),
+                    "contracts": event.contracts,
+                }))
+                .collect::<Vec<serde_json::Value>>(),
+            "legs": settlement.remaining,
+        }));
+    }
+
+    /// Records a combo execution, ignoring executions already seen or from unknown orders.
+    ///
+    /// The fill is journaled with the leg quotes its order was priced from, so fills can be told
+    /// apart from quote decay afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `execution_id` - The IBKR execution id.
+    /// * `order_ref` - The customer order id (cOID) of the executed order.
+    /// * `size` - The number of combos filled.
+    /// * `fill_price` - The combo fill price.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<f64>` with the realized edge of the execution in dollars, if it was recorded.
+    pub(crate) fn record_execution(
+        &mut self,
+        execution_id: &str,
+        order_ref: &str,
+        size: f64,
+        fill_price: f64,
+    ) -> Option<f64> {
+        let order: &JournalOrder = self.orders.get(order_ref)?;
+        if !self.executions.insert(execution_id.to_string()) {
+            return None;
+        }
+
+        let slippage: f64 = fill_price - order.model_price;
+        let realized_edge: f64 = (order.quoted_edge - slippage) * size * MULTIPLIER;
+        let quoted_edge: f64 = order.quoted_edge * size * MULTIPLIER;
+
+        log_journal(&json!({
+            "event": "fill",
+            "simulated": self.simulated,
+            "execution_id": execution_id,
+            "order_ref": order_ref,
+            "strategy": order.strategy,
+            "exp_date": order.exp_date,
+            "size": size,
+            "fill_price": fill_price,
+            "model_price": order.model_price,
+            "slippage": slippage,
+            "quoted_edge": quoted_edge,
+            "realized_edge": realized_edge,
+            "quoted_at": order.quoted_at,
+            "algo": order.algo,
+            "exchange": order.exchange,
+            "legs": order.legs,
+            "quotes": order.quotes,
+        }));
+
+        let stats: &mut EdgeStats = self.stats.entry(order.strategy.clone()).or_default();
+        stats.executions += 1;
+        stats.contracts += size;
+        stats.quoted_edge += quoted_edge;
+        stats.realized_edge += realized_edge;
+
+        Some(realized_edge)
+    }
+
+    /// Returns the realized edge per strategy, ordered by strategy name.
+    pub(crate) fn stats(&self) -> &BTreeMap<String, EdgeStats> {
+        &self.stats
+    }
+}
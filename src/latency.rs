@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+use crate::watchdog::note_stage;
+
+/// How long each stage of an iteration took, in the order the stages ran.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StageTimings {
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl StageTimings {
+    /// Creates a new, empty `StageTimings`.
+    pub(crate) fn new() -> Self {
+        StageTimings::default()
+    }
+
+    /// Adds the duration of a stage, summing it with earlier runs of the same stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - The name of the stage, e.g. `"chain_fetch"`.
+    /// * `duration` - How long the stage took.
+    pub(crate) fn record(&mut self, stage: &'static str, duration: Duration) {
+        match self.stages.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, total)) => *total += duration,
+            None => self.stages.push((stage, duration)),
+        }
+    }
+
+    /// Records the time elapsed since `start` under a stage, and notes it for the watchdog.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - The name of the stage.
+    /// * `start` - When the stage started.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let start = Instant::now();
+    /// let quotes = ibkr.get_ticker_data()?;
+    /// stage_timings.finish("chain_fetch", start);
+    /// ```
+    pub(crate) fn finish(&mut self, stage: &'static str, start: Instant) {
+        self.record(stage, start.elapsed());
+        note_stage(stage);
+    }
+
+    /// Returns the stages with their durations.
+    pub(crate) fn stages(&self) -> &[(&'static str, Duration)] {
+        &self.stages
+    }
+
+    /// Returns the summed duration of every stage.
+    pub(crate) fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// Formats the stages for the log, e.g. `"chain_fetch 1.204s, contender_calc 0.312s"`.
+    pub(crate) fn summary(&self) -> String {
+        self.stages
+            .iter()
+            .map(|(stage, duration)| format!("{} {:.3}s", stage, duration.as_secs_f64()))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
@@ -1,14 +1,190 @@
-#[allow(dead_code)]
+mod api;
+mod app;
+mod backtest;
+#[doc(hidden)]
+pub mod bench;
+mod chain;
+mod combo;
+mod control;
+mod dedup;
+mod dividends;
+mod error_report;
+mod events;
+mod expirations;
+mod fast_market;
+mod fill_model;
+mod gateway_log;
+mod grpc;
 mod helpers;
-#[allow(dead_code)]
+mod ibkr;
+mod implied_rates;
+mod journal;
+mod latency;
+mod lock;
+mod logging;
+mod machine;
+mod metrics;
+mod monitor;
+mod monte_carlo;
+mod optimize;
+mod orders;
+mod paper;
+mod portfolio;
+mod positions;
+mod pricing;
+mod publish;
+mod quality;
+mod rates;
+mod redact;
+mod reload;
+mod report;
+mod scan_summary;
+mod scanner;
+mod schedule;
+mod secrets;
+mod selection;
+mod setup;
+mod shared_state;
+mod state;
 mod structs;
+mod surface;
+mod testutil;
+mod underlying;
+mod watchdog;
+mod watchlist;
+
+pub use api::{Broker, ChainScanner, OrderBuilder};
+pub use app::run;
+pub use chain::{ChainKey, ExpiryDate, ExpiryId, OptionChain, Quotes, Right, StrikeKey};
+pub use fill_model::{FillMode, FillModel};
+pub use ibkr::models::OrderBody;
+pub use paper::{PaperBroker, PaperFill, PaperOrder};
+pub use scanner::OptionType;
+pub use structs::{Contender, Contract, LegQuote, Opt, Reason, ThresholdCheck};
 
 #[cfg(test)]
 mod tests {
-    use std::{env, error::Error};
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rustc_hash::FxHashMap;
+    use serde_json::{json, Value};
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        env,
+        error::Error,
+        io,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc::{channel, Sender},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
 
-    use crate::helpers::{
-        calc_final_num_orders, calc_rank_value, calc_time_difference, get_dotenv_variable,
+    use crate::{
+        api::{Broker, ChainScanner, OrderBuilder},
+        app::clean_up_after_panic,
+        backtest::Snapshot,
+        chain::{
+            ChainKey, ExpiryDate, ExpiryId, OptionChain, Quotes, Right, StrikeKey, StrikeLadder,
+        },
+        combo::{ComboBuilder, ComboSpread},
+        control::{
+            describe_status, is_paused, parse_webhook_request, set_paused, status, update_status,
+            ScanFilter,
+        },
+        dedup::{FillCooldown, RecentContenders},
+        dividends::{parse_ex_date, DividendCalendar, EarlyExerciseScreen},
+        error_report::{
+            install_panic_hook, record_endpoint, report_error, set_contender_summary,
+            set_iteration, ErrorContext, ERROR_CONTEXT,
+        },
+        events::{add_sink, emit, init_events, Event, EventSink},
+        expirations::{
+            market_holidays, parse_expiry_selectors, ExpirationCalendar, ExpiryCheck, ExpiryKind,
+        },
+        fast_market::{FastMarket, MarketSample},
+        fill_model::{FillMode, FillModel},
+        gateway_log::{is_gateway_error, LogTail},
+        helpers::{
+            calc_final_num_orders, calc_rank_value, calc_time_difference, get_dotenv_variable,
+            get_events_config, get_strategy_config, minutes_to_market_close, parse_dte_window,
+            parse_last_price, parse_no_trade_windows, parse_order_ladder, parse_scan_schedule,
+            parse_threshold_schedule, parse_wing_widths, resolve_profile, take_flag_arg,
+            take_profile_arg,
+        },
+        ibkr::client::{build_http_client, split_pem_bundle},
+        ibkr::compat::ResponseSchema,
+        ibkr::models::{
+            ContractRulesResponse, LiveOrdersResponse, MarketDataResponse, OrderBody, OrderReplies,
+            OrderReply, SecDefInfoResponse, SecDefResponse,
+        },
+        ibkr::preflight::{
+            can_resume_ordering, check_clock_skew, check_market_data, check_trading_permissions,
+            has_delayed_quotes, must_stop_ordering, MarketDataAvailability,
+        },
+        ibkr::rejection::{Rejection, Remediation},
+        ibkr::{
+            trading::{confirm_all, OrderBatch, MAX_CONFIRMATIONS},
+            IBKR,
+        },
+        implied_rates::{self, implied_rate_curve, ExpiryRate},
+        journal::{EdgeStats, Journal},
+        latency::StageTimings,
+        lock::InstanceLock,
+        logging::{
+            audit_entry, correlation_id, format_table, log_enabled, set_log_level,
+            start_correlation, write_contenders_csv,
+        },
+        machine::{Command, LoopEvent, Phase, Timings},
+        metrics::{GatewayError, Metrics},
+        monitor::{requote, RequoteAction, WorkingOrders},
+        monte_carlo::max_drawdown,
+        optimize::walk_forward_splits,
+        orders::{
+            combo_limit_price, drop_self_matches, match_replies, order_model_prices,
+            worst_case_loss, AcceptedOrder, LiquiditySizing, OrderLadder, OrderSizing,
+        },
+        paper::{PaperBroker, PaperFill, PaperOrder},
+        portfolio::PortfolioCache,
+        positions::{
+            parse_combo_args, parse_open_combos, ComboRequest, ComboSettlement, LegExecution,
+            LegOutcome, OpenCombo, PositionWatch,
+        },
+        pricing::black_scholes,
+        publish::{mqtt_topic, parse_mqtt_broker, MqttBroker},
+        quality::{score_expiries, ExpiryQuality},
+        rates::{parse_fred_yield, RateClient, RateCurve},
+        redact::{init_redaction, register_account, Redactor},
+        reload::{diff_config, parse_config, ConfigChange},
+        report::{build_report, Report, ReportTrade},
+        scan_summary::ScanSummary,
+        scanner::{OptionType, Scanner},
+        schedule::{ScanSchedule, SessionCalendar, ThresholdSchedule, TradingSession},
+        secrets::parse_secrets,
+        selection::{
+            correlation, diversify, fit_within_margin, rank_by_margin_efficiency, set_correlation,
+        },
+        setup::{parse_bot_config, render_bot_config, validate_setting},
+        shared_state::{
+            check_daily_loss, check_kill_switch, RiskCheck, SharedState, MAX_PNL_FAILURES,
+        },
+        state::{load_state, save_state, BotState},
+        structs::{
+            AlgoConfig, BoxFinancing, ClockSkewConfig, Contender, Contract, EventsConfig,
+            ExpiryFilter, FastMarketConfig, HttpConfig, LegPricing, LegQuote, LogLevel, MqttConfig,
+            Opt, PriceSource, ProxySetting, RateConfig, Reason, RedactionConfig, RequoteConfig,
+            ShadowConfig, SharedStateConfig, StrategyConfig, ThresholdCheck,
+        },
+        surface::{implied_volatility, IvSurface},
+        testutil::{synthetic_chain, synthetic_snapshots, ChainParams},
+        underlying::{osi_root, osi_symbol, parse_osi_symbol, ExchangeRouting, UnderlyingSpec},
+        watchdog::Heartbeat,
+        watchlist::{
+            export_watchlist, import_watchlist, parse_quote_args, parse_watchlist, watchlist,
+            QuoteLeg, QuoteRequest, WatchlistEntry, WatchlistLeg,
+        },
     };
 
     #[test]
@@ -29,6 +205,194 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_audit_entry() {
+        let url: &str = "https://localhost:5000/v1/api/iserver/account/U123/orders";
+        let request: Value = audit_entry(
+            "request",
+            "POST",
+            url,
+            None,
+            r#"{"orders": [{"acctId": "U123", "cOID": "abc-0"}]}"#,
+        );
+        assert_eq!(request["direction"], "request");
+        assert_eq!(request["method"], "POST");
+        assert_eq!(request["url"], url);
+        assert_eq!(request["status"], Value::Null);
+        assert_eq!(request["body"]["orders"][0]["cOID"], "abc-0");
+
+        // Bodies that aren't JSON, like gateway error pages, are kept verbatim as strings.
+        let response: Value = audit_entry("response", "POST", url, Some(500), "Internal Error");
+        assert_eq!(response["status"], 500);
+        assert_eq!(response["body"], "Internal Error");
+
+        // So are empty bodies, like those of cancel requests.
+        let cancel: Value = audit_entry("request", "DELETE", url, None, "");
+        assert_eq!(cancel["body"], json!(""));
+    }
+
+    #[test]
+    fn test_contenders_csv() {
+        let leg = |date: &str, strike: f64| Contract {
+            strike,
+            mkt_price: 1.0,
+            date: date.parse().unwrap(),
+            type_contract: "C".to_string(),
+        };
+        let contender = |strike: f64, above_threshold: bool| Contender {
+            arb_val: 0.35,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.5,
+            scan_time: 1_719_840_000_000,
+            fill_probability: 0.25,
+            above_threshold,
+            reason: Reason::default(),
+            contracts: vec![leg("240701", strike), leg("240708", strike)],
+        };
+        let path: std::path::PathBuf = env::temp_dir().join("trading_bot_test_contenders.csv");
+        let path: &str = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        // A scan without contenders still creates the file with its header.
+        write_contenders_csv(path, &[], "SPX", None).unwrap();
+        write_contenders_csv(
+            path,
+            &[contender(5000.0, true), contender(5010.0, false)],
+            "SPX",
+            None,
+        )
+        .unwrap();
+        // A later scan appends its rows without repeating the header.
+        write_contenders_csv(path, &[contender(5020.0, false)], "SPX", None).unwrap();
+        let text: String = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "scan_time,type_spread,exp_date,arb_val,quoted_edge,avg_ask,rank_value,fill_probability,above_threshold,legs,osi_legs"
+        );
+        let columns: usize = lines[0].split(',').count();
+        assert!(lines.iter().all(|line| line.split(',').count() == columns));
+        assert!(lines[1].starts_with("1719840000000,Calendar,240701,0.35,"));
+        assert!(lines[1].ends_with(
+            ",true,240701C5000@1.00 240708C5000@1.00,SPXW  240701C05000000;SPXW  240708C05000000"
+        ));
+        // Contenders below the threshold are exported too.
+        assert!(lines[2].contains(",false,240701C5010@1.00 240708C5010@1.00,"));
+        assert!(lines[3].contains("SPXW  240708C05020000"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = |event: Event| -> Value { serde_json::to_value(event).unwrap() };
+
+        assert_eq!(
+            event(Event::ScanStart { scan_time: 1 }),
+            json!({"event": "scan_start", "scan_time": 1})
+        );
+        assert_eq!(
+            event(Event::ScanEnd {
+                scan_time: 1,
+                contenders: 0,
+                duration_ms: 350,
+            }),
+            json!({"event": "scan_end", "scan_time": 1, "contenders": 0, "duration_ms": 350})
+        );
+
+        let leg = |date: &str, strike: f64| Contract {
+            strike,
+            mkt_price: 1.0,
+            date: date.parse().unwrap(),
+            type_contract: "C".to_string(),
+        };
+        let mut contender: Contender = Contender {
+            arb_val: 0.35,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.5,
+            scan_time: 1_719_840_000_000,
+            fill_probability: 0.25,
+            above_threshold: true,
+            reason: Reason::default(),
+            contracts: vec![leg("240701", 5000.0), leg("240708", 5000.0)],
+        };
+        assert_eq!(
+            event(Event::contender(&contender, "SPX", None)),
+            json!({
+                "event": "contender",
+                "scan_time": 1_719_840_000_000_i64,
+                "strategy": "Calendar",
+                "exp_date": "240701",
+                "arb_val": 0.35,
+                "rank_value": 1.5,
+                "fill_probability": 0.25,
+                "legs": ["240701C5000@1.00", "240708C5000@1.00"],
+                "osi_legs": ["SPXW  240701C05000000", "SPXW  240708C05000000"],
+                "reason": {"quotes": [], "formula": "", "checks": []},
+            })
+        );
+        // A contender without legs still serializes, with an empty list.
+        contender.contracts.clear();
+        assert_eq!(
+            event(Event::contender(&contender, "SPX", None))["legs"],
+            json!([])
+        );
+
+        assert_eq!(
+            event(Event::OrderSubmitted {
+                order_ref: "calendar-1".to_string(),
+                strategy: "Calendar".to_string(),
+                exp_date: "240701".parse().unwrap(),
+                price: -1.25,
+                quantity: 3,
+            }),
+            json!({
+                "event": "order_submitted",
+                "order_ref": "calendar-1",
+                "strategy": "Calendar",
+                "exp_date": "240701",
+                "price": -1.25,
+                "quantity": 3,
+            })
+        );
+        assert_eq!(
+            event(Event::OrderCanceled {
+                order_id: "123".to_string(),
+            }),
+            json!({"event": "order_canceled", "order_id": "123"})
+        );
+        assert_eq!(
+            event(Event::Fill {
+                execution_id: "0000e0d5.6576".to_string(),
+                order_ref: "calendar-1".to_string(),
+                size: 2.0,
+                fill_price: 1.2,
+                realized_edge: -0.05,
+            }),
+            json!({
+                "event": "fill",
+                "execution_id": "0000e0d5.6576",
+                "order_ref": "calendar-1",
+                "size": 2.0,
+                "fill_price": 1.2,
+                "realized_edge": -0.05,
+            })
+        );
+        // Messages are escaped, so quotes and newlines keep each event on one line.
+        let error: Value = event(Event::Error {
+            message: "Order rejected: \"price\"\ntoo far".to_string(),
+        });
+        assert_eq!(
+            error,
+            json!({"event": "error", "message": "Order rejected: \"price\"\ntoo far"})
+        );
+        assert!(!error.to_string().contains('\n'));
+    }
+
     #[test]
     fn test_calc_final_num_orders() {
         // Test for port_val less than 600.
@@ -49,42 +413,4744 @@ mod tests {
 
     #[test]
     fn test_calc_time_difference() {
+        let date = |yymmdd: &str| NaiveDate::parse_from_str(yymmdd, "%y%m%d").unwrap();
+        let expiry = |yymmdd: &str| -> ExpiryDate { yymmdd.parse().unwrap() };
         // Test with a difference of 1 day.
         // Current date: 220101, Date: 220102, Expected difference: 1 day.
-        let difference: i64 = calc_time_difference("220101", "220102");
+        let difference: i64 = calc_time_difference(date("220101"), expiry("220102"));
         assert_eq!(difference, 1);
 
         // Test with a difference of 5 days.
         // Current date: 220101, Date: 220106, Expected difference: 5 days.
-        let difference: i64 = calc_time_difference("220101", "220106");
+        let difference: i64 = calc_time_difference(date("220101"), expiry("220106"));
         assert_eq!(difference, 5);
 
         // Test with dates being the same.
         // Current date: 220101, Date: 220101, Expected difference: 0 days.
-        let difference: i64 = calc_time_difference("220101", "220101");
+        let difference: i64 = calc_time_difference(date("220101"), expiry("220101"));
         assert_eq!(difference, 0);
 
         // Test with the current date being later than the date.
         // Current date: 220106, Date: 220101, Expected difference: -5 days.
-        let difference: i64 = calc_time_difference("220106", "220101");
+        let difference: i64 = calc_time_difference(date("220106"), expiry("220101"));
         assert_eq!(difference, -5);
     }
 
     #[test]
     fn test_calc_rank_value() {
+        let date = |yymmdd: &str| NaiveDate::parse_from_str(yymmdd, "%y%m%d").unwrap();
+        let expiry = |yymmdd: &str| -> ExpiryDate { yymmdd.parse().unwrap() };
         // Test with a time difference of 1 day.
         // Current date: 220101, Date: 220102, avg_ask: 10.0, arb_val: 5.0, Expected rank value: 50.0.
-        let rank_value: f64 = calc_rank_value(10.0, 5.0, "220101", "220102");
+        let rank_value: f64 = calc_rank_value(10.0, 5.0, date("220101"), expiry("220102"));
         assert!((rank_value - (50.0 / 2.0)).abs() < 1e-9); // Using a small epsilon for floating point comparison.
 
         // Test with a time difference of 5 days.
         // Current date: 220101, Date: 220106, avg_ask: 10.0, arb_val: 5.0, Expected rank value: 12.5.
-        let rank_value: f64 = calc_rank_value(10.0, 5.0, "220101", "220106");
+        let rank_value: f64 = calc_rank_value(10.0, 5.0, date("220101"), expiry("220106"));
         assert!((rank_value - (50.0 / 6.0)).abs() < 1e-9);
 
         // Test with dates being the same.
         // Current date: 220101, Date: 220101, avg_ask: 10.0, arb_val: 5.0, Expected rank value: 500.0.
-        let rank_value: f64 = calc_rank_value(10.0, 5.0, "220101", "220101");
+        let rank_value: f64 = calc_rank_value(10.0, 5.0, date("220101"), expiry("220101"));
         assert!((rank_value - (50.0 / 1.0)).abs() < 1e-9);
     }
+
+    #[test]
+    fn test_expiry_date() {
+        let expiry: ExpiryDate = "240701".parse().unwrap();
+        assert_eq!(expiry.date(), NaiveDate::from_ymd_opt(2024, 7, 1).unwrap());
+        assert_eq!(expiry.to_string(), "240701");
+        assert_eq!(ExpiryDate::from_maturity("20240701"), Ok(expiry));
+        assert_eq!(
+            expiry.days_from(NaiveDate::from_ymd_opt(2024, 6, 28).unwrap()),
+            3
+        );
+        assert!("240701".parse::<ExpiryDate>().unwrap() < "240708".parse().unwrap());
+
+        assert!("20240701"
+            .parse::<ExpiryDate>()
+            .is_err_and(|e| e.contains("expected YYMMDD")));
+        assert!("241341"
+            .parse::<ExpiryDate>()
+            .is_err_and(|e| e.contains("\"241341\"")));
+        assert!(ExpiryDate::from_maturity("2024-07-01").is_err());
+
+        // Files and events keep the YYMMDD strings they had before the dates were typed.
+        assert_eq!(serde_json::to_string(&expiry).unwrap(), "\"240701\"");
+        assert_eq!(
+            serde_json::from_str::<ExpiryDate>("\"240701\"").unwrap(),
+            expiry
+        );
+        assert!(serde_json::from_str::<ExpiryDate>("\"2024-07-01\"").is_err());
+    }
+
+    #[test]
+    fn test_expiration_calendar() {
+        let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        let expiries = |dates: &[&str]| -> BTreeSet<ExpiryDate> {
+            dates.iter().map(|date| date.parse().unwrap()).collect()
+        };
+        assert!(market_holidays(2024).contains(&date(3, 29)));
+        assert!(market_holidays(2024).contains(&date(6, 19)));
+        assert!(market_holidays(2024).contains(&date(11, 28)));
+        // New Year's Day on a Saturday isn't observed the Friday before.
+        assert!(ExpirationCalendar::default()
+            .is_trading_day(NaiveDate::from_ymd_opt(2021, 12, 31).unwrap()));
+
+        let calendar: ExpirationCalendar = ExpirationCalendar::default();
+        assert_eq!(
+            calendar.expirations(date(7, 1), date(7, 19)),
+            expiries(&[
+                "240701", "240703", "240705", "240708", "240710", "240712", "240715", "240717",
+                "240719"
+            ])
+        );
+        // The Good Friday weekly and the end-of-month expiration move to the Thursday before.
+        assert_eq!(
+            calendar.expirations(date(3, 25), date(3, 29)),
+            expiries(&["240325", "240327", "240328"])
+        );
+        let mut closed: ExpirationCalendar = ExpirationCalendar::default();
+        closed.closures.insert(date(7, 10));
+        assert!(closed
+            .expirations(date(7, 8), date(7, 12))
+            .contains(&"240709".parse().unwrap()));
+
+        // Listed out to mid-August, but only checked four weeks ahead, where every weekly is out.
+        let mut listed: BTreeSet<ExpiryDate> = calendar.expirations(date(7, 1), date(8, 16));
+        listed.remove(&"240703".parse().unwrap());
+        listed.insert("240709".parse().unwrap());
+        let check: ExpiryCheck = calendar.cross_validate(&listed, date(7, 1));
+        assert_eq!(check.missing, vec!["240703".parse().unwrap()]);
+        assert_eq!(check.unexpected, vec!["240709".parse().unwrap()]);
+        assert_eq!(
+            check.describe(),
+            "Listed expirations differ from the exchange calendar through 240729: missing 240703, unexpected 240709"
+        );
+        assert!(calendar
+            .cross_validate(&expiries(&["240701", "240703"]), date(7, 1))
+            .is_consistent());
+    }
+
+    #[test]
+    fn test_expiry_selector() {
+        let calendar: ExpirationCalendar = ExpirationCalendar::default();
+        let date =
+            |year: i32, month: u32, day: u32| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let select = |text: &str| -> Vec<String> {
+            calendar
+                .select(
+                    &parse_expiry_selectors(text).unwrap(),
+                    date(2024, 7, 1),
+                    true,
+                )
+                .iter()
+                .map(|expiry| expiry.to_string())
+                .collect()
+        };
+
+        assert_eq!(calendar.kind(date(2024, 7, 19)), ExpiryKind::Monthly);
+        assert_eq!(calendar.kind(date(2024, 7, 31)), ExpiryKind::EndOfMonth);
+        assert_eq!(calendar.kind(date(2024, 7, 3)), ExpiryKind::Weekly);
+        // Good Friday moves the April 2025 monthly to the Thursday.
+        assert_eq!(calendar.kind(date(2025, 4, 17)), ExpiryKind::Monthly);
+
+        // Today's expiration is excluded, and the 4th of July is skipped.
+        assert_eq!(select("next 3 weeklies"), ["240703", "240705", "240708"]);
+        assert_eq!(select("Monthlies within 60 DTE"), ["240719", "240816"]);
+        assert_eq!(
+            select("next 2 end-of-month from 10 DTE"),
+            ["240731", "240830"]
+        );
+        assert_eq!(
+            select("next 1; 240719, 240628"),
+            ["240703", "240719"],
+            "selectors combine and past dates are dropped"
+        );
+
+        assert!(parse_expiry_selectors("next 0").is_err());
+        assert!(parse_expiry_selectors("fortnightlies within 30 DTE").is_err());
+        assert!(parse_expiry_selectors("weeklies within DTE").is_err());
+        assert!(parse_expiry_selectors("2407").is_err());
+        assert!(parse_expiry_selectors(" ; ").is_err());
+    }
+
+    #[test]
+    fn test_option_chain() {
+        let mut chain: OptionChain = OptionChain::new();
+        let first = chain.add_expiry("240101".parse().unwrap());
+        let second = chain.add_expiry("240102".parse().unwrap());
+        assert_eq!(chain.add_expiry("240101".parse().unwrap()), first);
+
+        // Insert strikes out of order to check they are sorted.
+        chain.insert(first, Right::Call, 4010.0, "3".to_string());
+        chain.insert(first, Right::Call, 4000.0, "1".to_string());
+        chain.insert(first, Right::Put, 4000.0, "2".to_string());
+        chain.insert(second, Right::Call, 4000.0, "4".to_string());
+        chain.sort_strikes();
+
+        assert_eq!(chain.strikes(first, Right::Call), &[4000.0, 4010.0]);
+        assert_eq!(chain.ladder(first, Right::Call).interval(), Some(10.0));
+        assert_eq!(
+            chain.ladder(first, Right::Call).offset(4000.0, 10.0),
+            Some(4010.0)
+        );
+        assert_eq!(chain.ladder(first, Right::Call).offset(4000.0, 5.0), None);
+        assert_eq!(chain.strikes(second, Right::Put), &[] as &[f64]);
+
+        // Check lookups in both directions.
+        assert_eq!(
+            chain.conid_for("240101".parse().unwrap(), "P", 4000.0),
+            Some(&"2".to_string())
+        );
+        assert_eq!(
+            chain.conid_for("240103".parse().unwrap(), "C", 4000.0),
+            None
+        );
+        assert_eq!(
+            chain.key("4"),
+            Some(&(second, Right::Call, StrikeKey::from_f64(4000.0)))
+        );
+
+        // Check conids are batched in key order.
+        assert_eq!(
+            chain.conid_batches(3),
+            vec!["1,3,2,".to_string(), "4,".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_strike_ladder() {
+        let ladder = |strikes: &[f64]| -> OptionChain {
+            let mut chain: OptionChain = OptionChain::new();
+            let expiry = chain.add_expiry("240701".parse().unwrap());
+            for &strike in strikes {
+                chain.insert(expiry, Right::Call, strike, format!("C{}", strike));
+            }
+            chain.sort_strikes();
+            chain
+        };
+        // A 5-point grid near the money, widening to 10 and 25 points further out.
+        let uneven: OptionChain = ladder(&[5050.0, 4990.0, 5000.0, 4995.0, 5010.0, 5025.0]);
+        let strikes: &StrikeLadder = uneven.ladder(0, Right::Call);
+        assert_eq!(strikes.interval(), Some(5.0));
+        assert_eq!(strikes.index_of(4990.0), Some(0));
+        assert_eq!(strikes.index_of(5025.0), Some(4));
+        assert_eq!(strikes.index_of(5050.0), Some(5));
+        assert_eq!(strikes.offset(5000.0, 10.0), Some(5010.0));
+        assert_eq!(strikes.offset(5025.0, -25.0), Some(5000.0));
+        assert_eq!(strikes.offset(5010.0, 5.0), None);
+
+        // Offsets past either end of the ladder.
+        assert_eq!(strikes.offset(4990.0, -5.0), None);
+        assert_eq!(strikes.offset(5050.0, 25.0), None);
+        assert_eq!(strikes.offset(4990.0, 60.0), Some(5050.0));
+        assert_eq!(strikes.offset(5050.0, -60.0), Some(4990.0));
+
+        // Strikes between the listed ones, or outside the ladder, aren't found.
+        for strike in [4985.0, 4997.5, 5005.0, 5049.99, 5075.0] {
+            assert_eq!(strikes.index_of(strike), None, "{}", strike);
+            assert!(!strikes.contains(strike), "{}", strike);
+        }
+        assert_eq!(strikes.offset(5005.0, 10.0), None);
+        // Float noise in a strike still matches the listed strike.
+        assert_eq!(strikes.index_of(5000.0 + 1e-9), Some(2));
+
+        // Fractional grids, and ladders too short to have a spacing.
+        let fractional: OptionChain = ladder(&[99.5, 100.0, 101.0, 102.5]);
+        assert_eq!(fractional.ladder(0, Right::Call).interval(), Some(0.5));
+        assert_eq!(
+            fractional.ladder(0, Right::Call).offset(100.0, 2.5),
+            Some(102.5)
+        );
+        assert_eq!(ladder(&[5000.0]).ladder(0, Right::Call).interval(), None);
+        let empty: OptionChain = ladder(&[]);
+        assert_eq!(empty.ladder(0, Right::Call).interval(), None);
+        assert_eq!(empty.ladder(0, Right::Call).index_of(5000.0), None);
+        assert_eq!(empty.ladder(0, Right::Call).offset(5000.0, 5.0), None);
+    }
+
+    #[test]
+    fn test_synthetic_chain() {
+        let start_date: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let params: ChainParams = ChainParams {
+            num_expiries: 3,
+            num_strikes: 20,
+            noise: 0.0,
+            ..ChainParams::default()
+        };
+        let (chain, quotes) = synthetic_chain(&params, start_date);
+        assert_eq!(
+            chain
+                .dates()
+                .iter()
+                .map(|date| date.to_string())
+                .collect::<Vec<String>>(),
+            ["240702", "240703", "240704"]
+        );
+        assert_eq!(chain.strikes(0, Right::Put).len(), 20);
+        assert_eq!(quotes.len(), 3 * 20 * 2);
+
+        // Without noise the quotes satisfy put-call parity.
+        let years: f64 = 3.0 / 365.0;
+        let call: f64 = black_scholes(Right::Call, 5000.0, 4990.0, years, 0.05, 0.15);
+        let put: f64 = black_scholes(Right::Put, 5000.0, 4990.0, years, 0.05, 0.15);
+        assert!((call - put - (5000.0 - 4990.0 * (-0.05 * years).exp())).abs() < 1e-6);
+        let key = (2, Right::Call, StrikeKey::from_f64(5000.0));
+        assert!(quotes[&key].bid < quotes[&key].mkt);
+
+        // The skew makes out-of-the-money puts richer and calls cheaper than without it.
+        let (_, flat_quotes) = synthetic_chain(
+            &ChainParams {
+                skew: 0.0,
+                ..params.clone()
+            },
+            start_date,
+        );
+        let otm_put = (2, Right::Put, StrikeKey::from_f64(4960.0));
+        let otm_call = (2, Right::Call, StrikeKey::from_f64(5040.0));
+        assert!(quotes[&otm_put].mkt > flat_quotes[&otm_put].mkt);
+        assert!(quotes[&otm_call].mkt < flat_quotes[&otm_call].mkt);
+
+        let snapshots = synthetic_snapshots(&params, start_date, 5);
+        assert_eq!(snapshots.len(), 5);
+        assert_eq!(snapshots[4].time - snapshots[0].time, 4 * 60_000);
+
+        // With noise, the same seed still gives the same chain and a different seed doesn't.
+        let noisy: ChainParams = ChainParams {
+            noise: 0.5,
+            ..params.clone()
+        };
+        let (noisy_chain, noisy_quotes) = synthetic_chain(&noisy, start_date);
+        let (_, repeat_quotes) = synthetic_chain(&noisy, start_date);
+        let (_, reseeded_quotes) = synthetic_chain(
+            &ChainParams {
+                seed: noisy.seed + 1,
+                ..noisy.clone()
+            },
+            start_date,
+        );
+        let prices = |quotes: &Quotes| -> BTreeMap<String, (f64, f64, f64, f64)> {
+            quotes
+                .iter()
+                .map(|((expiry, right, strike), opt)| {
+                    (
+                        format!("{}{}{}", expiry, right.as_str(), strike.to_f64()),
+                        (opt.bid, opt.mkt, opt.ask, opt.asz),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(prices(&noisy_quotes), prices(&repeat_quotes));
+        assert_ne!(prices(&noisy_quotes), prices(&reseeded_quotes));
+
+        // No bid is above its ask, and every ladder is sorted on the strike grid.
+        assert!(noisy_quotes
+            .values()
+            .all(|opt| 0.0 <= opt.bid && opt.bid <= opt.mkt && opt.mkt <= opt.ask));
+        for expiry in 0..noisy.num_expiries {
+            for right in Right::ALL {
+                let strikes: &[f64] = noisy_chain.strikes(expiry as ExpiryId, right);
+                assert_eq!(strikes.len(), noisy.num_strikes);
+                assert!(strikes.windows(2).all(|pair| pair[1] - pair[0] == 5.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_snapshot_quote_ask() {
+        // Recordings made before the ask was captured fall back to the mid.
+        let snapshot: Snapshot = serde_json::from_str(
+            r#"{"time":0,"date":"240701","minutes_to_close":390,"quotes":[{"date":"240701","right":"C","strike":5000.0,"bid":9.5,"mkt":10.0,"asz":5.0}]}"#,
+        )
+        .unwrap();
+        let (_, quotes) = snapshot.to_chain();
+        assert_eq!(
+            quotes[&(0, Right::Call, StrikeKey::from_f64(5000.0))].ask,
+            10.5
+        );
+
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams::default(),
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let snapshot: Snapshot = Snapshot::capture(
+            &chain,
+            &quotes,
+            0,
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+            390,
+            None,
+        );
+        assert!(snapshot
+            .quotes
+            .iter()
+            .all(|quote| quote.ask.is_some_and(|ask| ask > quote.bid)));
+    }
+
+    #[test]
+    fn test_dte_window() {
+        assert_eq!(parse_dte_window("30", " 90"), Some((30, 90)));
+        assert_eq!(parse_dte_window("0", "0"), Some((0, 0)));
+        assert_eq!(parse_dte_window("90", "30"), None);
+        assert_eq!(parse_dte_window("-1", "7"), None);
+        assert_eq!(parse_dte_window("abc", "7"), None);
+
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![5.0],
+            dte_window: Some((30, 90)),
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        assert!(!config.allows_dte(29));
+        assert!(config.allows_dte(30));
+        assert!(config.allows_dte(90));
+        assert!(!config.allows_dte(91));
+    }
+
+    #[test]
+    fn test_parallel_scan() {
+        let current_date: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 8,
+                num_strikes: 40,
+                ..ChainParams::default()
+            },
+            current_date,
+        );
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![5.0, 10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let scanner: Scanner = Scanner {
+            arb_val: 0.0,
+            butterfly_config: config.clone(),
+            boxspread_config: config.clone(),
+            calendar_config: config,
+            expiry_filter: ExpiryFilter {
+                exclude_same_day: false,
+                min_minutes_to_close: 0,
+            },
+            include_below_threshold: true,
+            near_miss_epsilon: None,
+            early_exercise: None,
+            dividends: DividendCalendar::new(),
+            rates: RateCurve::default(),
+            price_scale: 1.0,
+            leg_pricing: LegPricing::default(),
+            skipped_expiries: Vec::new(),
+            box_financing: None,
+            max_iv_deviation: None,
+        };
+        let ranked = |mut contenders: Vec<Contender>| -> Vec<(String, f64)> {
+            contenders.sort_by(|a, b| b.rank_value.total_cmp(&a.rank_value));
+            contenders
+                .iter()
+                .map(|contender| (contender.fingerprint(), contender.rank_value))
+                .collect()
+        };
+
+        // The expirations scanned in parallel merge into the same list as scanning them in turn.
+        let mut parallel: Vec<Contender> = scanner
+            .get_butterfly_contenders(&quotes, &chain, current_date, 390, None)
+            .unwrap();
+        parallel.extend(
+            scanner
+                .get_boxspread_contenders(&quotes, &chain, current_date, 390, None)
+                .unwrap(),
+        );
+        let mut sequential: Vec<Contender> = Vec::new();
+        for expiry in 0..chain.dates().len() as ExpiryId {
+            sequential.extend(
+                scanner
+                    .get_butterfly_contenders_for_expiry(
+                        &quotes,
+                        &chain,
+                        expiry,
+                        current_date,
+                        390,
+                        None,
+                    )
+                    .unwrap(),
+            );
+        }
+        for expiry in 0..chain.dates().len() as ExpiryId {
+            sequential.extend(
+                scanner
+                    .get_boxspread_contenders_for_expiry(
+                        &quotes,
+                        &chain,
+                        expiry,
+                        current_date,
+                        390,
+                        None,
+                    )
+                    .unwrap(),
+            );
+        }
+        assert!(!parallel.is_empty());
+        assert_eq!(
+            parallel
+                .iter()
+                .map(Contender::fingerprint)
+                .collect::<Vec<String>>(),
+            sequential
+                .iter()
+                .map(Contender::fingerprint)
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(ranked(parallel), ranked(sequential));
+    }
+
+    #[test]
+    fn test_butterfly_net_delta() {
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 2,
+                num_strikes: 60,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let scanner = |max_net_delta: Option<f64>| Scanner {
+            arb_val: 0.0,
+            butterfly_config: StrategyConfig {
+                wing_widths: vec![10.0],
+                dte_window: None,
+                max_spot_distance_percent: None,
+                straddle_spot: false,
+                max_net_delta,
+            },
+            boxspread_config: StrategyConfig {
+                wing_widths: vec![10.0],
+                dte_window: None,
+                max_spot_distance_percent: None,
+                straddle_spot: false,
+                max_net_delta: None,
+            },
+            calendar_config: StrategyConfig {
+                wing_widths: vec![10.0],
+                dte_window: None,
+                max_spot_distance_percent: None,
+                straddle_spot: false,
+                max_net_delta: None,
+            },
+            expiry_filter: ExpiryFilter {
+                exclude_same_day: false,
+                min_minutes_to_close: 0,
+            },
+            include_below_threshold: true,
+            near_miss_epsilon: None,
+            early_exercise: None,
+            dividends: DividendCalendar::new(),
+            rates: RateCurve::default(),
+            price_scale: 1.0,
+            leg_pricing: LegPricing::default(),
+            skipped_expiries: Vec::new(),
+            box_financing: None,
+            max_iv_deviation: None,
+        };
+        let all: Vec<Contender> = scanner(None)
+            .get_butterfly_contenders(
+                &quotes,
+                &chain,
+                NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                390,
+                None,
+            )
+            .unwrap();
+        let neutral: Vec<Contender> = scanner(Some(0.005))
+            .get_butterfly_contenders(
+                &quotes,
+                &chain,
+                NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                390,
+                None,
+            )
+            .unwrap();
+        assert!(!neutral.is_empty() && neutral.len() < all.len());
+        for contender in &neutral {
+            let check: &ThresholdCheck = contender.reason.checks.last().unwrap();
+            assert_eq!(check.name, "net_delta");
+            assert!(check.passed && check.value <= 0.005);
+        }
+
+        // Without deltas the limit can't be checked, so the butterfly is rejected.
+        assert!(scanner(Some(0.005))
+            .butterfly_config
+            .allows_net_delta(Some(-0.001)));
+        assert!(!scanner(Some(0.005)).butterfly_config.allows_net_delta(None));
+        assert!(scanner(None).butterfly_config.allows_net_delta(None));
+    }
+
+    #[test]
+    fn test_strike_ladder_gaps() {
+        let mut chain: OptionChain = OptionChain::new();
+        let expiry = chain.add_expiry("240701".parse().unwrap());
+        // A 5-point grid missing 5005, widening to 25 points from 5025.
+        for strike in [
+            4990.0, 4995.0, 5000.0, 5010.0, 5015.0, 5020.0, 5025.0, 5050.0, 5075.0,
+        ] {
+            chain.insert(expiry, Right::Call, strike, format!("C{}", strike));
+        }
+        chain.sort_strikes();
+        let ladder: &StrikeLadder = chain.ladder(expiry, Right::Call);
+        assert_eq!(ladder.gaps(), vec![(5000.0, 5010.0)]);
+        assert!(ladder.has_gap_between(4995.0, 5015.0));
+        assert!(ladder.has_gap_between(5000.0, 5010.0));
+        assert!(!ladder.has_gap_between(4990.0, 5000.0));
+        assert!(!ladder.has_gap_between(5010.0, 5020.0));
+        assert!(!ladder.has_gap_between(5025.0, 5075.0));
+        assert!(ladder.has_gap_between(5005.0, 5015.0));
+
+        let gaps = |strikes: &[f64]| -> Vec<(f64, f64)> {
+            let mut chain: OptionChain = OptionChain::new();
+            let expiry = chain.add_expiry("240701".parse().unwrap());
+            for &strike in strikes {
+                chain.insert(expiry, Right::Call, strike, format!("C{}", strike));
+            }
+            chain.sort_strikes();
+            chain.ladder(expiry, Right::Call).gaps()
+        };
+        // Consecutive missing strikes, as one wide spacing or as a run of them.
+        assert_eq!(
+            gaps(&[4995.0, 5000.0, 5015.0, 5020.0, 5025.0]),
+            vec![(5000.0, 5015.0)]
+        );
+        assert_eq!(
+            gaps(&[4995.0, 5000.0, 5010.0, 5020.0, 5025.0, 5030.0]),
+            vec![(5000.0, 5010.0), (5010.0, 5020.0)]
+        );
+        // A strike missing next to either end of the ladder.
+        assert_eq!(
+            gaps(&[4990.0, 5000.0, 5005.0, 5010.0, 5015.0, 5025.0]),
+            vec![(4990.0, 5000.0), (5015.0, 5025.0)]
+        );
+        // A grid widening on both sides, and a strike missing from the wider part.
+        assert!(gaps(&[4955.0, 4975.0, 4995.0, 5000.0, 5005.0, 5025.0, 5045.0]).is_empty());
+        assert_eq!(
+            gaps(&[4900.0, 4925.0, 4975.0, 5000.0, 5005.0, 5010.0, 5015.0]),
+            vec![(4925.0, 4975.0)]
+        );
+        assert!(gaps(&[5000.0]).is_empty());
+
+        // The 10-wide butterflies on 5000 and 5010 span the gap, the 5-wide ones beside it don't.
+        let (_, mut quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 1,
+                num_strikes: 40,
+                noise: 0.0,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        quotes.retain(|(_, right, _), _| *right == Right::Call);
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![5.0, 10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let scanner: Scanner = Scanner {
+            arb_val: 0.0,
+            butterfly_config: config.clone(),
+            boxspread_config: config.clone(),
+            calendar_config: config,
+            expiry_filter: ExpiryFilter {
+                exclude_same_day: false,
+                min_minutes_to_close: 0,
+            },
+            include_below_threshold: true,
+            near_miss_epsilon: None,
+            early_exercise: None,
+            dividends: DividendCalendar::new(),
+            rates: RateCurve::default(),
+            price_scale: 1.0,
+            leg_pricing: LegPricing::default(),
+            skipped_expiries: Vec::new(),
+            box_financing: None,
+            max_iv_deviation: None,
+        };
+        let bodies: Vec<(f64, f64)> = scanner
+            .get_butterfly_contenders(
+                &quotes,
+                &chain,
+                NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                390,
+                None,
+            )
+            .unwrap()
+            .iter()
+            .map(|c| {
+                (
+                    c.contracts[1].strike,
+                    c.contracts[2].strike - c.contracts[1].strike,
+                )
+            })
+            .collect();
+        assert!(bodies.contains(&(4995.0, 5.0)));
+        assert!(bodies.contains(&(5015.0, 5.0)));
+        assert!(!bodies.contains(&(5000.0, 10.0)));
+        assert!(!bodies.contains(&(5010.0, 10.0)));
+    }
+
+    #[test]
+    fn test_boxspread_width_normalization() {
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 5,
+                num_strikes: 40,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![5.0, 50.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let scanner: Scanner = Scanner {
+            arb_val: 0.0,
+            butterfly_config: config.clone(),
+            boxspread_config: config.clone(),
+            calendar_config: config,
+            expiry_filter: ExpiryFilter {
+                exclude_same_day: false,
+                min_minutes_to_close: 0,
+            },
+            include_below_threshold: true,
+            near_miss_epsilon: None,
+            early_exercise: None,
+            dividends: DividendCalendar::new(),
+            rates: RateCurve::flat(0.05),
+            price_scale: 1.0,
+            leg_pricing: LegPricing::default(),
+            skipped_expiries: Vec::new(),
+            box_financing: None,
+            max_iv_deviation: None,
+        };
+        let boxes: Vec<Contender> = scanner
+            .get_boxspread_contenders(
+                &quotes,
+                &chain,
+                NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                390,
+                None,
+            )
+            .unwrap();
+        let widths: Vec<f64> = boxes
+            .iter()
+            .map(|c| c.contracts[2].strike - c.contracts[1].strike)
+            .collect();
+        assert!(widths.contains(&5.0) && widths.contains(&50.0));
+
+        // The edge over the discounted width is ranked per point of width, in 5-point units.
+        for (contender, width) in boxes.iter().zip(widths) {
+            let arb_check: &ThresholdCheck = &contender.reason.checks[0];
+            let edge: f64 = arb_check.threshold - arb_check.value;
+            let expected: f64 = calc_rank_value(
+                contender.avg_ask,
+                edge / width * 5.0,
+                NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                contender.exp_date,
+            );
+            assert!((contender.rank_value - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_parse_wing_widths() {
+        // Widths are sorted and deduplicated.
+        assert_eq!(parse_wing_widths("25, 5,10,5"), Some(vec![5.0, 10.0, 25.0]));
+        assert_eq!(parse_wing_widths("5"), Some(vec![5.0]));
+
+        // Invalid, zero and negative widths reject the whole list.
+        assert_eq!(parse_wing_widths(""), None);
+        assert_eq!(parse_wing_widths("5,abc"), None);
+        assert_eq!(parse_wing_widths("0"), None);
+        assert_eq!(parse_wing_widths("-5"), None);
+    }
+
+    #[test]
+    fn test_wing_widths_per_strategy() {
+        let current_date: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 3,
+                num_strikes: 40,
+                ..ChainParams::default()
+            },
+            current_date,
+        );
+        let config = |wing_widths: Vec<f64>| StrategyConfig {
+            wing_widths,
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let scanner = |butterfly_widths: Vec<f64>, box_widths: Vec<f64>| Scanner {
+            arb_val: 0.0,
+            butterfly_config: config(butterfly_widths),
+            boxspread_config: config(box_widths),
+            calendar_config: config(vec![5.0]),
+            expiry_filter: ExpiryFilter {
+                exclude_same_day: false,
+                min_minutes_to_close: 0,
+            },
+            include_below_threshold: true,
+            near_miss_epsilon: None,
+            early_exercise: None,
+            dividends: DividendCalendar::new(),
+            rates: RateCurve::flat(0.05),
+            price_scale: 1.0,
+            leg_pricing: LegPricing::default(),
+            skipped_expiries: Vec::new(),
+            box_financing: None,
+            max_iv_deviation: None,
+        };
+        let widths = |contenders: Vec<Contender>, lower: usize, upper: usize| -> BTreeSet<i64> {
+            contenders
+                .iter()
+                .map(|c| (c.contracts[upper].strike - c.contracts[lower].strike) as i64)
+                .collect()
+        };
+
+        // Each strategy gets candidates for its own widths and no others.
+        let both: Scanner = scanner(vec![5.0, 10.0], vec![5.0]);
+        let butterflies: Vec<Contender> = both
+            .get_butterfly_contenders(&quotes, &chain, current_date, 390, None)
+            .unwrap();
+        assert_eq!(widths(butterflies, 0, 1), BTreeSet::from([5, 10]));
+        let boxes: Vec<Contender> = both
+            .get_boxspread_contenders(&quotes, &chain, current_date, 390, None)
+            .unwrap();
+        assert_eq!(widths(boxes, 1, 2), BTreeSet::from([5]));
+
+        // A width off the strike grid has no candidates rather than rounding to a listed one.
+        let off_grid: Scanner = scanner(vec![7.5], vec![7.5]);
+        assert!(off_grid
+            .get_butterfly_contenders(&quotes, &chain, current_date, 390, None)
+            .unwrap()
+            .is_empty());
+        assert!(off_grid
+            .get_boxspread_contenders(&quotes, &chain, current_date, 390, None)
+            .unwrap()
+            .is_empty());
+
+        // An invalid list falls back to the strike difference value.
+        env::set_var("TEST_WING_WIDTHS", "5,abc");
+        assert_eq!(get_strategy_config("TEST", 10.0).wing_widths, vec![10.0]);
+        env::set_var("TEST_WING_WIDTHS", "10, 5");
+        assert_eq!(
+            get_strategy_config("TEST", 10.0).wing_widths,
+            vec![5.0, 10.0]
+        );
+        env::remove_var("TEST_WING_WIDTHS");
+        assert_eq!(get_strategy_config("TEST", 10.0).wing_widths, vec![10.0]);
+    }
+
+    #[test]
+    fn test_box_financing() {
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 30,
+                num_strikes: 40,
+                noise: 0.0,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let boxes = |box_financing: Option<BoxFinancing>| -> Vec<Contender> {
+            let scanner: Scanner = Scanner {
+                // Boxes priced at fair value are never arbitrage.
+                arb_val: 0.01,
+                butterfly_config: config.clone(),
+                boxspread_config: config.clone(),
+                calendar_config: config.clone(),
+                expiry_filter: ExpiryFilter {
+                    exclude_same_day: false,
+                    min_minutes_to_close: 0,
+                },
+                include_below_threshold: false,
+                near_miss_epsilon: None,
+                early_exercise: None,
+                dividends: DividendCalendar::new(),
+                rates: RateCurve::flat(0.049),
+                price_scale: 1.0,
+                leg_pricing: LegPricing::default(),
+                skipped_expiries: Vec::new(),
+                box_financing,
+                max_iv_deviation: None,
+            };
+            scanner
+                .get_boxspread_contenders(
+                    &quotes,
+                    &chain,
+                    NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                    390,
+                    None,
+                )
+                .unwrap()
+        };
+        assert!(boxes(None).is_empty());
+
+        // The quotes are priced at 5%, 10 basis points over the curve.
+        let financing = |max_rate_spread: f64| {
+            boxes(Some(BoxFinancing {
+                max_rate_spread,
+                max_notional: None,
+            }))
+        };
+        let financed: Vec<Contender> = financing(0.002);
+        assert!(!financed.is_empty());
+        for contender in &financed {
+            assert!(contender.is_financing() && contender.above_threshold);
+            assert!((contender.reason.checks[0].value - 0.05).abs() < 1e-6);
+            assert!(contender.rank_value > 0.0);
+        }
+        assert!(financing(0.0005).is_empty());
+    }
+
+    #[test]
+    fn test_implied_rate_curve() {
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 10,
+                num_strikes: 40,
+                noise: 0.0,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let curve: Vec<ExpiryRate> = implied_rate_curve(
+            &chain,
+            &quotes,
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+            &[5.0, 25.0],
+            &RateCurve::flat(0.04),
+        );
+        assert_eq!(curve.len(), 10);
+        for rate in &curve {
+            // The quotes are priced at 5%, and crossing the spreads borrows dearer and lends cheaper.
+            assert!((rate.mid_rate - 0.05).abs() < 1e-6);
+            assert!(rate
+                .borrow_rate
+                .is_some_and(|borrow_rate| borrow_rate > rate.mid_rate));
+            assert!(rate
+                .lend_rate
+                .is_none_or(|lend_rate| lend_rate < rate.mid_rate));
+            assert_eq!(rate.benchmark_rate, 0.04);
+        }
+        assert_eq!(curve[0].dte, 1);
+
+        let csv: String = implied_rates::to_csv(&curve);
+        assert_eq!(csv.lines().count(), 11);
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with(&format!("{},1,", curve[0].exp_date)));
+    }
+
+    #[test]
+    fn test_iv_surface() {
+        let years: f64 = 30.0 / 365.0;
+        let price: f64 = black_scholes(Right::Put, 5000.0, 4900.0, years, 0.05, 0.2);
+        let iv: Option<f64> = implied_volatility(Right::Put, 5000.0, 4900.0, years, 0.05, price);
+        assert!(iv.is_some_and(|iv| (iv - 0.2).abs() < 1e-6));
+        assert_eq!(
+            implied_volatility(Right::Call, 5000.0, 4900.0, years, 0.05, 50.0),
+            None
+        );
+
+        let (chain, mut quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 5,
+                num_strikes: 40,
+                noise: 0.0,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        // A bad print on one call, with no implied volatility from the market data.
+        let bad_key: ChainKey = (4, Right::Call, StrikeKey::from_f64(5000.0));
+        let bad: &mut Opt = quotes.get_mut(&bad_key).unwrap();
+        bad.mkt += 20.0;
+        bad.bid += 20.0;
+        bad.ask += 20.0;
+        bad.iv = None;
+        let bad_date: ExpiryDate = chain.dates()[4];
+        let is_bad = |contract: &Contract| {
+            contract.date == bad_date && contract.type_contract == "C" && contract.strike == 5000.0
+        };
+
+        let surface: IvSurface = IvSurface::build(
+            &chain,
+            &quotes,
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+            Some(5000.0),
+            &RateCurve::flat(0.05),
+        );
+        let (bad_iv, neighbors) = surface.with_neighbors(&chain, &bad_key).unwrap();
+        assert!(bad_iv - neighbors > 0.05);
+        let leg = |strike: f64| Contract {
+            date: bad_date,
+            type_contract: "C".to_string(),
+            strike,
+            mkt_price: 0.0,
+        };
+        assert!(surface
+            .outlier_leg(&chain, &[leg(4990.0), leg(5000.0)], 0.05)
+            .is_some());
+        assert_eq!(surface.outlier_leg(&chain, &[leg(4980.0)], 0.05), None);
+
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let scan = |max_iv_deviation: Option<f64>| -> Vec<Contender> {
+            let scanner: Scanner = Scanner {
+                arb_val: 0.0,
+                butterfly_config: config.clone(),
+                boxspread_config: config.clone(),
+                calendar_config: config.clone(),
+                expiry_filter: ExpiryFilter {
+                    exclude_same_day: false,
+                    min_minutes_to_close: 0,
+                },
+                include_below_threshold: true,
+                near_miss_epsilon: None,
+                early_exercise: None,
+                dividends: DividendCalendar::new(),
+                rates: RateCurve::flat(0.05),
+                price_scale: 1.0,
+                leg_pricing: LegPricing::default(),
+                skipped_expiries: Vec::new(),
+                box_financing: None,
+                max_iv_deviation,
+            };
+            scanner
+                .scan(
+                    &OptionType::Butterfly,
+                    &quotes,
+                    &chain,
+                    NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                    390,
+                    Some(5000.0),
+                )
+                .unwrap()
+        };
+        let unchecked: Vec<Contender> = scan(None);
+        let num_bad: usize = unchecked
+            .iter()
+            .filter(|contender| contender.contracts.iter().any(is_bad))
+            .count();
+        assert!(num_bad > 0);
+        let checked: Vec<Contender> = scan(Some(0.05));
+        assert_eq!(checked.len(), unchecked.len() - num_bad);
+        assert!(checked
+            .iter()
+            .all(|contender| !contender.contracts.iter().any(is_bad)));
+    }
+
+    #[test]
+    fn test_gateway_log_tail() {
+        let path: std::path::PathBuf = env::temp_dir().join("trading_bot_test_gateway.log");
+        std::fs::write(&path, "INFO old line\n").unwrap();
+        let mut tail: LogTail = LogTail::new(path.clone());
+        assert!(tail.poll().unwrap().is_empty());
+
+        let append = |text: &str| {
+            let mut file: std::fs::File = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            std::io::Write::write_all(&mut file, text.as_bytes()).unwrap();
+        };
+        append("INFO placed\nERROR order rejec");
+        assert_eq!(tail.poll().unwrap(), vec!["INFO placed".to_string()]);
+        append("ted\n");
+        let lines: Vec<String> = tail.poll().unwrap();
+        assert_eq!(lines, vec!["ERROR order rejected".to_string()]);
+        assert!(is_gateway_error(&lines[0]) && !is_gateway_error("INFO placed"));
+
+        // A rotated log is read from its start.
+        std::fs::write(&path, "WARN new\n").unwrap();
+        assert_eq!(tail.poll().unwrap(), vec!["WARN new".to_string()]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(tail.poll().unwrap().is_empty());
+
+        let id: String = start_correlation(7);
+        assert!(id.starts_with("7-") && id.len() == 10);
+        assert_eq!(correlation_id(), Some(id.clone()));
+        assert_ne!(start_correlation(7), id);
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(LogLevel::from_str("DEBUG"), Some(LogLevel::Debug));
+        assert_eq!(LogLevel::from_str("warning"), Some(LogLevel::Warn));
+        assert_eq!(LogLevel::from_str("verbose"), None);
+        assert!(LogLevel::Error < LogLevel::Warn && LogLevel::Debug < LogLevel::Trace);
+
+        assert!(log_enabled(LogLevel::Info) && !log_enabled(LogLevel::Debug));
+        set_log_level(LogLevel::Warn);
+        assert!(log_enabled(LogLevel::Error) && log_enabled(LogLevel::Warn));
+        assert!(!log_enabled(LogLevel::Info));
+        set_log_level(LogLevel::Trace);
+        assert!(log_enabled(LogLevel::Trace));
+        set_log_level(LogLevel::default());
+    }
+
+    #[test]
+    fn test_log_redaction() {
+        let line: &str =
+            "Order ID 1234567 for DU7654321 cancelled, Authorization: Bearer abc.def-1 token=xyz";
+        let off: Redactor = Redactor::new(RedactionConfig::default());
+        assert_eq!(off.redact(line), line);
+
+        let mut redactor: Redactor = Redactor::new(RedactionConfig {
+            accounts: true,
+            order_ids: false,
+            tokens: true,
+        });
+        redactor.add_account("ACCT42");
+        redactor.add_secret("hunter2");
+        assert_eq!(
+            redactor.redact(line),
+            "Order ID 1234567 for [account] cancelled, Authorization: Bearer [token] token=[token]"
+        );
+        assert_eq!(
+            redactor.redact("POST /iserver/account/ACCT42/orders with hunter2"),
+            "POST /iserver/account/[account]/orders with [token]"
+        );
+
+        let redactor: Redactor = Redactor::new(RedactionConfig {
+            accounts: true,
+            order_ids: true,
+            tokens: true,
+        });
+        assert_eq!(
+            redactor.redact("Failed to reprice order 987654: U1234567 rejected, 5000C untouched."),
+            "Failed to reprice order [order]: [account] rejected, 5000C untouched."
+        );
+        // JSON entries stay valid, numeric order IDs included.
+        let entry: Value = json!({
+            "url": "https://localhost:5000/v1/api/iserver/account/U1234567/order/555",
+            "body": [{"order_id": 555, "local_order_id": "abc-0", "conid": 12345}],
+            "access_token": "secret-value",
+        });
+        assert_eq!(
+            redactor.redact_json(&entry),
+            json!({
+                "url": "https://localhost:5000/v1/api/iserver/account/[account]/order/[order]",
+                "body": [{"order_id": "[order]", "local_order_id": "[order]", "conid": 12345}],
+                "access_token": "[token]",
+            })
+        );
+
+        // Events and error reports leave the process, so they're masked too.
+        struct Recorder(Sender<String>);
+        impl EventSink for Recorder {
+            fn send(&mut self, line: &str) -> io::Result<()> {
+                self.0
+                    .send(line.to_string())
+                    .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+        }
+        init_redaction(
+            RedactionConfig {
+                accounts: true,
+                order_ids: false,
+                tokens: false,
+            },
+            &[],
+        );
+        register_account("ACCT9001");
+        let (sender, receiver) = channel();
+        add_sink(Box::new(Recorder(sender)));
+        emit(&Event::Error {
+            message: "test_log_redaction: DU7654321 and ACCT9001 lack permissions".to_string(),
+        });
+        let line: String = receiver
+            .try_iter()
+            .find(|line| line.contains("test_log_redaction"))
+            .unwrap();
+        let payload: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            payload["message"],
+            "test_log_redaction: [account] and [account] lack permissions"
+        );
+        assert_eq!(
+            report_error("Failed to get positions of DU7654321"),
+            "Failed to get positions of [account]"
+        );
+        init_redaction(RedactionConfig::default(), &[]);
+    }
+
+    #[test]
+    fn test_pretty_console() {
+        let table: String = format_table(
+            &["Strategy", "Arb", "Legs"],
+            &[
+                vec![
+                    "Butterfly".to_string(),
+                    "0.35".to_string(),
+                    "BUY 1*5000C".to_string(),
+                ],
+                vec![
+                    "Box".to_string(),
+                    "12.50".to_string(),
+                    "SELL 2*4950P".to_string(),
+                ],
+            ],
+        );
+        assert_eq!(
+            table.lines().collect::<Vec<&str>>(),
+            vec![
+                "Strategy   Arb    Legs",
+                "---------  -----  ------------",
+                "Butterfly  0.35   BUY 1*5000C",
+                "Box        12.50  SELL 2*4950P",
+            ]
+        );
+
+        let mut command_line: Vec<String> = vec![
+            "bot".to_string(),
+            "--pretty".to_string(),
+            "scan".to_string(),
+        ];
+        assert!(take_flag_arg(&mut command_line, "--pretty"));
+        assert_eq!(command_line, vec!["bot".to_string(), "scan".to_string()]);
+        assert!(!take_flag_arg(&mut command_line, "--pretty"));
+    }
+
+    #[test]
+    fn test_preflight() {
+        let rules: ContractRulesResponse = serde_json::from_str(
+            r#"{"con_id": 123, "rules": {"canTradeAcctIds": ["U1234567", "DU7654321"]}}"#,
+        )
+        .unwrap();
+        assert!(check_trading_permissions(&rules, "U1234567", "SPX").is_ok());
+        assert!(check_trading_permissions(&rules, "U9999999", "SPX")
+            .is_err_and(|e| e.contains("Trading Permissions")));
+        let no_rules: ContractRulesResponse = serde_json::from_str(r#"{"con_id": 123}"#).unwrap();
+        assert!(check_trading_permissions(&no_rules, "U1234567", "SPX").is_err());
+
+        let snapshot = |availability: &str| -> MarketDataResponse {
+            serde_json::from_str(&format!(
+                r#"{{"conidEx": "416904", "6509": "{}"}}"#,
+                availability
+            ))
+            .unwrap()
+        };
+        assert_eq!(
+            MarketDataAvailability::of(&snapshot("RpB")),
+            Some(MarketDataAvailability::RealTime)
+        );
+        assert_eq!(
+            check_market_data(&snapshot("RpB"), "SPX"),
+            Ok(MarketDataAvailability::RealTime)
+        );
+        assert_eq!(
+            check_market_data(&snapshot("Z"), "SPX"),
+            Ok(MarketDataAvailability::Frozen)
+        );
+        // Delayed data passes the check, and the bot downgrades to scanning only.
+        assert_eq!(
+            check_market_data(&snapshot("DpB"), "SPX"),
+            Ok(MarketDataAvailability::Delayed)
+        );
+        assert!(check_market_data(&snapshot("N"), "SPX options")
+            .is_err_and(|e| e.contains("No market data subscription for SPX options")));
+        let unreported: MarketDataResponse =
+            serde_json::from_str(r#"{"conidEx": "416904"}"#).unwrap();
+        assert_eq!(MarketDataAvailability::of(&unreported), None);
+    }
+
+    #[test]
+    fn test_delayed_data_downgrade() {
+        let snapshot = |availability: &str| -> MarketDataResponse {
+            serde_json::from_str(&format!(
+                r#"{{"conidEx": "416904", "6509": "{}"}}"#,
+                availability
+            ))
+            .unwrap()
+        };
+        let unreported: MarketDataResponse =
+            serde_json::from_str(r#"{"conidEx": "416904"}"#).unwrap();
+
+        // Real-time and frozen quotes keep the bot ordering.
+        let live: Vec<MarketDataResponse> = vec![snapshot("RpB"), snapshot("Z"), unreported];
+        assert!(!has_delayed_quotes(&live));
+        assert!(!must_stop_ordering(true, has_delayed_quotes(&live)));
+
+        // A single delayed quote downgrades an ordering bot to scanning.
+        let delayed: Vec<MarketDataResponse> = vec![snapshot("RpB"), snapshot("DpB")];
+        assert!(has_delayed_quotes(&delayed));
+        assert!(must_stop_ordering(true, has_delayed_quotes(&delayed)));
+
+        // A bot that only scans has nothing to downgrade.
+        assert!(!must_stop_ordering(false, has_delayed_quotes(&delayed)));
+        assert!(!has_delayed_quotes(&[]));
+
+        // The flag follows the last scan, so a downgraded bot orders again on real-time quotes.
+        let mut ibkr: IBKR = IBKR::new();
+        ibkr.record_quote_delay(has_delayed_quotes(&delayed));
+        assert!(must_stop_ordering(true, ibkr.has_delayed_data()));
+        assert!(!can_resume_ordering(true, ibkr.has_delayed_data()));
+        ibkr.record_quote_delay(has_delayed_quotes(&live));
+        assert!(!ibkr.has_delayed_data());
+        assert!(can_resume_ordering(true, ibkr.has_delayed_data()));
+        // A bot that wasn't downgraded mid-run, e.g. one that started on delayed data, doesn't.
+        assert!(!can_resume_ordering(false, ibkr.has_delayed_data()));
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let config: ClockSkewConfig = ClockSkewConfig {
+            warn_seconds: 2,
+            max_seconds: 30,
+        };
+        assert_eq!(check_clock_skew(0, &config), Ok(None));
+        assert_eq!(check_clock_skew(-1, &config), Ok(None));
+        assert!(check_clock_skew(5, &config)
+            .is_ok_and(|warning| warning.is_some_and(|w| w.contains("5s ahead of"))));
+        assert!(check_clock_skew(-30, &config)
+            .is_ok_and(|warning| warning.is_some_and(|w| w.contains("30s behind"))));
+        assert!(check_clock_skew(-31, &config)
+            .is_err_and(|e| e.contains("31s behind the gateway's") && e.contains("NTP")));
+
+        let never_warn: ClockSkewConfig = ClockSkewConfig {
+            warn_seconds: 0,
+            max_seconds: 30,
+        };
+        assert_eq!(check_clock_skew(10, &never_warn), Ok(None));
+    }
+
+    #[test]
+    fn test_order_rejection() {
+        let cases: [(&str, Rejection, Remediation); 8] = [
+            (
+                "Order rejected: YOUR ORDER IS NOT ACCEPTED. IN ORDER TO OBTAIN THE DESIRED POSITION YOUR EQUITY WITH LOAN VALUE [1000.00 USD] MUST EXCEED THE INITIAL MARGIN [5000.00 USD]",
+                Rejection::Margin,
+                Remediation::Downsize,
+            ),
+            (
+                "Order rejected: The price exceeds the Percentage constraint of 3%.",
+                Rejection::PriceCap,
+                Remediation::Reprice,
+            ),
+            (
+                "Order rejected: No Trading Permissions for this product.",
+                Rejection::Permissions,
+                Remediation::SkipStrategy,
+            ),
+            // Resubmitting smaller orders can't fix missing permissions.
+            (
+                "Order rejected: Insufficient trading permissions for OPT on CBOE.",
+                Rejection::Permissions,
+                Remediation::SkipStrategy,
+            ),
+            (
+                "Order rejected: Insufficient funds to place the order.",
+                Rejection::Margin,
+                Remediation::Downsize,
+            ),
+            (
+                "Order rejected: This order will not be placed outside Regular Trading Hours.",
+                Rejection::OutsideRth,
+                Remediation::Halt,
+            ),
+            (
+                "Order rejected: Orders are not accepted outside RTH for this contract.",
+                Rejection::OutsideRth,
+                Remediation::Halt,
+            ),
+            (
+                "Order rejected: Invalid account.",
+                Rejection::Other,
+                Remediation::Halt,
+            ),
+        ];
+        for (message, rejection, remediation) in cases {
+            assert_eq!(Rejection::classify(message), rejection, "{}", message);
+            assert_eq!(rejection.remediation(), remediation);
+        }
+    }
+
+    #[test]
+    fn test_reprice_rejection() {
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 4,
+                num_strikes: 40,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let contenders: Vec<Contender> = ChainScanner::new("SPX", 0.0, vec![5.0, 10.0])
+            .scan(
+                OptionType::All,
+                &chain,
+                &quotes,
+                NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                390,
+                None,
+                0,
+            )
+            .unwrap()
+            .into_iter()
+            .take(1)
+            .collect();
+        assert_eq!(contenders.len(), 1);
+        // No discount is configured, as when the thresholds were never set.
+        let mut ibkr: IBKR = IBKR::new();
+        ibkr.set_account_id("DU1234567");
+        ibkr.set_chain(chain);
+
+        // A price cap rejection resubmits the batch once, at the model prices.
+        let batch: OrderBatch = OrderBatch {
+            contenders: contenders.clone(),
+            num_fills: 2,
+            repriced: false,
+            attempt: 0,
+        };
+        let (_, first) = ibkr.build_orders(&batch);
+        assert!(first.orders.iter().all(|order| !order.c_oid.contains("-r")));
+        let message: &str = "Order rejected: The price exceeds the Percentage constraint of 3%.";
+        let mut batches: Vec<OrderBatch> =
+            ibkr.remediate(batch, &contenders, message.into()).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert!(batches[0].repriced && batches[0].num_fills == 2);
+        assert_eq!(batches[0].attempt, 1);
+
+        // The resubmitted orders get new cOIDs, so IBKR doesn't reject them as duplicates of
+        // orders it accepted from the first batch, and they're still matched to the contender.
+        let (built, request_data) = ibkr.build_orders(&batches[0]);
+        assert_eq!(built.len(), 1);
+        for (order, first) in request_data.orders.iter().zip(&first.orders) {
+            assert_eq!(order.c_oid, format!("{}-r1", first.c_oid));
+            assert!(contenders[0].owns_order(&order.c_oid));
+        }
+        let model_prices: Vec<f64> = order_model_prices(&contenders[0]);
+        assert_eq!(request_data.orders.len(), model_prices.len());
+        for (order, model_price) in request_data.orders.iter().zip(model_prices) {
+            assert_eq!(
+                order.price,
+                UnderlyingSpec::for_ticker("SPX").round_to_tick(model_price)
+            );
+        }
+
+        // A repriced batch that is rejected again is dropped.
+        let repriced: OrderBatch = batches.remove(0);
+        assert!(ibkr
+            .remediate(repriced, &contenders, message.into())
+            .unwrap()
+            .is_empty());
+
+        // Each remediation of the same contenders is a new generation of cOIDs.
+        let downsized: Vec<OrderBatch> = ibkr
+            .remediate(
+                OrderBatch {
+                    contenders: contenders.clone(),
+                    num_fills: 2,
+                    repriced: true,
+                    attempt: 1,
+                },
+                &contenders,
+                "Order rejected: Insufficient funds to place the order.".into(),
+            )
+            .unwrap();
+        assert_eq!((downsized[0].num_fills, downsized[0].attempt), (1, 2));
+        assert!(ibkr
+            .build_orders(&downsized[0])
+            .1
+            .orders
+            .iter()
+            .all(|order| order.c_oid.ends_with("-r2")));
+    }
+
+    #[test]
+    fn test_orders_without_account() {
+        // Before the account is known, submitting is an error rather than a panic.
+        let mut ibkr: IBKR = IBKR::new();
+        let submitted = ibkr.order_contender_contracts(&[], 1, &mut StageTimings::new());
+        assert!(submitted.is_err_and(|e| e.to_string() == "Account ID is not set"));
+    }
+
+    #[test]
+    fn test_confirm_replies() {
+        let warning = |id: &str| OrderReply {
+            id: Some(id.to_string()),
+            message: Some(vec!["The order size exceeds the size limit.".to_string()]),
+            ..OrderReply::default()
+        };
+        let accepted = || OrderReply {
+            order_id: Some("987654".to_string()),
+            ..OrderReply::default()
+        };
+
+        // Warnings are confirmed one after another until the order is accepted.
+        let mut confirmed: Vec<String> = Vec::new();
+        let replies: Vec<OrderReply> = confirm_all(vec![warning("a")], |reply_id| {
+            confirmed.push(reply_id.to_string());
+            Ok(if reply_id == "a" {
+                vec![warning("b")]
+            } else {
+                vec![accepted()]
+            })
+        })
+        .unwrap();
+        assert_eq!(confirmed, ["a", "b"]);
+        assert_eq!(replies[0].order_id.as_deref(), Some("987654"));
+        assert!(confirm_all(vec![accepted()], |_| unreachable!()).is_ok());
+
+        // A gateway that keeps asking is given up on instead of confirmed forever.
+        let mut rounds: usize = 0;
+        let endless = confirm_all(vec![warning("a")], |_| {
+            rounds += 1;
+            Ok(vec![warning("a")])
+        });
+        assert!(endless.is_err_and(|e| e.to_string().contains("still pending")));
+        assert_eq!(rounds, MAX_CONFIRMATIONS);
+
+        // A failed confirmation is returned as is.
+        assert!(
+            confirm_all(vec![warning("a")], |_| Err("Connection refused".into()))
+                .is_err_and(|e| e.to_string() == "Connection refused")
+        );
+    }
+
+    #[test]
+    fn test_fast_market() {
+        let (_, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 1,
+                num_strikes: 20,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let calm: MarketSample = MarketSample::capture(&quotes, Some(5000.0));
+        assert!(calm.avg_spread.is_some_and(|avg_spread| avg_spread > 0.0));
+        let sample = |spot: f64, spread_multiple: f64| MarketSample {
+            spot: Some(spot),
+            avg_spread: calm
+                .avg_spread
+                .map(|avg_spread| avg_spread * spread_multiple),
+        };
+
+        let mut fast_market: FastMarket = FastMarket::new(FastMarketConfig {
+            max_move_percent: Some(0.5),
+            max_spread_multiple: Some(2.0),
+            seconds_to_sleep: 5,
+            extra_edge: 0.1,
+            scans: 2,
+        });
+        assert_eq!(fast_market.observe(calm), None);
+        assert_eq!(fast_market.observe(sample(5020.0, 1.5)), None);
+        assert!(!fast_market.is_active());
+        assert_eq!(fast_market.interval(30), 30);
+        assert_eq!(fast_market.arb_threshold(0.2), 0.2);
+
+        // A 1% drop starts a fast market.
+        assert!(fast_market.observe(sample(4969.8, 1.5)).is_some());
+        assert!(fast_market.is_active());
+        assert_eq!(fast_market.interval(30), 5);
+        assert!((fast_market.arb_threshold(0.2) - 0.3).abs() < 1e-9);
+
+        // A blowout in spreads extends it, and it lasts two calm scans after that.
+        assert!(fast_market
+            .observe(sample(4969.8, 4.0))
+            .is_some_and(|trigger| trigger.contains("spread")));
+        assert_eq!(fast_market.observe(sample(4969.8, 4.0)), None);
+        assert!(fast_market.is_active());
+        assert_eq!(fast_market.observe(sample(4969.8, 4.0)), None);
+        assert!(!fast_market.is_active());
+    }
+
+    #[test]
+    fn test_butterfly_parity() {
+        let (chain, mut quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 1,
+                num_strikes: 40,
+                noise: 0.0,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let scanner: Scanner = Scanner {
+            arb_val: 0.0,
+            butterfly_config: config.clone(),
+            boxspread_config: config.clone(),
+            calendar_config: config,
+            expiry_filter: ExpiryFilter {
+                exclude_same_day: false,
+                min_minutes_to_close: 0,
+            },
+            include_below_threshold: false,
+            near_miss_epsilon: None,
+            early_exercise: None,
+            dividends: DividendCalendar::new(),
+            rates: RateCurve::default(),
+            price_scale: 1.0,
+            leg_pricing: LegPricing::default(),
+            skipped_expiries: Vec::new(),
+            box_financing: None,
+            max_iv_deviation: None,
+        };
+        // A bad call quote at 5000, and both bodies rich at 4970.
+        let mut bump = |right: Right, strike: f64| {
+            quotes
+                .get_mut(&(0, right, StrikeKey::from_f64(strike)))
+                .unwrap()
+                .mkt += 1.0;
+        };
+        bump(Right::Call, 5000.0);
+        bump(Right::Call, 4970.0);
+        bump(Right::Put, 4970.0);
+
+        let contenders: Vec<Contender> = scanner
+            .get_butterfly_contenders(
+                &quotes,
+                &chain,
+                NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                390,
+                None,
+            )
+            .unwrap();
+        let body = |contender: &Contender| contender.contracts[1].strike;
+        let quote_error: &Contender = contenders.iter().find(|c| body(c) == 5000.0).unwrap();
+        assert!(!quote_error.above_threshold);
+        assert!(!quote_error.reason.checks[1].passed);
+        assert_eq!(quote_error.reason.checks[1].name, "parity_arb_val");
+
+        let confirmed: Vec<&Contender> = contenders.iter().filter(|c| body(c) == 4970.0).collect();
+        assert_eq!(confirmed.len(), 2);
+        for contender in confirmed {
+            assert!(contender.above_threshold && contender.reason.checks[1].passed);
+        }
+    }
+
+    #[test]
+    fn test_leg_pricing() {
+        assert_eq!(PriceSource::from_str("Ask"), Some(PriceSource::Ask));
+        assert_eq!(PriceSource::from_str("last"), None);
+
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 1,
+                num_strikes: 40,
+                noise: 0.0,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let scan = |leg_pricing: LegPricing| -> Vec<f64> {
+            let scanner: Scanner = Scanner {
+                arb_val: 0.0,
+                butterfly_config: config.clone(),
+                boxspread_config: config.clone(),
+                calendar_config: config.clone(),
+                expiry_filter: ExpiryFilter {
+                    exclude_same_day: false,
+                    min_minutes_to_close: 0,
+                },
+                include_below_threshold: true,
+                near_miss_epsilon: None,
+                early_exercise: None,
+                dividends: DividendCalendar::new(),
+                rates: RateCurve::flat(0.05),
+                price_scale: 1.0,
+                leg_pricing,
+                skipped_expiries: Vec::new(),
+                box_financing: None,
+                max_iv_deviation: None,
+            };
+            scanner
+                .scan(
+                    &OptionType::Butterfly,
+                    &quotes,
+                    &chain,
+                    NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                    390,
+                    Some(5000.0),
+                )
+                .unwrap()
+                .iter()
+                .map(|contender| contender.arb_val)
+                .collect()
+        };
+        let mid: Vec<f64> = scan(LegPricing::default());
+        assert!(!mid.is_empty());
+
+        // Crossing the spread on every leg always costs edge.
+        let crossed: Vec<f64> = scan(LegPricing {
+            buy: PriceSource::Ask,
+            sell: PriceSource::Bid,
+        });
+        assert_eq!(crossed.len(), mid.len());
+        assert!(crossed.iter().zip(&mid).all(|(crossed, mid)| crossed < mid));
+
+        // Without noise the quotes are the model prices, and without marks the mark is the mid.
+        for source in [PriceSource::Model, PriceSource::Mark] {
+            let priced: Vec<f64> = scan(LegPricing {
+                buy: source,
+                sell: source,
+            });
+            assert_eq!(priced.len(), mid.len());
+            assert!(priced
+                .iter()
+                .zip(&mid)
+                .all(|(priced, mid)| (priced - mid).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_quote_quality() {
+        let (chain, mut quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 2,
+                num_strikes: 40,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let previous: Quotes = quotes.clone();
+        // Pull the bids of the second expiration and move the first's quotes.
+        for ((expiry, _, _), opt) in quotes.iter_mut() {
+            if *expiry == 1 {
+                opt.bid = 0.0;
+            } else {
+                opt.ask += 0.05;
+            }
+        }
+
+        let qualities: Vec<ExpiryQuality> = score_expiries(&chain, &quotes, Some(&previous));
+        assert_eq!(qualities.len(), 2);
+        assert!(qualities[0].two_sided > 0.9 && qualities[0].stale == 0.0);
+        assert!(qualities[0].avg_spread > 0.0);
+        assert!(qualities[0].score() > 0.5);
+        assert_eq!(qualities[1].score(), 0.0);
+        // Unchanged quotes are all stale.
+        assert_eq!(
+            score_expiries(&chain, &previous, Some(&previous))[0].score(),
+            0.0
+        );
+        assert_eq!(score_expiries(&chain, &previous, None)[0].stale, 0.0);
+
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let mut scanner: Scanner = Scanner {
+            arb_val: 0.0,
+            butterfly_config: config.clone(),
+            boxspread_config: config.clone(),
+            calendar_config: config,
+            expiry_filter: ExpiryFilter {
+                exclude_same_day: false,
+                min_minutes_to_close: 0,
+            },
+            include_below_threshold: true,
+            near_miss_epsilon: None,
+            early_exercise: None,
+            dividends: DividendCalendar::new(),
+            rates: RateCurve::default(),
+            price_scale: 1.0,
+            leg_pricing: LegPricing::default(),
+            skipped_expiries: Vec::new(),
+            box_financing: None,
+            max_iv_deviation: None,
+        };
+        let scan = |scanner: &Scanner| -> Vec<Contender> {
+            scanner
+                .scan(
+                    &OptionType::All,
+                    &previous,
+                    &chain,
+                    NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                    390,
+                    None,
+                )
+                .unwrap()
+        };
+        assert!(scan(&scanner)
+            .iter()
+            .any(|c| c.exp_date == chain.dates()[0]));
+        scanner.skipped_expiries = vec![0];
+        let contenders: Vec<Contender> = scan(&scanner);
+        assert!(!contenders.is_empty());
+        assert!(contenders
+            .iter()
+            .all(|c| c.exp_date != chain.dates()[0] && c.type_spread != "Calendar"));
+    }
+
+    #[test]
+    fn test_early_exercise_screen() {
+        let date = |day: u32| NaiveDate::from_ymd_opt(2024, 8, day).unwrap();
+        let mut dividends: DividendCalendar = DividendCalendar::new();
+        dividends.add(date(9), 0.25);
+        dividends.add(date(20), 0.25);
+        assert_eq!(dividends.dividends_between(date(1), date(9)), 0.25);
+        assert_eq!(dividends.dividends_between(date(9), date(16)), 0.0);
+        assert_eq!(dividends.dividends_between(date(1), date(30)), 0.5);
+
+        let screen: EarlyExerciseScreen = EarlyExerciseScreen {
+            max_put_itm_percent: 2.0,
+        };
+        let dividend: f64 = dividends.dividends_between(date(1), date(16));
+        // A short call with less time value than the dividend is at risk, one with more is not.
+        assert!(screen
+            .short_leg_risk("C", 190.0, 10.1, dividend, Some(200.0))
+            .is_some());
+        assert!(screen
+            .short_leg_risk("C", 190.0, 10.5, dividend, Some(200.0))
+            .is_none());
+        assert!(screen
+            .short_leg_risk("C", 190.0, 10.1, 0.0, Some(200.0))
+            .is_none());
+
+        // Deep in-the-money short puts are at risk.
+        assert!(screen
+            .short_leg_risk("P", 210.0, 10.0, dividend, Some(200.0))
+            .is_some());
+        assert!(screen
+            .short_leg_risk("P", 202.0, 3.0, dividend, Some(200.0))
+            .is_none());
+        assert!(screen
+            .short_leg_risk("P", 202.0, 3.0, dividend, None)
+            .is_some());
+    }
+
+    #[test]
+    fn test_underlying_spec() {
+        let spx: UnderlyingSpec = UnderlyingSpec::for_ticker("spx");
+        assert_eq!(spx.ticker, "SPX");
+        assert!(spx.has_mini());
+        assert_eq!(spx.round_to_tick(1.23), 1.25);
+        assert_eq!(spx.round_to_tick(-0.57), -0.55);
+
+        let xsp: UnderlyingSpec = UnderlyingSpec::for_ticker("XSP");
+        assert!(!xsp.has_mini());
+        assert_eq!(xsp.price_scale, 0.1);
+        assert_eq!(xsp.round_to_tick(0.123), 0.12);
+        assert_eq!(UnderlyingSpec::for_ticker("AAPL").price_scale, 1.0);
+    }
+
+    #[test]
+    fn test_exchange_routing() {
+        let routing: ExchangeRouting = ExchangeRouting::new(
+            "smart".to_string(),
+            vec![("spx".to_string(), "cboe".to_string())],
+        );
+        assert_eq!(routing.exchange_for("SPX"), "CBOE");
+        assert_eq!(routing.exchange_for("XSP"), "SMART");
+
+        // Switching underlyings re-applies the route of the new ticker.
+        let spx: UnderlyingSpec = UnderlyingSpec::for_ticker("SPX").routed(&routing);
+        assert_eq!(spx.listing_exchange, "CBOE");
+        let xsp: UnderlyingSpec = UnderlyingSpec::for_ticker("XSP").routed(&routing);
+        assert_eq!(xsp.listing_exchange, "SMART");
+        assert_eq!(
+            UnderlyingSpec::for_ticker("SPX").listing_exchange,
+            ExchangeRouting::default().exchange_for("SPX")
+        );
+    }
+
+    #[test]
+    fn test_rate_curve() {
+        let curve: RateCurve = RateCurve::from_points(vec![(91, 0.05), (30, 0.04)]);
+        assert_eq!(curve.rate(10), 0.04);
+        assert!((curve.rate(60) - (0.04 + 0.01 * 30.0 / 61.0)).abs() < 1e-12);
+        assert_eq!(curve.rate(365), 0.05);
+        assert_eq!(RateCurve::default().discount_factor(365), 1.0);
+        assert_eq!(curve.discount_factor(0), 1.0);
+        assert!((RateCurve::flat(0.05).discount_factor(365) - (-0.05f64).exp()).abs() < 1e-12);
+
+        // FRED reports "." on holidays, so the latest value is taken from the next observation.
+        let body: serde_json::Value = serde_json::json!({
+            "observations": [
+                {"date": "2024-07-04", "value": "."},
+                {"date": "2024-07-03", "value": "5.00"},
+            ]
+        });
+        let rate: f64 = parse_fred_yield(&body).unwrap();
+        assert!((rate - 2.0 * 1.025f64.ln()).abs() < 1e-12);
+        assert_eq!(
+            parse_fred_yield(&serde_json::json!({"observations": []})),
+            None
+        );
+
+        // A FRED outage keeps the previous curve instead of exiting, and isn't retried that day.
+        let mut rate_client: RateClient = RateClient::new(&RateConfig {
+            fred_api_key: Some("key".to_string()),
+            fred_url: "http://127.0.0.1:1".to_string(),
+            fallback_rate: 0.05,
+        })
+        .unwrap();
+        let today: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 5).unwrap();
+        assert!(rate_client.refresh(today).is_none());
+        assert!(rate_client.refresh(today).is_none());
+    }
+
+    #[test]
+    fn test_dividend_calendar_per_underlying() {
+        let contents: &str = "# ticker,ex_date,amount\n\
+                              AAPL,2024-08-09,0.25\n\
+                              MSFT,2024-08-15,0.75\n\
+                              aapl,20241108,0.25\n\
+                              2024-09-01,0.10\n\
+                              not,a,dividend\n";
+        let dividends: DividendCalendar = DividendCalendar::parse(contents, "AAPL");
+        assert_eq!(dividends.len(), 3);
+        assert_eq!(DividendCalendar::parse(contents, "SPX").len(), 1);
+        assert_eq!(
+            parse_ex_date("20241108"),
+            NaiveDate::from_ymd_opt(2024, 11, 8)
+        );
+
+        let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        assert_eq!(dividends.dividends_between(date(8, 1), date(8, 31)), 0.25);
+        assert_eq!(dividends.dividends_between(date(8, 9), date(11, 30)), 0.35);
+
+        // A failed dividend fetch keeps the calendar instead of exiting.
+        assert_eq!(IBKR::new().add_upcoming_dividend(), None);
+    }
+
+    #[test]
+    fn test_moneyness() {
+        assert_eq!(parse_last_price("5,512.25"), Some(5512.25));
+        assert_eq!(parse_last_price("C5498.50"), Some(5498.5));
+        assert_eq!(parse_last_price(""), None);
+
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![5.0],
+            dte_window: None,
+            max_spot_distance_percent: Some(2.0),
+            straddle_spot: true,
+            max_net_delta: None,
+        };
+        assert!(config.allows_moneyness(4995.0, 5005.0, Some(5000.0)));
+        assert!(!config.allows_moneyness(5005.0, 5015.0, Some(5000.0)));
+        assert!(!config.allows_moneyness(4800.0, 4900.0, Some(4850.0 / 0.97)));
+
+        // Without a spot price the filters can't pass, unless there are none.
+        assert!(!config.allows_moneyness(4995.0, 5005.0, None));
+        let unfiltered: StrategyConfig = StrategyConfig {
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            ..config
+        };
+        assert!(unfiltered.allows_moneyness(4995.0, 5005.0, None));
+
+        // A failed spot fetch leaves the scan without a spot price instead of exiting.
+        assert_eq!(IBKR::new().fetch_spot(), None);
+    }
+
+    #[test]
+    fn test_minutes_to_market_close() {
+        // 19:30 UTC is 3:30 PM ET during daylight saving time.
+        let before_close: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 7, 1, 19, 30, 0).unwrap();
+        assert_eq!(minutes_to_market_close(before_close), 30);
+
+        let after_close: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 7, 1, 20, 15, 0).unwrap();
+        assert_eq!(minutes_to_market_close(after_close), -15);
+    }
+
+    #[test]
+    fn test_check_daily_loss() {
+        // Without a limit the P&L doesn't matter, even when it can't be fetched.
+        assert_eq!(check_daily_loss(Some(-900.0), None, 0), RiskCheck::Trade);
+        assert_eq!(check_daily_loss(None, None, 10), RiskCheck::Trade);
+
+        // The limit is reached at exactly the loss, and gains never reach it.
+        assert_eq!(
+            check_daily_loss(Some(-499.99), Some(500.0), 0),
+            RiskCheck::Trade
+        );
+        assert_eq!(
+            check_daily_loss(Some(-500.0), Some(500.0), 0),
+            RiskCheck::LimitReached
+        );
+        assert_eq!(
+            check_daily_loss(Some(250.0), Some(500.0), 0),
+            RiskCheck::Trade
+        );
+
+        // An unknown P&L skips ordering until it has failed too often in a row.
+        for failures in 1..MAX_PNL_FAILURES {
+            assert_eq!(
+                check_daily_loss(None, Some(500.0), failures),
+                RiskCheck::SkipOrders
+            );
+        }
+        assert_eq!(
+            check_daily_loss(None, Some(500.0), MAX_PNL_FAILURES),
+            RiskCheck::PnlUnavailable
+        );
+
+        // A P&L fetched after failures is checked as usual.
+        assert_eq!(
+            check_daily_loss(Some(-100.0), Some(500.0), MAX_PNL_FAILURES),
+            RiskCheck::Trade
+        );
+    }
+
+    #[test]
+    fn test_check_kill_switch() {
+        // A set kill switch stops trading, and one that can't be read skips ordering.
+        assert_eq!(check_kill_switch(&Ok(true)), RiskCheck::Killed);
+        assert_eq!(check_kill_switch(&Ok(false)), RiskCheck::Trade);
+        assert_eq!(
+            check_kill_switch(&Err("connection refused".into())),
+            RiskCheck::SkipOrders
+        );
+
+        // Without Redis the kill switch is local to this instance.
+        let config: SharedStateConfig = SharedStateConfig {
+            redis_url: None,
+            prefix: "test".to_string(),
+            max_global_exposure: Some(10.0),
+        };
+        let mut shared_state: SharedState = SharedState::connect(&config, "SPX").unwrap();
+        assert_eq!(
+            check_kill_switch(&shared_state.is_killed()),
+            RiskCheck::Trade
+        );
+        shared_state
+            .trip_kill_switch("daily loss of 500.00")
+            .unwrap();
+        assert_eq!(
+            check_kill_switch(&shared_state.is_killed()),
+            RiskCheck::Killed
+        );
+    }
+
+    #[test]
+    fn test_shared_state_exposure() {
+        let config: SharedStateConfig = SharedStateConfig {
+            redis_url: None,
+            prefix: "test".to_string(),
+            max_global_exposure: Some(10.0),
+        };
+        let mut shared_state: SharedState = SharedState::connect(&config, "SPX").unwrap();
+
+        // Working orders count towards the exposure until they are cancelled.
+        shared_state.set_working(6.0);
+        assert_eq!(shared_state.global_exposure().unwrap(), 6.0);
+
+        // Fills move combos from working to held, so cancelling the rest keeps them counted.
+        shared_state.record_fill(2.0);
+        assert_eq!(shared_state.exposure(), 6.0);
+        shared_state.set_working(0.0);
+        assert_eq!(shared_state.global_exposure().unwrap(), 2.0);
+
+        // Fills beyond the working orders never make the working count negative.
+        shared_state.record_fill(3.0);
+        assert_eq!(shared_state.exposure(), 5.0);
+
+        // Settled combos leave the exposure, which never goes negative.
+        shared_state.record_settlement(3.0);
+        assert_eq!(shared_state.exposure(), 2.0);
+        shared_state.record_settlement(5.0);
+        assert_eq!(shared_state.exposure(), 0.0);
+
+        // The combos held at startup replace the count, which never goes negative.
+        shared_state.set_filled(4.0);
+        assert_eq!(shared_state.global_exposure().unwrap(), 4.0);
+        shared_state.set_filled(-1.0);
+        assert_eq!(shared_state.exposure(), 0.0);
+    }
+
+    #[test]
+    fn test_fit_within_margin() {
+        let contender = |exp_date: &str, arb_val: f64| Contender {
+            arb_val,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: exp_date.parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            reason: Reason::default(),
+            contracts: Vec::new(),
+        };
+        let fitted = |maintenance: f64, net_liquidation: f64, margin_efficient: bool| {
+            fit_within_margin(
+                maintenance,
+                net_liquidation,
+                vec![
+                    (contender("240701", 1.0), 3000.0),
+                    (contender("240708", 0.5), 3000.0),
+                    (contender("240715", 0.1), 500.0),
+                    (contender("240722", 0.05), -1000.0),
+                ],
+                50.0,
+                margin_efficient,
+            )
+            .iter()
+            .map(|contender| contender.exp_date.to_string())
+            .collect::<Vec<String>>()
+        };
+
+        // 10k of net liquidation allows 5k of margin: the first contender's 3k counts against the
+        // second, which would reach 70%, while the smaller ones still fit.
+        assert_eq!(
+            fitted(1000.0, 10_000.0, false),
+            vec!["240701", "240715", "240722"]
+        );
+        // Reaching the ceiling exactly still fits.
+        assert_eq!(fitted(2000.0, 10_000.0, false), vec!["240701", "240722"]);
+        // Nothing fits over the ceiling, except orders that free up margin.
+        assert_eq!(fitted(5000.0, 10_000.0, false), vec!["240722"]);
+        // By edge per dollar of margin, the order freeing up margin goes first and makes room.
+        assert_eq!(
+            fitted(1000.0, 10_000.0, true),
+            vec!["240722", "240701", "240715"]
+        );
+        // Without net liquidation nothing fits.
+        assert!(fitted(0.0, 0.0, false).is_empty());
+        assert!(fitted(0.0, -500.0, true).is_empty());
+    }
+
+    #[test]
+    fn test_recent_contenders() {
+        let contender = |mkt_price: f64| Contender {
+            arb_val: 0.5,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts: vec![Contract {
+                strike: 5500.0,
+                mkt_price,
+                date: "240701".parse().unwrap(),
+                type_contract: "C".to_string(),
+            }],
+            reason: Reason::default(),
+        };
+        let now: Instant = Instant::now();
+        let mut recent_contenders: RecentContenders =
+            RecentContenders::new(Duration::from_secs(60));
+
+        recent_contenders.record(&[contender(12.5)], now);
+
+        // The same legs at the same price are skipped until the TTL expires.
+        assert!(recent_contenders.contains(&contender(12.5), now + Duration::from_secs(59)));
+        assert!(!recent_contenders.contains(&contender(12.5), now + Duration::from_secs(60)));
+
+        // A price change is a different contender.
+        assert!(!recent_contenders.contains(&contender(12.6), now));
+    }
+
+    #[test]
+    fn test_fill_cooldown() {
+        let contender = |strikes: &[f64], mkt_price: f64| Contender {
+            arb_val: 0.5,
+            avg_ask: 10.0,
+            type_spread: "Butterfly".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts: strikes
+                .iter()
+                .map(|strike| Contract {
+                    strike: *strike,
+                    mkt_price,
+                    date: "240701".parse().unwrap(),
+                    type_contract: "C".to_string(),
+                })
+                .collect(),
+            reason: Reason::default(),
+        };
+        let now: Instant = Instant::now();
+        let mut fill_cooldown: FillCooldown = FillCooldown::new(Duration::from_secs(60));
+
+        fill_cooldown.track("a1", &contender(&[5500.0, 5505.0, 5510.0], 1.0), now);
+        assert!(!fill_cooldown.contains(&contender(&[5510.0, 5515.0, 5520.0], 1.0), now));
+        assert!(!fill_cooldown.record_fill("unknown", now));
+        assert!(fill_cooldown.record_fill("a1", now));
+
+        // Any contender sharing a leg is skipped until the cooldown expires, whatever its price.
+        let overlapping: Contender = contender(&[5510.0, 5515.0, 5520.0], 2.0);
+        assert!(fill_cooldown.contains(&overlapping, now + Duration::from_secs(59)));
+        assert!(!fill_cooldown.contains(&overlapping, now + Duration::from_secs(60)));
+        assert!(!fill_cooldown.contains(&contender(&[5515.0, 5520.0, 5525.0], 1.0), now));
+
+        // A zero cooldown tracks nothing.
+        let mut disabled: FillCooldown = FillCooldown::new(Duration::ZERO);
+        disabled.track("a1", &overlapping, now);
+        assert!(!disabled.record_fill("a1", now));
+    }
+
+    #[test]
+    fn test_working_leg_conids() {
+        let response: LiveOrdersResponse = serde_json::from_str(
+            r#"{"orders": [
+                {"conidex": "28812380;;;101/1,102/-1", "status": "Submitted"},
+                {"conidex": "103", "status": "PreSubmitted"},
+                {"conidex": "28812380;;;104/1,105/-1", "status": "Filled"},
+                {"conidex": "28812380;;;106/1,107/-1", "status": "Cancelled"},
+                {"status": "Submitted"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.working_leg_conids(), vec!["101", "102", "103"]);
+
+        let empty: LiveOrdersResponse = serde_json::from_str("{}").unwrap();
+        assert!(empty.working_leg_conids().is_empty());
+    }
+
+    #[test]
+    fn test_order_replies() {
+        let replies: Vec<OrderReply> = serde_json::from_str(
+            r#"[{"id": "a1b2", "message": ["Price exceeds the percentage constraint."]}]"#,
+        )
+        .unwrap();
+        assert_eq!(replies[0].id.as_deref(), Some("a1b2"));
+        assert!(replies[0].order_id.is_none());
+
+        let replies: Vec<OrderReply> = serde_json::from_str(
+            r#"[{"order_id": "1234", "local_order_id": "c0", "order_status": "Submitted"}]"#,
+        )
+        .unwrap();
+        assert!(replies[0].id.is_none());
+        assert_eq!(replies[0].order_id.as_deref(), Some("1234"));
+        assert_eq!(replies[0].local_order_id.as_deref(), Some("c0"));
+    }
+
+    #[test]
+    fn test_gateway_models() {
+        // Snapshot fields arrive as strings or numbers, and stay `None` until quoted.
+        let snapshot: MarketDataResponse = serde_json::from_str(
+            r#"{"conidEx": "101", "84": "1,001.50", "85": 12, "86": "", "7308": null}"#,
+        )
+        .unwrap();
+        assert_eq!(snapshot.bid().unwrap(), Some(1001.5));
+        assert_eq!(snapshot.ask_size().unwrap(), Some(12.0));
+        assert_eq!(snapshot.ask().unwrap(), None);
+        assert_eq!(snapshot.delta().unwrap(), None);
+
+        let malformed: MarketDataResponse =
+            serde_json::from_str(r#"{"conidEx": "101", "84": "n/a"}"#).unwrap();
+        assert!(malformed
+            .bid()
+            .unwrap_err()
+            .to_string()
+            .contains("bid of 101"));
+        assert!(
+            serde_json::from_str::<MarketDataResponse>(r#"{"84": "1.00"}"#)
+                .unwrap_err()
+                .to_string()
+                .contains("conidEx")
+        );
+
+        let search: Vec<SecDefResponse> =
+            serde_json::from_str(r#"[{"conid": 416904, "sections": []}]"#).unwrap();
+        assert_eq!(search[0].conid.as_deref(), Some("416904"));
+        assert!(search[0].company_name.is_empty());
+
+        let rejected: OrderReplies =
+            serde_json::from_str(r#"{"error": "Order price exceeds the limit"}"#).unwrap();
+        assert!(rejected
+            .into_replies()
+            .unwrap_err()
+            .to_string()
+            .contains("exceeds the limit"));
+    }
+
+    #[test]
+    fn test_response_schema() {
+        assert_eq!(
+            ResponseSchema::for_version("Build 10.25.0p, Dec 5, 2023 6:43:23 PM"),
+            Ok(ResponseSchema::Legacy)
+        );
+        assert_eq!(
+            ResponseSchema::for_version("Build 10.30.1t, Jul 22, 2024 1:24:32 PM"),
+            Ok(ResponseSchema::Current)
+        );
+        assert!(ResponseSchema::for_version("Build 9.8.2, Jan 1, 2020")
+            .unwrap_err()
+            .contains("Build 9.8.2"));
+
+        // Legacy camelCase keys and numeric IDs are rewritten, current ones are left alone.
+        let mut accounts: serde_json::Value =
+            serde_json::from_str(r#"[{"accountId": "DU123"}, {"id": "DU456"}]"#).unwrap();
+        ResponseSchema::Legacy.adapt("/portfolio/accounts", &mut accounts);
+        assert_eq!(accounts[0]["id"], "DU123");
+        assert_eq!(accounts[1]["id"], "DU456");
+
+        let mut replies: serde_json::Value =
+            serde_json::from_str(r#"[{"orderId": 1234, "localOrderId": "c0"}]"#).unwrap();
+        ResponseSchema::Current.adapt("/iserver/account/DU123/orders", &mut replies);
+        assert!(replies[0].get("order_id").is_none());
+        ResponseSchema::Legacy.adapt("/iserver/account/DU123/orders", &mut replies);
+        let replies: Vec<OrderReply> = serde_json::from_value(replies).unwrap();
+        assert_eq!(replies[0].order_id.as_deref(), Some("1234"));
+        assert_eq!(replies[0].local_order_id.as_deref(), Some("c0"));
+    }
+
+    #[test]
+    fn test_http_config() {
+        let pem: &str = "# gateway\n-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\n-----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\ntrailing";
+        let certificates: Vec<String> = split_pem_bundle(pem);
+        assert_eq!(certificates.len(), 2);
+        assert!(certificates[0].starts_with("-----BEGIN CERTIFICATE-----\nAAA"));
+        assert!(certificates[1].ends_with("BBB\n-----END CERTIFICATE-----"));
+        assert!(split_pem_bundle("not a certificate").is_empty());
+
+        assert!(build_http_client(&HttpConfig::default()).is_ok());
+        assert!(build_http_client(&HttpConfig {
+            proxy: ProxySetting::Disabled,
+            ca_bundle: None,
+            accept_invalid_certs: true,
+        })
+        .is_ok());
+        let missing_bundle: HttpConfig = HttpConfig {
+            ca_bundle: Some("/nonexistent/gateway.pem".to_string()),
+            ..HttpConfig::default()
+        };
+        assert!(build_http_client(&missing_bundle)
+            .unwrap_err()
+            .to_string()
+            .contains("/nonexistent/gateway.pem"));
+    }
+
+    #[test]
+    fn test_worst_case_loss() {
+        let contender = |type_spread: &str, arb_val: f64, legs: &[(&str, f64, f64)]| Contender {
+            arb_val,
+            avg_ask: 10.0,
+            type_spread: type_spread.to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts: legs
+                .iter()
+                .map(|(type_contract, strike, mkt_price)| Contract {
+                    strike: *strike,
+                    mkt_price: *mkt_price,
+                    date: "240701".parse().unwrap(),
+                    type_contract: type_contract.to_string(),
+                })
+                .collect(),
+            reason: Reason::default(),
+        };
+
+        // A butterfly bought for a 1.00 debit loses at most the debit and the discounts.
+        let butterfly: Contender = contender(
+            "Butterfly",
+            -1.0,
+            &[("C", 5500.0, 10.0), ("C", 5505.0, 6.0), ("C", 5510.0, 3.0)],
+        );
+        assert!((worst_case_loss(&butterfly, 0.01) - 102.0).abs() < 1e-9);
+
+        // A 10 wide box sold for 9.90 owes 0.10 more than its credit at expiry.
+        let boxspread: Contender = contender(
+            "Boxspread",
+            9.9,
+            &[
+                ("P", 5500.0, 1.0),
+                ("C", 5500.0, 8.0),
+                ("C", 5510.0, 3.1),
+                ("P", 5510.0, 6.0),
+            ],
+        );
+        assert!((worst_case_loss(&boxspread, 0.0) - 10.0).abs() < 1e-9);
+        assert!((worst_case_loss(&boxspread, 0.05) - 20.0).abs() < 1e-9);
+
+        let calendar = |arb_val: f64| {
+            contender(
+                "Calendar",
+                arb_val,
+                &[("C", 5500.0, 5.0), ("C", 5500.0, 5.5)],
+            )
+        };
+        assert!((worst_case_loss(&calendar(-0.5), 0.0) - 50.0).abs() < 1e-9);
+        assert_eq!(worst_case_loss(&calendar(0.3), 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_contender_correlation() {
+        let butterfly = |exp_date: &str, low_strike: f64| Contender {
+            arb_val: 0.5,
+            avg_ask: 10.0,
+            type_spread: "Butterfly".to_string(),
+            exp_date: exp_date.parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts: [0.0, 5.0, 10.0]
+                .iter()
+                .map(|offset| Contract {
+                    strike: low_strike + offset,
+                    mkt_price: 1.0,
+                    date: exp_date.parse().unwrap(),
+                    type_contract: "C".to_string(),
+                })
+                .collect(),
+            reason: Reason::default(),
+        };
+        let fly: Contender = butterfly("240701", 5500.0);
+        let shifted: Contender = butterfly("240701", 5505.0);
+        let next_week: Contender = butterfly("240708", 5500.0);
+
+        assert_eq!(correlation(&fly, &fly), 1.0);
+        // Two shared legs on the same expiration, each traded in the opposite direction.
+        assert!((correlation(&fly, &shifted) - (0.5 * 2.0 / 3.0 + 0.2)).abs() < 1e-9);
+        // The same strikes in the same direction a week later.
+        assert!((correlation(&fly, &next_week) - 0.3).abs() < 1e-9);
+
+        let kept: Vec<Contender> = diversify(vec![fly, shifted, next_week], 0.4);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[1].exp_date.to_string(), "240708");
+        assert!((set_correlation(&kept) - 0.3).abs() < 1e-9);
+        assert_eq!(set_correlation(&kept[..1]), 0.0);
+    }
+
+    #[test]
+    fn test_rank_by_margin_efficiency() {
+        let contender = |exp_date: &str, arb_val: f64| Contender {
+            arb_val,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: exp_date.parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts: Vec::new(),
+            reason: Reason::default(),
+        };
+
+        // The best ranked contender earns the most but ties up far more margin per dollar of edge.
+        let ranked: Vec<(Contender, f64)> = rank_by_margin_efficiency(vec![
+            (contender("240701", 1.0), 2000.0),
+            (contender("240708", 0.5), 500.0),
+            (contender("240715", 0.1), 400.0),
+            (contender("240722", 0.05), -50.0),
+        ]);
+        let order: Vec<String> = ranked
+            .iter()
+            .map(|(contender, _)| contender.exp_date.to_string())
+            .collect();
+        assert_eq!(order, vec!["240722", "240708", "240701", "240715"]);
+    }
+
+    #[test]
+    fn test_combo_builder() {
+        let leg = |date: &str, type_contract: &str, strike: f64| Contract {
+            strike,
+            mkt_price: 1.0,
+            date: date.parse().unwrap(),
+            type_contract: type_contract.to_string(),
+        };
+        let lower: Contract = leg("240701", "C", 5000.0);
+        let middle: Contract = leg("240701", "C", 5005.0);
+        let next_week: Contract = leg("240708", "C", 5000.0);
+
+        assert_eq!(
+            ComboBuilder::new(ComboSpread::Vertical)
+                .leg("2", &middle, -1)
+                .leg("1", &lower, 1)
+                .build()
+                .unwrap(),
+            "28812380;;;2/-1,1/1"
+        );
+        assert_eq!(
+            ComboBuilder::new(ComboSpread::Calendar)
+                .leg("1", &lower, -1)
+                .leg("3", &next_week, 1)
+                .build()
+                .unwrap(),
+            "28812380;;;1/-1,3/1"
+        );
+
+        let vertical = |legs: &[(&str, &Contract, i32)]| -> Result<String, String> {
+            legs.iter()
+                .fold(
+                    ComboBuilder::new(ComboSpread::Vertical),
+                    |combo, (conid, contract, ratio)| combo.leg(conid, contract, *ratio),
+                )
+                .build()
+        };
+        assert!(vertical(&[("1", &lower, 1)]).is_err());
+        assert!(vertical(&[("1", &lower, 1), ("1", &middle, -1)]).is_err());
+        assert!(vertical(&[("1", &lower, 1), ("2", &middle, 1)]).is_err());
+        assert!(vertical(&[("1", &lower, 2), ("2", &middle, -2)]).is_err());
+        assert!(vertical(&[("1", &lower, 1), ("2", &leg("240701", "P", 5005.0), -1)]).is_err());
+        assert!(vertical(&[("1", &lower, 1), ("3", &next_week, -1)]).is_err());
+        assert!(vertical(&[("", &lower, 1), ("2", &middle, -1)]).is_err());
+        // A calendar buying the nearer expiration is backwards.
+        assert!(ComboBuilder::new(ComboSpread::Calendar)
+            .leg("1", &lower, 1)
+            .leg("3", &next_week, -1)
+            .build()
+            .is_err());
+        assert!(ComboBuilder::new(ComboSpread::Calendar)
+            .leg("1", &lower, -1)
+            .leg("2", &middle, 1)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_osi_symbol() {
+        let leg: Contract = Contract {
+            strike: 5900.0,
+            mkt_price: 12.3,
+            date: "241219".parse().unwrap(),
+            type_contract: "P".to_string(),
+        };
+        assert_eq!(leg.osi_symbol("SPX", None), "SPXW  241219P05900000");
+        // Without a trading class, the third-Friday contract is taken to be the SPX monthly.
+        assert_eq!(
+            osi_symbol("spx", None, "241220".parse().unwrap(), "C", 5900.0),
+            "SPX   241220C05900000"
+        );
+        assert_eq!(
+            osi_symbol("XSP", None, "241220".parse().unwrap(), "P", 590.5),
+            "XSP   241220P00590500"
+        );
+        assert_eq!(osi_root("NDX", None, "241219".parse().unwrap()), "NDXP");
+
+        // The PM-settled weeklies also expire on the third Friday, and keep the SPXW root.
+        let third_friday: ExpiryDate = "241220".parse().unwrap();
+        assert_eq!(
+            osi_symbol("SPX", Some("SPXW"), third_friday, "C", 5900.0),
+            "SPXW  241220C05900000"
+        );
+        assert_eq!(osi_root("NDX", Some("NDX"), third_friday), "NDX");
+        assert_eq!(osi_root("RUT", Some("RUTW"), third_friday), "RUTW");
+
+        // The trading class comes from the gateway's secdef info through the chain.
+        let mut chain: OptionChain = OptionChain::new();
+        let expiry: ExpiryId = chain.add_expiry(third_friday);
+        chain.insert(expiry, Right::Call, 5900.0, "1".to_string());
+        chain.insert(expiry, Right::Call, 5905.0, "2".to_string());
+        chain.set_trading_class(expiry, Right::Call, 5900.0, "SPXW".to_string());
+        let weekly: Contract = Contract {
+            strike: 5900.0,
+            mkt_price: 12.3,
+            date: third_friday,
+            type_contract: "C".to_string(),
+        };
+        assert_eq!(
+            weekly.osi_symbol("SPX", Some(&chain)),
+            "SPXW  241220C05900000"
+        );
+        let unlisted: Contract = Contract {
+            strike: 5905.0,
+            ..weekly
+        };
+        assert_eq!(
+            unlisted.osi_symbol("SPX", Some(&chain)),
+            "SPX   241220C05905000"
+        );
+        let sec_def_info: SecDefInfoResponse = serde_json::from_str(
+            r#"{"conid": 1, "maturityDate": "20241220", "right": "C", "strike": 5900.0, "tradingClass": "SPXW"}"#,
+        )
+        .unwrap();
+        assert_eq!(sec_def_info.trading_class.as_deref(), Some("SPXW"));
+    }
+
+    #[test]
+    fn test_watchlist() {
+        let current_date: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 3,
+                num_strikes: 40,
+                noise: 0.0,
+                ..ChainParams::default()
+            },
+            current_date,
+        );
+        let date: ExpiryDate = chain.dates()[2];
+        let leg = |right: &str, strike: f64| WatchlistLeg {
+            date,
+            right: right.to_string(),
+            strike,
+        };
+        let butterfly: WatchlistEntry = WatchlistEntry {
+            strategy: "butterfly".to_string(),
+            legs: vec![leg("C", 4990.0), leg("C", 5000.0), leg("C", 5010.0)],
+            min_edge: Some(-100.0),
+            note: Some("wide wings".to_string()),
+        };
+        let lopsided: WatchlistEntry = WatchlistEntry {
+            legs: vec![leg("C", 4990.0), leg("C", 5000.0), leg("C", 5020.0)],
+            ..butterfly.clone()
+        };
+        assert!(butterfly.to_contender().is_ok());
+        assert!(lopsided.to_contender().is_err());
+        assert!(
+            parse_watchlist(&serde_json::to_string(&[&butterfly, &lopsided]).unwrap())
+                .unwrap_err()
+                .to_string()
+                .starts_with("watchlist entry 1:")
+        );
+        assert!(parse_watchlist(r#"[{"strategy": "Condor", "legs": []}]"#).is_err());
+
+        let config: StrategyConfig = StrategyConfig {
+            wing_widths: vec![10.0],
+            dte_window: None,
+            max_spot_distance_percent: None,
+            straddle_spot: false,
+            max_net_delta: None,
+        };
+        let scanner: Scanner = Scanner {
+            arb_val: 0.0,
+            butterfly_config: config.clone(),
+            boxspread_config: config.clone(),
+            calendar_config: config,
+            expiry_filter: ExpiryFilter {
+                exclude_same_day: false,
+                min_minutes_to_close: 0,
+            },
+            include_below_threshold: false,
+            near_miss_epsilon: None,
+            early_exercise: None,
+            dividends: DividendCalendar::new(),
+            rates: RateCurve::flat(0.05),
+            price_scale: 1.0,
+            leg_pricing: LegPricing::default(),
+            skipped_expiries: Vec::new(),
+            box_financing: None,
+            max_iv_deviation: None,
+        };
+        let priced: Vec<Contender> = scanner.price_watchlist(
+            std::slice::from_ref(&butterfly),
+            &quotes,
+            &chain,
+            current_date,
+            390,
+            Some(5000.0),
+        );
+        assert_eq!(priced.len(), 1);
+        assert!(priced[0].above_threshold);
+        assert!(priced[0].reason.formula.starts_with("watchlist: "));
+        assert!(priced[0]
+            .contracts
+            .iter()
+            .all(|contract| contract.mkt_price > 0.0));
+        // A long butterfly costs money, so it shows no edge at the arb value threshold.
+        let strict: WatchlistEntry = WatchlistEntry {
+            min_edge: None,
+            ..butterfly.clone()
+        };
+        assert!(scanner
+            .price_watchlist(&[strict], &quotes, &chain, current_date, 390, Some(5000.0))
+            .is_empty());
+
+        let path: std::path::PathBuf = env::temp_dir().join("trading_bot_test_watchlist.json");
+        let path: &str = path.to_str().unwrap();
+        assert_eq!(export_watchlist(path, &priced).unwrap(), 1);
+        assert_eq!(import_watchlist(path).unwrap(), 1);
+        let imported: Vec<WatchlistEntry> = watchlist();
+        assert_eq!(imported[0].strategy, "Butterfly");
+        assert_eq!(imported[0].legs, butterfly.legs);
+        assert_eq!(imported[0].min_edge, None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_quote_args() {
+        let args = |text: &str| -> Vec<String> { text.split(' ').map(str::to_string).collect() };
+        let quote_request: QuoteRequest = parse_quote_args(&args(
+            "250117/C/5900/-2 250117/c/5910/+1 250117/C/5890/1 --min-edge 0.1 --fills 2 --submit",
+        ))
+        .unwrap();
+        assert_eq!(quote_request.entry.strategy, "Butterfly");
+        assert_eq!(
+            quote_request
+                .entry
+                .legs
+                .iter()
+                .map(|leg| leg.strike)
+                .collect::<Vec<f64>>(),
+            vec![5890.0, 5900.0, 5910.0]
+        );
+        assert_eq!(quote_request.entry.min_edge, Some(0.1));
+        assert_eq!(quote_request.num_fills, 2);
+        assert!(quote_request.submit);
+
+        let boxspread: QuoteRequest = parse_quote_args(&args(
+            "250117/C/5910/1 250117/P/5900/1 250117/P/5910/-1 250117/C/5900/-1",
+        ))
+        .unwrap();
+        assert_eq!(boxspread.entry.strategy, "Boxspread");
+        assert_eq!(
+            boxspread
+                .entry
+                .legs
+                .iter()
+                .map(|leg| format!("{}{}", leg.right, leg.strike))
+                .collect::<Vec<String>>(),
+            vec!["P5900", "C5900", "C5910", "P5910"]
+        );
+        let calendar: QuoteRequest =
+            parse_quote_args(&args("250221/P/5900/1 250117/P/5900/-1")).unwrap();
+        assert_eq!(calendar.entry.strategy, "Calendar");
+        assert!(!calendar.submit);
+        assert_eq!(calendar.num_fills, 1);
+
+        // A short butterfly isn't traded, and the legs must be complete and well formed.
+        assert!(
+            parse_quote_args(&args("250117/C/5890/-1 250117/C/5900/2 250117/C/5910/-1")).is_err()
+        );
+        assert!(parse_quote_args(&args("250117/C/5900/1")).is_err());
+        assert!(parse_quote_args(&args("250221/P/5900/1 250117/P/5900/-1 --fills 0")).is_err());
+        assert!("250117/X/5900/1".parse::<QuoteLeg>().is_err());
+        assert!("250117/C/5900/0".parse::<QuoteLeg>().is_err());
+        assert!("250117/C/5900".parse::<QuoteLeg>().is_err());
+    }
+
+    #[test]
+    fn test_open_combos() {
+        let fill = |order_ref: &str, size: f64, fill_price: f64, simulated: bool| -> String {
+            json!({
+                "event": "fill",
+                "simulated": simulated,
+                "order_ref": order_ref,
+                "strategy": "Butterfly",
+                "exp_date": "250117",
+                "size": size,
+                "fill_price": fill_price,
+                "legs": [
+                    {"symbol": "SPXW  250117C05890000", "ratio": 1, "conid": "101"},
+                    {"symbol": "SPXW  250117C05900000", "ratio": -1, "conid": "102"},
+                ],
+            })
+            .to_string()
+        };
+        let journal: String = [
+            json!({"event": "order", "order_ref": "abc-0"}).to_string(),
+            fill("abc-0", 1.0, 1.0, false),
+            fill("abc-0.1", 3.0, 1.4, false),
+            fill("def-0", 2.0, 0.5, true),
+            // Journaled before leg conids were recorded.
+            json!({"event": "fill", "order_ref": "old-0", "exp_date": "250117", "size": 1.0,
+                "fill_price": 1.0, "legs": [{"symbol": "SPXW  250117C05890000", "ratio": 1}]})
+            .to_string(),
+        ]
+        .join("\n");
+        let combos: Vec<OpenCombo> = parse_open_combos(&journal);
+        assert_eq!(combos.len(), 1);
+        assert_eq!(combos[0].order_ref, "abc-0");
+        assert_eq!(combos[0].size, 4.0);
+        assert!((combos[0].fill_price - 1.3).abs() < 1e-9);
+        assert_eq!(combos[0].conidex(), "28812380;;;101/1,102/-1");
+
+        let positions = |long: f64, short: f64| -> FxHashMap<String, f64> {
+            [("101".to_string(), long), ("102".to_string(), short)]
+                .into_iter()
+                .collect()
+        };
+        assert_eq!(combos[0].held(&positions(4.0, -4.0)), 4.0);
+        assert_eq!(combos[0].held(&positions(6.0, -2.0)), 2.0);
+        assert_eq!(combos[0].held(&positions(4.0, 0.0)), 0.0);
+
+        let args =
+            |text: &str| -> Vec<String> { text.split_whitespace().map(str::to_string).collect() };
+        assert_eq!(parse_combo_args(&args("")), Ok(ComboRequest::default()));
+        assert_eq!(
+            parse_combo_args(&args("abc --fills 2")),
+            Ok(ComboRequest {
+                order_ref: Some("abc".to_string()),
+                num_fills: Some(2),
+                expiry: None,
+            })
+        );
+        assert!(parse_combo_args(&args("abc def")).is_err());
+        assert!(parse_combo_args(&args("abc --fills")).is_err());
+    }
+
+    #[test]
+    fn test_calendar_roll() {
+        let leg: Contract = parse_osi_symbol("SPX   250117P05900500").unwrap();
+        assert_eq!(leg.date, "250117".parse().unwrap());
+        assert_eq!(leg.type_contract, "P");
+        assert_eq!(leg.strike, 5900.5);
+        assert_eq!(leg.osi_symbol("SPX", None), "SPX   250117P05900500");
+        assert!(parse_osi_symbol("SPXW  250117X05900000").is_none());
+        assert!(parse_osi_symbol("SPXW  250117P0590").is_none());
+
+        let leg_json = |symbol: &str, ratio: i32, conid: &str| -> Value {
+            json!({"symbol": symbol, "ratio": ratio, "conid": conid})
+        };
+        let journal: String = [
+            json!({"event": "fill", "order_ref": "cal-0", "strategy": "Calendar",
+            "exp_date": "250117", "size": 2.0, "fill_price": 3.0, "legs": [
+                leg_json("SPXW  250117P05900000", -1, "201"),
+                leg_json("SPXW  250221P05900000", 1, "203"),
+            ]}),
+            json!({"event": "roll", "order_ref": "roll-cal-0", "rolls": "cal-0",
+            "strategy": "Calendar", "exp_date": "250124", "size": 2.0, "fill_price": 1.8,
+            "legs": [
+                leg_json("SPXW  250124P05900000", -1, "202"),
+                leg_json("SPXW  250221P05900000", 1, "203"),
+            ]}),
+        ]
+        .map(|entry| entry.to_string())
+        .join("\n");
+        let combos: Vec<OpenCombo> = parse_open_combos(&journal);
+        assert_eq!(combos.len(), 2);
+        assert_eq!(combos[1].order_ref, "roll-cal-0");
+        assert_eq!(combos[1].exp_date, "250124".parse().unwrap());
+
+        // Until the roll fills, the original calendar is held; after, the rolled one is.
+        let before: FxHashMap<String, f64> = [("201".to_string(), -2.0), ("203".to_string(), 2.0)]
+            .into_iter()
+            .collect();
+        let after: FxHashMap<String, f64> = [("202".to_string(), -2.0), ("203".to_string(), 2.0)]
+            .into_iter()
+            .collect();
+        assert_eq!(combos[0].held(&before), 2.0);
+        assert_eq!(combos[1].held(&before), 0.0);
+        assert_eq!(combos[0].held(&after), 0.0);
+        assert_eq!(combos[1].held(&after), 2.0);
+
+        // Calendars are due to roll once their short leg is within the days, until it expires.
+        assert!(!combos[0].roll_due(NaiveDate::from_ymd_opt(2025, 1, 15).unwrap(), 1));
+        assert!(combos[0].roll_due(NaiveDate::from_ymd_opt(2025, 1, 16).unwrap(), 1));
+        assert!(combos[0].roll_due(NaiveDate::from_ymd_opt(2025, 1, 17).unwrap(), 1));
+        assert!(!combos[0].roll_due(NaiveDate::from_ymd_opt(2025, 1, 18).unwrap(), 1));
+        assert!(!combos[1].roll_due(NaiveDate::from_ymd_opt(2025, 1, 16).unwrap(), 1));
+        let butterfly: OpenCombo = OpenCombo {
+            strategy: "Butterfly".to_string(),
+            ..combos[0].clone()
+        };
+        assert!(!butterfly.roll_due(NaiveDate::from_ymd_opt(2025, 1, 16).unwrap(), 1));
+
+        // Automatic rolls pick each calendar once inside the window, and none outside it.
+        let day = |day: u32| NaiveDate::from_ymd_opt(2025, 1, day).unwrap();
+        let due = |ibkr: &IBKR, today: NaiveDate| -> Vec<String> {
+            ibkr.due_rolls(combos.clone(), today)
+                .into_iter()
+                .map(|combo| combo.order_ref)
+                .collect()
+        };
+        let mut ibkr: IBKR = IBKR::new();
+        assert!(due(&ibkr, day(16)).is_empty());
+        ibkr.set_auto_roll(1, 0.05);
+        assert!(due(&ibkr, day(15)).is_empty());
+        assert_eq!(due(&ibkr, day(16)), vec!["cal-0"]);
+        ibkr.record_roll("cal-0", day(16));
+        assert!(due(&ibkr, day(16)).is_empty());
+        assert!(due(&ibkr, day(18)).is_empty());
+        // A roll that didn't fill is retried the next day; once it fills, the calendar isn't held.
+        assert_eq!(due(&ibkr, day(17)), vec!["cal-0"]);
+        assert_eq!(combos[0].held(&after), 0.0);
+
+        let args = |text: &str| -> Vec<String> { text.split(' ').map(str::to_string).collect() };
+        assert_eq!(
+            parse_combo_args(&args("cal --to 250124")).unwrap().expiry,
+            Some("250124".parse().unwrap())
+        );
+        assert!(parse_combo_args(&args("cal --to 2501")).is_err());
+    }
+
+    #[test]
+    fn test_assignments() {
+        let leg_json = |symbol: &str, ratio: i32, conid: &str| -> Value {
+            json!({"symbol": symbol, "ratio": ratio, "conid": conid})
+        };
+        let short_leg: Value = leg_json("SPXW  250117C05900000", -1, "201");
+        let long_leg: Value = leg_json("SPXW  250124C05900000", 1, "202");
+        let mut journal: Vec<String> = vec![
+            json!({"event": "fill", "order_ref": "cal-0", "strategy": "Calendar",
+                "exp_date": "250117", "size": 2.0, "fill_price": 3.0,
+                "legs": [short_leg, long_leg]})
+            .to_string(),
+            json!({"event": "fill", "order_ref": "abc-0", "strategy": "Butterfly",
+                "exp_date": "250117", "size": 1.0, "fill_price": 1.0,
+                "legs": [leg_json("SPXW  250117C05890000", 1, "101"),
+                    leg_json("SPXW  250117C05910000", -1, "102")]})
+            .to_string(),
+        ];
+        let combos: Vec<OpenCombo> = parse_open_combos(&journal.join("\n"));
+        let positions = |held: &[(&str, f64)]| -> FxHashMap<String, f64> {
+            held.iter()
+                .map(|(conid, position)| (conid.to_string(), *position))
+                .collect()
+        };
+        let date = |yymmdd: &str| NaiveDate::parse_from_str(yymmdd, "%y%m%d").unwrap();
+
+        // The first poll only records the positions.
+        let mut watch: PositionWatch = PositionWatch::default();
+        let held: FxHashMap<String, f64> =
+            positions(&[("201", -2.0), ("202", 2.0), ("101", 1.0), ("102", -1.0)]);
+        assert!(watch
+            .reconcile(&combos, &held, &[], date("250115"), 1_000)
+            .is_empty());
+
+        // One short call is assigned early while the butterfly is sold, which its executions
+        // explain.
+        let executions: Vec<LegExecution> = [("101", -1.0), ("102", 1.0)]
+            .iter()
+            .map(|(conid, quantity)| LegExecution {
+                execution_id: format!("exec-{}", conid),
+                conid: conid.to_string(),
+                quantity: *quantity,
+                time: 1_500,
+            })
+            .collect();
+        let held: FxHashMap<String, f64> = positions(&[("201", -1.0), ("202", 2.0)]);
+        let settlements: Vec<ComboSettlement> =
+            watch.reconcile(&combos, &held, &executions, date("250116"), 2_000);
+        assert_eq!(settlements.len(), 1);
+        let settlement: &ComboSettlement = &settlements[0];
+        assert_eq!(settlement.combo.order_ref, "cal-0");
+        assert_eq!(settlement.outcome(), LegOutcome::Assignment);
+        assert_eq!(settlement.size, 1.0);
+        assert_eq!(settlement.events[0].describe(), "SPXW  250117C05900000 -1");
+        let remainder: OpenCombo = settlement.remainder().unwrap();
+        assert_eq!(remainder.order_ref, "assignment-cal-0");
+        assert_eq!(remainder.conidex(), "202");
+
+        // The settlement shrinks the calendar, and the long call it left stays in the inventory.
+        journal.push(
+            json!({"event": "settlement", "outcome": "assignment",
+                "order_ref": remainder.order_ref, "settles": "cal-0", "strategy": "Calendar",
+                "exp_date": "250117", "size": 1.0, "fill_price": 3.0, "legs": [long_leg]})
+            .to_string(),
+        );
+        let combos: Vec<OpenCombo> = parse_open_combos(&journal.join("\n"));
+        assert_eq!(
+            combos
+                .iter()
+                .map(|combo| (combo.order_ref.as_str(), combo.size))
+                .collect::<Vec<(&str, f64)>>(),
+            vec![("cal-0", 1.0), ("abc-0", 1.0), ("assignment-cal-0", 1.0)]
+        );
+
+        // The other short call expires, which settles the rest of the calendar.
+        let held: FxHashMap<String, f64> = positions(&[("202", 2.0)]);
+        let settlements: Vec<ComboSettlement> =
+            watch.reconcile(&combos, &held, &executions, date("250118"), 3_000);
+        assert_eq!(settlements.len(), 1);
+        assert_eq!(settlements[0].combo.order_ref, "cal-0");
+        assert_eq!(settlements[0].outcome(), LegOutcome::Expiration);
+        assert_eq!(settlements[0].size, 1.0);
+    }
+
+    #[test]
+    fn test_contender_order_id() {
+        let mut contender: Contender = Contender {
+            arb_val: 0.5,
+            avg_ask: 10.0,
+            type_spread: "Butterfly".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 1719849600000,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts: Vec::new(),
+            reason: Reason::default(),
+        };
+
+        // The same scan yields the same id, each order of the contender gets its own.
+        assert_eq!(contender.order_id(0), contender.order_id(0));
+        assert_ne!(contender.order_id(0), contender.order_id(1));
+
+        // A later scan of the same legs is a new order.
+        let first_id: String = contender.order_id(0);
+        contender.scan_time += 1;
+        assert_ne!(contender.order_id(0), first_id);
+    }
+
+    #[test]
+    fn test_liquidity_sizing() {
+        let leg = |strike: f64, asz: f64, bsz: Option<f64>| {
+            let opt: Opt = Opt {
+                asz,
+                mkt: 5.0,
+                bid: 4.9,
+                ask: 5.1,
+                bsz,
+                delta: None,
+                mark: None,
+                iv: None,
+                model: None,
+            };
+            (
+                Contract {
+                    strike,
+                    mkt_price: opt.mkt,
+                    date: "240701".parse().unwrap(),
+                    type_contract: "C".to_string(),
+                },
+                LegQuote::new("240701".parse().unwrap(), "C", strike, &opt),
+            )
+        };
+        let (contracts, quotes): (Vec<Contract>, Vec<LegQuote>) = vec![
+            leg(5000.0, 8.0, Some(1.0)),
+            leg(5005.0, 20.0, Some(6.0)),
+            leg(5010.0, 4.0, None),
+        ]
+        .into_iter()
+        .unzip();
+        let contender: Contender = Contender {
+            arb_val: 0.5,
+            avg_ask: 10.0,
+            type_spread: "Butterfly".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts,
+            reason: Reason {
+                quotes,
+                ..Reason::default()
+            },
+        };
+
+        // The wings are bought at the ask, the body is sold twice at the bid.
+        assert_eq!(contender.displayed_size(), Some(3.0));
+        assert_eq!(LiquiditySizing::Cap.quantities(5, Some(3.0)), vec![3]);
+        assert_eq!(
+            LiquiditySizing::Split.quantities(8, Some(3.0)),
+            vec![3, 3, 2]
+        );
+        assert_eq!(LiquiditySizing::Off.quantities(5, Some(3.0)), vec![5]);
+        assert_eq!(LiquiditySizing::Cap.quantities(5, Some(0.5)), vec![1]);
+        assert_eq!(LiquiditySizing::Split.quantities(5, None), vec![5]);
+    }
+
+    #[test]
+    fn test_drop_self_matches() {
+        let order = |con_idex: &str, c_oid: &str| OrderBody {
+            acct_id: "U1".to_string(),
+            con_idex: con_idex.to_string(),
+            order_type: "LMT".to_string(),
+            listing_exchange: "SMART".to_string(),
+            outside_rth: false,
+            price: 1.0,
+            side: "BUY".to_string(),
+            ticker: "SPX".to_string(),
+            tif: "DAY".to_string(),
+            referrer: "NO_REFERRER_PROVIDED".to_string(),
+            quantity: 1,
+            use_adaptive: false,
+            strategy: None,
+            strategy_parameters: BTreeMap::new(),
+            c_oid: c_oid.to_string(),
+            price_offset: 0.0,
+        };
+        assert_eq!(
+            order("28812380;;;1/-1,2/1", "a").leg_ratios(),
+            vec![("1".to_string(), -1), ("2".to_string(), 1)]
+        );
+
+        // The butterfly sells leg 2, which the better-ranked box buys, so it is dropped; the
+        // calendar also buys leg 2 and is kept.
+        let kept: Vec<Vec<OrderBody>> = drop_self_matches(vec![
+            vec![
+                order("28812380;;;1/-1,2/1", "box-0"),
+                order("28812380;;;3/1,4/-1", "box-1"),
+            ],
+            vec![
+                order("28812380;;;5/1,2/-1", "fly-0"),
+                order("28812380;;;6/1,2/-1", "fly-1"),
+            ],
+            vec![order("28812380;;;7/-1,2/1", "cal-0")],
+        ]);
+        let c_oids: Vec<&str> = kept
+            .iter()
+            .flatten()
+            .map(|order| order.c_oid.as_str())
+            .collect();
+        assert_eq!(c_oids, vec!["box-0", "box-1", "cal-0"]);
+    }
+
+    #[test]
+    fn test_combo_quote() {
+        let snapshot = |fields: &str| -> MarketDataResponse {
+            serde_json::from_str(&format!(
+                r#"{{"conidEx": "28812380;;;1/1,2/-1"{}}}"#,
+                fields
+            ))
+            .unwrap()
+        };
+        assert_eq!(snapshot("").combo_quote(), None);
+        assert_eq!(
+            snapshot(r#", "84": "-10.15", "86": "-9.85""#).combo_quote(),
+            Some((-10.15, -9.85))
+        );
+        assert_eq!(
+            snapshot(r#", "84": "1.20", "86": "1.10""#).combo_quote(),
+            None
+        );
+
+        let order: OrderBody = OrderBody {
+            acct_id: "U1".to_string(),
+            con_idex: "28812380;;;1/1,2/-1".to_string(),
+            order_type: "LMT".to_string(),
+            listing_exchange: "SMART".to_string(),
+            outside_rth: false,
+            price: -9.95,
+            side: "BUY".to_string(),
+            ticker: "SPX".to_string(),
+            tif: "DAY".to_string(),
+            referrer: "NO_REFERRER_PROVIDED".to_string(),
+            quantity: 1,
+            use_adaptive: false,
+            strategy: None,
+            strategy_parameters: BTreeMap::new(),
+            c_oid: "a".to_string(),
+            price_offset: 0.0,
+        };
+        let spx: UnderlyingSpec = UnderlyingSpec::for_ticker("SPX");
+        // A combo mid below the leg-based price lowers the limit to the next tick.
+        assert_eq!(combo_limit_price(&order, -10.15, -9.95, 0.02, &spx), -10.05);
+        // A wider combo never raises it.
+        assert_eq!(combo_limit_price(&order, -10.0, -9.5, 0.02, &spx), -9.95);
+    }
+
+    #[test]
+    fn test_match_replies() {
+        let contender = |strike: f64| Contender {
+            arb_val: 0.35,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.5,
+            scan_time: 1_719_840_000_000,
+            fill_probability: 0.25,
+            above_threshold: true,
+            contracts: vec![
+                Contract {
+                    strike,
+                    mkt_price: 1.0,
+                    date: "240701".parse().unwrap(),
+                    type_contract: "C".to_string(),
+                },
+                Contract {
+                    strike,
+                    mkt_price: 1.0,
+                    date: "240708".parse().unwrap(),
+                    type_contract: "C".to_string(),
+                },
+            ],
+            reason: Reason::default(),
+        };
+        let contenders: Vec<Contender> = vec![contender(5000.0), contender(5010.0)];
+        let order = |c_oid: String, price: f64| OrderBody {
+            acct_id: "U1".to_string(),
+            con_idex: "28812380;;;1/1,2/-1".to_string(),
+            order_type: "LMT".to_string(),
+            listing_exchange: "SMART".to_string(),
+            outside_rth: false,
+            price,
+            side: "BUY".to_string(),
+            ticker: "SPX".to_string(),
+            tif: "DAY".to_string(),
+            referrer: "NO_REFERRER_PROVIDED".to_string(),
+            quantity: 1,
+            use_adaptive: false,
+            strategy: None,
+            strategy_parameters: BTreeMap::new(),
+            c_oid,
+            price_offset: 0.0,
+        };
+        let orders: Vec<OrderBody> = vec![
+            order(contenders[0].order_id(0), -0.33),
+            order(format!("{}.1", contenders[1].order_id(0)), -0.28),
+            order(format!("{}.2", contenders[1].order_id(0)), -0.27),
+        ];
+        let reply = |order_id: Option<&str>, local_order_id: Option<&str>| OrderReply {
+            order_id: order_id.map(str::to_string),
+            local_order_id: local_order_id.map(str::to_string),
+            ..OrderReply::default()
+        };
+
+        // A reply without an order id doesn't drop the orders accepted after it.
+        let replies: Vec<OrderReply> = vec![
+            reply(None, None),
+            reply(Some("101"), None),
+            reply(Some("102"), Some(&orders[0].c_oid)),
+            reply(Some("103"), None),
+        ];
+        let (accepted, missing): (Vec<AcceptedOrder>, usize) =
+            match_replies(&contenders, &orders, &replies);
+        assert_eq!(missing, 1);
+        let matched: Vec<(&str, Option<&str>, Option<f64>)> = accepted
+            .iter()
+            .map(|accepted| {
+                (
+                    accepted.order_id,
+                    accepted.submitted.map(|(order, _)| order.c_oid.as_str()),
+                    accepted
+                        .submitted
+                        .map(|(_, contender)| contender.contracts[0].strike),
+                )
+            })
+            .collect();
+        assert_eq!(
+            matched,
+            vec![
+                ("101", Some(orders[1].c_oid.as_str()), Some(5010.0)),
+                ("102", Some(orders[0].c_oid.as_str()), Some(5000.0)),
+                // Still tracked for its TTL, though there's no submitted order to journal.
+                ("103", None, None),
+            ]
+        );
+
+        assert!(contenders[1].owns_order(&orders[2].c_oid));
+        assert!(!contenders[0].owns_order(&orders[2].c_oid));
+        assert!((orders[0].model_price(0.02) + 0.35).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_journal_combo_quote_price() {
+        let contender: Contender = Contender {
+            arb_val: 0.35,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.5,
+            scan_time: 1_719_840_000_000,
+            fill_probability: 0.25,
+            above_threshold: true,
+            contracts: Vec::new(),
+            reason: Reason::default(),
+        };
+        // Priced from the legs at -0.35 with a 0.05 discount.
+        let mut order: OrderBody = OrderBody {
+            acct_id: "U1".to_string(),
+            con_idex: "28812380;;;1/1,2/-1".to_string(),
+            order_type: "LMT".to_string(),
+            listing_exchange: "SMART".to_string(),
+            outside_rth: false,
+            price: -0.30,
+            side: "BUY".to_string(),
+            ticker: "SPX".to_string(),
+            tif: "DAY".to_string(),
+            referrer: "NO_REFERRER_PROVIDED".to_string(),
+            quantity: 1,
+            use_adaptive: false,
+            strategy: None,
+            strategy_parameters: BTreeMap::new(),
+            c_oid: contender.order_id(0),
+            price_offset: 0.0,
+        };
+        // The combo quote beats the legs, so the order is sent at the combo price.
+        let spx: UnderlyingSpec = UnderlyingSpec::for_ticker("SPX");
+        order.price = combo_limit_price(&order, -0.60, -0.50, 0.05, &spx);
+        assert_eq!(order.price, -0.50);
+
+        let orders: Vec<OrderBody> = vec![order];
+        let replies: Vec<OrderReply> = vec![OrderReply {
+            order_id: Some("101".to_string()),
+            ..OrderReply::default()
+        }];
+        let contenders: Vec<Contender> = vec![contender];
+        let (accepted, _): (Vec<AcceptedOrder>, usize) =
+            match_replies(&contenders, &orders, &replies);
+        let (submitted, contender): (&OrderBody, &Contender) = accepted[0].submitted.unwrap();
+        assert_eq!(submitted.price, -0.50);
+
+        let mut journal: Journal = Journal::new();
+        journal.record_submitted(submitted, contender, 0.05, "none", "SMART", Vec::new());
+        // Filled at the submitted limit, the journal's model price is the combo mid of -0.55, so
+        // only the discount is slippage: 0.30 of the 0.35 quoted edge per combo.
+        let realized_edge: f64 = journal
+            .record_execution("e1", &submitted.c_oid, 1.0, -0.50)
+            .unwrap();
+        assert!((realized_edge - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_requote() {
+        let spx: UnderlyingSpec = UnderlyingSpec::for_ticker("SPX");
+        let config: RequoteConfig = RequoteConfig {
+            reprice_threshold: Some(0.1),
+            cancel_threshold: Some(0.2),
+        };
+        assert_eq!(
+            requote(-9.95, -10.0, -10.05, &config, &spx),
+            RequoteAction::Hold
+        );
+        assert_eq!(
+            requote(-9.95, -10.0, -10.15, &config, &spx),
+            RequoteAction::Reprice(-10.1)
+        );
+        assert_eq!(
+            requote(-9.95, -10.0, -9.75, &config, &spx),
+            RequoteAction::Cancel
+        );
+        let cancel_only: RequoteConfig = RequoteConfig {
+            reprice_threshold: None,
+            cancel_threshold: Some(0.2),
+        };
+        assert_eq!(
+            requote(-9.95, -10.0, -10.5, &cancel_only, &spx),
+            RequoteAction::Hold
+        );
+
+        let order: OrderBody = OrderBody {
+            acct_id: "U1".to_string(),
+            con_idex: "28812380;;;1/1,2/-1".to_string(),
+            order_type: "LMT".to_string(),
+            listing_exchange: "SMART".to_string(),
+            outside_rth: false,
+            price: -9.95,
+            side: "BUY".to_string(),
+            ticker: "SPX".to_string(),
+            tif: "DAY".to_string(),
+            referrer: "NO_REFERRER_PROVIDED".to_string(),
+            quantity: 1,
+            use_adaptive: false,
+            strategy: None,
+            strategy_parameters: BTreeMap::new(),
+            c_oid: "a".to_string(),
+            price_offset: 0.0,
+        };
+        let working_orders: WorkingOrders = WorkingOrders::new();
+        let expires_at: Instant = Instant::now() + Duration::from_secs(60);
+        working_orders.add("1", expires_at);
+        working_orders.add_priced("2", expires_at, order, -10.0);
+        working_orders.reprice("2", -10.1, -10.15);
+        let priced: Vec<(String, OrderBody, f64)> = working_orders.priced();
+        assert_eq!(priced.len(), 1);
+        assert_eq!((priced[0].1.price, priced[0].2), (-10.1, -10.15));
+
+        // An order canceled early is no longer left for the monitor.
+        assert!(working_orders.remove("2"));
+        assert!(!working_orders.remove("2"));
+        assert_eq!(working_orders.ids(), vec!["1"]);
+    }
+
+    #[test]
+    fn test_algo_config() {
+        let order = |algo: &AlgoConfig| {
+            let (strategy, strategy_parameters) = algo.strategy().unzip();
+            serde_json::to_value(OrderBody {
+                acct_id: "U1".to_string(),
+                con_idex: "28812380;;;1/-1,2/1".to_string(),
+                order_type: "LMT".to_string(),
+                listing_exchange: "SMART".to_string(),
+                outside_rth: false,
+                price: 1.0,
+                side: "BUY".to_string(),
+                ticker: "SPX".to_string(),
+                tif: "DAY".to_string(),
+                referrer: "NO_REFERRER_PROVIDED".to_string(),
+                quantity: 1,
+                use_adaptive: algo.use_adaptive,
+                strategy,
+                strategy_parameters: strategy_parameters.unwrap_or_default(),
+                c_oid: "abc-0".to_string(),
+                price_offset: 0.0,
+            })
+            .unwrap()
+        };
+
+        // Without the adaptive router no algo fields are sent.
+        let plain: serde_json::Value = order(&AlgoConfig::default());
+        assert_eq!(plain["useAdaptive"], false);
+        assert!(plain.get("strategy").is_none());
+        assert!(plain.get("strategyParameters").is_none());
+        assert!(plain.get("price_offset").is_none());
+
+        let algo: AlgoConfig = AlgoConfig {
+            use_adaptive: true,
+            adaptive_priority: "Patient".to_string(),
+            params: [("maxPctVol".to_string(), "0.1".to_string())].into(),
+        };
+        assert_eq!(algo.label(), "adaptive:Patient");
+        let adaptive: serde_json::Value = order(&algo);
+        assert_eq!(adaptive["useAdaptive"], true);
+        assert_eq!(adaptive["strategy"], "Adaptive");
+        assert_eq!(
+            adaptive["strategyParameters"]["adaptivePriority"],
+            "Patient"
+        );
+        assert_eq!(adaptive["strategyParameters"]["maxPctVol"], "0.1");
+    }
+
+    #[test]
+    fn test_order_ladder() {
+        let ladder: OrderLadder = parse_order_ladder("1@0, 2@0.05").unwrap();
+        assert_eq!(ladder.slices(3), vec![(1, 0.0), (2, 0.05)]);
+        assert_eq!(ladder.slices(1), vec![(1, 0.0)]);
+        // Quantity beyond the ladder goes on the most aggressive rung.
+        assert_eq!(ladder.slices(5), vec![(1, 0.0), (4, 0.05)]);
+        assert_eq!(parse_order_ladder("1@0,x@0.05"), None);
+        assert_eq!(parse_order_ladder("0@0"), None);
+
+        // The ladder slices the quantity left after capping to the displayed size.
+        let sizing: OrderSizing = OrderSizing {
+            liquidity: LiquiditySizing::Cap,
+            ladder,
+        };
+        assert_eq!(sizing.slices(9, Some(2.0)), vec![(1, 0.0), (1, 0.05)]);
+        let single_price: OrderSizing = OrderSizing {
+            liquidity: LiquiditySizing::Split,
+            ladder: OrderLadder::default(),
+        };
+        assert_eq!(
+            single_price.slices(5, Some(2.0)),
+            vec![(2, 0.0), (2, 0.0), (1, 0.0)]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_contender_invariants(
+            seed in any::<u64>(),
+            spot in 1000.0..6000.0f64,
+            num_strikes in 4..30usize,
+            noise in 0.0..5.0f64,
+            wing_steps in 1..4usize,
+        ) {
+            let start_date: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+            let (chain, quotes) = synthetic_chain(
+                &ChainParams {
+                    spot,
+                    num_strikes,
+                    num_expiries: 3,
+                    noise,
+                    seed,
+                    ..ChainParams::default()
+                },
+                start_date,
+            );
+            let config: StrategyConfig = StrategyConfig {
+                wing_widths: vec![5.0 * wing_steps as f64],
+                dte_window: None,
+                max_spot_distance_percent: None,
+                straddle_spot: false,
+                max_net_delta: None,
+            };
+            let scanner: Scanner = Scanner {
+                arb_val: 0.0,
+                butterfly_config: config.clone(),
+                boxspread_config: config.clone(),
+                calendar_config: config,
+                expiry_filter: ExpiryFilter {
+                    exclude_same_day: false,
+                    min_minutes_to_close: 0,
+                },
+                include_below_threshold: true,
+                near_miss_epsilon: None,
+                early_exercise: None,
+                dividends: DividendCalendar::new(),
+                rates: RateCurve::default(),
+                price_scale: 1.0,
+                leg_pricing: LegPricing::default(),
+                skipped_expiries: Vec::new(),
+                box_financing: None,
+                max_iv_deviation: None,
+            };
+            let current_date: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+            let mut contenders: Vec<Contender> = Vec::new();
+            contenders.extend(scanner.get_butterfly_contenders(&quotes, &chain, current_date, 390, None).unwrap());
+            contenders.extend(scanner.get_boxspread_contenders(&quotes, &chain, current_date, 390, None).unwrap());
+            contenders.extend(scanner.get_calendar_contenders(&quotes, &chain, current_date, 390, None).unwrap());
+
+            for contender in &contenders {
+                prop_assert_eq!(contender.validate(), Ok(()));
+                let legs: &[Contract] = &contender.contracts;
+                match contender.type_spread.as_str() {
+                    "Butterfly" => {
+                        prop_assert_eq!(legs[1].strike - legs[0].strike, legs[2].strike - legs[1].strike);
+                    }
+                    "Boxspread" => {
+                        prop_assert_eq!(legs[0].strike, legs[1].strike);
+                        prop_assert_eq!(legs[2].strike, legs[3].strike);
+                    }
+                    _ => prop_assert_eq!(legs[0].strike, legs[1].strike),
+                }
+
+                // The reason quotes every leg and records the checks that qualified it.
+                let reason: &Reason = &contender.reason;
+                prop_assert_eq!(reason.quotes.len(), legs.len());
+                for (quote, leg) in reason.quotes.iter().zip(legs) {
+                    prop_assert_eq!(&quote.leg, &format!("{}{}{}", leg.date, leg.type_contract, leg.strike));
+                    prop_assert_eq!(quote.mkt, leg.mkt_price);
+                }
+                let (arb_checks, filters): (Vec<&ThresholdCheck>, Vec<&ThresholdCheck>) = reason
+                    .checks
+                    .iter()
+                    .partition(|check| {
+                        ["arb_val", "parity_arb_val", "risk_free_profit"].contains(&check.name.as_str())
+                    });
+                prop_assert_eq!(arb_checks.iter().all(|check| check.passed), contender.above_threshold);
+                prop_assert!(filters.iter().all(|check| check.passed));
+
+                // Moving any one strike breaks the spread.
+                for index in 0..legs.len() {
+                    let mut broken: Contender = contender.clone();
+                    broken.contracts[index].strike += 1.0;
+                    prop_assert!(broken.validate().is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_near_miss_shortfall() {
+        let reason = |arb_val: f64, min_bid: f64| Reason {
+            checks: vec![
+                ThresholdCheck::new("arb_val", arb_val, ">=", 0.5),
+                ThresholdCheck::new("min_bid", min_bid, ">", 1.0),
+            ],
+            ..Reason::default()
+        };
+        assert_eq!(reason(0.5, 2.0).shortfall(), None);
+        assert!((reason(0.45, 2.0).shortfall().unwrap() - 0.05).abs() < 1e-9);
+
+        // A contender failing another check is not a near miss.
+        assert_eq!(reason(0.45, 0.5).shortfall(), None);
+
+        // Box spreads must stay below their threshold.
+        let box_check: ThresholdCheck = ThresholdCheck::new("arb_val", -4.9, "<=", -5.0);
+        assert!(!box_check.passed);
+        let box_reason: Reason = Reason {
+            checks: vec![box_check],
+            ..Reason::default()
+        };
+        assert!((box_reason.shortfall().unwrap() - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_reason() {
+        // Each comparison passes or fails exactly at the threshold, and unknown ones never pass.
+        let passed = |op: &str, value: f64| ThresholdCheck::new("arb_val", value, op, 0.5).passed;
+        assert!(passed(">=", 0.5) && !passed(">=", 0.49));
+        assert!(!passed(">", 0.5) && passed(">", 0.51));
+        assert!(passed("<=", 0.5) && !passed("<=", 0.51));
+        assert!(!passed("<", 0.5) && passed("<", 0.49));
+        assert!(!passed("==", 0.5));
+
+        // The summary lists every check in order, failed ones included.
+        let reason: Reason = Reason {
+            quotes: vec![LegQuote {
+                leg: "240701C5000".to_string(),
+                bid: 3.1,
+                ask: 3.3,
+                mkt: 3.2,
+                asz: 10.0,
+                ..LegQuote::default()
+            }],
+            formula: "240701C5000".to_string(),
+            checks: vec![
+                ThresholdCheck::new("arb_val", 0.6, ">=", 0.5),
+                ThresholdCheck::new("min_bid", 3.1, ">", 1.0),
+                ThresholdCheck::new("max_spread", 0.2, "<=", 0.1),
+            ],
+        };
+        assert_eq!(
+            reason.summary(),
+            "arb_val 0.60 >= 0.50, min_bid 3.10 > 1.00, max_spread 0.20 <= 0.10"
+        );
+        assert_eq!(reason.summary().split(", ").count(), reason.checks.len());
+        assert_eq!(Reason::default().summary(), "");
+
+        // The reason is recorded in full with the contender's event and journal entries.
+        let value: Value = serde_json::to_value(&reason).unwrap();
+        assert_eq!(value["quotes"][0]["leg"], "240701C5000");
+        assert_eq!(value["checks"].as_array().unwrap().len(), 3);
+        assert_eq!(value["checks"][2]["passed"], false);
+        assert_eq!(serde_json::from_value::<Reason>(value).unwrap(), reason);
+
+        // Contenders saved before reasons were recorded load with an empty one.
+        let contender: Contender = serde_json::from_str(
+            r#"{"arb_val":0.35,"avg_ask":10.0,"type_spread":"Butterfly","exp_date":"240701","rank_value":1.5,"scan_time":0,"fill_probability":0.25,"above_threshold":true,"contracts":[]}"#,
+        )
+        .unwrap();
+        assert_eq!(contender.reason, Reason::default());
+    }
+
+    #[test]
+    fn test_portfolio_cache() {
+        let now: Instant = Instant::now();
+        let portfolio_cache: PortfolioCache = PortfolioCache::new(Duration::from_secs(60), 5.0);
+        assert_eq!(portfolio_cache.get(now), None);
+
+        // The first value has nothing to compare against.
+        assert_eq!(portfolio_cache.update(100000.0, now), None);
+        assert_eq!(portfolio_cache.get(now), Some((100000.0, true)));
+        assert_eq!(
+            portfolio_cache.get(now + Duration::from_secs(60)),
+            Some((100000.0, false))
+        );
+
+        // Small moves are ignored, large ones report the previous value and percent change.
+        assert_eq!(portfolio_cache.update(104000.0, now), None);
+        assert_eq!(
+            portfolio_cache.update(93600.0, now),
+            Some((104000.0, -10.0))
+        );
+
+        // Only one refresh runs at a time.
+        assert!(portfolio_cache.begin_refresh());
+        assert!(!portfolio_cache.begin_refresh());
+        portfolio_cache.cancel_refresh();
+        assert!(portfolio_cache.begin_refresh());
+    }
+
+    #[test]
+    fn test_fill_model() {
+        let mut fill_model: FillModel = FillModel {
+            mode: FillMode::Cross,
+            cross_threshold: 0.05,
+            probability_midpoint: 0.5,
+            probability_scale: 0.25,
+        };
+
+        // The fill probability is one half at the midpoint and falls as the edge grows.
+        assert!((fill_model.fill_probability(0.5) - 0.5).abs() < 1e-9);
+        assert!(fill_model.fill_probability(0.1) > fill_model.fill_probability(1.0));
+
+        // The combo mid has to cross the limit by the threshold.
+        assert!(fill_model.fills_at_limit(-1.0, -1.05));
+        assert!(!fill_model.fills_at_limit(-1.0, -1.02));
+
+        let mut rng: StdRng = StdRng::seed_from_u64(42);
+        assert!(fill_model.simulate_fill(&[-1.0, 2.0], Some(&[-1.1, 1.9]), 0.0, &mut rng));
+        assert!(!fill_model.simulate_fill(&[-1.0, 2.0], Some(&[-1.1, 2.0]), 1.0, &mut rng));
+        assert!(!fill_model.simulate_fill(&[-1.0], None, 1.0, &mut rng));
+
+        fill_model.mode = FillMode::Probabilistic;
+        assert!(fill_model.simulate_fill(&[-1.0], None, 1.0, &mut rng));
+        assert!(!fill_model.simulate_fill(&[-1.0], None, 0.0, &mut rng));
+    }
+
+    #[test]
+    fn test_paper_broker() {
+        let contender = |exp_date: &str, fill_probability: f64| Contender {
+            arb_val: -0.5,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: exp_date.parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability,
+            above_threshold: true,
+            contracts: ["240701", "240708"]
+                .iter()
+                .map(|date| Contract {
+                    strike: 5500.0,
+                    mkt_price: 5.0,
+                    date: date.parse().unwrap(),
+                    type_contract: "C".to_string(),
+                })
+                .collect(),
+            reason: Reason::default(),
+        };
+        let order = |order_ref: &str| PaperOrder {
+            order_ref: order_ref.to_string(),
+            limit_price: 0.55,
+            quantity: 2.0,
+        };
+        let fill_model: FillModel = FillModel {
+            mode: FillMode::Probabilistic,
+            cross_threshold: 0.05,
+            probability_midpoint: 0.5,
+            probability_scale: 0.25,
+        };
+        let chain: OptionChain = OptionChain::new();
+        let contracts_map: Quotes = Quotes::default();
+
+        let mut paper: PaperBroker = PaperBroker::new(fill_model.clone(), 42);
+        paper.submit(&contender("240701", 1.0), vec![order("a-0")]);
+        paper.submit(&contender("240708", 0.0), vec![order("b-0")]);
+        assert_eq!(paper.working_order_count(), 2);
+
+        // Only the sure fill executes, at its limit, and unfilled orders don't carry over.
+        let fills: Vec<PaperFill> = paper.settle(&chain, &contracts_map);
+        assert_eq!(
+            fills,
+            vec![PaperFill {
+                execution_id: "paper-1".to_string(),
+                order_ref: "a-0".to_string(),
+                size: 2.0,
+                fill_price: 0.55,
+            }]
+        );
+        assert_eq!(paper.working_order_count(), 0);
+        assert!(paper.settle(&chain, &contracts_map).is_empty());
+
+        // Crossing fills need the legs quoted on the next scan.
+        let mut paper: PaperBroker = PaperBroker::new(
+            FillModel {
+                mode: FillMode::Cross,
+                ..fill_model
+            },
+            42,
+        );
+        paper.submit(&contender("240701", 1.0), vec![order("a-0")]);
+        assert!(paper.settle(&chain, &contracts_map).is_empty());
+    }
+
+    #[test]
+    fn test_public_api() {
+        let start_date: NaiveDate = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        let (chain, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 4,
+                num_strikes: 40,
+                ..ChainParams::default()
+            },
+            start_date,
+        );
+        let contenders: Vec<Contender> = ChainScanner::new("SPX", 0.0, vec![5.0, 10.0])
+            .scan(
+                OptionType::All,
+                &chain,
+                &quotes,
+                NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                390,
+                None,
+                7,
+            )
+            .unwrap();
+        assert!(!contenders.is_empty());
+        assert!(contenders
+            .windows(2)
+            .all(|pair| pair[0].rank_value >= pair[1].rank_value));
+        assert!(contenders.iter().all(|contender| contender.scan_time == 7));
+
+        // Each contender gets its own orders, and the broker works them until canceled.
+        let orders: Vec<(Contender, Vec<OrderBody>)> =
+            OrderBuilder::new("DU1234567", "SPX", 0.0).build(&contenders, &chain, 2);
+        assert!(!orders.is_empty());
+        let mut paper: PaperBroker = PaperBroker::new(
+            FillModel {
+                mode: FillMode::Cross,
+                cross_threshold: 0.05,
+                probability_midpoint: 0.5,
+                probability_scale: 0.25,
+            },
+            42,
+        );
+        let broker: &mut dyn Broker = &mut paper;
+        for (contender, contender_orders) in &orders {
+            let order_ref: String = contender.order_id(0);
+            assert!(contender_orders.iter().all(|order| {
+                order.acct_id == "DU1234567"
+                    && order.quantity == 2
+                    && order.c_oid.starts_with(&order_ref[..order_ref.len() - 1])
+            }));
+            broker.place_orders(contender, contender_orders).unwrap();
+        }
+        assert_eq!(
+            broker.working_order_count(),
+            orders.iter().map(|(_, orders)| orders.len()).sum::<usize>()
+        );
+        broker.cancel_orders().unwrap();
+        assert_eq!(broker.working_order_count(), 0);
+    }
+
+    #[test]
+    fn test_walk_forward_splits() {
+        // Ten snapshots over two windows make chunks of three, with the remainder in the last test.
+        assert_eq!(
+            walk_forward_splits(10, 2),
+            vec![(0..3, 3..6), (3..6, 6..10)]
+        );
+
+        // Chunks too small to replay a single iteration yield no windows.
+        assert!(walk_forward_splits(5, 2).is_empty());
+        assert!(walk_forward_splits(10, 0).is_empty());
+    }
+
+    #[test]
+    fn test_max_drawdown() {
+        // Equity goes 100, 50, 120, 40, 60: the drop from 120 to 40 is the largest.
+        assert_eq!(max_drawdown(&[100.0, -50.0, 70.0, -80.0, 20.0]), 80.0);
+
+        // A loss before any gain is measured from zero.
+        assert_eq!(max_drawdown(&[-30.0, 10.0]), 30.0);
+        assert_eq!(max_drawdown(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_build_report() {
+        let day: i64 = 86_400_000;
+        let trade = |time: i64, strategy: &str, realized_edge: f64| ReportTrade {
+            time,
+            strategy: strategy.to_string(),
+            quoted_edge: 50.0,
+            realized_edge,
+        };
+        let trades: Vec<ReportTrade> = vec![
+            trade(day, "Butterfly", 40.0),
+            trade(2 * day, "Boxspread", -20.0),
+            trade(3 * day, "Butterfly", 60.0),
+        ];
+
+        let report: Report = build_report(&trades);
+        assert_eq!(report.overall.trades, 3);
+        assert_eq!(report.overall.total_pnl, 80.0);
+        assert!((report.overall.win_rate - 2.0 / 3.0).abs() < 1e-9);
+        assert!((report.overall.edge_captured - 80.0 / 150.0).abs() < 1e-9);
+        assert_eq!(report.overall.max_drawdown, 20.0);
+        assert!(report.overall.sharpe > 0.0);
+        assert_eq!(report.by_strategy["Butterfly"].total_pnl, 100.0);
+        assert_eq!(report.equity_curve.last(), Some(&(3 * day, 80.0)));
+    }
+
+    #[test]
+    fn test_instance_lock() {
+        let lock: InstanceLock = InstanceLock::acquire("TEST_LOCK").unwrap();
+
+        // A second instance on the same account is refused while the lock is held.
+        assert!(InstanceLock::acquire("TEST_LOCK").is_err());
+
+        drop(lock);
+        assert!(InstanceLock::acquire("TEST_LOCK").is_ok());
+    }
+
+    #[test]
+    fn test_journal_realized_edge() {
+        let mut journal: Journal = Journal::new();
+        journal.record_order(
+            "abc-0",
+            "Butterfly",
+            "240701".parse().unwrap(),
+            1.0,
+            0.25,
+            &Reason::default(),
+            1_700_000_000_000,
+            "none",
+            "SMART",
+            Vec::new(),
+        );
+        journal.record_order(
+            "abc-1",
+            "Butterfly",
+            "240701".parse().unwrap(),
+            1.5,
+            0.25,
+            &Reason::default(),
+            1_700_000_000_000,
+            "none",
+            "SMART",
+            Vec::new(),
+        );
+
+        // Filling 0.05 worse than the model price leaves 0.20 of the 0.25 quoted edge per combo.
+        let realized_edge: f64 = journal.record_execution("e1", "abc-0", 2.0, 1.05).unwrap();
+        assert!((realized_edge - 40.0).abs() < 1e-9);
+
+        // Duplicate executions and unknown orders are ignored.
+        assert_eq!(journal.record_execution("e1", "abc-0", 2.0, 1.05), None);
+        assert_eq!(journal.record_execution("e2", "xyz-0", 2.0, 1.05), None);
+
+        journal.record_execution("e3", "abc-1", 2.0, 1.5).unwrap();
+        let stats: &EdgeStats = &journal.stats()["Butterfly"];
+        assert_eq!(stats.executions, 2);
+        assert_eq!(stats.contracts, 4.0);
+        assert!((stats.quoted_edge - 100.0).abs() < 1e-9);
+        assert!((stats.realized_edge - 90.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_error_context() {
+        let context = || -> Value {
+            let context: ErrorContext = ERROR_CONTEXT.lock().unwrap().clone();
+            serde_json::to_value(context).unwrap()
+        };
+
+        set_iteration(7);
+        record_endpoint("https://localhost:5000/v1/api/iserver/account/orders");
+        set_contender_summary("Butterfly 240701 @ 0.35".to_string());
+        assert_eq!(
+            context(),
+            json!({
+                "iteration": 7,
+                "last_endpoint": "https://localhost:5000/v1/api/iserver/account/orders",
+                "contenders": "Butterfly 240701 @ 0.35",
+            })
+        );
+
+        // A new iteration keeps the last endpoint but clears the contenders of the previous one.
+        set_iteration(8);
+        let report: Value = context();
+        assert_eq!(report["iteration"], 8);
+        assert!(report["last_endpoint"].is_string());
+        assert!(report["contenders"].is_null());
+    }
+
+    #[test]
+    fn test_crash_report() {
+        let directory: std::path::PathBuf = env::temp_dir().join("trading_bot_test_crashes");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+
+        install_panic_hook(&directory);
+        let result = std::thread::Builder::new()
+            .name("crash-test".to_string())
+            .spawn(|| panic!("crash test"))
+            .unwrap()
+            .join();
+        let _ = std::panic::take_hook();
+        assert!(result.is_err());
+
+        let path: std::path::PathBuf = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                std::fs::read_to_string(path).is_ok_and(|report| report.contains("crash test"))
+            })
+            .unwrap();
+        let report: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("crash_"));
+        assert_eq!(report["message"], "crash test");
+        assert!(report["location"]
+            .as_str()
+            .unwrap()
+            .starts_with("src/lib.rs:"));
+        assert_eq!(report["thread"], "crash-test");
+        assert!(report["time"].is_string());
+        assert!(report["backtrace"].is_string());
+        assert!(report["context"]["iteration"].is_u64());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_phase_transitions() {
+        let timings: Timings = Timings {
+            working: Duration::from_secs(60),
+            cooldown: Duration::from_secs(5),
+        };
+        let now: Instant = Instant::now();
+
+        let working: Phase = Phase::Scanning.next(LoopEvent::OrdersPlaced, now, &timings);
+        assert_eq!(
+            working,
+            Phase::OrdersWorking {
+                deadline: now + Duration::from_secs(60)
+            }
+        );
+
+        // A fill cancels the rest immediately instead of waiting for the deadline.
+        let cancelling: Phase = working.next(LoopEvent::Filled, now, &timings);
+        assert_eq!(cancelling, Phase::Cancelling);
+        let cooldown: Phase = cancelling.next(LoopEvent::Cancelled, now, &timings);
+        assert_eq!(
+            cooldown,
+            Phase::Cooldown {
+                deadline: now + Duration::from_secs(5)
+            }
+        );
+        assert_eq!(cooldown.next(LoopEvent::Timer, now, &timings), Phase::Idle);
+
+        // Events that don't apply keep the phase, and stop works from anywhere.
+        assert_eq!(cooldown.next(LoopEvent::Filled, now, &timings), cooldown);
+        assert_eq!(Command::parse(" STOP\n"), Some(Command::Stop));
+        assert_eq!(
+            working.next(LoopEvent::Command(Command::Stop), now, &timings),
+            Phase::Stopped
+        );
+        assert_eq!(
+            Phase::Idle.next(LoopEvent::Halt, now, &timings),
+            Phase::Stopped
+        );
+
+        // A fatal error stops the loop only after the working orders are canceled.
+        for phase in [
+            Phase::Idle,
+            Phase::Scanning,
+            working,
+            Phase::Cancelling,
+            cooldown,
+        ] {
+            assert_eq!(phase.next(LoopEvent::Failed, now, &timings), Phase::Failing);
+        }
+        assert_eq!(
+            Phase::Failing.next(LoopEvent::Command(Command::Stop), now, &timings),
+            Phase::Failing
+        );
+        assert_eq!(
+            Phase::Failing.next(LoopEvent::Cancelled, now, &timings),
+            Phase::Stopped
+        );
+    }
+
+    #[test]
+    fn test_control_status() {
+        update_status(|status| {
+            status.phase = Phase::Scanning.name().to_string();
+            status.iteration = 3;
+            status.live = true;
+        });
+        update_status(|status| status.working_orders = 2);
+        assert_eq!(status().phase, "scanning");
+        assert_eq!(status().iteration, 3);
+
+        set_paused(true);
+        assert!(is_paused());
+        assert_eq!(
+            describe_status(),
+            "Iteration 3, scanning, live, trading paused, 2 working orders, 0 contenders."
+        );
+        set_paused(false);
+        assert!(!is_paused());
+    }
+
+    #[test]
+    fn test_scan_filter() {
+        let calendar: Contender = Contender {
+            arb_val: 0.5,
+            avg_ask: 10.0,
+            type_spread: "Calendar".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts: vec![
+                Contract {
+                    strike: 5500.0,
+                    mkt_price: 12.5,
+                    date: "240701".parse().unwrap(),
+                    type_contract: "C".to_string(),
+                },
+                Contract {
+                    strike: 5500.0,
+                    mkt_price: 20.0,
+                    date: "240708".parse().unwrap(),
+                    type_contract: "C".to_string(),
+                },
+            ],
+            reason: Reason::default(),
+        };
+
+        assert!(ScanFilter::parse("").is_empty());
+        assert!(ScanFilter::parse("").matches(&calendar));
+        // The far leg's expiry matches too.
+        assert!(ScanFilter::parse("calendar 240708").matches(&calendar));
+        assert!(!ScanFilter::parse("butterfly").matches(&calendar));
+        assert!(!ScanFilter::parse("240715").matches(&calendar));
+
+        assert_eq!(
+            parse_webhook_request("POST /scan?strategy=butterfly&expiry=240701 HTTP/1.1"),
+            Ok(ScanFilter {
+                strategy: Some("butterfly".to_string()),
+                expiry: Some("240701".parse().unwrap()),
+            })
+        );
+        assert_eq!(
+            parse_webhook_request("POST /scan HTTP/1.1"),
+            Ok(ScanFilter::default())
+        );
+        assert_eq!(
+            parse_webhook_request("POST /scan?expiry=241341 HTTP/1.1"),
+            Err("400 Bad Request")
+        );
+        assert_eq!(
+            parse_webhook_request("GET /scan HTTP/1.1"),
+            Err("405 Method Not Allowed")
+        );
+        assert_eq!(
+            parse_webhook_request("POST /orders HTTP/1.1"),
+            Err("404 Not Found")
+        );
+    }
+
+    #[test]
+    fn test_scan_schedule() {
+        let time = |hour: u32, minute: u32| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        let (windows, default) = parse_scan_schedule("09:30-10:30=10; 12:00-13:00=off").unwrap();
+        let scan_schedule: ScanSchedule = ScanSchedule {
+            windows,
+            default_interval: default.unwrap_or(Some(30)),
+            jitter_percent: 20.0,
+            no_trade_windows: parse_no_trade_windows("09:30-09:45; 15:45-16:15").unwrap(),
+        };
+
+        assert_eq!(scan_schedule.interval_at(time(9, 45)), Some(10));
+        assert_eq!(scan_schedule.interval_at(time(11, 0)), Some(30));
+        assert_eq!(scan_schedule.interval_at(time(12, 15)), None);
+        assert_eq!(
+            scan_schedule.next_scan_in(time(12, 15)),
+            Some(Duration::from_secs(45 * 60))
+        );
+
+        // Scanning only in the first hour stops for the day once it's over.
+        let (windows, default) = parse_scan_schedule("09:30-10:30=10;*=off").unwrap();
+        assert_eq!(default, Some(None));
+        let first_hour: ScanSchedule = ScanSchedule {
+            windows,
+            default_interval: None,
+            jitter_percent: 0.0,
+            no_trade_windows: Vec::new(),
+        };
+        assert_eq!(
+            first_hour.next_scan_in(time(9, 0)),
+            Some(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(first_hour.next_scan_in(time(11, 0)), None);
+
+        // No-trade windows only stop orders, not scans.
+        assert!(!scan_schedule.trading_allowed_at(time(9, 40)));
+        assert_eq!(scan_schedule.interval_at(time(9, 40)), Some(10));
+        assert!(first_hour.trading_allowed_at(time(9, 40)));
+
+        // Each window starts at its first minute and ends just before its last, so the open and
+        // the close are both blocked.
+        let allowed = |hour: u32, minute: u32, second: u32| {
+            scan_schedule.trading_allowed_at(NaiveTime::from_hms_opt(hour, minute, second).unwrap())
+        };
+        assert!(allowed(9, 29, 59));
+        assert!(!allowed(9, 30, 0));
+        assert!(!allowed(9, 44, 59));
+        assert!(allowed(9, 45, 0));
+        assert!(allowed(15, 44, 59));
+        assert!(!allowed(15, 45, 0));
+        assert!(!allowed(16, 0, 0));
+        assert!(!allowed(16, 14, 59));
+        assert!(allowed(16, 15, 0));
+
+        // Empty, backwards and malformed windows are refused rather than ignored.
+        assert_eq!(parse_no_trade_windows(" ; "), Some(Vec::new()));
+        assert!(parse_no_trade_windows("09:45-09:30").is_none());
+        assert!(parse_no_trade_windows("09:30-09:30").is_none());
+        assert!(parse_no_trade_windows("09:30-09:45;15:45").is_none());
+        assert!(parse_no_trade_windows("09:30-24:00").is_none());
+
+        let mut rng: StdRng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let interval: Duration = scan_schedule.jitter(30, &mut rng);
+            assert!(interval >= Duration::from_secs(24) && interval <= Duration::from_secs(36));
+        }
+        assert!(parse_scan_schedule("10:30-09:30=10").is_none());
+        assert!(parse_scan_schedule("09:30-10:30=0").is_none());
+    }
+
+    #[test]
+    fn test_threshold_schedule() {
+        let time = |hour: u32, minute: u32| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        let threshold_schedule: ThresholdSchedule = ThresholdSchedule {
+            windows: parse_threshold_schedule("09:30-10:00=0.25/0.05; 11:30-14:00=0.10").unwrap(),
+        };
+
+        // A wider edge at the open, a tighter one mid-day keeping the discount.
+        assert_eq!(
+            threshold_schedule.thresholds_at(time(9, 45), 0.15, 0.02),
+            (0.25, 0.05)
+        );
+        assert_eq!(
+            threshold_schedule.thresholds_at(time(12, 0), 0.15, 0.02),
+            (0.10, 0.02)
+        );
+        assert_eq!(
+            threshold_schedule.thresholds_at(time(10, 0), 0.15, 0.02),
+            (0.15, 0.02)
+        );
+
+        assert!(parse_threshold_schedule("09:30-10:00=0.05").is_none());
+        assert!(parse_threshold_schedule("09:30-10:00=0.25/0.5").is_none());
+        assert!(parse_threshold_schedule("10:00-09:30=0.25").is_none());
+    }
+
+    #[test]
+    fn test_config_reload() {
+        let old: BTreeMap<String, String> = parse_config(
+            "# Strategy\nTICKER=SPX\nARB_VALUE=0.10\nexport DISCOUNT_VALUE=\"0.02\"\n\nEVENTS_PATH=events.jsonl # optional\n",
+        );
+        assert_eq!(old.get("DISCOUNT_VALUE").map(String::as_str), Some("0.02"));
+        assert_eq!(
+            old.get("EVENTS_PATH").map(String::as_str),
+            Some("events.jsonl")
+        );
+        assert_eq!(old.len(), 4);
+
+        let new: BTreeMap<String, String> = parse_config(
+            "TICKER=XSP\nARB_VALUE=0.15\nDISCOUNT_VALUE=0.02\nSECONDS_TO_SLEEP=30\nSHADOW_ACCOUNT_ID=DU1\n",
+        );
+        let change: ConfigChange = diff_config(&old, &new);
+        assert_eq!(
+            change.applied,
+            BTreeMap::from([
+                ("ARB_VALUE".to_string(), "0.15".to_string()),
+                ("SECONDS_TO_SLEEP".to_string(), "30".to_string()),
+            ])
+        );
+        // The ticker and account need a restart, and a removed setting keeps its running value.
+        assert_eq!(
+            change.rejected,
+            vec!["EVENTS_PATH", "TICKER", "SHADOW_ACCOUNT_ID"]
+        );
+        assert!(!change.applies("EVENTS_"));
+        assert_eq!(diff_config(&new, &new), ConfigChange::default());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let args =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+        let mut command_line: Vec<String> = args(&["bot", "--profile", "paper", "backtest"]);
+        assert_eq!(
+            take_profile_arg(&mut command_line),
+            Some("paper".to_string())
+        );
+        assert_eq!(command_line, args(&["bot", "backtest"]));
+        let mut command_line: Vec<String> = args(&["bot", "report", "--profile=live"]);
+        assert_eq!(
+            take_profile_arg(&mut command_line),
+            Some("live".to_string())
+        );
+        assert_eq!(command_line, args(&["bot", "report"]));
+        assert_eq!(take_profile_arg(&mut args(&["bot", "--profile"])), None);
+        assert_eq!(take_profile_arg(&mut args(&["bot"])), None);
+
+        let values: BTreeMap<String, String> = parse_config(
+            "PORT=5000\nARB_VALUE=0.10\nPAPER__PORT=5001\nPAPER__TEST_MODE=no\nLIVE__ARB_VALUE=0.20\n",
+        );
+        assert_eq!(
+            resolve_profile(&values, Some("paper")),
+            parse_config("PORT=5001\nARB_VALUE=0.10\nTEST_MODE=no\n")
+        );
+        assert_eq!(
+            resolve_profile(&values, Some("Live")),
+            parse_config("PORT=5000\nARB_VALUE=0.20\n")
+        );
+        assert_eq!(
+            resolve_profile(&values, None),
+            parse_config("PORT=5000\nARB_VALUE=0.10\n")
+        );
+    }
+
+    #[test]
+    fn test_parse_secrets() {
+        let expected: BTreeMap<String, String> = BTreeMap::from([
+            ("FRED_API_KEY".to_string(), "abc".to_string()),
+            ("SHADOW_ACCOUNT_ID".to_string(), "DU1234567".to_string()),
+        ]);
+        let secrets: serde_json::Value = serde_json::json!({
+            "FRED_API_KEY": "abc",
+            "SHADOW_ACCOUNT_ID": "DU1234567",
+            "RETRIES": 3,
+        });
+
+        // AWS Secrets Manager returns the object, Vault KV v1 and v2 nest it under data.
+        assert_eq!(parse_secrets(&secrets), expected);
+        assert_eq!(
+            parse_secrets(&serde_json::json!({ "data": secrets })),
+            expected
+        );
+        assert_eq!(
+            parse_secrets(&serde_json::json!({
+                "data": { "data": secrets, "metadata": { "version": 2 } }
+            })),
+            expected
+        );
+        assert!(parse_secrets(&serde_json::json!("plain text")).is_empty());
+    }
+
+    #[test]
+    fn test_bot_config() {
+        let settings: BTreeMap<String, String> = BTreeMap::from([
+            ("ARB_VALUE".to_string(), "0.15".to_string()),
+            ("OPTION".to_string(), "DEFAULT".to_string()),
+            ("PORT".to_string(), "5000".to_string()),
+            ("TEST_MODE".to_string(), "Y".to_string()),
+            ("TICKER".to_string(), "SPX".to_string()),
+        ]);
+        let rendered: String = render_bot_config(&settings).unwrap();
+        assert!(rendered.contains("ARB_VALUE = 0.15\n"));
+        assert!(rendered.contains("PORT = 5000\n"));
+        assert_eq!(parse_bot_config(&rendered).unwrap(), settings);
+
+        // Settings are validated with the ranges the getters accept, in both directions.
+        assert!(validate_setting("DISCOUNT_VALUE", "0.2").is_err());
+        assert!(validate_setting("EXPIRY_SELECTOR", "next 0").is_err());
+        assert!(validate_setting("SCAN_SCHEDULE", "anything").is_ok());
+        assert!(parse_bot_config("SECONDS_TO_SLEEP = 2").is_err());
+        assert!(parse_bot_config("TEST_MODE = false").is_err());
+        assert!(parse_bot_config("TICKER = ").is_err());
+    }
+
+    #[test]
+    fn test_shadow_paper_account() {
+        assert!(ShadowConfig::is_paper_account("DU1234567"));
+        assert!(ShadowConfig::is_paper_account("DF1234567"));
+        assert!(!ShadowConfig::is_paper_account("U1234567"));
+        assert!(!ShadowConfig::is_paper_account(""));
+    }
+
+    #[test]
+    fn test_session_calendar() {
+        // Times in UTC; New York is four hours behind in October 2026.
+        let time = |day: u32, hour: u32, minute: u32| {
+            Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+                .unwrap()
+        };
+        let gth: SessionCalendar = SessionCalendar { overnight: true };
+        assert_eq!(
+            gth.session_at(time(14, 14, 0)),
+            Some(TradingSession::Regular)
+        );
+        // Wednesday 9:00 PM ET.
+        let overnight: Option<TradingSession> = gth.session_at(time(15, 1, 0));
+        assert_eq!(overnight, Some(TradingSession::Overnight));
+        assert!(overnight.unwrap().outside_rth());
+        // Between the regular close and the overnight open the bot waits for 8:15 PM ET.
+        assert_eq!(gth.session_at(time(14, 21, 0)), None);
+        assert_eq!(
+            gth.next_session_in(time(14, 21, 0)),
+            Some(Duration::from_secs((3 * 60 + 15) * 60))
+        );
+        // There's no session on Friday night; the next one opens Sunday evening.
+        assert_eq!(gth.session_at(time(17, 1, 0)), None);
+        assert_eq!(
+            gth.next_session_in(time(17, 1, 0)),
+            Some(Duration::from_secs((47 * 60 + 15) * 60))
+        );
+
+        let regular: SessionCalendar = SessionCalendar { overnight: false };
+        assert_eq!(regular.session_at(time(15, 1, 0)), None);
+        assert_eq!(regular.next_session_in(time(15, 1, 0)), None);
+    }
+
+    #[test]
+    fn test_stage_timings() {
+        let mut stage_timings: StageTimings = StageTimings::new();
+        stage_timings.record("chain_fetch", Duration::from_millis(1200));
+        stage_timings.record("contender_calc", Duration::from_millis(300));
+        stage_timings.record("chain_fetch", Duration::from_millis(50));
+
+        // Repeated stages are summed in the order they first ran.
+        assert_eq!(
+            stage_timings.summary(),
+            "chain_fetch 1.250s, contender_calc 0.300s"
+        );
+        assert_eq!(stage_timings.total(), Duration::from_millis(1550));
+    }
+
+    #[test]
+    fn test_mqtt_sink() {
+        env::set_var("MQTT_BROKER", "bot:secret@broker.local:8883");
+        env::set_var("MQTT_FILL_TOPIC", "home/trading/fills");
+        let config: MqttConfig = get_events_config().mqtt.unwrap();
+        env::remove_var("MQTT_BROKER");
+        env::remove_var("MQTT_FILL_TOPIC");
+        assert_eq!(config.broker, "bot:secret@broker.local:8883");
+        assert_eq!(config.contender_topic, "trading_bot/contenders");
+        assert_eq!(config.fill_topic, "home/trading/fills");
+
+        assert_eq!(
+            parse_mqtt_broker(&config.broker),
+            Ok(MqttBroker {
+                login: Some(("bot", "secret")),
+                host: "broker.local",
+                port: 8883,
+            })
+        );
+        assert_eq!(
+            parse_mqtt_broker("localhost"),
+            Ok(MqttBroker {
+                login: None,
+                host: "localhost",
+                port: 1883,
+            })
+        );
+        assert_eq!(
+            parse_mqtt_broker("bot@localhost:1884"),
+            Ok(MqttBroker {
+                login: Some(("bot", "")),
+                host: "localhost",
+                port: 1884,
+            })
+        );
+        assert!(parse_mqtt_broker("localhost:mqtt")
+            .is_err_and(|e| e.contains("Invalid MQTT_BROKER port")));
+
+        // Contenders and fills go to their topics, other events aren't published.
+        let topic = |line: &str| mqtt_topic(&config.contender_topic, &config.fill_topic, line);
+        assert_eq!(
+            topic(r#"{"event": "contender", "arb_val": 0.35}"#).unwrap(),
+            Some("trading_bot/contenders")
+        );
+        assert_eq!(
+            topic(r#"{"event": "fill", "price": -0.5}"#).unwrap(),
+            Some("home/trading/fills")
+        );
+        assert_eq!(
+            topic(r#"{"event": "scan_start", "scan_time": 1}"#).unwrap(),
+            None
+        );
+        assert!(topic("not json").is_err());
+    }
+
+    #[test]
+    fn test_event_sinks() {
+        struct Recorder(Sender<String>);
+        impl EventSink for Recorder {
+            fn send(&mut self, line: &str) -> io::Result<()> {
+                self.0
+                    .send(line.to_string())
+                    .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+        }
+        struct Failing(Arc<AtomicUsize>);
+        impl EventSink for Failing {
+            fn send(&mut self, _line: &str) -> io::Result<()> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Err(io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+        }
+
+        // Each sink gets the event as one flat JSON line, stamped with when it was emitted. Other
+        // tests may emit at the same time, so only this test's order id is looked at.
+        let (sender, receiver) = channel();
+        add_sink(Box::new(Recorder(sender)));
+        emit(&Event::OrderCanceled {
+            order_id: "test_event_sinks".to_string(),
+        });
+        let line: String = receiver
+            .try_iter()
+            .find(|line| line.contains("test_event_sinks"))
+            .unwrap();
+        assert!(!line.contains('\n'));
+        let payload: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(payload.as_object().unwrap().len(), 3);
+        assert_eq!(payload["event"], "order_canceled");
+        assert_eq!(payload["order_id"], "test_event_sinks");
+        let time: &str = payload["time"].as_str().unwrap();
+        assert!(NaiveDateTime::parse_from_str(
+            time.strip_suffix(" UTC").unwrap(),
+            "%Y-%m-%d %H:%M:%S%.9f"
+        )
+        .is_ok());
+        drop(receiver);
+
+        // A sink that fails is dropped after its first failure.
+        let sends = Arc::new(AtomicUsize::new(0));
+        add_sink(Box::new(Failing(sends.clone())));
+        emit(&Event::ScanStart { scan_time: 1 });
+        emit(&Event::ScanStart { scan_time: 2 });
+        assert_eq!(sends.load(Ordering::SeqCst), 1);
+
+        // Without the broker features, configured brokers are logged and skipped instead of
+        // failing startup, even when nothing is listening on them.
+        #[cfg(not(any(feature = "kafka", feature = "nats", feature = "mqtt")))]
+        assert!(init_events(&EventsConfig {
+            path: None,
+            addr: None,
+            kafka: Some(("127.0.0.1:1".to_string(), "events".to_string())),
+            nats: Some(("nats://127.0.0.1:1".to_string(), "events".to_string())),
+            mqtt: Some(MqttConfig {
+                broker: "bot:secret@127.0.0.1:1".to_string(),
+                contender_topic: "trading_bot/contenders".to_string(),
+                fill_topic: "trading_bot/fills".to_string(),
+            }),
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let started: Instant = Instant::now();
+        let heartbeat: Heartbeat = Heartbeat {
+            iteration: 12,
+            phase: "scanning",
+            started,
+            allowance: Duration::ZERO,
+            stage: Some(("chain_fetch", started + Duration::from_secs(1))),
+            request: Some((
+                "GET https://localhost:5000/v1/api/iserver/marketdata/snapshot".to_string(),
+                started + Duration::from_secs(2),
+            )),
+            fired: false,
+        };
+        let budget: Duration = Duration::from_secs(60);
+        assert_eq!(heartbeat.overrun(budget, started + budget), None);
+        let now: Instant = started + Duration::from_secs(75);
+        assert_eq!(
+            heartbeat.overrun(budget, now),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            heartbeat.diagnose(budget, now),
+            "Watchdog: iteration 12 exceeded its 60s budget in scanning, stuck at:\n    0: phase scanning, entered 75s ago\n    1: after stage chain_fetch, finished 74s ago\n    2: request GET https://localhost:5000/v1/api/iserver/marketdata/snapshot, sent 73s ago"
+        );
+
+        // Working orders wait until their deadline on purpose.
+        let working: Heartbeat = Heartbeat {
+            phase: "orders_working",
+            allowance: Duration::from_secs(30),
+            stage: None,
+            request: None,
+            ..heartbeat
+        };
+        assert_eq!(working.overrun(budget, now), None);
+        assert!(working
+            .diagnose(budget, now)
+            .ends_with("stuck at:\n    0: phase orders_working, entered 75s ago"));
+    }
+
+    #[test]
+    fn test_gateway_metrics() {
+        assert_eq!(GatewayError::of_status(401), GatewayError::Unauthorized);
+        assert_eq!(GatewayError::of_status(429), GatewayError::RateLimited);
+        assert_eq!(GatewayError::of_status(404), GatewayError::Client);
+        assert_eq!(GatewayError::of_status(503), GatewayError::Server);
+
+        let mut metrics: Metrics = Metrics::default();
+        for seconds in [0.04, 0.3, 0.3, 12.0] {
+            metrics.snapshot_latency.observe(seconds);
+        }
+        metrics.gateway_requests = 10;
+        *metrics
+            .gateway_errors
+            .entry(GatewayError::Server)
+            .or_default() += 2;
+        *metrics
+            .gateway_errors
+            .entry(GatewayError::Timeout)
+            .or_default() += 1;
+
+        let rendered: String = metrics.render();
+        let lines: Vec<&str> = rendered.lines().collect();
+        // Buckets are cumulative.
+        for line in [
+            "trading_bot_snapshot_seconds_bucket{le=\"0.05\"} 1",
+            "trading_bot_snapshot_seconds_bucket{le=\"0.25\"} 1",
+            "trading_bot_snapshot_seconds_bucket{le=\"0.5\"} 3",
+            "trading_bot_snapshot_seconds_bucket{le=\"10\"} 3",
+            "trading_bot_snapshot_seconds_bucket{le=\"+Inf\"} 4",
+            "trading_bot_snapshot_seconds_count 4",
+            "trading_bot_gateway_requests_total 10",
+            "trading_bot_gateway_errors_total{class=\"timeout\"} 1",
+            "trading_bot_gateway_errors_total{class=\"server\"} 2",
+        ] {
+            assert!(lines.contains(&line), "missing {}", line);
+        }
+    }
+
+    #[test]
+    fn test_scan_summary() {
+        let (_, quotes) = synthetic_chain(
+            &ChainParams {
+                num_expiries: 3,
+                num_strikes: 20,
+                ..ChainParams::default()
+            },
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+        );
+        let contender = |type_spread: &str, arb_val: f64, above_threshold: bool| Contender {
+            arb_val,
+            avg_ask: 10.0,
+            type_spread: type_spread.to_string(),
+            exp_date: "240708".parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold,
+            contracts: Vec::new(),
+            reason: Reason::default(),
+        };
+        let mut scan_summary: ScanSummary = ScanSummary::capture(
+            &quotes,
+            &[
+                contender("Butterfly", 0.3, true),
+                contender("Butterfly", 0.2, true),
+                contender("Boxspread", 0.45, false),
+            ],
+        );
+        // Calls and puts of a strike count once.
+        assert_eq!((scan_summary.expirations, scan_summary.strikes), (3, 60));
+        scan_summary.orders = 2;
+
+        let mut stage_timings: StageTimings = StageTimings::new();
+        stage_timings.record("chain_fetch", Duration::from_millis(1200));
+        stage_timings.record("contender_calc", Duration::from_millis(300));
+        assert_eq!(
+            scan_summary.line(4, &stage_timings),
+            "Scan 4: 3 expirations, 60 strikes, contenders Butterfly 2, best edge 0.45 (Boxspread 240708), 2 orders, chain_fetch 1.200s, contender_calc 0.300s (total 1.500s)."
+        );
+        assert_eq!(
+            ScanSummary::default().line(5, &StageTimings::new()),
+            "Scan 5: 0 expirations, 0 strikes, no contenders, no edge, 0 orders, total 0.000s."
+        );
+    }
+
+    #[test]
+    fn test_working_orders() {
+        let now: Instant = Instant::now();
+        let working_orders: WorkingOrders = WorkingOrders::new();
+        working_orders.add("1", now + Duration::from_secs(10));
+        working_orders.add("2", now + Duration::from_secs(30));
+
+        // Each order expires at its own TTL.
+        assert!(working_orders.take_expired(now).is_empty());
+        assert_eq!(
+            working_orders.take_expired(now + Duration::from_secs(10)),
+            vec!["1".to_string()]
+        );
+        assert_eq!(working_orders.ids(), vec!["2".to_string()]);
+
+        // A clone shares the orders with the monitor thread.
+        assert_eq!(working_orders.clone().take_all(), vec!["2".to_string()]);
+        assert_eq!(working_orders.len(), 0);
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let contender: Contender = Contender {
+            arb_val: 0.5,
+            avg_ask: 10.0,
+            type_spread: "Butterfly".to_string(),
+            exp_date: "240701".parse().unwrap(),
+            rank_value: 1.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: true,
+            contracts: vec![Contract {
+                strike: 5500.0,
+                mkt_price: 12.5,
+                date: "240701".parse().unwrap(),
+                type_contract: "C".to_string(),
+            }],
+            reason: Reason::default(),
+        };
+        let now: Instant = Instant::now();
+        let now_ms: i64 = 1_700_000_000_000;
+        let mut recent_contenders: RecentContenders =
+            RecentContenders::new(Duration::from_secs(60));
+        recent_contenders.record(std::slice::from_ref(&contender), now);
+        let mut journal: Journal = Journal::new();
+        journal.record_order(
+            "abc-0",
+            "Butterfly",
+            "240701".parse().unwrap(),
+            1.0,
+            0.25,
+            &Reason::default(),
+            1_700_000_000_000,
+            "none",
+            "SMART",
+            Vec::new(),
+        );
+
+        let path: String = env::temp_dir()
+            .join("trading_bot_test_state.json")
+            .to_string_lossy()
+            .to_string();
+        save_state(
+            &path,
+            &BotState {
+                saved_at: now_ms,
+                live_orders: vec!["123".to_string()],
+                submitted: recent_contenders.export(now + Duration::from_secs(20), now_ms),
+                journal,
+                position_watch: PositionWatch::default(),
+            },
+        )
+        .unwrap();
+        let mut state: BotState = load_state(&path).unwrap().unwrap();
+        assert_eq!(state.live_orders, vec!["123".to_string()]);
+
+        // A restart 30 seconds later keeps the remaining 10 seconds of the cooldown.
+        let restarted: Instant = Instant::now();
+        let mut restored: RecentContenders = RecentContenders::new(Duration::from_secs(60));
+        restored.restore(&state.submitted, restarted, now_ms + 30_000);
+        assert!(restored.contains(&contender, restarted + Duration::from_secs(9)));
+        assert!(!restored.contains(&contender, restarted + Duration::from_secs(11)));
+
+        // Fills of orders placed before the restart are still matched.
+        assert!(state
+            .journal
+            .record_execution("e1", "abc-0", 1.0, 1.0)
+            .is_some());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_state(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_clean_up_after_panic() {
+        let path: String = env::temp_dir()
+            .join("trading_bot_test_panic_state.json")
+            .to_string_lossy()
+            .to_string();
+        save_state(
+            &path,
+            &BotState {
+                saved_at: Utc::now().timestamp_millis(),
+                live_orders: vec!["123".to_string(), "456".to_string()],
+                submitted: Vec::new(),
+                journal: Journal::default(),
+                position_watch: PositionWatch::default(),
+            },
+        )
+        .unwrap();
+        let mut ibkr: IBKR = IBKR::new();
+        ibkr.restore_state(&path).unwrap();
+        assert_eq!(ibkr.working_order_count(), 2);
+
+        // Without orders being sent nothing is canceled or saved.
+        clean_up_after_panic(&mut ibkr, false, &path);
+        assert_eq!(ibkr.working_order_count(), 2);
+
+        // The tracked orders are canceled, and the state left behind has none working.
+        clean_up_after_panic(&mut ibkr, true, &path);
+        assert_eq!(ibkr.working_order_count(), 0);
+        let state: BotState = load_state(&path).unwrap().unwrap();
+        assert!(state.live_orders.is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,78 @@
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Lockfile guarding an account against a second bot instance, removed when dropped.
+pub(crate) struct InstanceLock {
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Acquires the lock for an account, replacing a lockfile left behind by a process that is no
+    /// longer running.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The IBKR account the bot trades.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the lock, or an error naming the process that holds it.
+    pub(crate) fn acquire(account_id: &str) -> Result<Self, Box<dyn Error>> {
+        let path: PathBuf = PathBuf::from(format!("trading_bot_{}.lock", account_id));
+
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{}", process::id())?;
+                    return Ok(InstanceLock { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let holder: String = fs::read_to_string(&path).unwrap_or_default();
+                    match holder.trim().parse::<u32>() {
+                        Ok(pid) if is_process_running(pid) => {
+                            return Err(format!(
+                                "Account {} is already traded by process {} ({})",
+                                account_id,
+                                pid,
+                                path.display()
+                            )
+                            .into());
+                        }
+                        _ => fs::remove_file(&path)?,
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(format!("Failed to acquire {}", path.display()).into())
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Checks whether a process is still running. Where this cannot be determined, the process is
+/// assumed to be running.
+///
+/// # Arguments
+///
+/// * `pid` - The process ID.
+fn is_process_running(pid: u32) -> bool {
+    if pid == process::id() {
+        return true;
+    }
+    if cfg!(target_os = "linux") {
+        Path::new(&format!("/proc/{}", pid)).exists()
+    } else {
+        true
+    }
+}
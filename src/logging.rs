@@ -1,5 +1,131 @@
 use chrono::{DateTime, Utc};
-use std::{fs::OpenOptions, io::Write, path::Path, process::exit};
+use rand::Rng;
+use serde_json::{json, Value};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    chain::OptionChain,
+    error_report::report_error,
+    events::{emit, Event},
+    helpers::format_strike,
+    redact::{redact, redact_json},
+    structs::{Contender, LogLevel},
+};
+
+/// The `LogLevel` messages are logged up to, as set by `LOG_LEVEL`.
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Sets the level messages are logged up to.
+///
+/// # Arguments
+///
+/// * `level` - The most verbose level logged.
+pub(crate) fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns whether messages of a level are logged.
+///
+/// # Arguments
+///
+/// * `level` - The level of the message.
+pub(crate) fn log_enabled(level: LogLevel) -> bool {
+    level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Whether the console output is colored, with contender tables and iteration summaries, as set
+/// by `--pretty`. The file log is the same either way.
+static PRETTY: AtomicBool = AtomicBool::new(false);
+
+/// The ANSI escape codes of the pretty console output.
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const DIM: &str = "\x1b[2m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Turns the pretty console output on or off.
+///
+/// # Arguments
+///
+/// * `pretty` - Whether the console output is pretty.
+pub(crate) fn set_pretty(pretty: bool) {
+    PRETTY.store(pretty, Ordering::Relaxed);
+}
+
+/// Returns whether the console output is pretty.
+pub(crate) fn is_pretty() -> bool {
+    PRETTY.load(Ordering::Relaxed)
+}
+
+/// Prints a message to the console, colored by its level and with a dimmed correlation ID when
+/// the output is pretty.
+///
+/// # Arguments
+///
+/// * `color` - The color of the message's level, if any.
+/// * `status` - The message.
+fn print_console(color: Option<&str>, status: &str) {
+    let status: &str = &redact(status);
+    let correlation_id: Option<String> = correlation_id();
+    if !is_pretty() {
+        match correlation_id {
+            Some(correlation_id) => println!("[{}] {}", correlation_id, status),
+            None => println!("{}", status),
+        }
+        return;
+    }
+    let prefix: String = correlation_id.map_or(String::new(), |correlation_id| {
+        format!("{}[{}]{} ", DIM, correlation_id, RESET)
+    });
+    match color {
+        Some(color) => println!("{}{}{}{}", prefix, color, status, RESET),
+        None => println!("{}{}", prefix, status),
+    }
+}
+
+/// The correlation ID of the current scan iteration, tagged on log lines, audit entries and
+/// gateway requests.
+static CORRELATION_ID: Mutex<Option<String>> = Mutex::new(None);
+
+/// Starts a new correlation ID for a scan iteration.
+///
+/// # Arguments
+///
+/// * `iteration` - The iteration number, starting at 1.
+///
+/// # Returns
+///
+/// A `String` with the new ID, the iteration and a random suffix, e.g. `"12-9f3a61c2"`.
+///
+/// # Example
+///
+/// ```
+/// let correlation_id = start_correlation(12);
+/// assert_eq!(correlation_id(), Some(correlation_id));
+/// ```
+pub(crate) fn start_correlation(iteration: u64) -> String {
+    let correlation_id: String = format!("{}-{:08x}", iteration, rand::thread_rng().gen::<u32>());
+    *CORRELATION_ID.lock().unwrap_or_else(|e| e.into_inner()) = Some(correlation_id.clone());
+    correlation_id
+}
+
+/// Returns the correlation ID of the current scan iteration, if one has started.
+pub(crate) fn correlation_id() -> Option<String> {
+    CORRELATION_ID
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
 
 /// Logs a message to a specified text file.
 ///
@@ -19,11 +145,7 @@ use std::{fs::OpenOptions, io::Write, path::Path, process::exit};
 /// log_to_file("log.txt", "This is a log message").unwrap();
 /// ```
 fn log_to_file<P: AsRef<Path>>(path: P, message: &str) -> std::io::Result<()> {
-    let mut file: std::fs::File = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(path)?;
+    let mut file: std::fs::File = OpenOptions::new().create(true).append(true).open(path)?;
 
     writeln!(file, "{}", message)?; // Writes the message and a newline character.
 
@@ -32,8 +154,10 @@ fn log_to_file<P: AsRef<Path>>(path: P, message: &str) -> std::io::Result<()> {
 
 /// Logs a message to the console and a text file.
 ///
-/// The message is printed to the console. If not in test mode, the message is also logged to a
-/// text file with a timestamp in UTC.
+/// The message is printed to the console, prefixed with the correlation ID of the current scan
+/// iteration once one has started. If not in test mode, the message is also logged to a text file
+/// with a timestamp in UTC. Messages starting with `WARNING` are logged at the warn level, others
+/// at the info level.
 ///
 /// # Arguments
 ///
@@ -45,11 +169,372 @@ fn log_to_file<P: AsRef<Path>>(path: P, message: &str) -> std::io::Result<()> {
 /// log_message("Application started.".to_string());
 /// ```
 pub(crate) fn log_message(status: String) {
-    println!("{}", status);
-    if !cfg!(test) {
-        let now: DateTime<Utc> = Utc::now();
-        let formatted_now: String = now.format("%Y-%m-%d %H:%M:%S%.9f UTC").to_string();
-        let _ = log_to_file("log.txt", &format!("{}   {}", formatted_now, status));
+    let warning: bool = status.starts_with("WARNING");
+    if !log_enabled(if warning {
+        LogLevel::Warn
+    } else {
+        LogLevel::Info
+    }) {
+        return;
+    }
+    print_console(warning.then_some(YELLOW), &status);
+    log_to_text_file(&status);
+}
+
+/// Logs a message at the info level to the text file, and to the console unless the output is
+/// pretty, for messages the pretty output shows another way, such as the contender table.
+///
+/// # Arguments
+///
+/// * `status` - The log message.
+pub(crate) fn log_detail(status: String) {
+    if !log_enabled(LogLevel::Info) {
+        return;
+    }
+    if !is_pretty() {
+        print_console(None, &status);
+    }
+    log_to_text_file(&status);
+}
+
+/// Logs a message at the debug level, such as the legs of a submitted combo.
+///
+/// # Arguments
+///
+/// * `status` - The log message.
+pub(crate) fn log_debug(status: String) {
+    if log_enabled(LogLevel::Debug) {
+        print_console(Some(DIM), &status);
+        log_to_text_file(&status);
+    }
+}
+
+/// Logs a message at the trace level, such as a gateway request.
+///
+/// # Arguments
+///
+/// * `status` - The log message.
+pub(crate) fn log_trace(status: String) {
+    if log_enabled(LogLevel::Trace) {
+        print_console(Some(DIM), &status);
+        log_to_text_file(&status);
+    }
+}
+
+/// Appends a message to `log.txt` with a timestamp in UTC and the correlation ID, unless in test
+/// mode.
+///
+/// # Arguments
+///
+/// * `status` - The log message.
+fn log_to_text_file(status: &str) {
+    if cfg!(test) {
+        return;
+    }
+    let status: &str = &redact(status);
+    let now: DateTime<Utc> = Utc::now();
+    let formatted_now: String = now.format("%Y-%m-%d %H:%M:%S%.9f UTC").to_string();
+    let _ = match correlation_id() {
+        Some(correlation_id) => log_to_file(
+            "log.txt",
+            &format!("{}   [{}] {}", formatted_now, correlation_id, status),
+        ),
+        None => log_to_file("log.txt", &format!("{}   {}", formatted_now, status)),
+    };
+}
+
+/// Appends an order request or response to the audit file, `audit.jsonl`.
+///
+/// Each entry is a single JSON line with the UTC timestamp, correlation ID, direction, method, URL,
+/// HTTP status and the exact body, redacted as the logs are. Bodies that are valid JSON are stored
+/// as JSON, others as strings.
+///
+/// # Arguments
+///
+/// * `direction` - Either `"request"` or `"response"`.
+/// * `method` - The HTTP method of the call.
+/// * `url` - The URL of the call.
+/// * `status` - The HTTP status code, for responses.
+/// * `body` - The exact body sent or received.
+///
+/// # Example
+///
+/// ```
+/// log_audit("request", "POST", "https://localhost:5000/v1/api/iserver/account/U123/orders", None, "{}");
+/// ```
+pub(crate) fn log_audit(direction: &str, method: &str, url: &str, status: Option<u16>, body: &str) {
+    if cfg!(test) {
+        return;
+    }
+    let entry: Value = audit_entry(direction, method, url, status, body);
+    let _ = log_to_file("audit.jsonl", &redact_json(&entry).to_string());
+}
+
+/// Builds an audit entry before redaction.
+///
+/// # Arguments
+///
+/// * `direction` - Either `"request"` or `"response"`.
+/// * `method` - The HTTP method of the call.
+/// * `url` - The URL of the call.
+/// * `status` - The HTTP status code, for responses.
+/// * `body` - The exact body sent or received.
+///
+/// # Returns
+///
+/// A `Value` with the entry, the body parsed as JSON if it is valid JSON.
+pub(crate) fn audit_entry(
+    direction: &str,
+    method: &str,
+    url: &str,
+    status: Option<u16>,
+    body: &str,
+) -> Value {
+    let now: DateTime<Utc> = Utc::now();
+    json!({
+        "time": now.format("%Y-%m-%d %H:%M:%S%.9f UTC").to_string(),
+        "correlation_id": correlation_id(),
+        "direction": direction,
+        "method": method,
+        "url": url,
+        "status": status,
+        "body": serde_json::from_str::<Value>(body).unwrap_or_else(|_| Value::from(body)),
+    })
+}
+
+/// Appends an entry to the trade journal file, `journal.jsonl`.
+///
+/// # Arguments
+///
+/// * `entry` - The journal entry as a JSON value.
+///
+/// # Example
+///
+/// ```
+/// log_journal(&json!({ "event": "order", "order_ref": "abc-0" }));
+/// ```
+pub(crate) fn log_journal(entry: &Value) {
+    if cfg!(test) {
+        return;
+    }
+    let now: DateTime<Utc> = Utc::now();
+    let mut entry: Value = entry.clone();
+    entry["time"] = Value::from(now.format("%Y-%m-%d %H:%M:%S%.9f UTC").to_string());
+    let _ = log_to_file("journal.jsonl", &redact_json(&entry).to_string());
+}
+
+/// Appends a recorded quote snapshot to the quotes file, `quotes.jsonl`, for backtesting.
+///
+/// # Arguments
+///
+/// * `snapshot` - The snapshot serialized as a single JSON line.
+///
+/// # Example
+///
+/// ```
+/// log_quotes("{\"time\":0,\"date\":\"240701\",\"minutes_to_close\":390,\"quotes\":[]}");
+/// ```
+pub(crate) fn log_quotes(snapshot: &str) {
+    if cfg!(test) {
+        return;
+    }
+    let _ = log_to_file("quotes.jsonl", snapshot);
+}
+
+/// Appends a scan's contenders, including those below the arb threshold, to a dated CSV file,
+/// e.g. `contenders_2024-07-01.csv`, writing the header when the file is created.
+///
+/// # Arguments
+///
+/// * `contenders` - The contenders of the scan.
+/// * `ticker` - The ticker of the underlying, for the OSI symbols of the legs, which are separated
+///   by semicolons as they contain spaces.
+/// * `chain` - The chain the contenders were scanned from, for the trading classes of the legs.
+///
+/// # Example
+///
+/// ```
+/// log_contenders(&contenders, "SPX", None);
+/// ```
+pub(crate) fn log_contenders(contenders: &[Contender], ticker: &str, chain: Option<&OptionChain>) {
+    if cfg!(test) {
+        return;
+    }
+    let path: String = format!("contenders_{}.csv", Utc::now().format("%Y-%m-%d"));
+    let _ = write_contenders_csv(&path, contenders, ticker, chain);
+}
+
+/// Appends contenders to a CSV file, one row each, writing the header when the file is created.
+///
+/// # Arguments
+///
+/// * `path` - The path of the CSV file.
+/// * `contenders` - The contenders.
+/// * `ticker` - The ticker of the underlying, for the OSI symbols of the legs.
+/// * `chain` - The chain the contenders were scanned from, for the trading classes of the legs.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an error if the file can't be written.
+pub(crate) fn write_contenders_csv(
+    path: &str,
+    contenders: &[Contender],
+    ticker: &str,
+    chain: Option<&OptionChain>,
+) -> std::io::Result<()> {
+    if !Path::new(path).exists() {
+        log_to_file(
+            path,
+            "scan_time,type_spread,exp_date,arb_val,quoted_edge,avg_ask,rank_value,fill_probability,above_threshold,legs,osi_legs",
+        )?;
+    }
+
+    let rows: Vec<String> = contenders
+        .iter()
+        .map(|contender| {
+            let legs: Vec<String> = contender
+                .contracts
+                .iter()
+                .map(|contract| contract.describe())
+                .collect();
+            let osi_legs: Vec<String> = contender
+                .contracts
+                .iter()
+                .map(|contract| contract.osi_symbol(ticker, chain))
+                .collect();
+            format!(
+                "{},{},{},{:.2},{:.2},{},{:.4},{:.4},{},{},{}",
+                contender.scan_time,
+                contender.type_spread,
+                contender.exp_date,
+                contender.arb_val,
+                contender.quoted_edge(),
+                contender.avg_ask,
+                contender.rank_value,
+                contender.fill_probability,
+                contender.above_threshold,
+                legs.join(" "),
+                osi_legs.join(";")
+            )
+        })
+        .collect();
+    if !rows.is_empty() {
+        log_to_file(path, &rows.join("\n"))?;
+    }
+    Ok(())
+}
+
+/// Lays out rows as a table with aligned columns, for the console.
+///
+/// # Arguments
+///
+/// * `header` - The column names.
+/// * `rows` - The cells of each row, as many as there are columns.
+///
+/// # Returns
+///
+/// A `String` with the header, a rule and the rows, each column padded to its widest cell.
+///
+/// # Example
+///
+/// ```
+/// let table = format_table(&["Strategy", "Arb"], &[vec!["Butterfly".to_string(), "0.35".to_string()]]);
+/// ```
+pub(crate) fn format_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .chain([header[column].chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let format_row = |cells: Vec<&str>| -> String {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines: Vec<String> = vec![
+        format_row(header.to_vec()),
+        format_row(
+            widths
+                .iter()
+                .map(|width| "-".repeat(*width))
+                .collect::<Vec<String>>()
+                .iter()
+                .map(String::as_str)
+                .collect(),
+        ),
+    ];
+    lines.extend(
+        rows.iter()
+            .map(|row| format_row(row.iter().map(String::as_str).collect())),
+    );
+    lines.join("\n")
+}
+
+/// Prints the contenders about to be ordered as a table, when the console output is pretty.
+///
+/// # Arguments
+///
+/// * `contenders` - The contenders.
+/// * `num_fills` - The number of fills of each order.
+pub(crate) fn print_contender_table(contenders: &[Contender], num_fills: i32) {
+    if !is_pretty() || contenders.is_empty() {
+        return;
+    }
+    let rows: Vec<Vec<String>> = contenders
+        .iter()
+        .map(|contender| {
+            let legs: Vec<String> = (0..contender.contracts.len())
+                .map(|index| {
+                    let leg = &contender.contracts[index];
+                    format!(
+                        "{} {}*{}{}",
+                        contender.action(index),
+                        contender.multiplier(num_fills, index),
+                        format_strike(leg.strike),
+                        leg.type_contract
+                    )
+                })
+                .collect();
+            vec![
+                contender.type_spread.clone(),
+                contender.exp_date.to_string(),
+                num_fills.to_string(),
+                format!("{:.2}", contender.arb_val),
+                format!("{:.0}%", contender.fill_probability * 100.0),
+                legs.join(", "),
+            ]
+        })
+        .collect();
+    println!(
+        "{}",
+        format_table(
+            &["Strategy", "Expiry", "Fills", "Arb", "Fill", "Legs"],
+            &rows
+        )
+    );
+}
+
+/// Logs the one-line summary of a scan iteration at the info level, in bold when the console
+/// output is pretty.
+///
+/// # Arguments
+///
+/// * `status` - The summary.
+pub(crate) fn log_summary(status: String) {
+    if log_enabled(LogLevel::Info) {
+        print_console(Some(BOLD), &status);
+        log_to_text_file(&status);
     }
 }
 
@@ -68,12 +553,17 @@ pub(crate) fn log_message(status: String) {
 /// log_error("An unexpected error occurred.".to_string());
 /// ```
 pub(crate) fn log_error(error: String) {
-    eprintln!("{}", format!("Error: {}.", error));
-    if !cfg!(test) {
-        let now: DateTime<Utc> = Utc::now();
-        let formatted_now: String = now.format("%Y-%m-%d %H:%M:%S%.9f UTC").to_string();
-        let _ = log_to_file("log.txt", &format!("{}   Error: {}.", formatted_now, error));
+    report_error(&error);
+    emit(&Event::Error {
+        message: error.clone(),
+    });
+    let console_error: String = redact(&error);
+    if is_pretty() {
+        eprintln!("{}Error: {}.{}", RED, console_error, RESET);
+    } else {
+        eprintln!("Error: {}.", console_error);
     }
-    log_message(format!("Exiting..."));
+    log_to_text_file(&format!("Error: {}.", error));
+    log_message("Exiting...".to_string());
     exit(1);
 }
@@ -0,0 +1,294 @@
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    control::{contenders, describe_status, request_scan, set_paused, ScanFilter},
+    helpers::get_watchlist_path,
+    logging::log_message,
+    watchlist::{export_watchlist, import_watchlist},
+};
+
+/// The phases of the run loop.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Phase {
+    /// Checking the market hours, kill switch, portfolio and P&L before a scan.
+    Idle,
+    /// Scanning for contenders and submitting their orders.
+    Scanning,
+    /// Orders are working until the deadline or a fill.
+    OrdersWorking { deadline: Instant },
+    /// Canceling the orders still working.
+    Cancelling,
+    /// Canceling the orders still working after a fatal error, before the loop stops.
+    Failing,
+    /// Waiting out the deadline before the next scan.
+    Cooldown { deadline: Instant },
+    /// The loop is done.
+    Stopped,
+}
+
+/// A control command read from standard input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Command {
+    /// Cancel the working orders and scan again without waiting.
+    ScanNow,
+    /// Cancel the working orders.
+    CancelAll,
+    /// Cancel the working orders and stop the bot.
+    Stop,
+}
+
+impl Command {
+    /// Parses a command from a line of input.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The input line, e.g. `"scan"`, `"cancel"` or `"stop"`. Words after the command
+    ///   are its arguments.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Command>` containing the command, or `None` if the line is not a command.
+    pub(crate) fn parse(line: &str) -> Option<Self> {
+        match line
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "scan" => Some(Command::ScanNow),
+            "cancel" => Some(Command::CancelAll),
+            "stop" | "quit" => Some(Command::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// Something that happened while the loop was in a phase.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LoopEvent {
+    /// The pre-scan checks passed.
+    Ready,
+    /// The pre-scan checks require the bot to stop, e.g. the market closed or the kill switch.
+    Halt,
+    /// The scan schedule skips scanning for now.
+    Skip,
+    /// The scan submitted orders.
+    OrdersPlaced,
+    /// The scan submitted no orders.
+    NothingPlaced,
+    /// The phase's deadline elapsed.
+    Timer,
+    /// A working order filled.
+    Filled,
+    /// Every working order was canceled at its TTL.
+    Expired,
+    /// The working orders were canceled.
+    Cancelled,
+    /// An error the loop can't recover from, e.g. an order submission failed part way.
+    Failed,
+    /// A control command was received.
+    Command(Command),
+}
+
+/// How long the timed phases of the loop last.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Timings {
+    /// How long submitted orders work before they are canceled, or the wait until the next scan
+    /// when the schedule skips one.
+    pub(crate) working: Duration,
+    /// How long the loop waits after canceling before the next scan.
+    pub(crate) cooldown: Duration,
+}
+
+impl Phase {
+    /// Returns the name of the phase, as reported by the control plane.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Phase::Idle => "idle",
+            Phase::Scanning => "scanning",
+            Phase::OrdersWorking { .. } => "orders_working",
+            Phase::Cancelling => "cancelling",
+            Phase::Failing => "failing",
+            Phase::Cooldown { .. } => "cooldown",
+            Phase::Stopped => "stopped",
+        }
+    }
+
+    /// Moves the loop to its next phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - What happened in the current phase.
+    /// * `now` - The current instant, used to set deadlines.
+    /// * `timings` - How long the timed phases last.
+    ///
+    /// # Returns
+    ///
+    /// The next `Phase`, which is the current one if the event does not apply to it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let phase = Phase::Idle.next(LoopEvent::Ready, Instant::now(), &timings);
+    /// assert_eq!(phase, Phase::Scanning);
+    /// ```
+    pub(crate) fn next(self, event: LoopEvent, now: Instant, timings: &Timings) -> Phase {
+        match (self, event) {
+            (Phase::Stopped, _) => Phase::Stopped,
+            // Orders may be working whatever the phase, so they are canceled before stopping.
+            (Phase::Failing, LoopEvent::Cancelled) => Phase::Stopped,
+            (Phase::Failing, _) => Phase::Failing,
+            (_, LoopEvent::Failed) => Phase::Failing,
+            (_, LoopEvent::Command(Command::Stop)) | (Phase::Idle, LoopEvent::Halt) => {
+                Phase::Stopped
+            }
+            (Phase::Idle, LoopEvent::Ready) => Phase::Scanning,
+            (Phase::Idle, LoopEvent::Skip) => Phase::Cooldown {
+                deadline: now + timings.working,
+            },
+            (Phase::Scanning, LoopEvent::OrdersPlaced) => Phase::OrdersWorking {
+                deadline: now + timings.working,
+            },
+            (Phase::Scanning, LoopEvent::NothingPlaced) => Phase::Cooldown {
+                deadline: now + timings.working + timings.cooldown,
+            },
+            // A fill changes the portfolio, so the rest is canceled and the next scan sized anew.
+            (
+                Phase::OrdersWorking { .. },
+                LoopEvent::Timer
+                | LoopEvent::Filled
+                | LoopEvent::Command(Command::CancelAll | Command::ScanNow),
+            ) => Phase::Cancelling,
+            (Phase::OrdersWorking { .. }, LoopEvent::Expired)
+            | (Phase::Cancelling, LoopEvent::Cancelled) => Phase::Cooldown {
+                deadline: now + timings.cooldown,
+            },
+            (Phase::Cooldown { .. }, LoopEvent::Timer | LoopEvent::Command(Command::ScanNow)) => {
+                Phase::Idle
+            }
+            (phase, _) => phase,
+        }
+    }
+}
+
+/// Reads control commands from standard input on a background thread. `pause`, `resume`,
+/// `status`, `export` and `import` are handled on the thread, since they don't interrupt the loop.
+/// `export` writes the last scan's contenders to a watchlist file and `import` watches the
+/// structures of one, each at the path after the command or `WATCHLIST_PATH`. `scan` may be
+/// followed by a strategy and/or expiry to limit the scan to.
+///
+/// # Arguments
+///
+/// * `sender` - Where each command is sent as it is typed.
+pub(crate) fn spawn_command_reader(sender: Sender<Command>) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match line.trim().to_lowercase().as_str() {
+                "pause" => {
+                    set_paused(true);
+                    log_message("Trading paused.".to_string());
+                    continue;
+                }
+                "resume" => {
+                    set_paused(false);
+                    log_message("Trading resumed.".to_string());
+                    continue;
+                }
+                "status" => {
+                    log_message(describe_status());
+                    continue;
+                }
+                _ => {}
+            }
+            let (word, path): (&str, &str) =
+                line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let path: String = match path.trim() {
+                "" => get_watchlist_path(),
+                path => path.to_string(),
+            };
+            match word.to_lowercase().as_str() {
+                "export" => {
+                    match export_watchlist(&path, &contenders()) {
+                        Ok(count) => {
+                            log_message(format!("Exported {} contenders to {}.", count, path))
+                        }
+                        Err(e) => log_message(format!(
+                            "Failed to export the watchlist to {}: {}.",
+                            path, e
+                        )),
+                    }
+                    continue;
+                }
+                "import" => {
+                    match import_watchlist(&path) {
+                        Ok(count) => {
+                            log_message(format!("Watching {} structures from {}.", count, path))
+                        }
+                        Err(e) => log_message(format!(
+                            "Failed to import the watchlist from {}: {}.",
+                            path, e
+                        )),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(command) = Command::parse(&line) {
+                if command == Command::ScanNow {
+                    let args: &str = line.trim().split_once(' ').map_or("", |(_, args)| args);
+                    request_scan(ScanFilter::parse(args));
+                }
+                if sender.send(command).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Waits in a timed phase until its deadline, a command, or an event returned by `poll`.
+///
+/// # Arguments
+///
+/// * `deadline` - When the phase ends.
+/// * `poll_interval` - How often `poll` is called.
+/// * `commands` - The control commands.
+/// * `poll` - Checks for fills and expired orders, returning the event that ends the wait if any.
+///
+/// # Returns
+///
+/// The `LoopEvent` that ended the wait.
+pub(crate) fn wait_for_event(
+    deadline: Instant,
+    poll_interval: Duration,
+    commands: &Receiver<Command>,
+    mut poll: impl FnMut() -> Option<LoopEvent>,
+) -> LoopEvent {
+    loop {
+        let now: Instant = Instant::now();
+        if now >= deadline {
+            return LoopEvent::Timer;
+        }
+
+        match commands.recv_timeout(poll_interval.min(deadline - now)) {
+            Ok(command) => return LoopEvent::Command(command),
+            // Without standard input only the timer and fills remain.
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(poll_interval.min(deadline.saturating_duration_since(Instant::now())))
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if let Some(event) = poll() {
+            return event;
+        }
+    }
+}
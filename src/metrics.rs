@@ -0,0 +1,251 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+/// The upper bounds in seconds of the snapshot round-trip histogram's buckets.
+const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// A cumulative histogram, as Prometheus exports it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Histogram {
+    /// The observations at or below each of `LATENCY_BUCKETS`.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    /// Records an observation.
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds` - The observed value in seconds.
+    pub(crate) fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    /// Formats the histogram in the Prometheus text format.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The metric name.
+    /// * `help` - The metric description.
+    fn render(&self, name: &str, help: &str) -> String {
+        let mut lines: Vec<String> = vec![
+            format!("# HELP {} {}", name, help),
+            format!("# TYPE {} histogram", name),
+        ];
+        for (count, bound) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            lines.push(format!("{}_bucket{{le=\"{}\"}} {}", name, bound, count));
+        }
+        lines.push(format!("{}_bucket{{le=\"+Inf\"}} {}", name, self.count));
+        lines.push(format!("{}_sum {}", name, self.sum));
+        lines.push(format!("{}_count {}", name, self.count));
+        lines.join("\n")
+    }
+}
+
+/// The class of a failed gateway request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum GatewayError {
+    /// The request timed out.
+    Timeout,
+    /// The gateway couldn't be reached, e.g. it is down or restarting.
+    Connection,
+    /// The brokerage session expired or isn't authenticated (`401` or `403`).
+    Unauthorized,
+    /// The gateway throttled the request (`429`).
+    RateLimited,
+    /// Any other `4xx` status.
+    Client,
+    /// A `5xx` status.
+    Server,
+    /// The response didn't match the expected schema.
+    Decode,
+    Other,
+}
+
+impl GatewayError {
+    /// Classifies a request that failed before a response arrived.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The transport error.
+    pub(crate) fn of_transport(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            GatewayError::Timeout
+        } else if error.is_connect() {
+            GatewayError::Connection
+        } else {
+            GatewayError::Other
+        }
+    }
+
+    /// Classifies an error status.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The HTTP status code.
+    pub(crate) fn of_status(status: u16) -> Self {
+        match status {
+            401 | 403 => GatewayError::Unauthorized,
+            429 => GatewayError::RateLimited,
+            400..=499 => GatewayError::Client,
+            500..=599 => GatewayError::Server,
+            _ => GatewayError::Other,
+        }
+    }
+
+    /// Returns the label of the error class, e.g. `"rate_limited"`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            GatewayError::Timeout => "timeout",
+            GatewayError::Connection => "connection",
+            GatewayError::Unauthorized => "unauthorized",
+            GatewayError::RateLimited => "rate_limited",
+            GatewayError::Client => "client",
+            GatewayError::Server => "server",
+            GatewayError::Decode => "decode",
+            GatewayError::Other => "other",
+        }
+    }
+}
+
+/// The gateway health metrics.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Metrics {
+    /// The round-trip times of market data snapshots.
+    pub(crate) snapshot_latency: Histogram,
+    pub(crate) gateway_requests: u64,
+    pub(crate) gateway_errors: BTreeMap<GatewayError, u64>,
+}
+
+impl Metrics {
+    /// Formats the metrics in the Prometheus text format.
+    ///
+    /// # Returns
+    ///
+    /// A `String` with one metric family after another, ending with a newline.
+    pub(crate) fn render(&self) -> String {
+        let mut lines: Vec<String> = vec![
+            self.snapshot_latency.render(
+                "trading_bot_snapshot_seconds",
+                "Round-trip time of market data snapshot requests.",
+            ),
+            "# HELP trading_bot_gateway_requests_total Requests sent to the gateway.".to_string(),
+            "# TYPE trading_bot_gateway_requests_total counter".to_string(),
+            format!(
+                "trading_bot_gateway_requests_total {}",
+                self.gateway_requests
+            ),
+            "# HELP trading_bot_gateway_errors_total Failed gateway requests by error class."
+                .to_string(),
+            "# TYPE trading_bot_gateway_errors_total counter".to_string(),
+        ];
+        lines.extend(self.gateway_errors.iter().map(|(error, count)| {
+            format!(
+                "trading_bot_gateway_errors_total{{class=\"{}\"}} {}",
+                error.name(),
+                count
+            )
+        }));
+        lines.join("\n") + "\n"
+    }
+}
+
+/// The metrics of the bot's gateway requests.
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    snapshot_latency: Histogram {
+        buckets: [0; LATENCY_BUCKETS.len()],
+        sum: 0.0,
+        count: 0,
+    },
+    gateway_requests: 0,
+    gateway_errors: BTreeMap::new(),
+});
+
+/// Updates the metrics.
+fn update_metrics(update: impl FnOnce(&mut Metrics)) {
+    update(&mut METRICS.lock().unwrap_or_else(|e| e.into_inner()));
+}
+
+/// Records the round-trip time of a market data snapshot.
+///
+/// # Arguments
+///
+/// * `elapsed` - The time from sending the request to parsing the response.
+pub(crate) fn observe_snapshot_latency(elapsed: Duration) {
+    update_metrics(|metrics| metrics.snapshot_latency.observe(elapsed.as_secs_f64()));
+}
+
+/// Counts a request sent to the gateway.
+pub(crate) fn count_gateway_request() {
+    update_metrics(|metrics| metrics.gateway_requests += 1);
+}
+
+/// Counts a failed gateway request.
+///
+/// # Arguments
+///
+/// * `error` - The class of the failure.
+pub(crate) fn count_gateway_error(error: GatewayError) {
+    update_metrics(|metrics| *metrics.gateway_errors.entry(error).or_default() += 1);
+}
+
+/// Answers one scrape, with the metrics for `GET /metrics` and `404` for anything else.
+///
+/// # Arguments
+///
+/// * `stream` - The connection.
+fn handle_scrape(mut stream: TcpStream) {
+    let mut request_line: String = String::new();
+    if BufReader::new(&stream)
+        .read_line(&mut request_line)
+        .is_err()
+    {
+        return;
+    }
+    let mut parts = request_line.split_whitespace();
+    let response: String = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body: String = METRICS.lock().unwrap_or_else(|e| e.into_inner()).render();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// Serves the metrics at `GET /metrics` in the Prometheus text format on a background thread.
+///
+/// # Arguments
+///
+/// * `addr` - The `host:port` to listen on.
+///
+/// # Returns
+///
+/// A `Result` indicating the listener started, or an error if the address can't be bound.
+pub(crate) fn spawn_metrics_server(addr: &str) -> io::Result<()> {
+    let listener: TcpListener = TcpListener::bind(addr)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            handle_scrape(stream);
+        }
+    });
+    Ok(())
+}
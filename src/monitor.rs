@@ -0,0 +1,249 @@
+use std::{
+    error::Error,
+    sync::{Arc, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    events::{emit, Event},
+    ibkr::models::OrderBody,
+    logging::log_message,
+    structs::RequoteConfig,
+    underlying::UnderlyingSpec,
+};
+
+/// An order working at IBKR and when it is due to be canceled.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct WorkingOrder {
+    pub(crate) order_id: String,
+    pub(crate) expires_at: Instant,
+    /// The submitted order and the combo model price its limit was set from, if it is requoted.
+    pub(crate) pricing: Option<(OrderBody, f64)>,
+}
+
+/// What to do with a working order after its legs moved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RequoteAction {
+    Hold,
+    /// Move the limit to the given price.
+    Reprice(f64),
+    Cancel,
+}
+
+/// Decides how to requote a working combo order from the current model price of its legs.
+///
+/// Every order buys its combo, so a falling model price improves the edge and the limit follows it
+/// down, while a rising one eats the edge and the order is canceled before its TTL.
+///
+/// # Arguments
+///
+/// * `price` - The current limit price of the order.
+/// * `submitted_model_price` - The combo model price the limit was set from.
+/// * `model_price` - The current combo model price.
+/// * `config` - The requote thresholds.
+/// * `underlying` - The tick size of the underlying.
+///
+/// # Returns
+///
+/// A `RequoteAction` for the order.
+pub(crate) fn requote(
+    price: f64,
+    submitted_model_price: f64,
+    model_price: f64,
+    config: &RequoteConfig,
+    underlying: &UnderlyingSpec,
+) -> RequoteAction {
+    let drift: f64 = model_price - submitted_model_price;
+    if config
+        .cancel_threshold
+        .is_some_and(|threshold| drift >= threshold)
+    {
+        return RequoteAction::Cancel;
+    }
+    if config
+        .reprice_threshold
+        .is_some_and(|threshold| -drift >= threshold)
+    {
+        let new_price: f64 = underlying.round_to_tick(price + drift);
+        if new_price < price {
+            return RequoteAction::Reprice(new_price);
+        }
+    }
+    RequoteAction::Hold
+}
+
+/// The orders working at IBKR, shared between the trading loop and the order monitor thread.
+#[derive(Clone, Default)]
+pub(crate) struct WorkingOrders {
+    orders: Arc<Mutex<Vec<WorkingOrder>>>,
+}
+
+impl WorkingOrders {
+    /// Creates a new, empty `WorkingOrders`.
+    pub(crate) fn new() -> Self {
+        WorkingOrders::default()
+    }
+
+    /// Locks the orders, recovering them if another thread panicked while holding the lock.
+    fn lock(&self) -> MutexGuard<'_, Vec<WorkingOrder>> {
+        self.orders
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Adds a submitted order.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The IBKR order ID.
+    /// * `expires_at` - When the order is canceled if it is still working.
+    pub(crate) fn add(&self, order_id: &str, expires_at: Instant) {
+        self.lock().push(WorkingOrder {
+            order_id: order_id.to_string(),
+            expires_at,
+            pricing: None,
+        });
+    }
+
+    /// Adds a submitted order that is requoted when its legs move.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The IBKR order ID.
+    /// * `expires_at` - When the order is canceled if it is still working.
+    /// * `order` - The submitted order.
+    /// * `model_price` - The combo model price the limit was set from.
+    pub(crate) fn add_priced(
+        &self,
+        order_id: &str,
+        expires_at: Instant,
+        order: OrderBody,
+        model_price: f64,
+    ) {
+        self.lock().push(WorkingOrder {
+            order_id: order_id.to_string(),
+            expires_at,
+            pricing: Some((order, model_price)),
+        });
+    }
+
+    /// Returns the requoted orders with their pricing.
+    pub(crate) fn priced(&self) -> Vec<(String, OrderBody, f64)> {
+        self.lock()
+            .iter()
+            .filter_map(|order| {
+                let (body, model_price) = order.pricing.clone()?;
+                Some((order.order_id.clone(), body, model_price))
+            })
+            .collect()
+    }
+
+    /// Updates the limit price and model price of a requoted order.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The IBKR order ID.
+    /// * `price` - The new limit price.
+    /// * `model_price` - The combo model price the new limit was set from.
+    pub(crate) fn reprice(&self, order_id: &str, price: f64, model_price: f64) {
+        for order in self.lock().iter_mut() {
+            if order.order_id != order_id {
+                continue;
+            }
+            if let Some((body, submitted_model_price)) = &mut order.pricing {
+                body.price = price;
+                *submitted_model_price = model_price;
+            }
+        }
+    }
+
+    /// Removes an order so it can be canceled before its TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The IBKR order ID.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` indicating whether the order was still working, and not already taken by the
+    /// order monitor.
+    pub(crate) fn remove(&self, order_id: &str) -> bool {
+        let mut orders: MutexGuard<'_, Vec<WorkingOrder>> = self.lock();
+        let num_orders: usize = orders.len();
+        orders.retain(|order| order.order_id != order_id);
+        orders.len() < num_orders
+    }
+
+    /// Removes and returns the orders whose TTL elapsed.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<String>` with the IDs of the expired orders.
+    pub(crate) fn take_expired(&self, now: Instant) -> Vec<String> {
+        let mut orders: MutexGuard<'_, Vec<WorkingOrder>> = self.lock();
+        let (expired, working): (Vec<WorkingOrder>, Vec<WorkingOrder>) =
+            orders.drain(..).partition(|order| order.expires_at <= now);
+        *orders = working;
+        expired.into_iter().map(|order| order.order_id).collect()
+    }
+
+    /// Removes and returns every order.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<String>` with the IDs of all working orders.
+    pub(crate) fn take_all(&self) -> Vec<String> {
+        self.lock().drain(..).map(|order| order.order_id).collect()
+    }
+
+    /// Returns the IDs of the working orders.
+    pub(crate) fn ids(&self) -> Vec<String> {
+        self.lock()
+            .iter()
+            .map(|order| order.order_id.clone())
+            .collect()
+    }
+
+    /// Returns the number of working orders.
+    pub(crate) fn len(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+/// Starts a thread that cancels each working order once its TTL elapses, independently of the
+/// scans of the trading loop.
+///
+/// # Arguments
+///
+/// * `orders` - The working orders, shared with the trading loop.
+/// * `interval` - How often the orders are checked.
+/// * `cancel` - Cancels an order by ID, returning a message about the cancellation.
+///
+/// # Returns
+///
+/// A `JoinHandle` of the monitor thread, which runs for the lifetime of the process.
+pub(crate) fn spawn_order_monitor(
+    orders: WorkingOrders,
+    interval: Duration,
+    cancel: impl Fn(&str) -> Result<String, Box<dyn Error>> + Send + 'static,
+) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        for order_id in orders.take_expired(Instant::now()) {
+            match cancel(&order_id) {
+                Ok(message) => {
+                    emit(&Event::OrderCanceled {
+                        order_id: order_id.clone(),
+                    });
+                    log_message(format!("{} at its TTL.", message))
+                }
+                Err(e) => log_message(format!("{}.", e)),
+            }
+        }
+    })
+}
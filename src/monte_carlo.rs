@@ -0,0 +1,121 @@
+use rand::{seq::SliceRandom, Rng};
+
+use crate::backtest::BacktestTrade;
+
+/// Monte Carlo settings for resampling backtest fills.
+#[derive(Clone, Debug)]
+pub(crate) struct MonteCarloConfig {
+    pub(crate) simulations: usize,
+    pub(crate) miss_probability: f64,
+}
+
+/// Distribution of final P&L and maximum drawdown over the Monte Carlo simulations, in dollars.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MonteCarloReport {
+    pub(crate) simulations: usize,
+    pub(crate) mean_pnl: f64,
+    pub(crate) pnl_p5: f64,
+    pub(crate) pnl_p50: f64,
+    pub(crate) pnl_p95: f64,
+    pub(crate) drawdown_p50: f64,
+    pub(crate) drawdown_p95: f64,
+    pub(crate) worst_drawdown: f64,
+    pub(crate) loss_probability: f64,
+}
+
+/// Calculates the largest peak-to-trough decline of the cumulative P&L.
+///
+/// # Arguments
+///
+/// * `pnls` - The P&L of each trade in order.
+///
+/// # Returns
+///
+/// An `f64` with the maximum drawdown as a non-negative amount.
+pub(crate) fn max_drawdown(pnls: &[f64]) -> f64 {
+    let mut equity: f64 = 0.0;
+    let mut peak: f64 = 0.0;
+    let mut drawdown: f64 = 0.0;
+    for pnl in pnls {
+        equity += pnl;
+        peak = peak.max(equity);
+        drawdown = drawdown.max(peak - equity);
+    }
+    drawdown
+}
+
+/// Returns the value at the given percentile of sorted values, using the nearest rank.
+///
+/// # Arguments
+///
+/// * `sorted` - The values in ascending order.
+/// * `percentile` - The percentile between 0 and 1.
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted[((sorted.len() - 1) as f64 * percentile).round() as usize]
+}
+
+/// Simulates reordered and missing fills of a backtest to estimate the P&L and drawdown
+/// distribution.
+///
+/// Each simulation shuffles the filled trades and drops each one with the miss probability before
+/// accumulating the realized edge.
+///
+/// # Arguments
+///
+/// * `trades` - The trades of a backtest.
+/// * `config` - The number of simulations and the miss probability.
+/// * `rng` - The random number generator.
+///
+/// # Returns
+///
+/// A `MonteCarloReport` with the distribution statistics.
+pub(crate) fn simulate<R: Rng>(
+    trades: &[BacktestTrade],
+    config: &MonteCarloConfig,
+    rng: &mut R,
+) -> MonteCarloReport {
+    if config.simulations == 0 {
+        return MonteCarloReport::default();
+    }
+
+    let fills: Vec<f64> = trades
+        .iter()
+        .filter(|trade| trade.filled)
+        .map(|trade| trade.realized_edge)
+        .collect();
+
+    let mut pnls: Vec<f64> = Vec::with_capacity(config.simulations);
+    let mut drawdowns: Vec<f64> = Vec::with_capacity(config.simulations);
+    let mut sequence: Vec<f64> = Vec::with_capacity(fills.len());
+    for _ in 0..config.simulations {
+        sequence.clear();
+        sequence.extend(
+            fills
+                .iter()
+                .filter(|_| rng.gen::<f64>() >= config.miss_probability),
+        );
+        sequence.shuffle(rng);
+
+        pnls.push(sequence.iter().sum());
+        drawdowns.push(max_drawdown(&sequence));
+    }
+
+    pnls.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    drawdowns.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    MonteCarloReport {
+        simulations: config.simulations,
+        mean_pnl: pnls.iter().sum::<f64>() / config.simulations as f64,
+        pnl_p5: percentile(&pnls, 0.05),
+        pnl_p50: percentile(&pnls, 0.5),
+        pnl_p95: percentile(&pnls, 0.95),
+        drawdown_p50: percentile(&drawdowns, 0.5),
+        drawdown_p95: percentile(&drawdowns, 0.95),
+        worst_drawdown: drawdowns.last().copied().unwrap_or(0.0),
+        loss_probability: pnls.iter().filter(|pnl| **pnl < 0.0).count() as f64
+            / config.simulations as f64,
+    }
+}
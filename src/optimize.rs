@@ -0,0 +1,250 @@
+use rayon::prelude::*;
+use std::{error::Error, ops::Range};
+
+use crate::{
+    backtest::{run_backtest, BacktestParams, BacktestTrade, Snapshot},
+    dividends::DividendCalendar,
+    fill_model::FillModel,
+    rates::RateCurve,
+    scanner::{OptionType, Scanner},
+    structs::{ExpiryFilter, LegPricing, StrategyConfig},
+};
+
+/// One combination of the tunable scanner and order parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ParamSet {
+    pub(crate) discount_value: f64,
+    pub(crate) arb_val: f64,
+    pub(crate) wing_widths: Vec<f64>,
+    pub(crate) dte_window: Option<(i64, i64)>,
+}
+
+/// The values swept for each parameter.
+#[derive(Clone, Debug)]
+pub(crate) struct ParamGrid {
+    pub(crate) discount_values: Vec<f64>,
+    pub(crate) arb_values: Vec<f64>,
+    pub(crate) wing_widths: Vec<Vec<f64>>,
+    pub(crate) dte_windows: Vec<Option<(i64, i64)>>,
+}
+
+impl ParamGrid {
+    /// Expands the grid into every combination of its values.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<ParamSet>` with one entry per combination.
+    pub(crate) fn param_sets(&self) -> Vec<ParamSet> {
+        let mut param_sets: Vec<ParamSet> = Vec::new();
+        for &discount_value in &self.discount_values {
+            for &arb_val in &self.arb_values {
+                for wing_widths in &self.wing_widths {
+                    for &dte_window in &self.dte_windows {
+                        param_sets.push(ParamSet {
+                            discount_value,
+                            arb_val,
+                            wing_widths: wing_widths.clone(),
+                            dte_window,
+                        });
+                    }
+                }
+            }
+        }
+        param_sets
+    }
+}
+
+/// Settings shared by every backtest of an optimization run.
+pub(crate) struct OptimizeParams<'a> {
+    pub(crate) option_type: OptionType,
+    pub(crate) expiry_filter: ExpiryFilter,
+    pub(crate) num_orders: usize,
+    pub(crate) num_fills: i32,
+    pub(crate) fill_model: &'a FillModel,
+    pub(crate) seed: u64,
+}
+
+/// The best parameter set of one walk-forward window and how it held up out of sample.
+#[derive(Clone, Debug)]
+pub(crate) struct WalkForwardWindow {
+    pub(crate) train: Range<usize>,
+    pub(crate) test: Range<usize>,
+    pub(crate) best: ParamSet,
+    pub(crate) train_edge: f64,
+    pub(crate) test_edge: f64,
+}
+
+/// Splits snapshots into consecutive train and test ranges for walk-forward optimization.
+///
+/// The snapshots are cut into `folds + 1` equal chunks; each window trains on one chunk and tests
+/// on the chunk that follows it, with the last test chunk taking any remainder.
+///
+/// # Arguments
+///
+/// * `len` - The number of snapshots.
+/// * `folds` - The number of walk-forward windows.
+///
+/// # Returns
+///
+/// A `Vec<(Range<usize>, Range<usize>)>` of train and test ranges, empty if a chunk would hold
+/// fewer than two snapshots.
+pub(crate) fn walk_forward_splits(len: usize, folds: usize) -> Vec<(Range<usize>, Range<usize>)> {
+    let chunk: usize = len / (folds + 1);
+    if folds == 0 || chunk < 2 {
+        return Vec::new();
+    }
+
+    (0..folds)
+        .map(|fold| {
+            let test_end: usize = if fold + 1 == folds {
+                len
+            } else {
+                (fold + 2) * chunk
+            };
+            (
+                fold * chunk..(fold + 1) * chunk,
+                (fold + 1) * chunk..test_end,
+            )
+        })
+        .collect()
+}
+
+/// Builds a scanner for a parameter set, applying its wing widths and DTE window to every strategy.
+///
+/// # Arguments
+///
+/// * `param_set` - The parameters to scan with.
+/// * `expiry_filter` - The same-day expiration filter.
+///
+/// # Returns
+///
+/// A `Scanner` configured with the parameter set.
+fn build_scanner(param_set: &ParamSet, expiry_filter: &ExpiryFilter) -> Scanner {
+    let strategy_config: StrategyConfig = StrategyConfig {
+        wing_widths: param_set.wing_widths.clone(),
+        dte_window: param_set.dte_window,
+        max_spot_distance_percent: None,
+        straddle_spot: false,
+        max_net_delta: None,
+    };
+    Scanner {
+        arb_val: param_set.arb_val,
+        butterfly_config: strategy_config.clone(),
+        boxspread_config: strategy_config.clone(),
+        calendar_config: strategy_config,
+        expiry_filter: expiry_filter.clone(),
+        include_below_threshold: false,
+        near_miss_epsilon: None,
+        early_exercise: None,
+        dividends: DividendCalendar::new(),
+        rates: RateCurve::default(),
+        price_scale: 1.0,
+        leg_pricing: LegPricing::default(),
+        skipped_expiries: Vec::new(),
+        box_financing: None,
+        max_iv_deviation: None,
+    }
+}
+
+/// Backtests a parameter set over a range of snapshots.
+///
+/// # Arguments
+///
+/// * `snapshots` - The snapshots to replay.
+/// * `param_set` - The parameters to backtest.
+/// * `params` - The settings shared by the optimization run.
+///
+/// # Returns
+///
+/// A `Result` containing the total realized edge in dollars or an error.
+fn evaluate(
+    snapshots: &[Snapshot],
+    param_set: &ParamSet,
+    params: &OptimizeParams,
+) -> Result<f64, Box<dyn Error>> {
+    let scanner: Scanner = build_scanner(param_set, &params.expiry_filter);
+    let trades: Vec<BacktestTrade> = run_backtest(
+        snapshots,
+        &BacktestParams {
+            scanner: &scanner,
+            option_type: params.option_type,
+            num_orders: params.num_orders,
+            num_fills: params.num_fills,
+            discount_value: param_set.discount_value,
+            fill_model: params.fill_model,
+            seed: params.seed,
+        },
+    )?;
+    Ok(trades.iter().map(|trade| trade.realized_edge).sum())
+}
+
+/// Runs a walk-forward optimization of the parameter grid over recorded snapshots.
+///
+/// For each window, every parameter set is backtested on the train range in parallel and the one
+/// with the highest realized edge is then backtested on the following test range.
+///
+/// # Arguments
+///
+/// * `snapshots` - The recorded snapshots in time order.
+/// * `grid` - The parameter values to sweep.
+/// * `params` - The settings shared by every backtest.
+/// * `folds` - The number of walk-forward windows.
+///
+/// # Returns
+///
+/// A `Result` containing the best parameter set of each window or an error.
+pub(crate) fn optimize(
+    snapshots: &[Snapshot],
+    grid: &ParamGrid,
+    params: &OptimizeParams,
+    folds: usize,
+) -> Result<Vec<WalkForwardWindow>, Box<dyn Error>> {
+    let param_sets: Vec<ParamSet> = grid.param_sets();
+    if param_sets.is_empty() {
+        return Err("The parameter grid is empty".into());
+    }
+
+    let splits: Vec<(Range<usize>, Range<usize>)> = walk_forward_splits(snapshots.len(), folds);
+    if splits.is_empty() {
+        return Err(format!(
+            "{} snapshots are not enough for {} walk-forward windows",
+            snapshots.len(),
+            folds
+        )
+        .into());
+    }
+
+    let mut windows: Vec<WalkForwardWindow> = Vec::new();
+    for (train, test) in splits {
+        let train_edges: Vec<f64> = param_sets
+            .par_iter()
+            .map(|param_set| {
+                evaluate(&snapshots[train.clone()], param_set, params).map_err(|e| e.to_string())
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+
+        let (best_index, train_edge) =
+            train_edges
+                .iter()
+                .enumerate()
+                .fold((0, f64::MIN), |best, (index, &edge)| {
+                    if edge > best.1 {
+                        (index, edge)
+                    } else {
+                        best
+                    }
+                });
+        let best: ParamSet = param_sets[best_index].clone();
+        let test_edge: f64 = evaluate(&snapshots[test.clone()], &best, params)?;
+
+        windows.push(WalkForwardWindow {
+            train,
+            test,
+            best,
+            train_edge,
+            test_edge,
+        });
+    }
+
+    Ok(windows)
+}
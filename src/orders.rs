@@ -1,6 +1,88 @@
-use crate::structs::{Contender, OrderBody, RequestDataStruct};
-use ordered_float::OrderedFloat;
-use std::collections::HashMap;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    chain::OptionChain,
+    combo::{ComboBuilder, ComboSpread},
+    ibkr::models::{OrderBody, OrderReply, RequestDataStruct},
+    logging::log_message,
+    structs::{AlgoConfig, Contender, Contract},
+    underlying::UnderlyingSpec,
+};
+
+/// The number of shares each option contract is for.
+const MULTIPLIER: f64 = 100.0;
+
+/// Looks up the conid for a single leg of a contender.
+///
+/// # Arguments
+///
+/// * `chain` - A reference to the optional option chain.
+/// * `contract` - The leg to look up.
+///
+/// # Returns
+///
+/// A `Result` containing the conid of the leg, or an error if the chain doesn't have it.
+fn leg_conid<'a>(chain: Option<&'a OptionChain>, contract: &Contract) -> Result<&'a str, String> {
+    chain
+        .ok_or("the option chain is not set")?
+        .conid_for(contract.date, &contract.type_contract, contract.strike)
+        .map(String::as_str)
+        .ok_or(format!("no conid for leg {}", contract.describe()))
+}
+
+/// Computes the model (mid) price of each order a contender is split into, before the discount.
+///
+/// Calendars are a single order, butterflies and box spreads are two orders each, in the same
+/// order as `build_request_data` submits them.
+///
+/// # Arguments
+///
+/// * `contract` - A reference to a `Contender` that represents the contract.
+///
+/// # Returns
+///
+/// A `Vec<f64>` with the model price of each order.
+pub(crate) fn order_model_prices(contract: &Contender) -> Vec<f64> {
+    let legs: &Vec<Contract> = &contract.contracts;
+    match contract.type_spread.as_str() {
+        "Calendar" => vec![-contract.arb_val],
+        "Butterfly" => vec![
+            legs[0].mkt_price - legs[1].mkt_price,
+            legs[2].mkt_price - legs[1].mkt_price,
+        ],
+        "Boxspread" => vec![
+            legs[0].mkt_price - legs[3].mkt_price,
+            legs[2].mkt_price - legs[1].mkt_price,
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// Computes the worst-case loss of one combo of a contender held to expiry, at its limit prices.
+///
+/// Butterflies and calendars can lose at most their debit. Box spreads are sold for a credit and
+/// pay the wing width at expiry, so they lose the width beyond the credit.
+///
+/// # Arguments
+///
+/// * `contract` - A reference to a `Contender` that represents the contract.
+/// * `discount_value` - The discount applied to each order.
+///
+/// # Returns
+///
+/// An `f64` with the worst-case loss in dollars, `0.0` for a structure that can't lose.
+pub(crate) fn worst_case_loss(contract: &Contender, discount_value: f64) -> f64 {
+    let price: f64 = order_model_prices(contract)
+        .iter()
+        .map(|model_price| model_price + discount_value)
+        .sum();
+    let payout: f64 = if contract.type_spread == "Boxspread" {
+        contract.contracts[2].strike - contract.contracts[1].strike
+    } else {
+        0.0
+    };
+    (price + payout).max(0.0) * MULTIPLIER
+}
 
 /// Builds the order body for a calendar spread.
 ///
@@ -9,42 +91,55 @@ use std::collections::HashMap;
 /// * `contract` - A reference to a `Contender` that represents the contract.
 /// * `num_fills` - The number of fills required for the order.
 /// * `account_id` - An `Option<String>` containing the account ID.
-/// * `conids_map` - A reference to an optional mapping of conids.
+/// * `chain` - A reference to the optional option chain used for conid lookups.
 /// * `discount_value` - An optional discount value applied to the order.
+/// * `underlying` - The ticker and tick size of the underlying.
+/// * `algo` - The IBKR algo the order is routed with.
 ///
 /// # Returns
 ///
-/// An `OrderBody` representing the order for the calendar spread.
+/// A `Result` containing the `OrderBody` of the calendar spread, or an error if a leg's conid is
+/// missing or the legs don't make a valid combo.
 pub(crate) fn build_calendar_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    chain: Option<&OptionChain>,
     discount_value: Option<f64>,
-) -> OrderBody {
-    let order_val: f64 = contract.arb_val;
-    OrderBody {
+    underlying: &UnderlyingSpec,
+    algo: &AlgoConfig,
+) -> Result<OrderBody, String> {
+    let order_val: f64 = order_model_prices(contract)[0];
+    let (strategy, strategy_parameters) = algo.strategy().unzip();
+    Ok(OrderBody {
         acct_id: account_id.clone().unwrap(),
-        con_idex: format!(
-            "28812380;;;{}/-1,{}/1",
-            conids_map.as_ref().unwrap()[contract.contracts[0].date.as_str()]
-                [contract.contracts[0].type_contract.as_str()]
-                [(&contract.contracts[0].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[1].date.as_str()]
-                [contract.contracts[1].type_contract.as_str()]
-                [(&contract.contracts[1].strike).into()]
-        ),
+        con_idex: ComboBuilder::new(ComboSpread::Calendar)
+            .leg(
+                leg_conid(chain, &contract.contracts[0])?,
+                &contract.contracts[0],
+                -1,
+            )
+            .leg(
+                leg_conid(chain, &contract.contracts[1])?,
+                &contract.contracts[1],
+                1,
+            )
+            .build()?,
         order_type: "LMT".to_string(),
-        listing_exchange: "SMART".to_string(),
+        listing_exchange: underlying.listing_exchange.clone(),
         outside_rth: false,
-        price: -1.0 * (((order_val - discount_value.unwrap()) * 100.0).round() / 100.0),
+        price: underlying.round_to_tick(order_val + discount_value.unwrap()),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: underlying.ticker.clone(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
-        use_adaptive: false,
-    }
+        use_adaptive: algo.use_adaptive,
+        strategy,
+        strategy_parameters: strategy_parameters.unwrap_or_default(),
+        c_oid: contract.order_id(0),
+        price_offset: 0.0,
+    })
 }
 
 /// Builds the order body for a butterfly bull spread.
@@ -54,42 +149,55 @@ pub(crate) fn build_calendar_order(
 /// * `contract` - A reference to a `Contender` that represents the contract.
 /// * `num_fills` - The number of fills required for the order.
 /// * `account_id` - An `Option<String>` containing the account ID.
-/// * `conids_map` - A reference to an optional mapping of conids.
+/// * `chain` - A reference to the optional option chain used for conid lookups.
 /// * `discount_value` - An optional discount value applied to the order.
+/// * `underlying` - The ticker and tick size of the underlying.
+/// * `algo` - The IBKR algo the order is routed with.
 ///
 /// # Returns
 ///
-/// An `OrderBody` representing the order for the butterfly bull spread.
+/// A `Result` containing the `OrderBody` of the butterfly bull spread, or an error if a leg's conid is
+/// missing or the legs don't make a valid combo.
 pub(crate) fn build_butterfly_bull_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    chain: Option<&OptionChain>,
     discount_value: Option<f64>,
-) -> OrderBody {
-    let order_val: f64 = contract.contracts[0].mkt_price - contract.contracts[1].mkt_price;
-    OrderBody {
+    underlying: &UnderlyingSpec,
+    algo: &AlgoConfig,
+) -> Result<OrderBody, String> {
+    let order_val: f64 = order_model_prices(contract)[0];
+    let (strategy, strategy_parameters) = algo.strategy().unzip();
+    Ok(OrderBody {
         acct_id: account_id.clone().unwrap(),
-        con_idex: format!(
-            "28812380;;;{}/-1,{}/1",
-            conids_map.as_ref().unwrap()[contract.contracts[1].date.as_str()]
-                [contract.contracts[1].type_contract.as_str()]
-                [(&contract.contracts[1].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[0].date.as_str()]
-                [contract.contracts[0].type_contract.as_str()]
-                [(&contract.contracts[0].strike).into()]
-        ),
+        con_idex: ComboBuilder::new(ComboSpread::Vertical)
+            .leg(
+                leg_conid(chain, &contract.contracts[1])?,
+                &contract.contracts[1],
+                -1,
+            )
+            .leg(
+                leg_conid(chain, &contract.contracts[0])?,
+                &contract.contracts[0],
+                1,
+            )
+            .build()?,
         order_type: "LMT".to_string(),
-        listing_exchange: "SMART".to_string(),
+        listing_exchange: underlying.listing_exchange.clone(),
         outside_rth: false,
-        price: ((order_val + discount_value.unwrap()) * 100.0).round() / 100.0,
+        price: underlying.round_to_tick(order_val + discount_value.unwrap()),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: underlying.ticker.clone(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
-        use_adaptive: false,
-    }
+        use_adaptive: algo.use_adaptive,
+        strategy,
+        strategy_parameters: strategy_parameters.unwrap_or_default(),
+        c_oid: contract.order_id(0),
+        price_offset: 0.0,
+    })
 }
 
 /// Builds the order body for a butterfly bear spread.
@@ -99,42 +207,55 @@ pub(crate) fn build_butterfly_bull_order(
 /// * `contract` - A reference to a `Contender` that represents the contract.
 /// * `num_fills` - The number of fills required for the order.
 /// * `account_id` - An `Option<String>` containing the account ID.
-/// * `conids_map` - A reference to an optional mapping of conids.
+/// * `chain` - A reference to the optional option chain used for conid lookups.
 /// * `discount_value` - An optional discount value applied to the order.
+/// * `underlying` - The ticker and tick size of the underlying.
+/// * `algo` - The IBKR algo the order is routed with.
 ///
 /// # Returns
 ///
-/// An `OrderBody` representing the order for the butterfly bear spread.
+/// A `Result` containing the `OrderBody` of the butterfly bear spread, or an error if a leg's conid is
+/// missing or the legs don't make a valid combo.
 pub(crate) fn build_butterfly_bear_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    chain: Option<&OptionChain>,
     discount_value: Option<f64>,
-) -> OrderBody {
-    let order_val: f64 = contract.contracts[2].mkt_price - contract.contracts[1].mkt_price;
-    OrderBody {
+    underlying: &UnderlyingSpec,
+    algo: &AlgoConfig,
+) -> Result<OrderBody, String> {
+    let order_val: f64 = order_model_prices(contract)[1];
+    let (strategy, strategy_parameters) = algo.strategy().unzip();
+    Ok(OrderBody {
         acct_id: account_id.clone().unwrap(),
-        con_idex: format!(
-            "28812380;;;{}/-1,{}/1",
-            conids_map.as_ref().unwrap()[contract.contracts[1].date.as_str()]
-                [contract.contracts[1].type_contract.as_str()]
-                [(&contract.contracts[1].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[2].date.as_str()]
-                [contract.contracts[2].type_contract.as_str()]
-                [(&contract.contracts[2].strike).into()]
-        ),
+        con_idex: ComboBuilder::new(ComboSpread::Vertical)
+            .leg(
+                leg_conid(chain, &contract.contracts[1])?,
+                &contract.contracts[1],
+                -1,
+            )
+            .leg(
+                leg_conid(chain, &contract.contracts[2])?,
+                &contract.contracts[2],
+                1,
+            )
+            .build()?,
         order_type: "LMT".to_string(),
-        listing_exchange: "SMART".to_string(),
+        listing_exchange: underlying.listing_exchange.clone(),
         outside_rth: false,
-        price: (((order_val + discount_value.unwrap()) * 100.0).round() / 100.0),
+        price: underlying.round_to_tick(order_val + discount_value.unwrap()),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: underlying.ticker.clone(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
-        use_adaptive: false,
-    }
+        use_adaptive: algo.use_adaptive,
+        strategy,
+        strategy_parameters: strategy_parameters.unwrap_or_default(),
+        c_oid: contract.order_id(1),
+        price_offset: 0.0,
+    })
 }
 
 /// Builds the order body for a boxspread put spread.
@@ -144,42 +265,55 @@ pub(crate) fn build_butterfly_bear_order(
 /// * `contract` - A reference to a `Contender` that represents the contract.
 /// * `num_fills` - The number of fills required for the order.
 /// * `account_id` - An `Option<String>` containing the account ID.
-/// * `conids_map` - A reference to an optional mapping of conids.
+/// * `chain` - A reference to the optional option chain used for conid lookups.
 /// * `discount_value` - An optional discount value applied to the order.
+/// * `underlying` - The ticker and tick size of the underlying.
+/// * `algo` - The IBKR algo the order is routed with.
 ///
 /// # Returns
 ///
-/// An `OrderBody` representing the order for the boxspread put spread.
+/// A `Result` containing the `OrderBody` of the boxspread put spread, or an error if a leg's conid is
+/// missing or the legs don't make a valid combo.
 pub(crate) fn build_boxspread_put_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    chain: Option<&OptionChain>,
     discount_value: Option<f64>,
-) -> OrderBody {
-    let order_val: f64 = contract.contracts[0].mkt_price - contract.contracts[3].mkt_price;
-    OrderBody {
+    underlying: &UnderlyingSpec,
+    algo: &AlgoConfig,
+) -> Result<OrderBody, String> {
+    let order_val: f64 = order_model_prices(contract)[0];
+    let (strategy, strategy_parameters) = algo.strategy().unzip();
+    Ok(OrderBody {
         acct_id: account_id.clone().unwrap(),
-        con_idex: format!(
-            "28812380;;;{}/-1,{}/1",
-            conids_map.as_ref().unwrap()[contract.contracts[3].date.as_str()]
-                [contract.contracts[3].type_contract.as_str()]
-                [(&contract.contracts[3].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[0].date.as_str()]
-                [contract.contracts[0].type_contract.as_str()]
-                [(&contract.contracts[0].strike).into()]
-        ),
+        con_idex: ComboBuilder::new(ComboSpread::Vertical)
+            .leg(
+                leg_conid(chain, &contract.contracts[3])?,
+                &contract.contracts[3],
+                -1,
+            )
+            .leg(
+                leg_conid(chain, &contract.contracts[0])?,
+                &contract.contracts[0],
+                1,
+            )
+            .build()?,
         order_type: "LMT".to_string(),
-        listing_exchange: "SMART".to_string(),
+        listing_exchange: underlying.listing_exchange.clone(),
         outside_rth: false,
-        price: (((order_val + discount_value.unwrap()) * 100.0).round() / 100.0),
+        price: underlying.round_to_tick(order_val + discount_value.unwrap()),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: underlying.ticker.clone(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
-        use_adaptive: false,
-    }
+        use_adaptive: algo.use_adaptive,
+        strategy,
+        strategy_parameters: strategy_parameters.unwrap_or_default(),
+        c_oid: contract.order_id(0),
+        price_offset: 0.0,
+    })
 }
 
 /// Builds the order body for a boxspread call spread.
@@ -189,44 +323,311 @@ pub(crate) fn build_boxspread_put_order(
 /// * `contract` - A reference to a `Contender` that represents the contract.
 /// * `num_fills` - The number of fills required for the order.
 /// * `account_id` - An `Option<String>` containing the account ID.
-/// * `conids_map` - A reference to an optional mapping of conids.
+/// * `chain` - A reference to the optional option chain used for conid lookups.
 /// * `discount_value` - An optional discount value applied to the order.
+/// * `underlying` - The ticker and tick size of the underlying.
+/// * `algo` - The IBKR algo the order is routed with.
 ///
 /// # Returns
 ///
-/// An `OrderBody` representing the order for the boxspread call spread.
+/// A `Result` containing the `OrderBody` of the boxspread call spread, or an error if a leg's conid is
+/// missing or the legs don't make a valid combo.
 pub(crate) fn build_boxspread_call_order(
     contract: &Contender,
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    chain: Option<&OptionChain>,
     discount_value: Option<f64>,
-) -> OrderBody {
-    let order_val: f64 = contract.contracts[2].mkt_price - contract.contracts[1].mkt_price;
-    OrderBody {
+    underlying: &UnderlyingSpec,
+    algo: &AlgoConfig,
+) -> Result<OrderBody, String> {
+    let order_val: f64 = order_model_prices(contract)[1];
+    let (strategy, strategy_parameters) = algo.strategy().unzip();
+    Ok(OrderBody {
         acct_id: account_id.clone().unwrap(),
-        con_idex: format!(
-            "28812380;;;{}/1,{}/-1",
-            conids_map.as_ref().unwrap()[contract.contracts[2].date.as_str()]
-                [contract.contracts[2].type_contract.as_str()]
-                [(&contract.contracts[2].strike).into()],
-            conids_map.as_ref().unwrap()[contract.contracts[1].date.as_str()]
-                [contract.contracts[1].type_contract.as_str()]
-                [(&contract.contracts[1].strike).into()]
-        ),
+        con_idex: ComboBuilder::new(ComboSpread::Vertical)
+            .leg(
+                leg_conid(chain, &contract.contracts[2])?,
+                &contract.contracts[2],
+                1,
+            )
+            .leg(
+                leg_conid(chain, &contract.contracts[1])?,
+                &contract.contracts[1],
+                -1,
+            )
+            .build()?,
         order_type: "LMT".to_string(),
-        listing_exchange: "SMART".to_string(),
+        listing_exchange: underlying.listing_exchange.clone(),
         outside_rth: false,
-        price: (((order_val + discount_value.unwrap()) * 100.0).round() / 100.0),
+        price: underlying.round_to_tick(order_val + discount_value.unwrap()),
         side: "BUY".to_string(),
-        ticker: "SPX".to_string(),
+        ticker: underlying.ticker.clone(),
         tif: "DAY".to_string(),
         referrer: "NO_REFERRER_PROVIDED".to_string(),
         quantity: num_fills,
-        use_adaptive: false,
+        use_adaptive: algo.use_adaptive,
+        strategy,
+        strategy_parameters: strategy_parameters.unwrap_or_default(),
+        c_oid: contract.order_id(1),
+        price_offset: 0.0,
+    })
+}
+
+/// How order quantity is matched to the liquidity displayed on a contender's legs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LiquiditySizing {
+    /// Submit the full quantity regardless of the displayed size.
+    Off,
+    /// Cap the quantity at the displayed size.
+    Cap,
+    /// Split the quantity into child orders no larger than the displayed size.
+    Split,
+}
+
+impl LiquiditySizing {
+    /// Converts a string (`"off"`, `"cap"` or `"split"`) to a `LiquiditySizing`.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - A string representing the sizing mode.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Self>` representing the converted sizing mode.
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "off" => Some(LiquiditySizing::Off),
+            "cap" => Some(LiquiditySizing::Cap),
+            "split" => Some(LiquiditySizing::Split),
+            _ => None,
+        }
+    }
+
+    /// Splits the intended number of fills into the quantities of the orders to submit.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_fills` - The intended number of fills.
+    /// * `displayed_size` - The number of combos the displayed liquidity supports, if known.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<i32>` with the quantity of each order, at least one order of one combo.
+    pub(crate) fn quantities(self, num_fills: i32, displayed_size: Option<f64>) -> Vec<i32> {
+        let Some(displayed_size) = displayed_size else {
+            return vec![num_fills];
+        };
+        let size: i32 = (displayed_size.floor() as i32).max(1);
+        match self {
+            LiquiditySizing::Off => vec![num_fills],
+            LiquiditySizing::Cap => vec![num_fills.min(size)],
+            LiquiditySizing::Split => {
+                let mut quantities: Vec<i32> = Vec::new();
+                let mut remaining: i32 = num_fills;
+                while remaining > 0 {
+                    quantities.push(remaining.min(size));
+                    remaining -= size;
+                }
+                quantities
+            }
+        }
+    }
+}
+
+/// A price ladder that slices an order's quantity across increasingly aggressive prices, so part
+/// of it can fill on thin combos that won't trade at the full edge.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct OrderLadder {
+    /// The lots and the price concession of each rung, least aggressive first.
+    rungs: Vec<(i32, f64)>,
+}
+
+impl OrderLadder {
+    /// Creates a ladder from its rungs.
+    ///
+    /// # Arguments
+    ///
+    /// * `rungs` - Pairs of lots and the amount added to the limit price, e.g. `[(1, 0.0), (2, 0.05)]`.
+    pub(crate) fn new(rungs: Vec<(i32, f64)>) -> Self {
+        OrderLadder { rungs }
+    }
+
+    /// Returns whether the ladder has no rungs, i.e. orders go out at a single price.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rungs.is_empty()
+    }
+
+    /// Slices a quantity across the rungs in order. A quantity smaller than the ladder fills the
+    /// first rungs only, and any quantity beyond the ladder goes on the last rung.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantity` - The number of combos to slice.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<(i32, f64)>` with the quantity and price concession of each slice.
+    pub(crate) fn slices(&self, quantity: i32) -> Vec<(i32, f64)> {
+        let mut slices: Vec<(i32, f64)> = Vec::new();
+        let mut remaining: i32 = quantity;
+        for &(lots, price_offset) in &self.rungs {
+            if remaining <= 0 {
+                break;
+            }
+            slices.push((lots.min(remaining), price_offset));
+            remaining -= lots;
+        }
+        if let Some(last) = slices.last_mut() {
+            last.0 += remaining.max(0);
+        }
+        slices
+    }
+}
+
+/// How the quantity of each contender is sized and priced across its orders.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OrderSizing {
+    pub(crate) liquidity: LiquiditySizing,
+    pub(crate) ladder: OrderLadder,
+}
+
+impl OrderSizing {
+    /// Slices the intended number of fills into orders, first matching the displayed liquidity
+    /// and then laddering the sized quantity if a ladder is set. The ladder replaces split child
+    /// orders, since it already slices the quantity.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_fills` - The intended number of fills.
+    /// * `displayed_size` - The number of combos the displayed liquidity supports, if known.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<(i32, f64)>` with the quantity and price concession of each order.
+    pub(crate) fn slices(&self, num_fills: i32, displayed_size: Option<f64>) -> Vec<(i32, f64)> {
+        let quantities: Vec<i32> = self.liquidity.quantities(num_fills, displayed_size);
+        if self.ladder.is_empty() {
+            return quantities
+                .into_iter()
+                .map(|quantity| (quantity, 0.0))
+                .collect();
+        }
+        self.ladder.slices(quantities.iter().sum())
     }
 }
 
+/// Prices a combo order from the combo's own NBBO instead of its summed leg quotes, keeping the
+/// leg-based price when it is lower. Every order buys its combo, so a lower price only improves
+/// the edge the scanner qualified.
+///
+/// # Arguments
+///
+/// * `order` - The order priced from its leg quotes.
+/// * `bid` - The combo bid.
+/// * `ask` - The combo ask.
+/// * `discount_value` - The discount applied to orders.
+/// * `underlying` - The tick size of the underlying.
+///
+/// # Returns
+///
+/// An `f64` with the limit price on a valid tick.
+pub(crate) fn combo_limit_price(
+    order: &OrderBody,
+    bid: f64,
+    ask: f64,
+    discount_value: f64,
+    underlying: &UnderlyingSpec,
+) -> f64 {
+    let combo_price: f64 =
+        underlying.round_to_tick((bid + ask) / 2.0 + discount_value + order.price_offset);
+    order.price.min(combo_price)
+}
+
+/// An order IBKR accepted.
+#[derive(Clone, Copy)]
+pub(crate) struct AcceptedOrder<'a> {
+    pub(crate) order_id: &'a str,
+    /// The submitted order and the contender it was built from, if the reply could be matched.
+    pub(crate) submitted: Option<(&'a OrderBody, &'a Contender)>,
+}
+
+/// Matches the replies to an order request with the submitted orders and their contenders.
+///
+/// Replies carry the cOID when IBKR echoes it, and are in request order otherwise.
+///
+/// # Arguments
+///
+/// * `contenders` - The contenders whose orders were submitted.
+/// * `orders` - The submitted orders.
+/// * `replies` - The replies to the order request.
+///
+/// # Returns
+///
+/// A tuple `(Vec<AcceptedOrder>, usize)` with the replies that carry an order id, and how many
+/// replies didn't.
+pub(crate) fn match_replies<'a>(
+    contenders: &'a [Contender],
+    orders: &'a [OrderBody],
+    replies: &'a [OrderReply],
+) -> (Vec<AcceptedOrder<'a>>, usize) {
+    let mut accepted: Vec<AcceptedOrder<'a>> = Vec::new();
+    let mut missing: usize = 0;
+    for (index, reply) in replies.iter().enumerate() {
+        let Some(order_id) = reply.order_id.as_deref() else {
+            missing += 1;
+            continue;
+        };
+        let order: Option<&OrderBody> = reply
+            .local_order_id
+            .as_deref()
+            .and_then(|c_oid| orders.iter().find(|order| order.c_oid == c_oid))
+            .or_else(|| orders.get(index));
+        accepted.push(AcceptedOrder {
+            order_id,
+            submitted: order.and_then(|order| {
+                let contender: &Contender = contenders
+                    .iter()
+                    .find(|contender| contender.owns_order(&order.c_oid))?;
+                Some((order, contender))
+            }),
+        });
+    }
+    (accepted, missing)
+}
+
+/// Drops contenders whose orders trade a leg in the opposite direction of an order of a contender
+/// kept before them, so the bot never crosses its own orders, e.g. a butterfly selling a strike a
+/// box spread buys.
+///
+/// # Arguments
+///
+/// * `contender_orders` - The orders of each contender, best ranked first.
+///
+/// # Returns
+///
+/// A `Vec<Vec<OrderBody>>` with the orders of the contenders kept, in the same order.
+pub(crate) fn drop_self_matches(contender_orders: Vec<Vec<OrderBody>>) -> Vec<Vec<OrderBody>> {
+    let mut directions: FxHashMap<String, i32> = FxHashMap::default();
+    let mut kept: Vec<Vec<OrderBody>> = Vec::new();
+    for orders in contender_orders {
+        let legs: Vec<(String, i32)> = orders.iter().flat_map(OrderBody::leg_ratios).collect();
+        let conflicts: bool = legs.iter().any(|(conid, ratio)| {
+            directions
+                .get(conid)
+                .is_some_and(|direction| direction.signum() == -ratio.signum())
+        });
+        if conflicts {
+            continue;
+        }
+        for (conid, ratio) in legs {
+            directions.entry(conid).or_insert(ratio.signum());
+        }
+        kept.push(orders);
+    }
+    kept
+}
+
 /// Builds the request data structure that includes the orders for each contender contract.
 ///
 /// # Arguments
@@ -234,67 +635,128 @@ pub(crate) fn build_boxspread_call_order(
 /// * `contender_contracts` - A vector of `Contender` contracts.
 /// * `num_fills` - The number of fills for each order.
 /// * `account_id` - An `Option<String>` containing the account ID.
-/// * `conids_map` - A reference to an optional mapping of conids.
+/// * `chain` - A reference to the optional option chain used for conid lookups.
 /// * `discount_value` - An optional discount value applied to the orders.
+/// * `underlying` - The ticker and tick size of the underlying.
+/// * `sizing` - How each contender's quantity is matched to its displayed size and laddered.
+/// * `algo` - The IBKR algo the orders are routed with.
 ///
 /// # Returns
 ///
-/// A `RequestDataStruct` containing the order bodies for each contender contract.
+/// A `RequestDataStruct` containing the order bodies for each contender contract. Child orders
+/// split or laddered from the same order share its cOID with a `.1`, `.2`, ... suffix. Contenders
+/// that would trade against the orders of a better-ranked contender are left out.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn build_request_data(
-    contender_contracts: &Vec<Contender>,
+    contender_contracts: &[Contender],
     num_fills: i32,
     account_id: &Option<String>,
-    conids_map: &Option<HashMap<String, HashMap<String, HashMap<OrderedFloat<f64>, String>>>>,
+    chain: Option<&OptionChain>,
     discount_value: Option<f64>,
+    underlying: &UnderlyingSpec,
+    sizing: &OrderSizing,
+    algo: &AlgoConfig,
 ) -> RequestDataStruct {
-    let mut request_data: RequestDataStruct = RequestDataStruct { orders: Vec::new() };
+    let mut contender_orders: Vec<Vec<OrderBody>> = Vec::new();
 
     for contract in contender_contracts {
-        match contract.type_spread.as_str() {
-            "Calendar" => {
-                request_data.orders.push(build_calendar_order(
+        let orders: Result<Vec<OrderBody>, String> = match contract.type_spread.as_str() {
+            "Calendar" => build_calendar_order(
+                contract,
+                num_fills,
+                account_id,
+                chain,
+                discount_value,
+                underlying,
+                algo,
+            )
+            .map(|order| vec![order]),
+            "Butterfly" => build_butterfly_bull_order(
+                contract,
+                num_fills,
+                account_id,
+                chain,
+                discount_value,
+                underlying,
+                algo,
+            )
+            .and_then(|bull| {
+                let bear: OrderBody = build_butterfly_bear_order(
                     contract,
                     num_fills,
                     account_id,
-                    conids_map,
+                    chain,
                     discount_value,
-                ));
-            }
-            "Butterfly" => {
-                request_data.orders.push(build_butterfly_bull_order(
-                    contract,
-                    num_fills,
-                    account_id,
-                    conids_map,
-                    discount_value,
-                ));
-                request_data.orders.push(build_butterfly_bear_order(
+                    underlying,
+                    algo,
+                )?;
+                Ok(vec![bull, bear])
+            }),
+            "Boxspread" => build_boxspread_put_order(
+                contract,
+                num_fills,
+                account_id,
+                chain,
+                discount_value,
+                underlying,
+                algo,
+            )
+            .and_then(|put| {
+                let call: OrderBody = build_boxspread_call_order(
                     contract,
                     num_fills,
                     account_id,
-                    conids_map,
+                    chain,
                     discount_value,
+                    underlying,
+                    algo,
+                )?;
+                Ok(vec![put, call])
+            }),
+            _ => Ok(Vec::new()),
+        };
+        let orders: Vec<OrderBody> = match orders {
+            Ok(orders) => orders,
+            Err(e) => {
+                log_message(format!(
+                    "Skipping {} contender {}: {}.",
+                    contract.type_spread,
+                    contract.order_id(0),
+                    e
                 ));
+                continue;
             }
-            "Boxspread" => {
-                request_data.orders.push(build_boxspread_put_order(
-                    contract,
-                    num_fills,
-                    account_id,
-                    conids_map,
-                    discount_value,
-                ));
-                request_data.orders.push(build_boxspread_call_order(
-                    contract,
-                    num_fills,
-                    account_id,
-                    conids_map,
-                    discount_value,
-                ));
+        };
+
+        let slices: Vec<(i32, f64)> = sizing.slices(num_fills, contract.displayed_size());
+        let mut child_orders: Vec<OrderBody> = Vec::new();
+        for order in orders {
+            for (child, (quantity, price_offset)) in slices.iter().enumerate() {
+                let mut child_order: OrderBody = order.clone();
+                child_order.quantity = *quantity;
+                if *price_offset != 0.0 {
+                    child_order.price = underlying.round_to_tick(order.price + price_offset);
+                    child_order.price_offset = *price_offset;
+                }
+                if child > 0 {
+                    child_order.c_oid = format!("{}.{}", order.c_oid, child);
+                }
+                child_orders.push(child_order);
             }
-            _ => {}
         }
+        contender_orders.push(child_orders);
     }
 
-    request_data
+    let num_contenders: usize = contender_orders.len();
+    let contender_orders: Vec<Vec<OrderBody>> = drop_self_matches(contender_orders);
+    if contender_orders.len() < num_contenders {
+        log_message(format!(
+            "Dropped {} contenders trading a leg against a better-ranked contender.",
+            num_contenders - contender_orders.len()
+        ));
+    }
+
+    RequestDataStruct {
+        orders: contender_orders.into_iter().flatten().collect(),
+    }
 }
@@ -0,0 +1,148 @@
+use rand::{rngs::StdRng, SeedableRng};
+use std::error::Error;
+
+use crate::{
+    api::Broker,
+    backtest::reprice_orders,
+    chain::{OptionChain, Quotes},
+    fill_model::FillModel,
+    ibkr::models::OrderBody,
+    structs::Contender,
+};
+
+/// An order of a paper run, by customer order id (cOID), limit price and quantity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaperOrder {
+    pub order_ref: String,
+    pub limit_price: f64,
+    pub quantity: f64,
+}
+
+/// A simulated execution of a paper order at its limit price.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaperFill {
+    pub execution_id: String,
+    pub order_ref: String,
+    pub size: f64,
+    pub fill_price: f64,
+}
+
+/// Local fill simulator for paper runs.
+///
+/// Hypothetical orders work until the next scan, where the fill model decides whether the
+/// contender would have filled against the new quotes, the same way the backtester fills a
+/// snapshot against the next one. A contender fills all of its orders or none.
+pub struct PaperBroker {
+    fill_model: FillModel,
+    rng: StdRng,
+    pending: Vec<(Contender, Vec<PaperOrder>)>,
+    executions: u64,
+}
+
+impl PaperBroker {
+    /// Creates a broker with no working orders.
+    ///
+    /// # Arguments
+    ///
+    /// * `fill_model` - The fill model deciding whether orders fill.
+    /// * `seed` - The seed for probabilistic fills.
+    pub fn new(fill_model: FillModel, seed: u64) -> Self {
+        PaperBroker {
+            fill_model,
+            rng: StdRng::seed_from_u64(seed),
+            pending: Vec::new(),
+            executions: 0,
+        }
+    }
+
+    /// Places the orders of a contender, to be settled on the next scan.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender` - The contender the orders were built from.
+    /// * `orders` - Its orders.
+    pub fn submit(&mut self, contender: &Contender, orders: Vec<PaperOrder>) {
+        self.pending.push((contender.clone(), orders));
+    }
+
+    /// Settles every working order against a new scan's quotes. Orders that don't fill are
+    /// canceled, like live orders at the end of their TTL.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The option chain of the new scan.
+    /// * `contracts_map` - The quotes of the new scan.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<PaperFill>` with an execution for each order of the contenders that filled.
+    pub fn settle(&mut self, chain: &OptionChain, contracts_map: &Quotes) -> Vec<PaperFill> {
+        let mut fills: Vec<PaperFill> = Vec::new();
+        for (contender, orders) in std::mem::take(&mut self.pending) {
+            let limit_prices: Vec<f64> = orders.iter().map(|order| order.limit_price).collect();
+            // Sliced or laddered child orders share the repriced mid of the order they came from.
+            let next_prices: Option<Vec<f64>> = reprice_orders(&contender, chain, contracts_map)
+                .and_then(|model_prices| {
+                    let per_order: usize = orders.len() / model_prices.len().max(1);
+                    (per_order * model_prices.len() == orders.len()).then(|| {
+                        model_prices
+                            .iter()
+                            .flat_map(|price| std::iter::repeat_n(*price, per_order))
+                            .collect()
+                    })
+                });
+            if !self.fill_model.simulate_fill(
+                &limit_prices,
+                next_prices.as_deref(),
+                contender.fill_probability,
+                &mut self.rng,
+            ) {
+                continue;
+            }
+
+            for order in orders {
+                self.executions += 1;
+                fills.push(PaperFill {
+                    execution_id: format!("paper-{}", self.executions),
+                    order_ref: order.order_ref,
+                    size: order.quantity,
+                    fill_price: order.limit_price,
+                });
+            }
+        }
+        fills
+    }
+}
+
+impl Broker for PaperBroker {
+    /// Places the orders of a contender at their limit prices, to be settled on the next scan.
+    fn place_orders(
+        &mut self,
+        contender: &Contender,
+        orders: &[OrderBody],
+    ) -> Result<(), Box<dyn Error>> {
+        self.submit(
+            contender,
+            orders
+                .iter()
+                .map(|order| PaperOrder {
+                    order_ref: order.c_oid.clone(),
+                    limit_price: order.price,
+                    quantity: order.quantity as f64,
+                })
+                .collect(),
+        );
+        Ok(())
+    }
+
+    /// Cancels the orders waiting for the next scan.
+    fn cancel_orders(&mut self) -> Result<(), Box<dyn Error>> {
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Returns the number of orders waiting for the next scan.
+    fn working_order_count(&self) -> usize {
+        self.pending.iter().map(|(_, orders)| orders.len()).sum()
+    }
+}
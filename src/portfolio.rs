@@ -0,0 +1,101 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// Cached portfolio value, shared with the background refresh thread.
+#[derive(Default)]
+struct CachedValue {
+    value: Option<f64>,
+    updated_at: Option<Instant>,
+    refreshing: bool,
+}
+
+/// Portfolio value cache with a TTL and change detection.
+#[derive(Clone)]
+pub(crate) struct PortfolioCache {
+    ttl: Duration,
+    change_percent: f64,
+    state: Arc<Mutex<CachedValue>>,
+}
+
+impl PortfolioCache {
+    /// Creates a new, empty `PortfolioCache`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a cached value is considered fresh.
+    /// * `change_percent` - The change, in percent, that is reported by `update`.
+    pub(crate) fn new(ttl: Duration, change_percent: f64) -> Self {
+        PortfolioCache {
+            ttl,
+            change_percent,
+            state: Arc::new(Mutex::new(CachedValue::default())),
+        }
+    }
+
+    /// Returns the cached value and whether it is still fresh.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<(f64, bool)>` with the cached value and its freshness, or `None` if nothing is
+    /// cached yet.
+    pub(crate) fn get(&self, now: Instant) -> Option<(f64, bool)> {
+        let state: MutexGuard<'_, CachedValue> = self.state.lock().unwrap();
+        let value: f64 = state.value?;
+        let fresh: bool = state
+            .updated_at
+            .is_some_and(|updated_at| now.duration_since(updated_at) < self.ttl);
+        Some((value, fresh))
+    }
+
+    /// Marks a refresh as in progress.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the caller should refresh, `false` if a refresh is already running.
+    pub(crate) fn begin_refresh(&self) -> bool {
+        let mut state: MutexGuard<'_, CachedValue> = self.state.lock().unwrap();
+        if state.refreshing {
+            return false;
+        }
+        state.refreshing = true;
+        true
+    }
+
+    /// Ends a refresh that failed, leaving the cached value in place.
+    pub(crate) fn cancel_refresh(&self) {
+        self.state.lock().unwrap().refreshing = false;
+    }
+
+    /// Stores a new portfolio value and ends any refresh in progress.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The new portfolio value.
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<(f64, f64)>` with the previous value and the percent change when the value moved
+    /// by more than the configured percent, e.g. after a fill or withdrawal.
+    pub(crate) fn update(&self, value: f64, now: Instant) -> Option<(f64, f64)> {
+        let mut state: MutexGuard<'_, CachedValue> = self.state.lock().unwrap();
+        let previous: Option<f64> = state.value;
+        state.value = Some(value);
+        state.updated_at = Some(now);
+        state.refreshing = false;
+
+        let previous: f64 = previous.filter(|previous| *previous != 0.0)?;
+        let change: f64 = (value - previous) / previous * 100.0;
+        if change.abs() > self.change_percent {
+            Some((previous, change))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use chrono::NaiveDate;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{error::Error, fs};
+
+use crate::{
+    chain::ExpiryDate, combo::USD_CONID, journal::JournalLeg, underlying::parse_osi_symbol,
+};
+
+/// A combo the bot bought, from the fills of the trade journal.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OpenCombo {
+    /// The cOID of the order that opened it, without the `.1`, `.2`, ... suffix of child orders.
+    pub(crate) order_ref: String,
+    pub(crate) strategy: String,
+    pub(crate) exp_date: ExpiryDate,
+    pub(crate) legs: Vec<JournalLeg>,
+    /// The number of combos filled.
+    pub(crate) size: f64,
+    /// The average fill price per combo.
+    pub(crate) fill_price: f64,
+}
+
+impl OpenCombo {
+    /// Returns the conidex of the combo, e.g. `28812380;;;123/1,456/-1`, or the conid of its leg
+    /// if one long leg is all that is left of it.
+    pub(crate) fn conidex(&self) -> String {
+        if let [leg] = self.legs.as_slice() {
+            if leg.ratio == 1 {
+                return leg.conid.clone();
+            }
+        }
+        format!(
+            "{};;;{}",
+            USD_CONID,
+            self.legs
+                .iter()
+                .map(|leg| format!("{}/{}", leg.conid, leg.ratio))
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+
+    /// Returns the number of combos still held: the filled size, capped by the fewest combos any
+    /// leg's position covers. Other combos sharing a leg can net its position down, so this is
+    /// conservative.
+    ///
+    /// # Arguments
+    ///
+    /// * `positions` - The position of the account by conid, positive when long.
+    pub(crate) fn held(&self, positions: &FxHashMap<String, f64>) -> f64 {
+        self.legs.iter().fold(self.size, |held, leg| {
+            let position: f64 = positions.get(&leg.conid).copied().unwrap_or_default();
+            held.min((position / leg.ratio as f64).max(0.0).floor())
+        })
+    }
+
+    /// Returns whether the combo is a calendar whose short leg expires within a number of days,
+    /// so it is due to be rolled.
+    ///
+    /// # Arguments
+    ///
+    /// * `today` - The current date.
+    /// * `days` - How many days before the short leg's expiration the calendar is rolled.
+    pub(crate) fn roll_due(&self, today: NaiveDate, days: i64) -> bool {
+        self.strategy == "Calendar"
+            && self
+                .legs
+                .iter()
+                .filter(|leg| leg.ratio < 0)
+                .filter_map(|leg| parse_osi_symbol(&leg.symbol))
+                .any(|short_leg| (0..=days).contains(&short_leg.date.days_from(today)))
+    }
+
+    /// Describes the combo, e.g. `"Butterfly 250117 9f3c0a1b2c3d4e5f-0: 2 filled at 1.25, legs
+    /// SPXW  250117C05890000 1, SPXW  250117C05900000 -1"`.
+    pub(crate) fn describe(&self) -> String {
+        format!(
+            "{} {} {}: {} filled at {:.2}, legs {}",
+            self.strategy,
+            self.exp_date,
+            self.order_ref,
+            self.size,
+            self.fill_price,
+            self.legs
+                .iter()
+                .map(|leg| format!("{} {}", leg.symbol, leg.ratio))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+}
+
+/// Collects the combos filled or rolled into in a trade journal, merging the fills of child orders.
+/// Simulated fills and fills of orders journaled without leg conids are left out. Combos whose
+/// legs were assigned, exercised or expired shrink by the combos affected, and the legs still held
+/// of them become combos of their own.
+///
+/// # Arguments
+///
+/// * `text` - The journal, one JSON entry per line.
+///
+/// # Returns
+///
+/// A `Vec<OpenCombo>` with the combos in the order they were first filled.
+pub(crate) fn parse_open_combos(text: &str) -> Vec<OpenCombo> {
+    let mut combos: Vec<OpenCombo> = Vec::new();
+    for line in text.lines() {
+        let Ok(entry) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        if !["fill", "roll", "settlement"].contains(&entry["event"].as_str().unwrap_or_default())
+            || entry["simulated"] == true
+        {
+            continue;
+        }
+        if let (Some(settles), Some(size)) = (entry["settles"].as_str(), entry["size"].as_f64()) {
+            if let Some(combo) = combos.iter_mut().find(|combo| combo.order_ref == settles) {
+                combo.size -= size;
+            }
+            combos.retain(|combo| combo.size > 0.0);
+        }
+        let Some(order_ref) = entry["order_ref"].as_str() else {
+            continue;
+        };
+        let Ok(legs) = serde_json::from_value::<Vec<JournalLeg>>(entry["legs"].clone()) else {
+            continue;
+        };
+        let (Some(exp_date), Some(size), Some(fill_price)) = (
+            serde_json::from_value::<ExpiryDate>(entry["exp_date"].clone()).ok(),
+            entry["size"].as_f64(),
+            entry["fill_price"].as_f64(),
+        ) else {
+            continue;
+        };
+        if legs.is_empty() || legs.iter().any(|leg| leg.conid.is_empty()) {
+            continue;
+        }
+
+        let order_ref: &str = match order_ref.rsplit_once('.') {
+            Some((parent, child)) if child.chars().all(|c| c.is_ascii_digit()) => parent,
+            _ => order_ref,
+        };
+        match combos.iter_mut().find(|combo| combo.order_ref == order_ref) {
+            Some(combo) => {
+                combo.fill_price =
+                    (combo.fill_price * combo.size + fill_price * size) / (combo.size + size);
+                combo.size += size;
+            }
+            None => combos.push(OpenCombo {
+                order_ref: order_ref.to_string(),
+                strategy: entry["strategy"].as_str().unwrap_or_default().to_string(),
+                exp_date,
+                legs,
+                size,
+                fill_price,
+            }),
+        }
+    }
+    combos
+}
+
+/// Loads the combos filled in the live trade journal.
+///
+/// # Arguments
+///
+/// * `path` - The path of the journal file.
+///
+/// # Returns
+///
+/// A `Result` containing the combos, or an error if the journal can't be read.
+pub(crate) fn load_open_combos(path: &str) -> Result<Vec<OpenCombo>, Box<dyn Error>> {
+    Ok(parse_open_combos(&fs::read_to_string(path)?))
+}
+
+/// How a leg of a held combo left the account without an execution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LegOutcome {
+    /// A short leg was assigned before its expiration.
+    Assignment,
+    /// A long leg was exercised before its expiration.
+    Exercise,
+    /// The leg expired, worthless or cash settled.
+    Expiration,
+}
+
+impl LegOutcome {
+    /// Returns the name of the outcome, e.g. `"assignment"`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            LegOutcome::Assignment => "assignment",
+            LegOutcome::Exercise => "exercise",
+            LegOutcome::Expiration => "expiration",
+        }
+    }
+}
+
+/// A leg that left the account without an execution.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LegEvent {
+    pub(crate) leg: JournalLeg,
+    pub(crate) outcome: LegOutcome,
+    /// The number of contracts that left the account.
+    pub(crate) contracts: f64,
+}
+
+impl LegEvent {
+    /// Describes the leg with the signed contracts that left, e.g. `"SPXW  250117C05900000 -2"`.
+    pub(crate) fn describe(&self) -> String {
+        format!(
+            "{} {}",
+            self.leg.symbol,
+            self.contracts * self.leg.ratio.signum() as f64
+        )
+    }
+}
+
+/// The combos of one journaled order whose legs left the account without an execution.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ComboSettlement {
+    pub(crate) combo: OpenCombo,
+    /// The number of combos affected.
+    pub(crate) size: f64,
+    /// The legs that left the account.
+    pub(crate) events: Vec<LegEvent>,
+    /// The legs still held, which stay in the inventory as a combo of their own.
+    pub(crate) remaining: Vec<JournalLeg>,
+}
+
+impl ComboSettlement {
+    /// Returns the outcome of the settlement: an assignment if any leg was assigned, else an
+    /// exercise if any leg was exercised, else an expiration.
+    pub(crate) fn outcome(&self) -> LegOutcome {
+        [LegOutcome::Assignment, LegOutcome::Exercise]
+            .into_iter()
+            .find(|outcome| self.events.iter().any(|event| event.outcome == *outcome))
+            .unwrap_or(LegOutcome::Expiration)
+    }
+
+    /// Returns what is left of the combo, keyed by the outcome and the order that opened it, e.g.
+    /// `assignment-9f3c0a1b2c3d4e5f-0`, or `None` if no leg is left.
+    pub(crate) fn remainder(&self) -> Option<OpenCombo> {
+        (!self.remaining.is_empty()).then(|| OpenCombo {
+            order_ref: format!("{}-{}", self.outcome().name(), self.combo.order_ref),
+            strategy: self.combo.strategy.clone(),
+            exp_date: self.combo.exp_date,
+            legs: self.remaining.clone(),
+            size: self.size,
+            fill_price: self.combo.fill_price,
+        })
+    }
+
+    /// Describes the settlement, e.g. `"assignment of Calendar 250117 9f3c0a1b2c3d4e5f-0: 2
+    /// combos, SPXW  250117C05900000 -2, leaving SPXW  250124C05900000 1"`.
+    pub(crate) fn describe(&self) -> String {
+        let mut description: String = format!(
+            "{} of {} {} {}: {} combos, {}",
+            self.outcome().name(),
+            self.combo.strategy,
+            self.combo.exp_date,
+            self.combo.order_ref,
+            self.size,
+            self.events
+                .iter()
+                .map(LegEvent::describe)
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        if !self.remaining.is_empty() {
+            description += &format!(
+                ", leaving {}",
+                self.remaining
+                    .iter()
+                    .map(|leg| format!("{} {}", leg.symbol, leg.ratio))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+        }
+        description
+    }
+}
+
+/// An execution of a single option, e.g. a leg of a combo order.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LegExecution {
+    pub(crate) execution_id: String,
+    pub(crate) conid: String,
+    /// The signed number of contracts, positive when bought.
+    pub(crate) quantity: f64,
+    /// When the execution happened, in milliseconds since the epoch.
+    pub(crate) time: i64,
+}
+
+/// Watches the positions of the legs of journaled combos for changes no execution explains,
+/// which is how assignments, exercises and expirations show up at IBKR.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct PositionWatch {
+    /// The position of each held leg by conid at the last poll.
+    positions: FxHashMap<String, f64>,
+    /// When the positions were last polled, in milliseconds since the epoch.
+    polled_at: i64,
+    /// The executions already accounted for.
+    #[serde(skip)]
+    executions: FxHashSet<String>,
+}
+
+impl PositionWatch {
+    /// Compares the positions of the legs of journaled combos with the last poll. A leg moving
+    /// towards flat by more than its executions since then left the account without a trade: it
+    /// expired if its expiration has passed, and otherwise was assigned when short or exercised
+    /// when long. The contracts are attributed to the combos holding the leg, oldest first.
+    ///
+    /// Legs not held at the last poll only start being watched, so the first poll of a run with
+    /// no saved state reports nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `combos` - The combos of the trade journal.
+    /// * `positions` - The position of the account by conid, positive when long.
+    /// * `executions` - The recent option executions of the account.
+    /// * `today` - The current date.
+    /// * `now` - The current time in milliseconds since the epoch.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<ComboSettlement>` with the combos affected, in the order they were first filled.
+    pub(crate) fn reconcile(
+        &mut self,
+        combos: &[OpenCombo],
+        positions: &FxHashMap<String, f64>,
+        executions: &[LegExecution],
+        today: NaiveDate,
+        now: i64,
+    ) -> Vec<ComboSettlement> {
+        let mut traded: FxHashMap<&str, f64> = FxHashMap::default();
+        for execution in executions {
+            // Executions before the last poll are already in its positions.
+            if execution.time > self.polled_at
+                && self.executions.insert(execution.execution_id.clone())
+            {
+                *traded.entry(&execution.conid).or_default() += execution.quantity;
+            }
+        }
+
+        let previous: FxHashMap<String, f64> = std::mem::take(&mut self.positions);
+        let mut unexplained: FxHashMap<&str, (LegOutcome, f64)> = FxHashMap::default();
+        let mut checked: FxHashSet<&str> = FxHashSet::default();
+        for leg in combos.iter().flat_map(|combo| &combo.legs) {
+            if !checked.insert(&leg.conid) {
+                continue;
+            }
+            let position: f64 = positions.get(&leg.conid).copied().unwrap_or_default();
+            if position != 0.0 {
+                self.positions.insert(leg.conid.clone(), position);
+            }
+            let Some(last_position) = previous.get(&leg.conid).copied() else {
+                continue;
+            };
+
+            let change: f64 = position
+                - last_position
+                - traded.get(leg.conid.as_str()).copied().unwrap_or_default();
+            let contracts: f64 = if last_position > 0.0 {
+                (-change).clamp(0.0, last_position)
+            } else {
+                change.clamp(0.0, -last_position)
+            };
+            if contracts < 0.5 {
+                continue;
+            }
+            let outcome: LegOutcome = match parse_osi_symbol(&leg.symbol) {
+                Some(contract) if contract.date.date() <= today => LegOutcome::Expiration,
+                _ if last_position < 0.0 => LegOutcome::Assignment,
+                _ => LegOutcome::Exercise,
+            };
+            unexplained.insert(&leg.conid, (outcome, contracts.round()));
+        }
+        self.polled_at = now;
+
+        let mut settlements: Vec<ComboSettlement> = Vec::new();
+        for combo in combos {
+            let held: f64 = combo.held(&previous);
+            let size: f64 = combo
+                .legs
+                .iter()
+                .filter_map(|leg| {
+                    let (_, contracts) = unexplained.get(leg.conid.as_str())?;
+                    Some((contracts / leg.ratio.abs() as f64).ceil())
+                })
+                .fold(0.0, f64::max)
+                .min(held);
+            if size <= 0.0 {
+                continue;
+            }
+
+            let mut events: Vec<LegEvent> = Vec::new();
+            let mut remaining: Vec<JournalLeg> = Vec::new();
+            for leg in &combo.legs {
+                match unexplained.get_mut(leg.conid.as_str()) {
+                    Some((outcome, contracts)) if *contracts > 0.0 => {
+                        let taken: f64 = contracts.min(size * leg.ratio.abs() as f64);
+                        *contracts -= taken;
+                        events.push(LegEvent {
+                            leg: leg.clone(),
+                            outcome: *outcome,
+                            contracts: taken,
+                        });
+                    }
+                    _ => remaining.push(leg.clone()),
+                }
+            }
+            settlements.push(ComboSettlement {
+                combo: combo.clone(),
+                size,
+                events,
+                remaining,
+            });
+        }
+        settlements
+    }
+}
+
+/// A request of the `close` or `roll` subcommand: the combo to close or roll, or none to list the
+/// held ones.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ComboRequest {
+    /// The cOID of the order that opened the combo, or a unique prefix of it.
+    pub(crate) order_ref: Option<String>,
+    /// The number of combos to close or roll, or `None` for all that are held.
+    pub(crate) num_fills: Option<i32>,
+    /// The expiration to roll a calendar's short leg to, or `None` for the next one.
+    pub(crate) expiry: Option<ExpiryDate>,
+}
+
+/// Parses the arguments of the `close` and `roll` subcommands, e.g. `9f3c0a1b --fills 1` or
+/// `9f3c0a1b --to 250124`.
+///
+/// # Arguments
+///
+/// * `args` - The arguments after the subcommand.
+///
+/// # Returns
+///
+/// A `Result` containing the request, or an error naming the invalid argument.
+pub(crate) fn parse_combo_args(args: &[String]) -> Result<ComboRequest, String> {
+    let mut combo_request: ComboRequest = ComboRequest::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => {
+                combo_request.expiry = Some(args.next().ok_or("--to takes an expiry")?.parse()?)
+            }
+            "--fills" => {
+                combo_request.num_fills = Some(
+                    args.next()
+                        .and_then(|value| value.parse::<i32>().ok())
+                        .filter(|num_fills| *num_fills > 0)
+                        .ok_or("--fills takes a positive number")?,
+                )
+            }
+            order_ref if combo_request.order_ref.is_none() => {
+                combo_request.order_ref = Some(order_ref.to_string())
+            }
+            arg => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    Ok(combo_request)
+}
@@ -0,0 +1,105 @@
+use crate::chain::Right;
+
+/// Approximates the standard normal cumulative distribution function, accurate to about 1e-7.
+///
+/// # Arguments
+///
+/// * `x` - The point to evaluate.
+fn norm_cdf(x: f64) -> f64 {
+    // Abramowitz and Stegun 7.1.26 applied to erf(x / sqrt(2)).
+    let z: f64 = x.abs() / std::f64::consts::SQRT_2;
+    let t: f64 = 1.0 / (1.0 + 0.3275911 * z);
+    let poly: f64 = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf: f64 = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+/// Prices a European option with the Black-Scholes formula.
+///
+/// # Arguments
+///
+/// * `right` - Call or put.
+/// * `spot` - Price of the underlying.
+/// * `strike` - Strike of the option.
+/// * `years` - Time to expiry in years.
+/// * `rate` - Annualized risk-free rate.
+/// * `volatility` - Annualized volatility.
+///
+/// # Returns
+///
+/// An `f64` with the option price, or the discounted intrinsic value at expiry.
+///
+/// # Example
+///
+/// ```
+/// let price = black_scholes(Right::Call, 5000.0, 5000.0, 30.0 / 365.0, 0.05, 0.15);
+/// println!("At-the-money call: {:.2}", price);
+/// ```
+pub(crate) fn black_scholes(
+    right: Right,
+    spot: f64,
+    strike: f64,
+    years: f64,
+    rate: f64,
+    volatility: f64,
+) -> f64 {
+    let discount: f64 = (-rate * years).exp();
+    if years <= 0.0 || volatility <= 0.0 {
+        return match right {
+            Right::Call => (spot - strike * discount).max(0.0),
+            Right::Put => (strike * discount - spot).max(0.0),
+        };
+    }
+
+    let deviation: f64 = volatility * years.sqrt();
+    let d1: f64 = ((spot / strike).ln() + (rate + volatility.powi(2) / 2.0) * years) / deviation;
+    let d2: f64 = d1 - deviation;
+    match right {
+        Right::Call => spot * norm_cdf(d1) - strike * discount * norm_cdf(d2),
+        Right::Put => strike * discount * norm_cdf(-d2) - spot * norm_cdf(-d1),
+    }
+}
+
+/// Calculates the Black-Scholes delta of a European option.
+///
+/// # Arguments
+///
+/// * `right` - Call or put.
+/// * `spot` - Price of the underlying.
+/// * `strike` - Strike of the option.
+/// * `years` - Time to expiry in years.
+/// * `rate` - Annualized risk-free rate.
+/// * `volatility` - Annualized volatility.
+///
+/// # Returns
+///
+/// An `f64` with the delta, between 0 and 1 for calls and -1 and 0 for puts.
+pub(crate) fn black_scholes_delta(
+    right: Right,
+    spot: f64,
+    strike: f64,
+    years: f64,
+    rate: f64,
+    volatility: f64,
+) -> f64 {
+    let call_delta: f64 = if years <= 0.0 || volatility <= 0.0 {
+        if spot > strike {
+            1.0
+        } else {
+            0.0
+        }
+    } else {
+        let deviation: f64 = volatility * years.sqrt();
+        norm_cdf(((spot / strike).ln() + (rate + volatility.powi(2) / 2.0) * years) / deviation)
+    };
+    match right {
+        Right::Call => call_delta,
+        Right::Put => call_delta - 1.0,
+    }
+}
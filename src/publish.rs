@@ -0,0 +1,221 @@
+#[cfg(feature = "kafka")]
+use kafka::producer::{Producer, Record, RequiredAcks};
+#[cfg(feature = "mqtt")]
+use rumqttc::{Client, Connection, MqttOptions, QoS};
+use serde_json::Value;
+#[cfg(any(feature = "kafka", feature = "nats", feature = "mqtt"))]
+use std::io;
+#[cfg(feature = "mqtt")]
+use std::thread;
+#[cfg(any(feature = "kafka", feature = "mqtt"))]
+use std::time::Duration;
+
+#[cfg(any(feature = "kafka", feature = "nats", feature = "mqtt"))]
+use crate::events::EventSink;
+#[cfg(feature = "mqtt")]
+use crate::structs::MqttConfig;
+
+/// Publishes events to a Kafka topic. Requires the `kafka` feature.
+#[cfg(feature = "kafka")]
+pub(crate) struct KafkaSink {
+    producer: Producer,
+    topic: String,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink {
+    /// Connects a producer to the Kafka brokers.
+    ///
+    /// # Arguments
+    ///
+    /// * `brokers` - A comma-separated list of `host:port` brokers.
+    /// * `topic` - The topic the events are published to.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the sink or the connection error.
+    pub(crate) fn connect(brokers: &str, topic: &str) -> io::Result<Self> {
+        let producer: Producer = Producer::from_hosts(
+            brokers
+                .split(',')
+                .map(|broker| broker.trim().to_string())
+                .collect(),
+        )
+        .with_ack_timeout(Duration::from_secs(1))
+        .with_required_acks(RequiredAcks::One)
+        .create()
+        .map_err(io::Error::other)?;
+        Ok(KafkaSink {
+            producer,
+            topic: topic.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl EventSink for KafkaSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.producer
+            .send(&Record::from_value(&self.topic, line.as_bytes()))
+            .map_err(io::Error::other)
+    }
+}
+
+/// Publishes events to a NATS subject. Requires the `nats` feature.
+#[cfg(feature = "nats")]
+pub(crate) struct NatsSink {
+    connection: nats::Connection,
+    subject: String,
+}
+
+#[cfg(feature = "nats")]
+impl NatsSink {
+    /// Connects to the NATS server.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The NATS server URL, e.g. `nats://localhost:4222`.
+    /// * `subject` - The subject the events are published to.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the sink or the connection error.
+    pub(crate) fn connect(url: &str, subject: &str) -> io::Result<Self> {
+        Ok(NatsSink {
+            connection: nats::connect(url)?,
+            subject: subject.to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+impl EventSink for NatsSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.connection.publish(&self.subject, line)
+    }
+}
+
+/// Publishes contender and fill events to MQTT topics. Requires the `mqtt` feature.
+#[cfg(feature = "mqtt")]
+pub(crate) struct MqttSink {
+    client: Client,
+    contender_topic: String,
+    fill_topic: String,
+}
+
+#[cfg(feature = "mqtt")]
+impl MqttSink {
+    /// Connects to the MQTT broker, driving the connection on a background thread that reconnects
+    /// after failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The broker and topics.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the sink or an error if the broker address is invalid.
+    pub(crate) fn connect(config: &MqttConfig) -> io::Result<Self> {
+        let broker: MqttBroker = parse_mqtt_broker(&config.broker)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut options: MqttOptions =
+            MqttOptions::new("trading_bot_rust", broker.host, broker.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some((username, password)) = broker.login {
+            options.set_credentials(username, password);
+        }
+
+        let (client, mut connection): (Client, Connection) = Client::new(options, 64);
+        thread::spawn(move || {
+            for notification in connection.iter() {
+                // The next iteration reconnects, so only back off.
+                if notification.is_err() {
+                    thread::sleep(Duration::from_secs(5));
+                }
+            }
+        });
+        Ok(MqttSink {
+            client,
+            contender_topic: config.contender_topic.clone(),
+            fill_topic: config.fill_topic.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl EventSink for MqttSink {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        let Some(topic) = mqtt_topic(&self.contender_topic, &self.fill_topic, line)? else {
+            return Ok(());
+        };
+        self.client
+            .try_publish(topic, QoS::AtLeastOnce, false, line.as_bytes().to_vec())
+            .map_err(io::Error::other)
+    }
+}
+
+/// The address and login of an MQTT broker.
+#[derive(Debug, PartialEq)]
+pub(crate) struct MqttBroker<'a> {
+    /// The username and password, if the broker requires a login.
+    pub(crate) login: Option<(&'a str, &'a str)>,
+    pub(crate) host: &'a str,
+    pub(crate) port: u16,
+}
+
+/// Splits an MQTT broker setting into its login, host and port.
+///
+/// # Arguments
+///
+/// * `broker` - The broker as `host:port`, or `host` for port 1883, prefixed with
+///   `username:password@` if it requires a login.
+///
+/// # Returns
+///
+/// A `Result` containing the `MqttBroker`, or an error if the port is invalid.
+#[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+pub(crate) fn parse_mqtt_broker(broker: &str) -> Result<MqttBroker<'_>, String> {
+    let (login, address): (Option<&str>, &str) = match broker.rsplit_once('@') {
+        Some((login, address)) => (Some(login), address),
+        None => (None, broker),
+    };
+    let (host, port): (&str, u16) = match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|e| format!("Invalid MQTT_BROKER port {:?}: {}", port, e))?,
+        ),
+        None => (address, 1883),
+    };
+    Ok(MqttBroker {
+        login: login.map(|login| login.split_once(':').unwrap_or((login, ""))),
+        host,
+        port,
+    })
+}
+
+/// Picks the MQTT topic an event is published to. Only contenders and fills are published.
+///
+/// # Arguments
+///
+/// * `contender_topic` - The topic of contender events.
+/// * `fill_topic` - The topic of fill events.
+/// * `line` - The event as a JSON line.
+///
+/// # Returns
+///
+/// A `Result` containing the topic, or `None` if the event isn't published, or an error if the
+/// line isn't JSON.
+#[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+pub(crate) fn mqtt_topic<'a>(
+    contender_topic: &'a str,
+    fill_topic: &'a str,
+    line: &str,
+) -> Result<Option<&'a str>, serde_json::Error> {
+    let event: Value = serde_json::from_str(line)?;
+    Ok(match event["event"].as_str() {
+        Some("contender") => Some(contender_topic),
+        Some("fill") => Some(fill_topic),
+        _ => None,
+    })
+}
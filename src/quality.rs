@@ -0,0 +1,127 @@
+use crate::{
+    chain::{ChainKey, ExpiryId, OptionChain, Quotes, Right, StrikeKey},
+    structs::Opt,
+};
+
+/// How usable the quotes of one expiration are.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExpiryQuality {
+    pub(crate) expiry: ExpiryId,
+    /// The fraction of the expiration's contracts quoted on both sides.
+    pub(crate) two_sided: f64,
+    /// The average bid-ask spread of the two-sided quotes, relative to their mid.
+    pub(crate) avg_spread: f64,
+    /// The fraction of the two-sided quotes unchanged since the previous scan.
+    pub(crate) stale: f64,
+}
+
+impl ExpiryQuality {
+    /// Combines the measures into one score, from `0` for no usable quotes to `1` for every
+    /// contract quoted fresh with no spread.
+    pub(crate) fn score(&self) -> f64 {
+        self.two_sided * (1.0 - self.stale) / (1.0 + self.avg_spread)
+    }
+
+    /// Describes the measures, e.g. `"92% two-sided, 4% average spread, 10% stale"`.
+    pub(crate) fn describe(&self) -> String {
+        format!(
+            "{:.0}% two-sided, {:.0}% average spread, {:.0}% stale",
+            self.two_sided * 100.0,
+            self.avg_spread * 100.0,
+            self.stale * 100.0
+        )
+    }
+}
+
+/// Returns whether a quote has a bid, an ask at or above it and size on the ask.
+///
+/// # Arguments
+///
+/// * `opt` - The quote.
+fn is_two_sided(opt: &Opt) -> bool {
+    opt.bid > 0.0 && opt.ask >= opt.bid && opt.asz > 0.0
+}
+
+/// Returns whether a quote's prices and sizes are the same as in an earlier scan.
+///
+/// # Arguments
+///
+/// * `opt` - The quote.
+/// * `previous` - The quote of the same contract in the earlier scan.
+fn is_unchanged(opt: &Opt, previous: &Opt) -> bool {
+    opt.bid == previous.bid
+        && opt.ask == previous.ask
+        && opt.asz == previous.asz
+        && opt.bsz == previous.bsz
+}
+
+/// Scores the quotes of every expiration in the chain.
+///
+/// # Arguments
+///
+/// * `chain` - The option chain with expirations, strikes and conids.
+/// * `quotes` - The quotes of this scan.
+/// * `previous` - The quotes of the previous scan, if any, to measure staleness against.
+///
+/// # Returns
+///
+/// A `Vec<ExpiryQuality>` in expiration order. Without previous quotes nothing counts as stale.
+///
+/// # Example
+///
+/// ```
+/// for quality in score_expiries(&chain, &quotes, None) {
+///     println!("{}: {:.2} ({})", chain.dates()[quality.expiry as usize], quality.score(), quality.describe());
+/// }
+/// ```
+pub(crate) fn score_expiries(
+    chain: &OptionChain,
+    quotes: &Quotes,
+    previous: Option<&Quotes>,
+) -> Vec<ExpiryQuality> {
+    (0..chain.dates().len())
+        .map(|index| {
+            let expiry: ExpiryId = index as ExpiryId;
+            let mut num_contracts: usize = 0;
+            let mut num_two_sided: usize = 0;
+            let mut num_stale: usize = 0;
+            let mut spread_sum: f64 = 0.0;
+
+            for right in Right::ALL {
+                for strike in chain.strikes(expiry, right) {
+                    num_contracts += 1;
+                    let key: ChainKey = (expiry, right, StrikeKey::from_f64(*strike));
+                    let Some(opt) = quotes.get(&key).filter(|opt| is_two_sided(opt)) else {
+                        continue;
+                    };
+                    num_two_sided += 1;
+                    spread_sum += (opt.ask - opt.bid) / ((opt.ask + opt.bid) / 2.0);
+                    if previous
+                        .and_then(|previous| previous.get(&key))
+                        .is_some_and(|previous| is_unchanged(opt, previous))
+                    {
+                        num_stale += 1;
+                    }
+                }
+            }
+
+            let fraction = |count: usize, total: usize| -> f64 {
+                if total == 0 {
+                    0.0
+                } else {
+                    count as f64 / total as f64
+                }
+            };
+            ExpiryQuality {
+                expiry,
+                two_sided: fraction(num_two_sided, num_contracts),
+                avg_spread: if num_two_sided == 0 {
+                    0.0
+                } else {
+                    spread_sum / num_two_sided as f64
+                },
+                stale: fraction(num_stale, num_two_sided),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,224 @@
+use chrono::NaiveDate;
+use reqwest::blocking::{Client, Response};
+use serde_json::Value;
+use std::error::Error;
+
+use crate::{logging::log_message, structs::RateConfig};
+
+/// FRED series of Treasury constant-maturity yields and their tenors in days.
+const FRED_SERIES: [(&str, i64); 6] = [
+    ("DGS1MO", 30),
+    ("DGS3MO", 91),
+    ("DGS6MO", 182),
+    ("DGS1", 365),
+    ("DGS2", 730),
+    ("DGS5", 1826),
+];
+
+/// A risk-free rate curve of annualized, continuously compounded rates by days to maturity.
+///
+/// Rates between tenors are interpolated linearly and held flat beyond the first and last tenor.
+/// An empty curve is a zero rate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct RateCurve {
+    points: Vec<(i64, f64)>,
+}
+
+impl RateCurve {
+    /// Creates a curve with the same rate at every maturity.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The annualized rate, e.g. `0.05` for 5%.
+    pub(crate) fn flat(rate: f64) -> Self {
+        RateCurve {
+            points: vec![(0, rate)],
+        }
+    }
+
+    /// Creates a curve from rates by tenor.
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - Pairs of days to maturity and annualized rate, in any order.
+    pub(crate) fn from_points(mut points: Vec<(i64, f64)>) -> Self {
+        points.sort_by_key(|(days, _)| *days);
+        RateCurve { points }
+    }
+
+    /// Returns the rate for a maturity.
+    ///
+    /// # Arguments
+    ///
+    /// * `days` - The days to maturity.
+    ///
+    /// # Returns
+    ///
+    /// An `f64` with the annualized rate, `0.0` for an empty curve.
+    pub(crate) fn rate(&self, days: i64) -> f64 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return 0.0;
+        };
+        if days <= first.0 {
+            return first.1;
+        }
+        if days >= last.0 {
+            return last.1;
+        }
+        self.points
+            .windows(2)
+            .find(|pair| days <= pair[1].0)
+            .map_or(last.1, |pair| {
+                let (low, high) = (pair[0], pair[1]);
+                low.1 + (high.1 - low.1) * (days - low.0) as f64 / (high.0 - low.0) as f64
+            })
+    }
+
+    /// Returns the present value of one dollar paid at a maturity.
+    ///
+    /// # Arguments
+    ///
+    /// * `days` - The days to maturity.
+    ///
+    /// # Returns
+    ///
+    /// An `f64` discount factor, `1.0` at or before today.
+    pub(crate) fn discount_factor(&self, days: i64) -> f64 {
+        if days <= 0 {
+            return 1.0;
+        }
+        (-self.rate(days) * days as f64 / 365.0).exp()
+    }
+
+    /// Formats the curve for logging, e.g. `30d 5.25%, 91d 5.20%`.
+    pub(crate) fn summary(&self) -> String {
+        self.points
+            .iter()
+            .map(|(days, rate)| format!("{}d {:.2}%", days, rate * 100.0))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// Client fetching the Treasury yield curve from FRED, at most once a day.
+pub(crate) struct RateClient {
+    api_key: String,
+    client: Client,
+    /// The FRED API URL, without a trailing slash.
+    base_url: String,
+    fetched_on: Option<NaiveDate>,
+}
+
+impl RateClient {
+    /// Creates a client if a FRED API key is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The risk-free rate configuration.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<RateClient>`, or `None` without an API key.
+    pub(crate) fn new(config: &RateConfig) -> Option<Self> {
+        Some(RateClient {
+            api_key: config.fred_api_key.clone()?,
+            client: Client::new(),
+            base_url: config.fred_url.trim_end_matches('/').to_string(),
+            fetched_on: None,
+        })
+    }
+
+    /// Fetches the curve unless it was already fetched today. A failed fetch is logged and not
+    /// retried until the next day, so the previous curve stays in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `today` - The current date.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<RateCurve>` with the new curve, or `None` if it is cached or the fetch failed.
+    pub(crate) fn refresh(&mut self, today: NaiveDate) -> Option<RateCurve> {
+        if self.fetched_on == Some(today) {
+            return None;
+        }
+        self.fetched_on = Some(today);
+        match self.fetch_curve() {
+            Ok(curve) => Some(curve),
+            Err(e) => {
+                log_message(format!("Failed to fetch the Treasury yield curve: {}.", e));
+                None
+            }
+        }
+    }
+
+    /// Fetches the latest yield of every tenor in `FRED_SERIES`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the curve or an error if no tenor could be fetched.
+    fn fetch_curve(&self) -> Result<RateCurve, Box<dyn Error>> {
+        let mut points: Vec<(i64, f64)> = Vec::new();
+        for (series_id, days) in FRED_SERIES {
+            match self.fetch_latest_yield(series_id) {
+                Ok(rate) => points.push((days, rate)),
+                Err(e) => log_message(format!("Failed to fetch {}: {}.", series_id, e)),
+            }
+        }
+        if points.is_empty() {
+            return Err("no yields fetched".into());
+        }
+        Ok(RateCurve::from_points(points))
+    }
+
+    /// Fetches the latest observation of a FRED yield series.
+    ///
+    /// # Arguments
+    ///
+    /// * `series_id` - The FRED series, e.g. `"DGS3MO"`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the continuously compounded rate or an error.
+    fn fetch_latest_yield(&self, series_id: &str) -> Result<f64, Box<dyn Error>> {
+        let params: [(&str, &str); 5] = [
+            ("series_id", series_id),
+            ("api_key", &self.api_key),
+            ("file_type", "json"),
+            ("sort_order", "desc"),
+            ("limit", "10"),
+        ];
+        let response: Response = self
+            .client
+            .get(format!("{}/series/observations", self.base_url))
+            .query(&params)
+            .send()?;
+        if !response.status().is_success() {
+            return Err(format!("{}", response.status()).into());
+        }
+
+        let body: Value = response.json()?;
+        parse_fred_yield(&body).ok_or_else(|| "no observation in the response".into())
+    }
+}
+
+/// Parses the latest yield from a FRED observations response sorted newest first, skipping the
+/// `"."` FRED reports for holidays.
+///
+/// Treasury yields are quoted in percent on a bond-equivalent basis, so the yield is converted to
+/// a continuously compounded rate.
+///
+/// # Arguments
+///
+/// * `body` - The JSON response.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the rate, or `None` if no observation has a value.
+pub(crate) fn parse_fred_yield(body: &Value) -> Option<f64> {
+    body["observations"]
+        .as_array()?
+        .iter()
+        .find_map(|observation| observation["value"].as_str()?.parse::<f64>().ok())
+        .map(|yield_percent| 2.0 * (1.0 + yield_percent / 200.0).ln())
+}
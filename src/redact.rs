@@ -0,0 +1,291 @@
+use serde_json::{Map, Value};
+use std::sync::RwLock;
+
+use crate::structs::RedactionConfig;
+
+/// What account IDs are replaced with.
+const ACCOUNT_MASK: &str = "[account]";
+/// What order IDs are replaced with.
+const ORDER_MASK: &str = "[order]";
+/// What auth tokens and other secrets are replaced with.
+const TOKEN_MASK: &str = "[token]";
+
+/// The prefixes of IBKR account IDs: individual, financial advisor and institution accounts, live
+/// or paper (`D`).
+const ACCOUNT_PREFIXES: [&str; 6] = ["DU", "DF", "DI", "U", "F", "I"];
+
+/// The words an order ID follows, e.g. `order 123`, `order ID 123` or `"order_id": "123"`.
+const ORDER_ID_KEYS: [&str; 4] = ["order", "order_id", "orderid", "local_order_id"];
+
+/// Masks account IDs, order IDs and auth tokens in text, so logs can be shared without leaking
+/// account details.
+#[derive(Debug)]
+pub(crate) struct Redactor {
+    config: RedactionConfig,
+    /// The account IDs in use, masked even where they don't look like IBKR account IDs.
+    accounts: Vec<String>,
+    /// Tokens and keys from the config, masked wherever they appear.
+    secrets: Vec<String>,
+}
+
+/// The redactor every log line, audit entry and journal entry passes through.
+static REDACTOR: RwLock<Redactor> = RwLock::new(Redactor::new(RedactionConfig {
+    accounts: false,
+    order_ids: false,
+    tokens: false,
+}));
+
+impl Redactor {
+    /// Creates a new `Redactor`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - What is masked.
+    pub(crate) const fn new(config: RedactionConfig) -> Self {
+        Redactor {
+            config,
+            accounts: Vec::new(),
+            secrets: Vec::new(),
+        }
+    }
+
+    /// Adds an account ID to mask.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The account ID.
+    pub(crate) fn add_account(&mut self, account_id: &str) {
+        if !account_id.is_empty() && !self.accounts.iter().any(|known| known == account_id) {
+            self.accounts.push(account_id.to_string());
+        }
+    }
+
+    /// Adds a token or key to mask.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret` - The token or key.
+    pub(crate) fn add_secret(&mut self, secret: &str) {
+        if !secret.is_empty() && !self.secrets.iter().any(|known| known == secret) {
+            self.secrets.push(secret.to_string());
+        }
+    }
+
+    /// Masks what the config selects in a text.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - A log line, audit entry or journal entry.
+    ///
+    /// # Returns
+    ///
+    /// A `String` with account IDs replaced by `[account]`, order IDs by `[order]` and tokens by
+    /// `[token]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let redactor = Redactor::new(RedactionConfig { accounts: true, order_ids: true, tokens: false });
+    /// assert_eq!(redactor.redact("Cancelled order 1234567 of U7654321."), "Cancelled order [order] of [account].");
+    /// ```
+    pub(crate) fn redact(&self, text: &str) -> String {
+        let config: &RedactionConfig = &self.config;
+        if !config.accounts && !config.order_ids && !config.tokens {
+            return text.to_string();
+        }
+        let mut text: String = text.to_string();
+        if config.accounts {
+            for account_id in &self.accounts {
+                text = text.replace(account_id.as_str(), ACCOUNT_MASK);
+            }
+        }
+        if config.tokens {
+            for secret in &self.secrets {
+                text = text.replace(secret.as_str(), TOKEN_MASK);
+            }
+        }
+
+        let mut redacted: String = String::with_capacity(text.len());
+        // The last two words and the text since the last word.
+        let mut previous: [String; 2] = [String::new(), String::new()];
+        let mut separator: String = String::new();
+        let mut rest: &str = &text;
+        while let Some(start) = rest.find(is_word_char) {
+            separator.push_str(&rest[..start]);
+            redacted.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            if config.tokens && is_token_key(&previous[1], &separator) {
+                let end: usize = rest
+                    .find(|c: char| c.is_whitespace() || "\"',;&}]".contains(c))
+                    .unwrap_or(rest.len());
+                redacted.push_str(TOKEN_MASK);
+                rest = &rest[end..];
+                previous = [String::new(), String::new()];
+                separator.clear();
+                continue;
+            }
+
+            let end: usize = rest.find(|c: char| !is_word_char(c)).unwrap_or(rest.len());
+            let word: &str = &rest[..end];
+            rest = &rest[end..];
+            if config.accounts && is_account_id(word) {
+                redacted.push_str(ACCOUNT_MASK);
+            } else if config.order_ids && is_order_id(word, &previous) {
+                redacted.push_str(ORDER_MASK);
+            } else {
+                redacted.push_str(word);
+            }
+            previous = [previous[1].clone(), word.to_lowercase()];
+            separator.clear();
+        }
+        redacted.push_str(rest);
+        redacted
+    }
+
+    /// Masks what the config selects in a JSON entry, keeping it valid JSON: strings are redacted
+    /// like text, and order IDs and tokens stored under their keys, numbers included, are replaced
+    /// by strings.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - An audit or journal entry.
+    ///
+    /// # Returns
+    ///
+    /// A `Value` with the redacted entry.
+    pub(crate) fn redact_json(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::from(self.redact(text)),
+            Value::Array(values) => values.iter().map(|value| self.redact_json(value)).collect(),
+            Value::Object(entries) => entries
+                .iter()
+                .map(|(key, value)| {
+                    let key_lower: String = key.to_lowercase();
+                    let is_scalar: bool = value.is_string() || value.is_number();
+                    let value: Value = if self.config.order_ids
+                        && is_scalar
+                        && ORDER_ID_KEYS[1..].contains(&key_lower.as_str())
+                    {
+                        Value::from(ORDER_MASK)
+                    } else if self.config.tokens && is_scalar && is_secret_key(&key_lower) {
+                        Value::from(TOKEN_MASK)
+                    } else {
+                        self.redact_json(value)
+                    };
+                    (self.redact(key), value)
+                })
+                .collect::<Map<String, Value>>()
+                .into(),
+            _ => value.clone(),
+        }
+    }
+}
+
+/// Returns whether a character is part of a word, an ID or a key.
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Returns whether a word looks like an IBKR account ID, e.g. `U1234567` or `DU1234567`.
+fn is_account_id(word: &str) -> bool {
+    ACCOUNT_PREFIXES.iter().any(|prefix| {
+        word.strip_prefix(prefix)
+            .is_some_and(|digits| digits.len() >= 5 && digits.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// Returns whether a word is an order ID, a number following e.g. `order`, `order ID` or
+/// `order_id`.
+///
+/// # Arguments
+///
+/// * `word` - The word.
+/// * `previous` - The two words before it, lowercase.
+fn is_order_id(word: &str, previous: &[String; 2]) -> bool {
+    !word.is_empty()
+        && word.chars().all(|c| c.is_ascii_digit())
+        && (ORDER_ID_KEYS.contains(&previous[1].as_str())
+            || (previous[1] == "id" && previous[0] == "order"))
+}
+
+/// Returns whether a key names a secret, e.g. `access_token`, `password` or `api_key`.
+fn is_secret_key(key: &str) -> bool {
+    ["token", "secret", "password", "api_key", "apikey", "cookie"]
+        .iter()
+        .any(|suffix| key.ends_with(suffix))
+}
+
+/// Returns whether the next word is a token: the value of a key such as `token=`,
+/// `"access_token": ` or `password:`, or the credentials after `Bearer `.
+///
+/// # Arguments
+///
+/// * `key` - The word before it, lowercase.
+/// * `separator` - The text between the key and the next word.
+fn is_token_key(key: &str, separator: &str) -> bool {
+    (is_secret_key(key) && separator.contains(['=', ':'])) || (key == "bearer" && separator == " ")
+}
+
+/// Sets what the log redaction masks, and the tokens and keys from the config it masks wherever
+/// they appear.
+///
+/// # Arguments
+///
+/// * `config` - What is masked.
+/// * `secrets` - The tokens and keys, e.g. the scan webhook token.
+pub(crate) fn init_redaction(config: RedactionConfig, secrets: &[String]) {
+    let mut redactor: Redactor = Redactor::new(config);
+    for secret in secrets {
+        redactor.add_secret(secret);
+    }
+    let mut global = REDACTOR.write().unwrap_or_else(|e| e.into_inner());
+    for account_id in &global.accounts {
+        redactor.add_account(account_id);
+    }
+    *global = redactor;
+}
+
+/// Adds an account ID the log redaction masks, once the account in use is known.
+///
+/// # Arguments
+///
+/// * `account_id` - The account ID.
+pub(crate) fn register_account(account_id: &str) {
+    REDACTOR
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .add_account(account_id);
+}
+
+/// Masks a log line, audit entry or journal entry as the log redaction is configured.
+///
+/// # Arguments
+///
+/// * `text` - The text.
+///
+/// # Returns
+///
+/// A `String` with the redacted text.
+pub(crate) fn redact(text: &str) -> String {
+    REDACTOR
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .redact(text)
+}
+
+/// Masks a JSON audit or journal entry as the log redaction is configured.
+///
+/// # Arguments
+///
+/// * `value` - The entry.
+///
+/// # Returns
+///
+/// A `Value` with the redacted entry.
+pub(crate) fn redact_json(value: &Value) -> Value {
+    REDACTOR
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .redact_json(value)
+}
@@ -0,0 +1,233 @@
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::helpers::resolve_profile;
+
+/// Settings applied without a restart: thresholds, discounts, the sleep interval and the event
+/// stream targets. Every other setting, such as the ticker or the account, needs a restart.
+const RELOADABLE_KEYS: [&str; 15] = [
+    "ARB_VALUE",
+    "NEAR_MISS_EPSILON",
+    "DISCOUNT_VALUE",
+    "THRESHOLD_SCHEDULE",
+    "SECONDS_TO_SLEEP",
+    "SCAN_SCHEDULE",
+    "SLEEP_JITTER_PERCENT",
+    "NO_TRADE_WINDOWS",
+    "EVENTS_PATH",
+    "EVENTS_ADDR",
+    "EVENTS_KAFKA_BROKERS",
+    "EVENTS_KAFKA_TOPIC",
+    "EVENTS_NATS_URL",
+    "EVENTS_NATS_SUBJECT",
+    "LOG_LEVEL",
+];
+
+/// The settings that changed in the config file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ConfigChange {
+    /// The reloadable settings that changed, with their new values.
+    pub(crate) applied: BTreeMap<String, String>,
+    /// The settings that changed but only take effect after a restart, including reloadable
+    /// settings that were removed.
+    pub(crate) rejected: Vec<String>,
+}
+
+impl ConfigChange {
+    /// Checks whether any applied setting starts with a prefix, e.g. `"EVENTS_"`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the settings.
+    pub(crate) fn applies(&self, prefix: &str) -> bool {
+        self.applied.keys().any(|key| key.starts_with(prefix))
+    }
+}
+
+/// Compares two versions of the config file.
+///
+/// # Arguments
+///
+/// * `old` - The settings in effect.
+/// * `new` - The settings now in the file.
+///
+/// # Returns
+///
+/// A `ConfigChange` with the reloadable settings to apply and the settings that need a restart.
+pub(crate) fn diff_config(
+    old: &BTreeMap<String, String>,
+    new: &BTreeMap<String, String>,
+) -> ConfigChange {
+    let mut change: ConfigChange = ConfigChange::default();
+    for key in old
+        .keys()
+        .chain(new.keys().filter(|key| !old.contains_key(*key)))
+    {
+        match (old.get(key), new.get(key)) {
+            (old_value, Some(new_value)) if old_value != Some(new_value) => {
+                if RELOADABLE_KEYS.contains(&key.as_str()) {
+                    change.applied.insert(key.clone(), new_value.clone());
+                } else {
+                    change.rejected.push(key.clone());
+                }
+            }
+            (Some(_), None) => change.rejected.push(key.clone()),
+            _ => {}
+        }
+    }
+    change
+}
+
+/// Parses the `KEY=value` lines of a `.env` file, skipping blank lines and `#` comments and
+/// stripping an `export` prefix, quotes around the value and comments after an unquoted value.
+///
+/// # Arguments
+///
+/// * `text` - The contents of the file.
+///
+/// # Returns
+///
+/// A `BTreeMap<String, String>` with the settings by name.
+pub(crate) fn parse_config(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.trim_start_matches("export ").split_once('=')?;
+            let value: &str = value.trim();
+            let value: &str = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .or_else(|| {
+                    value
+                        .strip_prefix('\'')
+                        .and_then(|value| value.strip_suffix('\''))
+                })
+                .unwrap_or_else(|| value.split(" #").next().unwrap_or_default().trim_end());
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Reads the settings of a config file, as resolved for a profile.
+///
+/// # Arguments
+///
+/// * `path` - The path of the `.env` file.
+/// * `profile` - The selected profile, if any.
+///
+/// # Returns
+///
+/// A `Result` containing the settings by name or an error if the file could not be read.
+fn read_config(
+    path: &Path,
+    profile: Option<&str>,
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    Ok(resolve_profile(
+        &parse_config(&fs::read_to_string(path)?),
+        profile,
+    ))
+}
+
+/// Watches the `.env` file and applies changes to reloadable settings at the next iteration.
+pub(crate) struct ConfigWatcher {
+    path: PathBuf,
+    profile: Option<String>,
+    values: BTreeMap<String, String>,
+    changed: Arc<AtomicBool>,
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    /// Starts watching the `.env` file the settings were loaded from.
+    ///
+    /// The directory is watched rather than the file, so editors that replace the file on save
+    /// are still noticed.
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - The profile whose overrides apply on top of the base settings, if any.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the watcher or an error if there is no `.env` file to watch.
+    pub(crate) fn new(profile: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let path: PathBuf = dotenv::dotenv()?;
+        let values: BTreeMap<String, String> = read_config(&path, profile.as_deref())?;
+        let file_name: OsString = path
+            .file_name()
+            .ok_or("config path has no file name")?
+            .into();
+        let directory: PathBuf = path
+            .parent()
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+
+        let changed: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+        let flag: Arc<AtomicBool> = Arc::clone(&changed);
+        let mut watcher: RecommendedWatcher =
+            notify::recommended_watcher(move |result: notify::Result<Event>| {
+                let Ok(event) = result else {
+                    return;
+                };
+                if (event.kind.is_modify() || event.kind.is_create())
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == Some(file_name.as_os_str()))
+                {
+                    flag.store(true, Ordering::SeqCst);
+                }
+            })?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+        Ok(ConfigWatcher {
+            path,
+            profile,
+            values,
+            changed,
+            _watcher: watcher,
+        })
+    }
+
+    /// Returns the path of the watched file.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Re-reads the file if it changed since the last poll and applies the reloadable settings to
+    /// the environment, so the `.env` getters return the new values.
+    ///
+    /// Rejected settings keep their running value, so they are reported again on every change
+    /// until the bot restarts.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the change, `None` if the file didn't change, or an error if it could
+    /// not be read.
+    pub(crate) fn poll(&mut self) -> Result<Option<ConfigChange>, Box<dyn Error>> {
+        if !self.changed.swap(false, Ordering::SeqCst) {
+            return Ok(None);
+        }
+
+        let change: ConfigChange = diff_config(
+            &self.values,
+            &read_config(&self.path, self.profile.as_deref())?,
+        );
+        for (key, value) in &change.applied {
+            env::set_var(key, value);
+            self.values.insert(key.clone(), value.clone());
+        }
+        Ok(Some(change))
+    }
+}
@@ -0,0 +1,326 @@
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use crate::{backtest::BacktestTrade, monte_carlo::max_drawdown};
+
+/// Trading days per year, used to annualize the Sharpe ratio.
+const TRADING_DAYS: f64 = 252.0;
+
+/// A filled trade from a backtest or the live journal.
+#[derive(Clone, Debug)]
+pub(crate) struct ReportTrade {
+    pub(crate) time: i64,
+    pub(crate) strategy: String,
+    pub(crate) quoted_edge: f64,
+    pub(crate) realized_edge: f64,
+}
+
+/// Performance statistics of a set of trades, with amounts in dollars.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct PerformanceStats {
+    pub(crate) trades: usize,
+    pub(crate) win_rate: f64,
+    pub(crate) total_pnl: f64,
+    pub(crate) average_edge: f64,
+    pub(crate) edge_captured: f64,
+    pub(crate) sharpe: f64,
+    pub(crate) max_drawdown: f64,
+}
+
+/// Performance report with an overall and per-strategy breakdown and the equity curve.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Report {
+    pub(crate) overall: PerformanceStats,
+    pub(crate) by_strategy: BTreeMap<String, PerformanceStats>,
+    pub(crate) equity_curve: Vec<(i64, f64)>,
+}
+
+/// Converts the filled trades of a backtest to report trades.
+///
+/// # Arguments
+///
+/// * `trades` - The trades of a backtest.
+///
+/// # Returns
+///
+/// A `Vec<ReportTrade>` with the filled trades.
+pub(crate) fn from_backtest(trades: &[BacktestTrade]) -> Vec<ReportTrade> {
+    trades
+        .iter()
+        .filter(|trade| trade.filled)
+        .map(|trade| ReportTrade {
+            time: trade.time,
+            strategy: trade.strategy.clone(),
+            quoted_edge: trade.quoted_edge,
+            realized_edge: trade.realized_edge,
+        })
+        .collect()
+}
+
+/// Loads the fills of the live trade journal, skipping other events and lines that fail to parse.
+///
+/// # Arguments
+///
+/// * `path` - The path of the journal file.
+///
+/// # Returns
+///
+/// A `Result` containing the fills in file order or an error.
+pub(crate) fn load_journal(path: &str) -> Result<Vec<ReportTrade>, Box<dyn Error>> {
+    let reader: BufReader<File> = BufReader::new(File::open(path)?);
+    let mut trades: Vec<ReportTrade> = Vec::new();
+    for line in reader.lines() {
+        let Ok(entry) = serde_json::from_str::<Value>(&line?) else {
+            continue;
+        };
+        if entry["event"] != "fill" {
+            continue;
+        }
+        let Some(time) = entry["time"]
+            .as_str()
+            .and_then(|time| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.9f UTC").ok())
+        else {
+            continue;
+        };
+        trades.push(ReportTrade {
+            time: Utc.from_utc_datetime(&time).timestamp_millis(),
+            strategy: entry["strategy"].as_str().unwrap_or_default().to_string(),
+            quoted_edge: entry["quoted_edge"].as_f64().unwrap_or_default(),
+            realized_edge: entry["realized_edge"].as_f64().unwrap_or_default(),
+        });
+    }
+    Ok(trades)
+}
+
+/// Calculates the annualized Sharpe ratio of the daily P&L.
+///
+/// # Arguments
+///
+/// * `trades` - The trades in time order.
+///
+/// # Returns
+///
+/// An `f64` with the Sharpe ratio, or 0 with fewer than two trading days or no variance.
+fn daily_sharpe(trades: &[&ReportTrade]) -> f64 {
+    let mut daily: BTreeMap<String, f64> = BTreeMap::new();
+    for trade in trades {
+        let day: String = Utc
+            .timestamp_millis_opt(trade.time)
+            .single()
+            .map(|time| time.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        *daily.entry(day).or_default() += trade.realized_edge;
+    }
+    if daily.len() < 2 {
+        return 0.0;
+    }
+
+    let count: f64 = daily.len() as f64;
+    let mean: f64 = daily.values().sum::<f64>() / count;
+    let variance: f64 = daily.values().map(|pnl| (pnl - mean).powi(2)).sum::<f64>() / (count - 1.0);
+    if variance == 0.0 {
+        return 0.0;
+    }
+    mean / variance.sqrt() * TRADING_DAYS.sqrt()
+}
+
+/// Calculates the performance statistics of a set of trades.
+///
+/// # Arguments
+///
+/// * `trades` - The trades in time order.
+///
+/// # Returns
+///
+/// A `PerformanceStats` for the trades.
+pub(crate) fn performance_stats(trades: &[&ReportTrade]) -> PerformanceStats {
+    if trades.is_empty() {
+        return PerformanceStats::default();
+    }
+
+    let pnls: Vec<f64> = trades.iter().map(|trade| trade.realized_edge).collect();
+    let total_pnl: f64 = pnls.iter().sum();
+    let quoted_edge: f64 = trades.iter().map(|trade| trade.quoted_edge).sum();
+
+    PerformanceStats {
+        trades: trades.len(),
+        win_rate: pnls.iter().filter(|pnl| **pnl > 0.0).count() as f64 / trades.len() as f64,
+        total_pnl,
+        average_edge: total_pnl / trades.len() as f64,
+        edge_captured: if quoted_edge != 0.0 {
+            total_pnl / quoted_edge
+        } else {
+            0.0
+        },
+        sharpe: daily_sharpe(trades),
+        max_drawdown: max_drawdown(&pnls),
+    }
+}
+
+/// Builds a performance report from a set of trades.
+///
+/// # Arguments
+///
+/// * `trades` - The trades to report on.
+///
+/// # Returns
+///
+/// A `Report` with the trades sorted by time.
+pub(crate) fn build_report(trades: &[ReportTrade]) -> Report {
+    let mut sorted: Vec<&ReportTrade> = trades.iter().collect();
+    sorted.sort_by_key(|trade| trade.time);
+
+    let mut by_strategy: BTreeMap<String, Vec<&ReportTrade>> = BTreeMap::new();
+    for trade in &sorted {
+        by_strategy
+            .entry(trade.strategy.clone())
+            .or_default()
+            .push(trade);
+    }
+
+    let mut equity: f64 = 0.0;
+    let equity_curve: Vec<(i64, f64)> = sorted
+        .iter()
+        .map(|trade| {
+            equity += trade.realized_edge;
+            (trade.time, equity)
+        })
+        .collect();
+
+    Report {
+        overall: performance_stats(&sorted),
+        by_strategy: by_strategy
+            .into_iter()
+            .map(|(strategy, trades)| (strategy, performance_stats(&trades)))
+            .collect(),
+        equity_curve,
+    }
+}
+
+/// Formats a row of performance statistics for the CSV and HTML tables.
+///
+/// # Arguments
+///
+/// * `name` - The name of the row.
+/// * `stats` - The statistics of the row.
+fn stats_row(name: &str, stats: &PerformanceStats) -> Vec<String> {
+    vec![
+        name.to_string(),
+        stats.trades.to_string(),
+        format!("{:.1}", stats.win_rate * 100.0),
+        format!("{:.2}", stats.total_pnl),
+        format!("{:.2}", stats.average_edge),
+        format!("{:.1}", stats.edge_captured * 100.0),
+        format!("{:.2}", stats.sharpe),
+        format!("{:.2}", stats.max_drawdown),
+    ]
+}
+
+/// Column headers of the statistics tables.
+const HEADERS: [&str; 8] = [
+    "strategy",
+    "trades",
+    "win_rate_percent",
+    "total_pnl",
+    "average_edge",
+    "edge_captured_percent",
+    "sharpe",
+    "max_drawdown",
+];
+
+/// Renders the report as CSV, with one row per strategy followed by the overall row.
+///
+/// # Arguments
+///
+/// * `report` - The report to render.
+///
+/// # Returns
+///
+/// A `String` with the CSV contents.
+pub(crate) fn to_csv(report: &Report) -> String {
+    let mut csv: String = HEADERS.join(",") + "\n";
+    for (strategy, stats) in &report.by_strategy {
+        csv += &(stats_row(strategy, stats).join(",") + "\n");
+    }
+    csv += &(stats_row("All", &report.overall).join(",") + "\n");
+    csv
+}
+
+/// Renders the report as a standalone HTML page with the statistics table and equity curve.
+///
+/// # Arguments
+///
+/// * `report` - The report to render.
+///
+/// # Returns
+///
+/// A `String` with the HTML contents.
+pub(crate) fn to_html(report: &Report) -> String {
+    let mut rows: String = String::new();
+    for (strategy, stats) in &report.by_strategy {
+        rows += &format!(
+            "<tr><td>{}</td></tr>\n",
+            stats_row(strategy, stats).join("</td><td>")
+        );
+    }
+    rows += &format!(
+        "<tr><th>{}</th></tr>\n",
+        stats_row("All", &report.overall).join("</th><th>")
+    );
+
+    let (width, height): (f64, f64) = (800.0, 300.0);
+    let (min_equity, max_equity) = report
+        .equity_curve
+        .iter()
+        .fold((0.0_f64, 0.0_f64), |(min, max), (_, equity)| {
+            (min.min(*equity), max.max(*equity))
+        });
+    let range: f64 = (max_equity - min_equity).max(1.0);
+    let steps: f64 = report.equity_curve.len().max(1) as f64;
+    let points: Vec<String> = std::iter::once((0, 0.0))
+        .chain(report.equity_curve.iter().copied())
+        .enumerate()
+        .map(|(index, (_, equity))| {
+            format!(
+                "{:.1},{:.1}",
+                index as f64 / steps * width,
+                height - (equity - min_equity) / range * height
+            )
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Performance Report</title></head>\n<body>\n<h1>Performance Report</h1>\n<table border=\"1\" cellpadding=\"4\">\n<tr><th>{}</th></tr>\n{}</table>\n<h2>Equity Curve</h2>\n<svg width=\"{}\" height=\"{}\"><polyline fill=\"none\" stroke=\"steelblue\" points=\"{}\"/></svg>\n</body>\n</html>\n",
+        HEADERS.join("</th><th>"),
+        rows,
+        width,
+        height,
+        points.join(" ")
+    )
+}
+
+/// Writes the report to a file, as HTML if the path ends in `.html` and as CSV otherwise.
+///
+/// # Arguments
+///
+/// * `report` - The report to write.
+/// * `path` - The output path.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an error.
+pub(crate) fn write_report(report: &Report, path: &str) -> Result<(), Box<dyn Error>> {
+    let contents: String = if path.ends_with(".html") {
+        to_html(report)
+    } else {
+        to_csv(report)
+    };
+    std::fs::write(path, contents)?;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    chain::{ExpiryDate, ExpiryId, Quotes, StrikeKey},
+    latency::StageTimings,
+    structs::Contender,
+};
+
+/// The statistics of one scan, logged as a single line at the end of the iteration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ScanSummary {
+    /// The expirations with quotes.
+    pub(crate) expirations: usize,
+    /// The strikes quoted across the expirations, calls and puts counted once.
+    pub(crate) strikes: usize,
+    /// The contenders above the arb threshold by strategy, before the dedup and risk filters.
+    pub(crate) contenders: BTreeMap<String, usize>,
+    /// The strategy, expiration and arb value of the best contender the scanner returned, above
+    /// the threshold or not.
+    pub(crate) best_edge: Option<(String, ExpiryDate, f64)>,
+    /// The orders submitted, live or paper.
+    pub(crate) orders: usize,
+}
+
+impl ScanSummary {
+    /// Summarizes a scan's quotes and the contenders its scanner returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `quotes` - The quotes of the scan.
+    /// * `contenders` - The contenders, including those below the arb threshold if kept.
+    pub(crate) fn capture(quotes: &Quotes, contenders: &[Contender]) -> Self {
+        let expirations: BTreeSet<ExpiryId> = quotes.keys().map(|(expiry, _, _)| *expiry).collect();
+        let strikes: BTreeSet<(ExpiryId, StrikeKey)> = quotes
+            .keys()
+            .map(|(expiry, _, strike)| (*expiry, *strike))
+            .collect();
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for contender in contenders
+            .iter()
+            .filter(|contender| contender.above_threshold)
+        {
+            *counts.entry(contender.type_spread.clone()).or_default() += 1;
+        }
+        let best_edge: Option<(String, ExpiryDate, f64)> = contenders
+            .iter()
+            .max_by(|a, b| a.arb_val.total_cmp(&b.arb_val))
+            .map(|contender| {
+                (
+                    contender.type_spread.clone(),
+                    contender.exp_date,
+                    contender.arb_val,
+                )
+            });
+        ScanSummary {
+            expirations: expirations.len(),
+            strikes: strikes.len(),
+            contenders: counts,
+            best_edge,
+            orders: 0,
+        }
+    }
+
+    /// Formats the summary as one log line.
+    ///
+    /// # Arguments
+    ///
+    /// * `iteration` - The iteration number.
+    /// * `stage_timings` - The elapsed time of each stage of the iteration.
+    ///
+    /// # Returns
+    ///
+    /// A `String` such as `"Scan 12: 9 expirations, 412 strikes, contenders Butterfly 2 Boxspread
+    /// 0, best edge 0.42 (Butterfly 240701), 2 orders, chain_fetch 1.204s, contender_calc 0.312s
+    /// (total 1.516s)."`.
+    pub(crate) fn line(&self, iteration: u64, stage_timings: &StageTimings) -> String {
+        let contenders: String = if self.contenders.is_empty() {
+            "no contenders".to_string()
+        } else {
+            format!(
+                "contenders {}",
+                self.contenders
+                    .iter()
+                    .map(|(strategy, count)| format!("{} {}", strategy, count))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            )
+        };
+        let best_edge: String = match &self.best_edge {
+            Some((strategy, exp_date, arb_val)) => {
+                format!("best edge {:.2} ({} {})", arb_val, strategy, exp_date)
+            }
+            None => "no edge".to_string(),
+        };
+        let total: f64 = stage_timings.total().as_secs_f64();
+        let timings: String = match stage_timings.summary() {
+            stages if stages.is_empty() => format!("total {:.3}s", total),
+            stages => format!("{} (total {:.3}s)", stages, total),
+        };
+        format!(
+            "Scan {}: {} expirations, {} strikes, {}, {}, {} orders, {}.",
+            iteration, self.expirations, self.strikes, contenders, best_edge, self.orders, timings
+        )
+    }
+}
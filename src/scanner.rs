@@ -0,0 +1,1222 @@
+use chrono::NaiveDate;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use std::error::Error;
+
+use crate::{
+    chain::{ChainKey, ExpiryDate, ExpiryId, OptionChain, Quotes, Right, StrikeKey, StrikeLadder},
+    dividends::{DividendCalendar, EarlyExerciseScreen},
+    helpers::{calc_rank_value, calc_time_difference, calendar_spread_risk_free_profit},
+    logging::log_message,
+    pricing::black_scholes,
+    rates::RateCurve,
+    structs::{
+        BoxFinancing, Contender, Contract, ExpiryFilter, LegPricing, LegQuote, Opt, Reason,
+        StrategyConfig, ThresholdCheck,
+    },
+    surface::IvSurface,
+    watchlist::{structure_ratios, WatchlistEntry},
+};
+
+/// Enum representing option types for different strategies.
+#[derive(Clone, Copy)]
+pub enum OptionType {
+    Calendar,
+    Butterfly,
+    BoxSpread,
+    All,
+}
+
+impl OptionType {
+    /// Converts a string to an `OptionType`.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - A string representing the option type.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Self>` representing the converted option type.
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "1" => Some(OptionType::Calendar),
+            "2" => Some(OptionType::Butterfly),
+            "3" => Some(OptionType::BoxSpread),
+            _ => Some(OptionType::All),
+        }
+    }
+}
+
+/// How much a butterfly's rank is boosted when the butterfly of the other right on the same
+/// strikes also shows edge, confirming its quotes.
+const PARITY_RANK_BOOST: f64 = 1.25;
+
+/// Scanner settings and the spread scanners, independent of the IBKR client so they can run on
+/// live or recorded quotes.
+pub(crate) struct Scanner {
+    pub(crate) arb_val: f64,
+    pub(crate) butterfly_config: StrategyConfig,
+    pub(crate) boxspread_config: StrategyConfig,
+    pub(crate) calendar_config: StrategyConfig,
+    pub(crate) expiry_filter: ExpiryFilter,
+    pub(crate) include_below_threshold: bool,
+    /// How far below the arb threshold a contender may fall and still be kept as a near miss.
+    pub(crate) near_miss_epsilon: Option<f64>,
+    /// Screens out short legs at risk of early exercise, for American-style options.
+    pub(crate) early_exercise: Option<EarlyExerciseScreen>,
+    /// Upcoming dividends of the underlying, empty for indexes.
+    pub(crate) dividends: DividendCalendar,
+    /// The risk-free rate curve box spreads are discounted with.
+    pub(crate) rates: RateCurve,
+    /// The size of the underlying relative to SPX, which the minimum leg bid and calendar strike
+    /// distance scale with.
+    pub(crate) price_scale: f64,
+    /// Which price of each leg's quote the arb value is computed from, by side.
+    pub(crate) leg_pricing: LegPricing,
+    /// Expirations left out of the scan, e.g. for poor quote quality.
+    pub(crate) skipped_expiries: Vec<ExpiryId>,
+    /// Whether boxes short of fair value are sold to borrow, and at what rates.
+    pub(crate) box_financing: Option<BoxFinancing>,
+    /// How far a leg's implied volatility may be from its neighboring strikes' before the
+    /// contender is dropped as a likely bad print.
+    pub(crate) max_iv_deviation: Option<f64>,
+}
+
+impl Scanner {
+    /// Scans the chain for contenders of the given option type.
+    ///
+    /// # Arguments
+    ///
+    /// * `option_type` - The strategies to scan.
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the unsorted contenders or an error. Contenders whose legs don't form
+    /// a valid spread or whose short legs risk early exercise are logged and dropped.
+    pub(crate) fn scan(
+        &self,
+        option_type: &OptionType,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+    ) -> Result<Vec<Contender>, Box<dyn Error>> {
+        let mut contender_contracts_total: Vec<Contender> = Vec::new();
+        let priced_map: Quotes;
+        let contracts_map: &Quotes = if self.leg_pricing.uses_model() {
+            priced_map = self.with_model_prices(contracts_map, chain, current_date, spot);
+            &priced_map
+        } else {
+            contracts_map
+        };
+
+        if matches!(option_type, OptionType::Calendar | OptionType::All) {
+            contender_contracts_total.extend(self.get_calendar_contenders(
+                contracts_map,
+                chain,
+                current_date,
+                minutes_to_close,
+                spot,
+            )?);
+        }
+        if matches!(option_type, OptionType::Butterfly | OptionType::All) {
+            contender_contracts_total.extend(self.get_butterfly_contenders(
+                contracts_map,
+                chain,
+                current_date,
+                minutes_to_close,
+                spot,
+            )?);
+        }
+        if matches!(option_type, OptionType::BoxSpread | OptionType::All) {
+            contender_contracts_total.extend(self.get_boxspread_contenders(
+                contracts_map,
+                chain,
+                current_date,
+                minutes_to_close,
+                spot,
+            )?);
+        }
+
+        contender_contracts_total.retain(|contender| match contender.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                log_message(format!("Dropping {}: {}.", contender.fingerprint(), e));
+                false
+            }
+        });
+        if let Some(max_iv_deviation) = self.max_iv_deviation {
+            let surface: IvSurface =
+                IvSurface::build(chain, contracts_map, current_date, spot, &self.rates);
+            contender_contracts_total.retain(|contender| {
+                match surface.outlier_leg(chain, &contender.contracts, max_iv_deviation) {
+                    Some(outlier) => {
+                        log_message(format!(
+                            "Dropping {} {}: {}.",
+                            contender.type_spread, contender.exp_date, outlier
+                        ));
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+        if self.early_exercise.is_some() {
+            contender_contracts_total.retain(|contender| {
+                match self.early_exercise_risk(contender, current_date, spot) {
+                    Some(risk) => {
+                        log_message(format!(
+                            "Dropping {} {}: {}.",
+                            contender.type_spread, contender.exp_date, risk
+                        ));
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+
+        Ok(contender_contracts_total)
+    }
+
+    /// Prices the structures of a watchlist from the scan's quotes.
+    ///
+    /// A structure shows edge when its edge, the credit of selling the sold legs and buying the
+    /// bought ones less the discounted width of a box, reaches its `min_edge`, or the arb value
+    /// threshold if unset, and every leg has size on the ask.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The watchlist.
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<Contender>` of the structures showing edge, or of every quoted structure if
+    /// contenders below the threshold are kept. Structures with a leg outside the chain or
+    /// without quotes are left out.
+    pub(crate) fn price_watchlist(
+        &self,
+        entries: &[WatchlistEntry],
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+    ) -> Vec<Contender> {
+        if entries.is_empty() {
+            return Vec::new();
+        }
+        let priced_map: Quotes;
+        let contracts_map: &Quotes = if self.leg_pricing.uses_model() {
+            priced_map = self.with_model_prices(contracts_map, chain, current_date, spot);
+            &priced_map
+        } else {
+            contracts_map
+        };
+
+        entries
+            .iter()
+            .filter_map(|entry| {
+                self.price_entry(entry, contracts_map, chain, current_date, minutes_to_close)
+                    .ok()
+            })
+            .filter(|contender| contender.above_threshold || self.include_below_threshold)
+            .collect()
+    }
+
+    /// Prices one structure, e.g. one given on the command line, against the thresholds.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The structure.
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the priced contender, above the threshold or not, or an error if
+    /// its legs don't form the spread, its expiration isn't tradeable or a leg isn't quoted.
+    pub(crate) fn price_structure(
+        &self,
+        entry: &WatchlistEntry,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+    ) -> Result<Contender, String> {
+        let priced_map: Quotes;
+        let contracts_map: &Quotes = if self.leg_pricing.uses_model() {
+            priced_map = self.with_model_prices(contracts_map, chain, current_date, spot);
+            &priced_map
+        } else {
+            contracts_map
+        };
+        self.price_entry(entry, contracts_map, chain, current_date, minutes_to_close)
+    }
+
+    /// Prices a structure on quotes already repriced by the model if configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The structure.
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the priced contender or an error describing why it can't be priced.
+    fn price_entry(
+        &self,
+        entry: &WatchlistEntry,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+    ) -> Result<Contender, String> {
+        let mut contender: Contender = entry.to_contender()?;
+        let date: ExpiryDate = contender.exp_date;
+        let dte: i64 = calc_time_difference(current_date, date);
+        if !self.is_expiry_tradeable(dte, minutes_to_close) {
+            return Err(format!("expiry {} is not tradeable", date));
+        }
+        let opts: Option<Vec<&Opt>> = contender
+            .contracts
+            .iter()
+            .map(|contract| {
+                let key: ChainKey = (
+                    chain.expiry_id(contract.date)?,
+                    Right::from_str(&contract.type_contract)?,
+                    StrikeKey::from_f64(contract.strike),
+                );
+                contracts_map.get(&key)
+            })
+            .collect();
+        let Some(opts) = opts else {
+            return Err("a leg is outside the chain or has no quote".to_string());
+        };
+
+        // Bought legs have a positive ratio and sold legs a negative one.
+        let ratios: &[i32] = structure_ratios(&contender.type_spread);
+        let cost: f64 = opts
+            .iter()
+            .zip(ratios)
+            .map(|(opt, ratio)| {
+                let price: f64 = if *ratio > 0 {
+                    self.buy_price(opt)
+                } else {
+                    self.sell_price(opt)
+                };
+                *ratio as f64 * price
+            })
+            .sum();
+        let fair_value: f64 = if contender.type_spread == "Boxspread" {
+            (contender.contracts[2].strike - contender.contracts[1].strike)
+                * self.rates.discount_factor(dte)
+        } else {
+            0.0
+        };
+        let edge: f64 = -cost - fair_value;
+        let threshold: f64 = entry.min_edge.unwrap_or(self.arb_val);
+
+        let quotes: Vec<LegQuote> = contender
+            .contracts
+            .iter()
+            .zip(&opts)
+            .map(|(contract, opt)| {
+                LegQuote::new(contract.date, &contract.type_contract, contract.strike, opt)
+            })
+            .collect();
+        let formula: String = quotes
+            .iter()
+            .zip(ratios)
+            .enumerate()
+            .map(|(index, (quote, ratio))| {
+                let sign: &str = match (index, *ratio < 0) {
+                    (0, true) => "",
+                    (0, false) => "-",
+                    (_, true) => " + ",
+                    (_, false) => " - ",
+                };
+                match ratio.abs() {
+                    1 => format!("{}{}", sign, quote.leg),
+                    abs => format!("{}{} * {}", sign, abs, quote.leg),
+                }
+            })
+            .collect();
+        let checks: Vec<ThresholdCheck> = vec![
+            ThresholdCheck::new("arb_val", edge, ">=", threshold),
+            min_ask_size_check(&quotes),
+        ];
+        let above_threshold: bool = checks.iter().all(|check| check.passed);
+
+        contender.avg_ask =
+            (quotes.iter().map(|quote| quote.asz).sum::<f64>() / quotes.len() as f64).round();
+        contender.arb_val = (-cost * 100.0).round() / 100.0;
+        contender.rank_value = calc_rank_value(contender.avg_ask, edge, current_date, date);
+        contender.above_threshold = above_threshold;
+        for (contract, opt) in contender.contracts.iter_mut().zip(&opts) {
+            contract.mkt_price = opt.mkt;
+        }
+        contender.reason = Reason {
+            formula: format!("watchlist: {}", formula),
+            checks,
+            quotes,
+        };
+        Ok(contender)
+    }
+
+    /// Checks the short legs of a contender for early-exercise risk.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender` - The contender to check.
+    /// * `current_date` - The current date.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<String>` describing the first short leg at risk, or `None` if there is no screen
+    /// or every short leg passes it.
+    fn early_exercise_risk(
+        &self,
+        contender: &Contender,
+        current_date: NaiveDate,
+        spot: Option<f64>,
+    ) -> Option<String> {
+        let screen: &EarlyExerciseScreen = self.early_exercise.as_ref()?;
+
+        contender
+            .contracts
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| contender.action(*index) == "SELL")
+            .find_map(|(_, contract)| {
+                screen.short_leg_risk(
+                    &contract.type_contract,
+                    contract.strike,
+                    contract.mkt_price,
+                    self.dividends
+                        .dividends_between(current_date, contract.date.date()),
+                    spot,
+                )
+            })
+    }
+
+    /// Copies the quotes with their Black-Scholes prices at each quote's implied volatility.
+    ///
+    /// # Arguments
+    ///
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `current_date` - The current date.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// The quotes with `model` set wherever the spot and implied volatility are known.
+    fn with_model_prices(
+        &self,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        current_date: NaiveDate,
+        spot: Option<f64>,
+    ) -> Quotes {
+        contracts_map
+            .iter()
+            .map(|(key, opt)| {
+                let (expiry, right, strike) = *key;
+                let dte: i64 = calc_time_difference(current_date, chain.dates()[expiry as usize]);
+                let model: Option<f64> = match (spot, opt.iv) {
+                    (Some(spot), Some(iv)) if iv > 0.0 => Some(black_scholes(
+                        right,
+                        spot,
+                        strike.to_f64(),
+                        dte.max(0) as f64 / 365.0,
+                        self.rates.rate(dte),
+                        iv,
+                    )),
+                    _ => None,
+                };
+                (
+                    *key,
+                    Opt {
+                        model,
+                        ..opt.clone()
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the price a bought leg is valued at.
+    ///
+    /// # Arguments
+    ///
+    /// * `opt` - The quote of the leg.
+    fn buy_price(&self, opt: &Opt) -> f64 {
+        self.leg_pricing.buy.price(opt)
+    }
+
+    /// Returns the price a sold leg is valued at.
+    ///
+    /// # Arguments
+    ///
+    /// * `opt` - The quote of the leg.
+    fn sell_price(&self, opt: &Opt) -> f64 {
+        self.leg_pricing.sell.price(opt)
+    }
+
+    /// Returns the minimum bid each leg needs, one dollar for SPX scaled to the underlying.
+    fn min_bid(&self) -> f64 {
+        1.0 * self.price_scale
+    }
+
+    /// Sums the dividends going ex after one expiration and on or before another, which shift the
+    /// forward between them and break the pricing of spreads across the two.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The earlier expiration date.
+    /// * `to` - The later expiration date.
+    ///
+    /// # Returns
+    ///
+    /// An `f64` with the dividends per share, `0.0` if there are none.
+    fn dividend_between(&self, from: ExpiryDate, to: ExpiryDate) -> f64 {
+        if self.dividends.is_empty() {
+            return 0.0;
+        }
+        self.dividends.dividends_between(from.date(), to.date())
+    }
+
+    /// Checks whether a contender short of the arb threshold is close enough to be kept as a near
+    /// miss.
+    ///
+    /// # Arguments
+    ///
+    /// * `shortfall` - How far the arb value is from the threshold, negative if it met it.
+    fn is_near_miss(&self, shortfall: f64) -> bool {
+        self.near_miss_epsilon
+            .is_some_and(|epsilon| shortfall <= epsilon)
+    }
+
+    /// Checks whether an expiration can still be traded, given the same-day (0DTE) filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `dte` - The days to expiry of the expiration.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is `false` for today's expiration once too few minutes remain before the close.
+    fn is_expiry_tradeable(&self, dte: i64, minutes_to_close: i64) -> bool {
+        dte != 0
+            || (!self.expiry_filter.exclude_same_day
+                && minutes_to_close >= self.expiry_filter.min_minutes_to_close)
+    }
+
+    /// Retrieves contender contracts for calendar spreads.
+    ///
+    /// # Arguments
+    ///
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of calendar contenders or an error.
+    pub(crate) fn get_calendar_contenders(
+        &self,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+    ) -> Result<Vec<Contender>, Box<dyn Error>> {
+        let arb_threshold: f64 = 0.0 + self.arb_val;
+        let mut contender_contracts: Vec<Contender> = Vec::new();
+        let calendar_config: &StrategyConfig = &self.calendar_config;
+        let min_bid: f64 = self.min_bid();
+        let max_strike_distance: f64 = 500.0 * self.price_scale;
+
+        let dates_slice: &Vec<ExpiryDate> = chain.dates();
+        if dates_slice.is_empty() {
+            return Ok(contender_contracts);
+        }
+
+        let mean_strikes: &[f64] = chain.strikes(0, Right::Call);
+        let sum: f64 = mean_strikes.iter().sum();
+        let count: f64 = mean_strikes.len() as f64;
+        let mean_val: f64 = sum / count;
+
+        for date_index in 0..(dates_slice.len() - 1) {
+            let date: ExpiryDate = dates_slice[date_index];
+            let expiry: ExpiryId = date_index as ExpiryId;
+            let dte: i64 = calc_time_difference(current_date, date);
+            if !calendar_config.allows_dte(dte)
+                || !self.is_expiry_tradeable(dte, minutes_to_close)
+                || self.skipped_expiries.contains(&expiry)
+                || self.skipped_expiries.contains(&(expiry + 1))
+            {
+                continue;
+            }
+            let ex_dividend: f64 = self.dividend_between(date, dates_slice[date_index + 1]);
+
+            for right in Right::ALL {
+                let contract_type: &str = right.as_str();
+
+                for current_strike in chain.strikes(expiry, right) {
+                    let strike_key: StrikeKey = StrikeKey::from_f64(*current_strike);
+                    let current_opt: &Opt = contracts_map
+                        .get(&(expiry, right, strike_key))
+                        .ok_or("Error accessing current contract")?;
+
+                    let next_date: ExpiryDate = dates_slice[date_index + 1];
+                    let next_key: ChainKey = (expiry + 1, right, strike_key);
+
+                    if chain.conid(&next_key).is_some() {
+                        let next_opt: &Opt = contracts_map
+                            .get(&next_key)
+                            .ok_or("Error accessing next contract")?;
+
+                        // The front month is sold and the back month bought.
+                        let arb_val: f64 = self.sell_price(current_opt) - self.buy_price(next_opt);
+                        let above_threshold: bool = arb_val >= arb_threshold
+                            && calendar_spread_risk_free_profit(current_strike, arb_val) > 0.25;
+
+                        if (above_threshold
+                            || self.include_below_threshold
+                            || self.is_near_miss(arb_threshold - arb_val))
+                            && current_opt.bid > min_bid
+                            && next_opt.bid > min_bid
+                            && current_opt.asz > 0.0
+                            && next_opt.asz > 0.0
+                            && next_date.days_from(date.date()) == 1
+                            && ex_dividend <= 0.0
+                            && (current_strike - mean_val).abs() <= max_strike_distance
+                            && calendar_config.allows_moneyness(
+                                *current_strike,
+                                *current_strike,
+                                spot,
+                            )
+                        {
+                            let avg_ask: f64 = ((current_opt.asz + next_opt.asz) / 2.0).round();
+                            let rank_value: f64 =
+                                calc_rank_value(avg_ask, arb_val, current_date, date);
+                            let quotes: Vec<LegQuote> = vec![
+                                LegQuote::new(date, contract_type, *current_strike, current_opt),
+                                LegQuote::new(next_date, contract_type, *current_strike, next_opt),
+                            ];
+                            let reason: Reason = Reason {
+                                formula: format!("{} - {}", quotes[0].leg, quotes[1].leg),
+                                checks: vec![
+                                    ThresholdCheck::new("arb_val", arb_val, ">=", arb_threshold),
+                                    ThresholdCheck::new(
+                                        "risk_free_profit",
+                                        calendar_spread_risk_free_profit(current_strike, arb_val),
+                                        ">",
+                                        0.25,
+                                    ),
+                                    ThresholdCheck::new(
+                                        "strike_distance",
+                                        (current_strike - mean_val).abs(),
+                                        "<=",
+                                        max_strike_distance,
+                                    ),
+                                    ThresholdCheck::new("ex_dividend", ex_dividend, "<=", 0.0),
+                                    min_bid_check(&quotes, min_bid),
+                                    min_ask_size_check(&quotes),
+                                ],
+                                quotes,
+                            };
+
+                            contender_contracts.push(Contender {
+                                arb_val: (arb_val * 100.0).round() / 100.0,
+                                avg_ask,
+                                type_spread: "Calendar".to_string(),
+                                exp_date: date,
+                                rank_value,
+                                scan_time: 0,
+                                fill_probability: 0.0,
+                                above_threshold,
+                                contracts: vec![
+                                    Contract {
+                                        strike: *current_strike,
+                                        mkt_price: current_opt.mkt,
+                                        date,
+                                        type_contract: contract_type.to_string(),
+                                    },
+                                    Contract {
+                                        strike: *current_strike,
+                                        mkt_price: next_opt.mkt,
+                                        date: next_date,
+                                        type_contract: contract_type.to_string(),
+                                    },
+                                ],
+                                reason,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(contender_contracts)
+    }
+
+    /// Retrieves contender contracts for butterfly spreads.
+    ///
+    /// Expirations are scanned in parallel and the results are merged in expiration order.
+    ///
+    /// # Arguments
+    ///
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of butterfly contenders or an error.
+    pub(crate) fn get_butterfly_contenders(
+        &self,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+    ) -> Result<Vec<Contender>, Box<dyn Error>> {
+        let contenders_per_expiry: Vec<Vec<Contender>> = (0..chain.dates().len())
+            .into_par_iter()
+            .map(|date_index| {
+                self.get_butterfly_contenders_for_expiry(
+                    contracts_map,
+                    chain,
+                    date_index as ExpiryId,
+                    current_date,
+                    minutes_to_close,
+                    spot,
+                )
+            })
+            .collect::<Result<Vec<Vec<Contender>>, Box<dyn Error + Send + Sync>>>()
+            .map_err(|e| e as Box<dyn Error>)?;
+
+        Ok(contenders_per_expiry.into_iter().flatten().collect())
+    }
+
+    /// Retrieves butterfly contenders for a single expiration.
+    ///
+    /// # Arguments
+    ///
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `expiry` - The expiration to scan.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of butterfly contenders for the expiration or an error.
+    pub(crate) fn get_butterfly_contenders_for_expiry(
+        &self,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        expiry: ExpiryId,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+    ) -> Result<Vec<Contender>, Box<dyn Error + Send + Sync>> {
+        let arb_threshold: f64 = 0.0 + self.arb_val;
+        let mut contender_contracts: Vec<Contender> = Vec::new();
+        // The arb value of every quoted butterfly, by right and wing, center and wing strikes.
+        let mut fly_values: FxHashMap<(Right, StrikeKey, StrikeKey, StrikeKey), f64> =
+            FxHashMap::default();
+        let date: ExpiryDate = chain.dates()[expiry as usize];
+        let butterfly_config: &StrategyConfig = &self.butterfly_config;
+        let min_bid: f64 = self.min_bid();
+        let dte: i64 = calc_time_difference(current_date, date);
+        if !butterfly_config.allows_dte(dte)
+            || !self.is_expiry_tradeable(dte, minutes_to_close)
+            || self.skipped_expiries.contains(&expiry)
+        {
+            return Ok(contender_contracts);
+        }
+
+        for &wing_width in &butterfly_config.wing_widths {
+            for right in Right::ALL {
+                let contract_type: &str = right.as_str();
+                let ladder: &StrikeLadder = chain.ladder(expiry, right);
+                for current_strike in ladder.strikes() {
+                    let (Some(left_strike), Some(right_strike)) = (
+                        ladder.offset(*current_strike, -wing_width),
+                        ladder.offset(*current_strike, wing_width),
+                    ) else {
+                        continue;
+                    };
+                    if !butterfly_config.allows_moneyness(left_strike, right_strike, spot)
+                        || ladder.has_gap_between(left_strike, right_strike)
+                    {
+                        continue;
+                    }
+
+                    let current_contract: &Opt = contracts_map
+                        .get(&(expiry, right, StrikeKey::from_f64(*current_strike)))
+                        .ok_or("Error accessing current contract")?;
+                    let left_contract: &Opt = contracts_map
+                        .get(&(expiry, right, StrikeKey::from_f64(left_strike)))
+                        .ok_or("Error accessing left contract")?;
+                    let right_contract: &Opt = contracts_map
+                        .get(&(expiry, right, StrikeKey::from_f64(right_strike)))
+                        .ok_or("Error accessing right contract")?;
+
+                    let arb_val: f64 = (2.0 * self.sell_price(current_contract))
+                        - (self.buy_price(left_contract) + self.buy_price(right_contract));
+                    fly_values.insert(
+                        (
+                            right,
+                            StrikeKey::from_f64(left_strike),
+                            StrikeKey::from_f64(*current_strike),
+                            StrikeKey::from_f64(right_strike),
+                        ),
+                        arb_val,
+                    );
+                    let above_threshold: bool = arb_val >= arb_threshold;
+                    // The wings are bought and the body sold twice.
+                    let net_delta: Option<f64> = match (
+                        left_contract.delta,
+                        current_contract.delta,
+                        right_contract.delta,
+                    ) {
+                        (Some(left), Some(current), Some(right)) => {
+                            Some(left + right - 2.0 * current)
+                        }
+                        _ => None,
+                    };
+
+                    if (above_threshold
+                        || self.include_below_threshold
+                        || self.is_near_miss(arb_threshold - arb_val))
+                        && butterfly_config.allows_net_delta(net_delta)
+                        && left_contract.bid > min_bid
+                        && right_contract.bid > min_bid
+                        && current_contract.bid > min_bid
+                        && left_contract.asz > 0.0
+                        && right_contract.asz > 0.0
+                        && current_contract.asz > 0.0
+                    {
+                        let avg_ask: f64 = ((left_contract.asz
+                            + right_contract.asz
+                            + (2.0 * current_contract.asz))
+                            / 4.0)
+                            .round();
+                        let rank_value: f64 = calc_rank_value(avg_ask, arb_val, current_date, date);
+                        let quotes: Vec<LegQuote> = vec![
+                            LegQuote::new(date, contract_type, left_strike, left_contract),
+                            LegQuote::new(date, contract_type, *current_strike, current_contract),
+                            LegQuote::new(date, contract_type, right_strike, right_contract),
+                        ];
+                        let mut checks: Vec<ThresholdCheck> = vec![
+                            ThresholdCheck::new("arb_val", arb_val, ">=", arb_threshold),
+                            min_bid_check(&quotes, min_bid),
+                            min_ask_size_check(&quotes),
+                        ];
+                        if let (Some(max_net_delta), Some(net_delta)) =
+                            (butterfly_config.max_net_delta, net_delta)
+                        {
+                            checks.push(ThresholdCheck::new(
+                                "net_delta",
+                                net_delta.abs(),
+                                "<=",
+                                max_net_delta,
+                            ));
+                        }
+                        let reason: Reason = Reason {
+                            formula: format!(
+                                "2 * {} - ({} + {})",
+                                quotes[1].leg, quotes[0].leg, quotes[2].leg
+                            ),
+                            checks,
+                            quotes,
+                        };
+
+                        contender_contracts.push(Contender {
+                            arb_val: (arb_val * 100.0).round() / 100.0,
+                            avg_ask,
+                            type_spread: "Butterfly".to_string(),
+                            exp_date: date,
+                            rank_value,
+                            scan_time: 0,
+                            fill_probability: 0.0,
+                            above_threshold,
+                            contracts: vec![
+                                Contract {
+                                    strike: left_strike,
+                                    mkt_price: left_contract.mkt,
+                                    date,
+                                    type_contract: contract_type.to_string(),
+                                },
+                                Contract {
+                                    strike: *current_strike,
+                                    mkt_price: current_contract.mkt,
+                                    date,
+                                    type_contract: contract_type.to_string(),
+                                },
+                                Contract {
+                                    strike: right_strike,
+                                    mkt_price: right_contract.mkt,
+                                    date,
+                                    type_contract: contract_type.to_string(),
+                                },
+                            ],
+                            reason,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.check_butterfly_parity(&mut contender_contracts, &fly_values, arb_threshold);
+        Ok(contender_contracts)
+    }
+
+    /// Cross-checks butterflies against the butterfly of the other right on the same strikes,
+    /// which put-call parity prices the same. Edge on only one side is likely a quote error, so
+    /// that butterfly falls below the threshold; edge on both boosts its rank.
+    ///
+    /// # Arguments
+    ///
+    /// * `contenders` - The butterfly contenders of an expiration.
+    /// * `fly_values` - The arb value of every quoted butterfly of the expiration, by right and
+    ///   strikes.
+    /// * `arb_threshold` - The arb value a butterfly needs.
+    fn check_butterfly_parity(
+        &self,
+        contenders: &mut [Contender],
+        fly_values: &FxHashMap<(Right, StrikeKey, StrikeKey, StrikeKey), f64>,
+        arb_threshold: f64,
+    ) {
+        for contender in contenders
+            .iter_mut()
+            .filter(|contender| contender.above_threshold)
+        {
+            let [left, current, right] = contender.contracts.as_slice() else {
+                continue;
+            };
+            let other_right: Right = match Right::from_str(&left.type_contract) {
+                Some(Right::Call) => Right::Put,
+                Some(Right::Put) => Right::Call,
+                None => continue,
+            };
+            let Some(other_arb_val) = fly_values.get(&(
+                other_right,
+                StrikeKey::from_f64(left.strike),
+                StrikeKey::from_f64(current.strike),
+                StrikeKey::from_f64(right.strike),
+            )) else {
+                continue;
+            };
+
+            let check: ThresholdCheck =
+                ThresholdCheck::new("parity_arb_val", *other_arb_val, ">=", arb_threshold);
+            if check.passed {
+                contender.rank_value *= PARITY_RANK_BOOST;
+            } else {
+                log_message(format!(
+                    "Likely quote error on {}: the {} butterfly on the same strikes has an arb value of {:.2}.",
+                    contender.fingerprint(),
+                    other_right.as_str(),
+                    other_arb_val
+                ));
+                contender.above_threshold = false;
+            }
+            // Next to the arb value check it cross-checks.
+            contender.reason.checks.insert(1, check);
+        }
+    }
+
+    /// Retrieves contender contracts for box spread strategies.
+    ///
+    /// Expirations are scanned in parallel and the results are merged in expiration order.
+    ///
+    /// # Arguments
+    ///
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of box spread contenders or an error.
+    pub(crate) fn get_boxspread_contenders(
+        &self,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+    ) -> Result<Vec<Contender>, Box<dyn Error>> {
+        let contenders_per_expiry: Vec<Vec<Contender>> = (0..chain.dates().len())
+            .into_par_iter()
+            .map(|date_index| {
+                self.get_boxspread_contenders_for_expiry(
+                    contracts_map,
+                    chain,
+                    date_index as ExpiryId,
+                    current_date,
+                    minutes_to_close,
+                    spot,
+                )
+            })
+            .collect::<Result<Vec<Vec<Contender>>, Box<dyn Error + Send + Sync>>>()
+            .map_err(|e| e as Box<dyn Error>)?;
+
+        Ok(contenders_per_expiry.into_iter().flatten().collect())
+    }
+
+    /// Retrieves box spread contenders for a single expiration.
+    ///
+    /// # Arguments
+    ///
+    /// * `contracts_map` - A map of option quotes keyed by chain key.
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `expiry` - The expiration to scan.
+    /// * `current_date` - The current date.
+    /// * `minutes_to_close` - The minutes remaining before today's close.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of box spread contenders for the expiration or an error.
+    pub(crate) fn get_boxspread_contenders_for_expiry(
+        &self,
+        contracts_map: &Quotes,
+        chain: &OptionChain,
+        expiry: ExpiryId,
+        current_date: NaiveDate,
+        minutes_to_close: i64,
+        spot: Option<f64>,
+    ) -> Result<Vec<Contender>, Box<dyn Error + Send + Sync>> {
+        let mut contender_contracts: Vec<Contender> = Vec::new();
+        let date: ExpiryDate = chain.dates()[expiry as usize];
+        let boxspread_config: &StrategyConfig = &self.boxspread_config;
+        let min_bid: f64 = self.min_bid();
+        let dte: i64 = calc_time_difference(current_date, date);
+        if !boxspread_config.allows_dte(dte)
+            || !self.is_expiry_tradeable(dte, minutes_to_close)
+            || self.skipped_expiries.contains(&expiry)
+        {
+            return Ok(contender_contracts);
+        }
+
+        let calls: &StrikeLadder = chain.ladder(expiry, Right::Call);
+        let puts: &StrikeLadder = chain.ladder(expiry, Right::Put);
+
+        let discount_factor: f64 = self.rates.discount_factor(dte);
+        let years: f64 = dte as f64 / 365.0;
+        let max_financing_rate: Option<f64> = self
+            .box_financing
+            .as_ref()
+            .map(|box_financing| self.rates.rate(dte) + box_financing.max_rate_spread);
+        // Edge is ranked per point of width, in units of the narrowest width, so wide and narrow
+        // boxes compete on the margin they tie up rather than the raw credit.
+        let reference_width: f64 = boxspread_config
+            .wing_widths
+            .iter()
+            .copied()
+            .fold(f64::INFINITY, f64::min);
+
+        for &wing_width in &boxspread_config.wing_widths {
+            // The box pays the wing width at expiry, so it is worth that discounted to today.
+            let fair_value: f64 = wing_width * discount_factor;
+            let arb_threshold: f64 = -fair_value - self.arb_val;
+
+            for current_strike in calls.strikes() {
+                let Some(right_strike) = calls.offset(*current_strike, wing_width) else {
+                    continue;
+                };
+                if !puts.contains(*current_strike)
+                    || !puts.contains(right_strike)
+                    || calls.has_gap_between(*current_strike, right_strike)
+                    || puts.has_gap_between(*current_strike, right_strike)
+                    || !boxspread_config.allows_moneyness(*current_strike, right_strike, spot)
+                {
+                    continue;
+                }
+
+                let current_c: &Opt = contracts_map
+                    .get(&(expiry, Right::Call, StrikeKey::from_f64(*current_strike)))
+                    .ok_or("Error accessing current call contract")?;
+                let current_p: &Opt = contracts_map
+                    .get(&(expiry, Right::Put, StrikeKey::from_f64(*current_strike)))
+                    .ok_or("Error accessing current put contract")?;
+                let right_c: &Opt = contracts_map
+                    .get(&(expiry, Right::Call, StrikeKey::from_f64(right_strike)))
+                    .ok_or("Error accessing right call contract")?;
+                let right_p: &Opt = contracts_map
+                    .get(&(expiry, Right::Put, StrikeKey::from_f64(right_strike)))
+                    .ok_or("Error accessing right put contract")?;
+
+                // The lower put and upper call are bought, the lower call and upper put sold.
+                let arb_val: f64 = (self.buy_price(current_p) + self.buy_price(right_c))
+                    - (self.sell_price(current_c) + self.sell_price(right_p));
+                let above_threshold: bool = arb_val <= arb_threshold;
+                // A box sold short of fair value borrows its credit until expiry at the rate the
+                // discount implies.
+                let financing_check: Option<ThresholdCheck> = match max_financing_rate {
+                    Some(max_rate) if !above_threshold && -arb_val > 0.0 && years > 0.0 => {
+                        let implied_rate: f64 = (wing_width / -arb_val).ln() / years;
+                        Some(ThresholdCheck::new(
+                            "implied_rate",
+                            implied_rate,
+                            "<=",
+                            max_rate,
+                        ))
+                    }
+                    _ => None,
+                };
+                let financing: bool = financing_check.as_ref().is_some_and(|check| check.passed);
+
+                if (above_threshold
+                    || financing
+                    || self.include_below_threshold
+                    || self.is_near_miss(arb_val - arb_threshold))
+                    && current_c.bid > min_bid
+                    && current_p.bid > min_bid
+                    && right_c.bid > min_bid
+                    && right_p.bid > min_bid
+                    && current_c.asz > 0.0
+                    && current_p.asz > 0.0
+                    && right_c.asz > 0.0
+                    && right_p.asz > 0.0
+                {
+                    let avg_ask: f64 =
+                        ((current_c.asz + right_c.asz + current_p.asz + right_p.asz) / 4.0).round();
+                    // Financing boxes are ranked by the interest saved against the highest rate
+                    // they may borrow at.
+                    let edge: f64 = match &financing_check {
+                        Some(check) if financing => {
+                            (check.threshold - check.value) * years * wing_width
+                        }
+                        _ => -arb_val - fair_value,
+                    };
+                    let normalized_edge: f64 = edge / wing_width * reference_width;
+                    let rank_value: f64 =
+                        calc_rank_value(avg_ask, normalized_edge, current_date, date);
+                    let quotes: Vec<LegQuote> = vec![
+                        LegQuote::new(date, "P", *current_strike, current_p),
+                        LegQuote::new(date, "C", *current_strike, current_c),
+                        LegQuote::new(date, "C", right_strike, right_c),
+                        LegQuote::new(date, "P", right_strike, right_p),
+                    ];
+                    let reason: Reason = Reason {
+                        formula: format!(
+                            "({} + {}) - ({} + {})",
+                            quotes[0].leg, quotes[2].leg, quotes[1].leg, quotes[3].leg
+                        ),
+                        checks: vec![
+                            match financing_check {
+                                Some(check) if financing => check,
+                                _ => ThresholdCheck::new("arb_val", arb_val, "<=", arb_threshold),
+                            },
+                            min_bid_check(&quotes, min_bid),
+                            min_ask_size_check(&quotes),
+                        ],
+                        quotes,
+                    };
+
+                    contender_contracts.push(Contender {
+                        arb_val: (-arb_val * 100.0).round() / 100.0,
+                        avg_ask,
+                        type_spread: "Boxspread".to_string(),
+                        exp_date: date,
+                        rank_value,
+                        scan_time: 0,
+                        fill_probability: 0.0,
+                        above_threshold: above_threshold || financing,
+                        contracts: vec![
+                            Contract {
+                                strike: *current_strike,
+                                mkt_price: current_p.mkt,
+                                date,
+                                type_contract: "P".to_string(),
+                            },
+                            Contract {
+                                strike: *current_strike,
+                                mkt_price: current_c.mkt,
+                                date,
+                                type_contract: "C".to_string(),
+                            },
+                            Contract {
+                                strike: right_strike,
+                                mkt_price: right_c.mkt,
+                                date,
+                                type_contract: "C".to_string(),
+                            },
+                            Contract {
+                                strike: right_strike,
+                                mkt_price: right_p.mkt,
+                                date,
+                                type_contract: "P".to_string(),
+                            },
+                        ],
+                        reason,
+                    });
+                }
+            }
+        }
+
+        Ok(contender_contracts)
+    }
+}
+
+/// Checks the smallest bid of the legs against the minimum the scanners require.
+///
+/// # Arguments
+///
+/// * `quotes` - The quotes of the legs.
+/// * `min_bid_threshold` - The minimum bid of each leg.
+fn min_bid_check(quotes: &[LegQuote], min_bid_threshold: f64) -> ThresholdCheck {
+    let min_bid: f64 = quotes
+        .iter()
+        .map(|quote| quote.bid)
+        .fold(f64::INFINITY, f64::min);
+    ThresholdCheck::new("min_bid", min_bid, ">", min_bid_threshold)
+}
+
+/// Checks that every leg has size on the ask.
+///
+/// # Arguments
+///
+/// * `quotes` - The quotes of the legs.
+fn min_ask_size_check(quotes: &[LegQuote]) -> ThresholdCheck {
+    let min_ask_size: f64 = quotes
+        .iter()
+        .map(|quote| quote.asz)
+        .fold(f64::INFINITY, f64::min);
+    ThresholdCheck::new("min_ask_size", min_ask_size, ">", 0.0)
+}
@@ -0,0 +1,255 @@
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::{America::New_York, Tz};
+use rand::Rng;
+use std::time::Duration;
+
+use crate::helpers::is_us_stock_market_open;
+
+/// A time-of-day window with its own scan interval.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScheduleWindow {
+    pub(crate) start: NaiveTime,
+    pub(crate) end: NaiveTime,
+    /// Seconds between scans, or `None` to not scan in the window.
+    pub(crate) interval: Option<u64>,
+}
+
+/// When and how often the bot scans, in New York time.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScanSchedule {
+    /// The windows, checked in order; the first one containing the time applies.
+    pub(crate) windows: Vec<ScheduleWindow>,
+    /// Seconds between scans outside every window, or `None` to only scan inside the windows.
+    pub(crate) default_interval: Option<u64>,
+    /// How much each interval is randomly stretched or shrunk, in percent.
+    pub(crate) jitter_percent: f64,
+    /// The `(start, end)` times the bot scans and journals in but doesn't submit orders, e.g.
+    /// around the open and close auctions.
+    pub(crate) no_trade_windows: Vec<(NaiveTime, NaiveTime)>,
+}
+
+impl ScanSchedule {
+    /// Returns the scan interval at a time of day.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time of day in New York.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<u64>` with the seconds between scans, or `None` if the bot doesn't scan then.
+    pub(crate) fn interval_at(&self, time: NaiveTime) -> Option<u64> {
+        self.windows
+            .iter()
+            .find(|window| window.start <= time && time < window.end)
+            .map_or(self.default_interval, |window| window.interval)
+    }
+
+    /// Returns whether orders may be submitted at a time of day, i.e. it's outside every no-trade
+    /// window.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time of day in New York.
+    pub(crate) fn trading_allowed_at(&self, time: NaiveTime) -> bool {
+        !self
+            .no_trade_windows
+            .iter()
+            .any(|(start, end)| *start <= time && time < *end)
+    }
+
+    /// Calculates how long to wait until scanning resumes.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time of day in New York, at which the bot doesn't scan.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Duration>` until the next time scanning is allowed, or `None` if it isn't
+    /// allowed again today.
+    pub(crate) fn next_scan_in(&self, time: NaiveTime) -> Option<Duration> {
+        self.windows
+            .iter()
+            .flat_map(|window| [window.start, window.end])
+            .filter(|boundary| *boundary > time && self.interval_at(*boundary).is_some())
+            .min()
+            .and_then(|boundary| (boundary - time).to_std().ok())
+    }
+
+    /// Applies the random jitter to an interval, so scans don't land at predictable times.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The interval in seconds.
+    /// * `rng` - The random number generator.
+    ///
+    /// # Returns
+    ///
+    /// A `Duration` within `jitter_percent` of the interval, and at least one second.
+    pub(crate) fn jitter<R: Rng>(&self, interval: u64, rng: &mut R) -> Duration {
+        let jitter: f64 = self.jitter_percent.clamp(0.0, 100.0) / 100.0;
+        let factor: f64 = if jitter > 0.0 {
+            rng.gen_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            1.0
+        };
+        Duration::from_secs_f64((interval as f64 * factor).max(1.0))
+    }
+}
+
+/// A time-of-day window with its own arbitrage threshold and discount.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ThresholdWindow {
+    pub(crate) start: NaiveTime,
+    pub(crate) end: NaiveTime,
+    pub(crate) arb_val: f64,
+    /// The discount applied to orders, or `None` to keep `DISCOUNT_VALUE`.
+    pub(crate) discount_value: Option<f64>,
+}
+
+/// The arbitrage threshold and discount by time of day, in New York time, since spreads and fill
+/// quality vary predictably intraday.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ThresholdSchedule {
+    /// The windows, checked in order; the first one containing the time applies.
+    pub(crate) windows: Vec<ThresholdWindow>,
+}
+
+impl ThresholdSchedule {
+    /// Returns the thresholds at a time of day.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time of day in New York.
+    /// * `arb_val` - The arbitrage threshold outside every window.
+    /// * `discount_value` - The discount outside every window.
+    ///
+    /// # Returns
+    ///
+    /// The `(arb_val, discount_value)` that apply at the time.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let (arb_val, discount_value) = threshold_schedule.thresholds_at(ny_time, 0.10, 0.0);
+    /// ```
+    pub(crate) fn thresholds_at(
+        &self,
+        time: NaiveTime,
+        arb_val: f64,
+        discount_value: f64,
+    ) -> (f64, f64) {
+        self.windows
+            .iter()
+            .find(|window| window.start <= time && time < window.end)
+            .map_or((arb_val, discount_value), |window| {
+                (
+                    window.arb_val,
+                    window.discount_value.unwrap_or(discount_value),
+                )
+            })
+    }
+}
+
+/// The SPX session an order is placed in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TradingSession {
+    /// Regular trading hours.
+    Regular,
+    /// Global trading hours, the overnight session from 8:15 PM to 9:25 AM ET.
+    Overnight,
+}
+
+impl TradingSession {
+    /// Returns whether orders in the session must be allowed to work outside regular hours.
+    pub(crate) fn outside_rth(&self) -> bool {
+        *self == TradingSession::Overnight
+    }
+
+    /// Returns the session's name for logging.
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            TradingSession::Regular => "regular",
+            TradingSession::Overnight => "overnight",
+        }
+    }
+}
+
+/// The sessions the bot trades in, in New York time.
+///
+/// Global trading hours run from Sunday to Thursday evening into the next morning. Holidays are
+/// not observed, as for regular hours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SessionCalendar {
+    /// Whether the overnight session is traded.
+    pub(crate) overnight: bool,
+}
+
+impl SessionCalendar {
+    /// The time the overnight session opens in the evening.
+    const OVERNIGHT_OPEN: (u32, u32) = (20, 15);
+    /// The time the overnight session closes in the morning.
+    const OVERNIGHT_CLOSE: (u32, u32) = (9, 25);
+
+    /// Returns the session open at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time in UTC.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<TradingSession>` with the open session, or `None` if the bot doesn't trade then.
+    pub(crate) fn session_at(&self, time: DateTime<Utc>) -> Option<TradingSession> {
+        if is_us_stock_market_open(time) {
+            return Some(TradingSession::Regular);
+        }
+        if !self.overnight {
+            return None;
+        }
+
+        let ny_time: DateTime<Tz> = time.with_timezone(&New_York);
+        let (open_hour, open_minute) = Self::OVERNIGHT_OPEN;
+        let (close_hour, close_minute) = Self::OVERNIGHT_CLOSE;
+        let evening: bool = ny_time.time() >= NaiveTime::from_hms_opt(open_hour, open_minute, 0)?
+            && matches!(
+                ny_time.weekday(),
+                Weekday::Sun | Weekday::Mon | Weekday::Tue | Weekday::Wed | Weekday::Thu
+            );
+        let morning: bool = ny_time.time() < NaiveTime::from_hms_opt(close_hour, close_minute, 0)?
+            && !matches!(ny_time.weekday(), Weekday::Sat | Weekday::Sun);
+        (evening || morning).then_some(TradingSession::Overnight)
+    }
+
+    /// Calculates how long to wait until the next session opens.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time in UTC, at which no session is open.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Duration>` until the next session, or `None` if only regular hours are traded,
+    /// in which case the bot stops at the close.
+    pub(crate) fn next_session_in(&self, time: DateTime<Utc>) -> Option<Duration> {
+        if !self.overnight {
+            return None;
+        }
+
+        let ny_time: DateTime<Tz> = time.with_timezone(&New_York);
+        (0..=3)
+            .flat_map(|days| {
+                let date = ny_time.date_naive() + ChronoDuration::days(days);
+                [(9, 30), Self::OVERNIGHT_OPEN].map(|(hour, minute)| {
+                    New_York
+                        .with_ymd_and_hms(date.year(), date.month(), date.day(), hour, minute, 0)
+                        .single()
+                })
+            })
+            .flatten()
+            .map(|open| open.with_timezone(&Utc))
+            .find(|open| *open > time && self.session_at(*open).is_some())
+            .and_then(|open| (open - time).to_std().ok())
+    }
+}
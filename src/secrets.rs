@@ -0,0 +1,164 @@
+use reqwest::blocking::{Client, Response};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    process::{Command, Output},
+};
+
+use crate::structs::{SecretBackend, SecretsConfig};
+
+/// Reads the string values of a JSON object of secrets, skipping values that aren't strings.
+///
+/// Vault KV version 2 nests the secrets under `data.data`, version 1 under `data`, and AWS
+/// Secrets Manager returns the object itself.
+///
+/// # Arguments
+///
+/// * `body` - The JSON secret or Vault response.
+///
+/// # Returns
+///
+/// A `BTreeMap<String, String>` with the secrets by name.
+pub(crate) fn parse_secrets(body: &Value) -> BTreeMap<String, String> {
+    let secrets: &Value = if body["data"]["data"].is_object() {
+        &body["data"]["data"]
+    } else if body["data"].is_object() {
+        &body["data"]
+    } else {
+        body
+    };
+    secrets
+        .as_object()
+        .map(|secrets| {
+            secrets
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads the secrets from a Vault KV store.
+///
+/// # Arguments
+///
+/// * `addr` - The Vault address, e.g. `https://vault:8200`.
+/// * `token` - The Vault token.
+/// * `path` - The secret path, e.g. `secret/data/trading_bot`.
+///
+/// # Returns
+///
+/// A `Result` containing the secrets by name or an error.
+fn fetch_vault(
+    addr: &str,
+    token: &str,
+    path: &str,
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let response: Response = Client::new()
+        .get(format!("{}/v1/{}", addr.trim_end_matches('/'), path))
+        .header("X-Vault-Token", token)
+        .send()?;
+    if !response.status().is_success() {
+        return Err(format!("Vault returned {}", response.status()).into());
+    }
+    Ok(parse_secrets(&response.json()?))
+}
+
+/// Reads the secrets from AWS Secrets Manager with the AWS CLI, so its usual credential chain
+/// (environment, profile or instance role) applies.
+///
+/// # Arguments
+///
+/// * `secret_id` - The name or ARN of a secret holding a JSON object.
+///
+/// # Returns
+///
+/// A `Result` containing the secrets by name or an error.
+fn fetch_aws(secret_id: &str) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let output: Output = Command::new("aws")
+        .args([
+            "secretsmanager",
+            "get-secret-value",
+            "--secret-id",
+            secret_id,
+            "--query",
+            "SecretString",
+            "--output",
+            "text",
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "aws secretsmanager failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(parse_secrets(&serde_json::from_slice(&output.stdout)?))
+}
+
+/// Reads the secrets from the OS keyring, one entry per secret name.
+///
+/// # Arguments
+///
+/// * `service` - The keyring service the entries are stored under.
+/// * `keys` - The secret names.
+///
+/// # Returns
+///
+/// A `Result` containing the secrets found by name or an error.
+#[cfg(feature = "keyring")]
+fn fetch_keyring(
+    service: &str,
+    keys: &[String],
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut secrets: BTreeMap<String, String> = BTreeMap::new();
+    for key in keys {
+        match keyring::Entry::new(service, key)?.get_password() {
+            Ok(secret) => {
+                secrets.insert(key.clone(), secret);
+            }
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(secrets)
+}
+
+/// Loads the configured secrets from the secret backend into the environment, where they take
+/// precedence over the `.env` file. Secrets missing from the backend keep their `.env` value.
+///
+/// # Arguments
+///
+/// * `config` - The secret backend and the settings it holds.
+///
+/// # Returns
+///
+/// A `Result` containing the names of the loaded secrets or an error if the backend failed.
+pub(crate) fn load_secrets(config: &SecretsConfig) -> Result<Vec<String>, Box<dyn Error>> {
+    let secrets: BTreeMap<String, String> = match &config.backend {
+        SecretBackend::Env => return Ok(Vec::new()),
+        SecretBackend::Vault { addr, token, path } => fetch_vault(addr, token, path)?,
+        SecretBackend::Aws { secret_id } => fetch_aws(secret_id)?,
+        #[cfg(feature = "keyring")]
+        SecretBackend::Keyring { service } => fetch_keyring(service, &config.keys)?,
+        #[cfg(not(feature = "keyring"))]
+        SecretBackend::Keyring { .. } => {
+            return Err(
+                "SECRETS_BACKEND is keyring but the bot was built without the keyring feature"
+                    .into(),
+            )
+        }
+    };
+
+    let mut loaded: Vec<String> = Vec::new();
+    for key in &config.keys {
+        if let Some(secret) = secrets.get(key) {
+            env::set_var(key, secret);
+            loaded.push(key.clone());
+        }
+    }
+    Ok(loaded)
+}
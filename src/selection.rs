@@ -0,0 +1,198 @@
+use crate::{logging::log_message, structs::Contender};
+
+/// Weight of the share of legs two contenders have in common.
+const SHARED_LEG_WEIGHT: f64 = 0.5;
+/// Weight of two contenders expiring on the same date.
+const SHARED_EXPIRY_WEIGHT: f64 = 0.2;
+/// Weight of the share of strikes two contenders trade in the same direction on the same right,
+/// across expirations.
+const DIRECTION_WEIGHT: f64 = 0.3;
+
+/// Scores how correlated two contenders are, from `0.0` for unrelated spreads to `1.0` for the
+/// same legs on the same expiration.
+///
+/// # Arguments
+///
+/// * `a` - The first contender.
+/// * `b` - The second contender.
+///
+/// # Returns
+///
+/// An `f64` with the weighted share of shared legs, shared expiration and directional overlap.
+pub(crate) fn correlation(a: &Contender, b: &Contender) -> f64 {
+    let min_legs: usize = a.contracts.len().min(b.contracts.len());
+    if min_legs == 0 {
+        return 0.0;
+    }
+
+    let a_legs: Vec<String> = a.leg_keys();
+    let b_legs: Vec<String> = b.leg_keys();
+    let shared_legs: usize = a_legs.iter().filter(|leg| b_legs.contains(leg)).count();
+
+    let directions = |contender: &Contender| -> Vec<(String, f64, String)> {
+        contender
+            .contracts
+            .iter()
+            .enumerate()
+            .map(|(i, contract)| {
+                (
+                    contract.type_contract.clone(),
+                    contract.strike,
+                    contender.action(i).trim().to_string(),
+                )
+            })
+            .collect()
+    };
+    let b_directions: Vec<(String, f64, String)> = directions(b);
+    let same_direction: usize = directions(a)
+        .iter()
+        .filter(|direction| b_directions.contains(direction))
+        .count();
+
+    let shared_expiry: f64 = if a.exp_date == b.exp_date { 1.0 } else { 0.0 };
+    SHARED_LEG_WEIGHT * (shared_legs.min(min_legs) as f64 / min_legs as f64)
+        + SHARED_EXPIRY_WEIGHT * shared_expiry
+        + DIRECTION_WEIGHT * (same_direction.min(min_legs) as f64 / min_legs as f64)
+}
+
+/// Scores a set of contenders by the average correlation of each pair.
+///
+/// # Arguments
+///
+/// * `contenders` - The contenders.
+///
+/// # Returns
+///
+/// An `f64` with the average pairwise correlation, `0.0` for fewer than two contenders.
+pub(crate) fn set_correlation(contenders: &[Contender]) -> f64 {
+    let mut total: f64 = 0.0;
+    let mut pairs: usize = 0;
+    for (i, a) in contenders.iter().enumerate() {
+        for b in &contenders[i + 1..] {
+            total += correlation(a, b);
+            pairs += 1;
+        }
+    }
+    if pairs == 0 {
+        return 0.0;
+    }
+    total / pairs as f64
+}
+
+/// Prunes contenders too correlated with a better-ranked contender, so the selected set doesn't
+/// concentrate on the same legs, expiration or direction.
+///
+/// # Arguments
+///
+/// * `contenders` - The contenders, best ranked first.
+/// * `max_correlation` - The highest correlation allowed between two kept contenders.
+///
+/// # Returns
+///
+/// A `Vec<Contender>` with the kept contenders, in the same order.
+pub(crate) fn diversify(contenders: Vec<Contender>, max_correlation: f64) -> Vec<Contender> {
+    let mut kept: Vec<Contender> = Vec::new();
+    for contender in contenders {
+        if kept
+            .iter()
+            .all(|selected| correlation(selected, &contender) <= max_correlation)
+        {
+            kept.push(contender);
+        }
+    }
+    kept
+}
+
+/// Scores a contender by its edge per dollar of maintenance margin it would use.
+///
+/// # Arguments
+///
+/// * `contender` - The contender.
+/// * `margin` - The whatif maintenance margin change of its orders.
+///
+/// # Returns
+///
+/// An `f64` with the edge per dollar of margin, infinite for orders that don't add margin.
+pub(crate) fn margin_efficiency(contender: &Contender, margin: f64) -> f64 {
+    if margin <= 0.0 {
+        return f64::INFINITY;
+    }
+    contender.arb_val / margin
+}
+
+/// Orders contenders by edge per dollar of margin, so constrained capital goes to the spreads
+/// that earn the most for the margin they tie up. Ties keep their rank order.
+///
+/// # Arguments
+///
+/// * `contenders` - The contenders with their whatif maintenance margin change, best ranked first.
+///
+/// # Returns
+///
+/// A `Vec<(Contender, f64)>` with the same pairs, most margin-efficient first.
+pub(crate) fn rank_by_margin_efficiency(
+    mut contenders: Vec<(Contender, f64)>,
+) -> Vec<(Contender, f64)> {
+    contenders.sort_by(|(a, a_margin), (b, b_margin)| {
+        margin_efficiency(b, *b_margin).total_cmp(&margin_efficiency(a, *a_margin))
+    });
+    contenders
+}
+
+/// Accepts the contenders whose margin fits under a maintenance margin ceiling. Each accepted
+/// contender's margin counts towards the next, since existing positions and earlier orders share
+/// the same headroom.
+///
+/// # Arguments
+///
+/// * `maintenance` - The account's current maintenance margin.
+/// * `net_liquidation` - The account's net liquidation value; nothing fits if it isn't positive.
+/// * `changes` - The contenders, best first, with the maintenance margin change of their orders.
+/// * `max_margin_usage` - The maintenance margin ceiling in percent of net liquidation.
+/// * `margin_efficient` - Whether contenders that don't all fit are checked by edge per dollar of
+///   margin instead of rank.
+///
+/// # Returns
+///
+/// A `Vec<Contender>` with the contenders that fit, in the order they were checked.
+pub(crate) fn fit_within_margin(
+    maintenance: f64,
+    net_liquidation: f64,
+    changes: Vec<(Contender, f64)>,
+    max_margin_usage: f64,
+    margin_efficient: bool,
+) -> Vec<Contender> {
+    if net_liquidation <= 0.0 {
+        return Vec::new();
+    }
+
+    let total_change: f64 = changes.iter().map(|(_, change)| change.max(0.0)).sum();
+    let changes: Vec<(Contender, f64)> = if margin_efficient
+        && (maintenance + total_change) / net_liquidation * 100.0 > max_margin_usage
+    {
+        log_message(
+            "Not all contenders fit within the margin ceiling, ranking by edge per dollar of margin."
+                .to_string(),
+        );
+        rank_by_margin_efficiency(changes)
+    } else {
+        changes
+    };
+
+    let mut maintenance: f64 = maintenance;
+    let mut accepted: Vec<Contender> = Vec::new();
+    for (contender, change) in changes {
+        let margin_usage: f64 = (maintenance + change) / net_liquidation * 100.0;
+        if margin_usage > max_margin_usage {
+            log_message(format!(
+                "Skipping {} {}: maintenance margin would reach {:.1}% of net liquidation (limit {:.1}%).",
+                contender.type_spread, contender.exp_date, margin_usage, max_margin_usage
+            ));
+            continue;
+        }
+
+        maintenance += change;
+        accepted.push(contender);
+    }
+    accepted
+}
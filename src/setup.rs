@@ -0,0 +1,300 @@
+use std::{collections::BTreeMap, env, error::Error, fs, path::Path};
+use toml::Table;
+
+use crate::{
+    expirations::parse_expiry_selectors,
+    helpers::{get_http_config, get_user_input},
+    ibkr::{
+        client::GatewayClient,
+        endpoints,
+        models::{AccountResponse, SecDefResponse},
+    },
+};
+
+/// The file the setup wizard writes and the bot loads its settings from at startup.
+pub(crate) const BOT_CONFIG_PATH: &str = "bot.toml";
+
+/// Checks a setting against the range the bot accepts for it. Settings the wizard doesn't ask for
+/// are accepted as they are.
+///
+/// # Arguments
+///
+/// * `key` - The name of the setting, e.g. `ARB_VALUE`.
+/// * `value` - Its value.
+///
+/// # Returns
+///
+/// A `Result` that is an error describing the accepted values if the value is out of range.
+pub(crate) fn validate_setting(key: &str, value: &str) -> Result<(), String> {
+    let valid: bool = match key {
+        "DOMAIN" | "ACCOUNT_ID" => !value.is_empty(),
+        "TICKER" => !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric()),
+        "PORT" => value.parse::<u16>().is_ok(),
+        "OPTION" | "FILL_TYPE" => ["1", "2", "3", "DEFAULT"].contains(&value),
+        "TEST_MODE" => ["y", "yes", "n", "no"].contains(&value.to_lowercase().as_str()),
+        "ARB_VALUE" => value.parse::<f64>().is_ok_and(|val| val >= 0.10),
+        "DISCOUNT_VALUE" => value
+            .parse::<f64>()
+            .is_ok_and(|val| (-0.15..=0.15).contains(&val)),
+        "SECONDS_TO_SLEEP" => value.parse::<u64>().is_ok_and(|val| val >= 5),
+        "EXPIRY_SELECTOR" => parse_expiry_selectors(value).is_ok(),
+        _ => true,
+    };
+    if valid {
+        return Ok(());
+    }
+
+    let accepted: &str = match key {
+        "DOMAIN" | "ACCOUNT_ID" => "a non-empty value",
+        "TICKER" => "a symbol of letters and digits",
+        "PORT" => "a port number",
+        "OPTION" | "FILL_TYPE" => "1, 2, 3 or DEFAULT",
+        "TEST_MODE" => "Y or N",
+        "ARB_VALUE" => "a number of at least 0.10",
+        "DISCOUNT_VALUE" => "a number between -0.15 and 0.15",
+        "SECONDS_TO_SLEEP" => "a whole number of at least 5",
+        _ => "expiry selectors such as next 5 or monthlies within 60 DTE",
+    };
+    Err(format!("{} is {:?}, expected {}", key, value, accepted))
+}
+
+/// Renders settings as the contents of a `bot.toml` file, with numbers written as TOML numbers.
+///
+/// # Arguments
+///
+/// * `settings` - The settings by name.
+///
+/// # Returns
+///
+/// A `Result` containing the file contents or an error if a setting is invalid.
+pub(crate) fn render_bot_config(
+    settings: &BTreeMap<String, String>,
+) -> Result<String, Box<dyn Error>> {
+    let mut table: Table = Table::new();
+    for (key, value) in settings {
+        validate_setting(key, value)?;
+        let value: toml::Value = if let Ok(val) = value.parse::<i64>() {
+            toml::Value::Integer(val)
+        } else if let Ok(val) = value.parse::<f64>() {
+            toml::Value::Float(val)
+        } else {
+            toml::Value::String(value.clone())
+        };
+        table.insert(key.clone(), value);
+    }
+    Ok(format!(
+        "# Written by `trading_bot_rust init`. Settings in .env or the environment take precedence.\n{}",
+        toml::to_string(&table)?
+    ))
+}
+
+/// Parses and validates the contents of a `bot.toml` file.
+///
+/// # Arguments
+///
+/// * `text` - The contents of the file, top-level `KEY = value` pairs of strings and numbers.
+///
+/// # Returns
+///
+/// A `Result` containing the settings by name or an error if the file is malformed or a setting
+/// is invalid.
+pub(crate) fn parse_bot_config(text: &str) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut settings: BTreeMap<String, String> = BTreeMap::new();
+    for (key, value) in text.parse::<Table>()? {
+        let value: String = match value {
+            toml::Value::String(val) => val,
+            toml::Value::Integer(val) => val.to_string(),
+            toml::Value::Float(val) => val.to_string(),
+            _ => return Err(format!("{} must be a string or a number", key).into()),
+        };
+        validate_setting(&key, &value)?;
+        settings.insert(key, value);
+    }
+    Ok(settings)
+}
+
+/// Loads the settings of a `bot.toml` file into the environment, so the `.env` getters return
+/// them instead of prompting. Settings already in the `.env` file or the environment are kept.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+///
+/// # Returns
+///
+/// A `Result` containing the number of settings loaded, `0` if there is no file, or an error if
+/// the file is invalid.
+pub(crate) fn load_bot_config(path: &str) -> Result<usize, Box<dyn Error>> {
+    dotenv::dotenv().ok();
+    if !Path::new(path).exists() {
+        return Ok(0);
+    }
+
+    let mut num_loaded: usize = 0;
+    for (key, value) in parse_bot_config(&fs::read_to_string(path)?)? {
+        if env::var(&key).is_err() {
+            env::set_var(&key, value);
+            num_loaded += 1;
+        }
+    }
+    Ok(num_loaded)
+}
+
+/// Prompts for a setting until the answer is valid. An empty answer takes the default.
+///
+/// # Arguments
+///
+/// * `key` - The name of the setting.
+/// * `prompt` - The question to ask.
+/// * `default` - The value used when the answer is empty.
+///
+/// # Returns
+///
+/// A `String` with the accepted value.
+fn prompt_setting(key: &str, prompt: &str, default: &str) -> String {
+    loop {
+        let input: String = get_user_input(&format!("{} [{}]:", prompt, default));
+        let value: String = if input.is_empty() {
+            default.to_string()
+        } else {
+            input
+        };
+        match validate_setting(key, &value) {
+            Ok(()) => return value,
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Returns the current value of a setting, from the environment, the `.env` file or a loaded
+/// `bot.toml`, or a default.
+///
+/// # Arguments
+///
+/// * `key` - The name of the setting.
+/// * `default` - The value used when the setting isn't set.
+fn current_or(key: &str, default: &str) -> String {
+    env::var(key).unwrap_or_else(|_| default.to_string())
+}
+
+/// Runs the interactive first-run setup: probes the gateway, picks the account, looks up the
+/// underlying and asks for the thresholds, then writes the validated settings to `path`.
+///
+/// Current settings are offered as the defaults, so running it again edits the existing setup.
+///
+/// # Arguments
+///
+/// * `path` - The path of the `bot.toml` file to write.
+///
+/// # Returns
+///
+/// A `Result` that is an error if the gateway could not be reached or the file not written.
+pub(crate) fn run_setup(path: &str) -> Result<(), Box<dyn Error>> {
+    let mut settings: BTreeMap<String, String> = BTreeMap::new();
+
+    let client: GatewayClient = loop {
+        let domain: String = prompt_setting(
+            "DOMAIN",
+            "Gateway domain",
+            &current_or("DOMAIN", "localhost"),
+        );
+        let port: String = prompt_setting("PORT", "Gateway port", &current_or("PORT", "5000"));
+        let client: GatewayClient = GatewayClient::new(&domain, &port, &get_http_config())?;
+        match endpoints::auth_status(&client) {
+            Ok(status) if status.authenticated => {
+                settings.insert("DOMAIN".to_string(), domain);
+                settings.insert("PORT".to_string(), port);
+                break client;
+            }
+            Ok(_) => println!(
+                "The gateway at {}:{} is not logged in, log in and retry.",
+                domain, port
+            ),
+            Err(e) => println!("Could not reach the gateway: {}.", e),
+        }
+    };
+
+    let accounts: Vec<AccountResponse> = endpoints::accounts(&client)?;
+    let account_ids: Vec<String> = accounts.into_iter().map(|account| account.id).collect();
+    match account_ids.as_slice() {
+        [] => return Err("The gateway has no accounts".into()),
+        [account_id] => println!("Using account {}.", account_id),
+        _ => {
+            for (i, account_id) in account_ids.iter().enumerate() {
+                println!("{}. {}", i + 1, account_id);
+            }
+            let account_id: String = loop {
+                let input: String = get_user_input("Enter the number of the account to trade [1]:");
+                let index: usize = if input.is_empty() {
+                    1
+                } else {
+                    input.parse::<usize>().unwrap_or_default()
+                };
+                match account_ids.get(index.wrapping_sub(1)) {
+                    Some(account_id) => break account_id.clone(),
+                    None => println!("Not a listed account."),
+                }
+            };
+            settings.insert("ACCOUNT_ID".to_string(), account_id);
+        }
+    }
+
+    loop {
+        let ticker: String =
+            prompt_setting("TICKER", "Underlying ticker", &current_or("TICKER", "SPX"));
+        let search: Result<Vec<SecDefResponse>, Box<dyn Error>> =
+            endpoints::search_secdef(&client, &ticker);
+        match search.as_deref() {
+            Ok([result, ..])
+                if result
+                    .conid
+                    .as_deref()
+                    .is_some_and(|conid| !conid.is_empty()) =>
+            {
+                println!(
+                    "Found {} ({}), conid {}.",
+                    ticker,
+                    result.company_name,
+                    result.conid.as_deref().unwrap_or_default()
+                );
+                settings.insert("TICKER".to_string(), ticker);
+                break;
+            }
+            Ok(_) => println!("No contract found for {}.", ticker),
+            Err(e) => println!("Could not search for {}: {}.", ticker, e),
+        }
+    }
+
+    for (key, prompt, default) in [
+        (
+            "OPTION",
+            "Strategy: 1 Calendar, 2 Butterfly, 3 Boxspread, DEFAULT all",
+            "DEFAULT",
+        ),
+        (
+            "FILL_TYPE",
+            "Fill type: 1 single order/single fill, 2 single order/multiple fills, 3 multiple orders/single fill, DEFAULT multiple orders/multiple fills",
+            "DEFAULT",
+        ),
+        ("TEST_MODE", "Run in testing mode? (Y / N)", "Y"),
+        ("ARB_VALUE", "Minimum arbitrage value", "0.10"),
+        (
+            "DISCOUNT_VALUE",
+            "Discount value between -0.15 and 0.15",
+            "0.0",
+        ),
+        ("SECONDS_TO_SLEEP", "Seconds to sleep between scans", "60"),
+        (
+            "EXPIRY_SELECTOR",
+            "Expirations to scan, e.g. next 5, next 3 weeklies or monthlies within 60 DTE",
+            "next 5",
+        ),
+    ] {
+        let value: String = prompt_setting(key, prompt, &current_or(key, default));
+        settings.insert(key.to_string(), value);
+    }
+
+    fs::write(path, render_bot_config(&settings)?)?;
+    println!("Wrote {}.", path);
+    Ok(())
+}
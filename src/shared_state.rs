@@ -0,0 +1,262 @@
+#[cfg(feature = "redis")]
+use redis::Commands;
+use std::error::Error;
+
+use crate::{logging::log_message, structs::SharedStateConfig};
+
+/// How long an instance's exposure is kept after its last update, in seconds, so a crashed
+/// instance does not hold the global limit forever.
+#[cfg(feature = "redis")]
+const EXPOSURE_TTL_SECONDS: u64 = 600;
+
+/// How many P&L checks in a row may fail before the bot stops, when a daily loss limit is set.
+pub(crate) const MAX_PNL_FAILURES: u32 = 3;
+
+/// What the risk checks allow for an iteration.
+#[derive(Debug, PartialEq)]
+pub(crate) enum RiskCheck {
+    /// Trading continues.
+    Trade,
+    /// The P&L is unknown, so no orders are placed this iteration.
+    SkipOrders,
+    /// The daily loss reached the limit.
+    LimitReached,
+    /// The P&L could not be fetched `MAX_PNL_FAILURES` times in a row.
+    PnlUnavailable,
+    /// This or another instance tripped the kill switch.
+    Killed,
+}
+
+/// Checks the kill switch, failing closed when it can't be read.
+///
+/// # Arguments
+///
+/// * `killed` - The result of reading the kill switch.
+///
+/// # Returns
+///
+/// A `RiskCheck` saying whether to trade, skip ordering or stop.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(check_kill_switch(&shared_state.is_killed()), RiskCheck::Trade);
+/// ```
+pub(crate) fn check_kill_switch(killed: &Result<bool, Box<dyn Error>>) -> RiskCheck {
+    match killed {
+        Ok(true) => RiskCheck::Killed,
+        Ok(false) => RiskCheck::Trade,
+        Err(_) => RiskCheck::SkipOrders,
+    }
+}
+
+/// Checks the daily P&L against the daily loss limit, failing closed when the P&L is unknown.
+///
+/// # Arguments
+///
+/// * `daily_pnl` - The daily P&L, or `None` if it could not be fetched.
+/// * `max_daily_loss` - The loss limit as a positive amount, or `None` if there is no limit.
+/// * `failures` - How many P&L fetches in a row have failed, including this one.
+///
+/// # Returns
+///
+/// A `RiskCheck` saying whether to trade, skip ordering or stop.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(check_daily_loss(None, Some(500.0), 1), RiskCheck::SkipOrders);
+/// ```
+pub(crate) fn check_daily_loss(
+    daily_pnl: Option<f64>,
+    max_daily_loss: Option<f64>,
+    failures: u32,
+) -> RiskCheck {
+    let Some(max_daily_loss) = max_daily_loss else {
+        return RiskCheck::Trade;
+    };
+    match daily_pnl {
+        Some(daily_pnl) if daily_pnl <= -max_daily_loss => RiskCheck::LimitReached,
+        Some(_) => RiskCheck::Trade,
+        None if failures >= MAX_PNL_FAILURES => RiskCheck::PnlUnavailable,
+        None => RiskCheck::SkipOrders,
+    }
+}
+
+/// Risk state shared between bot instances, e.g. one instance per underlying.
+///
+/// With the `redis` feature and a `REDIS_URL`, the kill switch and each instance's exposure live in
+/// Redis so aggregate limits hold across processes. Otherwise the state is kept in this process
+/// only.
+pub(crate) struct SharedState {
+    #[cfg(feature = "redis")]
+    instance: String,
+    #[cfg(feature = "redis")]
+    prefix: String,
+    killed: bool,
+    /// The combos this instance holds after fills.
+    filled: f64,
+    /// The combos this instance has in working orders.
+    working: f64,
+    #[cfg(feature = "redis")]
+    connection: Option<redis::Connection>,
+}
+
+impl SharedState {
+    /// Creates the shared state, connecting to Redis when configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The shared state configuration.
+    /// * `instance` - The name of this instance, e.g. its ticker.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the shared state or an error if Redis could not be reached.
+    pub(crate) fn connect(
+        config: &SharedStateConfig,
+        instance: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        #[cfg(feature = "redis")]
+        let connection: Option<redis::Connection> = match &config.redis_url {
+            Some(url) => Some(redis::Client::open(url.as_str())?.get_connection()?),
+            None => None,
+        };
+        #[cfg(not(feature = "redis"))]
+        if config.redis_url.is_some() {
+            return Err("REDIS_URL is set but the bot was built without the redis feature".into());
+        }
+
+        #[cfg(not(feature = "redis"))]
+        let _ = instance;
+
+        Ok(SharedState {
+            #[cfg(feature = "redis")]
+            instance: instance.to_string(),
+            #[cfg(feature = "redis")]
+            prefix: config.prefix.clone(),
+            killed: false,
+            filled: 0.0,
+            working: 0.0,
+            #[cfg(feature = "redis")]
+            connection,
+        })
+    }
+
+    /// Checks whether this or any other instance tripped the kill switch.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `true` if trading must stop, or an error.
+    pub(crate) fn is_killed(&mut self) -> Result<bool, Box<dyn Error>> {
+        #[cfg(feature = "redis")]
+        if let Some(connection) = &mut self.connection {
+            let reason: Option<String> = connection.get(format!("{}:kill_switch", self.prefix))?;
+            if let Some(reason) = reason.filter(|_| !self.killed) {
+                log_message(format!("Kill switch tripped by {}.", reason));
+                self.killed = true;
+            }
+        }
+        Ok(self.killed)
+    }
+
+    /// Trips the kill switch for every instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why trading was stopped.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or an error.
+    pub(crate) fn trip_kill_switch(&mut self, reason: &str) -> Result<(), Box<dyn Error>> {
+        self.killed = true;
+        log_message(format!("Tripping the kill switch: {}.", reason));
+        #[cfg(feature = "redis")]
+        if let Some(connection) = &mut self.connection {
+            connection.set::<_, _, ()>(
+                format!("{}:kill_switch", self.prefix),
+                format!("{}: {}", self.instance, reason),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns this instance's exposure: the combos it holds plus the combos it has working.
+    pub(crate) fn exposure(&self) -> f64 {
+        self.filled + self.working
+    }
+
+    /// Sets the combos this instance holds, e.g. from the positions at startup.
+    ///
+    /// # Arguments
+    ///
+    /// * `filled` - The number of combos held.
+    pub(crate) fn set_filled(&mut self, filled: f64) {
+        self.filled = filled.max(0.0);
+    }
+
+    /// Sets the combos this instance has in working orders.
+    ///
+    /// # Arguments
+    ///
+    /// * `working` - The number of combos working.
+    pub(crate) fn set_working(&mut self, working: f64) {
+        self.working = working.max(0.0);
+    }
+
+    /// Moves filled combos from the working orders to the held combos.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The number of combos filled.
+    pub(crate) fn record_fill(&mut self, size: f64) {
+        self.working = (self.working - size).max(0.0);
+        self.filled += size;
+    }
+
+    /// Drops combos that left the account through an assignment, exercise or expiration.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The number of combos settled.
+    pub(crate) fn record_settlement(&mut self, size: f64) {
+        self.filled = (self.filled - size).max(0.0);
+    }
+
+    /// Publishes this instance's exposure to the other instances, refreshing its expiry.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or an error.
+    pub(crate) fn publish_exposure(&mut self) -> Result<(), Box<dyn Error>> {
+        #[cfg(feature = "redis")]
+        if let Some(connection) = &mut self.connection {
+            connection.set_ex::<_, _, ()>(
+                format!("{}:exposure:{}", self.prefix, self.instance),
+                self.filled + self.working,
+                EXPOSURE_TTL_SECONDS,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Sums the exposure of every instance, in combos.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the global exposure or an error.
+    pub(crate) fn global_exposure(&mut self) -> Result<f64, Box<dyn Error>> {
+        #[cfg(feature = "redis")]
+        if let Some(connection) = &mut self.connection {
+            let keys: Vec<String> = connection.keys(format!("{}:exposure:*", self.prefix))?;
+            let mut exposure: f64 = 0.0;
+            for key in keys {
+                let value: Option<f64> = connection.get(&key)?;
+                exposure += value.unwrap_or(0.0);
+            }
+            return Ok(exposure);
+        }
+        Ok(self.exposure())
+    }
+}
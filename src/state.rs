@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs, io::ErrorKind};
+
+use crate::{journal::Journal, positions::PositionWatch};
+
+/// In-flight state of the bot, saved every iteration so a restart resumes managing the orders it
+/// left working instead of forgetting them.
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct BotState {
+    /// When the state was saved, in milliseconds since the epoch.
+    pub(crate) saved_at: i64,
+    /// The IDs of the orders still working at IBKR.
+    pub(crate) live_orders: Vec<String>,
+    /// Fingerprints of recently submitted contenders with their submission time in milliseconds
+    /// since the epoch, so the resubmission cooldown survives a restart.
+    pub(crate) submitted: Vec<(String, i64)>,
+    /// The submitted orders and realized edge of the session.
+    pub(crate) journal: Journal,
+    /// The positions of the legs of held combos at the last assignment check.
+    #[serde(default)]
+    pub(crate) position_watch: PositionWatch,
+}
+
+/// Writes the state to disk, replacing the previous state atomically.
+///
+/// # Arguments
+///
+/// * `path` - The path of the state file.
+/// * `state` - The state to save.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an error.
+pub(crate) fn save_state(path: &str, state: &BotState) -> Result<(), Box<dyn Error>> {
+    let temp_path: String = format!("{}.tmp", path);
+    fs::write(&temp_path, serde_json::to_string(state)?)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Loads the state saved by a previous run.
+///
+/// # Arguments
+///
+/// * `path` - The path of the state file.
+///
+/// # Returns
+///
+/// A `Result` containing the state, `None` if no state was saved, or an error.
+pub(crate) fn load_state(path: &str) -> Result<Option<BotState>, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
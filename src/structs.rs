@@ -1,126 +1,604 @@
+use rustc_hash::FxHasher;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+};
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct Confirmation {
-    pub(crate) confirmed: bool,
-}
-
-#[derive(Serialize, Deserialize)]
-pub(crate) struct AccountResponse {
-    pub(crate) id: String,
-}
-
-#[derive(Serialize, Deserialize)]
-pub(crate) struct SecDefResponse {
-    #[serde(rename = "companyName")]
-    pub(crate) company_name: String,
-    pub(crate) conid: Option<String>,
-    pub(crate) sections: Option<Vec<Section>>,
-}
-
-#[derive(Serialize, Deserialize)]
-pub(crate) struct Section {
-    #[serde(rename = "secType")]
-    pub(crate) sec_type: String,
-    pub(crate) months: Option<String>,
-}
-
-#[derive(Serialize, Deserialize)]
-pub(crate) struct SecDefInfoResponse {
-    pub(crate) conid: f64,
-    #[serde(rename = "maturityDate")]
-    pub(crate) maturity_date: String,
-    pub(crate) right: String,
-    pub(crate) strike: f64,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) struct MarketDataResponse {
-    #[serde(rename = "conidEx")]
-    pub(crate) conid_ex: String,
-    #[serde(rename = "84")]
-    pub(crate) field_84: Option<String>,
-    #[serde(rename = "85")]
-    pub(crate) field_85: Option<String>,
-    #[serde(rename = "86")]
-    pub(crate) field_86: Option<String>,
-}
-
-#[derive(Serialize, Deserialize)]
-pub(crate) struct PortfolioResponse {
-    #[serde(rename = "equitywithloanvalue")]
-    pub(crate) equity_with_loan_value: PortfolioAmount,
-}
-
-#[derive(Serialize, Deserialize)]
-pub(crate) struct PortfolioAmount {
-    pub(crate) amount: f64,
-}
-
-#[derive(Serialize, Deserialize)]
-pub(crate) struct OrderBody {
-    #[serde(rename = "acctId")]
-    pub(crate) acct_id: String,
-    #[serde(rename = "conidex")]
-    pub(crate) con_idex: String,
-    #[serde(rename = "orderType")]
-    pub(crate) order_type: String,
-    #[serde(rename = "listingExchange")]
-    pub(crate) listing_exchange: String,
-    #[serde(rename = "outsideRTH")]
-    pub(crate) outside_rth: bool,
-    pub(crate) price: f64,
-    pub(crate) side: String,
-    pub(crate) ticker: String,
-    pub(crate) tif: String,
-    pub(crate) referrer: String,
-    pub(crate) quantity: i32,
-    #[serde(rename = "useAdaptive")]
+use crate::{
+    chain::{ExpiryDate, OptionChain},
+    underlying::osi_symbol,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Pnl {
+    pub(crate) daily: f64,
+    pub(crate) unrealized: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Opt {
+    pub asz: f64,
+    pub mkt: f64,
+    pub bid: f64,
+    pub ask: f64,
+    /// The bid size, when the market data includes it.
+    pub bsz: Option<f64>,
+    /// The delta, when the market data includes Greeks.
+    pub delta: Option<f64>,
+    /// The broker's mark price, when the market data includes it.
+    #[serde(default)]
+    pub mark: Option<f64>,
+    /// The implied volatility as a fraction, when the market data includes it.
+    #[serde(default)]
+    pub iv: Option<f64>,
+    /// The Black-Scholes price at the implied volatility, filled in by the scanner when a leg is
+    /// priced from the model.
+    #[serde(default)]
+    pub model: Option<f64>,
+}
+
+/// How much is logged, each level including the ones before it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LogLevel {
+    Error,
+    /// Warnings, such as delayed market data or a fast market.
+    Warn,
+    /// The bot's progress and one line per submitted combo.
+    #[default]
+    Info,
+    /// The legs of every submitted combo.
+    Debug,
+    /// Every gateway request.
+    Trace,
+}
+
+impl LogLevel {
+    /// Converts a string (`"error"`, `"warn"`, `"info"`, `"debug"` or `"trace"`) to a `LogLevel`.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - A string representing the log level.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Self>` representing the converted log level.
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// Which price of a leg's quote the arb value is computed from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum PriceSource {
+    Bid,
+    Ask,
+    #[default]
+    Mid,
+    /// The broker's mark, or the mid when there is none.
+    Mark,
+    /// The Black-Scholes price at the leg's implied volatility, or the mid when there is none.
+    Model,
+}
+
+impl PriceSource {
+    /// Converts a string (`"bid"`, `"ask"`, `"mid"`, `"mark"` or `"model"`) to a `PriceSource`.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - A string representing the price source.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<Self>` representing the converted price source.
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "bid" => Some(PriceSource::Bid),
+            "ask" => Some(PriceSource::Ask),
+            "mid" => Some(PriceSource::Mid),
+            "mark" => Some(PriceSource::Mark),
+            "model" => Some(PriceSource::Model),
+            _ => None,
+        }
+    }
+
+    /// Returns the price of a quote from this source.
+    ///
+    /// # Arguments
+    ///
+    /// * `opt` - The quote.
+    pub(crate) fn price(self, opt: &Opt) -> f64 {
+        match self {
+            PriceSource::Bid => opt.bid,
+            PriceSource::Ask => opt.ask,
+            PriceSource::Mid => opt.mkt,
+            PriceSource::Mark => opt.mark.unwrap_or(opt.mkt),
+            PriceSource::Model => opt.model.unwrap_or(opt.mkt),
+        }
+    }
+}
+
+/// The price sources of the bought and sold legs of a spread. Pricing bought legs at the ask and
+/// sold legs at the bid gives the edge available by crossing the spread.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct LegPricing {
+    pub(crate) buy: PriceSource,
+    pub(crate) sell: PriceSource,
+}
+
+impl LegPricing {
+    /// Returns whether either side is priced from the model, which needs the quotes' model prices.
+    pub(crate) fn uses_model(&self) -> bool {
+        self.buy == PriceSource::Model || self.sell == PriceSource::Model
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct StrategyConfig {
+    pub(crate) wing_widths: Vec<f64>,
+    pub(crate) dte_window: Option<(i64, i64)>,
+    /// How far the middle of the spread's strikes may be from the spot, in percent.
+    pub(crate) max_spot_distance_percent: Option<f64>,
+    /// Whether the spread's lowest strike must be at or below the spot and its highest at or above.
+    pub(crate) straddle_spot: bool,
+    /// The largest absolute net delta of a butterfly; only the butterfly scanner checks it.
+    pub(crate) max_net_delta: Option<f64>,
+}
+
+impl StrategyConfig {
+    pub(crate) fn allows_dte(&self, dte: i64) -> bool {
+        match self.dte_window {
+            Some((min_dte, max_dte)) => dte >= min_dte && dte <= max_dte,
+            None => true,
+        }
+    }
+
+    /// Checks a spread's strikes against the moneyness filters.
+    ///
+    /// # Arguments
+    ///
+    /// * `low_strike` - The lowest strike of the spread.
+    /// * `high_strike` - The highest strike of the spread.
+    /// * `spot` - The price of the underlying, if it could be fetched.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is `true` if the strikes pass every filter. Without a spot price the filters
+    /// can't be checked, so only a strategy without them passes.
+    pub(crate) fn allows_moneyness(
+        &self,
+        low_strike: f64,
+        high_strike: f64,
+        spot: Option<f64>,
+    ) -> bool {
+        if self.max_spot_distance_percent.is_none() && !self.straddle_spot {
+            return true;
+        }
+        let Some(spot) = spot else {
+            return false;
+        };
+
+        let center: f64 = (low_strike + high_strike) / 2.0;
+        self.max_spot_distance_percent
+            .is_none_or(|max_percent| (center - spot).abs() / spot * 100.0 <= max_percent)
+            && (!self.straddle_spot || (low_strike <= spot && spot <= high_strike))
+    }
+
+    /// Checks a spread's net delta against the delta limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `net_delta` - The net delta of the spread, if every leg has a delta.
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is `true` if there is no limit, or the net delta is known and within it.
+    pub(crate) fn allows_net_delta(&self, net_delta: Option<f64>) -> bool {
+        self.max_net_delta.is_none_or(|max_net_delta| {
+            net_delta.is_some_and(|net_delta| net_delta.abs() <= max_net_delta)
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ExpiryFilter {
+    pub(crate) exclude_same_day: bool,
+    pub(crate) min_minutes_to_close: i64,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PortfolioCacheConfig {
+    pub(crate) ttl_seconds: u64,
+    pub(crate) change_percent: f64,
+}
+
+/// How far the local clock may drift from the gateway's before the bot warns about it or refuses
+/// to run.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ClockSkewConfig {
+    pub(crate) warn_seconds: u64,
+    pub(crate) max_seconds: u64,
+}
+
+/// Settings of box financing: selling box spreads short of fair value to borrow at the rate they
+/// imply, the inverse of the box arbitrage.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BoxFinancing {
+    /// The highest annualized rate above the benchmark curve a financing box may borrow at.
+    pub(crate) max_rate_spread: f64,
+    /// The most that financing boxes may borrow per scan, in dollars of credit, if capped.
+    pub(crate) max_notional: Option<f64>,
+}
+
+/// Settings of fast market detection: when the underlying or the spreads move sharply between
+/// scans, the bot scans more often and demands more edge for a while.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FastMarketConfig {
+    /// The move of the underlying between scans, in percent, that starts a fast market, if checked.
+    pub(crate) max_move_percent: Option<f64>,
+    /// How many times wider than in the previous scan the average spread may get before a fast
+    /// market starts, if checked.
+    pub(crate) max_spread_multiple: Option<f64>,
+    /// Seconds between scans during a fast market.
+    pub(crate) seconds_to_sleep: u64,
+    /// Edge added to the arb threshold during a fast market.
+    pub(crate) extra_edge: f64,
+    /// How many scans a fast market lasts after the last trigger.
+    pub(crate) scans: u32,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RequoteConfig {
+    /// How far the combo model price must fall for the limit to follow it down.
+    pub(crate) reprice_threshold: Option<f64>,
+    /// How far the combo model price may rise before the order is canceled early.
+    pub(crate) cancel_threshold: Option<f64>,
+}
+
+/// The proxy the gateway client connects through.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) enum ProxySetting {
+    /// `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` from the environment, if set.
+    #[default]
+    Environment,
+    /// A direct connection, ignoring the proxy environment variables.
+    Disabled,
+    /// Every request goes through this proxy URL.
+    Url(String),
+}
+
+/// What the log redaction masks in logs, the audit file and the journal.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct RedactionConfig {
+    pub(crate) accounts: bool,
+    pub(crate) order_ids: bool,
+    /// Auth tokens, API keys and passwords.
+    pub(crate) tokens: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HttpConfig {
+    pub(crate) proxy: ProxySetting,
+    /// A PEM file of root certificates trusted in addition to the system ones, e.g. the gateway's
+    /// own certificate.
+    pub(crate) ca_bundle: Option<String>,
+    /// Whether certificates are accepted without verification, as the gateway's self-signed
+    /// localhost certificate otherwise fails it.
+    pub(crate) accept_invalid_certs: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ShadowConfig {
+    /// The domain of the gateway logged into the paper account.
+    pub(crate) domain: String,
+    /// The port of the paper gateway.
+    pub(crate) port: String,
+    /// The paper account to trade, or `None` for the first account of the paper gateway.
+    pub(crate) account_id: Option<String>,
+}
+
+impl ShadowConfig {
+    /// Checks whether an account ID is an IBKR paper account, which start with `DU` or `DF`.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The account ID.
+    pub(crate) fn is_paper_account(account_id: &str) -> bool {
+        account_id.starts_with("DU") || account_id.starts_with("DF")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct GthConfig {
+    /// Whether box spreads are traded in the SPX overnight session.
+    pub(crate) enabled: bool,
+    /// Seconds between scans overnight.
+    pub(crate) seconds_to_sleep: u64,
+    /// How long each overnight order works, or `None` to use the regular order TTL.
+    pub(crate) order_ttl_seconds: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AlgoConfig {
     pub(crate) use_adaptive: bool,
+    pub(crate) adaptive_priority: String,
+    pub(crate) params: BTreeMap<String, String>,
+}
+
+impl AlgoConfig {
+    /// Returns the IBKR algo strategy and its parameters, `None` without the adaptive router.
+    pub(crate) fn strategy(&self) -> Option<(String, BTreeMap<String, String>)> {
+        if !self.use_adaptive {
+            return None;
+        }
+        let mut params: BTreeMap<String, String> = self.params.clone();
+        params.insert(
+            "adaptivePriority".to_string(),
+            self.adaptive_priority.clone(),
+        );
+        Some(("Adaptive".to_string(), params))
+    }
+
+    /// Labels the algo for the trade journal, e.g. `"adaptive:Normal"` or `"none"`.
+    pub(crate) fn label(&self) -> String {
+        if self.use_adaptive {
+            format!("adaptive:{}", self.adaptive_priority)
+        } else {
+            "none".to_string()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RateConfig {
+    pub(crate) fred_api_key: Option<String>,
+    /// The FRED API URL, e.g. `https://api.stlouisfed.org/fred`.
+    pub(crate) fred_url: String,
+    pub(crate) fallback_rate: f64,
 }
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct RequestDataStruct {
-    pub(crate) orders: Vec<OrderBody>,
+/// Where secrets such as account IDs and API keys are loaded from.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SecretBackend {
+    /// The `.env` file and environment only.
+    Env,
+    /// The OS keyring, one entry per secret under a service name.
+    Keyring { service: String },
+    /// A HashiCorp Vault KV secret.
+    Vault {
+        addr: String,
+        token: String,
+        path: String,
+    },
+    /// An AWS Secrets Manager secret holding a JSON object.
+    Aws { secret_id: String },
 }
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct Order {
-    pub(crate) status: String,
-    pub(crate) order_id: f64,
+#[derive(Clone, Debug)]
+pub(crate) struct SecretsConfig {
+    pub(crate) backend: SecretBackend,
+    /// The settings read from the backend.
+    pub(crate) keys: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct OrdersResponse {
-    pub(crate) orders: Vec<Order>,
+#[derive(Clone, Debug)]
+pub(crate) struct SharedStateConfig {
+    pub(crate) redis_url: Option<String>,
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    pub(crate) prefix: String,
+    pub(crate) max_global_exposure: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) struct Opt {
-    pub(crate) asz: f64,
-    pub(crate) mkt: f64,
-    pub(crate) bid: f64,
+#[derive(Clone, Debug)]
+pub(crate) struct EventsConfig {
+    pub(crate) path: Option<String>,
+    pub(crate) addr: Option<String>,
+    pub(crate) kafka: Option<(String, String)>,
+    pub(crate) nats: Option<(String, String)>,
+    pub(crate) mqtt: Option<MqttConfig>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct Contract {
-    pub(crate) strike: f64,
-    pub(crate) mkt_price: f64,
-    pub(crate) date: String,
-    pub(crate) type_contract: String,
+/// Where contender and fill events are published over MQTT, for dashboards like Home Assistant.
+#[derive(Clone, Debug)]
+pub(crate) struct MqttConfig {
+    /// The broker as `host:port`, or `host` for port 1883, prefixed with `username:password@` if
+    /// it requires a login.
+    pub(crate) broker: String,
+    pub(crate) contender_topic: String,
+    pub(crate) fill_topic: String,
 }
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct Contender {
-    pub(crate) arb_val: f64,
-    pub(crate) avg_ask: f64,
-    pub(crate) type_spread: String,
-    pub(crate) exp_date: String,
-    pub(crate) rank_value: f64,
-    pub(crate) contracts: Vec<Contract>,
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Contract {
+    pub strike: f64,
+    pub mkt_price: f64,
+    pub date: ExpiryDate,
+    pub type_contract: String,
+}
+
+impl Contract {
+    /// Describes the leg as expiry, right and strike at its model price, e.g. `240701C5000@12.30`.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}{}{}@{:.2}",
+            self.date, self.type_contract, self.strike, self.mkt_price
+        )
+    }
+
+    /// Formats the leg as a standard OSI symbol, e.g. `SPXW  241220C05900000`, to cross-reference
+    /// it with other tools and broker statements.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticker` - The ticker of the underlying, e.g. `"SPX"`.
+    /// * `chain` - The chain the leg was scanned from, for its trading class, if available.
+    pub fn osi_symbol(&self, ticker: &str, chain: Option<&OptionChain>) -> String {
+        let trading_class: Option<&str> = chain
+            .and_then(|chain| chain.trading_class_for(self.date, &self.type_contract, self.strike));
+        osi_symbol(
+            ticker,
+            trading_class,
+            self.date,
+            &self.type_contract,
+            self.strike,
+        )
+    }
+}
+
+/// A leg's quote at the time of the scan.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LegQuote {
+    /// The leg as expiry, right and strike, e.g. `240701C5000`.
+    pub leg: String,
+    pub bid: f64,
+    #[serde(default)]
+    pub ask: f64,
+    pub mkt: f64,
+    pub asz: f64,
+    #[serde(default)]
+    pub bsz: Option<f64>,
+    #[serde(default)]
+    pub delta: Option<f64>,
+}
+
+impl LegQuote {
+    pub(crate) fn new(date: ExpiryDate, right: &str, strike: f64, opt: &Opt) -> Self {
+        LegQuote {
+            leg: format!("{}{}{}", date, right, strike),
+            bid: opt.bid,
+            ask: opt.ask,
+            mkt: opt.mkt,
+            asz: opt.asz,
+            bsz: opt.bsz,
+            delta: opt.delta,
+        }
+    }
+}
+
+/// A comparison of a scanned value against the threshold it had to meet.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ThresholdCheck {
+    /// What was compared, e.g. `"arb_val"` or `"min_bid"`.
+    pub name: String,
+    pub value: f64,
+    /// The comparison, one of `">="`, `">"`, `"<="` or `"<"`.
+    pub op: String,
+    pub threshold: f64,
+    pub passed: bool,
+}
+
+impl ThresholdCheck {
+    /// Compares a value against a threshold.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - What is compared.
+    /// * `value` - The scanned value.
+    /// * `op` - The comparison, one of `">="`, `">"`, `"<="` or `"<"`.
+    /// * `threshold` - The threshold.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let check = ThresholdCheck::new("min_bid", 1.25, ">", 1.0);
+    /// assert!(check.passed);
+    /// ```
+    pub(crate) fn new(name: &str, value: f64, op: &str, threshold: f64) -> Self {
+        let passed: bool = match op {
+            ">=" => value >= threshold,
+            ">" => value > threshold,
+            "<=" => value <= threshold,
+            "<" => value < threshold,
+            _ => false,
+        };
+        ThresholdCheck {
+            name: name.to_string(),
+            value,
+            op: op.to_string(),
+            threshold,
+            passed,
+        }
+    }
+}
+
+/// Why a contender qualified: the leg quotes it was priced from, the arb formula, and each
+/// threshold comparison, so surprising orders can be audited afterwards.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Reason {
+    /// The quotes of the legs, in the order of the contender's contracts.
+    pub quotes: Vec<LegQuote>,
+    /// The arb value formula over the legs' mid prices, e.g.
+    /// `2 * 240701C5000 - (240701C4995 + 240701C5005)`.
+    pub formula: String,
+    pub checks: Vec<ThresholdCheck>,
+}
+
+impl Reason {
+    /// Formats the checks for the log, e.g. `"arb_val 0.60 >= 0.50, min_bid 3.10 > 1.00"`.
+    pub fn summary(&self) -> String {
+        self.checks
+            .iter()
+            .map(|check| {
+                format!(
+                    "{} {:.2} {} {:.2}",
+                    check.name, check.value, check.op, check.threshold
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Returns how far the arb value fell short of its threshold, if that was the only check the
+    /// contender failed.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<f64>` with the shortfall, or `None` if the contender met the arb threshold or
+    /// failed another check.
+    pub(crate) fn shortfall(&self) -> Option<f64> {
+        let mut failed = self.checks.iter().filter(|check| !check.passed);
+        match (failed.next(), failed.next()) {
+            (Some(check), None) if check.name == "arb_val" => {
+                Some((check.threshold - check.value).abs())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Contender {
+    pub arb_val: f64,
+    pub avg_ask: f64,
+    pub type_spread: String,
+    pub exp_date: ExpiryDate,
+    pub rank_value: f64,
+    pub scan_time: i64,
+    pub fill_probability: f64,
+    pub above_threshold: bool,
+    pub contracts: Vec<Contract>,
+    #[serde(default)]
+    pub reason: Reason,
 }
 
 impl Contender {
+    /// Returns whether the contender is a box sold to borrow rather than for arbitrage.
+    pub(crate) fn is_financing(&self) -> bool {
+        self.type_spread == "Boxspread"
+            && self
+                .reason
+                .checks
+                .first()
+                .is_some_and(|check| check.name == "implied_rate")
+    }
+
     pub(crate) fn action(&self, index: usize) -> &str {
         match self.type_spread.as_str() {
             "Calendar" => {
@@ -148,6 +626,188 @@ impl Contender {
         }
     }
 
+    /// Returns how many combos the displayed liquidity supports, the smallest size across the legs
+    /// on the side each leg trades: the ask for bought legs and the bid for sold ones. The body of
+    /// a butterfly is sold by both of its orders, so its size is shared between them.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<f64>` with the number of combos, or `None` if the leg quotes weren't kept.
+    pub(crate) fn displayed_size(&self) -> Option<f64> {
+        if self.reason.quotes.len() != self.contracts.len() {
+            return None;
+        }
+        self.reason
+            .quotes
+            .iter()
+            .enumerate()
+            .map(|(index, quote)| {
+                let size: f64 = if self.action(index) == "SELL" {
+                    quote.bsz.unwrap_or(quote.asz)
+                } else {
+                    quote.asz
+                };
+                if self.type_spread == "Butterfly" && index == 1 {
+                    size / 2.0
+                } else {
+                    size
+                }
+            })
+            .reduce(f64::min)
+    }
+
+    /// Identifies the contender by its legs and prices, rounded to the cent.
+    pub fn fingerprint(&self) -> String {
+        let mut fingerprint: String = format!("{}@{:.2}", self.type_spread, self.arb_val);
+        for contract in &self.contracts {
+            fingerprint.push_str(&format!(
+                "|{}{}{}@{:.2}",
+                contract.date, contract.type_contract, contract.strike, contract.mkt_price
+            ));
+        }
+        fingerprint
+    }
+
+    /// Identifies each leg by its expiration, right and strike, regardless of price.
+    pub(crate) fn leg_keys(&self) -> Vec<String> {
+        self.contracts
+            .iter()
+            .map(|contract| {
+                format!(
+                    "{}{}{}",
+                    contract.date, contract.type_contract, contract.strike
+                )
+            })
+            .collect()
+    }
+
+    /// Builds the customer order id (cOID) for one of the contender's orders.
+    ///
+    /// The id is a hash of the legs, prices and scan time, so resubmitting the same scan after a
+    /// retry or reconnect reuses the id and is rejected by the broker as a duplicate. Orders
+    /// resubmitted to remediate a rejection append their attempt, e.g. `-r1`, to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_index` - The index of the order within the contender's orders.
+    pub fn order_id(&self, order_index: usize) -> String {
+        let mut hasher: FxHasher = FxHasher::default();
+        self.fingerprint().hash(&mut hasher);
+        self.scan_time.hash(&mut hasher);
+        format!("{:016x}-{}", hasher.finish(), order_index)
+    }
+
+    /// Returns whether an order, or a child order sliced from one, is one of the contender's.
+    ///
+    /// # Arguments
+    ///
+    /// * `c_oid` - The customer order id (cOID) of the order.
+    pub fn owns_order(&self, c_oid: &str) -> bool {
+        let order_id: String = self.order_id(0);
+        c_oid.starts_with(order_id.strip_suffix('0').unwrap_or(&order_id))
+    }
+
+    /// Returns the quoted arb of the contender beyond its guaranteed value, per combo.
+    ///
+    /// For box spreads this is the arb value minus the wing width the box pays at expiry.
+    pub fn quoted_edge(&self) -> f64 {
+        if self.type_spread == "Boxspread" {
+            self.arb_val - (self.contracts[2].strike - self.contracts[1].strike)
+        } else {
+            self.arb_val
+        }
+    }
+
+    /// Checks that the legs form the spread named by `type_spread`.
+    ///
+    /// Butterflies must be three legs of one expiration and right with equal wings, box spreads a
+    /// put and call at each of two strikes of one expiration, and calendars the same strike and
+    /// right in two consecutive legs of increasing expiration.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that is `Ok(())` for a well-formed spread, or an `Err` describing the violation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// if let Err(e) = contender.validate() {
+    ///     log_message(format!("Dropping {}: {}.", contender.fingerprint(), e));
+    /// }
+    /// ```
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let legs: &[Contract] = &self.contracts;
+        let expected_legs: usize = match self.type_spread.as_str() {
+            "Calendar" => 2,
+            "Butterfly" => 3,
+            "Boxspread" => 4,
+            other => return Err(format!("unknown spread type {}", other)),
+        };
+        if legs.len() != expected_legs {
+            return Err(format!(
+                "{} has {} legs instead of {}",
+                self.type_spread,
+                legs.len(),
+                expected_legs
+            ));
+        }
+        if legs[0].date != self.exp_date {
+            return Err(format!(
+                "first leg expires {} instead of {}",
+                legs[0].date, self.exp_date
+            ));
+        }
+
+        match self.type_spread.as_str() {
+            "Calendar" => {
+                if legs[0].strike != legs[1].strike {
+                    return Err("calendar legs have different strikes".to_string());
+                }
+                if legs[0].type_contract != legs[1].type_contract {
+                    return Err("calendar legs have different rights".to_string());
+                }
+                if legs[0].date >= legs[1].date {
+                    return Err("calendar legs are not in expiration order".to_string());
+                }
+            }
+            "Butterfly" => {
+                if legs.iter().any(|leg| leg.date != legs[0].date) {
+                    return Err("butterfly legs have different expirations".to_string());
+                }
+                if legs
+                    .iter()
+                    .any(|leg| leg.type_contract != legs[0].type_contract)
+                {
+                    return Err("butterfly legs have different rights".to_string());
+                }
+                let left_wing: f64 = legs[1].strike - legs[0].strike;
+                let right_wing: f64 = legs[2].strike - legs[1].strike;
+                if left_wing <= 0.0 || (left_wing - right_wing).abs() > 1e-9 {
+                    return Err(format!(
+                        "butterfly wings are {} and {} points",
+                        left_wing, right_wing
+                    ));
+                }
+            }
+            _ => {
+                if legs.iter().any(|leg| leg.date != legs[0].date) {
+                    return Err("box spread legs have different expirations".to_string());
+                }
+                let rights: Vec<&str> = legs.iter().map(|leg| leg.type_contract.as_str()).collect();
+                if rights != ["P", "C", "C", "P"] {
+                    return Err(format!("box spread rights are {}", rights.join("")));
+                }
+                if legs[0].strike != legs[1].strike || legs[2].strike != legs[3].strike {
+                    return Err("box spread strikes are not paired across rights".to_string());
+                }
+                if legs[0].strike >= legs[2].strike {
+                    return Err("box spread strikes are not increasing".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn multiplier(&self, num_fills: i32, index: usize) -> i32 {
         if self.type_spread == "Butterfly" && index == 1 {
             num_fills * 2
@@ -0,0 +1,178 @@
+use chrono::NaiveDate;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    chain::{ChainKey, ExpiryId, OptionChain, Quotes, Right, StrikeKey},
+    helpers::calc_time_difference,
+    pricing::black_scholes,
+    rates::RateCurve,
+    structs::Contract,
+};
+
+/// The lowest and highest volatility the solver searches.
+const VOLATILITY_BOUNDS: (f64, f64) = (0.001, 5.0);
+
+/// Solves the Black-Scholes implied volatility of an option price by bisection.
+///
+/// # Arguments
+///
+/// * `right` - Call or put.
+/// * `spot` - Price of the underlying.
+/// * `strike` - Strike of the option.
+/// * `years` - Time to expiry in years.
+/// * `rate` - Annualized risk-free rate.
+/// * `price` - The option price.
+///
+/// # Returns
+///
+/// An `Option<f64>` with the volatility, or `None` if no volatility within the bounds gives the
+/// price, e.g. for a price below intrinsic value.
+///
+/// # Example
+///
+/// ```
+/// let price = black_scholes(Right::Call, 5000.0, 5000.0, 30.0 / 365.0, 0.05, 0.15);
+/// let iv = implied_volatility(Right::Call, 5000.0, 5000.0, 30.0 / 365.0, 0.05, price);
+/// assert!((iv.unwrap() - 0.15).abs() < 1e-6);
+/// ```
+pub(crate) fn implied_volatility(
+    right: Right,
+    spot: f64,
+    strike: f64,
+    years: f64,
+    rate: f64,
+    price: f64,
+) -> Option<f64> {
+    let (mut low, mut high) = VOLATILITY_BOUNDS;
+    let price_at = |volatility: f64| black_scholes(right, spot, strike, years, rate, volatility);
+    if years <= 0.0 || price <= price_at(low) || price >= price_at(high) {
+        return None;
+    }
+    for _ in 0..100 {
+        let mid: f64 = (low + high) / 2.0;
+        if price_at(mid) < price {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+/// The implied volatility of every quoted contract of one scan.
+#[derive(Debug, Default)]
+pub(crate) struct IvSurface {
+    ivs: FxHashMap<ChainKey, f64>,
+}
+
+impl IvSurface {
+    /// Builds the surface from the quotes' implied volatilities, solving it from the mid where the
+    /// market data has none.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The option chain with expirations, strikes and conids.
+    /// * `quotes` - A map of option quotes keyed by chain key.
+    /// * `current_date` - The current date.
+    /// * `spot` - The price of the underlying, without which only quoted volatilities are used.
+    /// * `rates` - The risk-free rate curve.
+    pub(crate) fn build(
+        chain: &OptionChain,
+        quotes: &Quotes,
+        current_date: NaiveDate,
+        spot: Option<f64>,
+        rates: &RateCurve,
+    ) -> Self {
+        let ivs: FxHashMap<ChainKey, f64> = quotes
+            .iter()
+            .filter_map(|(key, opt)| {
+                let (expiry, right, strike) = *key;
+                let iv: Option<f64> = opt.iv.filter(|iv| *iv > 0.0).or_else(|| {
+                    let dte: i64 =
+                        calc_time_difference(current_date, chain.dates()[expiry as usize]);
+                    implied_volatility(
+                        right,
+                        spot?,
+                        strike.to_f64(),
+                        dte as f64 / 365.0,
+                        rates.rate(dte),
+                        opt.mkt,
+                    )
+                });
+                Some((*key, iv?))
+            })
+            .collect();
+        IvSurface { ivs }
+    }
+
+    /// Returns the implied volatility of a contract, if it could be found or solved.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The chain key of the contract.
+    pub(crate) fn iv(&self, key: &ChainKey) -> Option<f64> {
+        self.ivs.get(key).copied()
+    }
+
+    /// Compares a contract's implied volatility with the average of its neighboring strikes'.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The option chain the surface was built from.
+    /// * `key` - The chain key of the contract.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<(f64, f64)>` with the contract's volatility and its neighbors' average, or `None`
+    /// if either is unknown.
+    pub(crate) fn with_neighbors(&self, chain: &OptionChain, key: &ChainKey) -> Option<(f64, f64)> {
+        let (expiry, right, strike) = *key;
+        let iv: f64 = self.iv(key)?;
+        let strikes: &[f64] = chain.strikes(expiry, right);
+        let index: usize = chain.ladder(expiry, right).index_of(strike.to_f64())?;
+        let neighbors: Vec<f64> = [index.checked_sub(1), Some(index + 1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|neighbor| strikes.get(neighbor))
+            .filter_map(|neighbor| self.iv(&(expiry, right, StrikeKey::from_f64(*neighbor))))
+            .collect();
+        if neighbors.is_empty() {
+            return None;
+        }
+        Some((iv, neighbors.iter().sum::<f64>() / neighbors.len() as f64))
+    }
+
+    /// Finds the first leg whose implied volatility is further than `max_deviation` from its
+    /// neighbors', which almost always means a bad print rather than edge.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The option chain the surface was built from.
+    /// * `legs` - The legs of a contender.
+    /// * `max_deviation` - The largest allowed difference in volatility, e.g. `0.05` for 5 points.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<String>` describing the outlier leg, or `None` if every leg is in line.
+    pub(crate) fn outlier_leg(
+        &self,
+        chain: &OptionChain,
+        legs: &[Contract],
+        max_deviation: f64,
+    ) -> Option<String> {
+        legs.iter().find_map(|leg| {
+            let expiry: ExpiryId = chain.expiry_id(leg.date)?;
+            let right: Right = Right::from_str(&leg.type_contract)?;
+            let key: ChainKey = (expiry, right, StrikeKey::from_f64(leg.strike));
+            let (iv, neighbors) = self.with_neighbors(chain, &key)?;
+            ((iv - neighbors).abs() > max_deviation).then(|| {
+                format!(
+                    "{} has an implied volatility of {:.1}% against {:.1}% for its neighbors",
+                    leg.describe(),
+                    iv * 100.0,
+                    neighbors * 100.0
+                )
+            })
+        })
+    }
+}
@@ -0,0 +1,190 @@
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    backtest::Snapshot,
+    chain::{ExpiryDate, OptionChain, Quotes, Right, StrikeKey},
+    pricing::{black_scholes, black_scholes_delta},
+    structs::Opt,
+};
+
+/// Parameters of a synthetic option chain.
+#[derive(Clone, Debug)]
+pub(crate) struct ChainParams {
+    /// Price of the underlying.
+    pub(crate) spot: f64,
+    /// Distance between strikes.
+    pub(crate) strike_interval: f64,
+    /// Number of strikes per expiration and right, centered on the spot.
+    pub(crate) num_strikes: usize,
+    /// Number of daily expirations, starting the day after the chain's date.
+    pub(crate) num_expiries: usize,
+    /// Annualized at-the-money volatility.
+    pub(crate) volatility: f64,
+    /// Change in volatility per unit of log-moneyness; negative values make puts richer.
+    pub(crate) skew: f64,
+    /// Annualized risk-free rate.
+    pub(crate) rate: f64,
+    /// Largest random deviation of a quote from its Black-Scholes price.
+    pub(crate) noise: f64,
+    /// Seed of the random noise and sizes.
+    pub(crate) seed: u64,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        ChainParams {
+            spot: 5000.0,
+            strike_interval: 5.0,
+            num_strikes: 200,
+            num_expiries: 50,
+            volatility: 0.15,
+            skew: -0.3,
+            rate: 0.05,
+            noise: 0.05,
+            seed: 42,
+        }
+    }
+}
+
+/// Quotes every contract of the chain at the given spot.
+///
+/// # Arguments
+///
+/// * `params` - The chain parameters; `spot` is overridden.
+/// * `spot` - The price of the underlying.
+/// * `start_date` - The current date of the chain.
+/// * `rng` - The source of the quote noise and sizes.
+///
+/// # Returns
+///
+/// A tuple `(OptionChain, Quotes)` with every contract quoted.
+fn quote_chain<R: Rng>(
+    params: &ChainParams,
+    spot: f64,
+    start_date: NaiveDate,
+    rng: &mut R,
+) -> (OptionChain, Quotes) {
+    let mut chain: OptionChain = OptionChain::new();
+    let mut quotes: Quotes = Quotes::default();
+    let center: f64 = (params.spot / params.strike_interval).round() * params.strike_interval;
+    let first_strike: f64 = center - (params.num_strikes / 2) as f64 * params.strike_interval;
+
+    for day in 1..=params.num_expiries {
+        let date: ExpiryDate = ExpiryDate::new(start_date + Duration::days(day as i64));
+        let expiry = chain.add_expiry(date);
+        let years: f64 = day as f64 / 365.0;
+
+        for index in 0..params.num_strikes {
+            let strike: f64 = first_strike + index as f64 * params.strike_interval;
+            let volatility: f64 =
+                (params.volatility + params.skew * (strike / spot).ln()).max(0.01);
+
+            for right in Right::ALL {
+                let price: f64 = black_scholes(right, spot, strike, years, params.rate, volatility);
+                let noise: f64 = if params.noise > 0.0 {
+                    rng.gen_range(-params.noise..params.noise)
+                } else {
+                    0.0
+                };
+                let mkt: f64 = (price + noise).max(0.05);
+                let half_spread: f64 = rng.gen_range(0.05..0.5);
+                chain.insert(
+                    expiry,
+                    right,
+                    strike,
+                    format!("{}{}{}", date, right.as_str(), strike),
+                );
+                let asz: f64 = rng.gen_range(1..50) as f64;
+                quotes.insert(
+                    (expiry, right, StrikeKey::from_f64(strike)),
+                    Opt {
+                        asz,
+                        mkt,
+                        bid: (mkt - half_spread).max(0.0),
+                        ask: mkt + half_spread,
+                        bsz: Some(asz),
+                        delta: Some(black_scholes_delta(
+                            right,
+                            spot,
+                            strike,
+                            years,
+                            params.rate,
+                            volatility,
+                        )),
+                        mark: None,
+                        iv: Some(volatility),
+                        model: None,
+                    },
+                );
+            }
+        }
+    }
+    chain.sort_strikes();
+    (chain, quotes)
+}
+
+/// Generates a synthetic option chain with Black-Scholes quotes plus noise, for tests, benchmarks
+/// and demos.
+///
+/// # Arguments
+///
+/// * `params` - The spot, strike grid, expirations, volatility surface and noise.
+/// * `start_date` - The current date of the chain.
+///
+/// # Returns
+///
+/// A tuple `(OptionChain, Quotes)` with every contract quoted.
+///
+/// # Example
+///
+/// ```
+/// let (chain, quotes) = synthetic_chain(&ChainParams::default(), NaiveDate::from_ymd_opt(2024, 7, 1).unwrap());
+/// assert_eq!(quotes.len(), 50 * 200 * 2);
+/// ```
+pub(crate) fn synthetic_chain(
+    params: &ChainParams,
+    start_date: NaiveDate,
+) -> (OptionChain, Quotes) {
+    let mut rng: StdRng = StdRng::seed_from_u64(params.seed);
+    quote_chain(params, params.spot, start_date, &mut rng)
+}
+
+/// Generates a session of synthetic snapshots a minute apart, with the spot taking a random walk,
+/// so the backtester can run without recorded quotes.
+///
+/// # Arguments
+///
+/// * `params` - The chain parameters, with the opening spot.
+/// * `start_date` - The date of the session.
+/// * `count` - The number of snapshots.
+///
+/// # Returns
+///
+/// A `Vec<Snapshot>` in time order.
+pub(crate) fn synthetic_snapshots(
+    params: &ChainParams,
+    start_date: NaiveDate,
+    count: usize,
+) -> Vec<Snapshot> {
+    let mut rng: StdRng = StdRng::seed_from_u64(params.seed);
+    let open: i64 = start_date
+        .and_hms_opt(13, 30, 0)
+        .map_or(0, |time| Utc.from_utc_datetime(&time).timestamp_millis());
+    let mut spot: f64 = params.spot;
+
+    (0..count)
+        .map(|minute| {
+            spot *= 1.0 + rng.gen_range(-0.001..0.001);
+            let (chain, quotes) = quote_chain(params, spot, start_date, &mut rng);
+            Snapshot::capture(
+                &chain,
+                &quotes,
+                open + minute as i64 * 60_000,
+                start_date,
+                390 - minute as i64,
+                Some(spot),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    chain::ExpiryDate,
+    expirations::{ExpirationCalendar, ExpiryKind},
+    structs::Contract,
+};
+
+/// Contract and tick conventions of the underlying the bot trades.
+///
+/// Prices and strikes of the mini index options scale with the index, so the scanners' fixed
+/// thresholds, such as the minimum leg bid, are scaled by `price_scale` relative to SPX.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct UnderlyingSpec {
+    /// The ticker orders are submitted under.
+    pub(crate) ticker: String,
+    /// The size of the underlying relative to SPX, e.g. `0.1` for XSP.
+    pub(crate) price_scale: f64,
+    /// The minimum price increment of combo orders.
+    pub(crate) combo_tick: f64,
+    /// The listing exchange combo orders are routed to, e.g. `"SMART"` or `"CBOE"`.
+    pub(crate) listing_exchange: String,
+}
+
+impl UnderlyingSpec {
+    /// Returns the conventions of a ticker. SPX complex orders trade in nickels, XSP and other
+    /// underlyings in pennies.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticker` - The ticker, e.g. `"SPX"`.
+    ///
+    /// # Returns
+    ///
+    /// An `UnderlyingSpec` for the ticker, routed through SMART.
+    pub(crate) fn for_ticker(ticker: &str) -> Self {
+        let ticker: String = ticker.to_uppercase();
+        let (price_scale, combo_tick) = match ticker.as_str() {
+            "SPX" | "SPXW" => (1.0, 0.05),
+            "XSP" => (0.1, 0.01),
+            _ => (1.0, 0.01),
+        };
+        UnderlyingSpec {
+            ticker,
+            price_scale,
+            combo_tick,
+            listing_exchange: "SMART".to_string(),
+        }
+    }
+
+    /// Returns the spec with orders routed as configured for its ticker.
+    ///
+    /// # Arguments
+    ///
+    /// * `routing` - The exchange routing table.
+    pub(crate) fn routed(mut self, routing: &ExchangeRouting) -> Self {
+        self.listing_exchange = routing.exchange_for(&self.ticker);
+        self
+    }
+
+    /// Returns whether the ticker is SPX, which can fall back to XSP for small accounts.
+    pub(crate) fn has_mini(&self) -> bool {
+        matches!(self.ticker.as_str(), "SPX" | "SPXW")
+    }
+
+    /// Rounds a combo price to the nearest tick.
+    ///
+    /// # Arguments
+    ///
+    /// * `price` - The combo price.
+    ///
+    /// # Returns
+    ///
+    /// An `f64` with the price on a valid tick, rounded to the cent.
+    pub(crate) fn round_to_tick(&self, price: f64) -> f64 {
+        ((price / self.combo_tick).round() * self.combo_tick * 100.0).round() / 100.0
+    }
+}
+
+/// Listing exchanges combo orders are routed to, by underlying.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExchangeRouting {
+    default: String,
+    routes: BTreeMap<String, String>,
+}
+
+impl Default for ExchangeRouting {
+    fn default() -> Self {
+        ExchangeRouting {
+            default: "SMART".to_string(),
+            routes: BTreeMap::new(),
+        }
+    }
+}
+
+impl ExchangeRouting {
+    /// Creates a routing table.
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - The exchange of underlyings without a route, e.g. `"SMART"`.
+    /// * `routes` - Pairs of ticker and exchange, e.g. `("SPX", "CBOE")`.
+    pub(crate) fn new(default: String, routes: Vec<(String, String)>) -> Self {
+        ExchangeRouting {
+            default: default.to_uppercase(),
+            routes: routes
+                .into_iter()
+                .map(|(ticker, exchange)| (ticker.to_uppercase(), exchange.to_uppercase()))
+                .collect(),
+        }
+    }
+
+    /// Returns the exchange orders of a ticker are routed to.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticker` - The ticker, e.g. `"SPX"`.
+    ///
+    /// # Returns
+    ///
+    /// A `String` with the ticker's route, or the default exchange.
+    pub(crate) fn exchange_for(&self, ticker: &str) -> String {
+        self.routes
+            .get(&ticker.to_uppercase())
+            .unwrap_or(&self.default)
+            .clone()
+    }
+}
+
+/// Returns the OSI root of a ticker's option. SPX, NDX and RUT list their PM-settled weeklies
+/// under a separate root from the AM-settled monthlies, and both expire on the third Friday, so
+/// the contract's trading class decides the root. Without it, e.g. for recorded quotes, third
+/// Fridays are taken to be the monthlies.
+///
+/// # Arguments
+///
+/// * `ticker` - The ticker, e.g. `"SPX"`.
+/// * `trading_class` - The trading class of the contract, if known, e.g. `"SPXW"`.
+/// * `date` - The expiration date.
+///
+/// # Returns
+///
+/// A `String` with the root, e.g. `"SPXW"`.
+pub(crate) fn osi_root(ticker: &str, trading_class: Option<&str>, date: ExpiryDate) -> String {
+    if let Some(trading_class) = trading_class.filter(|class| !class.is_empty()) {
+        return trading_class.to_uppercase();
+    }
+    let ticker: String = ticker.to_uppercase();
+    let monthly: bool = ExpirationCalendar::default().kind(date.date()) == ExpiryKind::Monthly;
+    let root: &str = match ticker.as_str() {
+        "SPX" | "SPXW" if monthly => "SPX",
+        "SPX" | "SPXW" => "SPXW",
+        "NDX" | "NDXP" if monthly => "NDX",
+        "NDX" | "NDXP" => "NDXP",
+        "RUT" | "RUTW" if monthly => "RUT",
+        "RUT" | "RUTW" => "RUTW",
+        _ => ticker.as_str(),
+    };
+    root.to_string()
+}
+
+/// Formats an option as a standard OSI symbol: the root padded to six characters, the expiration
+/// as `YYMMDD`, the right and the strike in thousandths padded to eight digits.
+///
+/// # Arguments
+///
+/// * `ticker` - The ticker of the underlying, e.g. `"SPX"`.
+/// * `trading_class` - The trading class of the option, if known, e.g. `"SPXW"`.
+/// * `date` - The expiration date.
+/// * `right` - The right as `"C"` or `"P"`.
+/// * `strike` - The strike price.
+///
+/// # Returns
+///
+/// A `String` such as `"SPXW  241220C05900000"`.
+pub(crate) fn osi_symbol(
+    ticker: &str,
+    trading_class: Option<&str>,
+    date: ExpiryDate,
+    right: &str,
+    strike: f64,
+) -> String {
+    format!(
+        "{:<6}{}{}{:08}",
+        osi_root(ticker, trading_class, date),
+        date,
+        right,
+        (strike * 1000.0).round() as i64
+    )
+}
+
+/// Parses a standard OSI symbol back into the expiration, right and strike of the option.
+///
+/// # Arguments
+///
+/// * `symbol` - The symbol, e.g. `"SPXW  241220C05900000"`.
+///
+/// # Returns
+///
+/// An `Option<Contract>` with the leg, without a price, or `None` if the symbol isn't a valid
+/// OSI symbol.
+pub(crate) fn parse_osi_symbol(symbol: &str) -> Option<Contract> {
+    let (date, rest) = symbol.get(6..)?.split_at_checked(6)?;
+    let (right, strike) = rest.split_at_checked(1)?;
+    if !["C", "P"].contains(&right) || strike.len() != 8 {
+        return None;
+    }
+    Some(Contract {
+        strike: strike.parse::<u64>().ok()? as f64 / 1000.0,
+        mkt_price: 0.0,
+        date: date.parse().ok()?,
+        type_contract: right.to_string(),
+    })
+}
@@ -0,0 +1,217 @@
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    events::{emit, Event},
+    logging::{log_error, log_message},
+    monitor::WorkingOrders,
+};
+
+/// Where the run loop is in the current phase, for the watchdog to check and report.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Heartbeat {
+    pub(crate) iteration: u64,
+    pub(crate) phase: &'static str,
+    /// When the phase was entered.
+    pub(crate) started: Instant,
+    /// How long the phase may wait on purpose, e.g. for working orders, on top of the budget.
+    pub(crate) allowance: Duration,
+    /// The last stage that finished in the phase, and when.
+    pub(crate) stage: Option<(&'static str, Instant)>,
+    /// The last gateway request sent in the phase, and when.
+    pub(crate) request: Option<(String, Instant)>,
+    /// Whether the watchdog already fired for the phase.
+    pub(crate) fired: bool,
+}
+
+impl Heartbeat {
+    /// Returns how long the phase has run past its budget and allowance, if it has.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - How long a phase may take.
+    /// * `now` - The current instant.
+    pub(crate) fn overrun(&self, budget: Duration, now: Instant) -> Option<Duration> {
+        now.saturating_duration_since(self.started)
+            .checked_sub(budget + self.allowance)
+            .filter(|overrun| !overrun.is_zero())
+    }
+
+    /// Describes where the phase is stuck, innermost frame last, like a stack trace.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - How long a phase may take.
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    ///
+    /// A `String` such as:
+    ///
+    /// ```text
+    /// Watchdog: iteration 12 exceeded its 60s budget in scanning, stuck at:
+    ///     0: phase scanning, entered 75s ago
+    ///     1: after stage chain_fetch, finished 74s ago
+    ///     2: request GET https://localhost:5000/v1/api/iserver/marketdata/snapshot, sent 73s ago
+    /// ```
+    pub(crate) fn diagnose(&self, budget: Duration, now: Instant) -> String {
+        let ago = |instant: Instant| now.saturating_duration_since(instant).as_secs();
+        let mut frames: Vec<String> = vec![format!(
+            "phase {}, entered {}s ago",
+            self.phase,
+            ago(self.started)
+        )];
+        if let Some((stage, finished)) = self.stage {
+            frames.push(format!(
+                "after stage {}, finished {}s ago",
+                stage,
+                ago(finished)
+            ));
+        }
+        if let Some((request, sent)) = &self.request {
+            frames.push(format!("request {}, sent {}s ago", request, ago(*sent)));
+        }
+        format!(
+            "Watchdog: iteration {} exceeded its {}s budget in {}, stuck at:\n{}",
+            self.iteration,
+            budget.as_secs(),
+            self.phase,
+            frames
+                .iter()
+                .enumerate()
+                .map(|(index, frame)| format!("    {}: {}", index, frame))
+                .collect::<Vec<String>>()
+                .join("\n")
+        )
+    }
+}
+
+/// The phase the run loop is in, or `None` while it isn't running one.
+static HEARTBEAT: Mutex<Option<Heartbeat>> = Mutex::new(None);
+
+/// Whether the watchdog fired and the run loop should abort its iteration.
+static ABORT: AtomicBool = AtomicBool::new(false);
+
+/// Updates the heartbeat, if a phase is running.
+fn update_heartbeat(update: impl FnOnce(&mut Heartbeat)) {
+    if let Some(heartbeat) = HEARTBEAT.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        update(heartbeat);
+    }
+}
+
+/// Starts timing a phase of the run loop.
+///
+/// # Arguments
+///
+/// * `iteration` - The iteration number.
+/// * `phase` - The name of the phase, e.g. `"scanning"`.
+/// * `allowance` - How long the phase waits on purpose, e.g. until its deadline.
+pub(crate) fn enter_phase(iteration: u64, phase: &'static str, allowance: Duration) {
+    *HEARTBEAT.lock().unwrap_or_else(|e| e.into_inner()) = Some(Heartbeat {
+        iteration,
+        phase,
+        started: Instant::now(),
+        allowance,
+        stage: None,
+        request: None,
+        fired: false,
+    });
+}
+
+/// Stops timing the run loop, once it is done.
+pub(crate) fn stop_watching() {
+    *HEARTBEAT.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Notes that a stage of the current phase finished.
+///
+/// # Arguments
+///
+/// * `stage` - The name of the stage, e.g. `"chain_fetch"`.
+pub(crate) fn note_stage(stage: &'static str) {
+    update_heartbeat(|heartbeat| heartbeat.stage = Some((stage, Instant::now())));
+}
+
+/// Notes a gateway request about to be sent.
+///
+/// # Arguments
+///
+/// * `request` - The method and URL, e.g. `"GET https://localhost:5000/v1/api/..."`.
+pub(crate) fn note_request(request: String) {
+    update_heartbeat(|heartbeat| heartbeat.request = Some((request, Instant::now())));
+}
+
+/// Takes whether the watchdog fired since the last check, meaning the run loop should abort its
+/// iteration.
+pub(crate) fn take_abort() -> bool {
+    ABORT.swap(false, Ordering::SeqCst)
+}
+
+/// Starts a thread that watches the run loop's phases. When one runs past the budget, the
+/// watchdog logs where it is stuck, cancels the working orders and has the loop abort the
+/// iteration once the stuck call returns. If the phase is still stuck after a second budget, it
+/// exits so a supervisor can restart the bot.
+///
+/// # Arguments
+///
+/// * `budget` - How long a phase may take, besides its deliberate waits.
+/// * `orders` - The working orders, shared with the trading loop.
+/// * `cancel` - Cancels an order by ID, returning a message about the cancellation.
+///
+/// # Returns
+///
+/// A `JoinHandle` of the watchdog thread, which runs for the lifetime of the process.
+pub(crate) fn spawn_watchdog(
+    budget: Duration,
+    orders: WorkingOrders,
+    cancel: impl Fn(&str) -> Result<String, Box<dyn Error>> + Send + 'static,
+) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+        let now: Instant = Instant::now();
+        let Some(heartbeat) = HEARTBEAT.lock().unwrap_or_else(|e| e.into_inner()).clone() else {
+            continue;
+        };
+        let Some(overrun) = heartbeat.overrun(budget, now) else {
+            continue;
+        };
+        if heartbeat.fired {
+            if overrun > budget {
+                log_error(format!(
+                    "{}\nStill stuck after twice the budget, exiting so the bot can be restarted",
+                    heartbeat.diagnose(budget, now)
+                ));
+            }
+            continue;
+        }
+
+        update_heartbeat(|heartbeat| heartbeat.fired = true);
+        log_message(format!("WARNING: {}", heartbeat.diagnose(budget, now)));
+        let order_ids: Vec<String> = orders.take_all();
+        if !order_ids.is_empty() {
+            log_message(format!(
+                "Watchdog: cancelling {} working orders.",
+                order_ids.len()
+            ));
+        }
+        for order_id in order_ids {
+            match cancel(&order_id) {
+                Ok(message) => {
+                    emit(&Event::OrderCanceled {
+                        order_id: order_id.clone(),
+                    });
+                    log_message(format!("{}.", message))
+                }
+                Err(e) => log_message(format!("{}.", e)),
+            }
+        }
+        ABORT.store(true, Ordering::SeqCst);
+    })
+}
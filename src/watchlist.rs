@@ -0,0 +1,327 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs, str::FromStr, sync::Mutex};
+
+use crate::{
+    chain::ExpiryDate,
+    structs::{Contender, Contract, Reason},
+};
+
+/// A leg of a watchlist structure.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct WatchlistLeg {
+    pub(crate) date: ExpiryDate,
+    /// The right as `"C"` or `"P"`.
+    pub(crate) right: String,
+    pub(crate) strike: f64,
+}
+
+/// A structure the bot prices every scan and executes when it shows edge.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct WatchlistEntry {
+    /// `"Butterfly"`, `"Boxspread"` or `"Calendar"`.
+    pub(crate) strategy: String,
+    /// The legs in the order the scanner lists them: the lower wing, body and upper wing of a
+    /// butterfly, the lower put, lower call, upper call and upper put of a box spread, and the
+    /// near and far expiration of a calendar.
+    pub(crate) legs: Vec<WatchlistLeg>,
+    /// The edge that triggers execution, or the arb value threshold if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) min_edge: Option<f64>,
+    /// A free-form note, e.g. why the structure is watched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) note: Option<String>,
+}
+
+impl WatchlistEntry {
+    /// Creates a watchlist entry from the structure of a contender.
+    ///
+    /// # Arguments
+    ///
+    /// * `contender` - The contender.
+    pub(crate) fn from_contender(contender: &Contender) -> Self {
+        WatchlistEntry {
+            strategy: contender.type_spread.clone(),
+            legs: contender
+                .contracts
+                .iter()
+                .map(|contract| WatchlistLeg {
+                    date: contract.date,
+                    right: contract.type_contract.clone(),
+                    strike: contract.strike,
+                })
+                .collect(),
+            min_edge: None,
+            note: None,
+        }
+    }
+
+    /// Builds an unpriced contender of the structure, checking its legs form the spread.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the contender, or an error describing why the legs don't form the
+    /// spread.
+    pub(crate) fn to_contender(&self) -> Result<Contender, String> {
+        let strategy: &str = match self.strategy.to_lowercase().as_str() {
+            "butterfly" => "Butterfly",
+            "boxspread" => "Boxspread",
+            "calendar" => "Calendar",
+            _ => return Err(format!("unknown strategy {}", self.strategy)),
+        };
+        let contender: Contender = Contender {
+            arb_val: 0.0,
+            avg_ask: 0.0,
+            type_spread: strategy.to_string(),
+            exp_date: self.legs.first().ok_or("no legs")?.date,
+            rank_value: 0.0,
+            scan_time: 0,
+            fill_probability: 0.0,
+            above_threshold: false,
+            contracts: self
+                .legs
+                .iter()
+                .map(|leg| Contract {
+                    strike: leg.strike,
+                    mkt_price: 0.0,
+                    date: leg.date,
+                    type_contract: leg.right.to_uppercase(),
+                })
+                .collect(),
+            reason: Reason::default(),
+        };
+        contender.validate()?;
+        Ok(contender)
+    }
+}
+
+/// Returns the signed quantity of each leg of a structure in the order the scanner lists them,
+/// positive when the leg is bought.
+///
+/// # Arguments
+///
+/// * `strategy` - `"Butterfly"`, `"Boxspread"` or `"Calendar"`.
+pub(crate) fn structure_ratios(strategy: &str) -> &'static [i32] {
+    match strategy {
+        "Butterfly" => &[1, -2, 1],
+        "Boxspread" => &[1, -1, 1, -1],
+        _ => &[-1, 1],
+    }
+}
+
+/// A leg given on the command line as `expiry/right/strike/ratio`, e.g. `250117/C/5900/-2`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct QuoteLeg {
+    pub(crate) leg: WatchlistLeg,
+    /// The signed quantity per structure, positive when the leg is bought.
+    pub(crate) ratio: i32,
+}
+
+impl FromStr for QuoteLeg {
+    type Err = String;
+
+    /// Parses a leg such as `"250117/C/5900/-2"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [date, right, strike, ratio] = s.split('/').collect::<Vec<&str>>()[..] else {
+            return Err(format!("leg {:?} is not expiry/right/strike/ratio", s));
+        };
+        let right: String = right.to_uppercase();
+        if right != "C" && right != "P" {
+            return Err(format!("leg {:?} has an unknown right, expected C or P", s));
+        }
+        let strike: f64 = match strike.parse::<f64>() {
+            Ok(strike) if strike > 0.0 => strike,
+            _ => return Err(format!("leg {:?} has an invalid strike", s)),
+        };
+        let ratio: i32 = match ratio.trim_start_matches('+').parse::<i32>() {
+            Ok(ratio) if ratio != 0 => ratio,
+            _ => return Err(format!("leg {:?} has an invalid ratio", s)),
+        };
+        Ok(QuoteLeg {
+            leg: WatchlistLeg {
+                date: date.parse()?,
+                right,
+                strike,
+            },
+            ratio,
+        })
+    }
+}
+
+/// A structure to quote from the command line, and whether to submit it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct QuoteRequest {
+    pub(crate) entry: WatchlistEntry,
+    /// Whether to submit the structure if it shows edge.
+    pub(crate) submit: bool,
+    /// The number of structures to order.
+    pub(crate) num_fills: i32,
+}
+
+/// Recognizes the structure the bot trades in legs given in any order: a long butterfly, a box
+/// spread in the scanner's orientation or a calendar selling the nearer expiration.
+///
+/// # Arguments
+///
+/// * `legs` - The legs.
+/// * `min_edge` - The edge that triggers execution, or `None` for the arb value threshold.
+///
+/// # Returns
+///
+/// A `Result` containing the structure with its legs in scanner order, or an error if the legs
+/// don't form one.
+pub(crate) fn entry_from_legs(
+    legs: &[QuoteLeg],
+    min_edge: Option<f64>,
+) -> Result<WatchlistEntry, String> {
+    let mut sorted: Vec<QuoteLeg> = legs.to_vec();
+    let strategy: &str = match legs.len() {
+        2 => {
+            sorted.sort_by_key(|quote_leg| quote_leg.leg.date);
+            "Calendar"
+        }
+        3 => {
+            sorted.sort_by(|a, b| a.leg.strike.total_cmp(&b.leg.strike));
+            "Butterfly"
+        }
+        4 => {
+            // The lower put and call, then the upper call and put.
+            sorted.sort_by(|a, b| {
+                a.leg
+                    .strike
+                    .total_cmp(&b.leg.strike)
+                    .then(b.leg.right.cmp(&a.leg.right))
+            });
+            sorted.swap(2, 3);
+            "Boxspread"
+        }
+        num_legs => return Err(format!("expected 2 to 4 legs, got {}", num_legs)),
+    };
+    let ratios: Vec<i32> = sorted.iter().map(|quote_leg| quote_leg.ratio).collect();
+    if ratios != structure_ratios(strategy) {
+        return Err(format!(
+            "a {} has ratios {:?} in scanner order, got {:?}",
+            strategy,
+            structure_ratios(strategy),
+            ratios
+        ));
+    }
+    let entry: WatchlistEntry = WatchlistEntry {
+        strategy: strategy.to_string(),
+        legs: sorted.into_iter().map(|quote_leg| quote_leg.leg).collect(),
+        min_edge,
+        note: None,
+    };
+    entry.to_contender()?;
+    Ok(entry)
+}
+
+/// Parses the arguments of the `quote` subcommand, e.g. `250117/C/5890/1 250117/C/5900/-2
+/// 250117/C/5910/1 --min-edge 0.1 --fills 2 --submit`.
+///
+/// # Arguments
+///
+/// * `args` - The arguments after `quote`.
+///
+/// # Returns
+///
+/// A `Result` containing the request, or an error naming the invalid argument.
+pub(crate) fn parse_quote_args(args: &[String]) -> Result<QuoteRequest, String> {
+    let mut legs: Vec<QuoteLeg> = Vec::new();
+    let mut min_edge: Option<f64> = None;
+    let mut num_fills: i32 = 1;
+    let mut submit: bool = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--submit" => submit = true,
+            "--min-edge" => {
+                min_edge = Some(
+                    args.next()
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .ok_or("--min-edge takes a number")?,
+                )
+            }
+            "--fills" => {
+                num_fills = args
+                    .next()
+                    .and_then(|value| value.parse::<i32>().ok())
+                    .filter(|num_fills| *num_fills > 0)
+                    .ok_or("--fills takes a positive number")?
+            }
+            leg => legs.push(leg.parse()?),
+        }
+    }
+    Ok(QuoteRequest {
+        entry: entry_from_legs(&legs, min_edge)?,
+        submit,
+        num_fills,
+    })
+}
+
+/// The watchlist imported last.
+static WATCHLIST: Mutex<Vec<WatchlistEntry>> = Mutex::new(Vec::new());
+
+/// Returns the watchlist imported last.
+pub(crate) fn watchlist() -> Vec<WatchlistEntry> {
+    WATCHLIST.lock().unwrap().clone()
+}
+
+/// Parses a watchlist, a JSON array of entries.
+///
+/// # Arguments
+///
+/// * `text` - The JSON.
+///
+/// # Returns
+///
+/// A `Result` containing the entries, or an error naming the first entry whose legs don't form
+/// its spread.
+pub(crate) fn parse_watchlist(text: &str) -> Result<Vec<WatchlistEntry>, Box<dyn Error>> {
+    let entries: Vec<WatchlistEntry> = serde_json::from_str(text)?;
+    for (index, entry) in entries.iter().enumerate() {
+        entry
+            .to_contender()
+            .map_err(|e| format!("watchlist entry {}: {}", index, e))?;
+    }
+    Ok(entries)
+}
+
+/// Replaces the watchlist with the entries of a file.
+///
+/// # Arguments
+///
+/// * `path` - The path of the watchlist file.
+///
+/// # Returns
+///
+/// A `Result` containing the number of entries imported, or an error if the file can't be read
+/// or is invalid, in which case the watchlist is kept.
+pub(crate) fn import_watchlist(path: &str) -> Result<usize, Box<dyn Error>> {
+    let entries: Vec<WatchlistEntry> = parse_watchlist(&fs::read_to_string(path)?)?;
+    let count: usize = entries.len();
+    *WATCHLIST.lock().unwrap() = entries;
+    Ok(count)
+}
+
+/// Writes the structures of contenders to a watchlist file, to edit and import later.
+///
+/// # Arguments
+///
+/// * `path` - The path of the watchlist file.
+/// * `contenders` - The contenders, e.g. those of the last scan.
+///
+/// # Returns
+///
+/// A `Result` containing the number of entries exported, or an error if the file can't be
+/// written.
+pub(crate) fn export_watchlist(
+    path: &str,
+    contenders: &[Contender],
+) -> Result<usize, Box<dyn Error>> {
+    let entries: Vec<WatchlistEntry> = contenders
+        .iter()
+        .map(WatchlistEntry::from_contender)
+        .collect();
+    fs::write(path, serde_json::to_string_pretty(&entries)? + "\n")?;
+    Ok(entries.len())
+}